@@ -0,0 +1,408 @@
+// Structure analysis derived from interatomic distances, and the RMSD of trajectory frames
+
+use std::collections::HashMap;
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::constants::{get_covalent_radius, Element};
+use crate::neighbors::{Neighbor, NeighborList};
+use crate::structure::Crystal;
+use crate::trajectory::Trajectory;
+
+/// Frames whose RMSD is taken per update, so that long trajectories read from disk fill the plot
+/// in over several updates rather than holding up one.
+const RMSD_FRAMES_PER_UPDATE: usize = 64;
+
+/// Atoms are bonded when closer than the sum of their covalent radii times this factor.
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub(crate) struct BondTolerance(pub f32);
+
+impl Default for BondTolerance {
+    fn default() -> Self {
+        Self(1.2)
+    }
+}
+
+// Longest distance at which atoms of elements `a` and `b` count as bonded
+pub(crate) fn bond_length_limit(a: Element, b: Element, tolerance: f32) -> f32 {
+    (get_covalent_radius(a) + get_covalent_radius(b)) * tolerance
+}
+
+// Whether atoms of elements `a` and `b` at `distance` apart count as bonded
+pub(crate) fn is_bonded(a: Element, b: Element, distance: f32, tolerance: f32) -> bool {
+    distance <= bond_length_limit(a, b, tolerance)
+}
+
+// Neighbor list wide enough to hold every bond of `crystal`
+pub(crate) fn bond_neighbor_list(crystal: &Crystal, tolerance: f32) -> NeighborList {
+    let max_radius = crystal
+        .atoms
+        .iter()
+        .map(|atom| get_covalent_radius(atom.element))
+        .fold(0.0, f32::max);
+    NeighborList::from_crystal(crystal, 2.0 * max_radius * tolerance)
+}
+
+// Bonded neighbors of every atom; every bond is listed at both of its ends
+pub(crate) fn bonded_neighbors(crystal: &Crystal, tolerance: f32) -> Vec<Vec<Neighbor>> {
+    let list = bond_neighbor_list(crystal, tolerance);
+    crystal
+        .atoms
+        .iter()
+        .enumerate()
+        .map(|(i, atom)| {
+            list.neighbors(i)
+                .iter()
+                .filter(|n| {
+                    let other = crystal.atoms[n.index].element;
+                    is_bonded(atom.element, other, n.distance, tolerance)
+                })
+                .copied()
+                .collect()
+        })
+        .collect()
+}
+
+// Neighbors of every atom too far to be bonded but within `reach` times the sum of their
+// covalent radii: weak contacts such as hydrogen bonds, or a second coordination shell
+pub(crate) fn contact_neighbors(
+    crystal: &Crystal,
+    tolerance: f32,
+    reach: f32,
+) -> Vec<Vec<Neighbor>> {
+    let list = bond_neighbor_list(crystal, reach);
+    crystal
+        .atoms
+        .iter()
+        .enumerate()
+        .map(|(i, atom)| {
+            list.neighbors(i)
+                .iter()
+                .filter(|n| {
+                    let other = crystal.atoms[n.index].element;
+                    !is_bonded(atom.element, other, n.distance, tolerance)
+                        && is_bonded(atom.element, other, n.distance, reach)
+                })
+                .copied()
+                .collect()
+        })
+        .collect()
+}
+
+// Number of bonded neighbors of every atom
+pub(crate) fn coordination_numbers(crystal: &Crystal, tolerance: f32) -> Vec<usize> {
+    bonded_neighbors(crystal, tolerance)
+        .iter()
+        .map(Vec::len)
+        .collect()
+}
+
+// Per-atom coordination numbers of the current structure
+#[derive(Resource, Default)]
+pub(crate) struct Coordination {
+    pub numbers: Vec<usize>,
+}
+
+// Recompute coordination numbers whenever the structure or the bond tolerance changes
+pub(crate) fn update_coordination(
+    crystal: Res<Crystal>,
+    tolerance: Res<BondTolerance>,
+    mut coordination: ResMut<Coordination>,
+) {
+    if crystal.is_changed() || tolerance.is_changed() {
+        coordination.numbers = coordination_numbers(&crystal, tolerance.0);
+    }
+}
+
+// Running count, extremes and mean of a set of values
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Stat {
+    pub count: usize,
+    pub min: f32,
+    pub max: f32,
+    sum: f32,
+}
+
+impl Default for Stat {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            min: f32::INFINITY,
+            max: f32::NEG_INFINITY,
+            sum: 0.0,
+        }
+    }
+}
+
+impl Stat {
+    fn push(&mut self, value: f32) {
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+    }
+
+    pub fn mean(&self) -> f32 {
+        self.sum / self.count.max(1) as f32
+    }
+}
+
+// Bond lengths (Å) per element pair and bond angles (degrees) per element triple,
+// with the center atom in the middle of the triple
+#[derive(Resource, Default)]
+pub(crate) struct BondStatistics {
+    pub bonds: Vec<(String, Stat)>,
+    pub angles: Vec<(String, Stat)>,
+}
+
+fn add(stats: &mut Vec<(String, Stat)>, key: String, value: f32) {
+    match stats.iter_mut().find(|(k, _)| *k == key) {
+        Some((_, stat)) => stat.push(value),
+        None => {
+            let mut stat = Stat::default();
+            stat.push(value);
+            stats.push((key, stat));
+        }
+    }
+}
+
+pub(crate) fn bond_statistics(crystal: &Crystal, tolerance: f32) -> BondStatistics {
+    let element = |i: usize| crystal.atoms[i].element.symbol();
+    let bonded = bonded_neighbors(crystal, tolerance);
+
+    let mut statistics = BondStatistics::default();
+    for (i, neighbors) in bonded.iter().enumerate() {
+        for (k, a) in neighbors.iter().enumerate() {
+            // each bond is seen from both ends; count it from the lower index
+            if a.index > i || (a.index == i && (a.image.x, a.image.y, a.image.z) > (0, 0, 0)) {
+                let mut pair = [element(i), element(a.index)];
+                pair.sort();
+                add(&mut statistics.bonds, pair.join("-"), a.distance);
+            }
+
+            for b in &neighbors[k + 1..] {
+                let mut ends = [element(a.index), element(b.index)];
+                ends.sort();
+                let angle = a.vector.angle_between(b.vector).to_degrees();
+                add(
+                    &mut statistics.angles,
+                    format!("{}-{}-{}", ends[0], element(i), ends[1]),
+                    angle,
+                );
+            }
+        }
+    }
+
+    statistics.bonds.sort_by(|a, b| a.0.cmp(&b.0));
+    statistics.angles.sort_by(|a, b| a.0.cmp(&b.0));
+    statistics
+}
+
+impl BondStatistics {
+    // Both tables as CSV, lengths in Å and angles in degrees
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("kind,type,count,min,max,mean\n");
+        for (kind, stats) in [("bond", &self.bonds), ("angle", &self.angles)] {
+            for (key, stat) in stats {
+                csv.push_str(&format!(
+                    "{kind},{key},{},{:.4},{:.4},{:.4}\n",
+                    stat.count,
+                    stat.min,
+                    stat.max,
+                    stat.mean()
+                ));
+            }
+        }
+        csv
+    }
+}
+
+// Recompute bond statistics whenever the structure or the bond tolerance changes
+pub(crate) fn update_bond_statistics(
+    crystal: Res<Crystal>,
+    tolerance: Res<BondTolerance>,
+    mut statistics: ResMut<BondStatistics>,
+) {
+    if crystal.is_changed() || tolerance.is_changed() {
+        *statistics = bond_statistics(&crystal, tolerance.0);
+    }
+}
+
+// Largest eigenvalue of the symmetric matrix `m`, by Jacobi rotations
+fn largest_eigenvalue(mut m: [[f64; 4]; 4]) -> f64 {
+    const PAIRS: [(usize, usize); 6] = [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)];
+    for _ in 0..50 {
+        let off: f64 = PAIRS.iter().map(|&(p, q)| m[p][q] * m[p][q]).sum();
+        if off < 1e-18 {
+            break;
+        }
+        for (p, q) in PAIRS {
+            if m[p][q].abs() < 1e-30 {
+                continue;
+            }
+            let theta = (m[q][q] - m[p][p]) / (2.0 * m[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+            for row in m.iter_mut() {
+                let (kp, kq) = (row[p], row[q]);
+                row[p] = c * kp - s * kq;
+                row[q] = s * kp + c * kq;
+            }
+            let (row_p, row_q) = (m[p], m[q]);
+            for (k, (pk, qk)) in row_p.into_iter().zip(row_q).enumerate() {
+                m[p][k] = c * pk - s * qk;
+                m[q][k] = s * pk + c * qk;
+            }
+        }
+    }
+    (0..4).map(|i| m[i][i]).fold(f64::NEG_INFINITY, f64::max)
+}
+
+// Root-mean-square deviation (Å) of `positions` from `reference`, atom by atom; with `align`,
+// after the translation and rotation that best superpose them (Horn's quaternion method).
+// None when the two hold different numbers of atoms, or none
+pub(crate) fn rmsd(reference: &[Vec3], positions: &[Vec3], align: bool) -> Option<f32> {
+    if reference.len() != positions.len() || reference.is_empty() {
+        return None;
+    }
+    let n = reference.len() as f64;
+    if !align {
+        let sum: f64 = reference
+            .iter()
+            .zip(positions)
+            .map(|(a, b)| a.as_dvec3().distance_squared(b.as_dvec3()))
+            .sum();
+        return Some((sum / n).sqrt() as f32);
+    }
+
+    let center = |points: &[Vec3]| points.iter().map(|p| p.as_dvec3()).sum::<DVec3>() / n;
+    let (center_a, center_b) = (center(reference), center(positions));
+    let mut squares = 0.0;
+    // correlation of the centered positions, s[i][j] = sum of a_i * b_j
+    let mut s = [[0.0f64; 3]; 3];
+    for (a, b) in reference.iter().zip(positions) {
+        let a = a.as_dvec3() - center_a;
+        let b = b.as_dvec3() - center_b;
+        squares += a.length_squared() + b.length_squared();
+        for (row, a) in s.iter_mut().zip(a.to_array()) {
+            for (cell, b) in row.iter_mut().zip(b.to_array()) {
+                *cell += a * b;
+            }
+        }
+    }
+    let [[xx, xy, xz], [yx, yy, yz], [zx, zy, zz]] = s;
+    let k = [
+        [xx + yy + zz, yz - zy, zx - xz, xy - yx],
+        [yz - zy, xx - yy - zz, xy + yx, zx + xz],
+        [zx - xz, xy + yx, -xx + yy - zz, yz + zy],
+        [xy - yx, zx + xz, yz + zy, -xx - yy + zz],
+    ];
+    let deviation = (squares - 2.0 * largest_eigenvalue(k)).max(0.0) / n;
+    Some(deviation.sqrt() as f32)
+}
+
+/// Whether frames are superposed on the first before their RMSD is taken.
+#[derive(Resource, Default)]
+pub(crate) struct RmsdSettings {
+    pub align: bool,
+}
+
+/// RMSD (Å) of every buffered trajectory frame against the first; None for frames holding other
+/// atoms than the first, or not taken yet.
+#[derive(Resource, Default)]
+pub(crate) struct Rmsd {
+    pub values: Vec<Option<f32>>,
+    /// Values by frame index, for the trajectory revision, first frame and alignment they were
+    /// taken with.
+    cache: HashMap<u64, Option<f32>>,
+    cached_for: Option<(u64, u64, bool)>,
+    /// Whether every frame has been taken.
+    complete: bool,
+}
+
+// Take the RMSD of frames added to the trajectory, at most `RMSD_FRAMES_PER_UPDATE` of them per
+// update; every frame is taken again when frames are replaced or when the first frame or the
+// alignment changes
+pub(crate) fn update_rmsd(
+    trajectory: Res<Trajectory>,
+    settings: Res<RmsdSettings>,
+    mut deviations: ResMut<Rmsd>,
+) {
+    if !trajectory.is_changed() && !settings.is_changed() && deviations.complete {
+        return;
+    }
+    let key = trajectory
+        .info(0)
+        .map(|first| (trajectory.revision(), first.index, settings.align));
+    let deviations = &mut *deviations;
+    if deviations.cached_for != key {
+        deviations.cache.clear();
+        deviations.cached_for = key;
+    }
+    let reference = trajectory.positions_near_first(0);
+    let mut budget = RMSD_FRAMES_PER_UPDATE;
+    let values: Vec<Option<f32>> = (0..trajectory.len())
+        .filter_map(|position| trajectory.info(position).map(|info| (position, info.index)))
+        .map(|(position, index)| {
+            if let Some(value) = deviations.cache.get(&index) {
+                return *value;
+            }
+            if budget == 0 {
+                return None;
+            }
+            budget -= 1;
+            let value = trajectory
+                .positions_near_first(position)
+                .and_then(|positions| rmsd(reference.as_deref()?, &positions, settings.align));
+            deviations.cache.insert(index, value);
+            value
+        })
+        .collect();
+    deviations.complete = budget > 0;
+    if values != deviations.values {
+        deviations.values = values;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference() -> Vec<Vec3> {
+        vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.5, 0.2, -0.3),
+            Vec3::new(-0.4, 1.1, 0.8),
+            Vec3::new(0.7, -0.9, 1.6),
+            Vec3::new(2.1, 1.3, 0.4),
+        ]
+    }
+
+    #[test]
+    fn rmsd_of_rotated_copy_is_zero() {
+        let rotation = Quat::from_axis_angle(Vec3::new(1.0, 2.0, -0.5).normalize(), 1.3);
+        let shift = Vec3::new(3.0, -1.0, 2.5);
+        let moved: Vec<Vec3> = reference().iter().map(|p| rotation * *p + shift).collect();
+
+        let aligned = rmsd(&reference(), &moved, true).unwrap();
+        assert!(aligned < 1e-3, "aligned RMSD {aligned}");
+        let unaligned = rmsd(&reference(), &moved, false).unwrap();
+        assert!(unaligned > 1.0, "unaligned RMSD {unaligned}");
+    }
+
+    #[test]
+    fn rmsd_of_uniform_shift_without_alignment() {
+        let shift = Vec3::new(0.3, 0.4, 0.0);
+        let moved: Vec<Vec3> = reference().iter().map(|p| *p + shift).collect();
+        let value = rmsd(&reference(), &moved, false).unwrap();
+        assert!((value - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn rmsd_needs_matching_atoms() {
+        assert_eq!(rmsd(&reference(), &reference()[1..], true), None);
+        assert_eq!(rmsd(&[], &[], false), None);
+    }
+}
@@ -0,0 +1,112 @@
+// Atom info panel listing the selected atoms
+
+use bevy::prelude::*;
+
+use crate::analysis::Coordination;
+use crate::structure::{Crystal, Selection};
+use crate::theme::Themed;
+use crate::ui::SidePanelColumn;
+use crate::widgets::spawn_table_text;
+
+// Rows listed before the table is cut off
+const MAX_ROWS: usize = 20;
+
+/// Root node of the atom info panel, shown while atoms are selected.
+#[derive(Component)]
+pub(crate) struct AtomInfoPanel;
+
+/// Text holding the table of selected atoms.
+#[derive(Component)]
+pub(crate) struct AtomInfoText;
+
+// Spawn the (hidden) atom info panel in the side column
+pub(crate) fn setup_atom_info_panel(
+    mut commands: Commands,
+    column: Single<Entity, With<SidePanelColumn>>,
+) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            AtomInfoPanel,
+            ChildOf(*column),
+        ))
+        .with_children(|panel| {
+            spawn_table_text(panel, AtomInfoText);
+        });
+}
+
+// Rebuild the table when the selection or the structure changes
+pub(crate) fn refresh_atom_info_panel(
+    crystal: Res<Crystal>,
+    selection: Res<Selection>,
+    coordination: Res<Coordination>,
+    mut panels: Query<&mut Node, With<AtomInfoPanel>>,
+    mut texts: Query<&mut Text, With<AtomInfoText>>,
+) {
+    if !crystal.is_changed() && !selection.is_changed() && !coordination.is_changed() {
+        return;
+    }
+
+    for mut node in &mut panels {
+        node.display = if selection.atoms.is_empty() {
+            Display::None
+        } else {
+            Display::Flex
+        };
+    }
+
+    let mut table = format!("{:>5} {:<3} {:>9} {:>9} {:>9}", "#", "El", "x", "y", "z");
+    // periodic structures also list fractional coordinates
+    if crystal.lattice.is_some() {
+        table.push_str(&format!(" {:>7} {:>7} {:>7}", "a", "b", "c"));
+    }
+    table.push_str(&format!(" {:>3}", "CN"));
+    // streamed charges and forces get their own columns
+    let has_charges = crystal.charge(0).is_some();
+    let has_forces = crystal.force(0).is_some();
+    if has_charges {
+        table.push_str(&format!(" {:>7}", "q"));
+    }
+    if has_forces {
+        table.push_str(&format!(" {:>8}", "|F|"));
+    }
+    for &index in selection.atoms.iter().take(MAX_ROWS) {
+        let Some(atom) = crystal.atoms.get(index) else {
+            continue;
+        };
+        table.push_str(&format!(
+            "\n{:>5} {:<3} {:>9.4} {:>9.4} {:>9.4}",
+            index, atom.element, atom.x, atom.y, atom.z
+        ));
+        if let Some(frac) = crystal.to_fractional(atom.position()) {
+            table.push_str(&format!(" {:>7.4} {:>7.4} {:>7.4}", frac.x, frac.y, frac.z));
+        }
+        let cn = coordination
+            .numbers
+            .get(index)
+            .map_or_else(|| "-".to_string(), usize::to_string);
+        table.push_str(&format!(" {:>3}", cn));
+        if has_charges {
+            table.push_str(&format!(
+                " {:>7.3}",
+                crystal.charge(index).unwrap_or_default()
+            ));
+        }
+        if has_forces {
+            let force = crystal.force(index).unwrap_or_default();
+            table.push_str(&format!(" {:>8.4}", force.length()));
+        }
+    }
+    if selection.atoms.len() > MAX_ROWS {
+        table.push_str(&format!("\n... {} more", selection.atoms.len() - MAX_ROWS));
+    }
+
+    for mut text in &mut texts {
+        text.0 = table.clone();
+    }
+}
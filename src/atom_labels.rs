@@ -0,0 +1,348 @@
+// Atom labels
+// Atoms can be labeled with their index in the file (as in the atom info panel), their number
+// among the atoms of their element (Fe1, Fe2, ...) or their site label from the file, such as
+// the atom names of mmCIF files, falling back to the element numbering where the file has none.
+// The numbering is picked in the labels panel, or with `labels = "element"` in the
+// configuration. Labels are UI texts placed over the projected atoms; structures with more than
+// `MAX_LABELS` atoms only label the selected ones.
+// Being UI text, labels keep their size on screen and are rasterized at the screen's resolution,
+// so they stay sharp at any zoom without distance-field fonts. The panel also sets their size,
+// color and outline, a one-pixel shadow in a contrasting color that keeps them readable over
+// atoms of any color, and can hide the labels of atoms behind other atoms.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::color::ElementOverrides;
+use crate::constants::Element;
+use crate::settings::stepped_index;
+use crate::structure::{Crystal, Selection};
+use crate::theme::Themed;
+use crate::ui::{AtomScale, MainCamera, SidePanelColumn, ToggleId, ToggledPanel};
+use crate::widgets::{on_off, spawn_stepper_row, StepperSettings};
+
+/// Atoms above which only the selected ones are labeled.
+const MAX_LABELS: usize = 300;
+/// Font sizes of the labels, in logical pixels.
+const FONT_SIZE_STEP: f32 = 2.0;
+const FONT_SIZE_RANGE: (f32, f32) = (8.0, 32.0);
+/// Label colors with the colors of their outlines.
+const LABEL_COLORS: [(&str, Color, Color); 4] = [
+    ("white", Color::WHITE, Color::BLACK),
+    ("black", Color::BLACK, Color::WHITE),
+    ("yellow", Color::srgb(1.0, 0.9, 0.2), Color::BLACK),
+    ("cyan", Color::srgb(0.3, 0.9, 1.0), Color::BLACK),
+];
+/// Ray tests above which labels are not hidden behind atoms, each label being tested against
+/// every shown atom in every frame.
+const MAX_OCCLUSION_TESTS: usize = 4_000_000;
+
+/// What atom labels show.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LabelNumbering {
+    #[default]
+    Off,
+    /// Index of the atom in the file.
+    Index,
+    /// Element symbol and number among the atoms of the element.
+    Element,
+    /// Site label from the file.
+    Site,
+}
+
+impl LabelNumbering {
+    /// Every numbering, in the order the labels panel steps through them.
+    pub(crate) const ALL: [LabelNumbering; 4] = [
+        LabelNumbering::Off,
+        LabelNumbering::Index,
+        LabelNumbering::Element,
+        LabelNumbering::Site,
+    ];
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            LabelNumbering::Off => "off",
+            LabelNumbering::Index => "index",
+            LabelNumbering::Element => "Fe1, Fe2",
+            LabelNumbering::Site => "site",
+        }
+    }
+}
+
+/// Label settings shown in the labels panel.
+#[derive(Resource)]
+pub(crate) struct LabelSettings {
+    pub numbering: LabelNumbering,
+    pub font_size: f32,
+    /// Index into `LABEL_COLORS`.
+    pub color: usize,
+    pub outline: bool,
+    /// Whether labels of atoms behind other atoms are hidden.
+    pub occlusion: bool,
+}
+
+impl Default for LabelSettings {
+    fn default() -> Self {
+        Self {
+            numbering: LabelNumbering::Off,
+            font_size: 12.0,
+            color: 0,
+            outline: true,
+            occlusion: false,
+        }
+    }
+}
+
+impl LabelSettings {
+    fn text_style(&self) -> (TextFont, TextColor) {
+        (
+            TextFont {
+                font: default(),
+                font_size: self.font_size,
+                ..default()
+            },
+            TextColor(LABEL_COLORS[self.color].1),
+        )
+    }
+
+    fn shadow(&self) -> Option<TextShadow> {
+        self.outline.then(|| TextShadow {
+            offset: Vec2::ONE,
+            color: LABEL_COLORS[self.color].2,
+        })
+    }
+}
+
+impl StepperSettings for LabelSettings {
+    fn step(&mut self, field: usize, direction: i32) {
+        match field {
+            0 => {
+                let all = LabelNumbering::ALL;
+                let index = all.iter().position(|n| *n == self.numbering).unwrap_or(0);
+                self.numbering = all[stepped_index(index, direction, all.len())];
+            }
+            1 => {
+                let (min, max) = FONT_SIZE_RANGE;
+                self.font_size =
+                    (self.font_size + direction as f32 * FONT_SIZE_STEP).clamp(min, max);
+            }
+            2 => self.color = stepped_index(self.color, direction, LABEL_COLORS.len()),
+            3 => self.outline = direction > 0,
+            _ => self.occlusion = direction > 0,
+        }
+    }
+
+    fn value_text(&self, field: usize) -> String {
+        match field {
+            0 => self.numbering.label().to_string(),
+            1 => format!("{:.0} px", self.font_size),
+            2 => LABEL_COLORS[self.color].0.to_string(),
+            3 => on_off(self.outline),
+            _ => on_off(self.occlusion),
+        }
+    }
+}
+
+/// Label of the atom with this index.
+#[derive(Component)]
+pub(crate) struct AtomLabel(usize);
+
+// Label of every atom of `crystal` under `numbering`; elements are numbered in file order
+fn atom_labels(crystal: &Crystal, numbering: LabelNumbering) -> Vec<String> {
+    let mut counts: HashMap<Element, usize> = HashMap::new();
+    crystal
+        .atoms
+        .iter()
+        .enumerate()
+        .map(|(index, atom)| {
+            let count = counts.entry(atom.element).or_default();
+            *count += 1;
+            let numbered = format!("{}{count}", atom.element);
+            match numbering {
+                LabelNumbering::Index => index.to_string(),
+                LabelNumbering::Site => crystal.site_label(index).map_or(numbered, str::to_string),
+                LabelNumbering::Off | LabelNumbering::Element => numbered,
+            }
+        })
+        .collect()
+}
+
+// Spawn the (hidden) labels panel in the side column
+pub(crate) fn setup_label_panel(
+    mut commands: Commands,
+    column: Single<Entity, With<SidePanelColumn>>,
+    settings: Res<LabelSettings>,
+) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            ToggledPanel(ToggleId::Labels),
+            ChildOf(*column),
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                Text::new("Labels"),
+                TextFont {
+                    font: default(),
+                    font_size: 14.0,
+                    ..default()
+                },
+                Themed::Text,
+            ));
+            for (field, label) in ["Numbering", "Font size", "Color", "Outline", "Hide behind"]
+                .into_iter()
+                .enumerate()
+            {
+                spawn_stepper_row(panel, label, field, &*settings);
+            }
+        });
+}
+
+// Label the shown atoms, or the selected ones in large structures, when the structure, the
+// selection or the label settings change; existing label entities are reused and restyled
+pub(crate) fn update_atom_labels(
+    mut commands: Commands,
+    crystal: Res<Crystal>,
+    selection: Res<Selection>,
+    settings: Res<LabelSettings>,
+    overrides: Res<ElementOverrides>,
+    mut labels: Query<(Entity, &mut AtomLabel, &mut Text)>,
+) {
+    if !crystal.is_changed()
+        && !selection.is_changed()
+        && !settings.is_changed()
+        && !overrides.is_changed()
+    {
+        return;
+    }
+
+    let mut shown: Vec<(usize, String)> = Vec::new();
+    if settings.numbering != LabelNumbering::Off {
+        let texts = atom_labels(&crystal, settings.numbering);
+        let labeled: Vec<usize> = if crystal.atoms.len() <= MAX_LABELS {
+            (0..crystal.atoms.len()).collect()
+        } else {
+            selection.atoms.to_vec()
+        };
+        shown.extend(
+            labeled
+                .into_iter()
+                .filter(|&index| {
+                    crystal
+                        .atoms
+                        .get(index)
+                        .is_some_and(|atom| !overrides.is_hidden(atom.element))
+                })
+                .map(|index| (index, texts[index].clone())),
+        );
+    }
+
+    let mut shown = shown.into_iter();
+    for (entity, mut label, mut text) in &mut labels {
+        let Some((index, content)) = shown.next() else {
+            commands.entity(entity).despawn();
+            continue;
+        };
+        label.0 = index;
+        if text.0 != content {
+            text.0 = content;
+        }
+        if settings.is_changed() {
+            let mut entity = commands.entity(entity);
+            entity.insert(settings.text_style());
+            match settings.shadow() {
+                Some(shadow) => entity.insert(shadow),
+                None => entity.remove::<TextShadow>(),
+            };
+        }
+    }
+    for (index, content) in shown {
+        let mut entity = commands.spawn((
+            Text::new(content),
+            settings.text_style(),
+            Node {
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            // placed by `place_atom_labels` before it is first drawn
+            Visibility::Hidden,
+            AtomLabel(index),
+        ));
+        if let Some(shadow) = settings.shadow() {
+            entity.insert(shadow);
+        }
+    }
+}
+
+// Whether the sight line from `eye` to the front of the atom at `target` with `radius` passes
+// through one of `spheres`, the shown atoms as (center, radius)
+fn occluded(eye: Vec3, target: Vec3, radius: f32, spheres: &[(Vec3, f32)]) -> bool {
+    let to_target = target - eye;
+    let reach = to_target.length() - radius;
+    let direction = to_target.normalize_or_zero();
+    spheres.iter().any(|&(center, sphere_radius)| {
+        let to_center = center - eye;
+        let along = to_center.dot(direction);
+        along < reach
+            && center != target
+            && to_center.length_squared() - along * along < sphere_radius * sphere_radius
+    })
+}
+
+// Center each label on its atom, as seen by the main camera, hiding labels off screen and, when
+// occlusion is on, behind other atoms
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn place_atom_labels(
+    crystal: Res<Crystal>,
+    settings: Res<LabelSettings>,
+    overrides: Res<ElementOverrides>,
+    scale: Res<AtomScale>,
+    camera: Single<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut labels: Query<(&AtomLabel, &ComputedNode, &mut Node, &mut Visibility)>,
+    mut spheres: Local<Vec<(Vec3, f32)>>,
+) {
+    let (camera, camera_transform) = *camera;
+    let radius = |index: usize| overrides.size(crystal.atoms[index].element) * scale.0;
+    if crystal.is_changed() || overrides.is_changed() || scale.is_changed() {
+        *spheres = (0..crystal.atoms.len())
+            .filter(|&index| !overrides.is_hidden(crystal.atoms[index].element))
+            .map(|index| (crystal.atoms[index].position(), radius(index)))
+            .collect();
+    }
+    let mut occlusion = settings.occlusion;
+    if occlusion && labels.iter().count() * spheres.len() > MAX_OCCLUSION_TESTS {
+        warn_once!("Not hiding labels behind atoms, the structure has too many atoms");
+        occlusion = false;
+    }
+
+    let eye = camera_transform.translation();
+    for (label, computed, mut node, mut visibility) in &mut labels {
+        let position = crystal
+            .atoms
+            .get(label.0)
+            .filter(|atom| !occlusion || !occluded(eye, atom.position(), radius(label.0), &spheres))
+            .and_then(|atom| {
+                camera
+                    .world_to_viewport(camera_transform, atom.position())
+                    .ok()
+            });
+        let Some(position) = position else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *visibility = Visibility::Inherited;
+        let half_size = computed.size() * computed.inverse_scale_factor() / 2.0;
+        node.left = Val::Px(position.x - half_size.x);
+        node.top = Val::Px(position.y - half_size.y);
+    }
+}
@@ -0,0 +1,500 @@
+// GPU picking of atoms
+// After the main pass, the picking camera's view is drawn again into an ID buffer holding the
+// index of the atom covering each pixel. Only the pixel under the cursor is copied out and read
+// back, so hovering and clicking cost the same for a million atoms as for three. The read-back
+// atom arrives a frame or two after the cursor moved and is reported to bevy_picking as a hit on
+// the atom instances.
+
+use bevy::asset::{load_internal_asset, weak_handle, RenderAssetUsages};
+use bevy::core_pipeline::core_3d::graph::{Core3d, Node3d};
+use bevy::ecs::query::QueryItem;
+use bevy::pbr::RenderMeshInstances;
+use bevy::picking::backend::{HitData, PointerHits};
+use bevy::picking::pointer::PointerId;
+use bevy::picking::PickSet;
+use bevy::prelude::*;
+use bevy::render::camera::ExtractedCamera;
+use bevy::render::extract_component::{ExtractComponent, ExtractComponentPlugin};
+use bevy::render::extract_resource::{ExtractResource, ExtractResourcePlugin};
+use bevy::render::gpu_readback::{Readback, ReadbackComplete};
+use bevy::render::mesh::allocator::MeshAllocator;
+use bevy::render::mesh::{MeshVertexBufferLayoutRef, RenderMesh};
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_graph::{
+    NodeRunError, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode, ViewNodeRunner,
+};
+use bevy::render::render_resource::binding_types::uniform_buffer_sized;
+use bevy::render::render_resource::{
+    BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, Buffer,
+    BufferInitDescriptor, BufferUsages, CachedRenderPipelineId, ColorTargetState, ColorWrites,
+    CompareFunction, DepthStencilState, Extent3d, Face, FragmentState, LoadOp, MultisampleState,
+    Operations, Origin3d, PipelineCache, PrimitiveState, RenderPassColorAttachment,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipelineDescriptor, ShaderStages,
+    SpecializedMeshPipeline, SpecializedMeshPipelineError, SpecializedMeshPipelines, StoreOp,
+    TexelCopyBufferInfo, TexelCopyBufferLayout, TexelCopyTextureInfo, TextureAspect,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, VertexState,
+};
+use bevy::render::renderer::{RenderContext, RenderDevice};
+use bevy::render::storage::{GpuShaderStorageBuffer, ShaderStorageBuffer};
+use bevy::render::texture::{CachedTexture, TextureCache};
+use bevy::render::view::{ExtractedView, RenderVisibleEntities};
+use bevy::render::{Render, RenderApp, RenderSet};
+use bevy::window::PrimaryWindow;
+use bytemuck::{Pod, Zeroable};
+
+use crate::instancing::{
+    draw_sphere_instances, instance_buffer_layout, AtomInstance, AtomInstances, AtomRendering,
+    InstanceBuffer,
+};
+
+const ATOM_ID_SHADER_HANDLE: Handle<Shader> = weak_handle!("b5d27c41-0e9a-4f63-8a1d-2c7f94e063b8");
+
+/// Camera whose view atoms are picked in.
+#[derive(Component, ExtractComponent, Clone, Copy)]
+pub(crate) struct AtomPickingCamera;
+
+/// Cursor position in the picking camera's view, in physical pixels.
+#[derive(Resource, ExtractResource, Clone, Default)]
+struct PickingCursor(Option<UVec2>);
+
+/// Buffer the ID under the cursor is copied into and read back from.
+#[derive(Resource, ExtractResource, Clone)]
+struct PickedIdBuffer(Handle<ShaderStorageBuffer>);
+
+/// Atom under the mouse, and the one it was over when a button was last pressed.
+#[derive(Resource, Default)]
+pub(crate) struct PickedAtoms {
+    hovered: Option<usize>,
+    pressed: Option<usize>,
+}
+
+impl PickedAtoms {
+    // Atom clicked by `pointer`: pressed and released over the same atom
+    pub fn clicked(&self, pointer: PointerId) -> Option<usize> {
+        let hovered = self.hovered.filter(|_| pointer == PointerId::Mouse)?;
+        (self.pressed == Some(hovered)).then_some(hovered)
+    }
+}
+
+/// Picks atoms under the mouse in the view of the `AtomPickingCamera`.
+pub(crate) struct AtomPickingPlugin;
+
+impl Plugin for AtomPickingPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            ATOM_ID_SHADER_HANDLE,
+            "shaders/atom_ids.wgsl",
+            Shader::from_wgsl
+        );
+        app.add_plugins((
+            ExtractComponentPlugin::<AtomPickingCamera>::default(),
+            ExtractResourcePlugin::<PickingCursor>::default(),
+            ExtractResourcePlugin::<PickedIdBuffer>::default(),
+        ))
+        .init_resource::<PickingCursor>()
+        .init_resource::<PickedAtoms>()
+        .add_systems(Startup, setup_id_readback)
+        .add_systems(
+            PreUpdate,
+            (track_picking_cursor, pick_atoms.in_set(PickSet::Backend)).chain(),
+        )
+        .add_observer(press_atom);
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<SpecializedMeshPipelines<AtomIdPipeline>>()
+            .add_systems(
+                Render,
+                (
+                    queue_atom_ids.in_set(RenderSet::Queue),
+                    prepare_id_targets.in_set(RenderSet::PrepareBindGroups),
+                ),
+            )
+            .add_render_graph_node::<ViewNodeRunner<AtomIdNode>>(Core3d, AtomIdLabel)
+            .add_render_graph_edges(Core3d, (Node3d::EndMainPass, AtomIdLabel));
+    }
+
+    fn finish(&self, app: &mut App) {
+        if let Some(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app.init_resource::<AtomIdPipeline>();
+        }
+    }
+}
+
+// Create the buffer the ID under the cursor lands in, read back every frame
+fn setup_id_readback(mut commands: Commands, mut buffers: ResMut<Assets<ShaderStorageBuffer>>) {
+    let mut buffer = ShaderStorageBuffer::new(&[0; 4], RenderAssetUsages::RENDER_WORLD);
+    buffer.buffer_description.usage |= BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
+    let buffer = buffers.add(buffer);
+    commands
+        .spawn(Readback::buffer(buffer.clone()))
+        .observe(receive_picked_id);
+    commands.insert_resource(PickedIdBuffer(buffer));
+}
+
+fn receive_picked_id(trigger: Trigger<ReadbackComplete>, mut picked: ResMut<PickedAtoms>) {
+    let id: u32 = trigger.event().to_shader_type();
+    picked.hovered = id.checked_sub(1).map(|index| index as usize);
+}
+
+fn track_picking_cursor(
+    window: Option<Single<&Window, With<PrimaryWindow>>>,
+    mut cursor: ResMut<PickingCursor>,
+) {
+    let position = window
+        .and_then(|window| window.physical_cursor_position())
+        .map(|position| position.as_uvec2());
+    if cursor.0 != position {
+        cursor.0 = position;
+    }
+}
+
+// Picking backend reporting the atom read back under the mouse, as a hit on its chunk
+fn pick_atoms(
+    picked: Res<PickedAtoms>,
+    cursor: Res<PickingCursor>,
+    cameras: Query<(Entity, &Camera, &GlobalTransform), With<AtomPickingCamera>>,
+    chunks: Query<(Entity, Ref<AtomInstances>)>,
+    mut found: Local<Option<(Entity, AtomInstance)>>,
+    mut output: EventWriter<PointerHits>,
+) {
+    let (Some(index), Some(_)) = (picked.hovered, cursor.0) else {
+        return;
+    };
+    // look the atom up again only when it or the chunks changed
+    let stale = !found.is_some_and(|(_, atom)| atom.index as usize == index)
+        || chunks.iter().any(|(_, instances)| instances.is_changed());
+    if stale {
+        *found = chunks.iter().find_map(|(entity, instances)| {
+            let atom = instances.iter().find(|atom| atom.index as usize == index)?;
+            Some((entity, *atom))
+        });
+    }
+    // the structure may have changed since the ID was drawn
+    let Some((entity, atom)) = *found else {
+        return;
+    };
+    let Ok((camera_entity, camera, transform)) = cameras.single() else {
+        return;
+    };
+    let depth = (transform.translation().distance(atom.position) - atom.radius).max(0.0);
+    output.write(PointerHits::new(
+        PointerId::Mouse,
+        vec![(entity, HitData::new(camera_entity, depth, None, None))],
+        camera.order as f32,
+    ));
+}
+
+// Remember which atom a button went down on, so that dragging to another one is no click
+fn press_atom(trigger: Trigger<Pointer<Pressed>>, mut picked: ResMut<PickedAtoms>) {
+    if trigger.event().pointer_id == PointerId::Mouse {
+        picked.pressed = picked.hovered;
+    }
+}
+
+/// Camera matrices of a picking view; impostors are built in view space.
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct PickingView {
+    clip_from_world: Mat4,
+    view_from_world: Mat4,
+    clip_from_view: Mat4,
+}
+
+/// Pipeline drawing the sphere instances with their IDs.
+#[derive(Resource)]
+struct AtomIdPipeline {
+    view_layout: BindGroupLayout,
+}
+
+impl FromWorld for AtomIdPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let view_layout = world.resource::<RenderDevice>().create_bind_group_layout(
+            "atom id view layout",
+            &BindGroupLayoutEntries::single(
+                ShaderStages::VERTEX_FRAGMENT,
+                uniform_buffer_sized(false, None),
+            ),
+        );
+        AtomIdPipeline { view_layout }
+    }
+}
+
+impl SpecializedMeshPipeline for AtomIdPipeline {
+    type Key = AtomRendering;
+
+    fn specialize(
+        &self,
+        rendering: AtomRendering,
+        layout: &MeshVertexBufferLayoutRef,
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
+        let vertex_layout = layout
+            .0
+            .get_layout(&[Mesh::ATTRIBUTE_POSITION.at_shader_location(0)])?;
+        Ok(RenderPipelineDescriptor {
+            label: Some("atom id pipeline".into()),
+            layout: vec![self.view_layout.clone()],
+            push_constant_ranges: Vec::new(),
+            vertex: VertexState {
+                shader: ATOM_ID_SHADER_HANDLE,
+                shader_defs: rendering.shader_defs(),
+                entry_point: "vertex".into(),
+                buffers: vec![vertex_layout, instance_buffer_layout()],
+            },
+            fragment: Some(FragmentState {
+                shader: ATOM_ID_SHADER_HANDLE,
+                shader_defs: rendering.shader_defs(),
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: TextureFormat::R32Uint,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState {
+                cull_mode: Some(Face::Back),
+                ..default()
+            },
+            // reversed Z, as in the main pass
+            depth_stencil: Some(DepthStencilState {
+                format: TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::Greater,
+                stencil: default(),
+                bias: default(),
+            }),
+            multisample: MultisampleState::default(),
+            zero_initialize_workgroup_memory: false,
+        })
+    }
+}
+
+/// Atom chunks a picking view sees, as the ID pass draws them.
+#[derive(Component)]
+struct AtomIdDraws(Vec<AtomIdBatch>);
+
+struct AtomIdBatch {
+    pipeline: CachedRenderPipelineId,
+    mesh: AssetId<Mesh>,
+    instances: Buffer,
+    count: u32,
+}
+
+// Collect the chunks left after frustum culling in every picking view
+#[allow(clippy::too_many_arguments)]
+fn queue_atom_ids(
+    mut commands: Commands,
+    atom_id_pipeline: Res<AtomIdPipeline>,
+    mut pipelines: ResMut<SpecializedMeshPipelines<AtomIdPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    meshes: Res<RenderAssets<RenderMesh>>,
+    render_mesh_instances: Res<RenderMeshInstances>,
+    rendering: Res<AtomRendering>,
+    atoms: Query<&InstanceBuffer>,
+    views: Query<(Entity, &RenderVisibleEntities), With<AtomPickingCamera>>,
+) {
+    for (view, visible) in &views {
+        let batches = visible
+            .iter::<Mesh3d>()
+            .filter_map(|&(entity, main_entity)| {
+                let buffer = atoms.get(entity).ok()?;
+                let mesh_instance = render_mesh_instances.render_mesh_queue_data(main_entity)?;
+                let mesh = meshes.get(mesh_instance.mesh_asset_id)?;
+                let pipeline = pipelines
+                    .specialize(&pipeline_cache, &atom_id_pipeline, *rendering, &mesh.layout)
+                    .map_err(|e| error!("Cannot build the atom picking pipeline: {e}"))
+                    .ok()?;
+                Some(AtomIdBatch {
+                    pipeline,
+                    mesh: mesh_instance.mesh_asset_id,
+                    instances: buffer.buffer.clone(),
+                    count: buffer.length as u32,
+                })
+            })
+            .collect();
+        commands.entity(view).insert(AtomIdDraws(batches));
+    }
+}
+
+/// ID and depth textures of a picking view, with its camera matrix.
+#[derive(Component)]
+struct AtomIdTarget {
+    ids: CachedTexture,
+    depth: CachedTexture,
+    size: UVec2,
+    view: BindGroup,
+}
+
+fn prepare_id_targets(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    mut texture_cache: ResMut<TextureCache>,
+    atom_id_pipeline: Res<AtomIdPipeline>,
+    views: Query<(Entity, &ExtractedCamera, &ExtractedView), With<AtomPickingCamera>>,
+) {
+    for (entity, camera, view) in &views {
+        let Some(size) = camera.physical_viewport_size else {
+            continue;
+        };
+        let texture = |label, format, usage| TextureDescriptor {
+            label: Some(label),
+            size: Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        };
+        let ids = texture_cache.get(
+            &render_device,
+            texture(
+                "atom id texture",
+                TextureFormat::R32Uint,
+                TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            ),
+        );
+        let depth = texture_cache.get(
+            &render_device,
+            texture(
+                "atom id depth texture",
+                TextureFormat::Depth32Float,
+                TextureUsages::RENDER_ATTACHMENT,
+            ),
+        );
+
+        let view_from_world = view.world_from_view.compute_matrix().inverse();
+        let matrices = PickingView {
+            clip_from_world: view
+                .clip_from_world
+                .unwrap_or(view.clip_from_view * view_from_world),
+            view_from_world,
+            clip_from_view: view.clip_from_view,
+        };
+        let uniform = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("atom id view uniform"),
+            contents: bytemuck::bytes_of(&matrices),
+            usage: BufferUsages::UNIFORM,
+        });
+        let view = render_device.create_bind_group(
+            "atom id view bind group",
+            &atom_id_pipeline.view_layout,
+            &BindGroupEntries::single(uniform.as_entire_binding()),
+        );
+        commands.entity(entity).insert(AtomIdTarget {
+            ids,
+            depth,
+            size,
+            view,
+        });
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+struct AtomIdLabel;
+
+/// Draws the atom IDs and copies out the one under the cursor.
+#[derive(Default)]
+struct AtomIdNode;
+
+impl ViewNode for AtomIdNode {
+    type ViewQuery = (&'static AtomIdTarget, &'static AtomIdDraws);
+
+    fn run<'w>(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext<'w>,
+        (target, draws): QueryItem<'w, Self::ViewQuery>,
+        world: &'w World,
+    ) -> Result<(), NodeRunError> {
+        let Some(cursor) = world.resource::<PickingCursor>().0 else {
+            return Ok(());
+        };
+        if cursor.x >= target.size.x || cursor.y >= target.size.y {
+            return Ok(());
+        }
+        let buffers = world.resource::<RenderAssets<GpuShaderStorageBuffer>>();
+        let Some(readback) = buffers.get(&world.resource::<PickedIdBuffer>().0) else {
+            return Ok(());
+        };
+
+        {
+            let mut pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+                label: Some("atom id pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &target.ids.default_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(default()),
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                    view: &target.depth.default_view,
+                    depth_ops: Some(Operations {
+                        load: LoadOp::Clear(0.0),
+                        store: StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            // an empty scene still clears the ID under the cursor
+            let pipeline_cache = world.resource::<PipelineCache>();
+            let meshes = world.resource::<RenderAssets<RenderMesh>>();
+            for batch in &draws.0 {
+                let (Some(pipeline), Some(mesh)) = (
+                    pipeline_cache.get_render_pipeline(batch.pipeline),
+                    meshes.get(batch.mesh),
+                ) else {
+                    continue;
+                };
+                pass.set_render_pipeline(pipeline);
+                pass.set_bind_group(0, &target.view, &[]);
+                draw_sphere_instances(
+                    &mut pass,
+                    world.resource::<MeshAllocator>(),
+                    mesh,
+                    &batch.mesh,
+                    &batch.instances,
+                    batch.count,
+                );
+            }
+        }
+
+        render_context.command_encoder().copy_texture_to_buffer(
+            TexelCopyTextureInfo {
+                texture: &target.ids.texture,
+                mip_level: 0,
+                origin: Origin3d {
+                    x: cursor.x,
+                    y: cursor.y,
+                    z: 0,
+                },
+                aspect: TextureAspect::All,
+            },
+            TexelCopyBufferInfo {
+                buffer: &readback.buffer,
+                layout: TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: None,
+                    rows_per_image: None,
+                },
+            },
+            Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        Ok(())
+    }
+}
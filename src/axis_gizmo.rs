@@ -0,0 +1,256 @@
+// Axis gizmo in the bottom-left corner
+// Three arrows on their own render layer, seen by a camera that turns with the main camera but
+// stays at a fixed distance, so the gizmo keeps its size while zooming and panning. With a
+// lattice the arrows point along the cell vectors a, b and c, as in VESTA; molecules get the
+// Cartesian x, y and z. Labels are UI texts placed at the projected arrow tips.
+// Clicking an arrow head or its label turns the main camera to look along that axis, and
+// clicking the center of the gizmo turns it around to view the structure from behind.
+
+use bevy::prelude::*;
+
+use crate::structure::Crystal;
+use crate::ui::{AxisCamera, CameraRig, MainCamera, LAYER_GIZMO};
+
+/// Length of every arrow; the gizmo shows directions, not cell lengths.
+const ARROW_LENGTH: f32 = 2.0;
+const SHAFT_RADIUS: f32 = 0.08;
+const HEAD_RADIUS: f32 = 0.2;
+const HEAD_LENGTH: f32 = 0.5;
+/// Distance of the gizmo camera from the gizmo.
+const CAMERA_DISTANCE: f32 = 7.0;
+/// Labels sit this far beyond the arrow tips.
+const LABEL_OFFSET: f32 = 0.4;
+/// Half the size of a label, to center it on its anchor (logical pixels).
+const LABEL_HALF_SIZE: Vec2 = Vec2::new(5.0, 9.0);
+/// Clicks this close to an arrow head or the center hit it (logical pixels).
+const HIT_RADIUS: f32 = 12.0;
+
+const AXIS_COLORS: [Srgba; 3] = [Srgba::RED, Srgba::GREEN, Srgba::BLUE];
+
+/// Arrow of the gizmo for axis 0, 1 or 2.
+#[derive(Component)]
+pub(crate) struct AxisArrow(pub usize);
+
+/// Label at the tip of an arrow, clicked to look along the axis.
+#[derive(Component)]
+pub(crate) struct AxisLabel(pub usize);
+
+/// Part of the gizmo a click landed on.
+#[derive(Clone, Copy)]
+pub(crate) enum GizmoHit {
+    Axis(usize),
+    Center,
+}
+
+// Directions and names of the three axes shown for `crystal`
+pub(crate) fn axes(crystal: &Crystal) -> ([Vec3; 3], [&'static str; 3]) {
+    match crystal.lattice {
+        Some(lattice) => (
+            [lattice.x_axis, lattice.y_axis, lattice.z_axis].map(|v| v.normalize_or_zero()),
+            ["a", "b", "c"],
+        ),
+        None => ([Vec3::X, Vec3::Y, Vec3::Z], ["x", "y", "z"]),
+    }
+}
+
+pub(crate) fn spawn_axis(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    // arrows are built along +Y from the origin and turned into place
+    let shaft_length = ARROW_LENGTH - HEAD_LENGTH;
+    let shaft = meshes.add(Cylinder::new(SHAFT_RADIUS, shaft_length));
+    let head = meshes.add(Cone {
+        radius: HEAD_RADIUS,
+        height: HEAD_LENGTH,
+    });
+
+    commands
+        .spawn((Transform::default(), Visibility::default(), LAYER_GIZMO))
+        .with_children(|gizmo| {
+            for (axis, color) in AXIS_COLORS.into_iter().enumerate() {
+                let material = materials.add(StandardMaterial {
+                    base_color: color.into(),
+                    unlit: true,
+                    ..default()
+                });
+                gizmo
+                    .spawn((
+                        Transform::from_rotation(Quat::from_rotation_arc(
+                            Vec3::Y,
+                            Vec3::AXES[axis],
+                        )),
+                        Visibility::default(),
+                        LAYER_GIZMO,
+                        AxisArrow(axis),
+                    ))
+                    .with_children(|arrow| {
+                        arrow.spawn((
+                            Mesh3d(shaft.clone()),
+                            MeshMaterial3d(material.clone()),
+                            Transform::from_xyz(0.0, shaft_length / 2.0, 0.0),
+                            LAYER_GIZMO, // visible only to axis camera
+                        ));
+                        arrow.spawn((
+                            Mesh3d(head.clone()),
+                            MeshMaterial3d(material),
+                            Transform::from_xyz(0.0, shaft_length + HEAD_LENGTH / 2.0, 0.0),
+                            LAYER_GIZMO,
+                        ));
+                    });
+            }
+        });
+
+    for (axis, color) in AXIS_COLORS.into_iter().enumerate() {
+        commands.spawn((
+            Button,
+            Node {
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            Text::new(["x", "y", "z"][axis]),
+            TextFont {
+                font_size: 16.0,
+                ..default()
+            },
+            TextColor(color.into()),
+            Visibility::Hidden,
+            AxisLabel(axis),
+        ));
+    }
+}
+
+// Point the arrows along the axes of the structure and name them
+pub(crate) fn update_axis_gizmo(
+    crystal: Res<Crystal>,
+    mut arrows: Query<(&AxisArrow, &mut Transform)>,
+    mut labels: Query<(&AxisLabel, &mut Text)>,
+) {
+    if !crystal.is_changed() {
+        return;
+    }
+    let (directions, names) = axes(&crystal);
+    for (arrow, mut transform) in &mut arrows {
+        let direction = directions[arrow.0];
+        // a degenerate cell vector keeps the arrow where it was
+        if direction != Vec3::ZERO {
+            transform.rotation = Quat::from_rotation_arc(Vec3::Y, direction);
+        }
+    }
+    for (label, mut text) in &mut labels {
+        if text.0 != names[label.0] {
+            text.0 = names[label.0].to_string();
+        }
+    }
+}
+
+// Keep the gizmo camera at a fixed distance from the gizmo, looking at it the way the main
+// camera looks at the structure. The gizmo camera is a child of the main camera, so it turns
+// with it; only its offset has to undo the main camera's position.
+pub(crate) fn sync_axis_camera(
+    main: Single<&Transform, (With<MainCamera>, Without<AxisCamera>)>,
+    mut cameras: Query<&mut Transform, (With<AxisCamera>, Without<MainCamera>)>,
+) {
+    let offset = Vec3::Z * CAMERA_DISTANCE - main.rotation.inverse() * main.translation;
+    for mut transform in &mut cameras {
+        if transform.translation != offset {
+            transform.translation = offset;
+        }
+    }
+}
+
+// Put each label next to the tip of its arrow, as seen by the gizmo camera
+pub(crate) fn place_axis_labels(
+    crystal: Res<Crystal>,
+    cameras: Query<(&Camera, &GlobalTransform), With<AxisCamera>>,
+    mut labels: Query<(&AxisLabel, &mut Node, &mut Visibility)>,
+) {
+    let Ok((camera, camera_transform)) = cameras.single() else {
+        return;
+    };
+    let (directions, _) = axes(&crystal);
+    for (label, mut node, mut visibility) in &mut labels {
+        let tip = directions[label.0] * (ARROW_LENGTH + LABEL_OFFSET);
+        let position = camera
+            .is_active
+            .then(|| camera.world_to_viewport(camera_transform, tip).ok())
+            .flatten();
+        let Some(position) = position else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *visibility = Visibility::Inherited;
+        node.left = Val::Px(position.x - LABEL_HALF_SIZE.x);
+        node.top = Val::Px(position.y - LABEL_HALF_SIZE.y);
+    }
+}
+
+// The arrow head or center under `cursor`, whichever is nearest
+fn gizmo_hit(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    directions: &[Vec3; 3],
+    cursor: Vec2,
+) -> Option<GizmoHit> {
+    let head = ARROW_LENGTH - HEAD_LENGTH / 2.0;
+    let targets = [
+        (Vec3::ZERO, GizmoHit::Center),
+        (directions[0] * head, GizmoHit::Axis(0)),
+        (directions[1] * head, GizmoHit::Axis(1)),
+        (directions[2] * head, GizmoHit::Axis(2)),
+    ];
+    targets
+        .into_iter()
+        .filter_map(|(point, hit)| {
+            let position = camera.world_to_viewport(camera_transform, point).ok()?;
+            let distance = position.distance(cursor);
+            (distance <= HIT_RADIUS).then_some((distance, hit))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, hit)| hit)
+}
+
+// Turn the main camera to look along the axis whose arrow head or label was clicked, or around
+// to the other side when the center was clicked. The turn starts when the button is released,
+// so that the hand still on the mouse does not rotate the view out of it.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn snap_view_to_axis(
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window>,
+    crystal: Res<Crystal>,
+    labels: Query<(&Interaction, &AxisLabel)>,
+    cameras: Query<(&Camera, &GlobalTransform), With<AxisCamera>>,
+    main: Single<&Transform, With<MainCamera>>,
+    mut rig: ResMut<CameraRig>,
+    mut pressed: Local<Option<GizmoHit>>,
+) {
+    let (directions, _) = axes(&crystal);
+    if mouse_buttons.just_pressed(MouseButton::Left) {
+        let on_label = labels
+            .iter()
+            .find(|(interaction, _)| **interaction == Interaction::Pressed)
+            .map(|(_, label)| GizmoHit::Axis(label.0));
+        *pressed = on_label.or_else(|| {
+            let cursor = windows.single().ok()?.cursor_position()?;
+            let (camera, camera_transform) = cameras.single().ok()?;
+            if !camera.is_active {
+                return None;
+            }
+            gizmo_hit(camera, camera_transform, &directions, cursor)
+        });
+    }
+    if !mouse_buttons.just_released(MouseButton::Left) {
+        return;
+    }
+
+    let direction = match pressed.take() {
+        // the camera sits out along the axis, so that it points at the viewer
+        Some(GizmoHit::Axis(axis)) => directions[axis],
+        Some(GizmoHit::Center) => rig.target() - main.translation,
+        None => return,
+    };
+    if direction != Vec3::ZERO {
+        rig.turn_to(&main, direction);
+    }
+}
@@ -0,0 +1,430 @@
+// Keyboard bindings
+// Every keyboard shortcut goes through the `KeyBindings` table, which the `[keys]` table of the
+// configuration file can change, and the help overlay lists it, so both stay in step:
+//
+//   [keys]
+//   focus_camera = "Space"
+//   help = "F1"
+//   command_palette = "Ctrl+Shift+P"   # Ctrl stands for Cmd on macOS as well
+//
+// Mouse controls are fixed, but listed in the overlay too. "?" opens the overlay; it or Escape
+// closes it.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use bevy::prelude::*;
+
+use crate::theme::Themed;
+use crate::widgets::{spawn_table_text, FocusedField};
+
+/// Something done from the keyboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum KeyAction {
+    FocusCamera,
+    PreviousFrame,
+    NextFrame,
+    FirstFrame,
+    LastFrame,
+    PlayPause,
+    PreviousStructure,
+    NextStructure,
+    FlyMode,
+    Paste,
+    CommandPalette,
+    Help,
+}
+
+impl KeyAction {
+    /// Every action, in the order the help lists them.
+    const ALL: [KeyAction; 12] = [
+        KeyAction::FocusCamera,
+        KeyAction::PreviousFrame,
+        KeyAction::NextFrame,
+        KeyAction::FirstFrame,
+        KeyAction::LastFrame,
+        KeyAction::PlayPause,
+        KeyAction::PreviousStructure,
+        KeyAction::NextStructure,
+        KeyAction::FlyMode,
+        KeyAction::Paste,
+        KeyAction::CommandPalette,
+        KeyAction::Help,
+    ];
+
+    // Name in the `[keys]` table of the configuration
+    fn name(self) -> &'static str {
+        match self {
+            KeyAction::FocusCamera => "focus_camera",
+            KeyAction::PreviousFrame => "previous_frame",
+            KeyAction::NextFrame => "next_frame",
+            KeyAction::FirstFrame => "first_frame",
+            KeyAction::LastFrame => "last_frame",
+            KeyAction::PlayPause => "play_pause",
+            KeyAction::PreviousStructure => "previous_structure",
+            KeyAction::NextStructure => "next_structure",
+            KeyAction::FlyMode => "fly_mode",
+            KeyAction::Paste => "paste",
+            KeyAction::CommandPalette => "command_palette",
+            KeyAction::Help => "help",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            KeyAction::FocusCamera => "Focus the selection, or the whole structure",
+            KeyAction::PreviousFrame => "Previous frame",
+            KeyAction::NextFrame => "Next frame",
+            KeyAction::FirstFrame => "First frame",
+            KeyAction::LastFrame => "Last frame",
+            KeyAction::PlayPause => "Play or pause the trajectory",
+            KeyAction::PreviousStructure => "Previous structure tab",
+            KeyAction::NextStructure => "Next structure tab",
+            KeyAction::FlyMode => "Fly through the structure, or back to orbiting",
+            KeyAction::Paste => "Show the structure in the clipboard",
+            KeyAction::CommandPalette => "Command palette",
+            KeyAction::Help => "This help",
+        }
+    }
+
+    fn default_binding(self) -> KeyBinding {
+        let (key, ctrl, shift) = match self {
+            KeyAction::FocusCamera => (KeyCode::KeyF, false, false),
+            KeyAction::PreviousFrame => (KeyCode::ArrowLeft, false, false),
+            KeyAction::NextFrame => (KeyCode::ArrowRight, false, false),
+            KeyAction::FirstFrame => (KeyCode::Home, false, false),
+            KeyAction::LastFrame => (KeyCode::End, false, false),
+            KeyAction::PlayPause => (KeyCode::Space, false, false),
+            KeyAction::PreviousStructure => (KeyCode::PageUp, false, false),
+            KeyAction::NextStructure => (KeyCode::PageDown, false, false),
+            KeyAction::FlyMode => (KeyCode::KeyG, false, false),
+            KeyAction::Paste => (KeyCode::KeyV, true, false),
+            KeyAction::CommandPalette => (KeyCode::KeyP, true, false),
+            KeyAction::Help => (KeyCode::Slash, false, true),
+        };
+        KeyBinding { key, ctrl, shift }
+    }
+}
+
+// Names of the keys that can be bound, as written in the configuration and shown in the help
+const KEY_NAMES: &[(KeyCode, &str)] = &[
+    (KeyCode::KeyA, "A"),
+    (KeyCode::KeyB, "B"),
+    (KeyCode::KeyC, "C"),
+    (KeyCode::KeyD, "D"),
+    (KeyCode::KeyE, "E"),
+    (KeyCode::KeyF, "F"),
+    (KeyCode::KeyG, "G"),
+    (KeyCode::KeyH, "H"),
+    (KeyCode::KeyI, "I"),
+    (KeyCode::KeyJ, "J"),
+    (KeyCode::KeyK, "K"),
+    (KeyCode::KeyL, "L"),
+    (KeyCode::KeyM, "M"),
+    (KeyCode::KeyN, "N"),
+    (KeyCode::KeyO, "O"),
+    (KeyCode::KeyP, "P"),
+    (KeyCode::KeyQ, "Q"),
+    (KeyCode::KeyR, "R"),
+    (KeyCode::KeyS, "S"),
+    (KeyCode::KeyT, "T"),
+    (KeyCode::KeyU, "U"),
+    (KeyCode::KeyV, "V"),
+    (KeyCode::KeyW, "W"),
+    (KeyCode::KeyX, "X"),
+    (KeyCode::KeyY, "Y"),
+    (KeyCode::KeyZ, "Z"),
+    (KeyCode::Digit0, "0"),
+    (KeyCode::Digit1, "1"),
+    (KeyCode::Digit2, "2"),
+    (KeyCode::Digit3, "3"),
+    (KeyCode::Digit4, "4"),
+    (KeyCode::Digit5, "5"),
+    (KeyCode::Digit6, "6"),
+    (KeyCode::Digit7, "7"),
+    (KeyCode::Digit8, "8"),
+    (KeyCode::Digit9, "9"),
+    (KeyCode::F1, "F1"),
+    (KeyCode::F2, "F2"),
+    (KeyCode::F3, "F3"),
+    (KeyCode::F4, "F4"),
+    (KeyCode::F5, "F5"),
+    (KeyCode::F6, "F6"),
+    (KeyCode::F7, "F7"),
+    (KeyCode::F8, "F8"),
+    (KeyCode::F9, "F9"),
+    (KeyCode::F10, "F10"),
+    (KeyCode::F11, "F11"),
+    (KeyCode::F12, "F12"),
+    (KeyCode::ArrowLeft, "Left"),
+    (KeyCode::ArrowRight, "Right"),
+    (KeyCode::ArrowUp, "Up"),
+    (KeyCode::ArrowDown, "Down"),
+    (KeyCode::Home, "Home"),
+    (KeyCode::End, "End"),
+    (KeyCode::PageUp, "PageUp"),
+    (KeyCode::PageDown, "PageDown"),
+    (KeyCode::Insert, "Insert"),
+    (KeyCode::Delete, "Delete"),
+    (KeyCode::Backspace, "Backspace"),
+    (KeyCode::Space, "Space"),
+    (KeyCode::Tab, "Tab"),
+    (KeyCode::Enter, "Enter"),
+    (KeyCode::Slash, "/"),
+    (KeyCode::Backslash, "\\"),
+    (KeyCode::Comma, ","),
+    (KeyCode::Period, "."),
+    (KeyCode::Semicolon, ";"),
+    (KeyCode::Quote, "'"),
+    (KeyCode::Backquote, "`"),
+    (KeyCode::Minus, "-"),
+    (KeyCode::Equal, "="),
+    (KeyCode::BracketLeft, "["),
+    (KeyCode::BracketRight, "]"),
+];
+
+/// Key with the modifiers that must be held along.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct KeyBinding {
+    key: KeyCode,
+    /// Ctrl, or Cmd on macOS.
+    ctrl: bool,
+    shift: bool,
+}
+
+impl KeyBinding {
+    // Binding written like "F", "Ctrl+Shift+P" or "?", the latter standing for Shift+/
+    fn parse(text: &str) -> Option<KeyBinding> {
+        let text = text.trim();
+        if text == "?" {
+            return Some(KeyBinding {
+                key: KeyCode::Slash,
+                ctrl: false,
+                shift: true,
+            });
+        }
+        let (modifiers, key) = text.rsplit_once('+').unwrap_or(("", text));
+        let (mut ctrl, mut shift) = (false, false);
+        for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+            match modifier.trim().to_lowercase().as_str() {
+                "ctrl" | "control" | "cmd" | "super" => ctrl = true,
+                "shift" => shift = true,
+                _ => return None,
+            }
+        }
+        let key = KEY_NAMES
+            .iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(key.trim()))?
+            .0;
+        Some(KeyBinding { key, ctrl, shift })
+    }
+
+    // Pressed this frame, with its modifiers held. Ctrl must not be held unless bound, so that
+    // e.g. Ctrl+F stays free for the browser or window manager.
+    fn just_pressed(&self, keys: &ButtonInput<KeyCode>) -> bool {
+        let ctrl = keys.any_pressed([
+            KeyCode::ControlLeft,
+            KeyCode::ControlRight,
+            KeyCode::SuperLeft,
+            KeyCode::SuperRight,
+        ]);
+        let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        keys.just_pressed(self.key) && ctrl == self.ctrl && (shift || !self.shift)
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.key == KeyCode::Slash && self.shift && !self.ctrl {
+            return write!(f, "?");
+        }
+        let name = KEY_NAMES
+            .iter()
+            .find(|(key, _)| *key == self.key)
+            .map_or("?", |(_, name)| name);
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        write!(f, "{name}")
+    }
+}
+
+/// Key bound to each keyboard action.
+#[derive(Resource, Clone, Debug)]
+pub(crate) struct KeyBindings(HashMap<KeyAction, KeyBinding>);
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self(
+            KeyAction::ALL
+                .iter()
+                .map(|&action| (action, action.default_binding()))
+                .collect(),
+        )
+    }
+}
+
+impl KeyBindings {
+    /// Whether the key bound to `action` was pressed this frame.
+    pub(crate) fn just_pressed(&self, action: KeyAction, keys: &ButtonInput<KeyCode>) -> bool {
+        self.0
+            .get(&action)
+            .is_some_and(|binding| binding.just_pressed(keys))
+    }
+
+    /// Key bound to `action` as shown to the user, e.g. "Ctrl+P".
+    pub(crate) fn key_text(&self, action: KeyAction) -> String {
+        self.0
+            .get(&action)
+            .map_or_else(|| "-".to_string(), ToString::to_string)
+    }
+
+    // Bind the action called `name` to the key written in `binding`; unknown names and keys are
+    // reported and skipped
+    pub(crate) fn configure(&mut self, name: &str, binding: &str) {
+        let Some(action) = KeyAction::ALL.into_iter().find(|a| a.name() == name) else {
+            warn!("Ignoring unknown key action '{name}' in the configuration");
+            return;
+        };
+        match KeyBinding::parse(binding) {
+            Some(parsed) => {
+                self.0.insert(action, parsed);
+            }
+            None => warn!("Ignoring key '{binding}' for {name} in the configuration"),
+        }
+    }
+}
+
+// Mouse controls listed after the keys
+const MOUSE_BINDINGS: [(&str, &str); 7] = [
+    ("Left drag", "Rotate"),
+    ("Right drag", "Pan"),
+    ("Wheel", "Zoom"),
+    ("Click", "Pick an atom"),
+    ("Shift+Click", "Add to or remove from the selection"),
+    ("Click axis", "Look along that axis of the corner gizmo"),
+    ("Click center", "Look from the other side"),
+];
+
+// Fixed controls of fly mode, listed last
+const FLY_BINDINGS: [(&str, &str); 5] = [
+    ("Mouse", "Look around"),
+    ("W/A/S/D", "Move"),
+    ("Q/E", "Down/up"),
+    ("Shift", "Faster"),
+    ("Wheel", "Change the speed"),
+];
+
+fn help_text(bindings: &KeyBindings) -> String {
+    let mut text = "Keyboard".to_string();
+    for action in KeyAction::ALL {
+        let key = bindings.key_text(action);
+        text.push_str(&format!("\n  {key:<14}{}", action.description()));
+    }
+    text.push_str("\n\nMouse");
+    for (input, description) in MOUSE_BINDINGS {
+        text.push_str(&format!("\n  {input:<14}{description}"));
+    }
+    text.push_str("\n\nFly mode");
+    for (input, description) in FLY_BINDINGS {
+        text.push_str(&format!("\n  {input:<14}{description}"));
+    }
+    text
+}
+
+/// Whether the help overlay is showing.
+#[derive(Resource, Default)]
+pub(crate) struct HelpShown(pub bool);
+
+/// Root node of the help overlay.
+#[derive(Component)]
+pub(crate) struct HelpOverlay;
+
+/// Text listing the bindings.
+#[derive(Component)]
+pub(crate) struct HelpText;
+
+// Spawn the (hidden) help overlay in the middle of the window, above the other panels
+pub(crate) fn setup_help_overlay(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            GlobalZIndex(10),
+            HelpOverlay,
+        ))
+        .with_children(|overlay| {
+            overlay
+                .spawn((
+                    Node {
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(8.0),
+                        padding: UiRect::all(Val::Px(12.0)),
+                        ..default()
+                    },
+                    Themed::Panel,
+                ))
+                .with_children(|panel| {
+                    panel.spawn((
+                        Text::new("Controls"),
+                        TextFont {
+                            font: default(),
+                            font_size: 14.0,
+                            ..default()
+                        },
+                        Themed::Text,
+                    ));
+                    spawn_table_text(panel, HelpText);
+                });
+        });
+}
+
+// Show or hide the help with its key; Escape hides it too
+pub(crate) fn toggle_help(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    focused: Res<FocusedField>,
+    mut shown: ResMut<HelpShown>,
+) {
+    if focused.0.is_some() {
+        return;
+    }
+    if bindings.just_pressed(KeyAction::Help, &keys) {
+        shown.0 = !shown.0;
+    } else if shown.0 && keys.just_pressed(KeyCode::Escape) {
+        shown.0 = false;
+    }
+}
+
+// Show the overlay and list the bindings in effect
+pub(crate) fn refresh_help_overlay(
+    shown: Res<HelpShown>,
+    bindings: Res<KeyBindings>,
+    mut overlays: Query<&mut Node, With<HelpOverlay>>,
+    mut texts: Query<&mut Text, With<HelpText>>,
+) {
+    if !shown.is_changed() && !bindings.is_changed() {
+        return;
+    }
+    for mut node in &mut overlays {
+        node.display = if shown.0 {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+    let text = help_text(&bindings);
+    for mut content in &mut texts {
+        content.0 = text.clone();
+    }
+}
@@ -0,0 +1,216 @@
+// Bond sticks
+// Bonds found with the bond tolerance (see analysis.rs) are drawn as cylinders between the atoms
+// they join. Every bond is drawn as two halves, one from each atom to the middle of the bond:
+// in the neutral style both are gray, in the bicolor style each takes the color of the element
+// at its end, as in Jmol or Avogadro. Halves also keep bonds across periodic boundaries from
+// reaching into empty space, each atom only drawing up to the middle of its bond to the image.
+// The halves share one cylinder mesh and a material per color, and are lit by the lighting rig
+// like any mesh. Bonds are off by default; the settings panel or `bonds = "bicolor"` in the
+// configuration turns them on.
+// Contacts, atoms farther apart than a bond but within a wider cutoff (hydrogen bonds, the
+// second coordination shell), are drawn as thinner dashed sticks in the colors of the bonds,
+// when their cutoff is set in the settings panel.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::analysis::{bonded_neighbors, contact_neighbors, BondTolerance};
+use crate::color::{ColorScheme, ElementOverrides};
+use crate::constants::Element;
+use crate::neighbors::Neighbor;
+use crate::structure::Crystal;
+
+/// Radius of the bond cylinders, in Å.
+const BOND_RADIUS: f32 = 0.1;
+/// Radius of the contact dashes, in Å.
+const CONTACT_RADIUS: f32 = 0.04;
+/// Length of a contact dash and of the gap to the next one, in Å.
+const DASH_LENGTH: f32 = 0.12;
+const DASH_GAP: f32 = 0.08;
+/// Color of both halves in the neutral style.
+const NEUTRAL_COLOR: Color = Color::srgb(0.6, 0.6, 0.6);
+/// Cylinders above which bonds and contacts are left out; each is an entity, unlike the
+/// instanced atoms.
+const MAX_STICKS: usize = 200_000;
+
+/// How bonds are drawn.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum BondStyle {
+    #[default]
+    Off,
+    /// Gray sticks.
+    Neutral,
+    /// Halves colored by the elements at either end.
+    Bicolor,
+}
+
+impl BondStyle {
+    /// Every style, in the order the settings panel steps through them.
+    pub(crate) const ALL: [BondStyle; 3] = [BondStyle::Off, BondStyle::Neutral, BondStyle::Bicolor];
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            BondStyle::Off => "off",
+            BondStyle::Neutral => "neutral",
+            BondStyle::Bicolor => "bicolor",
+        }
+    }
+}
+
+/// Contacts are drawn between atoms up to this many times the sum of their covalent radii
+/// apart; None leaves them out.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct ContactCutoff(pub Option<f32>);
+
+/// Half of a bond, from an atom to the middle of the bond, or a dash of half a contact.
+#[derive(Component)]
+pub(crate) struct BondStick;
+
+/// One cylinder: the atom it belongs to, where it starts and ends relative to the atom, and its
+/// radius.
+type Stick = (usize, Vec3, Vec3, f32);
+
+// Dashes of the half contact `half`, from an atom to the middle of the contact; the dashes are
+// laid out from the middle so that both halves line up there
+fn dashes(index: usize, half: Vec3, sticks: &mut Vec<Stick>) {
+    let length = half.length();
+    let direction = half.normalize_or(Vec3::Y);
+    let mut end = length - DASH_GAP / 2.0;
+    while end > 0.0 {
+        let start = (end - DASH_LENGTH).max(0.0);
+        sticks.push((index, direction * start, direction * end, CONTACT_RADIUS));
+        end -= DASH_LENGTH + DASH_GAP;
+    }
+}
+
+// Vectors from the shown atoms to the middle of their bonds or contacts with other shown atoms
+fn halves(
+    crystal: &Crystal,
+    overrides: &ElementOverrides,
+    neighbors: Vec<Vec<Neighbor>>,
+) -> Vec<(usize, Vec3)> {
+    let hidden = |index: usize| overrides.is_hidden(crystal.atoms[index].element);
+    let mut halves = Vec::new();
+    for (index, neighbors) in neighbors.iter().enumerate() {
+        if hidden(index) {
+            continue;
+        }
+        halves.extend(
+            neighbors
+                .iter()
+                .filter(|neighbor| !hidden(neighbor.index))
+                .map(|neighbor| (index, neighbor.vector / 2.0)),
+        );
+    }
+    halves
+}
+
+// Rebuild the bond halves and contact dashes when the structure, the cutoffs, the style or the
+// colors change; existing stick entities are reused
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_bonds(
+    mut commands: Commands,
+    crystal: Res<Crystal>,
+    tolerance: Res<BondTolerance>,
+    contacts: Res<ContactCutoff>,
+    style: Res<BondStyle>,
+    color_scheme: Res<ColorScheme>,
+    overrides: Res<ElementOverrides>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut spawned: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut MeshMaterial3d<StandardMaterial>,
+        ),
+        With<BondStick>,
+    >,
+    mut cylinder: Local<Option<Handle<Mesh>>>,
+    mut colors: Local<HashMap<Option<Element>, Handle<StandardMaterial>>>,
+) {
+    let restyled = style.is_changed() || color_scheme.is_changed() || overrides.is_changed();
+    if !crystal.is_changed() && !tolerance.is_changed() && !contacts.is_changed() && !restyled {
+        return;
+    }
+    if restyled {
+        colors.clear();
+    }
+
+    let mut sticks: Vec<Stick> = Vec::new();
+    if *style != BondStyle::Off {
+        let bonds = halves(
+            &crystal,
+            &overrides,
+            bonded_neighbors(&crystal, tolerance.0),
+        );
+        sticks.extend(
+            bonds
+                .into_iter()
+                .map(|(index, half)| (index, Vec3::ZERO, half, BOND_RADIUS)),
+        );
+    }
+    if let Some(reach) = contacts.0 {
+        let contacts = contact_neighbors(&crystal, tolerance.0, reach);
+        for (index, half) in halves(&crystal, &overrides, contacts) {
+            dashes(index, half, &mut sticks);
+        }
+    }
+    if sticks.len() > MAX_STICKS {
+        warn_once!(
+            "Not drawing {} bond and contact sticks, more than {MAX_STICKS}",
+            sticks.len()
+        );
+        sticks.clear();
+    }
+
+    let mesh = cylinder
+        .get_or_insert_with(|| meshes.add(Cylinder::new(1.0, 1.0)))
+        .clone();
+    let mut material = |index: usize| {
+        let element = crystal.atoms[index].element;
+        let key = (*style == BondStyle::Bicolor).then_some(element);
+        colors
+            .entry(key)
+            .or_insert_with_key(|key| {
+                let color = match key {
+                    Some(element) => overrides.color(*color_scheme, *element),
+                    None => NEUTRAL_COLOR,
+                };
+                materials.add(StandardMaterial {
+                    base_color: color,
+                    perceptual_roughness: 0.6,
+                    ..default()
+                })
+            })
+            .clone()
+    };
+    // the unit cylinder stands on Y, centered on the origin
+    let transform = |(index, start, end, radius): Stick| Transform {
+        translation: crystal.atoms[index].position() + (start + end) / 2.0,
+        rotation: Quat::from_rotation_arc(Vec3::Y, (end - start).normalize_or(Vec3::Y)),
+        scale: Vec3::new(radius, start.distance(end), radius),
+    };
+
+    let mut sticks = sticks.into_iter();
+    for (entity, mut stick_transform, mut stick_material) in &mut spawned {
+        match sticks.next() {
+            Some(stick) => {
+                *stick_transform = transform(stick);
+                stick_material.set_if_neq(MeshMaterial3d(material(stick.0)));
+            }
+            None => commands.entity(entity).despawn(),
+        }
+    }
+    for stick in sticks {
+        commands.spawn((
+            Mesh3d(mesh.clone()),
+            MeshMaterial3d(material(stick.0)),
+            transform(stick),
+            BondStick,
+        ));
+    }
+}
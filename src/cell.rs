@@ -0,0 +1,420 @@
+// Unit cell transformations
+// `recell` rebuilds a periodic structure in a different cell of the same lattice;
+// primitive/conventional conversion picks that cell by looking for centering translations
+// (to shrink) or for small integer supercells with more right angles (to grow).
+
+use bevy::math::{IVec3, Mat3, Vec3};
+
+use crate::constants::Element;
+use crate::structure::{Atom, Crystal};
+
+// Sites closer than this (Å) are treated as the same site
+const SITE_TOLERANCE: f32 = 1e-2;
+
+// Cell angles within this many degrees of 90°/120° count as special
+const ANGLE_TOLERANCE: f32 = 0.5;
+
+// Largest volume ratio tried when searching for a conventional cell (F centering is 4)
+const MAX_CONVENTIONAL_MULTIPLE: i32 = 4;
+
+// Largest coefficient of the primitive vectors in a conventional cell vector
+const CONVENTIONAL_RANGE: i32 = 2;
+
+fn wrap(frac: Vec3) -> Vec3 {
+    frac - frac.floor()
+}
+
+// Cartesian distance between two fractional positions, taking the closest periodic image
+fn periodic_distance(lattice: Mat3, a: Vec3, b: Vec3) -> f32 {
+    let d = a - b;
+    (lattice * (d - d.round())).length()
+}
+
+// Rebuild `crystal` in the cell `new_lattice` (columns are cell vectors), which must span the
+// same lattice or a superlattice/sublattice of it. Every site whose image falls into the new
+// cell is kept once.
+pub(crate) fn recell(crystal: &Crystal, new_lattice: Mat3) -> Option<Crystal> {
+    let lattice = crystal.lattice?;
+    let inverse = lattice.inverse();
+    let new_inverse = new_lattice.inverse();
+
+    // Range of old-cell translations needed to cover every corner of the new cell
+    let corners = (0..8).map(|i| {
+        inverse
+            * (new_lattice
+                * Vec3::new((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32))
+    });
+    let (lo, hi) = corners.fold(
+        (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+        |(lo, hi), c| (lo.min(c), hi.max(c)),
+    );
+    let lo = lo.floor().as_ivec3() - IVec3::ONE;
+    let hi = hi.ceil().as_ivec3() + IVec3::ONE;
+
+    // Shrinking the cell maps several old sites onto one new site
+    let shrinking = new_lattice.determinant().abs() < lattice.determinant().abs() - 1e-6;
+    let eps = SITE_TOLERANCE / new_lattice.x_axis.length().max(1.0);
+
+    let mut atoms: Vec<Atom> = Vec::new();
+    // atom of `crystal` each new atom is an image of
+    let mut sources: Vec<usize> = Vec::new();
+    let mut sites: Vec<(Vec3, Element)> = Vec::new();
+    for (source, atom) in crystal.atoms.iter().enumerate() {
+        let frac = wrap(inverse * atom.position());
+        for i in lo.x..=hi.x {
+            for j in lo.y..=hi.y {
+                for k in lo.z..=hi.z {
+                    let shifted = lattice * (frac + Vec3::new(i as f32, j as f32, k as f32));
+                    let new_frac = new_inverse * shifted;
+                    if new_frac.cmplt(Vec3::splat(-eps)).any()
+                        || new_frac.cmpge(Vec3::splat(1.0 - eps)).any()
+                    {
+                        continue;
+                    }
+                    let new_frac = wrap(new_frac);
+
+                    if shrinking
+                        && sites.iter().any(|&(site, element)| {
+                            element == atom.element
+                                && periodic_distance(new_lattice, site, new_frac) < SITE_TOLERANCE
+                        })
+                    {
+                        continue;
+                    }
+                    sites.push((new_frac, atom.element));
+
+                    let position = new_lattice * new_frac;
+                    atoms.push(Atom {
+                        x: position.x,
+                        y: position.y,
+                        z: position.z,
+                        ..atom.clone()
+                    });
+                    sources.push(source);
+                }
+            }
+        }
+    }
+
+    Some(Crystal {
+        atoms,
+        lattice: Some(new_lattice),
+        pbc: crystal.pbc,
+        properties: crystal.properties.select(crystal.atoms.len(), &sources),
+        metadata: crystal.metadata.clone(),
+    })
+}
+
+// Pure translations (fractional, excluding zero) that map the structure onto itself
+fn centering_translations(crystal: &Crystal, lattice: Mat3) -> Vec<Vec3> {
+    let inverse = lattice.inverse();
+    let sites: Vec<(Vec3, Element)> = crystal
+        .atoms
+        .iter()
+        .map(|atom| (wrap(inverse * atom.position()), atom.element))
+        .collect();
+
+    // Candidates come from the least common species, which keeps the search small
+    let mut rarest: Option<(Element, usize)> = None;
+    for &(_, element) in &sites {
+        let count = sites.iter().filter(|(_, e)| *e == element).count();
+        if rarest.is_none_or(|(_, best)| count < best) {
+            rarest = Some((element, count));
+        }
+    }
+    let Some((rare, _)) = rarest else {
+        return Vec::new();
+    };
+    let Some(&(origin, _)) = sites.iter().find(|(_, e)| *e == rare) else {
+        return Vec::new();
+    };
+
+    sites
+        .iter()
+        .filter(|(_, e)| *e == rare)
+        .map(|&(site, _)| wrap(site - origin))
+        .filter(|t| (lattice * (*t - t.round())).length() > SITE_TOLERANCE)
+        .filter(|&t| {
+            sites.iter().all(|&(site, element)| {
+                sites.iter().any(|&(other, e)| {
+                    e == element && periodic_distance(lattice, site + t, other) < SITE_TOLERANCE
+                })
+            })
+        })
+        .collect()
+}
+
+// Smallest cell of the same structure, or None when the cell is already primitive
+pub(crate) fn find_primitive(crystal: &Crystal) -> Option<Crystal> {
+    let lattice = crystal.lattice?;
+    let translations = centering_translations(crystal, lattice);
+    if translations.is_empty() {
+        return None;
+    }
+
+    // Lattice vectors of the translation group near the origin, shortest first
+    let mut candidates: Vec<Vec3> = Vec::new();
+    for t in translations
+        .iter()
+        .copied()
+        .chain([Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z])
+    {
+        for i in -1..=1 {
+            for j in -1..=1 {
+                for k in -1..=1 {
+                    let v = lattice * (t + Vec3::new(i as f32, j as f32, k as f32));
+                    if v.length() > SITE_TOLERANCE {
+                        candidates.push(v);
+                    }
+                }
+            }
+        }
+    }
+    candidates.sort_by(|a, b| a.length().total_cmp(&b.length()));
+    let mut unique: Vec<Vec3> = Vec::new();
+    for v in candidates {
+        if !unique.iter().any(|u| u.distance(v) < SITE_TOLERANCE) {
+            unique.push(v);
+        }
+    }
+    let candidates = unique;
+
+    // Any three lattice vectors spanning the primitive volume form a basis
+    let target = lattice.determinant().abs() / (translations.len() + 1) as f32;
+    let n = candidates.len().min(40);
+    for i in 0..n {
+        for j in i + 1..n {
+            for k in j + 1..n {
+                let basis = Mat3::from_cols(candidates[i], candidates[j], candidates[k]);
+                let volume = basis.determinant();
+                if (volume.abs() - target).abs() < 1e-3 * target {
+                    let basis = if volume < 0.0 {
+                        Mat3::from_cols(candidates[i], candidates[k], candidates[j])
+                    } else {
+                        basis
+                    };
+                    return recell(crystal, basis);
+                }
+            }
+        }
+    }
+    None
+}
+
+fn angle_between(a: Vec3, b: Vec3) -> f32 {
+    a.angle_between(b).to_degrees()
+}
+
+// How "conventional" a cell looks: right angles count most, a 120° angle next to two
+// right angles marks the hexagonal setting
+fn cell_score(lattice: Mat3) -> i32 {
+    let [a, b, c] = [lattice.x_axis, lattice.y_axis, lattice.z_axis];
+    let angles = [
+        angle_between(b, c),
+        angle_between(a, c),
+        angle_between(a, b),
+    ];
+    let right = angles
+        .iter()
+        .filter(|angle| (*angle - 90.0).abs() < ANGLE_TOLERANCE)
+        .count() as i32;
+    let hexagonal = right == 2
+        && angles
+            .iter()
+            .any(|angle| (*angle - 120.0).abs() < ANGLE_TOLERANCE);
+    3 * right + if hexagonal { 2 } else { 0 }
+}
+
+// Number of equal-length cell vector pairs, to prefer cubic over tetragonal settings
+fn equal_edges(lattice: Mat3) -> usize {
+    let lengths = [
+        lattice.x_axis.length(),
+        lattice.y_axis.length(),
+        lattice.z_axis.length(),
+    ];
+    [(0, 1), (0, 2), (1, 2)]
+        .iter()
+        .filter(|&&(i, j)| (lengths[i] - lengths[j]).abs() < SITE_TOLERANCE)
+        .count()
+}
+
+// Conventional (centered, higher-symmetry-looking) cell of the lattice, or None when no
+// small supercell looks more conventional than the current one
+pub(crate) fn find_conventional(crystal: &Crystal) -> Option<Crystal> {
+    let lattice = crystal.lattice?;
+    let current = cell_score(lattice);
+    // hexagonal cells are already in their conventional setting
+    if current >= 8 {
+        return None;
+    }
+
+    // Short lattice vectors; conventional cell vectors are small combinations of primitive ones
+    let mut vectors: Vec<Vec3> = Vec::new();
+    for i in -CONVENTIONAL_RANGE..=CONVENTIONAL_RANGE {
+        for j in -CONVENTIONAL_RANGE..=CONVENTIONAL_RANGE {
+            for k in -CONVENTIONAL_RANGE..=CONVENTIONAL_RANGE {
+                if (i, j, k) != (0, 0, 0) {
+                    vectors.push(lattice * Vec3::new(i as f32, j as f32, k as f32));
+                }
+            }
+        }
+    }
+
+    let volume = lattice.determinant().abs();
+    let mut best: Option<(Mat3, (i32, usize, i32))> = None;
+    for i in 0..vectors.len() {
+        for j in i + 1..vectors.len() {
+            for k in j + 1..vectors.len() {
+                let mut candidate = Mat3::from_cols(vectors[i], vectors[j], vectors[k]);
+                let ratio = candidate.determinant() / volume;
+                let multiple = ratio.abs().round() as i32;
+                if !(1..=MAX_CONVENTIONAL_MULTIPLE).contains(&multiple)
+                    || (ratio.abs() - multiple as f32).abs() > 1e-3
+                {
+                    continue;
+                }
+                if ratio < 0.0 {
+                    candidate = Mat3::from_cols(vectors[i], vectors[k], vectors[j]);
+                }
+
+                let score = cell_score(candidate);
+                if score <= current {
+                    continue;
+                }
+                // higher score first, then more equal edges (cubic beats tetragonal), then smaller cell
+                let rank = (score, equal_edges(candidate), -multiple);
+                let better = match &best {
+                    None => true,
+                    Some((best_cell, best_rank)) => {
+                        rank > *best_rank
+                            || (rank == *best_rank && edge_sum(candidate) < edge_sum(*best_cell))
+                    }
+                };
+                if better {
+                    best = Some((candidate, rank));
+                }
+            }
+        }
+    }
+
+    let (new_lattice, _) = best?;
+    recell(crystal, new_lattice)
+}
+
+fn edge_sum(lattice: Mat3) -> f32 {
+    lattice.x_axis.length() + lattice.y_axis.length() + lattice.z_axis.length()
+}
+
+// Cell lengths a, b, c (Å) and angles α, β, γ (degrees) of a lattice
+pub(crate) fn lattice_parameters(lattice: Mat3) -> [f32; 6] {
+    let [a, b, c] = [lattice.x_axis, lattice.y_axis, lattice.z_axis];
+    [
+        a.length(),
+        b.length(),
+        c.length(),
+        angle_between(b, c),
+        angle_between(a, c),
+        angle_between(a, b),
+    ]
+}
+
+// Lattice with the given parameters in the standard orientation (a along x, b in the xy
+// plane), or None when the angles don't describe a cell
+pub(crate) fn lattice_from_parameters(parameters: [f32; 6]) -> Option<Mat3> {
+    let [a, b, c, alpha, beta, gamma] = parameters;
+    let (cos_alpha, cos_beta) = (alpha.to_radians().cos(), beta.to_radians().cos());
+    let (sin_gamma, cos_gamma) = gamma.to_radians().sin_cos();
+    if a <= 0.0 || b <= 0.0 || c <= 0.0 || sin_gamma.abs() < 1e-6 {
+        return None;
+    }
+
+    let cx = cos_beta;
+    let cy = (cos_alpha - cos_beta * cos_gamma) / sin_gamma;
+    let cz_squared = 1.0 - cx * cx - cy * cy;
+    if cz_squared <= 1e-6 {
+        return None;
+    }
+    Some(Mat3::from_cols(
+        Vec3::new(a, 0.0, 0.0),
+        Vec3::new(b * cos_gamma, b * sin_gamma, 0.0),
+        c * Vec3::new(cx, cy, cz_squared.sqrt()),
+    ))
+}
+
+// Put the structure into a cell with new parameters, keeping fractional coordinates and the
+// orientation of the current cell
+pub(crate) fn rescale_lattice(crystal: &Crystal, parameters: [f32; 6]) -> Option<Crystal> {
+    let lattice = crystal.lattice?;
+    let current = lattice_from_parameters(lattice_parameters(lattice))?;
+    let target = lattice_from_parameters(parameters)?;
+    // rotation taking the standard orientation to the current one
+    let new_lattice = lattice * current.inverse() * target;
+    let inverse = lattice.inverse();
+
+    let atoms = crystal
+        .atoms
+        .iter()
+        .map(|atom| {
+            let position = new_lattice * (inverse * atom.position());
+            Atom {
+                x: position.x,
+                y: position.y,
+                z: position.z,
+                ..atom.clone()
+            }
+        })
+        .collect();
+
+    Some(Crystal {
+        atoms,
+        lattice: Some(new_lattice),
+        pbc: crystal.pbc,
+        properties: crystal.properties.clone(),
+        metadata: crystal.metadata.clone(),
+    })
+}
+
+// Structure repeated `repeats` times along the cell vectors
+pub(crate) fn supercell(crystal: &Crystal, repeats: IVec3) -> Option<Crystal> {
+    let lattice = crystal.lattice?;
+    recell(
+        crystal,
+        lattice * Mat3::from_diagonal(repeats.max(IVec3::ONE).as_vec3()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn primitive_of_conventional_fcc_has_a_quarter_of_the_volume() {
+        let a = 3.61;
+        let atoms = [
+            [0.0, 0.0, 0.0],
+            [0.5, 0.5, 0.0],
+            [0.5, 0.0, 0.5],
+            [0.0, 0.5, 0.5],
+        ]
+        .map(|site| Atom::new("Cu", Vec3::from(site) * a))
+        .to_vec();
+        let conventional = Crystal::periodic(atoms, Mat3::from_diagonal(Vec3::splat(a)));
+
+        let primitive = find_primitive(&conventional).expect("fcc has a smaller cell");
+        let volume = primitive.lattice.unwrap().determinant().abs();
+        assert!((volume - a.powi(3) / 4.0).abs() < 1e-3);
+        assert_eq!(primitive.atoms.len(), 1);
+    }
+
+    #[test]
+    fn primitive_cell_has_no_smaller_cell() {
+        let a = 3.61;
+        let lattice = Mat3::from_cols(
+            Vec3::new(0.0, a, a) / 2.0,
+            Vec3::new(a, 0.0, a) / 2.0,
+            Vec3::new(a, a, 0.0) / 2.0,
+        );
+        let primitive = Crystal::periodic(vec![Atom::new("Cu", Vec3::ZERO)], lattice);
+        assert!(find_primitive(&primitive).is_none());
+    }
+}
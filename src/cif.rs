@@ -0,0 +1,325 @@
+// CIF reader
+// Splits the first data block of a CIF file into its loops, then reads the structure out of
+// them. For now that is the `_atom_site` Cartesian coordinates, atom names and occupancies of
+// mmCIF files as served by the PDB; the name of the data block is the title.
+
+use anyhow::{Context, Result};
+
+use crate::constants::Element;
+use crate::structure::{Atom, AtomProperties, Crystal};
+
+// A value or tag of a CIF file; quoted and text-field values can look like tags
+struct Token<'a> {
+    text: &'a str,
+    quoted: bool,
+}
+
+impl Token<'_> {
+    fn is_keyword(&self, keyword: &str) -> bool {
+        // by bytes, since a value may have a multibyte character where the keyword ends
+        !self.quoted
+            && self
+                .text
+                .as_bytes()
+                .get(..keyword.len())
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case(keyword.as_bytes()))
+    }
+
+    fn is_tag(&self) -> bool {
+        !self.quoted && self.text.starts_with('_')
+    }
+}
+
+fn tokenize(contents: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = contents;
+    let mut line_start = true;
+
+    while !rest.is_empty() {
+        // text fields run from a line starting with ';' to the next such line
+        if line_start && rest.starts_with(';') {
+            let body = &rest[1..];
+            let end = body.find("\n;").map_or(body.len(), |end| end + 1);
+            tokens.push(Token {
+                text: body[..end].trim(),
+                quoted: true,
+            });
+            rest = body.get(end + 1..).unwrap_or("");
+            line_start = false;
+            continue;
+        }
+
+        let c = rest.chars().next().unwrap_or(' ');
+        if c.is_whitespace() {
+            line_start = c == '\n';
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        line_start = false;
+
+        if c == '#' {
+            rest = rest.find('\n').map_or("", |end| &rest[end..]);
+        } else if c == '\'' || c == '"' {
+            // a quote only closes the value when followed by whitespace
+            let body = &rest[1..];
+            let end = body
+                .char_indices()
+                .find(|&(i, ch)| {
+                    ch == c && body[i + 1..].chars().next().is_none_or(char::is_whitespace)
+                })
+                .map_or(body.len(), |(i, _)| i);
+            tokens.push(Token {
+                text: &body[..end],
+                quoted: true,
+            });
+            rest = body.get(end + 1..).unwrap_or("");
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            tokens.push(Token {
+                text: &rest[..end],
+                quoted: false,
+            });
+            rest = &rest[end..];
+        }
+    }
+    tokens
+}
+
+// Tags of one `loop_` and its values, row by row
+struct CifLoop<'a> {
+    tags: Vec<&'a str>,
+    values: Vec<&'a str>,
+}
+
+impl<'a> CifLoop<'a> {
+    fn column(&self, tag: &str) -> Option<usize> {
+        self.tags.iter().position(|t| t.eq_ignore_ascii_case(tag))
+    }
+
+    fn rows(&self) -> impl Iterator<Item = &[&'a str]> {
+        self.values.chunks_exact(self.tags.len())
+    }
+}
+
+// Name and loops of the first data block
+struct CifBlock<'a> {
+    name: &'a str,
+    loops: Vec<CifLoop<'a>>,
+}
+
+impl<'a> CifBlock<'a> {
+    fn parse(contents: &'a str) -> Result<Self> {
+        let tokens = tokenize(contents);
+        let start = tokens
+            .iter()
+            .position(|token| token.is_keyword("data_"))
+            .context("No data block")?;
+
+        let mut block = CifBlock {
+            name: &tokens[start].text["data_".len()..],
+            loops: Vec::new(),
+        };
+        let mut i = start + 1;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            if token.is_keyword("data_") {
+                break;
+            }
+            if token.is_keyword("loop_") {
+                i += 1;
+                let mut cif_loop = CifLoop {
+                    tags: Vec::new(),
+                    values: Vec::new(),
+                };
+                while i < tokens.len() && tokens[i].is_tag() {
+                    cif_loop.tags.push(tokens[i].text);
+                    i += 1;
+                }
+                while i < tokens.len()
+                    && !tokens[i].is_tag()
+                    && !tokens[i].is_keyword("loop_")
+                    && !tokens[i].is_keyword("data_")
+                {
+                    cif_loop.values.push(tokens[i].text);
+                    i += 1;
+                }
+                if !cif_loop.tags.is_empty() {
+                    block.loops.push(cif_loop);
+                }
+            } else if token.is_tag() {
+                // single items are not needed yet; skip the value too
+                i += 2;
+            } else {
+                i += 1;
+            }
+        }
+        Ok(block)
+    }
+
+    fn find_loop(&self, tag: &str) -> Option<&CifLoop<'a>> {
+        self.loops.iter().find(|l| l.column(tag).is_some())
+    }
+}
+
+// Numeric value, ignoring a standard uncertainty such as the (3) in 1.234(3)
+fn number(value: &str) -> Option<f32> {
+    value.split('(').next()?.parse().ok()
+}
+
+// Atoms of an mmCIF file; only the first model and the first alternate location are kept
+pub(crate) fn parse_mmcif(contents: &str) -> Result<Crystal> {
+    let block = CifBlock::parse(contents)?;
+    let atom_site = block
+        .find_loop("_atom_site.Cartn_x")
+        .context("No _atom_site coordinates")?;
+    let column = |tag: &str| {
+        atom_site
+            .column(tag)
+            .with_context(|| format!("Missing {tag}"))
+    };
+    let [x, y, z] = [
+        column("_atom_site.Cartn_x")?,
+        column("_atom_site.Cartn_y")?,
+        column("_atom_site.Cartn_z")?,
+    ];
+    // without type symbols the element comes from the atom name, by its first letter
+    let (symbol, element): (usize, fn(&str) -> Element) =
+        match atom_site.column("_atom_site.type_symbol") {
+            Some(symbol) => (symbol, Element::parse),
+            None => (column("_atom_site.label_atom_id")?, Element::from_atom_name),
+        };
+    // atom names such as CA, kept as site labels
+    let label = atom_site.column("_atom_site.label_atom_id");
+    let occupancy = atom_site.column("_atom_site.occupancy");
+    let model = atom_site.column("_atom_site.pdbx_PDB_model_num");
+    let alt_id = atom_site.column("_atom_site.label_alt_id");
+
+    let mut first_model = None;
+    let mut atoms = Vec::new();
+    let mut labels = Vec::new();
+    let mut occupancies = Vec::new();
+    for row in atom_site.rows() {
+        if let Some(model) = model {
+            if *first_model.get_or_insert(row[model]) != row[model] {
+                continue;
+            }
+        }
+        if alt_id.is_some_and(|alt| !matches!(row[alt], "." | "?" | "A")) {
+            continue;
+        }
+        let position = [row[x], row[y], row[z]].map(number);
+        let [Some(px), Some(py), Some(pz)] = position else {
+            continue;
+        };
+        atoms.push(Atom {
+            element: element(row[symbol]),
+            x: px,
+            y: py,
+            z: pz,
+        });
+        if let Some(label) = label {
+            labels.push(row[label].to_string());
+        }
+        if let Some(occupancy) = occupancy {
+            occupancies.push(number(row[occupancy]).unwrap_or(1.0).clamp(0.0, 1.0));
+        }
+    }
+    let mut crystal = Crystal::molecule(atoms);
+    if label.is_some() {
+        crystal
+            .properties
+            .strings
+            .insert(AtomProperties::LABELS.to_string(), labels);
+    }
+    if occupancy.is_some() {
+        crystal
+            .properties
+            .scalars
+            .insert(AtomProperties::OCCUPANCIES.to_string(), occupancies);
+    }
+    if !block.name.is_empty() {
+        crystal.metadata.title = Some(block.name.to_string());
+    }
+    Ok(crystal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MMCIF: &str = "data_1ABC
+_struct.title 'Crambin, résolution 0.48 Å'
+_struct.keywords abcdé
+loop_
+_atom_site.group_PDB
+_atom_site.type_symbol
+_atom_site.label_atom_id
+_atom_site.label_alt_id
+_atom_site.Cartn_x
+_atom_site.Cartn_y
+_atom_site.Cartn_z
+_atom_site.occupancy
+_atom_site.pdbx_PDB_model_num
+ATOM N N  . 1.0 2.0 3.0 1.00 1
+ATOM C CA A 2.0 2.0 3.0 0.60 1
+ATOM C CA B 2.1 2.0 3.0 0.40 1
+ATOM O O  . 3.0(2) 2.0 3.0 1.00 1
+ATOM N N  . 9.0 9.0 9.0 1.00 2
+";
+
+    #[test]
+    fn keyword_check_survives_multibyte_values() {
+        for text in ["abcdé", "dataé", "é", "loo"] {
+            let token = Token {
+                text,
+                quoted: false,
+            };
+            assert!(!token.is_keyword("data_"));
+            assert!(!token.is_keyword("loop_"));
+        }
+        let token = Token {
+            text: "DATA_é",
+            quoted: false,
+        };
+        assert!(token.is_keyword("data_"));
+    }
+
+    #[test]
+    fn reads_the_first_model_and_location() {
+        let crystal = parse_mmcif(MMCIF).unwrap();
+        let elements: Vec<Element> = crystal.atoms.iter().map(|atom| atom.element).collect();
+        assert_eq!(elements, [Element::N, Element::C, Element::O]);
+        assert_eq!(crystal.atoms[2].x, 3.0);
+        assert_eq!(crystal.metadata.title.as_deref(), Some("1ABC"));
+        assert_eq!(
+            crystal.properties.strings[AtomProperties::LABELS],
+            ["N", "CA", "O"]
+        );
+        assert_eq!(
+            crystal.properties.scalars[AtomProperties::OCCUPANCIES],
+            [1.0, 0.6, 1.0]
+        );
+    }
+
+    #[test]
+    fn text_fields_and_quotes_are_single_values() {
+        let tokens = tokenize("_a 'it''s here' \"x y\"\n;line one\nline two\n;\n_b");
+        let texts: Vec<&str> = tokens.iter().map(|token| token.text).collect();
+        assert_eq!(
+            texts,
+            ["_a", "it''s here", "x y", "line one\nline two", "_b"]
+        );
+    }
+
+    #[test]
+    fn atom_names_stand_in_for_missing_type_symbols() {
+        let contents = MMCIF
+            .replace("_atom_site.type_symbol\n", "")
+            .replace("ATOM N N ", "ATOM N ")
+            .replace("ATOM C CA", "ATOM CA")
+            .replace("ATOM O O ", "ATOM O ");
+        let crystal = parse_mmcif(&contents).unwrap();
+        let elements: Vec<Element> = crystal.atoms.iter().map(|atom| atom.element).collect();
+        assert_eq!(elements, [Element::N, Element::C, Element::O]);
+    }
+}
@@ -0,0 +1,170 @@
+// Command-line interface
+// Parsed in main.rs and handed to `run_app`, which keeps it as a resource for the systems
+// that open files or set up connections. The web build starts from the defaults, amended by
+// the query string of the page (see web.rs).
+// `--headless` runs a command without opening a window instead, e.g.
+// `vizmat --headless render water.xyz -o water.png`.
+
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use clap::{Args, Parser, Subcommand};
+
+use crate::parse::Format;
+
+/// Options of the `vizmat` binary.
+#[derive(Parser, Resource, Debug, Clone, Default)]
+#[command(
+    name = "vizmat",
+    version,
+    about = "Interactive viewer for crystals and molecules"
+)]
+pub struct Cli {
+    /// Structure files to open; several files, or files with several frames, can be stepped
+    /// through with the arrow keys. A single file is reloaded whenever it changes.
+    pub files: Vec<PathBuf>,
+
+    /// Format of the structure files, instead of guessing it from their extension
+    #[arg(long, value_enum, global = true)]
+    pub format: Option<Format>,
+
+    /// Frame to show first when the files hold several (counting from 0)
+    #[arg(long, value_name = "N", global = true)]
+    pub frame: Option<usize>,
+
+    /// Images of a nudged elastic band calculation to show together: a directory of numbered
+    /// image directories (00, 01, ...) holding POSCAR or CONTCAR files, or a file with one
+    /// frame per image
+    #[arg(long, value_name = "PATH")]
+    pub neb: Option<PathBuf>,
+
+    /// Vibrational modes to animate, from a Molden file or a phonopy YAML file written with
+    /// eigenvectors (qpoints.yaml, band.yaml or mesh.yaml)
+    #[arg(long, value_name = "FILE")]
+    pub modes: Option<PathBuf>,
+
+    /// Volumetric data to show, from a Gaussian cube or XSF file; repeat to add the grids of
+    /// further files, the atoms being those of the first
+    #[arg(long = "volume", value_name = "FILE")]
+    pub volumes: Vec<PathBuf>,
+
+    /// Structure file to download at startup
+    #[cfg(feature = "fetch")]
+    #[arg(long, value_name = "URL")]
+    pub url: Option<String>,
+
+    /// WebSocket server streaming structures [default: ws://127.0.0.1:9001]
+    #[cfg(feature = "websocket")]
+    #[arg(long, value_name = "URL")]
+    pub ws_url: Option<String>,
+
+    /// Additional named WebSocket server, as NAME=URL; repeat to follow several
+    #[cfg(feature = "websocket")]
+    #[arg(long = "source", value_name = "NAME=URL")]
+    pub sources: Vec<String>,
+
+    /// Read XYZ frames or JSON messages from standard input instead of a server
+    #[cfg(feature = "websocket")]
+    #[arg(long)]
+    pub stdin: bool,
+
+    /// Do not connect to or listen for a structure server
+    #[cfg(feature = "websocket")]
+    #[arg(long, conflicts_with_all = ["ws_url", "sources", "stdin"])]
+    pub no_network: bool,
+
+    /// Accept structure pushes on ADDRESS instead of connecting to a server; a bare port
+    /// listens on localhost only
+    #[cfg(all(feature = "websocket", not(target_arch = "wasm32")))]
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        env = "VIZMAT_LISTEN",
+        conflicts_with_all = ["ws_url", "sources", "stdin", "ipc", "zmq", "no_network"]
+    )]
+    pub listen: Option<String>,
+
+    /// Listen on a local socket (named pipe on Windows) for processes on the same machine,
+    /// given as a path or a bare name
+    #[cfg(all(feature = "websocket", not(target_arch = "wasm32")))]
+    #[arg(
+        long,
+        value_name = "SOCKET",
+        env = "VIZMAT_IPC",
+        conflicts_with_all = ["ws_url", "sources", "stdin", "zmq", "no_network"]
+    )]
+    pub ipc: Option<String>,
+
+    /// Subscribe to a ZeroMQ publisher, e.g. tcp://host:port (needs the `zmq` feature)
+    #[cfg(all(feature = "websocket", not(target_arch = "wasm32")))]
+    #[arg(
+        long,
+        value_name = "ENDPOINT",
+        env = "VIZMAT_ZMQ",
+        conflicts_with_all = ["ws_url", "sources", "stdin", "no_network"]
+    )]
+    pub zmq: Option<String>,
+
+    /// Access token sent to the server as a bearer token and in the hello; with `--listen`,
+    /// clients must present it. The browser takes it from the page URL.
+    #[cfg(feature = "websocket")]
+    #[arg(
+        long,
+        value_name = "TOKEN",
+        env = "VIZMAT_TOKEN",
+        hide_env_values = true,
+        conflicts_with_all = ["stdin", "ipc", "zmq", "no_network"]
+    )]
+    pub token: Option<String>,
+
+    /// Rhai script to run once the viewer has started; repeat to run several in order
+    /// (needs the `scripting` feature)
+    #[arg(long = "script", value_name = "FILE")]
+    pub scripts: Vec<PathBuf>,
+
+    /// Run the given command without opening a window
+    #[arg(long)]
+    pub headless: bool,
+
+    /// CSS selector of the canvas to draw into, given by the page in the browser
+    #[cfg(target_arch = "wasm32")]
+    #[arg(skip)]
+    pub canvas: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+// Scripts given to a build without the engine
+#[cfg(not(feature = "scripting"))]
+pub(crate) fn reject_script_arguments(cli: Res<Cli>) {
+    if !cli.scripts.is_empty() {
+        error!("Running scripts needs the `scripting` feature");
+    }
+}
+
+/// Commands run without a window.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Render a structure to an image file and exit
+    Render(RenderArgs),
+}
+
+/// Options of the `render` command.
+#[derive(Args, Debug, Clone)]
+pub struct RenderArgs {
+    /// Structure file to render
+    pub file: PathBuf,
+
+    /// Image to write; the format follows the extension (png, jpg, ...)
+    #[arg(short, long, value_name = "FILE")]
+    pub output: PathBuf,
+
+    /// Width of the image in pixels
+    #[arg(long, default_value_t = 1600)]
+    pub width: u32,
+
+    /// Height of the image in pixels
+    #[arg(long, default_value_t = 1200)]
+    pub height: u32,
+}
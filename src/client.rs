@@ -1,166 +1,1464 @@
 // WebSocket client module for connecting to structure update server
 // Supports both native (async-tungstenite) and WASM (web-sys) targets
+// The server is ws://127.0.0.1:9001 unless `--ws-url` or the configuration file names another;
+// `--no-network` skips every transport below.
+// Natively, `--stdin` reads the same messages (one JSON object per line) or plain XYZ frames
+// from standard input instead, e.g. `my_md | vizmat --stdin`. With the `zmq` feature,
+// `--zmq tcp://host:port` subscribes to a ZeroMQ publisher sending those messages.
+// `--ipc <path or name>` listens on a Unix socket (named pipe on Windows) for the same
+// line-based input from processes on the same machine, and `--listen 9001` accepts structure
+// pushes over WebSocket instead of connecting. These options exclude each other (see cli.rs),
+// and each can also be given as VIZMAT_ZMQ, VIZMAT_IPC and VIZMAT_LISTEN.
+// `wss://` URLs connect over TLS, and `--token` (or VIZMAT_TOKEN) is sent as a bearer token
+// in the handshake and in the hello; in server mode, clients must present the same token.
+// Several servers can be followed at once with repeated `--source name=ws://host:port`; a
+// dropdown under the connection indicator picks the one that drives the scene, while the
+// others keep their newest structure for when they are picked.
+// Besides receiving structures, the viewer reports picked atoms, local structure edits and the
+// camera pose back to the server.
 
-use crate::structure::{Atom, UpdateStructure};
+use crate::cli::Cli;
+use crate::config::Config;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::parse::parse_structure;
+use crate::protocol::{
+    lattice_to_rows, AtomData, ClientMessage, Encoding, Frame, Hello, PeerCell, RpcRequest,
+    ServerMessage, PROTOCOL_VERSION,
+};
+use crate::structure::{Atom, Crystal, Selection, UpdateStructure};
+use crate::theme::{Themed, UiTheme};
+use crate::toast::Toast;
+use crate::trajectory::{FrameInfo, StreamedFrame, Trajectory};
+use crate::ui::{CameraRig, MainCamera, SidePanelColumn};
 use bevy::prelude::*;
 use crossbeam_channel::{unbounded, Receiver, Sender};
-use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct AtomData {
-    element: String,
-    x: f32,
-    y: f32,
-    z: f32,
+// Delay before the first reconnection attempt; doubled after every failure up to the maximum
+const INITIAL_BACKOFF_SECS: f32 = 0.5;
+const MAX_BACKOFF_SECS: f32 = 30.0;
+
+const SERVER_URL: &str = "ws://127.0.0.1:9001";
+
+// Minimum time between two camera pose messages
+const CAMERA_SEND_INTERVAL_SECS: f32 = 0.1;
+
+fn next_backoff(delay: f32) -> f32 {
+    (delay * 2.0).min(MAX_BACKOFF_SECS)
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct StructureMessage {
-    atoms: Vec<AtomData>,
+// The token set up for the page, which the reconnections in the browser look up
+#[cfg(target_arch = "wasm32")]
+fn auth_token() -> Option<String> {
+    WASM_TOKEN.with(|token| token.borrow().clone())
 }
 
-impl From<AtomData> for Atom {
-    fn from(data: AtomData) -> Self {
-        Atom {
-            element: data.element,
-            x: data.x,
-            y: data.y,
-            z: data.z,
+// State of the connection to the structure server
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum ConnectionState {
+    #[default]
+    Connecting,
+    Connected,
+    // Waiting `retry_in` seconds before reconnection attempt number `attempt`
+    Reconnecting {
+        attempt: u32,
+        retry_in: f32,
+    },
+    // Server mode with the number of connected clients
+    Listening {
+        clients: usize,
+    },
+    // Server mode could not bind its address
+    ListenFailed,
+    // Reading from standard input, until it is closed
+    Stdin {
+        open: bool,
+    },
+    // Subscribed to a ZeroMQ publisher, or failed to; whether the publisher is up cannot be told
+    #[cfg(feature = "zmq")]
+    Zmq {
+        subscribed: bool,
+    },
+    // Started with `--no-network`
+    Offline,
+}
+
+impl ConnectionState {
+    fn label(self) -> String {
+        match self {
+            ConnectionState::Connecting => "Server: connecting...".to_string(),
+            ConnectionState::Connected => "Server: connected".to_string(),
+            ConnectionState::Reconnecting { attempt, retry_in } => {
+                format!("Server: offline, retry #{attempt} in {retry_in:.1}s")
+            }
+            ConnectionState::Listening { clients } => {
+                format!("Listening: {clients} client(s)")
+            }
+            ConnectionState::ListenFailed => "Listening: failed to bind".to_string(),
+            ConnectionState::Stdin { open: true } => "Input: stdin".to_string(),
+            ConnectionState::Stdin { open: false } => "Input: stdin closed".to_string(),
+            #[cfg(feature = "zmq")]
+            ConnectionState::Zmq { subscribed: true } => "ZMQ: subscribed".to_string(),
+            #[cfg(feature = "zmq")]
+            ConnectionState::Zmq { subscribed: false } => "ZMQ: failed to subscribe".to_string(),
+            ConnectionState::Offline => "Network: off".to_string(),
         }
     }
+
+    fn color(self, theme: &UiTheme) -> Color {
+        match self {
+            ConnectionState::Connecting => theme.pending,
+            ConnectionState::Connected => theme.positive,
+            ConnectionState::Reconnecting { .. } | ConnectionState::ListenFailed => theme.negative,
+            ConnectionState::Listening { clients: 0 } => theme.pending,
+            ConnectionState::Listening { .. } => theme.positive,
+            ConnectionState::Stdin { open: true } => theme.positive,
+            ConnectionState::Stdin { open: false } | ConnectionState::Offline => theme.placeholder,
+            #[cfg(feature = "zmq")]
+            ConnectionState::Zmq { subscribed: true } => theme.positive,
+            #[cfg(feature = "zmq")]
+            ConnectionState::Zmq { subscribed: false } => theme.negative,
+        }
+    }
+
+    // Toast announcing the change from `previous` to this state, for changes worth interrupting
+    // the user for
+    fn toast(self, previous: ConnectionState) -> Option<Toast> {
+        match (previous, self) {
+            (ConnectionState::Connected, ConnectionState::Connected) => None,
+            (_, ConnectionState::Connected) => Some(Toast::info("Connected to server")),
+            (ConnectionState::Connected, ConnectionState::Reconnecting { .. }) => {
+                Some(Toast::warning("Lost connection to server, reconnecting"))
+            }
+            (_, ConnectionState::ListenFailed) => {
+                Some(Toast::error("Failed to listen for clients"))
+            }
+            (ConnectionState::Stdin { open: true }, ConnectionState::Stdin { open: false }) => {
+                Some(Toast::info("Standard input closed"))
+            }
+            #[cfg(feature = "zmq")]
+            (_, ConnectionState::Zmq { subscribed: false }) => {
+                Some(Toast::error("Failed to subscribe to the ZMQ publisher"))
+            }
+            _ => None,
+        }
+    }
+
+    // Whether messages for the server have anyone to go to
+    fn is_connected(self) -> bool {
+        matches!(
+            self,
+            ConnectionState::Connected | ConnectionState::Listening { clients: 1.. }
+        )
+    }
+}
+
+// Messages from the connection task to Bevy
+enum StreamEvent {
+    State(ConnectionState),
+    Hello(Hello),
+    Structure(UpdateStructure),
+    Frame(StreamedFrame),
+    Rpc(RpcRequest),
+}
+
+// One connection structures arrive on: the channel receiver, and the sender for messages to
+// the server
+struct Source {
+    name: String,
+    receiver: Receiver<StreamEvent>,
+    outgoing: async_channel::Sender<ClientMessage>,
+    state: ConnectionState,
+    // Newest structure received while another source was driving the scene
+    latest: Option<UpdateStructure>,
 }
 
-// Resource to hold the channel receiver
+impl Source {
+    // Source plus the ends its connection task uses
+    fn new(
+        name: impl Into<String>,
+    ) -> (
+        Self,
+        Sender<StreamEvent>,
+        async_channel::Receiver<ClientMessage>,
+    ) {
+        let (tx, rx) = unbounded();
+        let (outgoing_tx, outgoing_rx) = async_channel::unbounded();
+        let source = Self {
+            name: name.into(),
+            receiver: rx,
+            outgoing: outgoing_tx,
+            state: ConnectionState::default(),
+            latest: None,
+        };
+        (source, tx, outgoing_rx)
+    }
+}
+
+// Resource to hold the sources and which of them drives the scene
 #[derive(Resource)]
 pub struct WebSocketStream {
-    receiver: Receiver<UpdateStructure>,
+    sources: Vec<Source>,
+    active: usize,
+}
+
+impl WebSocketStream {
+    // Queue a message for the active server; dropped unless connected, so nothing stale piles up
+    pub(crate) fn send(&self, state: ConnectionState, message: ClientMessage) {
+        if state.is_connected() {
+            let _ = self.sources[self.active].outgoing.try_send(message);
+        }
+    }
 }
 
-// System to set up WebSocket connection
-pub fn setup_websocket_stream(mut commands: Commands) {
-    let (tx, rx) = unbounded();
+/// Text showing the connection state.
+#[derive(Component)]
+pub(crate) struct ConnectionIndicator;
+
+/// Header of the source dropdown, only spawned with several sources.
+#[derive(Component)]
+pub(crate) struct SourceButton;
+
+/// Text of the source dropdown header.
+#[derive(Component)]
+pub(crate) struct SourceText;
+
+/// Collapsed list of the sources.
+#[derive(Component)]
+pub(crate) struct SourceList;
+
+/// Entry of the source list, by index.
+#[derive(Component)]
+pub(crate) struct SourceOption(usize);
+
+// Named sources given as `--source name=url`, or `--source url` to use the URL as the name
+#[cfg(not(target_arch = "wasm32"))]
+fn named_sources(arguments: &[String]) -> Vec<(String, String)> {
+    arguments
+        .iter()
+        .map(|value| match value.split_once('=') {
+            // an `=` after the scheme belongs to the URL's query
+            Some((name, url)) if !name.contains("://") => (name.to_string(), url.to_string()),
+            _ => (value.clone(), value.clone()),
+        })
+        .collect()
+}
+
+// System to set up WebSocket connection, or one connection per source
+pub fn setup_websocket_stream(
+    mut commands: Commands,
+    cli: Res<Cli>,
+    config: Res<Config>,
+    mut state: ResMut<ConnectionState>,
+) {
+    let mut sources = Vec::new();
+
+    if cli.no_network {
+        let (mut source, _, _) = Source::new("offline");
+        source.state = ConnectionState::Offline;
+        *state = ConnectionState::Offline;
+        commands.insert_resource(WebSocketStream {
+            sources: vec![source],
+            active: 0,
+        });
+        info!("Network disabled");
+        return;
+    }
 
     #[cfg(not(target_arch = "wasm32"))]
     {
-        setup_native_websocket(tx);
+        // clap rejects combinations of these, so at most one applies
+        let named = named_sources(&cli.sources);
+        let token = cli.token.clone();
+
+        if !named.is_empty() {
+            for (name, url) in named {
+                let (source, tx, outgoing_rx) = Source::new(name);
+                setup_native_websocket(url, token.clone(), tx, outgoing_rx);
+                sources.push(source);
+            }
+        } else if cli.stdin {
+            let (source, tx, _) = Source::new("stdin");
+            setup_stdin_stream(tx);
+            sources.push(source);
+        } else if let Some(endpoint) = cli.zmq.clone() {
+            let (source, tx, _) = Source::new(endpoint.clone());
+            setup_zmq_subscriber(endpoint, tx);
+            sources.push(source);
+        } else if let Some(name) = cli.ipc.clone() {
+            let (source, tx, outgoing_rx) = Source::new(name.clone());
+            setup_ipc_server(name, tx, outgoing_rx);
+            sources.push(source);
+        } else if let Some(listen) = &cli.listen {
+            let address = listen_address(listen);
+            let (source, tx, outgoing_rx) = Source::new(address.clone());
+            setup_native_server(address, token, tx, outgoing_rx);
+            sources.push(source);
+        } else {
+            let url = cli
+                .ws_url
+                .clone()
+                .or_else(|| config.ws_url.clone())
+                .unwrap_or_else(|| SERVER_URL.to_string());
+            let (source, tx, outgoing_rx) = Source::new(url.clone());
+            setup_native_websocket(url, token, tx, outgoing_rx);
+            sources.push(source);
+        }
     }
 
+    // the page URL or the previous visit may name the server (see web.rs and persist.rs)
     #[cfg(target_arch = "wasm32")]
     {
-        setup_wasm_websocket(tx);
+        let url = cli
+            .ws_url
+            .clone()
+            .or_else(|| config.ws_url.clone())
+            .unwrap_or_else(|| SERVER_URL.to_string());
+        let (source, tx, outgoing_rx) = Source::new(url.clone());
+        WASM_TOKEN.with(|token| *token.borrow_mut() = cli.token.clone());
+        setup_wasm_websocket(url, tx, outgoing_rx);
+        sources.push(source);
     }
 
-    commands.insert_resource(WebSocketStream { receiver: rx });
+    commands.insert_resource(WebSocketStream { sources, active: 0 });
     info!("WebSocket stream initialized");
 }
 
-// System to poll WebSocket stream and send updates to Bevy
-pub fn poll_websocket_stream(
+// Spawn the connection indicator at the top of the side column
+pub(crate) fn setup_connection_indicator(
+    mut commands: Commands,
+    column: Single<Entity, With<SidePanelColumn>>,
+    state: Res<ConnectionState>,
     stream: Res<WebSocketStream>,
+    theme: Res<UiTheme>,
+) {
+    commands
+        .spawn((
+            Node {
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
+                ..default()
+            },
+            Themed::Panel,
+            ChildOf(*column),
+        ))
+        .with_children(|indicator| {
+            indicator.spawn((
+                Text::new(state.label()),
+                TextFont {
+                    font: default(),
+                    font_size: 12.0,
+                    ..default()
+                },
+                TextColor(state.color(&theme)),
+                ConnectionIndicator,
+            ));
+            if stream.sources.len() > 1 {
+                spawn_source_dropdown(indicator, &stream);
+            }
+        });
+}
+
+// Source dropdown: header button plus a collapsed list of the sources
+fn spawn_source_dropdown(parent: &mut ChildSpawnerCommands, stream: &WebSocketStream) {
+    let text_font = TextFont {
+        font: default(),
+        font_size: 12.0,
+        ..default()
+    };
+    parent
+        .spawn((
+            Button,
+            Node {
+                padding: UiRect::axes(Val::Px(10.0), Val::Px(6.0)),
+                border: UiRect::all(Val::Px(1.0)),
+                ..default()
+            },
+            Themed::Button,
+            SourceButton,
+        ))
+        .with_children(|button| {
+            button.spawn((
+                Text::new(format!("Source: {}", stream.sources[stream.active].name)),
+                text_font.clone(),
+                Themed::Text,
+                SourceText,
+            ));
+        });
+
+    parent
+        .spawn((
+            Node {
+                display: Display::None,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(2.0),
+                ..default()
+            },
+            SourceList,
+        ))
+        .with_children(|list| {
+            for (index, source) in stream.sources.iter().enumerate() {
+                list.spawn((
+                    Button,
+                    Node {
+                        padding: UiRect::axes(Val::Px(10.0), Val::Px(4.0)),
+                        ..default()
+                    },
+                    Themed::Button,
+                    SourceOption(index),
+                ))
+                .with_children(|button| {
+                    button.spawn((
+                        Text::new(source.name.clone()),
+                        text_font.clone(),
+                        Themed::Text,
+                    ));
+                });
+            }
+        });
+}
+
+// Open/close the source dropdown and switch to the picked source. The scene keeps what the
+// previous source showed until the new one has a structure.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn source_dropdown(
+    headers: Query<&Interaction, (Changed<Interaction>, With<SourceButton>)>,
+    options: Query<(&Interaction, &SourceOption), Changed<Interaction>>,
+    mut lists: Query<&mut Node, With<SourceList>>,
+    mut texts: Query<&mut Text, With<SourceText>>,
+    mut stream: ResMut<WebSocketStream>,
+    mut state: ResMut<ConnectionState>,
+    crystal: Res<Crystal>,
+    mut trajectory: ResMut<Trajectory>,
+    mut updates: EventWriter<UpdateStructure>,
+) {
+    if headers.iter().any(|i| *i == Interaction::Pressed) {
+        for mut node in &mut lists {
+            node.display = match node.display {
+                Display::None => Display::Flex,
+                _ => Display::None,
+            };
+        }
+    }
+
+    for (interaction, option) in &options {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        for mut node in &mut lists {
+            node.display = Display::None;
+        }
+        let previous = stream.active;
+        if option.0 == previous {
+            continue;
+        }
+
+        // remember what the previous source showed, for switching back
+        stream.sources[previous].latest = Some(UpdateStructure {
+            atoms: crystal.atoms.clone(),
+            lattice: crystal.lattice,
+            pbc: crystal.pbc,
+            properties: crystal.properties.clone(),
+            metadata: Some(crystal.metadata.clone()),
+        });
+        stream.active = option.0;
+        let source = &mut stream.sources[option.0];
+        info!("Switched to source {}", source.name);
+        *state = source.state;
+        trajectory.clear();
+        if let Some(latest) = source.latest.take() {
+            updates.write(latest);
+        }
+        for mut text in &mut texts {
+            text.0 = format!("Source: {}", source.name);
+        }
+    }
+}
+
+// System to poll WebSocket stream and send updates to Bevy. Only the active source drives the
+// scene; the others just keep their newest structure.
+pub fn poll_websocket_stream(
+    mut stream: ResMut<WebSocketStream>,
     mut events: EventWriter<UpdateStructure>,
+    mut frames: EventWriter<StreamedFrame>,
+    mut requests: EventWriter<RpcRequest>,
+    mut state: ResMut<ConnectionState>,
+    mut toasts: EventWriter<Toast>,
+) {
+    let active = stream.active;
+    for (index, source) in stream.sources.iter_mut().enumerate() {
+        while let Ok(event) = source.receiver.try_recv() {
+            if index == active {
+                handle_stream_event(
+                    event,
+                    &mut events,
+                    &mut frames,
+                    &mut requests,
+                    &mut state,
+                    &mut toasts,
+                );
+                continue;
+            }
+            match event {
+                StreamEvent::State(new_state) => {
+                    info!("{}: {}", source.name, new_state.label());
+                    source.state = new_state;
+                }
+                StreamEvent::Structure(update) => source.latest = Some(update),
+                StreamEvent::Frame(frame) => source.latest = Some(frame.structure),
+                StreamEvent::Hello(_) | StreamEvent::Rpc(_) => {}
+            }
+        }
+    }
+    let current = *state;
+    stream.sources[active].state = current;
+}
+
+// Hand an event of the active source to Bevy
+fn handle_stream_event(
+    event: StreamEvent,
+    events: &mut EventWriter<UpdateStructure>,
+    frames: &mut EventWriter<StreamedFrame>,
+    requests: &mut EventWriter<RpcRequest>,
+    state: &mut ResMut<ConnectionState>,
+    toasts: &mut EventWriter<Toast>,
+) {
+    match event {
+        StreamEvent::State(new_state) => {
+            info!("WebSocket {}", new_state.label());
+            if let Some(toast) = new_state.toast(**state) {
+                toasts.write(toast);
+            }
+            **state = new_state;
+        }
+        StreamEvent::Hello(hello) if hello.protocol != PROTOCOL_VERSION => {
+            let message = format!(
+                "Peer speaks protocol v{}, expected v{}; some messages may be ignored",
+                hello.protocol, PROTOCOL_VERSION
+            );
+            warn!("{message}");
+            toasts.write(Toast::warning(message));
+        }
+        StreamEvent::Hello(hello) => {
+            info!(
+                "Peer speaks protocol v{} with [{}]",
+                hello.protocol,
+                hello.capabilities.join(", ")
+            );
+        }
+        StreamEvent::Structure(update) => {
+            info!(
+                "Received structure update with {} atoms",
+                update.atoms.len()
+            );
+            events.write(update);
+        }
+        StreamEvent::Frame(frame) => {
+            debug!(
+                "Received frame {} with {} atoms",
+                frame.info.index,
+                frame.structure.atoms.len()
+            );
+            frames.write(frame);
+        }
+        StreamEvent::Rpc(request) => {
+            debug!("Received RPC request `{}`", request.method);
+            requests.write(request);
+        }
+    }
+}
+
+// Keep the indicator in sync with the connection state
+pub(crate) fn refresh_connection_indicator(
+    state: Res<ConnectionState>,
+    theme: Res<UiTheme>,
+    mut texts: Query<(&mut Text, &mut TextColor), With<ConnectionIndicator>>,
+) {
+    if !state.is_changed() && !theme.is_changed() {
+        return;
+    }
+    for (mut text, mut color) in &mut texts {
+        text.0 = state.label();
+        color.0 = state.color(&theme);
+    }
+}
+
+// Report changes of the picked atoms
+pub(crate) fn send_selection(
+    stream: Res<WebSocketStream>,
+    state: Res<ConnectionState>,
+    selection: Res<Selection>,
+) {
+    if selection.is_changed() {
+        stream.send(
+            *state,
+            ClientMessage::Selection {
+                atoms: selection.atoms.clone(),
+            },
+        );
+    }
+}
+
+// Report structures edited in the viewer; structures received from the server are not echoed
+pub(crate) fn send_structure_edits(
+    stream: Res<WebSocketStream>,
+    state: Res<ConnectionState>,
+    crystal: Res<Crystal>,
+    mut updates: EventReader<UpdateStructure>,
+    mut last_known: Local<Option<Vec<Atom>>>,
 ) {
-    while let Ok(update) = stream.receiver.try_recv() {
-        info!(
-            "Received structure update with {} atoms",
-            update.atoms.len()
+    let received = updates.read().count() > 0;
+    if !crystal.is_changed() {
+        return;
+    }
+    // the first structure and server updates only record the reference
+    let edited = !received
+        && last_known
+            .as_ref()
+            .is_some_and(|atoms| *atoms != crystal.atoms);
+    if edited {
+        stream.send(
+            *state,
+            ClientMessage::Structure {
+                atoms: crystal.atoms.iter().map(AtomData::from).collect(),
+                lattice: crystal.lattice.map(lattice_to_rows),
+                pbc: crystal.pbc,
+            },
         );
-        events.write(update);
     }
+    *last_known = Some(crystal.atoms.clone());
+}
+
+// Report the camera pose while it moves, at most every CAMERA_SEND_INTERVAL_SECS
+pub(crate) fn send_camera_pose(
+    stream: Res<WebSocketStream>,
+    state: Res<ConnectionState>,
+    time: Res<Time>,
+    camera_rig: Res<CameraRig>,
+    camera: Single<Ref<Transform>, With<MainCamera>>,
+    mut since_last: Local<f32>,
+    mut pending: Local<bool>,
+) {
+    *since_last += time.delta_secs();
+    // remember the move, so the final pose is sent once the interval has passed
+    *pending |= camera.is_changed();
+    if !*pending || *since_last < CAMERA_SEND_INTERVAL_SECS {
+        return;
+    }
+    *since_last = 0.0;
+    *pending = false;
+    stream.send(
+        *state,
+        ClientMessage::Camera {
+            position: camera.translation.to_array(),
+            rotation: camera.rotation.to_array(),
+            target: camera_rig.target().to_array(),
+        },
+    );
+}
+
+// Decode a frame in either encoding and hand its message to Bevy; unknown messages are
+// ignored. `cell` is the last cell on the connection. Returns false once the Bevy side is gone.
+fn forward_frame(frame: &Frame, cell: &mut PeerCell, tx: &Sender<StreamEvent>) -> bool {
+    let event = match frame.decode_server_message() {
+        Ok(ServerMessage::Hello(hello)) => StreamEvent::Hello(hello),
+        Ok(ServerMessage::Structure(structure_msg)) => match structure_msg.frame {
+            Some(index) => StreamEvent::Frame(StreamedFrame {
+                info: FrameInfo {
+                    index,
+                    step: structure_msg.step,
+                    time: structure_msg.time,
+                    energy: structure_msg.energy,
+                },
+                structure: structure_msg.into_update(cell),
+            }),
+            None => StreamEvent::Structure(structure_msg.into_update(cell)),
+        },
+        Ok(ServerMessage::Rpc(request)) => StreamEvent::Rpc(request),
+        Err(_) => return true,
+    };
+    tx.send(event).is_ok()
 }
 
-// Native WebSocket client using async-tungstenite run on async_std runtime
+// Exchange messages over one open connection until either side closes it; returns false once
+// the Bevy side is gone. With a `token`, the peer has to open with a hello carrying it, or the
+// connection is closed before anything is exchanged. `admitted` runs once the peer is let in.
 #[cfg(not(target_arch = "wasm32"))]
-fn setup_native_websocket(tx: Sender<UpdateStructure>) {
-    use bevy::tasks::IoTaskPool;
+async fn run_session<S>(
+    ws_stream: async_tungstenite::WebSocketStream<S>,
+    hello: Hello,
+    token: Option<&str>,
+    admitted: impl FnOnce(),
+    tx: &Sender<StreamEvent>,
+    outgoing: &async_channel::Receiver<ClientMessage>,
+) -> bool
+where
+    S: async_std::io::Read + async_std::io::Write + Unpin,
+{
+    use async_tungstenite::tungstenite::Message;
+    use futures_util::future::{select, Either};
+    use futures_util::StreamExt;
 
-    let pool = IoTaskPool::get();
+    let to_message = |frame| match frame {
+        Frame::Text(text) => Message::Text(text.into()),
+        Frame::Binary(bytes) => Message::Binary(bytes.into()),
+    };
 
-    pool.spawn(async move {
-        let url = "ws://127.0.0.1:9001";
-        println!("Connecting to WS: {url}");
-
-        match async_tungstenite::async_std::connect_async(url).await {
-            Ok((ws_stream, _)) => {
-                use futures_util::StreamExt;
-
-                println!("Connected!");
-                let (_, mut read) = ws_stream.split();
-
-                while let Some(msg) = read.next().await {
-                    match msg {
-                        Ok(async_tungstenite::tungstenite::Message::Text(text)) => {
-                            if let Ok(structure_msg) =
-                                serde_json::from_str::<StructureMessage>(&text)
-                            {
-                                let atoms = structure_msg
-                                    .atoms
-                                    .into_iter()
-                                    .map(std::convert::Into::into)
-                                    .collect();
-
-                                if tx.send(UpdateStructure { atoms }).is_err() {
-                                    println!("Bevy channel closed");
-                                    break;
-                                }
-                            }
-                        }
-                        Ok(async_tungstenite::tungstenite::Message::Close(_)) => {
-                            println!("Server closed WebSocket");
+    let (mut write, mut read) = ws_stream.split();
+    // replies follow the encoding of the last frame received
+    let mut encoding = Encoding::default();
+    let mut cell = PeerCell::default();
+
+    if let Some(token) = token {
+        let first = match read.next().await {
+            Some(Ok(Message::Text(text))) => Some(Frame::Text(text.to_string())),
+            Some(Ok(Message::Binary(bytes))) => Some(Frame::Binary(bytes.to_vec())),
+            _ => None,
+        }
+        .and_then(|frame| frame.inflate().ok());
+        let authorized = first.as_ref().is_some_and(|frame| {
+            matches!(
+                frame.decode_server_message(),
+                Ok(ServerMessage::Hello(Hello { token: Some(sent), .. }))
+                    if same_token(&sent, token)
+            )
+        });
+        let Some(first) = first.filter(|_| authorized) else {
+            warn!("Closing connection that did not open with the token");
+            let _ = write.close(None).await;
+            return true;
+        };
+        admitted();
+        encoding = Encoding::of(&first);
+        if !forward_frame(&first, &mut cell, tx) {
+            return false;
+        }
+    } else {
+        admitted();
+    }
+
+    // announce the protocol version before anything else
+    if let Ok(hello) = encoding.encode(&ClientMessage::Hello(hello)) {
+        if let Err(e) = write.send(to_message(hello)).await {
+            warn!("WS send error: {}", e);
+            return true;
+        }
+    }
+
+    loop {
+        let msg = match select(read.next(), Box::pin(outgoing.recv())).await {
+            Either::Left((Some(msg), _)) => msg,
+            Either::Left((None, _)) => return true,
+            Either::Right((Ok(message), _)) => {
+                let Ok(frame) = encoding.encode(&message) else {
+                    continue;
+                };
+                if let Err(e) = write.send(to_message(frame)).await {
+                    warn!("WS send error: {}", e);
+                    return true;
+                }
+                continue;
+            }
+            Either::Right((Err(_), _)) => return false,
+        };
+        let frame = match msg {
+            Ok(Message::Text(text)) => Frame::Text(text.to_string()),
+            Ok(Message::Binary(bytes)) => Frame::Binary(bytes.to_vec()),
+            Ok(Message::Close(_)) => {
+                info!("Peer closed WebSocket");
+                return true;
+            }
+            Err(e) => {
+                warn!("WS error: {}", e);
+                return true;
+            }
+            _ => continue,
+        };
+        let frame = match frame.inflate() {
+            Ok(frame) => frame,
+            Err(e) => {
+                warn!("Dropping frame: {e}");
+                continue;
+            }
+        };
+        encoding = Encoding::of(&frame);
+        if !forward_frame(&frame, &mut cell, tx) {
+            info!("Bevy channel closed");
+            return false;
+        }
+    }
+}
+
+// Native WebSocket client using async-tungstenite on a single-threaded tokio runtime of its own,
+// which wss:// URLs need for rustls. Reconnects with exponential backoff whenever the
+// connection fails or drops.
+#[cfg(not(target_arch = "wasm32"))]
+fn setup_native_websocket(
+    url: String,
+    token: Option<String>,
+    tx: Sender<StreamEvent>,
+    outgoing: async_channel::Receiver<ClientMessage>,
+) {
+    use std::time::Duration;
+
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            error!("Failed to start the WebSocket runtime: {e}");
+            return;
+        }
+    };
+
+    std::thread::spawn(move || {
+        runtime.block_on(async move {
+            let mut delay = INITIAL_BACKOFF_SECS;
+            let mut attempt = 0;
+
+            loop {
+                if tx
+                    .send(StreamEvent::State(ConnectionState::Connecting))
+                    .is_err()
+                {
+                    break;
+                }
+                info!("Connecting to WS: {url}");
+
+                let connected = match handshake_request(&url, token.as_deref()) {
+                    Ok(request) => async_tungstenite::tokio::connect_async(request).await,
+                    Err(e) => Err(e),
+                };
+                match connected {
+                    Ok((ws_stream, _)) => {
+                        info!("Connected!");
+                        delay = INITIAL_BACKOFF_SECS;
+                        attempt = 0;
+                        if tx
+                            .send(StreamEvent::State(ConnectionState::Connected))
+                            .is_err()
+                        {
                             break;
                         }
-                        Err(e) => {
-                            eprintln!("WS error: {}", e);
+                        let hello = Hello::viewer(token.clone());
+                        if !run_session(ws_stream, hello, None, || {}, &tx, &outgoing).await {
                             break;
                         }
-                        _ => {}
                     }
+                    Err(e) => warn!("Failed to connect WS: {}", e),
+                }
+
+                attempt += 1;
+                let state = ConnectionState::Reconnecting {
+                    attempt,
+                    retry_in: delay,
+                };
+                if tx.send(StreamEvent::State(state)).is_err() {
+                    break;
                 }
+                tokio::time::sleep(Duration::from_secs_f32(delay)).await;
+                delay = next_backoff(delay);
+            }
+        })
+    });
+}
+
+// Handshake request for `url`, carrying the token as `Authorization: Bearer <token>`
+#[cfg(not(target_arch = "wasm32"))]
+fn handshake_request(
+    url: &str,
+    token: Option<&str>,
+) -> Result<
+    async_tungstenite::tungstenite::handshake::client::Request,
+    async_tungstenite::tungstenite::Error,
+> {
+    use async_tungstenite::tungstenite::client::IntoClientRequest;
+    use async_tungstenite::tungstenite::http::{header, HeaderValue};
+
+    let mut request = url.into_client_request()?;
+    if let Some(token) = token {
+        let value = HeaderValue::from_str(&format!("Bearer {token}"))
+            .map_err(async_tungstenite::tungstenite::http::Error::from)?;
+        request.headers_mut().insert(header::AUTHORIZATION, value);
+    }
+    Ok(request)
+}
+
+// Address given to `--listen`; a bare port listens on localhost only
+#[cfg(not(target_arch = "wasm32"))]
+fn listen_address(value: &str) -> String {
+    let value = value.trim();
+    if value.parse::<u16>().is_ok() {
+        format!("127.0.0.1:{value}")
+    } else {
+        value.to_string()
+    }
+}
+
+// Native WebSocket server: every tool connecting to `address` can push structures, and messages
+// from the viewer are broadcast to all of them
+#[cfg(not(target_arch = "wasm32"))]
+fn setup_native_server(
+    address: String,
+    token: Option<String>,
+    tx: Sender<StreamEvent>,
+    outgoing: async_channel::Receiver<ClientMessage>,
+) {
+    use async_tungstenite::tungstenite::handshake::server::{Request, Response};
+    use bevy::tasks::IoTaskPool;
+    use std::sync::{Arc, Mutex};
+
+    let pool = IoTaskPool::get();
+    // outgoing queue of every connected client
+    let clients: Arc<Mutex<Vec<async_channel::Sender<ClientMessage>>>> = Arc::default();
+
+    let broadcast_clients = clients.clone();
+    pool.spawn(async move {
+        while let Ok(message) = outgoing.recv().await {
+            let mut clients = broadcast_clients.lock().unwrap();
+            clients.retain(|client| client.try_send(message.clone()).is_ok());
+        }
+    })
+    .detach();
+
+    pool.spawn(async move {
+        let listener = match async_std::net::TcpListener::bind(&address).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Failed to listen on {address}: {e}");
+                let _ = tx.send(StreamEvent::State(ConnectionState::ListenFailed));
+                return;
             }
-            Err(e) => eprintln!("Failed to connect WS: {}", e),
+        };
+        info!("Listening for WS clients on {address}");
+        let _ = tx.send(StreamEvent::State(ConnectionState::Listening {
+            clients: 0,
+        }));
+
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("Failed to accept WS client: {e}");
+                    continue;
+                }
+            };
+            let tx = tx.clone();
+            let clients = clients.clone();
+            let token = token.clone();
+            pool.spawn(async move {
+                let mut token_in_header = false;
+                // the error type is tungstenite's
+                #[allow(clippy::result_large_err)]
+                let check = |request: &Request, response: Response| {
+                    token_in_header = token
+                        .as_deref()
+                        .zip(bearer_token(request))
+                        .is_some_and(|(token, presented)| same_token(presented, token));
+                    authorize(request, token.as_deref(), response)
+                };
+                let ws_stream = match async_tungstenite::accept_hdr_async(stream, check).await {
+                    Ok(ws_stream) => ws_stream,
+                    Err(e) => {
+                        warn!("WS handshake with {peer} failed: {e}");
+                        return;
+                    }
+                };
+                info!("WS client {peer} connected");
+
+                // clients join the broadcast and the count only once they are let in
+                let (client_tx, client_rx) = async_channel::unbounded();
+                let admitted = || {
+                    let count = {
+                        let mut clients = clients.lock().unwrap();
+                        clients.push(client_tx);
+                        clients.len()
+                    };
+                    let _ = tx.send(StreamEvent::State(ConnectionState::Listening {
+                        clients: count,
+                    }));
+                };
+
+                // clients that could not send the header, such as browsers, send the token in
+                // their hello instead
+                let required = token.as_deref().filter(|_| !token_in_header);
+                let hello = Hello::viewer(None);
+                run_session(ws_stream, hello, required, admitted, &tx, &client_rx).await;
+                info!("WS client {peer} disconnected");
+
+                // the broadcaster drops the queue of a client that is gone
+                client_rx.close();
+                let count = {
+                    let mut clients = clients.lock().unwrap();
+                    clients.retain(|client| !client.is_closed());
+                    clients.len()
+                };
+                let _ = tx.send(StreamEvent::State(ConnectionState::Listening {
+                    clients: count,
+                }));
+            })
+            .detach();
         }
     })
     .detach();
 }
 
-// WASM WebSocket client using web-sys
+// Feed newline-delimited input to Bevy: lines starting with `{` are messages in the WebSocket
+// format, anything else should begin an XYZ frame (atom count, comment, atoms). XYZ frames are
+// numbered in order of arrival and buffered as a trajectory. Returns false once the Bevy side
+// is gone.
+#[cfg(not(target_arch = "wasm32"))]
+fn forward_lines(reader: impl std::io::BufRead, source: &str, tx: &Sender<StreamEvent>) -> bool {
+    let mut lines = reader.lines().map_while(Result::ok);
+    let mut index = 0;
+    let mut cell = PeerCell::default();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if trimmed.starts_with('{') {
+            if !forward_frame(&Frame::Text(line), &mut cell, tx) {
+                return false;
+            }
+            continue;
+        }
+        let Ok(count) = trimmed.parse::<usize>() else {
+            warn!("Ignoring {source} line that starts no XYZ frame: {trimmed}");
+            continue;
+        };
+
+        let mut contents = format!("{line}\n");
+        for line in lines.by_ref().take(count + 1) {
+            contents.push_str(&line);
+            contents.push('\n');
+        }
+        match parse_structure(&format!("{source}.xyz"), &contents) {
+            Ok(crystal) => {
+                let frame = StreamedFrame {
+                    info: FrameInfo {
+                        index,
+                        step: None,
+                        time: None,
+                        energy: None,
+                    },
+                    structure: crystal.into(),
+                };
+                index += 1;
+                if tx.send(StreamEvent::Frame(frame)).is_err() {
+                    return false;
+                }
+            }
+            Err(e) => warn!("{e:#}"),
+        }
+    }
+    true
+}
+
+// Read standard input line by line on a thread
+#[cfg(not(target_arch = "wasm32"))]
+fn setup_stdin_stream(tx: Sender<StreamEvent>) {
+    std::thread::spawn(move || {
+        let _ = tx.send(StreamEvent::State(ConnectionState::Stdin { open: true }));
+        if forward_lines(std::io::stdin().lock(), "stdin", &tx) {
+            info!("Standard input closed");
+            let _ = tx.send(StreamEvent::State(ConnectionState::Stdin { open: false }));
+        }
+    });
+}
+
+// Local socket name given to `--ipc`: a path (`/tmp/vizmat.sock`, `\\.\pipe\vizmat`) is used as
+// is, a bare name becomes an abstract socket on Linux, a named pipe on Windows and a socket in
+// the temporary directory elsewhere
+#[cfg(not(target_arch = "wasm32"))]
+fn ipc_name(value: &str) -> std::io::Result<interprocess::local_socket::Name<'static>> {
+    use interprocess::local_socket::{prelude::*, GenericFilePath, GenericNamespaced};
+
+    if value.contains(['/', '\\']) {
+        value.to_string().to_fs_name::<GenericFilePath>()
+    } else if GenericNamespaced::is_supported() {
+        value.to_string().to_ns_name::<GenericNamespaced>()
+    } else {
+        std::env::temp_dir()
+            .join(value)
+            .to_fs_name::<GenericFilePath>()
+    }
+}
+
+// Local IPC server: every process connecting to the socket or pipe can send newline-delimited
+// messages or XYZ frames, like on standard input, and receives the viewer's messages as JSON
+// lines
+#[cfg(not(target_arch = "wasm32"))]
+fn setup_ipc_server(
+    name: String,
+    tx: Sender<StreamEvent>,
+    outgoing: async_channel::Receiver<ClientMessage>,
+) {
+    use interprocess::local_socket::{prelude::*, ListenerOptions, SendHalf};
+    use std::io::{BufReader, Write};
+    use std::sync::{Arc, Mutex};
+
+    // write half of every connected client, by connection number
+    let clients: Arc<Mutex<Vec<(usize, SendHalf)>>> = Arc::default();
+
+    let broadcast_clients = clients.clone();
+    std::thread::spawn(move || {
+        while let Ok(message) = outgoing.recv_blocking() {
+            let Ok(mut line) = serde_json::to_string(&message) else {
+                continue;
+            };
+            line.push('\n');
+            let mut clients = broadcast_clients.lock().unwrap();
+            clients.retain_mut(|(_, client)| client.write_all(line.as_bytes()).is_ok());
+        }
+    });
+
+    std::thread::spawn(move || {
+        let listener = match ipc_name(&name)
+            .and_then(|socket| ListenerOptions::new().name(socket).create_sync())
+        {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Failed to listen on {name}: {e}");
+                let _ = tx.send(StreamEvent::State(ConnectionState::ListenFailed));
+                return;
+            }
+        };
+        info!("Listening for IPC clients on {name}");
+        let _ = tx.send(StreamEvent::State(ConnectionState::Listening {
+            clients: 0,
+        }));
+
+        for (id, connection) in listener.incoming().enumerate() {
+            let connection = match connection {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("Failed to accept IPC client: {e}");
+                    continue;
+                }
+            };
+            let (receive, send) = connection.split();
+            let count = {
+                let mut clients = clients.lock().unwrap();
+                clients.push((id, send));
+                clients.len()
+            };
+            let _ = tx.send(StreamEvent::State(ConnectionState::Listening {
+                clients: count,
+            }));
+
+            let tx = tx.clone();
+            let clients = clients.clone();
+            std::thread::spawn(move || {
+                info!("IPC client {id} connected");
+                forward_lines(BufReader::new(receive), "ipc", &tx);
+                info!("IPC client {id} disconnected");
+                let count = {
+                    let mut clients = clients.lock().unwrap();
+                    clients.retain(|(client, _)| *client != id);
+                    clients.len()
+                };
+                let _ = tx.send(StreamEvent::State(ConnectionState::Listening {
+                    clients: count,
+                }));
+            });
+        }
+    });
+}
+
+// ZeroMQ SUB socket subscribed to everything at `endpoint`. Each message carries one
+// structure or RPC request in the WebSocket format, JSON or MessagePack; with several parts
+// the last one is the payload and the leading ones (e.g. a topic) are skipped. Messages for
+// the server are dropped, as PUB/SUB only goes one way.
+#[cfg(all(feature = "zmq", not(target_arch = "wasm32")))]
+fn setup_zmq_subscriber(endpoint: String, tx: Sender<StreamEvent>) {
+    use std::time::Duration;
+
+    // libzmq reconnects on its own, so a blocking thread is all that is needed
+    std::thread::spawn(move || {
+        let context = zmq::Context::new();
+        let subscribed = context.socket(zmq::SUB).and_then(|socket| {
+            socket.connect(&endpoint)?;
+            socket.set_subscribe(b"")?;
+            Ok(socket)
+        });
+        let socket = match subscribed {
+            Ok(socket) => socket,
+            Err(e) => {
+                warn!("Failed to subscribe to ZMQ publisher {endpoint}: {e}");
+                let _ = tx.send(StreamEvent::State(ConnectionState::Zmq {
+                    subscribed: false,
+                }));
+                return;
+            }
+        };
+        info!("Subscribed to ZMQ publisher {endpoint}");
+        let _ = tx.send(StreamEvent::State(ConnectionState::Zmq {
+            subscribed: true,
+        }));
+
+        // a failing socket is retried with backoff rather than in a tight loop
+        let mut delay = INITIAL_BACKOFF_SECS;
+        let mut cell = PeerCell::default();
+        loop {
+            let mut parts = match socket.recv_multipart(0) {
+                Ok(parts) => parts,
+                // the context was terminated, so the socket is of no further use
+                Err(zmq::Error::ETERM) => return,
+                Err(e) => {
+                    warn!("ZMQ error: {e}");
+                    std::thread::sleep(Duration::from_secs_f32(delay));
+                    delay = next_backoff(delay);
+                    continue;
+                }
+            };
+            delay = INITIAL_BACKOFF_SECS;
+            let Some(payload) = parts.pop() else {
+                continue;
+            };
+            let frame = match String::from_utf8(payload) {
+                Ok(text) => Frame::Text(text),
+                Err(e) => Frame::Binary(e.into_bytes()),
+            };
+            let frame = match frame.inflate() {
+                Ok(frame) => frame,
+                Err(e) => {
+                    warn!("Dropping ZMQ message: {e}");
+                    continue;
+                }
+            };
+            if !forward_frame(&frame, &mut cell, &tx) {
+                return;
+            }
+        }
+    });
+}
+
+#[cfg(all(not(feature = "zmq"), not(target_arch = "wasm32")))]
+fn setup_zmq_subscriber(endpoint: String, _tx: Sender<StreamEvent>) {
+    warn!("--zmq {endpoint} needs a build with the `zmq` feature");
+}
+
+// Whether a presented token matches the required one, comparing every byte so the time taken
+// does not tell how much of it was right
+#[cfg(not(target_arch = "wasm32"))]
+fn same_token(presented: &str, token: &str) -> bool {
+    presented.len() == token.len()
+        && presented
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+// Bearer token in the `Authorization` header of a client's handshake
+#[cfg(not(target_arch = "wasm32"))]
+fn bearer_token(
+    request: &async_tungstenite::tungstenite::handshake::server::Request,
+) -> Option<&str> {
+    use async_tungstenite::tungstenite::http::header;
+
+    request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+// Reject a client's handshake if it carries a wrong bearer token. Clients without the header
+// are let through when a token is required, to present it in their hello.
+#[cfg(not(target_arch = "wasm32"))]
+#[allow(clippy::result_large_err)]
+fn authorize(
+    request: &async_tungstenite::tungstenite::handshake::server::Request,
+    token: Option<&str>,
+    response: async_tungstenite::tungstenite::handshake::server::Response,
+) -> Result<
+    async_tungstenite::tungstenite::handshake::server::Response,
+    async_tungstenite::tungstenite::handshake::server::ErrorResponse,
+> {
+    use async_tungstenite::tungstenite::http::StatusCode;
+
+    let (Some(token), Some(presented)) = (token, bearer_token(request)) else {
+        return Ok(response);
+    };
+    if same_token(presented, token) {
+        return Ok(response);
+    }
+    let mut rejection = async_tungstenite::tungstenite::handshake::server::ErrorResponse::new(
+        Some("Wrong token".to_string()),
+    );
+    *rejection.status_mut() = StatusCode::UNAUTHORIZED;
+    Err(rejection)
+}
+
+// WASM WebSocket client using web-sys.
+// A closed socket schedules a fresh connection with exponential backoff.
+#[cfg(target_arch = "wasm32")]
+fn setup_wasm_websocket(
+    url: String,
+    tx: Sender<StreamEvent>,
+    outgoing: async_channel::Receiver<ClientMessage>,
+) {
+    // forward queued messages to whichever socket is currently open
+    wasm_bindgen_futures::spawn_local(async move {
+        while let Ok(message) = outgoing.recv().await {
+            let Ok(frame) = WASM_ENCODING.with(|encoding| encoding.get().encode(&message)) else {
+                continue;
+            };
+            WASM_SOCKET.with(|socket| {
+                if let Some(ws) = socket.borrow().as_ref() {
+                    let sent = match &frame {
+                        Frame::Text(text) => ws.send_with_str(text),
+                        Frame::Binary(bytes) => ws.send_with_u8_array(bytes),
+                    };
+                    if let Err(e) = sent {
+                        warn!("WebSocket send error: {:?}", e);
+                    }
+                }
+            });
+        }
+    });
+    connect_wasm_websocket(url, tx, 0, INITIAL_BACKOFF_SECS);
+}
+
+// The open WebSocket, if any; web-sys handles cannot be kept in a Bevy resource
+#[cfg(target_arch = "wasm32")]
+thread_local! {
+    static WASM_SOCKET: std::cell::RefCell<Option<web_sys::WebSocket>> =
+        const { std::cell::RefCell::new(None) };
+    // encoding of the last frame received, used for messages to the server
+    static WASM_ENCODING: std::cell::Cell<Encoding> =
+        const { std::cell::Cell::new(Encoding::Json) };
+    // access token sent in the hello, since browsers cannot set the header
+    static WASM_TOKEN: std::cell::RefCell<Option<String>> =
+        const { std::cell::RefCell::new(None) };
+}
+
 #[cfg(target_arch = "wasm32")]
-fn setup_wasm_websocket(tx: Sender<UpdateStructure>) {
+fn connect_wasm_websocket(url: String, tx: Sender<StreamEvent>, attempt: u32, delay: f32) {
     use wasm_bindgen::prelude::*;
     use wasm_bindgen::JsCast;
-    use web_sys::{ErrorEvent, MessageEvent, WebSocket};
+    use web_sys::{BinaryType, CloseEvent, ErrorEvent, MessageEvent, WebSocket};
+
+    let _ = tx.send(StreamEvent::State(ConnectionState::Connecting));
 
-    let ws = WebSocket::new("ws://127.0.0.1:9001").unwrap();
+    let ws = match WebSocket::new(&url) {
+        Ok(ws) => ws,
+        Err(e) => {
+            warn!("WebSocket error: {:?}", e);
+            schedule_wasm_reconnect(url, tx, attempt + 1, delay);
+            return;
+        }
+    };
+    // binary frames as ArrayBuffer rather than Blob, so they can be decoded synchronously
+    ws.set_binary_type(BinaryType::Arraybuffer);
 
     // onmessage callback
     let tx_clone = tx.clone();
+    let mut cell = PeerCell::default();
     let onmessage_callback = Closure::wrap(Box::new(move |e: MessageEvent| {
-        if let Ok(txt) = e.data().dyn_into::<js_sys::JsString>() {
-            let text: String = txt.into();
-            if let Ok(structure_msg) = serde_json::from_str::<StructureMessage>(&text) {
-                let atoms: Vec<Atom> = structure_msg.atoms.into_iter().map(|a| a.into()).collect();
-
-                let _ = tx_clone.send(UpdateStructure { atoms });
+        let data = e.data();
+        let frame = if let Some(text) = data.as_string() {
+            Frame::Text(text)
+        } else if let Ok(buffer) = data.dyn_into::<js_sys::ArrayBuffer>() {
+            Frame::Binary(js_sys::Uint8Array::new(&buffer).to_vec())
+        } else {
+            return;
+        };
+        let frame = match frame.inflate() {
+            Ok(frame) => frame,
+            Err(e) => {
+                warn!("Dropping frame: {e}");
+                return;
             }
-        }
+        };
+        WASM_ENCODING.with(|encoding| encoding.set(Encoding::of(&frame)));
+        forward_frame(&frame, &mut cell, &tx_clone);
     }) as Box<dyn FnMut(MessageEvent)>);
     ws.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
     onmessage_callback.forget();
 
     // onerror callback
     let onerror_callback = Closure::wrap(Box::new(move |e: ErrorEvent| {
-        web_sys::console::error_1(&format!("WebSocket error: {:?}", e).into());
+        warn!("WebSocket error: {:?}", e);
     }) as Box<dyn FnMut(ErrorEvent)>);
     ws.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
     onerror_callback.forget();
 
-    // onopen callback
+    // onopen callback; a successful connection resets the backoff
+    let tx_open = tx.clone();
+    let opened = std::rc::Rc::new(std::cell::Cell::new(false));
+    let opened_flag = opened.clone();
+    let ws_open = ws.clone();
     let onopen_callback = Closure::wrap(Box::new(move |_| {
-        web_sys::console::log_1(&"WebSocket connected".into());
+        info!("WebSocket connected");
+        opened_flag.set(true);
+        // announce the protocol version before anything else; the peer has not picked an
+        // encoding yet, so this is always JSON
+        WASM_ENCODING.with(|encoding| encoding.set(Encoding::Json));
+        if let Ok(Frame::Text(hello)) =
+            Encoding::Json.encode(&ClientMessage::Hello(Hello::viewer(auth_token())))
+        {
+            let _ = ws_open.send_with_str(&hello);
+        }
+        let _ = tx_open.send(StreamEvent::State(ConnectionState::Connected));
     }) as Box<dyn FnMut(JsValue)>);
     ws.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
     onopen_callback.forget();
 
-    // Keep the WebSocket alive by leaking it
-    // In production, you'd want proper cleanup
-    Box::leak(Box::new(ws));
+    // onclose callback: fires both for failed connects and dropped connections
+    let onclose_callback = Closure::once(move |_: CloseEvent| {
+        WASM_SOCKET.with(|socket| socket.borrow_mut().take());
+        if opened.get() {
+            schedule_wasm_reconnect(url, tx, 1, INITIAL_BACKOFF_SECS);
+        } else {
+            schedule_wasm_reconnect(url, tx, attempt + 1, delay);
+        }
+    });
+    ws.set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
+    onclose_callback.forget();
+
+    // Keep the WebSocket alive until it closes
+    WASM_SOCKET.with(|socket| *socket.borrow_mut() = Some(ws));
+}
+
+#[cfg(target_arch = "wasm32")]
+fn schedule_wasm_reconnect(url: String, tx: Sender<StreamEvent>, attempt: u32, delay: f32) {
+    use wasm_bindgen::prelude::*;
+    use wasm_bindgen::JsCast;
+
+    let state = ConnectionState::Reconnecting {
+        attempt,
+        retry_in: delay,
+    };
+    if tx.send(StreamEvent::State(state)).is_err() {
+        return;
+    }
+
+    let Some(window) = web_sys::window() else {
+        return;
+    };
+    let retry = Closure::once_into_js(move || {
+        connect_wasm_websocket(url, tx, attempt, next_backoff(delay));
+    });
+    let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(
+        retry.unchecked_ref(),
+        (delay * 1000.0) as i32,
+    );
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_match_only_when_identical() {
+        assert!(same_token("s3cret", "s3cret"));
+        assert!(!same_token("s3creT", "s3cret"));
+        assert!(!same_token("s3cre", "s3cret"));
+        assert!(!same_token("", "s3cret"));
+    }
 }
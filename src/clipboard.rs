@@ -0,0 +1,138 @@
+// Structures pasted from the clipboard
+// Ctrl+V (Cmd+V on macOS) outside text fields shows the structure in the clipboard text, e.g.
+// coordinates copied from a terminal. The format is guessed from the text, and bare atom lines
+// without the XYZ header are taken as one frame. Natively the clipboard is read with arboard;
+// browsers only hand the clipboard to the page's paste event, whose text is then shown like a
+// structure pushed by the page.
+
+#[cfg(target_arch = "wasm32")]
+use std::sync::LazyLock;
+
+use bevy::prelude::*;
+#[cfg(target_arch = "wasm32")]
+use crossbeam_channel::{unbounded, Receiver, Sender};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::bindings::{KeyAction, KeyBindings};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::events::StructureLoaded;
+use crate::parse::{parse_frames, Format};
+use crate::structure::Crystal;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::structure::Selection;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::trajectory::Trajectory;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::ui::FitView;
+#[cfg(target_arch = "wasm32")]
+use crate::web::push;
+#[cfg(target_arch = "wasm32")]
+use crate::widgets::FocusedField;
+
+// Name of pasted structures in log messages and events
+const SOURCE: &str = "clipboard";
+
+// Text of the paste events, which arrive outside the app
+#[cfg(target_arch = "wasm32")]
+static PASTED: LazyLock<(Sender<String>, Receiver<String>)> = LazyLock::new(unbounded);
+
+// Frames of the structure in pasted text, in the format the text looks like
+fn pasted_frames(text: &str) -> anyhow::Result<Vec<Crystal>> {
+    let text = text.trim_start();
+    let counted = text
+        .lines()
+        .next()
+        .is_some_and(|line| line.trim().parse::<usize>().is_ok());
+    if Format::sniff(text) == Format::Xyz && !counted {
+        // bare atom lines, as copied out of an XYZ file, get their header back
+        let atoms = text.lines().filter(|line| !line.trim().is_empty()).count();
+        return parse_frames(SOURCE, &format!("{atoms}\n\n{text}"), Some(Format::Xyz));
+    }
+    parse_frames(SOURCE, text, None)
+}
+
+// Show the structure in the clipboard on Ctrl+V
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn paste_structure(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut crystal: ResMut<Crystal>,
+    mut selection: ResMut<Selection>,
+    mut trajectory: ResMut<Trajectory>,
+    mut fit: EventWriter<FitView>,
+    mut loaded: EventWriter<StructureLoaded>,
+) {
+    if !bindings.just_pressed(KeyAction::Paste, &keys) {
+        return;
+    }
+    let text = match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text()) {
+        Ok(text) => text,
+        Err(e) => {
+            error!("Failed to read the clipboard: {e}");
+            return;
+        }
+    };
+    let frames = match pasted_frames(&text) {
+        Ok(frames) => frames,
+        Err(e) => {
+            error!("{e:#}");
+            return;
+        }
+    };
+    info!(
+        "Pasted {} frame(s), showing 0 with {} atoms",
+        frames.len(),
+        frames[0].atoms.len()
+    );
+    loaded.write(StructureLoaded {
+        source: SOURCE.to_string(),
+        atom_count: frames[0].atoms.len(),
+        frame_count: frames.len(),
+    });
+    selection.atoms.clear();
+    *crystal = frames[0].clone();
+    trajectory.clear();
+    if frames.len() > 1 {
+        trajectory.load(frames.into_iter().map(Into::into).collect(), 0);
+    }
+    fit.write(FitView);
+}
+
+// Collect the text pasted into the page for as long as it lives
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn listen_for_paste() {
+    use wasm_bindgen::prelude::*;
+
+    let Some(window) = web_sys::window() else {
+        return;
+    };
+    let listener =
+        Closure::<dyn Fn(web_sys::ClipboardEvent)>::new(|event: web_sys::ClipboardEvent| {
+            let text = event
+                .clipboard_data()
+                .and_then(|data| data.get_data("text/plain").ok());
+            if let Some(text) = text.filter(|text| !text.trim().is_empty()) {
+                let _ = PASTED.0.send(text);
+            }
+        });
+    if let Err(e) =
+        window.add_event_listener_with_callback("paste", listener.as_ref().unchecked_ref())
+    {
+        error!("Failed to listen for paste events: {e:?}");
+    }
+    listener.forget();
+}
+
+// Show the structure in text pasted into the page; text meant for a text field is dropped
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn receive_pasted_text(focused: Res<FocusedField>) {
+    for text in PASTED.1.try_iter() {
+        if focused.0.is_some() {
+            continue;
+        }
+        match pasted_frames(&text) {
+            Ok(frames) => push(SOURCE.to_string(), frames),
+            Err(e) => error!("{e:#}"),
+        }
+    }
+}
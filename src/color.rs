@@ -0,0 +1,284 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{get_element_size, Element, DEFAULT_COLOR};
+
+// Palette used to color atoms by element
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ColorScheme {
+    Cpk,
+    #[default]
+    Jmol,
+    Vesta,
+}
+
+impl ColorScheme {
+    pub const ALL: [ColorScheme; 3] = [ColorScheme::Cpk, ColorScheme::Jmol, ColorScheme::Vesta];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ColorScheme::Cpk => "CPK",
+            ColorScheme::Jmol => "Jmol",
+            ColorScheme::Vesta => "VESTA",
+        }
+    }
+
+    pub fn color(self, element: Element) -> Color {
+        if !element.is_known() {
+            return DEFAULT_COLOR;
+        }
+
+        match self {
+            ColorScheme::Jmol => element.color(),
+            ColorScheme::Cpk => lookup(CPK_COLORS, element).unwrap_or(CPK_OTHER),
+            // VESTA has its own defaults for the common elements; fall back to Jmol elsewhere
+            ColorScheme::Vesta => lookup(VESTA_COLORS, element).unwrap_or_else(|| element.color()),
+        }
+    }
+}
+
+// Property that decides atom colors
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ColorBy {
+    #[default]
+    Element,
+    Coordination,
+}
+
+impl ColorBy {
+    #[cfg_attr(
+        not(any(feature = "websocket", feature = "scripting")),
+        allow(dead_code)
+    )]
+    pub const ALL: [ColorBy; 2] = [ColorBy::Element, ColorBy::Coordination];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ColorBy::Element => "Element",
+            ColorBy::Coordination => "Coordination",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            ColorBy::Element => ColorBy::Coordination,
+            ColorBy::Coordination => ColorBy::Element,
+        }
+    }
+}
+
+// Coordination numbers above this share the last color of the ramp
+const MAX_RAMP_COORDINATION: usize = 12;
+
+// Blue (isolated) to red (highly coordinated) ramp
+pub(crate) fn coordination_color(coordination: usize) -> Color {
+    let t = coordination.min(MAX_RAMP_COORDINATION) as f32 / MAX_RAMP_COORDINATION as f32;
+    Color::hsl(240.0 * (1.0 - t), 0.8, 0.5)
+}
+
+/// Stops of the ramp for values of one sign, dark purple through teal to yellow, as viridis.
+const VALUE_RAMP: [(f32, f32, f32); 5] = [
+    (0.27, 0.00, 0.33),
+    (0.23, 0.32, 0.55),
+    (0.13, 0.57, 0.55),
+    (0.37, 0.79, 0.38),
+    (0.99, 0.91, 0.14),
+];
+/// Ends of the ramp for signed values, through white at zero.
+const NEGATIVE_COLOR: (f32, f32, f32) = (0.23, 0.30, 0.75);
+const POSITIVE_COLOR: (f32, f32, f32) = (0.71, 0.02, 0.15);
+
+fn mix((r1, g1, b1): (f32, f32, f32), (r2, g2, b2): (f32, f32, f32), t: f32) -> Color {
+    Color::srgb(r1 + (r2 - r1) * t, g1 + (g2 - g1) * t, b1 + (b2 - b1) * t)
+}
+
+// Color of a grid value scaled to 0..1, or to -1..1 for signed data, which runs from blue
+// through white to red
+pub(crate) fn value_color(value: f32, signed: bool) -> Color {
+    if signed {
+        let value = value.clamp(-1.0, 1.0);
+        return if value < 0.0 {
+            mix((1.0, 1.0, 1.0), NEGATIVE_COLOR, -value)
+        } else {
+            mix((1.0, 1.0, 1.0), POSITIVE_COLOR, value)
+        };
+    }
+    let position = value.clamp(0.0, 1.0) * (VALUE_RAMP.len() - 1) as f32;
+    let stop = (position as usize).min(VALUE_RAMP.len() - 2);
+    mix(
+        VALUE_RAMP[stop],
+        VALUE_RAMP[stop + 1],
+        position - stop as f32,
+    )
+}
+
+// Surface of an element's atoms beyond their color, each from 0 to 1; the atom shader turns
+// roughness into the size of the highlights, metallic into highlights tinted by the color, and
+// emissive into a glow that does not need light
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ElementMaterial {
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: f32,
+}
+
+impl Default for ElementMaterial {
+    fn default() -> Self {
+        Self {
+            metallic: 0.0,
+            roughness: 0.5,
+            emissive: 0.0,
+        }
+    }
+}
+
+impl ElementMaterial {
+    // Layout of the instance attribute
+    pub fn to_array(self) -> [f32; 3] {
+        [self.metallic, self.roughness, self.emissive]
+    }
+}
+
+// Color/radius/material edits for a single element
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct ElementOverride {
+    pub color: Option<Color>,
+    pub radius: Option<f32>,
+    pub material: Option<ElementMaterial>,
+}
+
+// Per-element edits made during this session, layered on top of the color scheme and default sizes
+#[derive(Resource, Default)]
+pub(crate) struct ElementOverrides {
+    overrides: HashMap<Element, ElementOverride>,
+    // Elements whose atoms are not drawn; kept apart so that resetting an element's color and
+    // radius leaves it hidden
+    hidden: HashSet<Element>,
+}
+
+impl ElementOverrides {
+    pub fn get(&self, element: Element) -> ElementOverride {
+        self.overrides.get(&element).copied().unwrap_or_default()
+    }
+
+    pub fn get_mut(&mut self, element: Element) -> &mut ElementOverride {
+        self.overrides.entry(element).or_default()
+    }
+
+    pub fn clear(&mut self, element: Element) {
+        self.overrides.remove(&element);
+    }
+
+    pub fn set_hidden(&mut self, element: Element, hidden: bool) {
+        if hidden {
+            self.hidden.insert(element);
+        } else {
+            self.hidden.remove(&element);
+        }
+    }
+
+    // Whether atoms of `element` are left out of the view
+    pub fn is_hidden(&self, element: Element) -> bool {
+        self.hidden.contains(&element)
+    }
+
+    // Color of `element` under `scheme`, unless the user picked one
+    pub fn color(&self, scheme: ColorScheme, element: Element) -> Color {
+        self.get(element)
+            .color
+            .unwrap_or_else(|| scheme.color(element))
+    }
+
+    // Surface of `element`, unless the user edited it
+    pub fn material(&self, element: Element) -> ElementMaterial {
+        self.get(element).material.unwrap_or_default()
+    }
+
+    // Drawn radius of `element`, unless the user set one
+    pub fn size(&self, element: Element) -> f32 {
+        self.get(element)
+            .radius
+            .unwrap_or_else(|| get_element_size(element))
+    }
+}
+
+fn lookup(table: &[(&str, u32)], element: Element) -> Option<Color> {
+    table
+        .iter()
+        .find(|(symbol, _)| *symbol == element.symbol())
+        .map(|&(_, rgb)| {
+            let [_, r, g, b] = rgb.to_be_bytes();
+            Color::srgb_u8(r, g, b)
+        })
+}
+
+// RasMol CPK colors; every element not listed is deep pink
+const CPK_OTHER: Color = Color::srgb(1.0, 0.078, 0.576);
+
+static CPK_COLORS: &[(&str, u32)] = &[
+    ("H", 0xFFFFFF),
+    ("He", 0xFFC0CB),
+    ("Li", 0xB22222),
+    ("B", 0x00FF00),
+    ("C", 0xC8C8C8),
+    ("N", 0x8F8FFF),
+    ("O", 0xF00000),
+    ("F", 0xDAA520),
+    ("Na", 0x0000FF),
+    ("Mg", 0x228B22),
+    ("Al", 0x808090),
+    ("Si", 0xDAA520),
+    ("P", 0xFFA500),
+    ("S", 0xFFC832),
+    ("Cl", 0x00FF00),
+    ("Ca", 0x808090),
+    ("Ti", 0x808090),
+    ("Cr", 0x808090),
+    ("Mn", 0x808090),
+    ("Fe", 0xFFA500),
+    ("Ni", 0xA52A2A),
+    ("Cu", 0xA52A2A),
+    ("Zn", 0xA52A2A),
+    ("Br", 0xA52A2A),
+    ("Ag", 0x808090),
+    ("I", 0xA020F0),
+    ("Ba", 0xFFA500),
+    ("Au", 0xDAA520),
+];
+
+// VESTA default element colors (elements.ini)
+static VESTA_COLORS: &[(&str, u32)] = &[
+    ("H", 0xFFCCCC),
+    ("He", 0xFCE9CF),
+    ("Li", 0x86E074),
+    ("Be", 0x5ED77B),
+    ("B", 0x1FA20F),
+    ("C", 0x814929),
+    ("N", 0xB0B9E6),
+    ("O", 0xFE0300),
+    ("F", 0xB0B9E6),
+    ("Ne", 0xFE37B5),
+    ("Na", 0xF9DC3C),
+    ("Mg", 0xFB7B15),
+    ("Al", 0x81B2D6),
+    ("Si", 0x1B3BFA),
+    ("P", 0xC09CC2),
+    ("S", 0xFFFA00),
+    ("Cl", 0x32FC03),
+    ("Ar", 0xCFFEC4),
+    ("K", 0xA122F6),
+    ("Ca", 0x5A96BD),
+    ("Ti", 0x78CAFF),
+    ("V", 0xE51900),
+    ("Cr", 0x00009E),
+    ("Mn", 0xA8089E),
+    ("Fe", 0xB57100),
+    ("Co", 0x0000AF),
+    ("Ni", 0xB7BBBD),
+    ("Cu", 0x2247DC),
+    ("Zn", 0x8F8F81),
+];
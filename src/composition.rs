@@ -0,0 +1,241 @@
+// Composition summary panel
+// Chemical formula, per-element counts and, for periodic structures, cell volume and density,
+// with a checkbox per element to hide or show all its atoms (hydrogens, solvent).
+
+use bevy::prelude::*;
+
+use crate::color::ElementOverrides;
+use crate::constants::Element;
+use crate::structure::Crystal;
+use crate::theme::Themed;
+use crate::ui::{SidePanelColumn, ToggleId, ToggledPanel};
+use crate::widgets::{spawn_button, spawn_table_text};
+
+// g/cm³ per u/Å³
+const DENSITY_CONVERSION: f32 = 1.660_539;
+
+/// Root node of the composition panel, hidden until toggled on.
+#[derive(Component)]
+pub(crate) struct CompositionPanel;
+
+/// Text holding the composition summary.
+#[derive(Component)]
+pub(crate) struct CompositionText;
+
+/// Row holding the visibility checkbox of every element.
+#[derive(Component)]
+pub(crate) struct SpeciesToggleRow;
+
+/// Checkbox hiding or showing the atoms of an element.
+#[derive(Component)]
+pub(crate) struct SpeciesToggle(Element);
+
+/// Number of atoms of each element, in Hill order.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Composition {
+    pub counts: Vec<(Element, usize)>,
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+impl Composition {
+    pub fn from_crystal(crystal: &Crystal) -> Self {
+        let mut counts: Vec<(Element, usize)> = Vec::new();
+        for atom in &crystal.atoms {
+            match counts
+                .iter_mut()
+                .find(|(element, _)| *element == atom.element)
+            {
+                Some((_, count)) => *count += 1,
+                None => counts.push((atom.element, 1)),
+            }
+        }
+
+        // Hill order: C and H first when carbon is present, everything else alphabetical
+        let has_carbon = counts.iter().any(|&(element, _)| element == Element::C);
+        counts.sort_by_key(|&(element, _)| {
+            let rank = match element {
+                Element::C if has_carbon => 0,
+                Element::H if has_carbon => 1,
+                _ => 2,
+            };
+            (rank, element.symbol())
+        });
+        Self { counts }
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().map(|(_, count)| count).sum()
+    }
+
+    // Formula with every count divided by `divisor`
+    fn formula_divided(&self, divisor: usize) -> String {
+        self.counts
+            .iter()
+            .map(|(element, count)| match count / divisor {
+                1 => element.to_string(),
+                n => format!("{element}{n}"),
+            })
+            .collect()
+    }
+
+    pub fn formula(&self) -> String {
+        self.formula_divided(1)
+    }
+
+    // Formula reduced by the common factor of all counts, and that factor
+    pub fn reduced_formula(&self) -> (String, usize) {
+        let factor = self
+            .counts
+            .iter()
+            .fold(0, |factor, &(_, count)| gcd(factor, count))
+            .max(1);
+        (self.formula_divided(factor), factor)
+    }
+
+    // Total mass in u; unknown elements count as 1 u
+    pub fn mass(&self) -> f32 {
+        self.counts
+            .iter()
+            .map(|&(element, count)| element.data().mass * count as f32)
+            .sum()
+    }
+}
+
+fn summary(crystal: &Crystal) -> String {
+    let composition = Composition::from_crystal(crystal);
+    let total = composition.total();
+    if total == 0 {
+        return "No atoms".to_string();
+    }
+
+    let (reduced, factor) = composition.reduced_formula();
+    let mut text = format!("Formula  {}\n", composition.formula());
+    if factor > 1 {
+        text.push_str(&format!("Reduced  {reduced} (Z = {factor})\n"));
+    }
+    text.push_str(&format!("Atoms    {total}\n"));
+
+    for (element, count) in &composition.counts {
+        text.push_str(&format!(
+            "\n{:<3} {:>6} {:>6.1}%",
+            element,
+            count,
+            100.0 * *count as f32 / total as f32
+        ));
+    }
+
+    if let Some(lattice) = crystal.lattice {
+        let volume = lattice.determinant().abs();
+        text.push_str(&format!("\n\nVolume   {volume:.3} Å³"));
+        if volume > 0.0 {
+            text.push_str(&format!(
+                "\nDensity  {:.4} g/cm^3",
+                composition.mass() / volume * DENSITY_CONVERSION
+            ));
+        }
+    }
+    text
+}
+
+// Spawn the (hidden) composition panel in the side column
+pub(crate) fn setup_composition_panel(
+    mut commands: Commands,
+    column: Single<Entity, With<SidePanelColumn>>,
+) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(6.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            CompositionPanel,
+            ToggledPanel(ToggleId::Composition),
+            ChildOf(*column),
+        ))
+        .with_children(|panel| {
+            spawn_table_text(panel, CompositionText);
+            panel.spawn((
+                Node {
+                    flex_wrap: FlexWrap::Wrap,
+                    max_width: Val::Px(240.0),
+                    column_gap: Val::Px(4.0),
+                    row_gap: Val::Px(4.0),
+                    ..default()
+                },
+                SpeciesToggleRow,
+            ));
+        });
+}
+
+// Rebuild the summary whenever the structure changes
+pub(crate) fn refresh_composition_panel(
+    crystal: Res<Crystal>,
+    mut texts: Query<&mut Text, With<CompositionText>>,
+) {
+    if !crystal.is_changed() {
+        return;
+    }
+
+    let text = summary(&crystal);
+    for mut content in &mut texts {
+        content.0 = text.clone();
+    }
+}
+
+// Rebuild the element checkboxes when the elements present or the hidden ones change
+pub(crate) fn refresh_species_toggles(
+    mut commands: Commands,
+    crystal: Res<Crystal>,
+    overrides: Res<ElementOverrides>,
+    rows: Query<Entity, With<SpeciesToggleRow>>,
+) {
+    if !crystal.is_changed() && !overrides.is_changed() {
+        return;
+    }
+
+    let composition = Composition::from_crystal(&crystal);
+    for row in &rows {
+        commands
+            .entity(row)
+            .despawn_related::<Children>()
+            .with_children(|row| {
+                for &(element, _) in &composition.counts {
+                    let check = if overrides.is_hidden(element) {
+                        "[ ]"
+                    } else {
+                        "[x]"
+                    };
+                    spawn_button(row, &format!("{check} {element}"), SpeciesToggle(element));
+                }
+            });
+    }
+}
+
+// Hide or show the atoms of the element whose checkbox was clicked
+pub(crate) fn species_toggle_buttons(
+    interactions: Query<(&Interaction, &SpeciesToggle), Changed<Interaction>>,
+    mut overrides: ResMut<ElementOverrides>,
+) {
+    for (interaction, toggle) in &interactions {
+        if *interaction == Interaction::Pressed {
+            let hidden = overrides.is_hidden(toggle.0);
+            overrides.set_hidden(toggle.0, !hidden);
+            info!(
+                "{} {} atoms",
+                if hidden { "Showing" } else { "Hiding" },
+                toggle.0.symbol()
+            );
+        }
+    }
+}
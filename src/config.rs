@@ -0,0 +1,283 @@
+// Persistent defaults from TOML files
+// `vizcrystal/config.toml` in the user's config directory (`~/.config` on Linux) is read first,
+// then `vizcrystal.toml` in the working directory, whose settings win. Every key is optional:
+//
+//   ws_url = "ws://127.0.0.1:9001"
+//   color_scheme = "vesta"         # cpk, jmol or vesta
+//   background = "#202020"
+//   mouse_sensitivity = 1.5        # multiplier for rotating, panning and zooming
+//   render_quality = "high"        # low, medium or high
+//   atom_rendering = "impostors"   # meshes or impostors, see instancing.rs
+//   lighting = "three-point"       # headlight, three-point or studio, see lighting.rs
+//   bonds = "bicolor"              # off, neutral or bicolor, see bonds.rs
+//   labels = "element"             # off, index, element or site, see atom_labels.rs
+//   theme = "light"                # dark, light or a [themes.NAME] table, see theme.rs
+//   frame_cache_mb = 512           # memory for frames of large trajectories, see lazy_frames.rs
+//   materials_project_api_key = "..."  # wins over MP_API_KEY, see materials_project.rs
+//
+//   [elements.O]
+//   color = "#ff2020"
+//   radius = 0.6
+//
+//   [elements.Au]                  # material, each from 0 to 1, see atoms.wgsl
+//   metallic = 1.0
+//   roughness = 0.2
+//   emissive = 0.0
+//
+//   [keys]                         # see bindings.rs
+//   focus_camera = "Space"
+//
+// Command-line options take precedence over the files.
+
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::atom_labels::{LabelNumbering, LabelSettings};
+use crate::bindings::KeyBindings;
+use crate::bonds::BondStyle;
+use crate::color::{ColorScheme, ElementOverrides};
+use crate::constants::Element;
+use crate::instancing::AtomRendering;
+use crate::lighting::{LightingPreset, LightingRig};
+use crate::theme::UiTheme;
+use crate::ui::{MouseSensitivity, RenderQuality, SphereDetail};
+
+#[cfg(not(target_arch = "wasm32"))]
+const CONFIG_DIR: &str = "vizcrystal";
+#[cfg(not(target_arch = "wasm32"))]
+const CONFIG_FILE: &str = "config.toml";
+#[cfg(not(target_arch = "wasm32"))]
+const PROJECT_FILE: &str = "vizcrystal.toml";
+
+/// Per-element color, radius and material replacing the defaults.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ElementConfig {
+    pub color: Option<String>,
+    pub radius: Option<f32>,
+    pub metallic: Option<f32>,
+    pub roughness: Option<f32>,
+    pub emissive: Option<f32>,
+}
+
+/// Interface theme defined in the configuration: a preset with some colors replaced.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ThemeConfig {
+    pub base: Option<String>,
+    pub panel: Option<String>,
+    pub button: Option<String>,
+    pub button_hovered: Option<String>,
+    pub button_pressed: Option<String>,
+    pub border: Option<String>,
+    pub focused_border: Option<String>,
+    pub field: Option<String>,
+    pub text: Option<String>,
+    pub placeholder: Option<String>,
+    pub positive: Option<String>,
+    pub pending: Option<String>,
+    pub negative: Option<String>,
+}
+
+/// Defaults read from the configuration files.
+#[derive(Resource, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    pub ws_url: Option<String>,
+    pub color_scheme: Option<ColorScheme>,
+    pub background: Option<String>,
+    pub mouse_sensitivity: Option<f32>,
+    pub render_quality: Option<RenderQuality>,
+    pub atom_rendering: Option<AtomRendering>,
+    pub lighting: Option<LightingPreset>,
+    pub bonds: Option<BondStyle>,
+    pub labels: Option<LabelNumbering>,
+    pub theme: Option<String>,
+    pub frame_cache_mb: Option<u64>,
+    pub materials_project_api_key: Option<String>,
+    pub themes: BTreeMap<String, ThemeConfig>,
+    pub elements: BTreeMap<String, ElementConfig>,
+    pub keys: BTreeMap<String, String>,
+}
+
+impl Config {
+    // Settings of `other` layered over these
+    fn merge(mut self, other: Config) -> Config {
+        self.ws_url = other.ws_url.or(self.ws_url);
+        self.color_scheme = other.color_scheme.or(self.color_scheme);
+        self.background = other.background.or(self.background);
+        self.mouse_sensitivity = other.mouse_sensitivity.or(self.mouse_sensitivity);
+        self.render_quality = other.render_quality.or(self.render_quality);
+        self.atom_rendering = other.atom_rendering.or(self.atom_rendering);
+        self.lighting = other.lighting.or(self.lighting);
+        self.bonds = other.bonds.or(self.bonds);
+        self.labels = other.labels.or(self.labels);
+        self.theme = other.theme.or(self.theme);
+        self.frame_cache_mb = other.frame_cache_mb.or(self.frame_cache_mb);
+        self.materials_project_api_key = other
+            .materials_project_api_key
+            .or(self.materials_project_api_key);
+        self.themes.extend(other.themes);
+        self.elements.extend(other.elements);
+        self.keys.extend(other.keys);
+        self
+    }
+
+    // User configuration with the project override on top; unreadable files are reported and
+    // skipped
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load() -> Config {
+        let user_file = dirs::config_dir().map(|dir| dir.join(CONFIG_DIR).join(CONFIG_FILE));
+        let project_file = std::path::PathBuf::from(PROJECT_FILE);
+
+        let mut config = Config::default();
+        for path in user_file.into_iter().chain([project_file]) {
+            let contents = match std::fs::read_to_string(&path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    warn!("Cannot read {}: {e}", path.display());
+                    continue;
+                }
+            };
+            match toml::from_str::<Config>(&contents) {
+                Ok(file) => {
+                    info!("Loaded configuration from {}", path.display());
+                    config = config.merge(file);
+                }
+                Err(e) => warn!("Ignoring {}: {e}", path.display()),
+            }
+        }
+        config
+    }
+
+    // The browser has no configuration files
+    #[cfg(target_arch = "wasm32")]
+    fn load() -> Config {
+        Config::default()
+    }
+}
+
+fn parse_color(value: &str) -> Option<Color> {
+    match Srgba::hex(value) {
+        Ok(color) => Some(color.into()),
+        Err(e) => {
+            warn!("Ignoring color '{value}' in the configuration: {e}");
+            None
+        }
+    }
+}
+
+// Theme called `name`: a preset, or one of the configured themes over its base preset
+fn resolve_theme(name: &str, themes: &BTreeMap<String, ThemeConfig>) -> Option<UiTheme> {
+    let Some(custom) = themes.get(name) else {
+        return UiTheme::preset(name);
+    };
+    let base = custom.base.as_deref().unwrap_or("dark");
+    let mut theme = UiTheme::preset(base).unwrap_or_else(|| {
+        warn!("Unknown base '{base}' of theme '{name}', using dark");
+        UiTheme::dark()
+    });
+    for (color, value) in [
+        (&mut theme.panel, &custom.panel),
+        (&mut theme.button, &custom.button),
+        (&mut theme.button_hovered, &custom.button_hovered),
+        (&mut theme.button_pressed, &custom.button_pressed),
+        (&mut theme.border, &custom.border),
+        (&mut theme.focused_border, &custom.focused_border),
+        (&mut theme.field, &custom.field),
+        (&mut theme.text, &custom.text),
+        (&mut theme.placeholder, &custom.placeholder),
+        (&mut theme.positive, &custom.positive),
+        (&mut theme.pending, &custom.pending),
+        (&mut theme.negative, &custom.negative),
+    ] {
+        if let Some(parsed) = value.as_deref().and_then(parse_color) {
+            *color = parsed;
+        }
+    }
+    Some(theme)
+}
+
+// Read the configuration files and apply their defaults
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn load_config(
+    mut config: ResMut<Config>,
+    mut color_scheme: ResMut<ColorScheme>,
+    mut clear_color: ResMut<ClearColor>,
+    mut sensitivity: ResMut<MouseSensitivity>,
+    mut quality: ResMut<RenderQuality>,
+    mut detail: ResMut<SphereDetail>,
+    mut rendering: ResMut<AtomRendering>,
+    mut lighting: ResMut<LightingRig>,
+    mut bonds: ResMut<BondStyle>,
+    mut labels: ResMut<LabelSettings>,
+    mut overrides: ResMut<ElementOverrides>,
+    mut theme: ResMut<UiTheme>,
+    mut bindings: ResMut<KeyBindings>,
+) {
+    *config = Config::load();
+
+    if let Some(scheme) = config.color_scheme {
+        *color_scheme = scheme;
+    }
+    if let Some(background) = config.background.as_deref().and_then(parse_color) {
+        clear_color.0 = background;
+    }
+    if let Some(factor) = config.mouse_sensitivity {
+        if factor > 0.0 {
+            sensitivity.0 = factor;
+        } else {
+            warn!("Ignoring mouse_sensitivity {factor}, expected a positive number");
+        }
+    }
+    if let Some(render_quality) = config.render_quality {
+        *quality = render_quality;
+        detail.0 = render_quality.subdivisions();
+    }
+    if let Some(atom_rendering) = config.atom_rendering {
+        *rendering = atom_rendering;
+    }
+    if let Some(preset) = config.lighting {
+        lighting.preset = preset;
+    }
+    if let Some(style) = config.bonds {
+        *bonds = style;
+    }
+    if let Some(numbering) = config.labels {
+        labels.numbering = numbering;
+    }
+    if let Some(name) = config.theme.as_deref() {
+        match resolve_theme(name, &config.themes) {
+            Some(configured) => *theme = configured,
+            None => warn!("Ignoring unknown theme '{name}'"),
+        }
+    }
+    for (symbol, settings) in &config.elements {
+        let Some(element) = Element::from_symbol(symbol) else {
+            warn!("Ignoring unknown element '{symbol}' in the configuration");
+            continue;
+        };
+        let edited = overrides.get_mut(element);
+        if let Some(color) = settings.color.as_deref().and_then(parse_color) {
+            edited.color = Some(color);
+        }
+        if let Some(radius) = settings.radius {
+            edited.radius = Some(radius);
+        }
+        if let Some(metallic) = settings.metallic {
+            edited.material.get_or_insert_default().metallic = metallic.clamp(0.0, 1.0);
+        }
+        if let Some(roughness) = settings.roughness {
+            edited.material.get_or_insert_default().roughness = roughness.clamp(0.0, 1.0);
+        }
+        if let Some(emissive) = settings.emissive {
+            edited.material.get_or_insert_default().emissive = emissive.clamp(0.0, 1.0);
+        }
+    }
+    for (action, key) in &config.keys {
+        bindings.configure(action, key);
+    }
+}
@@ -0,0 +1,225 @@
+// Log console panel
+// Warnings and errors logged anywhere in the app (parse failures, stream disconnects, dropped
+// frames) are captured by a tracing layer and listed in a scrollable panel, so they can be
+// read without starting the viewer from a terminal.
+
+use std::collections::VecDeque;
+
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+use bevy::log::tracing::field::{Field, Visit};
+use bevy::log::tracing::{Event, Subscriber};
+use bevy::log::tracing_subscriber::layer::{Context, Layer};
+use bevy::log::{BoxedLayer, Level};
+use bevy::prelude::*;
+use crossbeam_channel::{unbounded, Receiver, Sender};
+
+use crate::theme::{Themed, UiTheme};
+use crate::ui::{ToggleId, ToggledPanel, ToolPanelRow};
+use crate::widgets::spawn_button;
+
+// Lines kept; older ones are dropped
+const MAX_LINES: usize = 200;
+// Pixels scrolled per wheel line
+const LINE_HEIGHT: f32 = 16.0;
+
+/// Log lines captured by the tracing layer, waiting to be shown.
+#[derive(Resource)]
+pub(crate) struct LogReceiver(Receiver<(Level, String)>);
+
+/// Warnings and errors logged so far, oldest first.
+#[derive(Resource, Default)]
+pub(crate) struct ConsoleLog {
+    pub lines: VecDeque<(Level, String)>,
+}
+
+/// Scrolling node holding one text per log line.
+#[derive(Component)]
+pub(crate) struct ConsoleLines;
+
+/// Button emptying the console.
+#[derive(Component)]
+pub(crate) struct ClearConsoleButton;
+
+// Forwards the message of every warning and error to the console
+struct CaptureLayer(Sender<(Level, String)>);
+
+impl<S: Subscriber> Layer<S> for CaptureLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let level = *event.metadata().level();
+        if level > Level::WARN {
+            return;
+        }
+        let mut message = MessageVisitor(None);
+        event.record(&mut message);
+        if let Some(message) = message.0 {
+            let _ = self.0.send((level, message));
+        }
+    }
+}
+
+struct MessageVisitor(Option<String>);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = Some(format!("{value:?}"));
+        }
+    }
+}
+
+/// `LogPlugin::custom_layer` hook capturing warnings and errors for the console.
+pub(crate) fn capture_log(app: &mut App) -> Option<BoxedLayer> {
+    let (tx, rx) = unbounded();
+    app.insert_resource(LogReceiver(rx));
+    Some(Box::new(CaptureLayer(tx)))
+}
+
+fn line_color(level: Level, theme: &UiTheme) -> Color {
+    if level == Level::ERROR {
+        theme.negative
+    } else {
+        theme.pending
+    }
+}
+
+// Spawn the (hidden) console panel in the tool row
+pub(crate) fn setup_console_panel(mut commands: Commands, row: Single<Entity, With<ToolPanelRow>>) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            ToggledPanel(ToggleId::Console),
+            ChildOf(*row),
+        ))
+        .with_children(|panel| {
+            panel
+                .spawn(Node {
+                    flex_direction: FlexDirection::Row,
+                    justify_content: JustifyContent::SpaceBetween,
+                    align_items: AlignItems::Center,
+                    column_gap: Val::Px(8.0),
+                    ..default()
+                })
+                .with_children(|header| {
+                    header.spawn((
+                        Text::new("Log"),
+                        TextFont {
+                            font: default(),
+                            font_size: 14.0,
+                            ..default()
+                        },
+                        Themed::Text,
+                    ));
+                    spawn_button(header, "Clear", ClearConsoleButton);
+                });
+            // hovered to scroll with the mouse wheel
+            panel.spawn((
+                Node {
+                    width: Val::Px(420.0),
+                    height: Val::Px(160.0),
+                    flex_direction: FlexDirection::Column,
+                    overflow: Overflow::scroll_y(),
+                    ..default()
+                },
+                Interaction::default(),
+                ScrollPosition::default(),
+                ConsoleLines,
+            ));
+        });
+}
+
+// Move captured lines into the console, dropping the oldest past the limit
+pub(crate) fn receive_log_lines(receiver: Option<Res<LogReceiver>>, mut log: ResMut<ConsoleLog>) {
+    let Some(receiver) = receiver else {
+        return;
+    };
+    let received: Vec<_> = receiver.0.try_iter().collect();
+    if received.is_empty() {
+        return;
+    }
+    log.lines.extend(received);
+    let excess = log.lines.len().saturating_sub(MAX_LINES);
+    log.lines.drain(..excess);
+}
+
+// Rebuild the listed lines when the log or the theme changes, keeping the newest in view
+pub(crate) fn refresh_console_panel(
+    mut commands: Commands,
+    log: Res<ConsoleLog>,
+    theme: Res<UiTheme>,
+    mut lines: Query<(Entity, &mut ScrollPosition), With<ConsoleLines>>,
+) {
+    if !log.is_changed() && !theme.is_changed() {
+        return;
+    }
+
+    for (entity, mut scroll) in &mut lines {
+        commands.entity(entity).despawn_related::<Children>();
+        if log.lines.is_empty() {
+            commands.spawn((
+                Text::new("No warnings or errors"),
+                TextFont {
+                    font: default(),
+                    font_size: 12.0,
+                    ..default()
+                },
+                TextColor(theme.placeholder),
+                ChildOf(entity),
+            ));
+        }
+        for (level, message) in &log.lines {
+            commands.spawn((
+                Text::new(format!("{level:<5} {message}")),
+                TextFont {
+                    font: default(),
+                    font_size: 12.0,
+                    ..default()
+                },
+                TextColor(line_color(*level, &theme)),
+                ChildOf(entity),
+            ));
+        }
+        // layout clamps the offset to the end of the content
+        scroll.offset_y = f32::MAX;
+    }
+}
+
+// Scroll the console under the cursor with the mouse wheel
+pub(crate) fn scroll_console(
+    mut wheel: EventReader<MouseWheel>,
+    mut lines: Query<(&Interaction, &mut ScrollPosition), With<ConsoleLines>>,
+) {
+    let delta: f32 = wheel
+        .read()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y * LINE_HEIGHT,
+            MouseScrollUnit::Pixel => event.y,
+        })
+        .sum();
+    if delta == 0.0 {
+        return;
+    }
+    for (interaction, mut scroll) in &mut lines {
+        if *interaction != Interaction::None {
+            scroll.offset_y = (scroll.offset_y - delta).max(0.0);
+        }
+    }
+}
+
+// Empty the console when Clear is clicked
+pub(crate) fn clear_console_button(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<ClearConsoleButton>)>,
+    mut log: ResMut<ConsoleLog>,
+) {
+    for interaction in &interactions {
+        if *interaction == Interaction::Pressed {
+            log.lines.clear();
+        }
+    }
+}
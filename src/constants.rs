@@ -1,37 +1,383 @@
+use std::borrow::Cow;
+use std::fmt;
+
 use bevy::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// Drawn atom radius as a fraction of the van der Waals radius
+pub(crate) const ATOM_SIZE_SCALE: f32 = 0.25;
+
+// Fallbacks for symbols that are not in the periodic table; an unknown atom weighs 1 u, so
+// mass-weighted sums over it stay meaningful
+pub(crate) const DEFAULT_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
+const DEFAULT_SIZE: f32 = 0.35;
+const DEFAULT_COVALENT_RADIUS: f32 = 0.77;
+static UNKNOWN: ElementData = e(
+    "X",
+    "Unknown",
+    1.0,
+    DEFAULT_COVALENT_RADIUS,
+    DEFAULT_SIZE / ATOM_SIZE_SCALE,
+    0x808080,
+);
+
+// Per-element reference data
+// masses: IUPAC standard atomic weights (mass number of the most stable isotope for radioactive
+// elements)
+// covalent radii: Cordero et al. 2008 up to Cm, Pyykkö single-bond radii beyond
+// van der Waals radii: Alvarez 2013 where available, 2.0 Å otherwise
+// colors: Jmol palette
+pub struct ElementData {
+    pub symbol: &'static str,
+    pub name: &'static str,
+    pub mass: f32,
+    pub covalent_radius: f32,
+    pub vdw_radius: f32,
+    pub color: u32,
+}
+
+// The `Element` enum, the `ELEMENTS` table and the elements by atomic number, from one
+// (symbol, name, mass, covalent radius, van der Waals radius, color) row per element
+macro_rules! elements {
+    ($((
+        $symbol:ident,
+        $name:literal,
+        $mass:literal,
+        $covalent:literal,
+        $vdw:literal,
+        $color:literal
+    )),* $(,)?) => {
+        /// A chemical element, or `Unknown` for an atom name that does not start with an element
+        /// symbol, such as the dummy atom "X". The discriminant is the atomic number.
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        #[repr(u8)]
+        pub enum Element {
+            #[default]
+            Unknown = 0,
+            $($symbol),*
+        }
+
+        // Indexed by atomic number - 1
+        pub(crate) static ELEMENTS: [ElementData; 118] =
+            [$(e(stringify!($symbol), $name, $mass, $covalent, $vdw, $color)),*];
+
+        // Indexed by atomic number - 1
+        const BY_NUMBER: [Element; 118] = [$(Element::$symbol),*];
+    };
+}
+
+const fn e(
+    symbol: &'static str,
+    name: &'static str,
+    mass: f32,
+    covalent_radius: f32,
+    vdw_radius: f32,
+    color: u32,
+) -> ElementData {
+    ElementData {
+        symbol,
+        name,
+        mass,
+        covalent_radius,
+        vdw_radius,
+        color,
+    }
+}
+
+elements! {
+    (H, "Hydrogen", 1.008, 0.31, 1.10, 0xFFFFFF),
+    (He, "Helium", 4.0026, 0.28, 1.40, 0xD9FFFF),
+    (Li, "Lithium", 6.94, 1.28, 1.81, 0xCC80FF),
+    (Be, "Beryllium", 9.0122, 0.96, 1.53, 0xC2FF00),
+    (B, "Boron", 10.81, 0.84, 1.92, 0xFFB5B5),
+    (C, "Carbon", 12.011, 0.76, 1.70, 0x909090),
+    (N, "Nitrogen", 14.007, 0.71, 1.55, 0x3050F8),
+    (O, "Oxygen", 15.999, 0.66, 1.52, 0xFF0D0D),
+    (F, "Fluorine", 18.998, 0.57, 1.47, 0x90E050),
+    (Ne, "Neon", 20.180, 0.58, 1.54, 0xB3E3F5),
+    (Na, "Sodium", 22.990, 1.66, 2.27, 0xAB5CF2),
+    (Mg, "Magnesium", 24.305, 1.41, 1.73, 0x8AFF00),
+    (Al, "Aluminium", 26.982, 1.21, 1.84, 0xBFA6A6),
+    (Si, "Silicon", 28.085, 1.11, 2.10, 0xF0C8A0),
+    (P, "Phosphorus", 30.974, 1.07, 1.80, 0xFF8000),
+    (S, "Sulfur", 32.06, 1.05, 1.80, 0xFFFF30),
+    (Cl, "Chlorine", 35.45, 1.02, 1.75, 0x1FF01F),
+    (Ar, "Argon", 39.948, 1.06, 1.88, 0x80D1E3),
+    (K, "Potassium", 39.098, 2.03, 2.75, 0x8F40D4),
+    (Ca, "Calcium", 40.078, 1.76, 2.31, 0x3DFF00),
+    (Sc, "Scandium", 44.956, 1.70, 2.15, 0xE6E6E6),
+    (Ti, "Titanium", 47.867, 1.60, 2.11, 0xBFC2C7),
+    (V, "Vanadium", 50.942, 1.53, 2.07, 0xA6A6AB),
+    (Cr, "Chromium", 51.996, 1.39, 2.06, 0x8A99C7),
+    (Mn, "Manganese", 54.938, 1.39, 2.05, 0x9C7AC7),
+    (Fe, "Iron", 55.845, 1.32, 2.04, 0xE06633),
+    (Co, "Cobalt", 58.933, 1.26, 2.00, 0xF090A0),
+    (Ni, "Nickel", 58.693, 1.24, 1.97, 0x50D050),
+    (Cu, "Copper", 63.546, 1.32, 1.96, 0xC88033),
+    (Zn, "Zinc", 65.38, 1.22, 2.01, 0x7D80B0),
+    (Ga, "Gallium", 69.723, 1.22, 1.87, 0xC28F8F),
+    (Ge, "Germanium", 72.630, 1.20, 2.11, 0x668F8F),
+    (As, "Arsenic", 74.922, 1.19, 1.85, 0xBD80E3),
+    (Se, "Selenium", 78.971, 1.20, 1.90, 0xFFA100),
+    (Br, "Bromine", 79.904, 1.20, 1.83, 0xA62929),
+    (Kr, "Krypton", 83.798, 1.16, 2.02, 0x5CB8D1),
+    (Rb, "Rubidium", 85.468, 2.20, 3.03, 0x702EB0),
+    (Sr, "Strontium", 87.62, 1.95, 2.49, 0x00FF00),
+    (Y, "Yttrium", 88.906, 1.90, 2.32, 0x94FFFF),
+    (Zr, "Zirconium", 91.224, 1.75, 2.23, 0x94E0E0),
+    (Nb, "Niobium", 92.906, 1.64, 2.18, 0x73C2C9),
+    (Mo, "Molybdenum", 95.95, 1.54, 2.17, 0x54B5B5),
+    (Tc, "Technetium", 98.0, 1.47, 2.16, 0x3B9E9E),
+    (Ru, "Ruthenium", 101.07, 1.46, 2.13, 0x248F8F),
+    (Rh, "Rhodium", 102.91, 1.42, 2.10, 0x0A7D8C),
+    (Pd, "Palladium", 106.42, 1.39, 2.10, 0x006985),
+    (Ag, "Silver", 107.87, 1.45, 2.11, 0xC0C0C0),
+    (Cd, "Cadmium", 112.41, 1.44, 2.18, 0xFFD98F),
+    (In, "Indium", 114.82, 1.42, 1.93, 0xA67573),
+    (Sn, "Tin", 118.71, 1.39, 2.17, 0x668080),
+    (Sb, "Antimony", 121.76, 1.39, 2.06, 0x9E63B5),
+    (Te, "Tellurium", 127.60, 1.38, 2.06, 0xD47A00),
+    (I, "Iodine", 126.90, 1.39, 1.98, 0x940094),
+    (Xe, "Xenon", 131.29, 1.40, 2.16, 0x429EB0),
+    (Cs, "Caesium", 132.91, 2.44, 3.43, 0x57178F),
+    (Ba, "Barium", 137.33, 2.15, 2.68, 0x00C900),
+    (La, "Lanthanum", 138.91, 2.07, 2.43, 0x70D4FF),
+    (Ce, "Cerium", 140.12, 2.04, 2.42, 0xFFFFC7),
+    (Pr, "Praseodymium", 140.91, 2.03, 2.40, 0xD9FFC7),
+    (Nd, "Neodymium", 144.24, 2.01, 2.39, 0xC7FFC7),
+    (Pm, "Promethium", 145.0, 1.99, 2.38, 0xA3FFC7),
+    (Sm, "Samarium", 150.36, 1.98, 2.36, 0x8FFFC7),
+    (Eu, "Europium", 151.96, 1.98, 2.35, 0x61FFC7),
+    (Gd, "Gadolinium", 157.25, 1.96, 2.34, 0x45FFC7),
+    (Tb, "Terbium", 158.93, 1.94, 2.33, 0x30FFC7),
+    (Dy, "Dysprosium", 162.50, 1.92, 2.31, 0x1FFFC7),
+    (Ho, "Holmium", 164.93, 1.92, 2.30, 0x00FF9C),
+    (Er, "Erbium", 167.26, 1.89, 2.29, 0x00E675),
+    (Tm, "Thulium", 168.93, 1.90, 2.27, 0x00D452),
+    (Yb, "Ytterbium", 173.05, 1.87, 2.26, 0x00BF38),
+    (Lu, "Lutetium", 174.97, 1.87, 2.24, 0x00AB24),
+    (Hf, "Hafnium", 178.49, 1.75, 2.23, 0x4DC2FF),
+    (Ta, "Tantalum", 180.95, 1.70, 2.22, 0x4DA6FF),
+    (W, "Tungsten", 183.84, 1.62, 2.18, 0x2194D6),
+    (Re, "Rhenium", 186.21, 1.51, 2.16, 0x267DAB),
+    (Os, "Osmium", 190.23, 1.44, 2.16, 0x266696),
+    (Ir, "Iridium", 192.22, 1.41, 2.13, 0x175487),
+    (Pt, "Platinum", 195.08, 1.36, 2.13, 0xD0D0E0),
+    (Au, "Gold", 196.97, 1.36, 2.14, 0xFFD123),
+    (Hg, "Mercury", 200.59, 1.32, 2.23, 0xB8B8D0),
+    (Tl, "Thallium", 204.38, 1.45, 1.96, 0xA6544D),
+    (Pb, "Lead", 207.2, 1.46, 2.02, 0x575961),
+    (Bi, "Bismuth", 208.98, 1.48, 2.07, 0x9E4FB5),
+    (Po, "Polonium", 209.0, 1.40, 1.97, 0xAB5C00),
+    (At, "Astatine", 210.0, 1.50, 2.02, 0x754F45),
+    (Rn, "Radon", 222.0, 1.50, 2.20, 0x428296),
+    (Fr, "Francium", 223.0, 2.60, 3.48, 0x420066),
+    (Ra, "Radium", 226.0, 2.21, 2.83, 0x007D00),
+    (Ac, "Actinium", 227.0, 2.15, 2.47, 0x70ABFA),
+    (Th, "Thorium", 232.04, 2.06, 2.45, 0x00BAFF),
+    (Pa, "Protactinium", 231.04, 2.00, 2.43, 0x00A1FF),
+    (U, "Uranium", 238.03, 1.96, 2.41, 0x008FFF),
+    (Np, "Neptunium", 237.0, 1.90, 2.39, 0x0080FF),
+    (Pu, "Plutonium", 244.0, 1.87, 2.43, 0x006BFF),
+    (Am, "Americium", 243.0, 1.80, 2.44, 0x545CF2),
+    (Cm, "Curium", 247.0, 1.69, 2.45, 0x785CE3),
+    (Bk, "Berkelium", 247.0, 1.68, 2.44, 0x8A4FE3),
+    (Cf, "Californium", 251.0, 1.68, 2.45, 0xA136D4),
+    (Es, "Einsteinium", 252.0, 1.65, 2.45, 0xB31FD4),
+    (Fm, "Fermium", 257.0, 1.67, 2.45, 0xB31FBA),
+    (Md, "Mendelevium", 258.0, 1.73, 2.46, 0xB30DA6),
+    (No, "Nobelium", 259.0, 1.76, 2.46, 0xBD0D87),
+    (Lr, "Lawrencium", 266.0, 1.61, 2.46, 0xC70066),
+    (Rf, "Rutherfordium", 267.0, 1.57, 2.00, 0xCC0059),
+    (Db, "Dubnium", 268.0, 1.49, 2.00, 0xD1004F),
+    (Sg, "Seaborgium", 269.0, 1.43, 2.00, 0xD90045),
+    (Bh, "Bohrium", 270.0, 1.41, 2.00, 0xE00038),
+    (Hs, "Hassium", 269.0, 1.34, 2.00, 0xE6002E),
+    (Mt, "Meitnerium", 278.0, 1.29, 2.00, 0xEB0026),
+    (Ds, "Darmstadtium", 281.0, 1.28, 2.00, 0xEB0026),
+    (Rg, "Roentgenium", 282.0, 1.21, 2.00, 0xEB0026),
+    (Cn, "Copernicium", 285.0, 1.22, 2.00, 0xEB0026),
+    (Nh, "Nihonium", 286.0, 1.36, 2.00, 0xEB0026),
+    (Fl, "Flerovium", 289.0, 1.43, 2.00, 0xEB0026),
+    (Mc, "Moscovium", 290.0, 1.62, 2.00, 0xEB0026),
+    (Lv, "Livermorium", 293.0, 1.75, 2.00, 0xEB0026),
+    (Ts, "Tennessine", 294.0, 1.65, 2.00, 0xEB0026),
+    (Og, "Oganesson", 294.0, 1.57, 2.00, 0xEB0026),
+}
+
+// Element `text` starts with, so that labels such as "Fe1", "Fe_pv", "OW" or "HW1" resolve to
+// their element. Two letters name the element only when the second is lowercase ("Fe1",
+// "cl2"): all-caps names such as PDB's "CA1", "ND1" or "SG1" give C, N and S from their first
+// letter rather than Ca, Nd and Sg
+fn symbol_prefix(text: &str) -> Option<Element> {
+    let letters = text
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .map_or(text, |end| &text[..end]);
+    let two_letters = letters
+        .as_bytes()
+        .get(1)
+        .is_some_and(u8::is_ascii_lowercase)
+        .then(|| Element::from_symbol(&letters[..2]))
+        .flatten();
+    two_letters.or_else(|| Element::from_symbol(letters.get(..1)?))
+}
+
+impl Element {
+    pub fn from_atomic_number(number: u8) -> Option<Self> {
+        BY_NUMBER.get((number as usize).checked_sub(1)?).copied()
+    }
+
+    // Case-insensitive symbol lookup, so "FE", "fe" and " Fe" all resolve to iron
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        let symbol = symbol.trim();
+        ELEMENTS
+            .iter()
+            .position(|data| data.symbol.eq_ignore_ascii_case(symbol))
+            .map(|index| BY_NUMBER[index])
+    }
+
+    // Element of an atom name as structure files write it: a symbol in any case, possibly
+    // followed by a number or suffix ("Fe1", "Fe_pv", "O2-"); Unknown when it names none
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim();
+        Self::from_symbol(text)
+            .or_else(|| symbol_prefix(text))
+            .unwrap_or(Element::Unknown)
+    }
+
+    // Element of a PDB atom name such as "CA", "ND1" or "1HB": the first letter after any
+    // leading digits, since these names spell the element in one letter and give two-letter
+    // elements only in the type symbol ("CA" is an alpha carbon, not calcium)
+    pub fn from_atom_name(name: &str) -> Self {
+        let name = name.trim().trim_start_matches(|c: char| c.is_ascii_digit());
+        name.get(..1)
+            .and_then(Self::from_symbol)
+            .unwrap_or(Element::Unknown)
+    }
+
+    // Atomic number, 0 for Unknown
+    pub fn atomic_number(self) -> u8 {
+        self as u8
+    }
+
+    pub fn is_known(self) -> bool {
+        self != Element::Unknown
+    }
+
+    pub fn data(self) -> &'static ElementData {
+        match self {
+            Element::Unknown => &UNKNOWN,
+            element => &ELEMENTS[element as usize - 1],
+        }
+    }
+
+    // Symbol, "X" for Unknown
+    pub fn symbol(self) -> &'static str {
+        self.data().symbol
+    }
+
+    pub fn color(self) -> Color {
+        let [_, r, g, b] = self.data().color.to_be_bytes();
+        Color::srgb_u8(r, g, b)
+    }
+}
+
+impl fmt::Display for Element {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+impl From<&str> for Element {
+    fn from(text: &str) -> Self {
+        Element::parse(text)
+    }
+}
+
+impl From<&String> for Element {
+    fn from(text: &String) -> Self {
+        Element::parse(text)
+    }
+}
+
+impl From<String> for Element {
+    fn from(text: String) -> Self {
+        Element::parse(&text)
+    }
+}
+
+// Elements travel as their symbols, e.g. in the WebSocket protocol
+impl Serialize for Element {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.symbol())
+    }
+}
+
+impl<'de> Deserialize<'de> for Element {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let symbol = <Cow<str>>::deserialize(deserializer)?;
+        Ok(Element::parse(&symbol))
+    }
+}
+
+// Drawn size of `element` (van der Waals radius scaled)
+pub(crate) fn get_element_size(element: Element) -> f32 {
+    element.data().vdw_radius * ATOM_SIZE_SCALE
+}
+
+// Covalent radius of `element`
+pub(crate) fn get_covalent_radius(element: Element) -> f32 {
+    element.data().covalent_radius
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_resolve_to_their_element() {
+        for (label, element) in [
+            ("Fe1", Element::Fe),
+            ("Fe_pv", Element::Fe),
+            ("cl2", Element::Cl),
+            ("OW", Element::O),
+            ("HW1", Element::H),
+            ("O2-", Element::O),
+            ("FE", Element::Fe),
+            ("Ca", Element::Ca),
+        ] {
+            assert_eq!(Element::parse(label), element, "{label}");
+        }
+        assert_eq!(Element::parse("Q1"), Element::Unknown);
+    }
+
+    #[test]
+    fn all_caps_names_use_their_first_letter() {
+        for (label, element) in [
+            ("CA1", Element::C),
+            ("ND1", Element::N),
+            ("NE2", Element::N),
+            ("CD1", Element::C),
+            ("SG1", Element::S),
+            ("HG21", Element::H),
+        ] {
+            assert_eq!(Element::parse(label), element, "{label}");
+        }
+    }
 
-// Get color for different elements
-pub(crate) fn get_element_color(element: &str) -> Color {
-    match element.to_uppercase().as_str() {
-        "H" => Color::srgb(1.0, 1.0, 1.0),     // Hydrogen - white
-        "C" => Color::srgb(0.0, 0.0, 0.0),     // Carbon - black
-        "N" => Color::srgb(0.0, 0.0, 1.0),     // Nitrogen - blue
-        "O" => Color::srgb(1.0, 0.0, 0.0),     // Oxygen - red
-        "S" => Color::srgb(1.0, 1.0, 0.0),     // Sulfur - yellow
-        "P" => Color::srgb(1.0, 0.65, 0.0),    // Phosphorus - orange
-        "CL" => Color::srgb(0.0, 1.0, 0.0),    // Chlorine - green
-        "BR" => Color::srgb(0.65, 0.16, 0.16), // Bromine - dark red
-        "I" => Color::srgb(0.58, 0.0, 0.58),   // Iodine - purple
-        "FE" => Color::srgb(1.0, 0.65, 0.0),   // Iron - orange
-        "ZN" => Color::srgb(0.49, 0.50, 0.69), // Zinc - bluish
-        _ => Color::srgb(0.5, 0.5, 0.5),       // Default - gray
-    }
-}
-
-// Get size for different elements (van der Waals radius scaled)
-pub(crate) fn get_element_size(element: &str) -> f32 {
-    match element.to_uppercase().as_str() {
-        "H" => 0.3,   // Hydrogen
-        "C" => 0.4,   // Carbon
-        "N" => 0.35,  // Nitrogen
-        "O" => 0.32,  // Oxygen
-        "S" => 0.45,  // Sulfur
-        "P" => 0.42,  // Phosphorus
-        "CL" => 0.4,  // Chlorine
-        "BR" => 0.45, // Bromine
-        "I" => 0.5,   // Iodine
-        "FE" => 0.4,  // Iron
-        "ZN" => 0.35, // Zinc
-        _ => 0.35,    // Default
+    #[test]
+    fn pdb_atom_names_use_their_first_letter() {
+        for (name, element) in [
+            ("CA", Element::C),
+            ("ND1", Element::N),
+            ("NE2", Element::N),
+            ("CD1", Element::C),
+            ("SG", Element::S),
+            ("HG", Element::H),
+            ("1HB", Element::H),
+        ] {
+            assert_eq!(Element::from_atom_name(name), element, "{name}");
+        }
+        assert_eq!(Element::from_atom_name(""), Element::Unknown);
     }
 }
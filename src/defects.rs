@@ -0,0 +1,224 @@
+// Point-defect editing
+// Builds a supercell and edits it through the current selection: selected atoms can be removed
+// (vacancies) or given another element (substitutions), and a new atom can be inserted at the
+// center of the selection (interstitial). The result can be exported as extended XYZ.
+
+use bevy::prelude::*;
+
+use crate::cell::supercell;
+use crate::constants::{Element, ELEMENTS};
+use crate::io::export_text_file;
+use crate::parse::write_xyz;
+use crate::structure::{Atom, Crystal, Selection};
+use crate::theme::Themed;
+use crate::toast::Toast;
+use crate::ui::{ToggleId, ToggledPanel, ToolPanelRow};
+use crate::widgets::{spawn_button, spawn_stepper_row, StepperSettings};
+
+const EXPORT_FILE_NAME: &str = "defect_structure.xyz";
+const MAX_REPEATS: i32 = 8;
+
+/// Supercell size and the element used for substitutions and interstitials.
+#[derive(Resource, Clone, Copy, Debug)]
+pub(crate) struct DefectSettings {
+    pub repeats: IVec3,
+    pub element: Element,
+}
+
+impl Default for DefectSettings {
+    fn default() -> Self {
+        Self {
+            repeats: IVec3::splat(2),
+            element: Element::C,
+        }
+    }
+}
+
+impl StepperSettings for DefectSettings {
+    fn step(&mut self, field: usize, direction: i32) {
+        match field {
+            0..=2 => self.repeats[field] = (self.repeats[field] + direction).clamp(1, MAX_REPEATS),
+            _ => {
+                let count = ELEMENTS.len() as i32;
+                let number =
+                    (self.element.atomic_number() as i32 - 1 + direction).rem_euclid(count);
+                if let Some(element) = Element::from_atomic_number(number as u8 + 1) {
+                    self.element = element;
+                }
+            }
+        }
+    }
+
+    fn value_text(&self, field: usize) -> String {
+        match field {
+            0..=2 => self.repeats[field].to_string(),
+            _ => self.element.symbol().to_string(),
+        }
+    }
+}
+
+/// Actions of the defect panel.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum DefectAction {
+    Supercell,
+    Remove,
+    Substitute,
+    Insert,
+    Export,
+}
+
+impl DefectAction {
+    fn label(self) -> &'static str {
+        match self {
+            DefectAction::Supercell => "Build Supercell",
+            DefectAction::Remove => "Remove Selected",
+            DefectAction::Substitute => "Substitute Selected",
+            DefectAction::Insert => "Insert at Selection",
+            DefectAction::Export => "Export XYZ",
+        }
+    }
+}
+
+// Spawn the (hidden) defect panel in the tool row
+pub(crate) fn setup_defect_panel(
+    mut commands: Commands,
+    row: Single<Entity, With<ToolPanelRow>>,
+    settings: Res<DefectSettings>,
+) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            ToggledPanel(ToggleId::DefectTool),
+            ChildOf(*row),
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                Text::new("Point defects"),
+                TextFont {
+                    font: default(),
+                    font_size: 14.0,
+                    ..default()
+                },
+                Themed::Text,
+            ));
+            for (field, label) in ["Repeat a", "Repeat b", "Repeat c"].into_iter().enumerate() {
+                spawn_stepper_row(panel, label, field, &*settings);
+            }
+            spawn_button(
+                panel,
+                DefectAction::Supercell.label(),
+                DefectAction::Supercell,
+            );
+            spawn_stepper_row(panel, "Element", 3, &*settings);
+            for action in [
+                DefectAction::Remove,
+                DefectAction::Substitute,
+                DefectAction::Insert,
+                DefectAction::Export,
+            ] {
+                spawn_button(panel, action.label(), action);
+            }
+        });
+}
+
+// Apply defect panel actions to the structure and the selection
+pub(crate) fn defect_actions(
+    interactions: Query<(&Interaction, &DefectAction), Changed<Interaction>>,
+    settings: Res<DefectSettings>,
+    mut crystal: ResMut<Crystal>,
+    mut selection: ResMut<Selection>,
+    mut toasts: EventWriter<Toast>,
+) {
+    for (interaction, action) in &interactions {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        match action {
+            DefectAction::Supercell => match supercell(&crystal, settings.repeats) {
+                Some(repeated) => {
+                    info!(
+                        "Built {}x{}x{} supercell with {} atoms",
+                        settings.repeats.x,
+                        settings.repeats.y,
+                        settings.repeats.z,
+                        repeated.atoms.len()
+                    );
+                    selection.atoms.clear();
+                    *crystal = repeated;
+                }
+                None => {
+                    let message = "A supercell needs a periodic structure";
+                    warn!("{message}");
+                    toasts.write(Toast::warning(message));
+                }
+            },
+            DefectAction::Remove => {
+                if selection.atoms.is_empty() {
+                    let message = "Select the atoms to remove first";
+                    warn!("{message}");
+                    toasts.write(Toast::warning(message));
+                    continue;
+                }
+                let removed = selection.atoms.len();
+                let count = crystal.atoms.len();
+                let kept: Vec<usize> = (0..count)
+                    .filter(|index| !selection.atoms.contains(index))
+                    .collect();
+                crystal.atoms = kept.iter().map(|&i| crystal.atoms[i].clone()).collect();
+                crystal.properties = crystal.properties.select(count, &kept);
+                // indices after the removed atoms have shifted
+                selection.atoms.clear();
+                info!("Created {removed} vacancy(ies)");
+            }
+            DefectAction::Substitute => {
+                if selection.atoms.is_empty() {
+                    let message = "Select the atoms to substitute first";
+                    warn!("{message}");
+                    toasts.write(Toast::warning(message));
+                    continue;
+                }
+                for &index in &selection.atoms {
+                    if let Some(atom) = crystal.atoms.get_mut(index) {
+                        atom.element = settings.element;
+                    }
+                }
+                info!(
+                    "Substituted {} atom(s) with {}",
+                    selection.atoms.len(),
+                    settings.element
+                );
+            }
+            DefectAction::Insert => {
+                // an empty selection would mean "all atoms" to bounding_sphere
+                if selection.atoms.is_empty() {
+                    let message = "Select the atoms surrounding the interstitial site first";
+                    warn!("{message}");
+                    toasts.write(Toast::warning(message));
+                    continue;
+                }
+                let Some((center, _)) = crystal.bounding_sphere(&selection.atoms) else {
+                    continue;
+                };
+                let symbol = settings.element.symbol();
+                crystal.atoms.push(Atom::new(symbol, center));
+                crystal.properties.push_default();
+                selection.atoms = vec![crystal.atoms.len() - 1];
+                info!("Inserted {symbol} interstitial at {center}");
+            }
+            DefectAction::Export => export_text_file(
+                EXPORT_FILE_NAME,
+                &write_xyz(&crystal),
+                "structure",
+                &mut toasts,
+            ),
+        }
+    }
+}
@@ -0,0 +1,68 @@
+// Events for applications embedding the viewer
+// `VizmatPlugin` registers them and sends them as things happen, so an embedding app can react
+// with an ordinary `EventReader` instead of watching the viewer's resources.
+
+use bevy::prelude::*;
+
+use crate::constants::Element;
+use crate::structure::Selection;
+
+/// A structure was opened from files or a URL, or an opened file was reloaded after it
+/// changed on disk.
+#[derive(Event, Clone, Debug)]
+pub struct StructureLoaded {
+    /// File paths or the URL the structure came from.
+    pub source: String,
+    /// Atoms of the frame shown.
+    pub atom_count: usize,
+    /// Frames read; more than one can be stepped through.
+    pub frame_count: usize,
+}
+
+/// An atom was clicked.
+#[derive(Event, Clone, Debug)]
+pub struct AtomPicked {
+    /// Index into `Crystal::atoms`.
+    pub index: usize,
+    pub element: Element,
+    pub position: Vec3,
+    /// Shift was held, adding the atom to the selection or removing it.
+    pub extend: bool,
+}
+
+/// The selected atoms changed, by clicking, a script or a remote request.
+#[derive(Event, Clone, Debug)]
+pub struct SelectionChanged {
+    /// Indices into `Crystal::atoms`, in the order they were picked.
+    pub atoms: Vec<usize>,
+}
+
+/// Another frame of a trajectory is shown.
+#[derive(Event, Clone, Debug)]
+pub struct FrameChanged {
+    /// Position of the frame among the buffered ones, counting from 0.
+    pub position: usize,
+    /// Frames buffered.
+    pub frame_count: usize,
+    /// Frame number given by the file or the sender.
+    pub index: u64,
+    /// MD step number, if the sender reports it.
+    pub step: Option<u64>,
+    /// Simulation time in the sender's units, if reported.
+    pub time: Option<f64>,
+}
+
+// Report the selection whenever its contents change
+pub(crate) fn send_selection_changed(
+    selection: Res<Selection>,
+    mut reported: Local<Vec<usize>>,
+    mut changes: EventWriter<SelectionChanged>,
+) {
+    if !selection.is_changed() || selection.atoms == *reported {
+        return;
+    }
+    reported.clone_from(&selection.atoms);
+    changes.write(SelectionChanged {
+        atoms: selection.atoms.clone(),
+    });
+}
@@ -0,0 +1,143 @@
+// Figures
+// Screenshots taken by scripts and remote requests are rendered by a camera of their own, a copy
+// of the main camera drawing only the structure (`LAYER_CANVAS`) into an offscreen image the size
+// of the window, so buttons, panels and atom labels, which are all UI, never end up in saved
+// figures. The axis gizmo can be drawn into its corner of the image as well, without its UI
+// letters. The image is saved once the copy has rendered a few frames, and the copy removed.
+
+use bevy::asset::RenderAssetUsages;
+use bevy::core_pipeline::dof::DepthOfField;
+use bevy::pbr::DistanceFog;
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages};
+use bevy::render::view::screenshot::{save_to_disk, Screenshot, ScreenshotCaptured};
+use bevy::render::view::NoIndirectDrawing;
+use bevy::window::PrimaryWindow;
+
+use crate::ui::{AxisCamera, MainCamera, LAYER_CANVAS, LAYER_GIZMO};
+
+// Frames rendered by the figure camera before the capture, so that its first frame is not saved
+// half-prepared
+const FIGURE_FRAMES: u32 = 3;
+
+/// Request to save the view of the main camera as an image file, without the UI.
+#[derive(Event, Clone, Debug)]
+pub(crate) struct SaveFigure {
+    pub path: String,
+    /// Whether the axis gizmo is drawn in its corner.
+    pub axes: bool,
+}
+
+/// Camera rendering a figure, with the frames left before it is saved.
+#[derive(Component)]
+pub(crate) struct FigureCamera {
+    path: String,
+    image: Handle<Image>,
+    frames: u32,
+}
+
+// Spawn a copy of the main camera, and of the axis camera if asked for, rendering into a new
+// image for each requested figure
+#[allow(clippy::type_complexity)]
+pub(crate) fn spawn_figure_cameras(
+    mut commands: Commands,
+    mut requests: EventReader<SaveFigure>,
+    mut images: ResMut<Assets<Image>>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    main_camera: Single<
+        (
+            &Camera,
+            &Transform,
+            &Projection,
+            &Msaa,
+            Option<&DistanceFog>,
+            Option<&DepthOfField>,
+        ),
+        With<MainCamera>,
+    >,
+    axis_camera: Single<(&Camera, &Transform), (With<AxisCamera>, Without<MainCamera>)>,
+) {
+    let (camera, transform, projection, msaa, fog, depth_of_field) = *main_camera;
+    let (axis_camera, axis_transform) = *axis_camera;
+    for request in requests.read() {
+        let size = Extent3d {
+            width: window.physical_width().max(1),
+            height: window.physical_height().max(1),
+            depth_or_array_layers: 1,
+        };
+        let mut image = Image::new_fill(
+            size,
+            TextureDimension::D2,
+            &[0, 0, 0, 255],
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        );
+        image.texture_descriptor.usage = TextureUsages::TEXTURE_BINDING
+            | TextureUsages::COPY_SRC
+            | TextureUsages::COPY_DST
+            | TextureUsages::RENDER_ATTACHMENT;
+        let image = images.add(image);
+        let target = RenderTarget::Image(image.clone().into());
+
+        let mut figure = commands.spawn((
+            Camera3d::default(),
+            Camera {
+                target: target.clone(),
+                ..camera.clone()
+            },
+            *transform,
+            projection.clone(),
+            *msaa,
+            NoIndirectDrawing,
+            LAYER_CANVAS,
+            FigureCamera {
+                path: request.path.clone(),
+                image,
+                frames: FIGURE_FRAMES,
+            },
+        ));
+        if let Some(fog) = fog {
+            figure.insert(fog.clone());
+        }
+        if let Some(depth_of_field) = depth_of_field {
+            figure.insert(*depth_of_field);
+        }
+        // the gizmo camera is off when the window is too small for its corner
+        if request.axes && axis_camera.is_active {
+            figure.with_child((
+                Camera3d::default(),
+                Camera {
+                    target,
+                    ..axis_camera.clone()
+                },
+                *axis_transform,
+                LAYER_GIZMO,
+            ));
+        }
+    }
+}
+
+// Save each figure once its camera has rendered, then remove the camera
+pub(crate) fn capture_figures(
+    mut commands: Commands,
+    mut cameras: Query<(Entity, &mut FigureCamera)>,
+) {
+    for (entity, mut figure) in &mut cameras {
+        figure.frames = figure.frames.saturating_sub(1);
+        if figure.frames > 0 {
+            continue;
+        }
+        info!("Saving figure {}", figure.path);
+        commands
+            .spawn(Screenshot::image(figure.image.clone()))
+            .observe(save_to_disk(figure.path.clone()))
+            .observe(
+                move |_: Trigger<ScreenshotCaptured>, mut commands: Commands| {
+                    commands.entity(entity).despawn();
+                },
+            );
+        // captured once, the camera only waits to be removed
+        commands.entity(entity).remove::<FigureCamera>();
+    }
+}
@@ -0,0 +1,127 @@
+// "Open File..." button, for embeddings where dropping files onto the window does not work
+// Natively it shows the system file dialog (rfd), and the picked files are opened like files
+// named on the command line: read in the background, several of them as a trajectory. In the
+// browser it shows the page's file picker instead; paths are not available there, so the files
+// are read through the picker and parsed like structures pushed by the page.
+
+use bevy::prelude::*;
+use bevy::tasks::AsyncComputeTaskPool;
+use crossbeam_channel::{unbounded, Receiver, Sender};
+
+#[cfg(target_arch = "wasm32")]
+use crate::parse::parse_frames;
+use crate::parse::Format;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::watch::OpenFiles;
+#[cfg(target_arch = "wasm32")]
+use crate::web::push;
+
+// Paths of the picked files; empty when the dialog was cancelled
+#[cfg(not(target_arch = "wasm32"))]
+type Picked = Vec<std::path::PathBuf>;
+// Names and contents of the picked files
+#[cfg(target_arch = "wasm32")]
+type Picked = Vec<(String, Vec<u8>)>;
+
+/// Button showing the file dialog.
+#[derive(Component)]
+pub(crate) struct OpenFileButton;
+
+/// Channel the file dialog reports the picked files on, once it is closed.
+#[derive(Resource)]
+pub(crate) struct FileDialog {
+    tx: Sender<Picked>,
+    rx: Receiver<Picked>,
+    /// A dialog is showing; clicks wait until it is closed.
+    open: bool,
+}
+
+impl Default for FileDialog {
+    fn default() -> Self {
+        let (tx, rx) = unbounded();
+        Self {
+            tx,
+            rx,
+            open: false,
+        }
+    }
+}
+
+// Show the file dialog when the button is clicked
+pub(crate) fn open_file_button(
+    buttons: Query<&Interaction, (Changed<Interaction>, With<OpenFileButton>)>,
+    mut dialog: ResMut<FileDialog>,
+) {
+    if buttons.iter().any(|i| *i == Interaction::Pressed) {
+        show_file_dialog(&mut dialog);
+    }
+}
+
+// Show the file dialog, unless one is showing already
+pub(crate) fn show_file_dialog(dialog: &mut FileDialog) {
+    if dialog.open {
+        return;
+    }
+    dialog.open = true;
+    let tx = dialog.tx.clone();
+    let picker = rfd::AsyncFileDialog::new()
+        .set_title("Open structure")
+        .add_filter("Structure files", Format::extensions());
+    AsyncComputeTaskPool::get()
+        .spawn(async move {
+            let files = picker.pick_files().await.unwrap_or_default();
+            #[cfg(not(target_arch = "wasm32"))]
+            let picked = files.iter().map(|file| file.path().to_path_buf()).collect();
+            #[cfg(target_arch = "wasm32")]
+            let mut picked = Vec::new();
+            #[cfg(target_arch = "wasm32")]
+            for file in files {
+                picked.push((file.file_name(), file.read().await));
+            }
+            let _ = tx.send(picked);
+        })
+        .detach();
+}
+
+// Open the files picked in the dialog
+pub(crate) fn receive_picked_files(
+    mut dialog: ResMut<FileDialog>,
+    #[cfg(not(target_arch = "wasm32"))] mut files: EventWriter<OpenFiles>,
+) {
+    let Ok(picked) = dialog.rx.try_recv() else {
+        return;
+    };
+    dialog.open = false;
+    if picked.is_empty() {
+        return;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    files.write(OpenFiles {
+        paths: picked,
+        format: None,
+        frame: 0,
+        list: false,
+    });
+
+    // the files are concatenated into one trajectory, like several files opened natively
+    #[cfg(target_arch = "wasm32")]
+    {
+        let names = picked
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut frames = Vec::new();
+        for (name, contents) in &picked {
+            match parse_frames(name, &String::from_utf8_lossy(contents), None) {
+                Ok(parsed) => frames.extend(parsed),
+                Err(e) => {
+                    error!("{e:#}");
+                    return;
+                }
+            }
+        }
+        push(names, frames);
+    }
+}
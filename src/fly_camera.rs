@@ -0,0 +1,140 @@
+// First-person fly camera
+// The orbit rig suits looking at a structure from outside; walking through the channels of a
+// zeolite or the pores of a framework needs a camera that moves itself. In fly mode the mouse
+// is captured and turns the view, W/A/S/D move, Q/E go down and up, Shift speeds up and the
+// wheel changes the speed. Leaving the mode (the same key, or Escape) hands the view back to
+// the orbit rig, which then orbits the point ahead of the camera.
+
+use bevy::input::mouse::{MouseMotion, MouseWheel};
+use bevy::prelude::*;
+use bevy::window::CursorGrabMode;
+
+use crate::bindings::{KeyAction, KeyBindings};
+use crate::ui::{CameraRig, MainCamera, MouseSensitivity};
+use crate::widgets::FocusedField;
+
+/// Speed in Å/s when entering fly mode for the first time.
+const DEFAULT_SPEED: f32 = 5.0;
+const MIN_SPEED: f32 = 0.1;
+const MAX_SPEED: f32 = 500.0;
+/// Speed factor while Shift is held.
+const BOOST: f32 = 4.0;
+/// Looking straight up or down would flip the view.
+const MAX_PITCH: f32 = 1.54;
+
+/// Whether the camera flies instead of orbiting, and how fast.
+#[derive(Resource)]
+pub(crate) struct FlyCamera {
+    pub active: bool,
+    pub speed: f32,
+}
+
+impl Default for FlyCamera {
+    fn default() -> Self {
+        Self {
+            active: false,
+            speed: DEFAULT_SPEED,
+        }
+    }
+}
+
+/// Run condition for the orbit controls, which rest while flying.
+pub(crate) fn not_flying(fly: Res<FlyCamera>) -> bool {
+    !fly.active
+}
+
+// Switch fly mode with its key; Escape leaves it too
+pub(crate) fn fly_mode_hotkey(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    focused: Res<FocusedField>,
+    mut fly: ResMut<FlyCamera>,
+) {
+    if focused.0.is_some() {
+        return;
+    }
+    if bindings.just_pressed(KeyAction::FlyMode, &keys) {
+        fly.active = !fly.active;
+    } else if fly.active && keys.just_pressed(KeyCode::Escape) {
+        fly.active = false;
+    }
+}
+
+// Capture the mouse when entering fly mode, and give it back, with the view handed to the orbit
+// rig, when leaving
+pub(crate) fn apply_fly_mode(
+    fly: Res<FlyCamera>,
+    mut windows: Query<&mut Window>,
+    camera: Single<&Transform, With<MainCamera>>,
+    mut rig: ResMut<CameraRig>,
+) {
+    if !fly.is_changed() || fly.is_added() {
+        return;
+    }
+    for mut window in &mut windows {
+        let options = &mut window.cursor_options;
+        if fly.active {
+            options.grab_mode = CursorGrabMode::Locked;
+            options.visible = false;
+        } else {
+            options.grab_mode = CursorGrabMode::None;
+            options.visible = true;
+        }
+    }
+    if fly.active {
+        info!("Fly mode on");
+    } else {
+        rig.orbit_ahead(&camera);
+        info!("Fly mode off");
+    }
+}
+
+// Turn the view with the mouse and move with the keys while flying
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn fly_camera(
+    mut fly: ResMut<FlyCamera>,
+    keys: Res<ButtonInput<KeyCode>>,
+    focused: Res<FocusedField>,
+    mut motion: EventReader<MouseMotion>,
+    mut wheel: EventReader<MouseWheel>,
+    sensitivity: Res<MouseSensitivity>,
+    time: Res<Time>,
+    mut camera: Single<&mut Transform, With<MainCamera>>,
+) {
+    let turn: Vec2 = motion.read().map(|event| event.delta).sum();
+    let scroll: f32 = wheel.read().map(|event| event.y).sum();
+    if !fly.active {
+        return;
+    }
+
+    if scroll != 0.0 {
+        fly.speed = (fly.speed * 1.2_f32.powf(scroll)).clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    if turn != Vec2::ZERO {
+        let (yaw, pitch, _) = camera.rotation.to_euler(EulerRot::YXZ);
+        let sensitivity = 0.002 * sensitivity.0;
+        let yaw = yaw - turn.x * sensitivity;
+        let pitch = (pitch - turn.y * sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        camera.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0);
+    }
+
+    if focused.0.is_some() {
+        return;
+    }
+    let axis = |negative: KeyCode, positive: KeyCode| {
+        keys.pressed(positive) as i32 as f32 - keys.pressed(negative) as i32 as f32
+    };
+    let step = camera.forward() * axis(KeyCode::KeyS, KeyCode::KeyW)
+        + camera.right() * axis(KeyCode::KeyA, KeyCode::KeyD)
+        + Vec3::Y * axis(KeyCode::KeyQ, KeyCode::KeyE);
+    if step == Vec3::ZERO {
+        return;
+    }
+    let boost = if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        BOOST
+    } else {
+        1.0
+    };
+    camera.translation += step.normalize() * fly.speed * boost * time.delta_secs();
+}
@@ -0,0 +1,214 @@
+// GROMACS reader
+// .gro files: a title line, the atom count, one fixed-column line per atom (residue number and
+// name, atom name and number, then the position and optionally the velocity) and the box
+// vectors; trajectories repeat these blocks. Lengths are in nm and are converted to Å on
+// import; velocities, in nm/ps, become Å/ps. Atom names become the site labels, and their
+// element is guessed from the name as GROMACS topologies write it.
+
+use anyhow::{ensure, Context, Result};
+use bevy::math::{Mat3, Vec3};
+
+use crate::constants::Element;
+use crate::structure::{Atom, AtomProperties, Crystal};
+use crate::units::LengthUnit;
+
+/// Column where the coordinates of an atom line start.
+const COORDINATES: usize = 20;
+
+// Width of the coordinate fields of an atom line, the distance between their decimal points
+fn field_width(line: &str) -> Option<usize> {
+    let fields = line.get(COORDINATES..)?;
+    let first = fields.find('.')?;
+    let second = fields[first + 1..].find('.')?;
+    Some(second + 1)
+}
+
+// Element of an atom name: ions are named like their residue ("NA" in residue "NA"), a few
+// names spell a two-letter symbol ("Cl", "Zn"), and others start with the element's letter, as
+// "CA" does for an alpha carbon
+fn element(name: &str, residue: &str) -> Element {
+    let name = name.trim_start_matches(|c: char| c.is_ascii_digit());
+    let letters = name
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .map_or(name, |end| &name[..end]);
+    if letters.eq_ignore_ascii_case(residue) {
+        if let Some(element) = Element::from_symbol(letters) {
+            return element;
+        }
+    }
+    let mut chars = letters.chars();
+    if chars.nth(1).is_some_and(|c| c.is_ascii_lowercase()) {
+        if let Some(element) = Element::from_symbol(&letters[..2]) {
+            return element;
+        }
+    }
+    Element::parse(letters.get(..1).unwrap_or(""))
+}
+
+// Cell of a box line: the diagonal v1(x) v2(y) v3(z), then for triclinic boxes v1(y) v1(z)
+// v2(x) v2(z) v3(x) v3(y); None for a box of zero size
+fn cell(line: &str) -> Result<Option<Mat3>> {
+    let values = line
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<f32>, _>>()
+        .with_context(|| format!("Failed to parse the box: {line}"))?;
+    ensure!(
+        values.len() == 3 || values.len() == 9,
+        "Expected 3 or 9 box values: {line}"
+    );
+    let off = |index: usize| values.get(index).copied().unwrap_or(0.0);
+    let lattice = Mat3::from_cols(
+        Vec3::new(values[0], off(3), off(4)),
+        Vec3::new(off(5), values[1], off(6)),
+        Vec3::new(off(7), off(8), values[2]),
+    );
+    Ok((lattice.determinant().abs() > f32::EPSILON).then_some(lattice))
+}
+
+// One frame from `lines`, which it advances past the box line
+fn parse_frame<'a>(lines: &mut impl Iterator<Item = &'a str>) -> Result<Crystal> {
+    let mut next = |what: &str| {
+        lines
+            .next()
+            .with_context(|| format!("GRO file ends before the {what}"))
+    };
+    let title = next("title")?.trim();
+    let count: usize = next("atom count")?
+        .trim()
+        .parse()
+        .context("Failed to parse the atom count")?;
+
+    let mut atoms = Vec::with_capacity(count);
+    let mut labels = Vec::with_capacity(count);
+    let mut residues = Vec::with_capacity(count);
+    let mut velocities = Vec::with_capacity(count);
+    for _ in 0..count {
+        let line = next("atoms")?;
+        let width = field_width(line).with_context(|| format!("Expected an atom line: {line}"))?;
+        let field = |index: usize| {
+            let start = COORDINATES + index * width;
+            line.get(start..start + width)
+                .unwrap_or("")
+                .trim()
+                .parse::<f32>()
+                .with_context(|| format!("Failed to parse the position: {line}"))
+        };
+        let position = Vec3::new(field(0)?, field(1)?, field(2)?);
+        let residue = line.get(5..10).unwrap_or("").trim();
+        let name = line.get(10..15).unwrap_or("").trim();
+        atoms.push(Atom::new(element(name, residue), position));
+        labels.push(name.to_string());
+        residues.push(residue.to_string());
+        let velocity = line
+            .get(COORDINATES + 3 * width..)
+            .unwrap_or("")
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<f32>, _>>();
+        if let Ok([vx, vy, vz]) = velocity.as_deref() {
+            velocities.push(Vec3::new(*vx, *vy, *vz) * LengthUnit::Nanometer.in_angstrom());
+        }
+    }
+
+    let mut crystal = match cell(next("box")?)? {
+        Some(lattice) => Crystal::periodic(atoms, lattice),
+        None => Crystal::molecule(atoms),
+    };
+    crystal.convert_lengths(LengthUnit::Nanometer);
+    if !title.is_empty() {
+        crystal.metadata.title = Some(title.to_string());
+    }
+    let properties = &mut crystal.properties;
+    properties
+        .strings
+        .insert(AtomProperties::LABELS.to_string(), labels);
+    properties.strings.insert("residues".to_string(), residues);
+    if velocities.len() == count {
+        properties
+            .vectors
+            .insert(AtomProperties::VELOCITIES.to_string(), velocities);
+    }
+    Ok(crystal)
+}
+
+// First frame of a GROMACS .gro file
+pub(crate) fn parse_gro(contents: &str) -> Result<Crystal> {
+    parse_frame(&mut contents.lines())
+}
+
+// Every frame of a GROMACS .gro file
+pub(crate) fn parse_gro_frames(contents: &str) -> Result<Vec<Crystal>> {
+    let mut lines = contents.lines().peekable();
+    let mut frames = Vec::new();
+    while lines.peek().is_some_and(|line| !line.trim().is_empty()) {
+        let frame = parse_frame(&mut lines)
+            .with_context(|| format!("Failed to parse frame {}", frames.len() + 1))?;
+        frames.push(frame);
+    }
+    ensure!(!frames.is_empty(), "GRO file has no frames");
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRO: &str = "Water and ion
+    2
+    1SOL     OW    1   0.126   1.624   1.679  0.1000 -0.0500  0.0000
+    2NA      NA    2   1.000   1.000   1.000  0.0000  0.0000  0.0000
+   2.00000   2.00000   2.00000
+Alpha carbon
+    1
+    1LYS     CA    1   0.100   0.200   0.300
+   0.00000   0.00000   0.00000
+";
+
+    #[test]
+    fn converts_nm_to_angstrom() {
+        let crystal = parse_gro(GRO).unwrap();
+        assert_eq!(crystal.metadata.title.as_deref(), Some("Water and ion"));
+        assert_eq!(crystal.metadata.length_unit, Some(LengthUnit::Nanometer));
+        assert_eq!(
+            crystal
+                .atoms
+                .iter()
+                .map(|atom| atom.element)
+                .collect::<Vec<_>>(),
+            [Element::O, Element::Na]
+        );
+        assert!((crystal.atoms[1].position() - Vec3::splat(10.0)).length() < 1e-4);
+        assert_eq!(
+            crystal.lattice,
+            Some(Mat3::from_diagonal(Vec3::splat(20.0)))
+        );
+        assert_eq!(
+            crystal.properties.vectors[AtomProperties::VELOCITIES][0],
+            Vec3::new(1.0, -0.5, 0.0)
+        );
+        assert_eq!(crystal.properties.strings["residues"], ["SOL", "NA"]);
+    }
+
+    #[test]
+    fn reads_every_frame() {
+        let frames = parse_gro_frames(GRO).unwrap();
+        assert_eq!(frames.len(), 2);
+        let carbon = &frames[1];
+        assert_eq!(carbon.atoms[0].element, Element::C);
+        assert_eq!(carbon.lattice, None);
+        assert!(!carbon
+            .properties
+            .vectors
+            .contains_key(AtomProperties::VELOCITIES));
+    }
+
+    #[test]
+    fn names_resolve_like_gromacs_topologies() {
+        assert_eq!(element("CA", "LYS"), Element::C);
+        assert_eq!(element("CA", "CA"), Element::Ca);
+        assert_eq!(element("Cl", "CL"), Element::Cl);
+        assert_eq!(element("1HB", "ALA"), Element::H);
+        assert_eq!(element("SG", "CYS"), Element::S);
+    }
+}
@@ -0,0 +1,218 @@
+// Headless rendering
+// `vizmat --headless render water.xyz -o water.png` draws the structure into an offscreen image
+// instead of a window, saves it and exits, for batch figures and CI of simulation pipelines.
+// Colors, quality, lighting, bonds and background come from the configuration files, as in the
+// viewer.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::asset::RenderAssetUsages;
+use bevy::log::{Level, LogPlugin};
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages};
+use bevy::render::view::screenshot::{Screenshot, ScreenshotCaptured};
+use bevy::render::view::NoIndirectDrawing;
+use bevy::window::ExitCondition;
+use bevy::winit::WinitPlugin;
+
+use crate::analysis::{BondTolerance, Coordination};
+use crate::atom_labels::LabelSettings;
+use crate::bindings::KeyBindings;
+use crate::bonds::{update_bonds, BondStyle, ContactCutoff};
+use crate::cli::{Cli, RenderArgs};
+use crate::color::{ColorBy, ColorScheme, ElementOverrides};
+use crate::config::{load_config, Config};
+use crate::instancing::AtomInstancingPlugin;
+use crate::lighting::{apply_lighting, LightingRig};
+use crate::structure::Crystal;
+use crate::theme::UiTheme;
+use crate::ui::{
+    draw_unit_cell, fit_distance, refresh_atoms_system, setup_scene, AtomChunkQueue, AtomScale,
+    MouseSensitivity, RenderQuality, SphereDetail, SphereMeshes, ToggleStates,
+};
+use crate::watch::read_frames;
+
+// Frames rendered before the capture, so that shaders have finished compiling
+const PRE_ROLL_FRAMES: u32 = 30;
+
+/// Image being rendered and where it goes.
+#[derive(Resource)]
+struct RenderJob {
+    output: PathBuf,
+    width: u32,
+    height: u32,
+    target: Handle<Image>,
+    frames: u32,
+}
+
+// Run `vizmat render` without a window; the exit code reports whether the image was written
+pub(crate) fn render(cli: &Cli, args: &RenderArgs) -> AppExit {
+    let crystal = match read_frames(std::slice::from_ref(&args.file), cli.format) {
+        Ok(mut frames) => {
+            let frame = cli.frame.unwrap_or(0).min(frames.len() - 1);
+            frames.swap_remove(frame)
+        }
+        Err(e) => {
+            eprintln!("{e:#}");
+            return AppExit::error();
+        }
+    };
+
+    App::new()
+        .add_plugins(
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    ..default()
+                })
+                .set(LogPlugin {
+                    level: Level::INFO,
+                    filter: "wgpu=error,naga=warn".to_string(),
+                    custom_layer: |_| None,
+                })
+                .disable::<WinitPlugin>(),
+        )
+        .add_plugins(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+            1.0 / 60.0,
+        )))
+        .add_plugins(AtomInstancingPlugin)
+        .insert_resource(crystal)
+        .insert_resource(RenderJob {
+            output: args.output.clone(),
+            width: args.width.max(1),
+            height: args.height.max(1),
+            target: Handle::default(),
+            frames: 0,
+        })
+        .init_resource::<Config>()
+        .init_resource::<MouseSensitivity>()
+        .init_resource::<RenderQuality>()
+        .init_resource::<AtomScale>()
+        .init_resource::<BondTolerance>()
+        .init_resource::<BondStyle>()
+        .init_resource::<ContactCutoff>()
+        .init_resource::<LabelSettings>()
+        .init_resource::<SphereDetail>()
+        .init_resource::<SphereMeshes>()
+        .init_resource::<AtomChunkQueue>()
+        .init_resource::<ColorScheme>()
+        .init_resource::<ColorBy>()
+        .init_resource::<Coordination>()
+        .init_resource::<ElementOverrides>()
+        .init_resource::<UiTheme>()
+        .init_resource::<KeyBindings>()
+        .init_resource::<LightingRig>()
+        .init_resource::<ToggleStates>()
+        .add_systems(
+            Startup,
+            (load_config, setup_scene, setup_render_camera).chain(),
+        )
+        .add_systems(
+            Update,
+            (
+                refresh_atoms_system,
+                draw_unit_cell,
+                update_bonds,
+                apply_lighting,
+                capture_image,
+            ),
+        )
+        .run()
+}
+
+// Camera rendering into an offscreen image, framing the whole structure from the viewer's
+// initial direction
+fn setup_render_camera(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut job: ResMut<RenderJob>,
+    mut lighting: ResMut<LightingRig>,
+    crystal: Res<Crystal>,
+    quality: Res<RenderQuality>,
+) {
+    let size = Extent3d {
+        width: job.width,
+        height: job.height,
+        depth_or_array_layers: 1,
+    };
+    let mut image = Image::new_fill(
+        size,
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.texture_descriptor.usage = TextureUsages::TEXTURE_BINDING
+        | TextureUsages::COPY_SRC
+        | TextureUsages::COPY_DST
+        | TextureUsages::RENDER_ATTACHMENT;
+    job.target = images.add(image);
+
+    let projection = Projection::Perspective(PerspectiveProjection {
+        aspect_ratio: job.width as f32 / job.height as f32,
+        ..default()
+    });
+    let (center, radius) = crystal.bounding_sphere(&[]).unwrap_or((Vec3::ZERO, 1.0));
+    let distance = fit_distance(radius, &projection);
+    let position = center + Vec3::ONE.normalize() * distance;
+
+    commands
+        .spawn((
+            Camera3d::default(),
+            Camera {
+                target: RenderTarget::Image(job.target.clone().into()),
+                ..default()
+            },
+            projection,
+            quality.msaa(),
+            NoIndirectDrawing,
+            Transform::from_translation(position).looking_at(center, Vec3::Y),
+        ))
+        .with_children(|parent| {
+            // the lights of the configured preset hang from here, as from the viewer's camera
+            lighting.pivot = Some(parent.spawn(Transform::default()).id());
+        });
+}
+
+// Capture the image once the atoms are built and the scene has settled, save it and exit
+fn capture_image(
+    mut commands: Commands,
+    mut job: ResMut<RenderJob>,
+    atom_chunks: Res<AtomChunkQueue>,
+) {
+    if atom_chunks.progress().is_some() {
+        return;
+    }
+    job.frames += 1;
+    if job.frames != PRE_ROLL_FRAMES {
+        return;
+    }
+    let output = job.output.clone();
+    commands
+        .spawn(Screenshot::image(job.target.clone()))
+        .observe(
+            move |captured: Trigger<ScreenshotCaptured>, mut exit: EventWriter<AppExit>| {
+                let saved = captured
+                    .event()
+                    .0
+                    .clone()
+                    .try_into_dynamic()
+                    .map_err(|e| e.to_string())
+                    .and_then(|image| image.to_rgb8().save(&output).map_err(|e| e.to_string()));
+                match saved {
+                    Ok(()) => {
+                        info!("Rendered {}", output.display());
+                        exit.write(AppExit::Success);
+                    }
+                    Err(e) => {
+                        error!("Cannot write {}: {e}", output.display());
+                        exit.write(AppExit::error());
+                    }
+                }
+            },
+        );
+}
@@ -0,0 +1,676 @@
+// Instanced atom rendering
+// Atoms are drawn as instances of a single unit sphere, each moved, scaled, colored and given
+// its element's material by per-instance vertex attributes, instead of one entity and material
+// per atom, so that MD frames with a million atoms stay interactive. The instance buffer is
+// only uploaded again when the atoms change, and rewritten in place while their number stays
+// the same. `atom_picking` draws the same instances into an ID buffer.
+//
+// The custom pipeline bypasses Bevy's materials, and with them its shadow pass, so the atoms
+// are also queued into the shadow maps of the directional lights with a depth-only pipeline of
+// their own, and the atom shader samples those maps: atoms cast shadows and receive them, from
+// other atoms and from bonds alike.
+//
+// Large structures are split by a spatial grid into chunks of a few thousand atoms, one entity
+// and draw call each, whose bounding boxes let Bevy's frustum culling skip the parts of a slab
+// or grain that are off screen.
+//
+// With `atom_rendering = "impostors"` in the configuration, the sphere mesh is replaced by a
+// camera-facing quad per atom on which the fragment shader ray-traces the sphere and writes its
+// depth: four vertices per atom instead of hundreds, and round at any zoom.
+
+use bevy::asset::{load_internal_asset, weak_handle};
+use bevy::core_pipeline::core_3d::Transparent3d;
+use bevy::core_pipeline::tonemapping::{DebandDither, Tonemapping};
+use bevy::ecs::system::lifetimeless::{Read, SRes};
+use bevy::ecs::system::SystemChangeTick;
+use bevy::ecs::system::SystemParamItem;
+use bevy::pbr::{
+    tonemapping_pipeline_key, ExtractedDirectionalLight, LightEntity, MeshPipeline,
+    MeshPipelineKey, PrepassPipeline, RenderCascadesVisibleEntities, RenderMeshInstances,
+    SetMeshBindGroup, SetMeshViewBindGroup, SetPrepassViewBindGroup, Shadow, ShadowBatchSetKey,
+    ShadowBinKey, ShadowFilteringMethod, ViewLightEntities,
+};
+use bevy::prelude::*;
+use bevy::render::extract_resource::{ExtractResource, ExtractResourcePlugin};
+use bevy::render::mesh::allocator::MeshAllocator;
+use bevy::render::mesh::{MeshVertexBufferLayoutRef, RenderMesh, RenderMeshBufferInfo};
+use bevy::render::primitives::Aabb;
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_phase::{
+    AddRenderCommand, BinnedRenderPhaseType, DrawFunctions, InputUniformIndex, PhaseItem,
+    PhaseItemExtraIndex, RenderCommand, RenderCommandResult, SetItemPipeline, TrackedRenderPass,
+    ViewBinnedRenderPhases, ViewSortedRenderPhases,
+};
+use bevy::render::render_resource::{
+    BindGroupLayout, Buffer, BufferInitDescriptor, BufferUsages, CompareFunction,
+    DepthStencilState, Face, FragmentState, MultisampleState, PipelineCache, PrimitiveState,
+    RenderPipelineDescriptor, ShaderDefVal, SpecializedMeshPipeline, SpecializedMeshPipelineError,
+    SpecializedMeshPipelines, TextureFormat, VertexAttribute, VertexBufferLayout, VertexFormat,
+    VertexState, VertexStepMode,
+};
+use bevy::render::renderer::{RenderDevice, RenderQueue};
+use bevy::render::sync_component::SyncComponentPlugin;
+use bevy::render::sync_world::RenderEntity;
+use bevy::render::view::{ExtractedView, RenderVisibleEntities};
+use bevy::render::{Extract, ExtractSchedule, Render, RenderApp, RenderSet};
+use bytemuck::{Pod, Zeroable};
+use serde::Deserialize;
+
+const ATOM_SHADER_HANDLE: Handle<Shader> = weak_handle!("3f0b6a52-8d1e-4c7a-9b25-6e4d0c8a71f3");
+const IMPOSTOR_SHADER_HANDLE: Handle<Shader> = weak_handle!("c81e4f27-5a3d-4b96-8e0c-1f7d2a6b9e45");
+const ATOM_SHADOW_SHADER_HANDLE: Handle<Shader> =
+    weak_handle!("7d4e2a91-3c6b-4f08-a5e7-92b1d0c4f36a");
+
+/// How the atom spheres are drawn.
+#[derive(
+    Resource, ExtractResource, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum AtomRendering {
+    /// Tessellated spheres, see `SphereDetail`.
+    #[default]
+    Meshes,
+    /// Ray-traced spheres on camera-facing quads.
+    Impostors,
+}
+
+impl AtomRendering {
+    // Shader define selecting the impostor code paths
+    pub(crate) fn shader_defs(self) -> Vec<ShaderDefVal> {
+        match self {
+            AtomRendering::Meshes => Vec::new(),
+            AtomRendering::Impostors => vec!["SPHERE_IMPOSTOR".into()],
+        }
+    }
+}
+
+// Quad every impostor is drawn on, spanning -1 to 1; the shader turns it towards the camera
+pub(crate) fn impostor_quad() -> Mesh {
+    Rectangle::new(2.0, 2.0).mesh().build()
+}
+
+/// One atom as the shader sees it.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub(crate) struct AtomInstance {
+    pub position: Vec3,
+    pub radius: f32,
+    /// Linear RGBA.
+    pub color: [f32; 4],
+    /// Index of the atom in the `Crystal`.
+    pub index: u32,
+    /// Metallic, roughness and emissive of the atom's element.
+    pub material: [f32; 3],
+}
+
+/// Chunk of atoms drawn as instances of the entity's sphere mesh.
+#[derive(Component, Clone, Default, Deref, DerefMut)]
+pub(crate) struct AtomInstances(pub Vec<AtomInstance>);
+
+/// Atoms per chunk the spatial grid aims for.
+const CHUNK_ATOMS: usize = 4096;
+
+// Split atoms into chunks of nearby atoms, by a grid sized for about `CHUNK_ATOMS` atoms per
+// cell; small structures stay in one chunk
+pub(crate) fn partition_atoms(positions: &[Vec3]) -> Vec<Vec<usize>> {
+    if positions.is_empty() {
+        return Vec::new();
+    }
+    if positions.len() <= CHUNK_ATOMS {
+        return vec![(0..positions.len()).collect()];
+    }
+    let min = positions
+        .iter()
+        .copied()
+        .reduce(Vec3::min)
+        .unwrap_or_default();
+    let max = positions
+        .iter()
+        .copied()
+        .reduce(Vec3::max)
+        .unwrap_or_default();
+    // flat slabs and wires have no thickness to fill a cube-shaped cell
+    let extent = (max - min).max(Vec3::ONE);
+    let target = positions.len().div_ceil(CHUNK_ATOMS);
+    let cells = |edge: f32| (extent / edge).ceil().as_uvec3().max(UVec3::ONE);
+    let mut edge = (extent.element_product() / target as f32).cbrt();
+    while cells(edge).element_product() as usize > 2 * target {
+        edge *= 1.25;
+    }
+    let cells = cells(edge);
+
+    let mut chunks = vec![Vec::new(); cells.element_product() as usize];
+    for (index, position) in positions.iter().enumerate() {
+        let cell = ((*position - min) / edge)
+            .as_uvec3()
+            .min(cells - UVec3::ONE);
+        chunks[((cell.z * cells.y + cell.y) * cells.x + cell.x) as usize].push(index);
+    }
+    chunks.retain(|chunk| !chunk.is_empty());
+    chunks
+}
+
+// Box around the spheres of a chunk, for frustum culling
+pub(crate) fn chunk_bounds(instances: &[AtomInstance]) -> Aabb {
+    let (min, max) = instances.iter().fold(
+        (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+        |(min, max), atom| {
+            (
+                min.min(atom.position - atom.radius),
+                max.max(atom.position + atom.radius),
+            )
+        },
+    );
+    Aabb::from_min_max(min, max)
+}
+
+/// Draws `AtomInstances` in the main 3D pass and the shadow maps.
+pub(crate) struct AtomInstancingPlugin;
+
+impl Plugin for AtomInstancingPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            ATOM_SHADER_HANDLE,
+            "shaders/atoms.wgsl",
+            Shader::from_wgsl
+        );
+        load_internal_asset!(
+            app,
+            IMPOSTOR_SHADER_HANDLE,
+            "shaders/impostor.wgsl",
+            Shader::from_wgsl
+        );
+        load_internal_asset!(
+            app,
+            ATOM_SHADOW_SHADER_HANDLE,
+            "shaders/atom_shadows.wgsl",
+            Shader::from_wgsl
+        );
+        // meshes are not mirrored in the render world, the instances need an entity there
+        app.add_plugins((
+            SyncComponentPlugin::<AtomInstances>::default(),
+            ExtractResourcePlugin::<AtomRendering>::default(),
+        ))
+        .init_resource::<AtomRendering>();
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .add_render_command::<Transparent3d, DrawAtoms>()
+            .add_render_command::<Shadow, DrawAtomShadows>()
+            .init_resource::<SpecializedMeshPipelines<AtomPipeline>>()
+            .init_resource::<SpecializedMeshPipelines<AtomShadowPipeline>>()
+            .add_systems(ExtractSchedule, extract_atom_instances)
+            .add_systems(
+                Render,
+                (
+                    queue_atoms.in_set(RenderSet::QueueMeshes),
+                    queue_atom_shadows.in_set(RenderSet::QueueMeshes),
+                    prepare_instance_buffers.in_set(RenderSet::PrepareResources),
+                ),
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        if let Some(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .init_resource::<AtomPipeline>()
+                .init_resource::<AtomShadowPipeline>();
+        }
+    }
+}
+
+// Copy the instances to the render world when they change
+fn extract_atom_instances(
+    mut commands: Commands,
+    atoms: Extract<Query<(RenderEntity, &AtomInstances), Changed<AtomInstances>>>,
+) {
+    for (entity, instances) in &atoms {
+        commands.entity(entity).insert(instances.clone());
+    }
+}
+
+/// Instances uploaded to the GPU.
+#[derive(Component)]
+pub(crate) struct InstanceBuffer {
+    pub buffer: Buffer,
+    pub length: usize,
+}
+
+// Layout of the instance buffer: center and radius at location 3, color at location 4, the atom
+// index at location 5 and the material at location 6
+pub(crate) fn instance_buffer_layout() -> VertexBufferLayout {
+    VertexBufferLayout {
+        array_stride: std::mem::size_of::<AtomInstance>() as u64,
+        step_mode: VertexStepMode::Instance,
+        attributes: vec![
+            // locations 0-2 are the position, normal and UV of the sphere
+            VertexAttribute {
+                format: VertexFormat::Float32x4,
+                offset: 0,
+                shader_location: 3,
+            },
+            VertexAttribute {
+                format: VertexFormat::Float32x4,
+                offset: VertexFormat::Float32x4.size(),
+                shader_location: 4,
+            },
+            // atom index
+            VertexAttribute {
+                format: VertexFormat::Uint32,
+                offset: 2 * VertexFormat::Float32x4.size(),
+                shader_location: 5,
+            },
+            VertexAttribute {
+                format: VertexFormat::Float32x3,
+                offset: 2 * VertexFormat::Float32x4.size() + VertexFormat::Uint32.size(),
+                shader_location: 6,
+            },
+        ],
+    }
+}
+
+// Draw `count` instances of the sphere mesh; false when the mesh is not uploaded yet
+pub(crate) fn draw_sphere_instances<'w>(
+    pass: &mut TrackedRenderPass<'w>,
+    mesh_allocator: &'w MeshAllocator,
+    mesh: &RenderMesh,
+    mesh_id: &AssetId<Mesh>,
+    instances: &'w Buffer,
+    count: u32,
+) -> bool {
+    let Some(vertex_slice) = mesh_allocator.mesh_vertex_slice(mesh_id) else {
+        return false;
+    };
+    pass.set_vertex_buffer(0, vertex_slice.buffer.slice(..));
+    pass.set_vertex_buffer(1, instances.slice(..));
+
+    match &mesh.buffer_info {
+        RenderMeshBufferInfo::Indexed {
+            index_format,
+            count: index_count,
+        } => {
+            let Some(index_slice) = mesh_allocator.mesh_index_slice(mesh_id) else {
+                return false;
+            };
+            pass.set_index_buffer(index_slice.buffer.slice(..), 0, *index_format);
+            pass.draw_indexed(
+                index_slice.range.start..(index_slice.range.start + index_count),
+                vertex_slice.range.start as i32,
+                0..count,
+            );
+        }
+        RenderMeshBufferInfo::NonIndexed => {
+            pass.draw(vertex_slice.range, 0..count);
+        }
+    }
+    true
+}
+
+// Upload newly extracted instances, into the existing buffer when the atom count is unchanged;
+// the copy in the render world is dropped afterwards
+fn prepare_instance_buffers(
+    mut commands: Commands,
+    atoms: Query<(Entity, &AtomInstances, Option<&InstanceBuffer>)>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    for (entity, instances, current) in &atoms {
+        let mut entity = commands.entity(entity);
+        entity.remove::<AtomInstances>();
+        if instances.is_empty() {
+            entity.remove::<InstanceBuffer>();
+            continue;
+        }
+        let contents = bytemuck::cast_slice(instances.as_slice());
+        if let Some(current) = current.filter(|current| current.length == instances.len()) {
+            render_queue.write_buffer(&current.buffer, 0, contents);
+            continue;
+        }
+        let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("atom instance buffer"),
+            contents,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+        });
+        entity.insert(InstanceBuffer {
+            buffer,
+            length: instances.len(),
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn queue_atoms(
+    draw_functions: Res<DrawFunctions<Transparent3d>>,
+    atom_pipeline: Res<AtomPipeline>,
+    mut pipelines: ResMut<SpecializedMeshPipelines<AtomPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    meshes: Res<RenderAssets<RenderMesh>>,
+    render_mesh_instances: Res<RenderMeshInstances>,
+    rendering: Res<AtomRendering>,
+    atoms: Query<(), With<InstanceBuffer>>,
+    mut phases: ResMut<ViewSortedRenderPhases<Transparent3d>>,
+    views: Query<(
+        &ExtractedView,
+        &RenderVisibleEntities,
+        &Msaa,
+        Option<&Tonemapping>,
+        Option<&DebandDither>,
+        Option<&ShadowFilteringMethod>,
+    )>,
+) {
+    let draw_atoms = draw_functions.read().id::<DrawAtoms>();
+
+    for (view, visible, msaa, tonemapping, dither, shadow_filter) in &views {
+        let Some(phase) = phases.get_mut(&view.retained_view_entity) else {
+            continue;
+        };
+
+        let mut view_key = MeshPipelineKey::from_msaa_samples(msaa.samples())
+            | MeshPipelineKey::from_hdr(view.hdr);
+        // shadow maps are filtered like the standard materials filter them
+        view_key |= match shadow_filter.copied().unwrap_or_default() {
+            ShadowFilteringMethod::Hardware2x2 => {
+                MeshPipelineKey::SHADOW_FILTER_METHOD_HARDWARE_2X2
+            }
+            ShadowFilteringMethod::Gaussian => MeshPipelineKey::SHADOW_FILTER_METHOD_GAUSSIAN,
+            ShadowFilteringMethod::Temporal => MeshPipelineKey::SHADOW_FILTER_METHOD_TEMPORAL,
+        };
+        // tonemap like the standard materials do on LDR targets
+        if !view.hdr {
+            if let Some(tonemapping) = tonemapping {
+                view_key |= MeshPipelineKey::TONEMAP_IN_SHADER;
+                view_key |= tonemapping_pipeline_key(*tonemapping);
+            }
+            if let Some(DebandDither::Enabled) = dither {
+                view_key |= MeshPipelineKey::DEBAND_DITHER;
+            }
+        }
+        let rangefinder = view.rangefinder3d();
+
+        for &(entity, main_entity) in visible.iter::<Mesh3d>() {
+            if !atoms.contains(entity) {
+                continue;
+            }
+            let Some(mesh_instance) = render_mesh_instances.render_mesh_queue_data(main_entity)
+            else {
+                continue;
+            };
+            let Some(mesh) = meshes.get(mesh_instance.mesh_asset_id) else {
+                continue;
+            };
+            let key = (
+                view_key | MeshPipelineKey::from_primitive_topology(mesh.primitive_topology()),
+                *rendering,
+            );
+            let pipeline =
+                match pipelines.specialize(&pipeline_cache, &atom_pipeline, key, &mesh.layout) {
+                    Ok(pipeline) => pipeline,
+                    Err(e) => {
+                        error!("Cannot build the atom pipeline: {e}");
+                        continue;
+                    }
+                };
+            phase.add(Transparent3d {
+                entity: (entity, main_entity),
+                pipeline,
+                draw_function: draw_atoms,
+                distance: rangefinder.distance_translation(&mesh_instance.translation),
+                batch_range: 0..1,
+                extra_index: PhaseItemExtraIndex::None,
+                indexed: true,
+            });
+        }
+    }
+}
+
+// Add the atom chunks every cascade of a directional light sees to its shadow map; the lighting
+// rig has no other lights
+#[allow(clippy::too_many_arguments)]
+fn queue_atom_shadows(
+    draw_functions: Res<DrawFunctions<Shadow>>,
+    shadow_pipeline: Res<AtomShadowPipeline>,
+    mut pipelines: ResMut<SpecializedMeshPipelines<AtomShadowPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    meshes: Res<RenderAssets<RenderMesh>>,
+    render_mesh_instances: Res<RenderMeshInstances>,
+    rendering: Res<AtomRendering>,
+    atoms: Query<(), With<InstanceBuffer>>,
+    mut phases: ResMut<ViewBinnedRenderPhases<Shadow>>,
+    views: Query<(Entity, &ViewLightEntities), With<ExtractedView>>,
+    light_views: Query<(&LightEntity, &ExtractedView)>,
+    cascades: Query<&RenderCascadesVisibleEntities, With<ExtractedDirectionalLight>>,
+    ticks: SystemChangeTick,
+) {
+    let draw_shadows = draw_functions.read().id::<DrawAtomShadows>();
+
+    for (view, lights) in &views {
+        for &light_view in &lights.lights {
+            let Ok((light, light_view)) = light_views.get(light_view) else {
+                continue;
+            };
+            let LightEntity::Directional {
+                light_entity,
+                cascade_index,
+            } = *light
+            else {
+                continue;
+            };
+            let Some(phase) = phases.get_mut(&light_view.retained_view_entity) else {
+                continue;
+            };
+            let Some(visible) = cascades
+                .get(light_entity)
+                .ok()
+                .and_then(|cascades| cascades.entities.get(&view))
+                .and_then(|cascades| cascades.get(cascade_index))
+            else {
+                continue;
+            };
+
+            for &(entity, main_entity) in &visible.entities {
+                if !atoms.contains(entity) {
+                    continue;
+                }
+                let Some(mesh_instance) = render_mesh_instances.render_mesh_queue_data(main_entity)
+                else {
+                    continue;
+                };
+                let Some(mesh) = meshes.get(mesh_instance.mesh_asset_id) else {
+                    continue;
+                };
+                let pipeline = match pipelines.specialize(
+                    &pipeline_cache,
+                    &shadow_pipeline,
+                    *rendering,
+                    &mesh.layout,
+                ) {
+                    Ok(pipeline) => pipeline,
+                    Err(e) => {
+                        error!("Cannot build the atom shadow pipeline: {e}");
+                        continue;
+                    }
+                };
+                // the instances are drawn by `DrawSphereInstances`, not by Bevy's mesh batching
+                phase.add(
+                    ShadowBatchSetKey {
+                        pipeline,
+                        draw_function: draw_shadows,
+                        material_bind_group_index: None,
+                        vertex_slab: default(),
+                        index_slab: None,
+                    },
+                    ShadowBinKey {
+                        asset_id: mesh_instance.mesh_asset_id.into(),
+                    },
+                    (entity, main_entity),
+                    InputUniformIndex::default(),
+                    BinnedRenderPhaseType::NonMesh,
+                    ticks.this_run(),
+                );
+            }
+        }
+    }
+}
+
+/// The mesh pipeline with the atom shader and the instance buffer.
+#[derive(Resource)]
+struct AtomPipeline {
+    mesh_pipeline: MeshPipeline,
+}
+
+impl FromWorld for AtomPipeline {
+    fn from_world(world: &mut World) -> Self {
+        AtomPipeline {
+            mesh_pipeline: world.resource::<MeshPipeline>().clone(),
+        }
+    }
+}
+
+impl SpecializedMeshPipeline for AtomPipeline {
+    type Key = (MeshPipelineKey, AtomRendering);
+
+    fn specialize(
+        &self,
+        (key, rendering): Self::Key,
+        layout: &MeshVertexBufferLayoutRef,
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
+        let mut descriptor = self.mesh_pipeline.specialize(key, layout)?;
+        descriptor.label = Some("atom instancing pipeline".into());
+        descriptor.vertex.shader = ATOM_SHADER_HANDLE;
+        descriptor
+            .vertex
+            .shader_defs
+            .extend(rendering.shader_defs());
+        descriptor.vertex.buffers.push(instance_buffer_layout());
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            fragment.shader = ATOM_SHADER_HANDLE;
+            fragment.shader_defs.extend(rendering.shader_defs());
+        }
+        Ok(descriptor)
+    }
+}
+
+/// Depth-only pipeline drawing the atoms into shadow maps.
+#[derive(Resource)]
+struct AtomShadowPipeline {
+    /// Layout of the view bind group Bevy's shadow pass sets up.
+    view_layout: BindGroupLayout,
+}
+
+impl FromWorld for AtomShadowPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let prepass = world.resource::<PrepassPipeline<StandardMaterial>>();
+        AtomShadowPipeline {
+            view_layout: prepass.internal.view_layout_no_motion_vectors.clone(),
+        }
+    }
+}
+
+impl SpecializedMeshPipeline for AtomShadowPipeline {
+    type Key = AtomRendering;
+
+    fn specialize(
+        &self,
+        rendering: AtomRendering,
+        layout: &MeshVertexBufferLayoutRef,
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
+        let vertex_layout = layout
+            .0
+            .get_layout(&[Mesh::ATTRIBUTE_POSITION.at_shader_location(0)])?;
+        // meshes only need the rasterizer's depth, impostors write the depth of the sphere
+        let fragment = (rendering == AtomRendering::Impostors).then(|| FragmentState {
+            shader: ATOM_SHADOW_SHADER_HANDLE,
+            shader_defs: rendering.shader_defs(),
+            entry_point: "fragment".into(),
+            targets: Vec::new(),
+        });
+        Ok(RenderPipelineDescriptor {
+            label: Some("atom shadow pipeline".into()),
+            layout: vec![self.view_layout.clone()],
+            push_constant_ranges: Vec::new(),
+            vertex: VertexState {
+                shader: ATOM_SHADOW_SHADER_HANDLE,
+                shader_defs: rendering.shader_defs(),
+                entry_point: "vertex".into(),
+                buffers: vec![vertex_layout, instance_buffer_layout()],
+            },
+            fragment,
+            primitive: PrimitiveState {
+                cull_mode: Some(Face::Back),
+                ..default()
+            },
+            // reversed Z, as in Bevy's shadow pass
+            depth_stencil: Some(DepthStencilState {
+                format: TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::GreaterEqual,
+                stencil: default(),
+                bias: default(),
+            }),
+            multisample: MultisampleState::default(),
+            zero_initialize_workgroup_memory: false,
+        })
+    }
+}
+
+type DrawAtoms = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetMeshBindGroup<1>,
+    DrawSphereInstances,
+);
+
+type DrawAtomShadows = (
+    SetItemPipeline,
+    SetPrepassViewBindGroup<0>,
+    DrawSphereInstances,
+);
+
+/// Draws the sphere mesh once per instance in the buffer.
+struct DrawSphereInstances;
+
+impl<P: PhaseItem> RenderCommand<P> for DrawSphereInstances {
+    type Param = (
+        SRes<RenderAssets<RenderMesh>>,
+        SRes<RenderMeshInstances>,
+        SRes<MeshAllocator>,
+    );
+    type ViewQuery = ();
+    type ItemQuery = Read<InstanceBuffer>;
+
+    fn render<'w>(
+        item: &P,
+        _view: (),
+        instance_buffer: Option<&'w InstanceBuffer>,
+        (meshes, render_mesh_instances, mesh_allocator): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        // the allocator's slices must outlive the borrow of the parameter
+        let mesh_allocator = mesh_allocator.into_inner();
+
+        let Some(mesh_instance) = render_mesh_instances.render_mesh_queue_data(item.main_entity())
+        else {
+            return RenderCommandResult::Skip;
+        };
+        let Some(gpu_mesh) = meshes.into_inner().get(mesh_instance.mesh_asset_id) else {
+            return RenderCommandResult::Skip;
+        };
+        let Some(instance_buffer) = instance_buffer else {
+            return RenderCommandResult::Skip;
+        };
+        if draw_sphere_instances(
+            pass,
+            mesh_allocator,
+            gpu_mesh,
+            &mesh_instance.mesh_asset_id,
+            &instance_buffer.buffer,
+            instance_buffer.length as u32,
+        ) {
+            RenderCommandResult::Success
+        } else {
+            RenderCommandResult::Skip
+        }
+    }
+}
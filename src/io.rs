@@ -1,35 +1,100 @@
 use bevy::prelude::*;
 
+use crate::constants::Element;
 use crate::structure::{Atom, Crystal};
+use crate::toast::Toast;
 
-// System to load crystal data
-pub fn load_crystal(mut commands: Commands) {
+// System to load crystal data, unless the app was given a structure already
+pub fn load_crystal(mut commands: Commands, existing: Option<Res<Crystal>>) {
+    if existing.is_some() {
+        return;
+    }
     // For now, use the default water molecule structure
     // In the future, this can be extended to load from embedded assets or user input
-    println!("Loading default water molecule structure");
-
-    let crystal = Crystal {
-        atoms: vec![
-            Atom {
-                element: "O".to_string(),
-                x: 0.0,
-                y: 0.0,
-                z: 0.0,
-            },
-            Atom {
-                element: "H".to_string(),
-                x: 0.757,
-                y: 0.587,
-                z: 0.0,
-            },
-            Atom {
-                element: "H".to_string(),
-                x: -0.757,
-                y: 0.587,
-                z: 0.0,
-            },
-        ],
-    };
+    info!("Loading default water molecule structure");
+
+    let crystal = Crystal::molecule(vec![
+        Atom {
+            element: Element::O,
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        },
+        Atom {
+            element: Element::H,
+            x: 0.757,
+            y: 0.587,
+            z: 0.0,
+        },
+        Atom {
+            element: Element::H,
+            x: -0.757,
+            y: 0.587,
+            z: 0.0,
+        },
+    ]);
 
     commands.insert_resource(crystal);
 }
+
+// Save `contents` like `save_text_file` and tell the user where `what` went, or why it did not
+pub(crate) fn export_text_file(
+    file_name: &str,
+    contents: &str,
+    what: &str,
+    toasts: &mut EventWriter<Toast>,
+) {
+    match save_text_file(file_name, contents) {
+        Ok(location) => {
+            info!("Saved {what} to {location}");
+            toasts.write(Toast::info(format!("Saved {what} to {location}")));
+        }
+        Err(e) => {
+            error!("Failed to export {what}: {e:#}");
+            toasts.write(Toast::error(format!("Failed to export {what}: {e:#}")));
+        }
+    }
+}
+
+// Save `contents` under `file_name`: into the working directory on native builds, as a
+// browser download on the web. Returns where the file went.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn save_text_file(file_name: &str, contents: &str) -> anyhow::Result<String> {
+    use anyhow::Context;
+
+    std::fs::write(file_name, contents).with_context(|| format!("Failed to write {file_name}"))?;
+    let path = std::env::current_dir()
+        .map(|dir| dir.join(file_name))
+        .unwrap_or_else(|_| file_name.into());
+    Ok(path.display().to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn save_text_file(file_name: &str, contents: &str) -> anyhow::Result<String> {
+    use wasm_bindgen::JsCast;
+    use web_sys::{Blob, BlobPropertyBag, HtmlAnchorElement, Url};
+
+    let js_error = |e: wasm_bindgen::JsValue| anyhow::anyhow!("{e:?}");
+
+    let parts = js_sys::Array::new();
+    parts.push(&wasm_bindgen::JsValue::from_str(contents));
+    let options = BlobPropertyBag::new();
+    options.set_type("text/plain");
+    let blob = Blob::new_with_str_sequence_and_options(&parts, &options).map_err(js_error)?;
+    let url = Url::create_object_url_with_blob(&blob).map_err(js_error)?;
+
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| anyhow::anyhow!("No document to attach the download to"))?;
+    let anchor: HtmlAnchorElement = document
+        .create_element("a")
+        .map_err(js_error)?
+        .dyn_into()
+        .map_err(|_| anyhow::anyhow!("Failed to create download link"))?;
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+    Url::revoke_object_url(&url).map_err(js_error)?;
+
+    Ok(format!("download {file_name}"))
+}
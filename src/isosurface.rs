@@ -0,0 +1,366 @@
+// Isosurfaces of volumetric data
+// The surface where a grid of the volume panel (see volume.rs) crosses a level, drawn as a mesh
+// lit like the atoms. The level is a fraction of the largest magnitude of the grid, so the same
+// setting suits densities of any unit. For signed data, such as orbitals, spin densities and
+// density differences, the surface at minus the level is drawn too, in a second color: yellow
+// for positive and cyan for negative values, the usual way of showing the lobes of an orbital.
+// Surfaces are extracted with marching tetrahedra, six per grid cell, which needs no case
+// tables and leaves no holes; their normals follow the gradient of the data, so they shade
+// smoothly even on coarse grids. Grid slabs are processed in parallel.
+// A surface can be colored by a second grid (Color by row), such as the electrostatic potential
+// on a density surface: the second grid is sampled at each vertex and colored like the slice
+// plane, blue through white to red around zero, over the range of values found on the surface.
+
+use bevy::asset::RenderAssetUsages;
+use bevy::prelude::*;
+use bevy::render::mesh::PrimitiveTopology;
+use rayon::prelude::*;
+
+use crate::settings::stepped_index;
+use crate::slice::value_colors;
+use crate::structure::Crystal;
+use crate::volume::{VolumeGrid, VolumePanel, Volumes};
+use crate::widgets::{on_off, spawn_stepper_row, StepperSettings};
+
+/// Levels as fractions of the largest magnitude of the grid.
+const LEVELS: [f32; 9] = [0.5, 0.2, 0.1, 0.05, 0.02, 0.01, 0.005, 0.002, 0.001];
+const OPACITIES: [f32; 4] = [1.0, 0.8, 0.6, 0.4];
+const POSITIVE_COLOR: Color = Color::srgb(1.0, 0.85, 0.2);
+const NEGATIVE_COLOR: Color = Color::srgb(0.2, 0.8, 0.95);
+/// Triangles above which a surface is not drawn.
+const MAX_TRIANGLES: usize = 4_000_000;
+/// The six tetrahedra of a grid cell, by corner, all around the diagonal from corner 0 to 7;
+/// corner c is at (c & 1, c >> 1 & 1, c >> 2) in the cell.
+const TETRAHEDRA: [[usize; 4]; 6] = [
+    [0, 1, 3, 7],
+    [0, 3, 2, 7],
+    [0, 2, 6, 7],
+    [0, 6, 4, 7],
+    [0, 4, 5, 7],
+    [0, 5, 1, 7],
+];
+
+/// Isosurface settings, shown in the volume panel.
+#[derive(Resource)]
+pub(crate) struct IsosurfaceSettings {
+    pub shown: bool,
+    /// Index into the loaded grids.
+    pub grid: usize,
+    /// Index into `LEVELS`.
+    pub level: usize,
+    /// Whether signed data also gets the surface at minus the level.
+    pub both_signs: bool,
+    /// Index into `OPACITIES`.
+    pub opacity: usize,
+    /// Grid the surface is colored by, counted from 1; 0 for plain colors.
+    pub color_by: usize,
+}
+
+impl Default for IsosurfaceSettings {
+    fn default() -> Self {
+        Self {
+            shown: true,
+            grid: 0,
+            level: 3,
+            both_signs: true,
+            opacity: 0,
+            color_by: 0,
+        }
+    }
+}
+
+impl StepperSettings for IsosurfaceSettings {
+    fn step(&mut self, field: usize, direction: i32) {
+        match field {
+            0 => self.shown = direction > 0,
+            // kept below the number of grids by `update_isosurfaces`
+            1 => self.grid = self.grid.saturating_add_signed(direction as isize),
+            // up means a higher level, further down the list
+            2 => self.level = stepped_index(self.level, -direction, LEVELS.len()),
+            3 => self.both_signs = direction > 0,
+            4 => self.opacity = stepped_index(self.opacity, -direction, OPACITIES.len()),
+            // kept within the number of grids by `update_isosurfaces`
+            _ => self.color_by = self.color_by.saturating_add_signed(direction as isize),
+        }
+    }
+
+    fn value_text(&self, field: usize) -> String {
+        match field {
+            0 => on_off(self.shown),
+            1 => (self.grid + 1).to_string(),
+            2 => format!("{}% of max", LEVELS[self.level] * 100.0),
+            3 => on_off(self.both_signs),
+            4 => format!("{:.0}%", OPACITIES[self.opacity] * 100.0),
+            _ if self.color_by == 0 => "none".to_string(),
+            _ => self.color_by.to_string(),
+        }
+    }
+}
+
+/// A drawn isosurface.
+#[derive(Component)]
+pub(crate) struct Isosurface;
+
+/// Triangles of a surface, three vertices each.
+#[derive(Default)]
+pub(crate) struct Surface {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+}
+
+impl Surface {
+    pub fn triangle_count(&self) -> usize {
+        self.positions.len() / 3
+    }
+
+    pub fn to_mesh(&self) -> Mesh {
+        let array = |vectors: &[Vec3]| vectors.iter().map(|v| v.to_array()).collect::<Vec<_>>();
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, array(&self.positions))
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, array(&self.normals))
+    }
+}
+
+// Gradient of `grid` at a grid point, in grid coordinates, by central differences (one-sided at
+// the faces)
+fn gradient(grid: &VolumeGrid, point: [usize; 3]) -> Vec3 {
+    let mut gradient = Vec3::ZERO;
+    for axis in 0..3 {
+        let (mut low, mut high) = (point, point);
+        low[axis] = point[axis].saturating_sub(1);
+        high[axis] = (point[axis] + 1).min(grid.dims[axis] - 1);
+        gradient[axis] = (grid.value(high) - grid.value(low)) / (high[axis] - low[axis]) as f32;
+    }
+    gradient
+}
+
+/// One grid cell as marching tetrahedra see it.
+struct Cell<'a> {
+    grid: &'a VolumeGrid,
+    corners: [[usize; 3]; 8],
+    /// Signed value minus the level at each corner; the surface is where it is 0.
+    values: [f32; 8],
+    sign: f32,
+    /// Turns gradients in grid coordinates into world directions.
+    normal_matrix: Mat3,
+}
+
+impl Cell<'_> {
+    // Position and outward normal of the surface on the edge between two corners
+    fn crossing(&self, a: usize, b: usize) -> (Vec3, Vec3) {
+        let t = self.values[a] / (self.values[a] - self.values[b]);
+        let [pa, pb] =
+            [a, b].map(|corner| Vec3::from_array(self.corners[corner].map(|x| x as f32)));
+        let [ga, gb] = [a, b].map(|corner| gradient(self.grid, self.corners[corner]));
+        let normal = -(self.normal_matrix * (self.sign * ga.lerp(gb, t)));
+        (
+            self.grid.position(pa.lerp(pb, t)),
+            normal.normalize_or(Vec3::Z),
+        )
+    }
+
+    // Triangle through three edge crossings, wound to face along its normals
+    fn triangle(&self, edges: [(usize, usize); 3], surface: &mut Surface) {
+        let mut vertices = edges.map(|(a, b)| self.crossing(a, b));
+        let facing = (vertices[1].0 - vertices[0].0).cross(vertices[2].0 - vertices[0].0);
+        if facing.dot(vertices[0].1 + vertices[1].1 + vertices[2].1) < 0.0 {
+            vertices.swap(1, 2);
+        }
+        for (position, normal) in vertices {
+            surface.positions.push(position);
+            surface.normals.push(normal);
+        }
+    }
+
+    // Triangles of the surface inside one tetrahedron of the cell
+    fn march(&self, tetrahedron: [usize; 4], surface: &mut Surface) {
+        let (inside, outside): (Vec<usize>, Vec<usize>) = tetrahedron
+            .iter()
+            .partition(|&&corner| self.values[corner] > 0.0);
+        match (inside.as_slice(), outside.as_slice()) {
+            (&[a], &[b, c, d]) | (&[b, c, d], &[a]) => {
+                self.triangle([(a, b), (a, c), (a, d)], surface);
+            }
+            (&[a, b], &[c, d]) => {
+                self.triangle([(a, c), (a, d), (b, d)], surface);
+                self.triangle([(a, c), (b, d), (b, c)], surface);
+            }
+            _ => {}
+        }
+    }
+}
+
+// Surface where `sign` times the grid value equals `level`, with normals pointing to lower
+// values
+pub(crate) fn isosurface(grid: &VolumeGrid, level: f32, sign: f32) -> Surface {
+    let [nx, ny, nz] = grid.dims;
+    let normal_matrix = grid.steps.inverse().transpose();
+    let slabs: Vec<Surface> = (0..nz - 1)
+        .into_par_iter()
+        .map(|k| {
+            let mut surface = Surface::default();
+            for j in 0..ny - 1 {
+                for i in 0..nx - 1 {
+                    let corners: [[usize; 3]; 8] =
+                        std::array::from_fn(|c| [i + (c & 1), j + (c >> 1 & 1), k + (c >> 2)]);
+                    let values = corners.map(|corner| sign * grid.value(corner) - level);
+                    if values.iter().all(|&v| v > 0.0) || values.iter().all(|&v| v <= 0.0) {
+                        continue;
+                    }
+                    let cell = Cell {
+                        grid,
+                        corners,
+                        values,
+                        sign,
+                        normal_matrix,
+                    };
+                    for tetrahedron in TETRAHEDRA {
+                        cell.march(tetrahedron, &mut surface);
+                    }
+                }
+            }
+            surface
+        })
+        .collect();
+    let mut surface = Surface::default();
+    for slab in slabs {
+        surface.positions.extend(slab.positions);
+        surface.normals.extend(slab.normals);
+    }
+    surface
+}
+
+// Add the isosurface rows to the volume panel
+pub(crate) fn setup_isosurface_controls(
+    mut commands: Commands,
+    panel: Single<Entity, With<VolumePanel>>,
+    settings: Res<IsosurfaceSettings>,
+) {
+    commands.entity(*panel).with_children(|panel| {
+        for (field, label) in [
+            "Surface",
+            "Grid",
+            "Level",
+            "Both signs",
+            "Opacity",
+            "Color by",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            spawn_stepper_row(panel, label, field, &*settings);
+        }
+    });
+}
+
+// Rebuild the isosurfaces when their settings or the grids change, and remove them while the
+// grids' structure is not on screen
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_isosurfaces(
+    mut commands: Commands,
+    mut settings: ResMut<IsosurfaceSettings>,
+    volumes: Res<Volumes>,
+    crystal: Res<Crystal>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    surfaces: Query<Entity, With<Isosurface>>,
+    mut drawn: Local<bool>,
+) {
+    if settings.grid >= volumes.grids.len() && settings.grid > 0 {
+        settings.grid = volumes.grids.len().saturating_sub(1);
+    }
+    if settings.color_by > volumes.grids.len() {
+        settings.color_by = volumes.grids.len();
+    }
+    let grid = volumes.grids.get(settings.grid);
+    let visible = settings.shown && volumes.shown(&crystal) && grid.is_some();
+    if visible == *drawn && !settings.is_changed() && !volumes.is_changed() {
+        return;
+    }
+    *drawn = visible;
+    for entity in &surfaces {
+        commands.entity(entity).despawn();
+    }
+    let Some(grid) = grid.filter(|_| visible) else {
+        return;
+    };
+
+    let (min, max) = grid.range();
+    let level = LEVELS[settings.level] * min.abs().max(max);
+    let mut signs = vec![(1.0, POSITIVE_COLOR)];
+    if settings.both_signs && min < 0.0 {
+        signs.push((-1.0, NEGATIVE_COLOR));
+    }
+    let surfaces: Vec<(Surface, Color)> = signs
+        .into_iter()
+        .map(|(sign, color)| (isosurface(grid, level, sign), color))
+        .filter(|(surface, _)| {
+            let drawable = surface.triangle_count() <= MAX_TRIANGLES;
+            if !drawable {
+                warn!(
+                    "Not drawing an isosurface of {} triangles, more than {MAX_TRIANGLES}",
+                    surface.triangle_count()
+                );
+            }
+            drawable
+        })
+        .collect();
+
+    // values of the coloring grid at the vertices, over the range of all drawn surfaces
+    let coloring = settings
+        .color_by
+        .checked_sub(1)
+        .and_then(|index| volumes.grids.get(index))
+        .map(|coloring| {
+            let values: Vec<Vec<f32>> = surfaces
+                .iter()
+                .map(|(surface, _)| {
+                    surface
+                        .positions
+                        .iter()
+                        .map(|&position| coloring.sample(coloring.grid_point(position)))
+                        .collect()
+                })
+                .collect();
+            let range = values
+                .iter()
+                .flatten()
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &value| {
+                    (min.min(value), max.max(value))
+                });
+            (values, range)
+        });
+
+    let opacity = OPACITIES[settings.opacity];
+    for (index, (surface, color)) in surfaces.iter().enumerate() {
+        let mut mesh = surface.to_mesh();
+        let mut color = *color;
+        if let Some((values, range)) = &coloring {
+            let colors = value_colors(&values[index], *range, 1.0)
+                .into_iter()
+                .map(|color| color.to_linear().to_f32_array())
+                .collect::<Vec<_>>();
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+            color = Color::WHITE;
+        }
+        commands.spawn((
+            Mesh3d(meshes.add(mesh)),
+            MeshMaterial3d(materials.add(StandardMaterial {
+                base_color: color.with_alpha(opacity),
+                alpha_mode: if opacity < 1.0 {
+                    AlphaMode::Blend
+                } else {
+                    AlphaMode::Opaque
+                },
+                perceptual_roughness: 0.5,
+                double_sided: true,
+                cull_mode: None,
+                ..default()
+            })),
+            Isosurface,
+        ));
+    }
+}
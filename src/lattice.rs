@@ -0,0 +1,217 @@
+// Lattice parameter panel
+// Shows a, b, c, α, β, γ of the current cell and lets them be stepped, or typed in as
+// `a=5.431 gamma=120` (any subset) or as all six values in order; the structure is rescaled
+// with fractional coordinates held fixed.
+
+use bevy::prelude::*;
+
+use crate::cell::{lattice_from_parameters, lattice_parameters, rescale_lattice};
+use crate::structure::Crystal;
+use crate::theme::Themed;
+use crate::toast::Toast;
+use crate::ui::{SidePanelColumn, ToggleId, ToggledPanel};
+use crate::widgets::{
+    entered_values, spawn_button, spawn_stepper_row, spawn_text_field, StepperSettings, TextField,
+    TextSubmitted,
+};
+
+// Relative change of a cell length per step
+const LENGTH_STEP: f32 = 0.01;
+// Change of a cell angle per step, in degrees
+const ANGLE_STEP: f32 = 1.0;
+// Parameters closer than this are considered unchanged
+const PARAMETER_TOLERANCE: f32 = 1e-4;
+const PARAMETER_NAMES: [&str; 6] = ["a", "b", "c", "alpha", "beta", "gamma"];
+
+/// Text field taking typed lattice parameters.
+#[derive(Component)]
+pub(crate) struct LatticeField;
+
+/// Button applying the typed lattice parameters.
+#[derive(Component)]
+pub(crate) struct LatticeSetButton;
+
+/// Lattice parameters shown in the panel, mirroring the current cell (None for molecules).
+#[derive(Resource, Default)]
+pub(crate) struct LatticeEditor {
+    parameters: Option<[f32; 6]>,
+    // Set by steps and typed values, so cells mirrored from new structures are not applied
+    edited: bool,
+}
+
+impl StepperSettings for LatticeEditor {
+    fn step(&mut self, field: usize, direction: i32) {
+        let Some(mut parameters) = self.parameters else {
+            return;
+        };
+        if field < 3 {
+            parameters[field] *= 1.0 + direction as f32 * LENGTH_STEP;
+        } else {
+            parameters[field] += direction as f32 * ANGLE_STEP;
+        }
+        // ignore steps that would collapse the cell
+        if lattice_from_parameters(parameters).is_some() {
+            self.parameters = Some(parameters);
+            self.edited = true;
+        }
+    }
+
+    fn value_text(&self, field: usize) -> String {
+        match self.parameters {
+            None => "-".to_string(),
+            Some(parameters) if field < 3 => format!("{:.4} Å", parameters[field]),
+            Some(parameters) => format!("{:.2} deg", parameters[field]),
+        }
+    }
+}
+
+// Parameters typed as `name=value` pairs, which change only the parameters they name, or as
+// six values in the order a, b, c, alpha, beta, gamma
+fn typed_parameters(text: &str, current: [f32; 6]) -> Result<[f32; 6], String> {
+    let mut parameters = current;
+    let tokens: Vec<&str> = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|token| !token.is_empty())
+        .collect();
+    let number = |value: &str| {
+        value
+            .parse::<f32>()
+            .map_err(|_| format!("Not a number: {value}"))
+    };
+    if tokens.iter().all(|token| !token.contains('=')) {
+        if tokens.len() != 6 {
+            return Err("Enter name=value pairs or all six parameters".to_string());
+        }
+        for (parameter, token) in parameters.iter_mut().zip(&tokens) {
+            *parameter = number(token)?;
+        }
+    } else {
+        for token in tokens {
+            let (name, value) = token
+                .split_once('=')
+                .ok_or_else(|| format!("Expected name=value: {token}"))?;
+            let index = PARAMETER_NAMES
+                .iter()
+                .position(|known| known.eq_ignore_ascii_case(name.trim()))
+                .ok_or_else(|| format!("Unknown lattice parameter: {name}"))?;
+            parameters[index] = number(value.trim())?;
+        }
+    }
+    if lattice_from_parameters(parameters).is_none() {
+        return Err("These parameters do not make a cell".to_string());
+    }
+    Ok(parameters)
+}
+
+fn same_parameters(a: Option<[f32; 6]>, b: Option<[f32; 6]>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a
+            .iter()
+            .zip(b.iter())
+            .all(|(x, y)| (x - y).abs() < PARAMETER_TOLERANCE),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+// Spawn the (hidden) lattice panel in the side column
+pub(crate) fn setup_lattice_panel(
+    mut commands: Commands,
+    column: Single<Entity, With<SidePanelColumn>>,
+    editor: Res<LatticeEditor>,
+) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            ToggledPanel(ToggleId::LatticeEditor),
+            ChildOf(*column),
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                Text::new("Lattice parameters"),
+                TextFont {
+                    font: default(),
+                    font_size: 14.0,
+                    ..default()
+                },
+                Themed::Text,
+            ));
+            for (field, label) in PARAMETER_NAMES.into_iter().enumerate() {
+                spawn_stepper_row(panel, label, field, &*editor);
+            }
+            panel
+                .spawn(Node {
+                    flex_direction: FlexDirection::Row,
+                    column_gap: Val::Px(4.0),
+                    ..default()
+                })
+                .with_children(|row| {
+                    spawn_text_field(row, "a=5.431 gamma=120", 180.0, LatticeField);
+                    spawn_button(row, "Set", LatticeSetButton);
+                });
+        });
+}
+
+// Take typed parameters on Enter or a click on Set
+pub(crate) fn lattice_entry(
+    buttons: Query<&Interaction, (Changed<Interaction>, With<LatticeSetButton>)>,
+    fields: Query<&TextField, With<LatticeField>>,
+    mut submitted: EventReader<TextSubmitted>,
+    mut editor: ResMut<LatticeEditor>,
+    mut toasts: EventWriter<Toast>,
+) {
+    let pressed = buttons.iter().any(|i| *i == Interaction::Pressed);
+    for text in entered_values(&mut submitted, &fields, pressed) {
+        let Some(current) = editor.parameters else {
+            let message = "The structure has no cell to edit";
+            warn!("{message}");
+            toasts.write(Toast::warning(message));
+            continue;
+        };
+        match typed_parameters(&text, current) {
+            Ok(parameters) => {
+                editor.parameters = Some(parameters);
+                editor.edited = true;
+            }
+            Err(e) => {
+                warn!("{e}");
+                toasts.write(Toast::warning(e));
+            }
+        }
+    }
+}
+
+// Mirror the cell of a new or changed structure into the panel
+pub(crate) fn sync_lattice_editor(crystal: Res<Crystal>, mut editor: ResMut<LatticeEditor>) {
+    if !crystal.is_changed() {
+        return;
+    }
+    let parameters = crystal.lattice.map(lattice_parameters);
+    if !same_parameters(editor.parameters, parameters) {
+        editor.parameters = parameters;
+    }
+}
+
+// Rescale the structure to parameters stepped or typed in the panel
+pub(crate) fn apply_lattice_edits(mut editor: ResMut<LatticeEditor>, mut crystal: ResMut<Crystal>) {
+    if !editor.edited {
+        return;
+    }
+    editor.edited = false;
+    let Some(parameters) = editor.parameters else {
+        return;
+    };
+    if same_parameters(crystal.lattice.map(lattice_parameters), Some(parameters)) {
+        return;
+    }
+    if let Some(rescaled) = rescale_lattice(&crystal, parameters) {
+        *crystal = rescaled;
+    }
+}
@@ -0,0 +1,216 @@
+// Trajectory files read frame by frame
+// XYZ files larger than the frame cache are not read whole when opened: they are scanned once
+// for the byte offset of every frame, and a frame is only parsed when it is needed, e.g. shown,
+// played or plotted. Parsed frames are kept in a cache that drops the least recently used ones
+// to stay within its memory cap, `frame_cache_mb` in the configuration (512 MB by default), so a
+// trajectory of any length takes about as much memory as the cache plus the offsets. Smaller
+// files, and files of other formats, are read whole as before.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::Context;
+use bevy::math::Vec3;
+
+use crate::config::Config;
+use crate::parse::{parse_structure_as, Format};
+use crate::structure::{Atom, Crystal};
+
+/// Memory the frame cache takes when the configuration sets none, in MB.
+const DEFAULT_CACHE_MB: u64 = 512;
+const MB: u64 = 1 << 20;
+/// Bytes scanned between two progress reports.
+const REPORT_BYTES: u64 = 4 << 20;
+
+// Memory cap of the frame cache, in bytes, which is also the size above which files are indexed
+// rather than read whole
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+pub(crate) fn cache_bytes(config: &Config) -> u64 {
+    config.frame_cache_mb.unwrap_or(DEFAULT_CACHE_MB) * MB
+}
+
+/// File a frame is stored in, and the byte range of its lines.
+#[derive(Clone, Copy, Debug)]
+struct FrameSpan {
+    file: usize,
+    offset: u64,
+    length: u64,
+}
+
+/// Parsed frames by position, with the time they were last used.
+#[derive(Default)]
+struct FrameCache {
+    frames: HashMap<usize, (Crystal, u64)>,
+    /// Positions of the cached frames by the time they were last used, oldest first.
+    uses: BTreeMap<u64, usize>,
+    clock: u64,
+    bytes: u64,
+}
+
+impl FrameCache {
+    fn get(&mut self, position: usize) -> Option<Crystal> {
+        let (crystal, used) = self.frames.get_mut(&position)?;
+        self.uses.remove(&*used);
+        self.clock += 1;
+        *used = self.clock;
+        self.uses.insert(self.clock, position);
+        Some(crystal.clone())
+    }
+
+    // Keep `crystal`, dropping the least recently used frames while the cache is over `cap`;
+    // the newest frame is kept even when it alone is larger
+    fn insert(&mut self, position: usize, crystal: Crystal, cap: u64) {
+        self.clock += 1;
+        self.bytes += size(&crystal);
+        self.uses.insert(self.clock, position);
+        if let Some((replaced, used)) = self.frames.insert(position, (crystal, self.clock)) {
+            self.uses.remove(&used);
+            self.bytes -= size(&replaced);
+        }
+        while self.bytes > cap && self.frames.len() > 1 {
+            let Some((_, oldest)) = self.uses.pop_first() else {
+                break;
+            };
+            if let Some((dropped, _)) = self.frames.remove(&oldest) {
+                self.bytes -= size(&dropped);
+            }
+        }
+    }
+}
+
+// Approximate memory taken by a parsed frame, its atoms and their properties
+fn size(crystal: &Crystal) -> u64 {
+    use std::mem::size_of;
+
+    let atoms = crystal.atoms.len() * size_of::<Atom>();
+    let properties = &crystal.properties;
+    let scalars: usize = properties
+        .scalars
+        .values()
+        .map(|values| values.len() * size_of::<f32>())
+        .sum();
+    let vectors: usize = properties
+        .vectors
+        .values()
+        .map(|values| values.len() * size_of::<Vec3>())
+        .sum();
+    let strings: usize = properties
+        .strings
+        .values()
+        .flatten()
+        .map(|value| size_of::<String>() + value.len())
+        .sum();
+    (size_of::<Crystal>() + atoms + scalars + vectors + strings) as u64
+}
+
+/// Frames of XYZ files, found by their byte offsets and parsed on demand.
+pub(crate) struct FrameFile {
+    paths: Vec<PathBuf>,
+    spans: Vec<FrameSpan>,
+    /// Memory the cached frames may take, in bytes.
+    cap: u64,
+    cache: Mutex<FrameCache>,
+}
+
+impl FrameFile {
+    // Find the frames of every file in order, reporting the bytes scanned and the frames found
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub fn index(
+        paths: &[PathBuf],
+        cap: u64,
+        mut report: impl FnMut(u64, usize),
+    ) -> anyhow::Result<Self> {
+        let mut spans = Vec::new();
+        let mut scanned = 0;
+        let mut reported = 0;
+        for (file, path) in paths.iter().enumerate() {
+            let failed = || format!("Failed to read {}", path.display());
+            let mut reader = BufReader::new(File::open(path).with_context(failed)?);
+            let mut line = Vec::new();
+            let mut offset = 0;
+            loop {
+                line.clear();
+                let read = reader.read_until(b'\n', &mut line).with_context(failed)? as u64;
+                if read == 0 {
+                    break;
+                }
+                let start = offset;
+                offset += read;
+                let count = String::from_utf8_lossy(&line);
+                let count = count.trim();
+                if count.is_empty() {
+                    continue;
+                }
+                let atoms: usize = count.parse().with_context(|| {
+                    format!(
+                        "Failed to parse number of atoms of frame {} of {}",
+                        spans.len() + 1,
+                        path.display()
+                    )
+                })?;
+                // the comment line and the atom lines
+                for _ in 0..atoms + 1 {
+                    line.clear();
+                    let read = reader.read_until(b'\n', &mut line).with_context(failed)?;
+                    if read == 0 {
+                        break;
+                    }
+                    offset += read as u64;
+                }
+                spans.push(FrameSpan {
+                    file,
+                    offset: start,
+                    length: offset - start,
+                });
+                if scanned + offset - reported >= REPORT_BYTES {
+                    reported = scanned + offset;
+                    report(reported, spans.len());
+                }
+            }
+            scanned += offset;
+            report(scanned, spans.len());
+        }
+        anyhow::ensure!(!spans.is_empty(), "XYZ file too short");
+        Ok(Self {
+            paths: paths.to_vec(),
+            spans,
+            cap,
+            cache: Mutex::default(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    // The frame at `position`, from the cache or read from its file
+    pub fn read(&self, position: usize) -> anyhow::Result<Crystal> {
+        if let Some(crystal) = self.lock().get(position) {
+            return Ok(crystal);
+        }
+        let span = *self
+            .spans
+            .get(position)
+            .with_context(|| format!("No frame {position}, the files hold {}", self.len()))?;
+        let path = &self.paths[span.file];
+        let failed = || format!("Failed to read frame {position} of {}", path.display());
+        let mut file = File::open(path).with_context(failed)?;
+        file.seek(SeekFrom::Start(span.offset))
+            .with_context(failed)?;
+        let mut bytes = vec![0; span.length as usize];
+        file.read_exact(&mut bytes).with_context(failed)?;
+        let contents = String::from_utf8(bytes)
+            .with_context(|| format!("{} is not a text file", path.display()))?;
+        let crystal = parse_structure_as(&path.to_string_lossy(), &contents, Some(Format::Xyz))?;
+        self.lock().insert(position, crystal.clone(), self.cap);
+        Ok(crystal)
+    }
+
+    // The cache, even if a thread panicked while holding it
+    fn lock(&self) -> std::sync::MutexGuard<'_, FrameCache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -1,67 +1,874 @@
+//! Interactive viewer for crystals and molecules, built on Bevy.
+//!
+//! Besides the `vizmat` binary, the crate can be used as a library: build a [`Crystal`] with
+//! [`Crystal::builder`] or read one with [`parse_structure`], then show it by adding
+//! [`VizmatPlugin`] to an app with the crystal as a resource. The plugin reports what happens
+//! in the viewer through events such as [`StructureLoaded`] and [`AtomPicked`].
+
+use bevy::diagnostic::{EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin};
 use bevy::log::{Level, LogPlugin};
 use bevy::prelude::*;
 
 pub(crate) mod io;
+pub(crate) mod lattice;
+pub(crate) mod lazy_frames;
+pub(crate) mod lighting;
+pub(crate) mod loading;
+#[cfg(feature = "fetch")]
+pub(crate) mod materials_project;
 pub(crate) mod ui;
 
+pub(crate) mod analysis;
+pub(crate) mod atom_info;
+pub(crate) mod atom_labels;
+pub(crate) mod atom_picking;
+pub(crate) mod axis_gizmo;
+pub(crate) mod bindings;
+pub(crate) mod bonds;
+pub(crate) mod cell;
+#[cfg(feature = "cif")]
+pub(crate) mod cif;
+pub(crate) mod cli;
+#[cfg(feature = "websocket")]
 pub(crate) mod client;
+pub(crate) mod clipboard;
+pub(crate) mod color;
+pub(crate) mod composition;
+pub(crate) mod config;
+pub(crate) mod console;
 pub(crate) mod constants;
+pub(crate) mod defects;
+pub(crate) mod events;
+pub(crate) mod figure;
+pub(crate) mod file_dialog;
+pub(crate) mod fly_camera;
+pub(crate) mod gro;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod headless;
+pub(crate) mod instancing;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod isosurface;
+pub(crate) mod nanoparticle;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod neb;
+pub(crate) mod neighbors;
+pub(crate) mod palette;
 pub(crate) mod parse;
+pub(crate) mod performance;
+pub(crate) mod periodic_table;
+pub(crate) mod persist;
+pub(crate) mod poscar;
+#[cfg(feature = "websocket")]
+pub(crate) mod protocol;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod raymarch;
+#[cfg(feature = "fetch")]
+pub(crate) mod remote;
+#[cfg(feature = "websocket")]
+pub(crate) mod rpc;
+pub(crate) mod sanity;
+#[cfg(feature = "scripting")]
+pub(crate) mod scripting;
+pub(crate) mod settings;
+pub(crate) mod slab;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod slice;
+pub(crate) mod statistics;
 pub(crate) mod structure;
+pub(crate) mod structure_info;
+pub(crate) mod structure_list;
+pub(crate) mod theme;
+pub(crate) mod toast;
+pub(crate) mod trajectory;
+pub(crate) mod units;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod vibrations;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod volume;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod watch;
+#[cfg(target_arch = "wasm32")]
+pub(crate) mod web;
+pub(crate) mod widgets;
 
-use crate::client::{poll_websocket_stream, setup_websocket_stream};
+use crate::analysis::{
+    update_bond_statistics, update_coordination, update_rmsd, BondStatistics, BondTolerance,
+    Coordination, Rmsd, RmsdSettings,
+};
+use crate::atom_info::{refresh_atom_info_panel, setup_atom_info_panel};
+use crate::atom_labels::{place_atom_labels, setup_label_panel, update_atom_labels, LabelSettings};
+use crate::atom_picking::AtomPickingPlugin;
+use crate::axis_gizmo::{
+    place_axis_labels, snap_view_to_axis, spawn_axis, sync_axis_camera, update_axis_gizmo,
+};
+use crate::bindings::{
+    refresh_help_overlay, setup_help_overlay, toggle_help, HelpShown, KeyBindings,
+};
+use crate::bonds::{update_bonds, BondStyle, ContactCutoff};
+#[cfg(feature = "websocket")]
+use crate::client::{
+    poll_websocket_stream, refresh_connection_indicator, send_camera_pose, send_selection,
+    send_structure_edits, setup_connection_indicator, setup_websocket_stream, source_dropdown,
+    ConnectionState,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::clipboard::paste_structure;
+#[cfg(target_arch = "wasm32")]
+use crate::clipboard::{listen_for_paste, receive_pasted_text};
+use crate::color::{ColorBy, ColorScheme, ElementOverrides};
+use crate::composition::{
+    refresh_composition_panel, refresh_species_toggles, setup_composition_panel,
+    species_toggle_buttons,
+};
+use crate::config::{load_config, Config};
+use crate::console::{
+    capture_log, clear_console_button, receive_log_lines, refresh_console_panel, scroll_console,
+    setup_console_panel, ConsoleLog,
+};
+use crate::defects::{defect_actions, setup_defect_panel, DefectSettings};
+use crate::events::send_selection_changed;
+use crate::figure::{capture_figures, spawn_figure_cameras, SaveFigure};
+use crate::file_dialog::{open_file_button, receive_picked_files, FileDialog};
+use crate::fly_camera::{apply_fly_mode, fly_camera, fly_mode_hotkey, not_flying, FlyCamera};
+use crate::instancing::AtomInstancingPlugin;
 use crate::io::load_crystal;
-use crate::structure::{update_crystal_system, UpdateStructure};
-use crate::ui::{camera_controls, refresh_atoms_system, setup_cameras, setup_scene};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::isosurface::{setup_isosurface_controls, update_isosurfaces, IsosurfaceSettings};
+use crate::lattice::{
+    apply_lattice_edits, lattice_entry, setup_lattice_panel, sync_lattice_editor, LatticeEditor,
+};
+use crate::lighting::{apply_lighting, LightingRig};
+use crate::loading::{setup_loading_indicator, spin_loading_indicator, update_loading_indicator};
+#[cfg(feature = "fetch")]
+use crate::materials_project::materials_project_actions;
+use crate::nanoparticle::{carve_button, setup_nanoparticle_panel, NanoparticleSettings};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::neb::{
+    open_neb_argument, open_neb_paths, place_neb_labels, refresh_neb_panel, setup_neb_panel,
+    sweep_neb_path, update_neb_ghosts, update_neb_labels, NebPath, NebSettings, OpenNebPath,
+};
+use crate::palette::{
+    command_palette_input, refresh_command_palette, run_palette_actions, setup_command_palette,
+    toggle_command_palette, CommandPalette, PaletteAction,
+};
+use crate::performance::{refresh_performance_panel, setup_performance_panel};
+use crate::periodic_table::{
+    element_cell_interaction, element_editor_interaction, refresh_periodic_table,
+    setup_periodic_table, EditingElement,
+};
+use crate::persist::{restore_ui_state, save_ui_state};
+#[cfg(feature = "websocket")]
+use crate::protocol::RpcRequest;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::raymarch::{
+    refresh_transfer_preview, setup_raymarch_controls, update_volume_rendering, RaymarchSettings,
+    VolumeRenderingPlugin,
+};
+#[cfg(all(feature = "fetch", feature = "cif"))]
+use crate::remote::fetch_pdb_actions;
+#[cfg(feature = "fetch")]
+use crate::remote::{
+    fetch_url_argument, open_url_actions, receive_downloads, setup_open_panel, RemoteLoader,
+};
+#[cfg(feature = "websocket")]
+use crate::rpc::handle_rpc_requests;
+use crate::sanity::{
+    dismiss_warnings_button, refresh_warning_banner, setup_warning_banner,
+    update_structure_warnings, StructureWarnings,
+};
+#[cfg(feature = "scripting")]
+use crate::scripting::{
+    queue_script_arguments, run_scripts, script_console_actions, setup_script_panel, ScriptQueue,
+};
+#[cfg(not(all(target_arch = "wasm32", feature = "webgl2")))]
+use crate::settings::update_depth_of_field;
+use crate::settings::{apply_settings, setup_settings_panel, update_fog, SettingsEditor};
+use crate::slab::{setup_slab_panel, slab_build_button, SlabSettings};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::slice::{setup_slice_controls, update_slice_plane, SliceSettings};
+use crate::statistics::{
+    export_statistics_button, refresh_rmsd_plot, refresh_statistics_panel, rmsd_plot_clicks,
+    setup_statistics_panel,
+};
+use crate::structure::{update_crystal_system, Selection};
+use crate::structure_info::{refresh_structure_info_panel, setup_structure_info_panel};
+use crate::structure_list::{
+    close_structure_tab, refresh_structure_tabs, setup_structure_tabs, switch_structure,
+    unmark_replaced_entry, StructureList,
+};
+use crate::theme::{apply_theme, themed_button_feedback, UiTheme};
+use crate::toast::{expire_toasts, setup_toast_stack, show_toasts, Toast};
+use crate::trajectory::{
+    play_trajectory, playback_controls, record_streamed_frames, refresh_play_button,
+    refresh_timeline, refresh_trajectory_panel, scrub_timeline, scrub_trajectory, setup_timeline,
+    setup_trajectory_panel, Playback, StreamedFrame, Trajectory,
+};
+use crate::ui::{
+    camera_controls, refresh_atoms_system, setup_cameras, setup_scene, setup_side_panels,
+    update_gizmo_viewport,
+};
+use crate::ui::{
+    cell_conversion_buttons, color_by_button, color_scheme_dropdown, draw_unit_cell, setup_buttons,
+};
+use crate::ui::{
+    center_structure_buttons, draw_selection, fit_camera_on_load, focus_camera_hotkey,
+    refresh_color_labels, select_atom_on_click, AtomChunkQueue, AtomScale, FitView,
+    MouseSensitivity, RenderQuality, SphereDetail, SphereMeshes,
+};
 use crate::ui::{
     handle_toggle_events, reset_camera_button_interaction, toggle_button, ToggleEvent, ToggleStates,
 };
-use crate::ui::{setup_buttons, spawn_axis};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::vibrations::{
+    animate_vibrations, draw_vibration_arrows, open_mode_files, open_modes_argument,
+    refresh_vibration_panel, setup_vibration_panel, OpenModes, Vibrations,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::volume::{
+    open_volume_argument, open_volume_files, refresh_volume_panel, setup_volume_panel, OpenVolumes,
+    Volumes,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::watch::{
+    open_cli_files, open_dropped_files, open_files, receive_loaded_files, FileLoader, OpenFiles,
+};
+#[cfg(all(feature = "watch", not(target_arch = "wasm32")))]
+use crate::watch::{reload_watched_file, WatchedFile};
+#[cfg(target_arch = "wasm32")]
+use crate::web::{
+    listen_for_page_messages, post_user_events, read_page_query, receive_pushed_structures,
+    share_shown_structure,
+};
+use crate::widgets::{
+    focus_text_fields, no_text_focus, refresh_stepper_text, refresh_text_fields, stepper_buttons,
+    text_field_input, FocusedField, TextSubmitted,
+};
+
+#[cfg(not(feature = "scripting"))]
+use crate::cli::reject_script_arguments;
+pub use crate::cli::{Cli, Command, RenderArgs};
+pub use crate::constants::{Element, ElementData};
+pub use crate::events::{AtomPicked, FrameChanged, SelectionChanged, StructureLoaded};
+pub use crate::parse::{parse_frames, parse_structure, write_xyz, Format};
+pub use crate::structure::{
+    Atom, AtomProperties, Crystal, CrystalBuilder, Metadata, UpdateStructure,
+};
+pub use crate::units::LengthUnit;
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
-/// Entry point for WASM
+/// Entry point for WASM: run the viewer in the canvas matching the `canvas` selector, sized to
+/// fit its parent element, or in a canvas of its own if omitted.
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
-pub fn start() {
-    run_app();
+pub fn start(canvas: Option<String>) {
+    run_app(Cli {
+        canvas,
+        ..default()
+    });
 }
 
 /// Shared function for Bevy app setup
-pub fn run_app() {
+pub fn run_app(cli: Cli) -> AppExit {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(Command::Render(args)) = &cli.command {
+        return headless::render(&cli, args);
+    }
+
+    // a page handing over its own canvas lays it out itself
+    #[cfg(target_arch = "wasm32")]
+    let window = Window {
+        fit_canvas_to_parent: cli.canvas.is_some(),
+        canvas: cli.canvas.clone(),
+        ..default()
+    };
+    #[cfg(not(target_arch = "wasm32"))]
+    let window = Window::default();
+
     App::new()
-        .add_plugins(DefaultPlugins.set(LogPlugin {
-            level: Level::DEBUG,
-            filter: "wgpu=error,bevy_render=info,bevy_ecs=trace".to_string(),
-            custom_layer: |_| None,
-        }))
-        .init_resource::<ToggleStates>()
-        .add_event::<UpdateStructure>()
-        .add_event::<ToggleEvent>()
-        .add_systems(Startup, load_crystal)
-        .add_systems(Startup, setup_scene.after(load_crystal))
-        .add_systems(
-            Startup,
-            (
-                setup_cameras,
-                spawn_axis,
-                setup_buttons,
-                setup_websocket_stream,
-            )
-                .after(setup_scene),
+        .insert_resource(cli)
+        .add_plugins(
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: Some(window),
+                    ..default()
+                })
+                .set(LogPlugin {
+                    level: Level::DEBUG,
+                    filter: "wgpu=error,bevy_render=info,bevy_ecs=trace".to_string(),
+                    custom_layer: capture_log,
+                }),
         )
-        .add_systems(
+        .add_plugins(VizmatPlugin)
+        .run()
+}
+
+/// The viewer as a Bevy plugin, for apps that build on it. Add it after `DefaultPlugins`.
+///
+/// The structure shown is the [`Crystal`] resource: insert one before the app runs to replace
+/// the default water molecule, and send [`UpdateStructure`] events to change it later. A
+/// [`Cli`] resource, if inserted, configures the viewer like the command-line options do.
+///
+/// Read [`StructureLoaded`], [`AtomPicked`], [`SelectionChanged`] and [`FrameChanged`] events
+/// to react to the user.
+pub struct VizmatPlugin;
+
+impl Plugin for VizmatPlugin {
+    fn build(&self, app: &mut App) {
+        // for the statistics panel; apps building on the plugin may have added them already
+        if !app.is_plugin_added::<FrameTimeDiagnosticsPlugin>() {
+            app.add_plugins(FrameTimeDiagnosticsPlugin::default());
+        }
+        if !app.is_plugin_added::<EntityCountDiagnosticsPlugin>() {
+            app.add_plugins(EntityCountDiagnosticsPlugin);
+        }
+        app.add_plugins((AtomInstancingPlugin, AtomPickingPlugin))
+            .init_resource::<Cli>()
+            .init_resource::<Config>()
+            .init_resource::<MouseSensitivity>()
+            .init_resource::<FlyCamera>()
+            .init_resource::<LightingRig>()
+            .init_resource::<RenderQuality>()
+            .init_resource::<AtomScale>()
+            .init_resource::<BondTolerance>()
+            .init_resource::<BondStyle>()
+            .init_resource::<ContactCutoff>()
+            .init_resource::<LabelSettings>()
+            .init_resource::<SphereDetail>()
+            .init_resource::<SphereMeshes>()
+            .init_resource::<AtomChunkQueue>()
+            .init_resource::<ToggleStates>()
+            .init_resource::<UiTheme>()
+            .init_resource::<Selection>()
+            .init_resource::<ColorScheme>()
+            .init_resource::<ColorBy>()
+            .init_resource::<Coordination>()
+            .init_resource::<ElementOverrides>()
+            .init_resource::<EditingElement>()
+            .init_resource::<SlabSettings>()
+            .init_resource::<NanoparticleSettings>()
+            .init_resource::<DefectSettings>()
+            .init_resource::<LatticeEditor>()
+            .init_resource::<BondStatistics>()
+            .init_resource::<StructureWarnings>()
+            .init_resource::<Trajectory>()
+            .init_resource::<Playback>()
+            .init_resource::<Rmsd>()
+            .init_resource::<RmsdSettings>()
+            .init_resource::<FocusedField>()
+            .init_resource::<FileDialog>()
+            .init_resource::<CommandPalette>()
+            .init_resource::<KeyBindings>()
+            .init_resource::<HelpShown>()
+            .init_resource::<StructureList>()
+            .init_resource::<ConsoleLog>()
+            .add_event::<UpdateStructure>()
+            .add_event::<StreamedFrame>()
+            .add_event::<TextSubmitted>()
+            .add_event::<FitView>()
+            .add_event::<ToggleEvent>()
+            .add_event::<StructureLoaded>()
+            .add_event::<AtomPicked>()
+            .add_event::<SelectionChanged>()
+            .add_event::<FrameChanged>()
+            .add_event::<PaletteAction>()
+            .add_event::<Toast>()
+            .add_event::<SaveFigure>()
+            .add_systems(Startup, load_config.before(load_crystal))
+            .add_systems(
+                Startup,
+                restore_ui_state.after(load_config).before(load_crystal),
+            )
+            .add_systems(Last, save_ui_state)
+            .add_systems(Startup, load_crystal)
+            .add_systems(Startup, setup_scene.after(load_crystal))
+            .add_systems(
+                Startup,
+                (
+                    setup_cameras,
+                    spawn_axis,
+                    setup_buttons,
+                    setup_periodic_table,
+                    setup_side_panels,
+                    setup_warning_banner,
+                    setup_command_palette,
+                    setup_help_overlay,
+                    setup_loading_indicator,
+                    setup_toast_stack,
+                    setup_structure_tabs,
+                    setup_console_panel.after(setup_side_panels),
+                    (
+                        setup_trajectory_panel,
+                        setup_structure_info_panel.before(setup_buttons),
+                        setup_atom_info_panel,
+                        setup_composition_panel,
+                        setup_lattice_panel,
+                        setup_statistics_panel,
+                        setup_performance_panel,
+                        setup_settings_panel,
+                        setup_label_panel,
+                    )
+                        .chain()
+                        .after(setup_side_panels),
+                    (
+                        setup_timeline,
+                        setup_slab_panel,
+                        setup_nanoparticle_panel,
+                        setup_defect_panel,
+                    )
+                        .chain()
+                        .after(setup_side_panels),
+                )
+                    .after(setup_scene),
+            )
+            .add_systems(Startup, fit_camera_on_load.after(setup_cameras))
+            .add_observer(select_atom_on_click)
+            .add_systems(
+                Update,
+                (
+                    update_crystal_system,
+                    update_coordination
+                        .after(update_crystal_system)
+                        .before(refresh_atoms_system),
+                    refresh_atoms_system,
+                    toggle_button,
+                    reset_camera_button_interaction,
+                    color_scheme_dropdown,
+                    color_by_button,
+                    handle_toggle_events,
+                    focus_camera_hotkey.before(camera_controls),
+                    camera_controls.run_if(not_flying),
+                    draw_selection,
+                    draw_unit_cell,
+                    cell_conversion_buttons,
+                    element_cell_interaction,
+                    element_editor_interaction,
+                    refresh_periodic_table,
+                    refresh_atom_info_panel.after(update_coordination),
+                    refresh_structure_info_panel.after(update_coordination),
+                    update_gizmo_viewport,
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    stepper_buttons::<SlabSettings>,
+                    refresh_stepper_text::<SlabSettings>,
+                    slab_build_button,
+                    center_structure_buttons.before(camera_controls),
+                    refresh_composition_panel,
+                    species_toggle_buttons.before(refresh_atoms_system),
+                    refresh_species_toggles.after(species_toggle_buttons),
+                    stepper_buttons::<LatticeEditor>,
+                    lattice_entry,
+                    apply_lattice_edits
+                        .after(stepper_buttons::<LatticeEditor>)
+                        .after(lattice_entry)
+                        .after(update_crystal_system),
+                    sync_lattice_editor.after(apply_lattice_edits),
+                    refresh_stepper_text::<LatticeEditor>.after(sync_lattice_editor),
+                    update_bond_statistics.after(update_crystal_system),
+                    refresh_statistics_panel.after(update_bond_statistics),
+                    refresh_performance_panel,
+                    export_statistics_button,
+                    update_structure_warnings.after(update_crystal_system),
+                    refresh_warning_banner.after(update_structure_warnings),
+                    dismiss_warnings_button,
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    stepper_buttons::<SettingsEditor>,
+                    refresh_stepper_text::<SettingsEditor>,
+                    apply_settings
+                        .after(stepper_buttons::<SettingsEditor>)
+                        .before(update_coordination)
+                        .before(update_bond_statistics)
+                        .before(refresh_atoms_system),
+                    update_fog.after(camera_controls).after(fly_camera),
+                    update_axis_gizmo,
+                    apply_lighting.after(apply_settings),
+                    update_bonds
+                        .after(apply_settings)
+                        .after(update_crystal_system),
+                    sync_axis_camera.after(camera_controls).after(fly_camera),
+                    snap_view_to_axis.before(camera_controls),
+                    fly_mode_hotkey,
+                    apply_fly_mode.after(fly_mode_hotkey),
+                    fly_camera.after(apply_fly_mode),
+                ),
+            )
+            // labels follow the arrows and atoms once their transforms are propagated
+            .add_systems(
+                PostUpdate,
+                (place_axis_labels, place_atom_labels).after(TransformSystem::TransformPropagate),
+            )
+            .add_systems(
+                Update,
+                (
+                    stepper_buttons::<NanoparticleSettings>,
+                    refresh_stepper_text::<NanoparticleSettings>,
+                    carve_button,
+                    stepper_buttons::<DefectSettings>,
+                    refresh_stepper_text::<DefectSettings>,
+                    defect_actions,
+                    stepper_buttons::<LabelSettings>,
+                    refresh_stepper_text::<LabelSettings>,
+                    update_atom_labels
+                        .after(stepper_buttons::<LabelSettings>)
+                        .after(update_crystal_system),
+                    record_streamed_frames.before(update_crystal_system),
+                    scrub_trajectory
+                        .run_if(no_text_focus)
+                        .before(update_crystal_system),
+                    refresh_trajectory_panel
+                        .after(record_streamed_frames)
+                        .after(scrub_trajectory),
+                    scrub_timeline.before(update_crystal_system),
+                    refresh_timeline
+                        .after(record_streamed_frames)
+                        .after(scrub_trajectory)
+                        .after(scrub_timeline)
+                        .after(play_trajectory),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    stepper_buttons::<Playback>,
+                    refresh_stepper_text::<Playback>,
+                    playback_controls.before(play_trajectory),
+                    play_trajectory
+                        .after(scrub_trajectory)
+                        .after(scrub_timeline)
+                        .before(update_crystal_system),
+                    refresh_play_button
+                        .after(playback_controls)
+                        .after(play_trajectory),
+                    stepper_buttons::<RmsdSettings>,
+                    refresh_stepper_text::<RmsdSettings>,
+                    rmsd_plot_clicks.before(update_crystal_system),
+                    update_rmsd
+                        .after(stepper_buttons::<RmsdSettings>)
+                        .after(record_streamed_frames)
+                        .after(scrub_trajectory)
+                        .after(scrub_timeline)
+                        .after(play_trajectory)
+                        .after(rmsd_plot_clicks),
+                    refresh_rmsd_plot.after(update_rmsd),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    focus_text_fields,
+                    text_field_input.after(focus_text_fields),
+                    refresh_text_fields
+                        .after(text_field_input)
+                        .after(apply_theme),
+                    refresh_color_labels,
+                    apply_theme,
+                    themed_button_feedback,
+                    send_selection_changed,
+                    open_file_button,
+                    capture_figures,
+                    spawn_figure_cameras.after(capture_figures),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    toggle_command_palette.after(text_field_input),
+                    command_palette_input.after(toggle_command_palette),
+                    refresh_command_palette.after(command_palette_input),
+                    toggle_help.after(text_field_input),
+                    refresh_help_overlay
+                        .after(toggle_help)
+                        .after(run_palette_actions),
+                    run_palette_actions
+                        .after(command_palette_input)
+                        .before(camera_controls)
+                        .before(focus_camera_hotkey)
+                        .before(update_crystal_system),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    receive_log_lines,
+                    clear_console_button.after(receive_log_lines),
+                    refresh_console_panel
+                        .after(clear_console_button)
+                        .after(apply_theme),
+                    scroll_console,
+                    update_loading_indicator.after(refresh_atoms_system),
+                    spin_loading_indicator.after(update_loading_indicator),
+                    expire_toasts,
+                    show_toasts.after(expire_toasts),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    switch_structure
+                        .before(focus_camera_hotkey)
+                        .before(update_crystal_system),
+                    close_structure_tab
+                        .after(switch_structure)
+                        .before(focus_camera_hotkey)
+                        .before(update_crystal_system),
+                    unmark_replaced_entry,
+                    refresh_structure_tabs
+                        .after(close_structure_tab)
+                        .after(unmark_replaced_entry),
+                ),
+            );
+
+        // depth of field needs depth textures that WebGL2 can't sample
+        #[cfg(not(all(target_arch = "wasm32", feature = "webgl2")))]
+        app.add_systems(
             Update,
-            (
-                poll_websocket_stream,
-                update_crystal_system,
-                refresh_atoms_system,
-                toggle_button,
-                reset_camera_button_interaction,
-                handle_toggle_events,
-                camera_controls,
-            ),
-        )
-        .run();
+            update_depth_of_field
+                .after(camera_controls)
+                .after(fly_camera),
+        );
+
+        // structures and commands streamed from other programs
+        #[cfg(feature = "websocket")]
+        app.init_resource::<ConnectionState>()
+            .add_event::<RpcRequest>()
+            .add_systems(
+                Startup,
+                (
+                    setup_websocket_stream,
+                    // first in the side column
+                    setup_connection_indicator
+                        .after(setup_websocket_stream)
+                        .after(setup_side_panels)
+                        .before(setup_trajectory_panel),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    poll_websocket_stream.before(record_streamed_frames),
+                    refresh_connection_indicator.after(poll_websocket_stream),
+                    send_selection,
+                    send_structure_edits.after(update_crystal_system),
+                    send_camera_pose.after(camera_controls),
+                    handle_rpc_requests
+                        .after(poll_websocket_stream)
+                        .before(camera_controls)
+                        .before(update_crystal_system),
+                    source_dropdown
+                        .after(poll_websocket_stream)
+                        .before(update_crystal_system),
+                ),
+            );
+
+        // downloads from URLs and structure databases
+        #[cfg(feature = "fetch")]
+        app.init_resource::<RemoteLoader>()
+            .add_systems(
+                Startup,
+                (
+                    setup_open_panel
+                        .after(setup_defect_panel)
+                        .after(setup_side_panels),
+                    fetch_url_argument,
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    open_url_actions.after(text_field_input),
+                    materials_project_actions.after(text_field_input),
+                    receive_downloads.before(focus_camera_hotkey),
+                ),
+            );
+        #[cfg(all(feature = "fetch", feature = "cif"))]
+        app.add_systems(Update, fetch_pdb_actions.after(text_field_input));
+
+        // rhai scripts from the console or the command line, the latter run on the opened files
+        #[cfg(feature = "scripting")]
+        {
+            let run = run_scripts
+                .after(script_console_actions)
+                .before(camera_controls)
+                .before(focus_camera_hotkey)
+                .before(update_crystal_system);
+            #[cfg(not(target_arch = "wasm32"))]
+            let run = run.after(open_files);
+            app.init_resource::<ScriptQueue>()
+                .add_systems(Startup, queue_script_arguments)
+                .add_systems(Startup, setup_script_panel.after(setup_side_panels))
+                .add_systems(
+                    Update,
+                    (script_console_actions.after(text_field_input), run),
+                );
+        }
+        #[cfg(not(feature = "scripting"))]
+        app.add_systems(Startup, reject_script_arguments);
+
+        // structure files on disk, watched for changes
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_event::<OpenFiles>()
+            .init_resource::<FileLoader>()
+            .add_systems(Startup, open_cli_files)
+            .add_systems(
+                Update,
+                (
+                    receive_picked_files,
+                    open_dropped_files,
+                    open_files,
+                    receive_loaded_files
+                        .before(focus_camera_hotkey)
+                        .before(update_crystal_system),
+                )
+                    .chain(),
+            );
+        // NEB images named on the command line, drawn together along their path
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_event::<OpenNebPath>()
+            .init_resource::<NebPath>()
+            .init_resource::<NebSettings>()
+            .add_systems(Startup, open_neb_argument)
+            .add_systems(Startup, setup_neb_panel.after(setup_label_panel))
+            .add_systems(
+                Update,
+                (
+                    open_neb_paths
+                        .after(receive_loaded_files)
+                        .before(focus_camera_hotkey)
+                        .before(update_crystal_system),
+                    stepper_buttons::<NebSettings>,
+                    refresh_stepper_text::<NebSettings>,
+                    sweep_neb_path
+                        .after(stepper_buttons::<NebSettings>)
+                        .after(open_neb_paths)
+                        .after(play_trajectory)
+                        .after(playback_controls)
+                        .before(update_crystal_system),
+                    refresh_neb_panel.after(open_neb_paths),
+                    update_neb_ghosts
+                        .after(open_neb_paths)
+                        .after(stepper_buttons::<NebSettings>)
+                        .after(apply_settings),
+                    update_neb_labels
+                        .after(open_neb_paths)
+                        .after(stepper_buttons::<NebSettings>),
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                place_neb_labels.after(TransformSystem::TransformPropagate),
+            );
+        // vibrational modes named on the command line, animated on their structure
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_event::<OpenModes>()
+            .init_resource::<Vibrations>()
+            .add_systems(Startup, open_modes_argument)
+            .add_systems(Startup, setup_vibration_panel.after(setup_neb_panel))
+            .add_systems(
+                Update,
+                (
+                    open_mode_files
+                        .after(receive_loaded_files)
+                        .before(focus_camera_hotkey)
+                        .before(update_crystal_system),
+                    stepper_buttons::<Vibrations>,
+                    refresh_stepper_text::<Vibrations>,
+                    refresh_vibration_panel.after(open_mode_files),
+                    animate_vibrations
+                        .after(open_mode_files)
+                        .after(stepper_buttons::<Vibrations>)
+                        .before(update_crystal_system),
+                    draw_vibration_arrows,
+                ),
+            );
+        // volumetric grids named on the command line and the tools showing them
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_event::<OpenVolumes>()
+            .init_resource::<Volumes>()
+            .init_resource::<SliceSettings>()
+            .init_resource::<IsosurfaceSettings>()
+            .init_resource::<RaymarchSettings>()
+            .add_plugins(VolumeRenderingPlugin)
+            .add_systems(Startup, open_volume_argument)
+            .add_systems(
+                Startup,
+                (
+                    setup_volume_panel.after(setup_vibration_panel),
+                    setup_slice_controls.after(setup_volume_panel),
+                    setup_isosurface_controls.after(setup_slice_controls),
+                    setup_raymarch_controls.after(setup_isosurface_controls),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    open_volume_files
+                        .after(receive_loaded_files)
+                        .before(focus_camera_hotkey)
+                        .before(update_crystal_system),
+                    refresh_volume_panel.after(open_volume_files),
+                    stepper_buttons::<SliceSettings>,
+                    refresh_stepper_text::<SliceSettings>.after(update_slice_plane),
+                    update_slice_plane
+                        .after(open_volume_files)
+                        .after(stepper_buttons::<SliceSettings>),
+                    stepper_buttons::<IsosurfaceSettings>,
+                    refresh_stepper_text::<IsosurfaceSettings>.after(update_isosurfaces),
+                    update_isosurfaces
+                        .after(open_volume_files)
+                        .after(stepper_buttons::<IsosurfaceSettings>),
+                    stepper_buttons::<RaymarchSettings>,
+                    refresh_stepper_text::<RaymarchSettings>.after(update_volume_rendering),
+                    refresh_transfer_preview.after(update_volume_rendering),
+                    update_volume_rendering
+                        .after(open_volume_files)
+                        .after(stepper_buttons::<RaymarchSettings>),
+                ),
+            );
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(
+            Update,
+            paste_structure
+                .run_if(no_text_focus)
+                .before(focus_camera_hotkey)
+                .before(update_crystal_system),
+        );
+        #[cfg(all(feature = "watch", not(target_arch = "wasm32")))]
+        app.init_resource::<WatchedFile>()
+            .add_systems(Update, reload_watched_file.before(receive_loaded_files));
+
+        // structures pushed or posted by the page embedding the canvas, or named in its URL
+        #[cfg(target_arch = "wasm32")]
+        {
+            let query = read_page_query
+                .after(restore_ui_state)
+                .before(setup_settings_panel);
+            #[cfg(feature = "fetch")]
+            let query = query.before(fetch_url_argument);
+            #[cfg(feature = "websocket")]
+            let query = query.before(setup_websocket_stream);
+            app.add_systems(Startup, (query, listen_for_page_messages, listen_for_paste))
+                .add_systems(
+                    Update,
+                    (
+                        (
+                            (receive_picked_files, receive_pasted_text),
+                            receive_pushed_structures
+                                .before(focus_camera_hotkey)
+                                .before(update_crystal_system),
+                        )
+                            .chain(),
+                        post_user_events.after(send_selection_changed),
+                    ),
+                )
+                .add_systems(Last, share_shown_structure);
+        }
+    }
 }
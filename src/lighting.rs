@@ -0,0 +1,191 @@
+// Lighting rigs
+// The scene is lit by directional lights hung from a pivot that follows the camera, so that the
+// structure is lit alike from every side it is looked at; detaching the light (its toggle)
+// leaves the pivot where it is while the camera orbits on. Presets trade contrast for softness:
+// a single headlight, a three-point setup (key, fill and rim light) and a soft studio of several
+// weak lights over a bright ambient. The atom shader reads these lights (see atoms.wgsl); only
+// the key light casts shadows, of the atoms and bonds alike (see instancing.rs). The preset is
+// picked in the settings panel, or with `lighting = "three-point"` in the configuration.
+
+use bevy::pbr::light_consts::lux;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::ui::{ToggleId, ToggleStates};
+
+/// Set of lights the scene is lit by.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum LightingPreset {
+    #[default]
+    Headlight,
+    ThreePoint,
+    Studio,
+}
+
+/// One light of a preset, turned away from the direction the camera looks in.
+struct RigLight {
+    /// Turn to the left, in radians; negative shines to the right.
+    yaw: f32,
+    /// Turn upwards, in radians; negative shines down.
+    pitch: f32,
+    illuminance: f32,
+    color: Color,
+    /// Whether this is the key light, the one casting shadows.
+    key: bool,
+}
+
+const HEADLIGHT: [RigLight; 1] = [RigLight {
+    yaw: 0.0,
+    pitch: 0.0,
+    illuminance: lux::AMBIENT_DAYLIGHT,
+    color: Color::WHITE,
+    key: true,
+}];
+
+const THREE_POINT: [RigLight; 3] = [
+    // key, from the upper left
+    RigLight {
+        yaw: -0.6,
+        pitch: -0.5,
+        illuminance: lux::AMBIENT_DAYLIGHT,
+        color: Color::srgb(1.0, 0.96, 0.9),
+        key: true,
+    },
+    // fill, weaker and cooler, from the right
+    RigLight {
+        yaw: 0.8,
+        pitch: -0.15,
+        illuminance: 0.35 * lux::AMBIENT_DAYLIGHT,
+        color: Color::srgb(0.85, 0.9, 1.0),
+        key: false,
+    },
+    // rim, from behind the structure, lighting its outline
+    RigLight {
+        yaw: 2.8,
+        pitch: -0.7,
+        illuminance: 0.6 * lux::AMBIENT_DAYLIGHT,
+        color: Color::WHITE,
+        key: false,
+    },
+];
+
+const STUDIO: [RigLight; 4] = [
+    RigLight {
+        yaw: -0.3,
+        pitch: -0.6,
+        illuminance: 0.5 * lux::AMBIENT_DAYLIGHT,
+        color: Color::WHITE,
+        key: true,
+    },
+    RigLight {
+        yaw: 1.0,
+        pitch: -0.2,
+        illuminance: 0.25 * lux::AMBIENT_DAYLIGHT,
+        color: Color::WHITE,
+        key: false,
+    },
+    RigLight {
+        yaw: -1.0,
+        pitch: -0.2,
+        illuminance: 0.25 * lux::AMBIENT_DAYLIGHT,
+        color: Color::WHITE,
+        key: false,
+    },
+    RigLight {
+        yaw: 0.0,
+        pitch: -1.4,
+        illuminance: 0.25 * lux::AMBIENT_DAYLIGHT,
+        color: Color::WHITE,
+        key: false,
+    },
+];
+
+impl LightingPreset {
+    /// Every preset, in the order the settings panel steps through them.
+    pub(crate) const ALL: [LightingPreset; 3] = [
+        LightingPreset::Headlight,
+        LightingPreset::ThreePoint,
+        LightingPreset::Studio,
+    ];
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            LightingPreset::Headlight => "headlight",
+            LightingPreset::ThreePoint => "three-point",
+            LightingPreset::Studio => "studio",
+        }
+    }
+
+    fn lights(self) -> &'static [RigLight] {
+        match self {
+            LightingPreset::Headlight => &HEADLIGHT,
+            LightingPreset::ThreePoint => &THREE_POINT,
+            LightingPreset::Studio => &STUDIO,
+        }
+    }
+
+    // Brightness of the ambient light under the preset
+    fn ambient(self) -> f32 {
+        match self {
+            LightingPreset::Headlight => 0.3,
+            LightingPreset::ThreePoint => 0.15,
+            LightingPreset::Studio => 0.6,
+        }
+    }
+}
+
+/// The lights of the scene: their preset, and the pivot they hang from.
+#[derive(Resource, Default)]
+pub(crate) struct LightingRig {
+    pub preset: LightingPreset,
+    /// Child of the main camera while the light is attached; set by `setup_cameras`.
+    pub pivot: Option<Entity>,
+}
+
+/// The light of a rig that casts shadows.
+#[derive(Component)]
+pub(crate) struct KeyLight;
+
+// Hang the lights of the preset from the pivot, replacing the ones of the previous preset
+pub(crate) fn apply_lighting(
+    mut commands: Commands,
+    rig: Res<LightingRig>,
+    toggle_states: Res<ToggleStates>,
+    mut ambient: ResMut<AmbientLight>,
+) {
+    if !rig.is_changed() {
+        return;
+    }
+    let Some(pivot) = rig.pivot else {
+        return;
+    };
+
+    ambient.brightness = rig.preset.ambient();
+    let shadows = toggle_states.get(ToggleId::Shadows);
+    commands
+        .entity(pivot)
+        .despawn_related::<Children>()
+        .with_children(|pivot| {
+            for light in rig.preset.lights() {
+                // a directional light shines along its local -Z, the camera's view direction
+                let mut entity = pivot.spawn((
+                    DirectionalLight {
+                        color: light.color,
+                        illuminance: light.illuminance,
+                        shadows_enabled: light.key && shadows,
+                        ..default()
+                    },
+                    Transform::from_rotation(Quat::from_euler(
+                        EulerRot::YXZ,
+                        light.yaw,
+                        light.pitch,
+                        0.0,
+                    )),
+                ));
+                if light.key {
+                    entity.insert(KeyLight);
+                }
+            }
+        });
+}
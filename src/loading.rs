@@ -0,0 +1,241 @@
+// Loading indicator
+// A line below the structure tabs follows whatever runs in the background: files being read
+// and parsed (see watch.rs), downloads (see remote.rs) and the atom chunks built afterwards. A
+// spinner shows it is alive, and a bar fills up when the amount of work is known.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+#[cfg(feature = "fetch")]
+use crate::remote::RemoteLoader;
+use crate::theme::{Themed, UiTheme};
+use crate::ui::AtomChunkQueue;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::watch::FileLoader;
+
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+const SPINNER_STEP: Duration = Duration::from_millis(120);
+const BAR_WIDTH: f32 = 160.0;
+const MB: f64 = 1024.0 * 1024.0;
+
+/// Line at the top of the screen following the background loads.
+#[derive(Component)]
+pub(crate) struct LoadingIndicator;
+
+/// Text of the `LoadingIndicator`.
+#[derive(Component)]
+pub(crate) struct LoadingText;
+
+/// Spinner in front of the `LoadingText`.
+#[derive(Component)]
+pub(crate) struct LoadingSpinner;
+
+/// Track of the progress bar, hidden when the amount of work is unknown.
+#[derive(Component)]
+pub(crate) struct LoadingBar;
+
+/// Filled part of the progress bar.
+#[derive(Component)]
+pub(crate) struct LoadingBarFill;
+
+pub(crate) fn setup_loading_indicator(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                // clear of the structure tabs
+                top: Val::Px(48.0),
+                left: Val::Px(0.0),
+                right: Val::Px(0.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            Visibility::Hidden,
+            LoadingIndicator,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    Node {
+                        padding: UiRect::axes(Val::Px(10.0), Val::Px(6.0)),
+                        align_items: AlignItems::Center,
+                        column_gap: Val::Px(8.0),
+                        ..default()
+                    },
+                    Themed::Panel,
+                ))
+                .with_children(|panel| {
+                    let font = TextFont {
+                        font_size: 13.0,
+                        ..default()
+                    };
+                    panel.spawn((
+                        Text::new(SPINNER[0].to_string()),
+                        font.clone(),
+                        Themed::Text,
+                        LoadingSpinner,
+                    ));
+                    panel.spawn((Text::new(""), font, Themed::Text, LoadingText));
+                    panel
+                        .spawn((
+                            Node {
+                                width: Val::Px(BAR_WIDTH),
+                                height: Val::Px(6.0),
+                                ..default()
+                            },
+                            Themed::Field,
+                            LoadingBar,
+                        ))
+                        .with_children(|bar| {
+                            bar.spawn((
+                                Node {
+                                    width: Val::Percent(0.0),
+                                    height: Val::Percent(100.0),
+                                    ..default()
+                                },
+                                BackgroundColor(Color::NONE),
+                                LoadingBarFill,
+                            ));
+                        });
+                });
+        });
+}
+
+fn file_name(path: &str) -> String {
+    std::path::Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
+
+// What the background file load is doing, and how much of it is done if known
+#[cfg(not(target_arch = "wasm32"))]
+fn file_status(loader: &FileLoader) -> Option<(String, Option<f32>)> {
+    let progress = loader.progress()?;
+    let name = if progress.file.is_empty() {
+        "files".to_string()
+    } else {
+        file_name(&progress.file)
+    };
+    let (mut text, done) = if progress.parsing {
+        (format!("Parsing {name}..."), None)
+    } else {
+        let done = (progress.total_bytes > 0)
+            .then(|| progress.bytes_read as f32 / progress.total_bytes as f32);
+        let text = format!(
+            "Reading {name}: {:.1} of {:.1} MB",
+            progress.bytes_read as f64 / MB,
+            progress.total_bytes as f64 / MB
+        );
+        (text, done)
+    };
+    if progress.frames > 0 {
+        text.push_str(&format!(" ({} frames so far)", progress.frames));
+    }
+    Some((text, done))
+}
+
+// What the oldest download in flight is doing, and how much of it arrived if its size is known
+#[cfg(feature = "fetch")]
+fn download_status(loader: &RemoteLoader) -> Option<(String, Option<f32>)> {
+    let mut downloads = loader.downloads();
+    let progress = downloads.next()?;
+    let name = file_name(&progress.name);
+    let (mut text, done) = if progress.parsing {
+        (format!("Parsing {name}..."), None)
+    } else {
+        let read = progress.bytes_read as f64 / MB;
+        match progress.total_bytes.filter(|&total| total > 0) {
+            Some(total) => (
+                format!(
+                    "Downloading {name}: {read:.1} of {:.1} MB",
+                    total as f64 / MB
+                ),
+                Some(progress.bytes_read as f32 / total as f32),
+            ),
+            None => (format!("Downloading {name}: {read:.1} MB"), None),
+        }
+    };
+    let more = downloads.count();
+    if more > 0 {
+        text.push_str(&format!(" (+{more} more)"));
+    }
+    Some((text, done))
+}
+
+// Show what the background loads, or the atom chunks built afterwards, are doing, and hide the
+// line once all are done
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_loading_indicator(
+    #[cfg(not(target_arch = "wasm32"))] files: Res<FileLoader>,
+    #[cfg(feature = "fetch")] downloads: Res<RemoteLoader>,
+    atom_chunks: Res<AtomChunkQueue>,
+    theme: Res<UiTheme>,
+    mut indicator: Single<&mut Visibility, With<LoadingIndicator>>,
+    mut text: Single<&mut Text, With<LoadingText>>,
+    mut bar: Single<&mut Node, (With<LoadingBar>, Without<LoadingBarFill>)>,
+    mut fill: Single<(&mut Node, &mut BackgroundColor), With<LoadingBarFill>>,
+) {
+    #[allow(unused_mut)]
+    let mut changed = atom_chunks.is_changed() || theme.is_changed();
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        changed |= files.is_changed();
+    }
+    #[cfg(feature = "fetch")]
+    {
+        changed |= downloads.is_changed();
+    }
+    if !changed {
+        return;
+    }
+
+    let status = None;
+    #[cfg(not(target_arch = "wasm32"))]
+    let status = status.or_else(|| file_status(&files));
+    #[cfg(feature = "fetch")]
+    let status = status.or_else(|| download_status(&downloads));
+    let status = status.or_else(|| {
+        atom_chunks.progress().map(|(built, total)| {
+            (
+                format!("Building atoms: {built} of {total} chunks"),
+                Some(built as f32 / total as f32),
+            )
+        })
+    });
+
+    let Some((status, done)) = status else {
+        **indicator = Visibility::Hidden;
+        return;
+    };
+    **indicator = Visibility::Inherited;
+    text.0 = status;
+    match done {
+        Some(done) => {
+            bar.display = Display::Flex;
+            let (node, color) = &mut *fill;
+            node.width = Val::Percent(done.clamp(0.0, 1.0) * 100.0);
+            color.0 = theme.positive;
+        }
+        None => bar.display = Display::None,
+    }
+}
+
+// Turn the spinner while the indicator is shown
+pub(crate) fn spin_loading_indicator(
+    time: Res<Time>,
+    indicator: Single<&Visibility, With<LoadingIndicator>>,
+    mut spinner: Single<&mut Text, With<LoadingSpinner>>,
+    mut elapsed: Local<Duration>,
+) {
+    if **indicator == Visibility::Hidden {
+        return;
+    }
+    *elapsed += time.delta();
+    let step = (elapsed.as_millis() / SPINNER_STEP.as_millis()) as usize;
+    let glyph = SPINNER[step % SPINNER.len()].to_string();
+    if spinner.0 != glyph {
+        spinner.0 = glyph;
+    }
+}
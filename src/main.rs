@@ -1,5 +1,17 @@
-use vizmat::run_app;
+use bevy::app::AppExit;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use vizmat::{run_app, Cli};
 
-fn main() {
-    run_app();
+fn main() -> AppExit {
+    let cli = Cli::parse();
+    if cli.headless && cli.command.is_none() {
+        Cli::command()
+            .error(
+                ErrorKind::MissingSubcommand,
+                "--headless needs a command, e.g. `render <FILE> -o <IMAGE>`",
+            )
+            .exit();
+    }
+    run_app(cli)
 }
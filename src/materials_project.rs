@@ -0,0 +1,202 @@
+// Materials Project lookup
+// An mp-id (mp-149) loads that material; a formula (Fe2O3) loads its most stable polymorph.
+// Requests go to the summary endpoint of the REST API with the key from
+// `materials_project_api_key` in the configuration, or else from MP_API_KEY; in the browser the
+// key comes from `?mp_api_key=` in the page URL, is stored in localStorage and is then removed
+// from the URL. The pymatgen structure in the response becomes the new crystal.
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::constants::Element;
+use crate::remote::{FetchRequest, RemoteLoader};
+use crate::structure::{Atom, AtomProperties, Crystal};
+use crate::toast::Toast;
+use crate::widgets::{entered_values, spawn_button, spawn_text_field, TextField, TextSubmitted};
+
+const SUMMARY_URL: &str = "https://api.materialsproject.org/materials/summary/";
+const SUMMARY_FIELDS: &str = "material_id,formula_pretty,structure";
+const API_KEY_ENV: &str = "MP_API_KEY";
+
+/// Text field holding the mp-id or formula to look up.
+#[derive(Component)]
+pub(crate) struct MaterialsProjectField;
+
+/// Button starting the lookup.
+#[derive(Component)]
+pub(crate) struct MaterialsProjectButton;
+
+#[derive(Deserialize)]
+struct SummaryResponse {
+    data: Vec<SummaryDoc>,
+}
+
+#[derive(Deserialize)]
+struct SummaryDoc {
+    material_id: String,
+    #[serde(default)]
+    formula_pretty: String,
+    structure: PymatgenStructure,
+}
+
+// The parts of a serialized pymatgen `Structure` needed here
+#[derive(Deserialize)]
+struct PymatgenStructure {
+    lattice: PymatgenLattice,
+    sites: Vec<PymatgenSite>,
+}
+
+#[derive(Deserialize)]
+struct PymatgenLattice {
+    // Cell vectors a, b, c as rows
+    matrix: [[f32; 3]; 3],
+    #[serde(default)]
+    pbc: Option<[bool; 3]>,
+}
+
+#[derive(Deserialize)]
+struct PymatgenSite {
+    species: Vec<PymatgenSpecies>,
+    xyz: [f32; 3],
+}
+
+#[derive(Deserialize)]
+struct PymatgenSpecies {
+    element: Element,
+    #[serde(default = "full_occupancy")]
+    occu: f32,
+}
+
+fn full_occupancy() -> f32 {
+    1.0
+}
+
+// Key from the configuration, or else from the environment
+fn api_key(config: &Config) -> Option<String> {
+    config
+        .materials_project_api_key
+        .clone()
+        .or_else(env_api_key)
+        .filter(|key| !key.trim().is_empty())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn env_api_key() -> Option<String> {
+    std::env::var(API_KEY_ENV).ok()
+}
+
+// The browser has no environment to take the key from
+#[cfg(target_arch = "wasm32")]
+fn env_api_key() -> Option<String> {
+    None
+}
+
+// Summary request for an mp-id or a formula
+fn summary_request(query: &str, config: &Config) -> anyhow::Result<FetchRequest> {
+    let Some(key) = api_key(config) else {
+        anyhow::bail!(
+            "Set materials_project_api_key in the configuration or {API_KEY_ENV} to search the \
+             Materials Project"
+        );
+    };
+    if !query
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-()".contains(c))
+    {
+        anyhow::bail!("'{query}' is neither an mp-id nor a formula");
+    }
+
+    let lowercase = query.to_ascii_lowercase();
+    let filter = if lowercase.starts_with("mp-") || lowercase.starts_with("mvc-") {
+        format!("material_ids={lowercase}")
+    } else {
+        // the ground state among the polymorphs
+        format!("formula={query}&_sort_fields=energy_above_hull&_limit=1")
+    };
+    Ok(FetchRequest {
+        name: format!("Materials Project {query}"),
+        url: format!("{SUMMARY_URL}?{filter}&_fields={SUMMARY_FIELDS}"),
+        headers: vec![("X-API-KEY", key)],
+        parse: Box::new(parse_summary),
+    })
+}
+
+// First structure of a summary response
+fn parse_summary(name: &str, contents: &str) -> anyhow::Result<Crystal> {
+    let response: SummaryResponse = serde_json::from_str(contents)?;
+    let Some(doc) = response.data.into_iter().next() else {
+        anyhow::bail!("No match for {name}");
+    };
+    info!(
+        "Materials Project: {} ({})",
+        doc.material_id, doc.formula_pretty
+    );
+
+    // disordered sites show their majority species, with its occupancy
+    let (atoms, occupancies) = doc
+        .structure
+        .sites
+        .iter()
+        .filter_map(|site| {
+            let species = site
+                .species
+                .iter()
+                .max_by(|a, b| a.occu.total_cmp(&b.occu))?;
+            Some((
+                Atom::new(species.element, Vec3::from(site.xyz)),
+                species.occu.clamp(0.0, 1.0),
+            ))
+        })
+        .unzip();
+    let lattice = &doc.structure.lattice;
+    let mut crystal = Crystal::periodic(atoms, Mat3::from_cols_array_2d(&lattice.matrix));
+    if let Some(pbc) = lattice.pbc {
+        crystal.pbc = pbc;
+    }
+    crystal
+        .properties
+        .scalars
+        .insert(AtomProperties::OCCUPANCIES.to_string(), occupancies);
+    crystal.metadata.title = Some(doc.material_id.clone());
+    crystal
+        .metadata
+        .entries
+        .insert("formula".to_string(), doc.formula_pretty);
+    Ok(crystal)
+}
+
+/// Spawns the `[mp-id or formula] [Search MP]` row of the Open panel.
+pub(crate) fn spawn_materials_project_row(parent: &mut ChildSpawnerCommands) {
+    parent
+        .spawn(Node {
+            flex_direction: FlexDirection::Row,
+            column_gap: Val::Px(4.0),
+            ..default()
+        })
+        .with_children(|row| {
+            spawn_text_field(row, "mp-149 or Fe2O3", 260.0, MaterialsProjectField);
+            spawn_button(row, "Search MP", MaterialsProjectButton);
+        });
+}
+
+// Look up the entered mp-id or formula on Enter or a click on Search MP
+pub(crate) fn materials_project_actions(
+    buttons: Query<&Interaction, (Changed<Interaction>, With<MaterialsProjectButton>)>,
+    fields: Query<&TextField, With<MaterialsProjectField>>,
+    mut submitted: EventReader<TextSubmitted>,
+    loader: Res<RemoteLoader>,
+    config: Res<Config>,
+    mut toasts: EventWriter<Toast>,
+) {
+    let pressed = buttons.iter().any(|i| *i == Interaction::Pressed);
+    for query in entered_values(&mut submitted, &fields, pressed) {
+        match summary_request(&query, &config) {
+            Ok(request) => loader.fetch(request),
+            Err(e) => {
+                error!("{e:#}");
+                toasts.write(Toast::error(format!("{e:#}")));
+            }
+        }
+    }
+}
@@ -0,0 +1,269 @@
+// Nanoparticle carving
+// The bulk crystal is replicated around the atom closest to the cell center and every atom
+// inside a sphere, or inside a polyhedron bounded by low-index facets, is kept as a finite
+// cluster.
+
+use bevy::prelude::*;
+
+use crate::structure::{Atom, Crystal, Selection};
+use crate::theme::Themed;
+use crate::ui::{ToggleId, ToggledPanel, ToolPanelRow};
+use crate::widgets::{spawn_button, spawn_stepper_row, StepperSettings};
+
+const RADIUS_STEP: f32 = 0.5;
+const MIN_RADIUS: f32 = 1.0;
+const MAX_RADIUS: f32 = 50.0;
+
+/// Outline of the carved cluster.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum ParticleShape {
+    #[default]
+    Sphere,
+    Cube,
+    Octahedron,
+    Cuboctahedron,
+}
+
+impl ParticleShape {
+    const ALL: [ParticleShape; 4] = [
+        ParticleShape::Sphere,
+        ParticleShape::Cube,
+        ParticleShape::Octahedron,
+        ParticleShape::Cuboctahedron,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            ParticleShape::Sphere => "Sphere",
+            ParticleShape::Cube => "{100}",
+            ParticleShape::Octahedron => "{111}",
+            ParticleShape::Cuboctahedron => "{100}+{111}",
+        }
+    }
+
+    // Facet families bounding the shape and their distance from the center in units of the
+    // radius; empty for the sphere
+    fn facets(self) -> &'static [(IVec3, f32)] {
+        match self {
+            ParticleShape::Sphere => &[],
+            ParticleShape::Cube => CUBE_FACETS,
+            ParticleShape::Octahedron => OCTAHEDRON_FACETS,
+            ParticleShape::Cuboctahedron => CUBOCTAHEDRON_FACETS,
+        }
+    }
+}
+
+const CUBE_FACETS: &[(IVec3, f32)] = &[(IVec3::new(1, 0, 0), 1.0)];
+const OCTAHEDRON_FACETS: &[(IVec3, f32)] = &[(IVec3::new(1, 1, 1), 1.0)];
+// {111} facets of a regular cuboctahedron sit at 2/sqrt(3) of the {100} distance
+const CUBOCTAHEDRON_FACETS: &[(IVec3, f32)] = &[
+    (IVec3::new(1, 0, 0), 1.0),
+    (IVec3::new(1, 1, 1), 1.154_700_5),
+];
+
+/// Parameters of the cluster to carve, edited in the cluster panel.
+#[derive(Resource, Clone, Copy, Debug)]
+pub(crate) struct NanoparticleSettings {
+    pub shape: ParticleShape,
+    /// Sphere radius, or distance (Å) of the first facet family from the center.
+    pub radius: f32,
+}
+
+impl Default for NanoparticleSettings {
+    fn default() -> Self {
+        Self {
+            shape: ParticleShape::Sphere,
+            radius: 8.0,
+        }
+    }
+}
+
+impl StepperSettings for NanoparticleSettings {
+    fn step(&mut self, field: usize, direction: i32) {
+        match field {
+            0 => {
+                let count = ParticleShape::ALL.len() as i32;
+                let index = ParticleShape::ALL
+                    .iter()
+                    .position(|&shape| shape == self.shape)
+                    .unwrap_or(0) as i32;
+                self.shape = ParticleShape::ALL[(index + direction).rem_euclid(count) as usize];
+            }
+            _ => {
+                self.radius =
+                    (self.radius + direction as f32 * RADIUS_STEP).clamp(MIN_RADIUS, MAX_RADIUS)
+            }
+        }
+    }
+
+    fn value_text(&self, field: usize) -> String {
+        match field {
+            0 => self.shape.label().to_string(),
+            _ => format!("{:.1} Å", self.radius),
+        }
+    }
+}
+
+/// Button that replaces the structure with the configured cluster.
+#[derive(Component)]
+pub(crate) struct CarveButton;
+
+// Every signed permutation of a Miller index. Facet families assume cubic symmetry, which is
+// where these shapes are meaningful.
+fn family(miller: IVec3) -> Vec<IVec3> {
+    let [h, k, l] = miller.to_array();
+    let mut members: Vec<IVec3> = Vec::new();
+    for [a, b, c] in [
+        [h, k, l],
+        [h, l, k],
+        [k, h, l],
+        [k, l, h],
+        [l, h, k],
+        [l, k, h],
+    ] {
+        for signs in 0..8 {
+            let flip = |value: i32, bit: i32| if signs & bit != 0 { -value } else { value };
+            let member = IVec3::new(flip(a, 1), flip(b, 2), flip(c, 4));
+            if !members.contains(&member) {
+                members.push(member);
+            }
+        }
+    }
+    members
+}
+
+// Cut a cluster of the given shape out of the replicated bulk, or None without a lattice
+pub(crate) fn carve_nanoparticle(
+    crystal: &Crystal,
+    shape: ParticleShape,
+    radius: f32,
+) -> Option<Crystal> {
+    let lattice = crystal.lattice?;
+    let inverse = lattice.inverse();
+
+    // Plane normals of the facets (rows of the inverse lattice) with their distances
+    let reciprocal = inverse.transpose();
+    let planes: Vec<(Vec3, f32)> = shape
+        .facets()
+        .iter()
+        .flat_map(|&(miller, ratio)| {
+            family(miller)
+                .into_iter()
+                .map(move |m| ((reciprocal * m.as_vec3()).normalize(), ratio * radius))
+        })
+        .collect();
+    // Largest distance from the center an atom inside the shape can have
+    let extent = planes
+        .iter()
+        .map(|&(_, distance)| distance)
+        .fold(radius, f32::max)
+        * if planes.is_empty() { 1.0 } else { 3f32.sqrt() };
+
+    // Center on the atom closest to the middle of the cell, so the cluster is atom-centered
+    let middle = lattice * Vec3::splat(0.5);
+    let center = crystal
+        .atoms
+        .iter()
+        .map(Atom::position)
+        .min_by(|a, b| a.distance(middle).total_cmp(&b.distance(middle)))?;
+
+    // Number of cells to replicate along each axis to cover the shape
+    let spacings = [
+        lattice.determinant().abs() / lattice.y_axis.cross(lattice.z_axis).length(),
+        lattice.determinant().abs() / lattice.x_axis.cross(lattice.z_axis).length(),
+        lattice.determinant().abs() / lattice.x_axis.cross(lattice.y_axis).length(),
+    ];
+    let reach = spacings.map(|spacing| (extent / spacing).ceil() as i32 + 1);
+
+    let center_cell = (inverse * center).floor();
+    let mut atoms = Vec::new();
+    for atom in &crystal.atoms {
+        let frac = inverse * atom.position();
+        let frac = frac - frac.floor() + center_cell;
+        for i in -reach[0]..=reach[0] {
+            for j in -reach[1]..=reach[1] {
+                for k in -reach[2]..=reach[2] {
+                    let position = lattice * (frac + Vec3::new(i as f32, j as f32, k as f32));
+                    let offset = position - center;
+                    let inside = if planes.is_empty() {
+                        offset.length() <= radius
+                    } else {
+                        planes
+                            .iter()
+                            .all(|&(normal, distance)| offset.dot(normal) <= distance + 1e-4)
+                    };
+                    if inside {
+                        atoms.push(Atom {
+                            x: offset.x,
+                            y: offset.y,
+                            z: offset.z,
+                            ..atom.clone()
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    Some(Crystal::molecule(atoms))
+}
+
+// Spawn the (hidden) cluster panel in the tool row
+pub(crate) fn setup_nanoparticle_panel(
+    mut commands: Commands,
+    row: Single<Entity, With<ToolPanelRow>>,
+    settings: Res<NanoparticleSettings>,
+) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            ToggledPanel(ToggleId::NanoparticleTool),
+            ChildOf(*row),
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                Text::new("Nanoparticle"),
+                TextFont {
+                    font: default(),
+                    font_size: 14.0,
+                    ..default()
+                },
+                Themed::Text,
+            ));
+            spawn_stepper_row(panel, "Shape", 0, &*settings);
+            spawn_stepper_row(panel, "Radius", 1, &*settings);
+            spawn_button(panel, "Carve Cluster", CarveButton);
+        });
+}
+
+// Replace the structure with the configured cluster
+pub(crate) fn carve_button(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<CarveButton>)>,
+    settings: Res<NanoparticleSettings>,
+    mut crystal: ResMut<Crystal>,
+    mut selection: ResMut<Selection>,
+) {
+    for interaction in &interactions {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some(cluster) = carve_nanoparticle(&crystal, settings.shape, settings.radius) else {
+            warn!("Nanoparticle carving needs a periodic structure");
+            continue;
+        };
+        info!(
+            "Carved {} cluster with {} atoms",
+            settings.shape.label(),
+            cluster.atoms.len()
+        );
+        selection.atoms.clear();
+        *crystal = cluster;
+    }
+}
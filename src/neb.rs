@@ -0,0 +1,590 @@
+// NEB paths
+// `vizmat --neb run/` opens the images of a nudged elastic band calculation: a directory of
+// numbered image directories (00, 01, ...) as VASP writes them, each read from its CONTCAR, or
+// its POSCAR for the fixed end points, or a file holding one frame per image. Image energies
+// come from the last E0 of each OSZICAR (or the OUTCAR), or from the `energy=` key of extended
+// XYZ frames.
+// The images are loaded as a trajectory, so they can be stepped through and played like any,
+// with their energies along the timeline. On top of that, the atoms that move along the path
+// are drawn in every image at once as translucent ghosts, each image is labeled with its energy
+// relative to the first, and the sweep moves the atoms back and forth along the whole path,
+// gliding between the images. The NEB panel in the side column turns these on and off; it is
+// shown while the path is the trajectory on screen.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use bevy::prelude::*;
+
+use crate::cli::Cli;
+use crate::color::{ColorScheme, ElementOverrides};
+use crate::constants::Element;
+use crate::events::{FrameChanged, StructureLoaded};
+use crate::parse::{parse_frames, parse_structure_as, parse_xyz_energies, Format};
+use crate::structure::{Crystal, Selection, UpdateStructure};
+use crate::structure_list::{tab_name, StructureList};
+use crate::theme::Themed;
+use crate::toast::Toast;
+use crate::trajectory::{interpolate, Playback, Trajectory};
+use crate::ui::{AtomScale, FitView, MainCamera, SidePanelColumn};
+use crate::widgets::{on_off, spawn_stepper_row, StepperSettings};
+
+/// Distance an atom has to move from the first image, in Å, to be drawn as a ghost.
+const MOVING_DISTANCE: f32 = 0.1;
+/// Size of the ghosts relative to the atoms, and their opacity.
+const GHOST_SCALE: f32 = 0.8;
+const GHOST_ALPHA: f32 = 0.3;
+/// Subdivisions of the ghost spheres.
+const GHOST_SUBDIVISIONS: u32 = 3;
+/// Ghosts above which none are drawn; each is an entity.
+const MAX_GHOSTS: usize = 20_000;
+/// Seconds the sweep takes from the first image to the last.
+const SWEEP_SECONDS: f32 = 4.0;
+/// Gap between an energy label and the atoms it labels, in logical pixels.
+const LABEL_GAP: f32 = 8.0;
+
+/// Request to open the images of a NEB calculation.
+#[derive(Event, Clone)]
+pub(crate) struct OpenNebPath {
+    pub path: PathBuf,
+    /// Format of the image files; picked from their names when None.
+    pub format: Option<Format>,
+}
+
+/// An atom that moves along the path, with its position in every image.
+struct MovingAtom {
+    element: Element,
+    positions: Vec<Vec3>,
+}
+
+/// The NEB path on screen, if any.
+#[derive(Resource, Default)]
+pub(crate) struct NebPath {
+    /// Revision of the trajectory the images were loaded as; the path is shown while the
+    /// trajectory still holds them.
+    revision: Option<u64>,
+    /// Energy of every image, if known.
+    energies: Vec<Option<f64>>,
+    moving: Vec<MovingAtom>,
+}
+
+impl NebPath {
+    // Path through the frames of `trajectory`; atoms are followed through periodic boundaries
+    fn new(trajectory: &Trajectory, energies: Vec<Option<f64>>) -> Self {
+        let images: Option<Vec<Vec<Vec3>>> = (0..trajectory.len())
+            .map(|position| trajectory.positions_near_first(position))
+            .collect();
+        let mut moving = Vec::new();
+        match (images, trajectory.structure(0)) {
+            (Some(images), Some(first)) => {
+                for (index, atom) in first.atoms.iter().enumerate() {
+                    let positions: Vec<Vec3> = images.iter().map(|image| image[index]).collect();
+                    if positions
+                        .iter()
+                        .any(|position| position.distance(positions[0]) > MOVING_DISTANCE)
+                    {
+                        moving.push(MovingAtom {
+                            element: atom.element,
+                            positions,
+                        });
+                    }
+                }
+            }
+            _ => warn!("The NEB images do not hold the same atoms, not drawing ghosts"),
+        }
+        Self {
+            revision: Some(trajectory.revision()),
+            energies,
+            moving,
+        }
+    }
+
+    fn is_shown(&self, trajectory: &Trajectory) -> bool {
+        self.revision == Some(trajectory.revision())
+    }
+
+    // Energy of every image relative to the first one with an energy
+    fn relative_energies(&self) -> Vec<Option<f64>> {
+        let reference = self.energies.iter().flatten().next().copied();
+        self.energies
+            .iter()
+            .map(|energy| Some(energy.as_ref()? - reference?))
+            .collect()
+    }
+
+    // Image with the highest relative energy, and that energy
+    fn barrier(&self) -> Option<(usize, f64)> {
+        self.relative_energies()
+            .into_iter()
+            .enumerate()
+            .filter_map(|(image, energy)| Some((image, energy?)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    // Mean position of the moving atoms in `image`
+    fn center(&self, image: usize) -> Option<Vec3> {
+        let positions = self
+            .moving
+            .iter()
+            .filter_map(|atom| atom.positions.get(image));
+        let count = positions.clone().count();
+        (count > 0).then(|| positions.sum::<Vec3>() / count as f32)
+    }
+}
+
+/// NEB display settings shown in the NEB panel.
+#[derive(Resource)]
+pub(crate) struct NebSettings {
+    pub ghosts: bool,
+    pub labels: bool,
+    /// Whether the atoms move back and forth along the path.
+    pub sweep: bool,
+}
+
+impl Default for NebSettings {
+    fn default() -> Self {
+        Self {
+            ghosts: true,
+            labels: true,
+            sweep: false,
+        }
+    }
+}
+
+impl StepperSettings for NebSettings {
+    fn step(&mut self, field: usize, direction: i32) {
+        match field {
+            0 => self.ghosts = direction > 0,
+            1 => self.labels = direction > 0,
+            _ => self.sweep = direction > 0,
+        }
+    }
+
+    fn value_text(&self, field: usize) -> String {
+        match field {
+            0 => on_off(self.ghosts),
+            1 => on_off(self.labels),
+            _ => on_off(self.sweep),
+        }
+    }
+}
+
+/// Root node of the NEB panel, shown while a path is on screen.
+#[derive(Component)]
+pub(crate) struct NebPanel;
+
+/// Text describing the path.
+#[derive(Component)]
+pub(crate) struct NebText;
+
+/// Translucent atom of an image.
+#[derive(Component)]
+pub(crate) struct NebGhost;
+
+/// Energy label of the image with this index.
+#[derive(Component)]
+pub(crate) struct NebLabel(usize);
+
+// Energy of a VASP image: the last E0 of its OSZICAR, or else the last energy(sigma->0) of
+// its OUTCAR
+fn image_energy(image: &Path) -> Option<f64> {
+    let last_value = |file: &str, key: &str| {
+        let contents = fs::read_to_string(image.join(file)).ok()?;
+        contents.lines().rev().find_map(|line| {
+            let (_, value) = line.split_once(key)?;
+            value.split_whitespace().next()?.parse().ok()
+        })
+    };
+    last_value("OSZICAR", "E0=").or_else(|| last_value("OUTCAR", "energy(sigma->0) ="))
+}
+
+// Images and energies of the numbered image directories in `directory`, in order
+fn read_image_directories(
+    directory: &Path,
+    format: Option<Format>,
+) -> Result<(Vec<Crystal>, Vec<Option<f64>>)> {
+    let failed = || format!("Failed to read {}", directory.display());
+    let mut images: Vec<(usize, PathBuf)> = fs::read_dir(directory)
+        .with_context(failed)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let number = entry.file_name().to_str()?.parse().ok()?;
+            entry.path().is_dir().then(|| (number, entry.path()))
+        })
+        .collect();
+    images.sort();
+    ensure!(
+        !images.is_empty(),
+        "{} holds no numbered image directories (00, 01, ...)",
+        directory.display()
+    );
+
+    let mut crystals = Vec::with_capacity(images.len());
+    let mut energies = Vec::with_capacity(images.len());
+    for (_, image) in images {
+        // relaxed images have a CONTCAR, the fixed end points only their POSCAR
+        let file = ["CONTCAR", "POSCAR"]
+            .iter()
+            .map(|name| image.join(name))
+            .find(|file| fs::metadata(file).is_ok_and(|metadata| metadata.len() > 0))
+            .with_context(|| format!("{} has no POSCAR or CONTCAR", image.display()))?;
+        let contents = fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        let name = file.to_string_lossy();
+        crystals.push(parse_structure_as(
+            &name,
+            &contents,
+            format.or(Some(Format::Poscar)),
+        )?);
+        energies.push(image_energy(&image));
+    }
+    Ok((crystals, energies))
+}
+
+// Images of a NEB calculation and their energies, from a directory of image directories or a
+// file with a frame per image
+fn read_images(path: &Path, format: Option<Format>) -> Result<(Vec<Crystal>, Vec<Option<f64>>)> {
+    if path.is_dir() {
+        return read_image_directories(path, format);
+    }
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let name = path.to_string_lossy();
+    let images = parse_frames(&name, &contents, format)?;
+    let format = format
+        .or_else(|| Format::from_name(&name))
+        .unwrap_or_else(|| Format::sniff(&contents));
+    let mut energies = match format {
+        Format::Xyz => parse_xyz_energies(&contents),
+        _ => Vec::new(),
+    };
+    energies.resize(images.len(), None);
+    Ok((images, energies))
+}
+
+// Open the NEB path named on the command line
+pub(crate) fn open_neb_argument(cli: Res<Cli>, mut requests: EventWriter<OpenNebPath>) {
+    if let Some(path) = &cli.neb {
+        requests.write(OpenNebPath {
+            path: path.clone(),
+            format: cli.format,
+        });
+    }
+}
+
+// Load requested NEB paths as the trajectory on screen, in a tab of their own; the images are
+// few and small, so they are read right away
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn open_neb_paths(
+    mut requests: EventReader<OpenNebPath>,
+    mut crystal: ResMut<Crystal>,
+    mut selection: ResMut<Selection>,
+    mut trajectory: ResMut<Trajectory>,
+    mut list: ResMut<StructureList>,
+    mut path: ResMut<NebPath>,
+    mut fit: EventWriter<FitView>,
+    mut loaded: EventWriter<StructureLoaded>,
+    mut toasts: EventWriter<Toast>,
+) {
+    for request in requests.read() {
+        let (images, energies) = match read_images(&request.path, request.format) {
+            Ok(read) => read,
+            Err(e) => {
+                error!("{e:#}");
+                toasts.write(Toast::error(format!("{e:#}")));
+                continue;
+            }
+        };
+        let source = request.path.display().to_string();
+        info!("Loaded {} NEB images from {source}", images.len());
+        loaded.write(StructureLoaded {
+            source: source.clone(),
+            atom_count: images[0].atoms.len(),
+            frame_count: images.len(),
+        });
+        selection.atoms.clear();
+        *crystal = images[0].clone();
+        trajectory.clear();
+        trajectory.load(images.iter().cloned().map(Into::into).collect(), 0);
+        trajectory.set_energies(&energies);
+        *path = NebPath::new(&trajectory, energies);
+        list.open(source.clone(), vec![(tab_name(&source), images)], 0);
+        fit.write(FitView);
+    }
+}
+
+// Spawn the (hidden) NEB panel in the side column
+pub(crate) fn setup_neb_panel(
+    mut commands: Commands,
+    column: Single<Entity, With<SidePanelColumn>>,
+    settings: Res<NebSettings>,
+) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            NebPanel,
+            ChildOf(*column),
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                Text::new("NEB path"),
+                TextFont {
+                    font: default(),
+                    font_size: 14.0,
+                    ..default()
+                },
+                Themed::Text,
+            ));
+            panel.spawn((
+                Text::new(""),
+                TextFont {
+                    font: default(),
+                    font_size: 12.0,
+                    ..default()
+                },
+                Themed::Text,
+                NebText,
+            ));
+            for (field, label) in ["Ghosts", "Energies", "Sweep"].into_iter().enumerate() {
+                spawn_stepper_row(panel, label, field, &*settings);
+            }
+        });
+}
+
+// Show the NEB panel while the path is on screen, with the image count and the barrier
+pub(crate) fn refresh_neb_panel(
+    path: Res<NebPath>,
+    trajectory: Res<Trajectory>,
+    mut panels: Query<&mut Node, With<NebPanel>>,
+    mut texts: Query<&mut Text, With<NebText>>,
+) {
+    if !path.is_changed() && !trajectory.is_changed() {
+        return;
+    }
+    let shown = path.is_shown(&trajectory);
+    for mut node in &mut panels {
+        node.display = if shown { Display::Flex } else { Display::None };
+    }
+    if !path.is_changed() {
+        return;
+    }
+    let mut text = format!(
+        "{} images, {} moving atoms",
+        path.energies.len(),
+        path.moving.len()
+    );
+    if let Some((image, barrier)) = path.barrier() {
+        text.push_str(&format!("\nBarrier {barrier:+.3} eV at image {image}"));
+    }
+    for mut content in &mut texts {
+        content.0 = text.clone();
+    }
+}
+
+// Move the atoms back and forth along the path while the sweep is on, gliding between the
+// images; playing the trajectory takes over from the sweep
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn sweep_neb_path(
+    time: Res<Time>,
+    settings: Res<NebSettings>,
+    path: Res<NebPath>,
+    mut playback: ResMut<Playback>,
+    mut trajectory: ResMut<Trajectory>,
+    mut updates: EventWriter<UpdateStructure>,
+    mut frame_changes: EventWriter<FrameChanged>,
+    // progress through a sweep there and back, from 0 to 2
+    mut phase: Local<f32>,
+) {
+    if settings.is_changed() && settings.sweep {
+        playback.playing = false;
+    }
+    let last = trajectory.last();
+    if !settings.sweep || playback.playing || !path.is_shown(&trajectory) || last == 0 {
+        // put back the image on screen in place of the atoms between images
+        if settings.is_changed() && !settings.sweep && path.is_shown(&trajectory) {
+            updates.write_batch(
+                trajectory
+                    .structure(trajectory.position())
+                    .map(Cow::into_owned),
+            );
+        }
+        *phase = 0.0;
+        return;
+    }
+    *phase = (*phase + time.delta_secs() / SWEEP_SECONDS) % 2.0;
+    let along = if *phase < 1.0 { *phase } else { 2.0 - *phase } * last as f32;
+    trajectory.show(
+        (along.round() as usize).min(last),
+        &mut updates,
+        &mut frame_changes,
+    );
+    let from = (along.floor() as usize).min(last - 1);
+    if let (Some(a), Some(b)) = (trajectory.structure(from), trajectory.structure(from + 1)) {
+        updates.write_batch(interpolate(&a, &b, along - from as f32));
+    }
+}
+
+// Draw the moving atoms of every image as translucent ghosts while the path is on screen; the
+// ghosts are rebuilt when the path, the settings, the atom size or the colors change
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_neb_ghosts(
+    mut commands: Commands,
+    path: Res<NebPath>,
+    settings: Res<NebSettings>,
+    trajectory: Res<Trajectory>,
+    scale: Res<AtomScale>,
+    color_scheme: Res<ColorScheme>,
+    overrides: Res<ElementOverrides>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    ghosts: Query<Entity, With<NebGhost>>,
+    mut sphere: Local<Option<Handle<Mesh>>>,
+    mut colors: Local<HashMap<Element, Handle<StandardMaterial>>>,
+    mut drawn: Local<bool>,
+) {
+    let visible = settings.ghosts && path.is_shown(&trajectory);
+    let restyled = color_scheme.is_changed() || overrides.is_changed();
+    if visible == *drawn
+        && !path.is_changed()
+        && !settings.is_changed()
+        && !scale.is_changed()
+        && !restyled
+    {
+        return;
+    }
+    *drawn = visible;
+    if restyled {
+        colors.clear();
+    }
+    for entity in &ghosts {
+        commands.entity(entity).despawn();
+    }
+    if !visible {
+        return;
+    }
+
+    let shown: Vec<&MovingAtom> = path
+        .moving
+        .iter()
+        .filter(|atom| !overrides.is_hidden(atom.element))
+        .collect();
+    let count: usize = shown.iter().map(|atom| atom.positions.len()).sum();
+    if count > MAX_GHOSTS {
+        warn_once!("Not drawing {count} NEB ghosts, more than {MAX_GHOSTS}");
+        return;
+    }
+    let mesh = sphere
+        .get_or_insert_with(|| {
+            meshes.add(
+                Sphere::new(1.0)
+                    .mesh()
+                    .ico(GHOST_SUBDIVISIONS)
+                    .expect("subdivisions are below the icosphere limit"),
+            )
+        })
+        .clone();
+    for atom in shown {
+        let material = colors
+            .entry(atom.element)
+            .or_insert_with_key(|&element| {
+                materials.add(StandardMaterial {
+                    base_color: overrides
+                        .color(*color_scheme, element)
+                        .with_alpha(GHOST_ALPHA),
+                    alpha_mode: AlphaMode::Blend,
+                    perceptual_roughness: 0.6,
+                    ..default()
+                })
+            })
+            .clone();
+        let radius = overrides.size(atom.element) * scale.0 * GHOST_SCALE;
+        for &position in &atom.positions {
+            commands.spawn((
+                Mesh3d(mesh.clone()),
+                MeshMaterial3d(material.clone()),
+                Transform::from_translation(position).with_scale(Vec3::splat(radius)),
+                NebGhost,
+            ));
+        }
+    }
+}
+
+// Label every image with its energy while the path is on screen and labels are on
+pub(crate) fn update_neb_labels(
+    mut commands: Commands,
+    path: Res<NebPath>,
+    settings: Res<NebSettings>,
+    trajectory: Res<Trajectory>,
+    labels: Query<Entity, With<NebLabel>>,
+    mut drawn: Local<bool>,
+) {
+    let visible = settings.labels && path.is_shown(&trajectory);
+    if visible == *drawn && !path.is_changed() && !settings.is_changed() {
+        return;
+    }
+    *drawn = visible;
+    for entity in &labels {
+        commands.entity(entity).despawn();
+    }
+    if !visible {
+        return;
+    }
+    for (image, energy) in path.relative_energies().into_iter().enumerate() {
+        let text = match energy {
+            Some(energy) => format!("{image}: {energy:+.2} eV"),
+            None => image.to_string(),
+        };
+        commands.spawn((
+            Text::new(text),
+            TextFont {
+                font: default(),
+                font_size: 12.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+            TextShadow {
+                offset: Vec2::ONE,
+                color: Color::BLACK,
+            },
+            Node {
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            // placed by `place_neb_labels` before it is first drawn
+            Visibility::Hidden,
+            NebLabel(image),
+        ));
+    }
+}
+
+// Place each energy label just above the moving atoms of its image, as seen by the main camera
+pub(crate) fn place_neb_labels(
+    path: Res<NebPath>,
+    camera: Single<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut labels: Query<(&NebLabel, &ComputedNode, &mut Node, &mut Visibility)>,
+) {
+    let (camera, camera_transform) = *camera;
+    for (label, computed, mut node, mut visibility) in &mut labels {
+        let position = path
+            .center(label.0)
+            .and_then(|center| camera.world_to_viewport(camera_transform, center).ok());
+        let Some(position) = position else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *visibility = Visibility::Inherited;
+        let size = computed.size() * computed.inverse_scale_factor();
+        node.left = Val::Px(position.x - size.x / 2.0);
+        node.top = Val::Px(position.y - size.y - LABEL_GAP);
+    }
+}
@@ -0,0 +1,339 @@
+// Neighbor search with linked cell lists
+// Atoms are binned into a grid of cells at least `cutoff` wide, so each atom only has to be
+// compared against atoms in the surrounding cells. Periodic axes wrap around, giving the
+// lattice image each neighbor was found in.
+
+use bevy::math::{IVec3, Mat3, Vec3};
+
+use crate::structure::{Atom, Crystal};
+
+/// Upper bound on grid cells per atom, so sparse or very elongated systems don't allocate huge grids.
+const MAX_CELLS_PER_ATOM: usize = 4;
+
+/// Marks the end of a linked cell list.
+const END: usize = usize::MAX;
+
+/// One neighbor of an atom.
+#[derive(Debug, Clone, Copy)]
+pub struct Neighbor {
+    /// Index of the neighboring atom.
+    pub index: usize,
+    /// Lattice translation (in units of the lattice vectors) applied to the neighbor.
+    pub image: IVec3,
+    /// Vector from the atom to the (translated) neighbor.
+    pub vector: Vec3,
+    pub distance: f32,
+}
+
+/// Every pair of atoms closer than a cutoff, stored per atom in both directions.
+#[derive(Debug, Clone, Default)]
+pub struct NeighborList {
+    neighbors: Vec<Vec<Neighbor>>,
+}
+
+impl NeighborList {
+    /// Builds the list for the atoms of `crystal`, across its periodic boundaries.
+    pub fn from_crystal(crystal: &Crystal, cutoff: f32) -> Self {
+        let positions: Vec<Vec3> = crystal.atoms.iter().map(Atom::position).collect();
+        match crystal.periodicity() {
+            Some((lattice, pbc)) => Self::build(&positions, cutoff, Some(lattice), pbc),
+            None => Self::build(&positions, cutoff, None, [false; 3]),
+        }
+    }
+
+    /// Builds the list for `positions`. When a `lattice` (lattice vectors as columns) is given,
+    /// axes flagged in `pbc` are treated as periodic.
+    pub fn build(positions: &[Vec3], cutoff: f32, lattice: Option<Mat3>, pbc: [bool; 3]) -> Self {
+        let mut neighbors = vec![Vec::new(); positions.len()];
+        if positions.is_empty() || cutoff.is_nan() || cutoff <= 0.0 {
+            return Self { neighbors };
+        }
+
+        let grid = CellGrid::new(positions, cutoff, lattice, pbc);
+        let cutoff_squared = cutoff * cutoff;
+        let [rx, ry, rz] = grid.reach;
+
+        for (i, atom_neighbors) in neighbors.iter_mut().enumerate() {
+            let bin = grid.bins[i];
+            for dx in -rx..=rx {
+                for dy in -ry..=ry {
+                    for dz in -rz..=rz {
+                        let Some((cell, image)) = grid.offset_cell(bin, IVec3::new(dx, dy, dz))
+                        else {
+                            continue;
+                        };
+                        let shift = grid.matrix * image.as_vec3();
+
+                        let mut j = grid.head[cell];
+                        while j != END {
+                            if j != i || image != IVec3::ZERO {
+                                let vector = grid.positions[j] + shift - grid.positions[i];
+                                let distance_squared = vector.length_squared();
+                                if distance_squared <= cutoff_squared {
+                                    atom_neighbors.push(Neighbor {
+                                        index: j,
+                                        image: image + grid.wraps[j] - grid.wraps[i],
+                                        vector,
+                                        distance: distance_squared.sqrt(),
+                                    });
+                                }
+                            }
+                            j = grid.next[j];
+                        }
+                    }
+                }
+            }
+        }
+
+        Self { neighbors }
+    }
+
+    /// Neighbors of atom `index`.
+    pub fn neighbors(&self, index: usize) -> &[Neighbor] {
+        self.neighbors.get(index).map_or(&[], Vec::as_slice)
+    }
+
+    /// Each neighboring pair exactly once, as `(i, neighbor)` with `i <= neighbor.index`.
+    pub fn pairs(&self) -> impl Iterator<Item = (usize, &Neighbor)> {
+        self.neighbors.iter().enumerate().flat_map(|(i, list)| {
+            list.iter()
+                .filter(move |n| {
+                    n.index > i || (n.index == i && (n.image.x, n.image.y, n.image.z) > (0, 0, 0))
+                })
+                .map(move |n| (i, n))
+        })
+    }
+}
+
+/// Grid of cells over the structure (fractional space when a lattice is given) with the
+/// atoms threaded through per-cell linked lists.
+struct CellGrid {
+    matrix: Mat3,
+    periodic: [bool; 3],
+    dims: IVec3,
+    reach: [i32; 3],
+    /// Positions with periodic coordinates wrapped into the cell.
+    positions: Vec<Vec3>,
+    /// Lattice translation that was applied to each atom when wrapping.
+    wraps: Vec<IVec3>,
+    bins: Vec<IVec3>,
+    head: Vec<usize>,
+    next: Vec<usize>,
+}
+
+impl CellGrid {
+    fn new(positions: &[Vec3], cutoff: f32, lattice: Option<Mat3>, pbc: [bool; 3]) -> Self {
+        let (matrix, periodic) = match lattice {
+            Some(matrix) if matrix.determinant().abs() > f32::EPSILON => (matrix, pbc),
+            _ => (Mat3::IDENTITY, [false; 3]),
+        };
+        let inverse = matrix.inverse();
+
+        let mut wrapped = Vec::with_capacity(positions.len());
+        let mut wraps = Vec::with_capacity(positions.len());
+        let mut fractional = Vec::with_capacity(positions.len());
+        for &position in positions {
+            let mut frac = inverse * position;
+            let mut wrap = IVec3::ZERO;
+            for axis in 0..3 {
+                if periodic[axis] {
+                    let shift = -frac[axis].floor();
+                    frac[axis] += shift;
+                    wrap[axis] = shift as i32;
+                }
+            }
+            wrapped.push(position + matrix * wrap.as_vec3());
+            wraps.push(wrap);
+            fractional.push(frac);
+        }
+
+        // Binned range per axis: the unit cell when periodic, the occupied extent otherwise
+        let mut lo = Vec3::ZERO;
+        let mut span = Vec3::ONE;
+        for axis in 0..3 {
+            if !periodic[axis] {
+                let (min, max) = fractional
+                    .iter()
+                    .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), f| {
+                        (min.min(f[axis]), max.max(f[axis]))
+                    });
+                lo[axis] = min;
+                span[axis] = (max - min).max(f32::EPSILON);
+            }
+        }
+
+        // Perpendicular width of the binned region along each axis
+        let cols = [matrix.x_axis, matrix.y_axis, matrix.z_axis];
+        let volume = matrix.determinant().abs();
+        let width = Vec3::from_array(std::array::from_fn(|axis| {
+            let face = cols[(axis + 1) % 3].cross(cols[(axis + 2) % 3]).length();
+            volume / face * span[axis]
+        }));
+
+        let mut dims = Vec3::from_array(std::array::from_fn(|axis| {
+            (width[axis] / cutoff).floor().max(1.0)
+        }));
+        let limit = (positions.len() * MAX_CELLS_PER_ATOM).max(27) as f32;
+        let total = dims.x * dims.y * dims.z;
+        if total > limit {
+            dims = (dims * (limit / total).cbrt()).floor().max(Vec3::ONE);
+        }
+        let dims = dims.as_ivec3();
+
+        let reach = std::array::from_fn(|axis| {
+            let reach = (cutoff / (width[axis] / dims[axis] as f32)).ceil() as i32;
+            if periodic[axis] {
+                reach
+            } else {
+                // Cells past the occupied extent are empty
+                reach.min(dims[axis] - 1)
+            }
+        });
+
+        let mut head = vec![END; (dims.x * dims.y * dims.z) as usize];
+        let mut next = vec![END; positions.len()];
+        let mut bins = Vec::with_capacity(positions.len());
+        for (i, frac) in fractional.iter().enumerate() {
+            let bin = IVec3::from_array(std::array::from_fn(|axis| {
+                let t = (frac[axis] - lo[axis]) / span[axis];
+                ((t * dims[axis] as f32) as i32).clamp(0, dims[axis] - 1)
+            }));
+            let cell = Self::cell_index(dims, bin);
+            next[i] = head[cell];
+            head[cell] = i;
+            bins.push(bin);
+        }
+
+        Self {
+            matrix,
+            periodic,
+            dims,
+            reach,
+            positions: wrapped,
+            wraps,
+            bins,
+            head,
+            next,
+        }
+    }
+
+    fn cell_index(dims: IVec3, bin: IVec3) -> usize {
+        ((bin.x * dims.y + bin.y) * dims.z + bin.z) as usize
+    }
+
+    /// Cell at `bin + offset` and the lattice image it lies in, or `None` when it falls off a
+    /// non-periodic edge.
+    fn offset_cell(&self, bin: IVec3, offset: IVec3) -> Option<(usize, IVec3)> {
+        let mut target = bin + offset;
+        let mut image = IVec3::ZERO;
+        for axis in 0..3 {
+            let n = self.dims[axis];
+            if self.periodic[axis] {
+                image[axis] = target[axis].div_euclid(n);
+                target[axis] = target[axis].rem_euclid(n);
+            } else if target[axis] < 0 || target[axis] >= n {
+                return None;
+            }
+        }
+        Some((Self::cell_index(self.dims, target), image))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // fcc and bcc lattice constant, Å
+    const A: f32 = 3.6;
+
+    // The cubic cell as a, a + b, c, which holds the same lattice in a skewed cell
+    fn skewed_cell() -> Mat3 {
+        Mat3::from_cols(Vec3::X * A, Vec3::new(A, A, 0.0), Vec3::Z * A)
+    }
+
+    fn fcc_sites() -> Vec<Vec3> {
+        [
+            [0.0, 0.0, 0.0],
+            [0.5, 0.5, 0.0],
+            [0.5, 0.0, 0.5],
+            [0.0, 0.5, 0.5],
+        ]
+        .map(|site| Vec3::from(site) * A)
+        .to_vec()
+    }
+
+    fn bcc_sites() -> Vec<Vec3> {
+        [[0.0, 0.0, 0.0], [0.5, 0.5, 0.5]]
+            .map(|site| Vec3::from(site) * A)
+            .to_vec()
+    }
+
+    // Neighbors of every atom within `cutoff`, over every image close enough to matter
+    fn brute_force_counts(positions: &[Vec3], lattice: Mat3, cutoff: f32) -> Vec<usize> {
+        let reach = 3;
+        positions
+            .iter()
+            .enumerate()
+            .map(|(i, a)| {
+                let mut count = 0;
+                for (j, b) in positions.iter().enumerate() {
+                    for x in -reach..=reach {
+                        for y in -reach..=reach {
+                            for z in -reach..=reach {
+                                let image = IVec3::new(x, y, z);
+                                if i == j && image == IVec3::ZERO {
+                                    continue;
+                                }
+                                let shifted = *b + lattice * image.as_vec3();
+                                if a.distance(shifted) <= cutoff {
+                                    count += 1;
+                                }
+                            }
+                        }
+                    }
+                }
+                count
+            })
+            .collect()
+    }
+
+    fn counts(positions: &[Vec3], lattice: Mat3, cutoff: f32) -> Vec<usize> {
+        let list = NeighborList::build(positions, cutoff, Some(lattice), [true; 3]);
+        (0..positions.len())
+            .map(|i| list.neighbors(i).len())
+            .collect()
+    }
+
+    #[test]
+    fn fcc_in_skewed_cell_matches_brute_force() {
+        // the first two shells: 12 at a/√2 and 6 at a
+        let cutoff = 1.1 * A;
+        let positions = fcc_sites();
+        let expected = brute_force_counts(&positions, skewed_cell(), cutoff);
+        assert_eq!(expected, vec![18; 4]);
+        assert_eq!(counts(&positions, skewed_cell(), cutoff), expected);
+    }
+
+    #[test]
+    fn bcc_in_skewed_cell_matches_brute_force() {
+        // the first two shells: 8 at a√3/2 and 6 at a
+        let cutoff = 1.1 * A;
+        let positions = bcc_sites();
+        let expected = brute_force_counts(&positions, skewed_cell(), cutoff);
+        assert_eq!(expected, vec![14; 2]);
+        assert_eq!(counts(&positions, skewed_cell(), cutoff), expected);
+    }
+
+    #[test]
+    fn neighbor_vectors_reach_the_reported_image() {
+        let lattice = skewed_cell();
+        let positions = fcc_sites();
+        let list = NeighborList::build(&positions, 1.1 * A, Some(lattice), [true; 3]);
+        for (i, neighbors) in (0..positions.len()).map(|i| (i, list.neighbors(i))) {
+            for n in neighbors {
+                let target = positions[n.index] + lattice * n.image.as_vec3();
+                assert!((target - positions[i] - n.vector).length() < 1e-4);
+            }
+        }
+    }
+}
@@ -0,0 +1,410 @@
+// Command palette
+// Ctrl+P (Cmd+P on macOS) or the "Commands" button lists every action of the viewer: opening
+// and exporting files, camera views, structure edits, colorings and each toggle. Typing filters
+// the list with a fuzzy match, so "rcam" finds "Reset Camera"; Up / Down move the highlight,
+// Enter or a click runs the action and Escape closes the palette.
+
+use bevy::prelude::*;
+
+use crate::analysis::BondStatistics;
+use crate::bindings::{HelpShown, KeyAction, KeyBindings};
+use crate::color::{ColorBy, ColorScheme};
+use crate::file_dialog::{show_file_dialog, FileDialog};
+use crate::fly_camera::FlyCamera;
+use crate::io::export_text_file;
+use crate::parse::write_xyz;
+use crate::statistics::export_statistics;
+use crate::structure::{Crystal, Selection};
+use crate::theme::Themed;
+use crate::toast::Toast;
+use crate::ui::{
+    center_structure, convert_cell, reset_camera, CameraRig, CellSetting, CenterMode, FitView,
+    MainCamera, ToggleEvent, ToggleId, ToggleStates,
+};
+use crate::widgets::{spawn_button, spawn_text_field, FocusedField, TextField, TextSubmitted};
+
+const EXPORT_FILE_NAME: &str = "structure.xyz";
+// Matches listed at once; typing more narrows them down
+const MAX_SHOWN: usize = 12;
+
+/// Something the palette can run.
+#[derive(Event, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum PaletteAction {
+    OpenFile,
+    ExportStructure,
+    ExportStatistics,
+    ResetCamera,
+    FitView,
+    FlyMode,
+    ClearSelection,
+    ConvertCell(CellSetting),
+    Center(CenterMode),
+    ColorBy(ColorBy),
+    ColorScheme(ColorScheme),
+    Toggle(ToggleId),
+    Help,
+}
+
+impl PaletteAction {
+    // Every action, in the order listed before anything is typed
+    fn all() -> Vec<PaletteAction> {
+        let mut actions = vec![
+            PaletteAction::OpenFile,
+            PaletteAction::ExportStructure,
+            PaletteAction::ExportStatistics,
+            PaletteAction::ResetCamera,
+            PaletteAction::FitView,
+            PaletteAction::FlyMode,
+            PaletteAction::ClearSelection,
+            PaletteAction::ConvertCell(CellSetting::Primitive),
+            PaletteAction::ConvertCell(CellSetting::Conventional),
+            PaletteAction::Center(CenterMode::Centroid),
+            PaletteAction::Center(CenterMode::CenterOfMass),
+        ];
+        actions.extend(ColorBy::ALL.map(PaletteAction::ColorBy));
+        actions.extend(ColorScheme::ALL.map(PaletteAction::ColorScheme));
+        actions.extend(ToggleId::ALL.iter().copied().map(PaletteAction::Toggle));
+        actions.push(PaletteAction::Help);
+        actions
+    }
+
+    fn label(self, toggles: &ToggleStates) -> String {
+        match self {
+            PaletteAction::OpenFile => "File: Open...".to_string(),
+            PaletteAction::ExportStructure => "File: Export Structure as XYZ".to_string(),
+            PaletteAction::ExportStatistics => "File: Export Bond Statistics as CSV".to_string(),
+            PaletteAction::ResetCamera => "View: Reset Camera".to_string(),
+            PaletteAction::FitView => "View: Fit Structure".to_string(),
+            PaletteAction::FlyMode => "View: Toggle Fly Mode".to_string(),
+            PaletteAction::ClearSelection => "Selection: Clear".to_string(),
+            PaletteAction::ConvertCell(setting) => format!("Structure: {}", setting.label()),
+            PaletteAction::Center(CenterMode::Centroid) => {
+                "Structure: Center on Centroid".to_string()
+            }
+            PaletteAction::Center(CenterMode::CenterOfMass) => {
+                "Structure: Center on Center of Mass".to_string()
+            }
+            PaletteAction::ColorBy(by) => format!("Color by: {}", by.label()),
+            PaletteAction::ColorScheme(scheme) => format!("Colors: {}", scheme.label()),
+            PaletteAction::Toggle(id) => {
+                // "Slab Tool: Hidden" reads "Toggle Slab Tool (Hidden)"
+                let label = id.label(toggles.get(id));
+                match label.split_once(": ") {
+                    Some((name, state)) => format!("Toggle {name} ({state})"),
+                    None => format!("Toggle {label}"),
+                }
+            }
+            PaletteAction::Help => "Help: Keyboard and Mouse Controls".to_string(),
+        }
+    }
+}
+
+// How well `query` matches `text`: its characters must appear in order, ignoring case. Runs of
+// consecutive characters and characters starting a word score higher, gaps lower. None when
+// some character is missing.
+fn fuzzy_score(query: &str, text: &str) -> Option<i32> {
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in query.chars().flat_map(char::to_lowercase) {
+        if wanted.is_whitespace() {
+            continue;
+        }
+        let found = position + text[position..].iter().position(|&c| c == wanted)?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += 4;
+        } else if let Some(previous) = previous {
+            score -= (found - previous - 1).min(5) as i32;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+/// Palette state: whether it is showing, and the matches of what was typed.
+#[derive(Resource, Default)]
+pub(crate) struct CommandPalette {
+    open: bool,
+    matches: Vec<(PaletteAction, String)>,
+    highlighted: usize,
+}
+
+/// Button opening the palette.
+#[derive(Component)]
+pub(crate) struct PaletteButton;
+
+/// Root node of the palette, hidden while closed.
+#[derive(Component)]
+pub(crate) struct PaletteOverlay;
+
+/// Search field of the palette.
+#[derive(Component)]
+pub(crate) struct PaletteSearch;
+
+/// Container of the matching entries.
+#[derive(Component)]
+pub(crate) struct PaletteList;
+
+/// Entry running the match at this index.
+#[derive(Component)]
+pub(crate) struct PaletteEntry(usize);
+
+// Spawn the (hidden) palette, centered near the top of the window above the other panels
+pub(crate) fn setup_command_palette(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                top: Val::Percent(12.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            GlobalZIndex(10),
+            PaletteOverlay,
+        ))
+        .with_children(|overlay| {
+            overlay
+                .spawn((
+                    Node {
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(4.0),
+                        padding: UiRect::all(Val::Px(8.0)),
+                        ..default()
+                    },
+                    Themed::Panel,
+                ))
+                .with_children(|panel| {
+                    spawn_text_field(panel, "Type a command...", 400.0, PaletteSearch);
+                    panel.spawn((
+                        Node {
+                            flex_direction: FlexDirection::Column,
+                            row_gap: Val::Px(2.0),
+                            ..default()
+                        },
+                        PaletteList,
+                    ));
+                });
+        });
+}
+
+// Open or close the palette with Ctrl+P or its button; opening starts a fresh search
+pub(crate) fn toggle_command_palette(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    buttons: Query<&Interaction, (Changed<Interaction>, With<PaletteButton>)>,
+    mut palette: ResMut<CommandPalette>,
+    mut focused: ResMut<FocusedField>,
+    mut overlays: Query<&mut Node, With<PaletteOverlay>>,
+    mut fields: Query<(Entity, &mut TextField), With<PaletteSearch>>,
+) {
+    let shortcut = bindings.just_pressed(KeyAction::CommandPalette, &keys);
+    let clicked = buttons.iter().any(|i| *i == Interaction::Pressed);
+    if !shortcut && !clicked {
+        return;
+    }
+
+    palette.open = !palette.open || clicked;
+    palette.highlighted = 0;
+    for mut node in &mut overlays {
+        node.display = if palette.open {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+    for (entity, mut field) in &mut fields {
+        if palette.open {
+            field.value.clear();
+            focused.0 = Some(entity);
+        } else if focused.0 == Some(entity) {
+            focused.0 = None;
+        }
+    }
+}
+
+// Filter the actions as the search changes, move the highlight with the arrow keys and run the
+// highlighted action on Enter, or the entry clicked. Losing the focus closes the palette.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn command_palette_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut submitted: EventReader<TextSubmitted>,
+    entries: Query<(&Interaction, &PaletteEntry), Changed<Interaction>>,
+    fields: Query<(Entity, Ref<TextField>), With<PaletteSearch>>,
+    toggles: Res<ToggleStates>,
+    mut focused: ResMut<FocusedField>,
+    mut palette: ResMut<CommandPalette>,
+    mut overlays: Query<&mut Node, With<PaletteOverlay>>,
+    mut actions: EventWriter<PaletteAction>,
+) {
+    if !palette.open {
+        return;
+    }
+    let Ok((field, search)) = fields.single() else {
+        return;
+    };
+
+    if search.is_changed() || toggles.is_changed() {
+        let mut matches: Vec<(i32, PaletteAction, String)> = PaletteAction::all()
+            .into_iter()
+            .filter_map(|action| {
+                let label = action.label(&toggles);
+                Some((fuzzy_score(&search.value, &label)?, action, label))
+            })
+            .collect();
+        // stable, so equal scores keep the listed order
+        matches.sort_by_key(|(score, _, _)| std::cmp::Reverse(*score));
+        matches.truncate(MAX_SHOWN);
+        let matches: Vec<_> = matches
+            .into_iter()
+            .map(|(_, action, label)| (action, label))
+            .collect();
+        if palette.matches != matches {
+            palette.matches = matches;
+            palette.highlighted = 0;
+        }
+    }
+
+    let count = palette.matches.len();
+    if count > 0 && keys.just_pressed(KeyCode::ArrowDown) {
+        palette.highlighted = (palette.highlighted + 1) % count;
+    }
+    if count > 0 && keys.just_pressed(KeyCode::ArrowUp) {
+        palette.highlighted = (palette.highlighted + count - 1) % count;
+    }
+
+    let clicked = entries
+        .iter()
+        .find(|(interaction, _)| **interaction == Interaction::Pressed)
+        .map(|(_, entry)| entry.0);
+    let entered = submitted
+        .read()
+        .any(|event| event.field == field)
+        .then_some(palette.highlighted);
+    let chosen = clicked
+        .or(entered)
+        .and_then(|index| palette.matches.get(index));
+    if let Some(&(action, _)) = chosen {
+        actions.write(action);
+    } else if focused.0 == Some(field) {
+        return;
+    }
+
+    palette.open = false;
+    if focused.0 == Some(field) {
+        focused.0 = None;
+    }
+    for mut node in &mut overlays {
+        node.display = Display::None;
+    }
+}
+
+// Rebuild the entries when the matches or the highlight change
+pub(crate) fn refresh_command_palette(
+    mut commands: Commands,
+    palette: Res<CommandPalette>,
+    lists: Query<Entity, With<PaletteList>>,
+) {
+    if !palette.is_changed() || !palette.open {
+        return;
+    }
+    for list in &lists {
+        commands
+            .entity(list)
+            .despawn_related::<Children>()
+            .with_children(|list| {
+                if palette.matches.is_empty() {
+                    list.spawn((
+                        Text::new("No matching command"),
+                        TextFont {
+                            font: default(),
+                            font_size: 12.0,
+                            ..default()
+                        },
+                        Themed::Text,
+                    ));
+                }
+                for (index, (_, label)) in palette.matches.iter().enumerate() {
+                    let marker = if index == palette.highlighted {
+                        "> "
+                    } else {
+                        ""
+                    };
+                    spawn_button(list, &format!("{marker}{label}"), PaletteEntry(index));
+                }
+            });
+    }
+}
+
+// Run the actions picked in the palette, as their buttons would
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn run_palette_actions(
+    mut actions: EventReader<PaletteAction>,
+    mut dialog: ResMut<FileDialog>,
+    mut crystal: ResMut<Crystal>,
+    mut selection: ResMut<Selection>,
+    statistics: Res<BondStatistics>,
+    mut camera: Query<&mut Transform, With<MainCamera>>,
+    mut camera_rig: ResMut<CameraRig>,
+    mut fit: EventWriter<FitView>,
+    mut fly: ResMut<FlyCamera>,
+    mut color_by: ResMut<ColorBy>,
+    mut color_scheme: ResMut<ColorScheme>,
+    mut toggle_states: ResMut<ToggleStates>,
+    mut toggle_events: EventWriter<ToggleEvent>,
+    mut help: ResMut<HelpShown>,
+    mut toasts: EventWriter<Toast>,
+) {
+    for &action in actions.read() {
+        match action {
+            PaletteAction::OpenFile => show_file_dialog(&mut dialog),
+            PaletteAction::ExportStructure => export_text_file(
+                EXPORT_FILE_NAME,
+                &write_xyz(&crystal),
+                "structure",
+                &mut toasts,
+            ),
+            PaletteAction::ExportStatistics => export_statistics(&statistics, &mut toasts),
+            PaletteAction::ResetCamera => {
+                if let Ok(transform) = camera.single() {
+                    reset_camera(&mut camera_rig, transform);
+                }
+            }
+            PaletteAction::FitView => {
+                fit.write(FitView);
+            }
+            PaletteAction::FlyMode => fly.active = !fly.active,
+            PaletteAction::ClearSelection => selection.atoms.clear(),
+            PaletteAction::ConvertCell(setting) => {
+                if let Some(converted) = convert_cell(setting, &crystal) {
+                    selection.atoms.clear();
+                    *crystal = converted;
+                }
+            }
+            PaletteAction::Center(mode) => center_structure(
+                mode,
+                &mut crystal,
+                &mut camera_rig,
+                camera.single_mut().ok().as_deref_mut(),
+            ),
+            PaletteAction::ColorBy(by) => {
+                color_by.set_if_neq(by);
+            }
+            PaletteAction::ColorScheme(scheme) => {
+                color_scheme.set_if_neq(scheme);
+            }
+            PaletteAction::Toggle(id) => {
+                let state = toggle_states.toggle(id);
+                toggle_events.write(ToggleEvent { id, state });
+            }
+            PaletteAction::Help => help.0 = true,
+        }
+    }
+}
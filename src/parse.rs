@@ -1,11 +1,400 @@
+#[cfg(feature = "cif")]
+use crate::cif::parse_mmcif;
+use crate::constants::Element;
+use crate::gro::{parse_gro, parse_gro_frames};
+use crate::poscar::parse_poscar;
 use crate::structure::{Atom, Crystal};
-use anyhow::{Context, Result};
+use crate::units::LengthUnit;
+use anyhow::{bail, ensure, Context, Result};
+use bevy::math::{Mat3, Vec3};
+use clap::ValueEnum;
+use rayon::prelude::*;
+
+/// Atom lines a parsing thread takes at least, so small frames are not split up.
+const MIN_LINES_PER_TASK: usize = 4096;
+
+/// Structure file formats that can be read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// XYZ or extended XYZ, possibly with several frames
+    #[value(alias = "extxyz")]
+    Xyz,
+    /// mmCIF, as served by the PDB
+    #[cfg(feature = "cif")]
+    #[value(alias = "mmcif")]
+    Cif,
+    /// VASP POSCAR or CONTCAR
+    #[value(alias = "vasp", alias = "contcar")]
+    Poscar,
+    /// GROMACS .gro, possibly with several frames; lengths in nm
+    #[value(alias = "gromacs")]
+    Gro,
+}
+
+impl Format {
+    /// Format named by the extension of `name` (a path or URL), if it is a known one. VASP
+    /// files are also known by their names, POSCAR and CONTCAR, which have no extension.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let file_name = name.split(['?', '#']).next().unwrap_or(name);
+        let base_name = file_name.rsplit(['/', '\\']).next().unwrap_or(file_name);
+        let vasp_name = ["POSCAR", "CONTCAR"].iter().any(|prefix| {
+            base_name
+                .get(..prefix.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+        });
+        let extension = base_name
+            .rsplit_once('.')
+            .map(|(_, extension)| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("xyz" | "extxyz") => Some(Format::Xyz),
+            #[cfg(feature = "cif")]
+            Some("cif" | "mmcif") => Some(Format::Cif),
+            Some("vasp" | "poscar") => Some(Format::Poscar),
+            Some("gro") => Some(Format::Gro),
+            _ if vasp_name => Some(Format::Poscar),
+            _ => None,
+        }
+    }
+
+    /// Format the text of a structure file looks like: CIF when it opens with a data block, XYZ
+    /// otherwise.
+    pub fn sniff(contents: &str) -> Self {
+        let data_block = contents
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .and_then(|line| line.get(..5))
+            .is_some_and(|start| start.eq_ignore_ascii_case("data_"));
+        match data_block {
+            #[cfg(feature = "cif")]
+            true => Format::Cif,
+            _ => Format::Xyz,
+        }
+    }
+
+    /// Name of the format for display.
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Xyz => "XYZ",
+            #[cfg(feature = "cif")]
+            Format::Cif => "mmCIF",
+            Format::Poscar => "POSCAR",
+            Format::Gro => "GRO",
+        }
+    }
+
+    /// Extensions of the formats this build reads, e.g. for the filter of a file dialog.
+    pub(crate) fn extensions() -> &'static [&'static str] {
+        if cfg!(feature = "cif") {
+            &["xyz", "extxyz", "cif", "mmcif", "vasp", "gro"]
+        } else {
+            &["xyz", "extxyz", "vasp", "gro"]
+        }
+    }
+}
+
+/// Parse a structure file, picking the format from the extension of `name` (a path or URL), or
+/// from the contents when the extension is unknown. Only the first frame of an XYZ file is read.
+pub fn parse_structure(name: &str, contents: &str) -> Result<Crystal> {
+    parse_structure_as(name, contents, None)
+}
+
+// First frame of a structure file in the given format, or the one of its extension
+pub(crate) fn parse_structure_as(
+    name: &str,
+    contents: &str,
+    format: Option<Format>,
+) -> Result<Crystal> {
+    let format = format
+        .or_else(|| Format::from_name(name))
+        .unwrap_or_else(|| Format::sniff(contents));
+    let parsed = match format {
+        Format::Xyz => parse_xyz_content(contents),
+        #[cfg(feature = "cif")]
+        Format::Cif => parse_mmcif(contents),
+        Format::Poscar => parse_poscar(contents),
+        Format::Gro => parse_gro(contents),
+    };
+    let mut crystal = parsed.with_context(|| format!("Failed to parse {name}"))?;
+    crystal.metadata.format = Some(format.name().to_string());
+    // readers of formats in other units convert and record them
+    crystal
+        .metadata
+        .length_unit
+        .get_or_insert(LengthUnit::Angstrom);
+    Ok(crystal)
+}
+
+/// Parse every frame of a structure file in the given format, or the one of its extension, or
+/// the one its contents look like.
+pub fn parse_frames(name: &str, contents: &str, format: Option<Format>) -> Result<Vec<Crystal>> {
+    let format = format
+        .or_else(|| Format::from_name(name))
+        .unwrap_or_else(|| Format::sniff(contents));
+    let parsed = match format {
+        Format::Xyz => parse_xyz_frames(contents),
+        #[cfg(feature = "cif")]
+        Format::Cif => parse_mmcif(contents).map(|crystal| vec![crystal]),
+        Format::Poscar => parse_poscar(contents).map(|crystal| vec![crystal]),
+        Format::Gro => parse_gro_frames(contents),
+    };
+    let mut frames = parsed.with_context(|| format!("Failed to parse {name}"))?;
+    for frame in &mut frames {
+        frame.metadata.format = Some(format.name().to_string());
+        frame
+            .metadata
+            .length_unit
+            .get_or_insert(LengthUnit::Angstrom);
+    }
+    Ok(frames)
+}
+
+// Split concatenated XYZ frames, each starting with its atom count line; the frames are then
+// parsed in parallel
+fn parse_xyz_frames(contents: &str) -> Result<Vec<Crystal>> {
+    let lines = contents.lines().collect::<Vec<&str>>();
+    let mut frames = Vec::new();
+    let mut start = 0;
+    while start < lines.len() {
+        if lines[start].trim().is_empty() {
+            start += 1;
+            continue;
+        }
+        let num_atoms: usize = lines[start].trim().parse().with_context(|| {
+            format!(
+                "Failed to parse number of atoms of frame {}",
+                frames.len() + 1
+            )
+        })?;
+        let end = (start + num_atoms + 2).min(lines.len());
+        frames.push(&lines[start..end]);
+        start = end;
+    }
+    if frames.is_empty() {
+        return Err(anyhow::anyhow!("XYZ file too short"));
+    }
+    frames.into_par_iter().map(parse_xyz_lines).collect()
+}
+
+// Energy of every frame of an XYZ file, from the `energy=` key of its extended XYZ comment line
+pub(crate) fn parse_xyz_energies(contents: &str) -> Vec<Option<f64>> {
+    let mut energies = Vec::new();
+    let mut lines = contents.lines();
+    while let Some(line) = lines.next() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Ok(num_atoms) = line.trim().parse::<usize>() else {
+            break;
+        };
+        let energy = lines
+            .next()
+            .and_then(|comment| comment_value(comment, "energy")?.parse().ok());
+        energies.push(energy);
+        lines.by_ref().take(num_atoms).for_each(drop);
+    }
+    energies
+}
+
+// Value of `key` in an extended XYZ comment line, whose values may be quoted to hold spaces
+fn comment_value<'a>(comment: &'a str, key: &str) -> Option<&'a str> {
+    let mut rest = comment;
+    loop {
+        rest = rest.trim_start();
+        let end = rest.find(|c: char| c == '=' || c.is_whitespace())?;
+        let name = &rest[..end];
+        rest = &rest[end..];
+        let mut value = "";
+        if let Some(after) = rest.strip_prefix('=') {
+            let (quoted, after) = match after.strip_prefix('"') {
+                Some(after) => (true, after),
+                None => (false, after),
+            };
+            let close = if quoted {
+                after.find('"')
+            } else {
+                after.find(char::is_whitespace)
+            }
+            .unwrap_or(after.len());
+            value = &after[..close];
+            rest = after.get(close + usize::from(quoted)..).unwrap_or("");
+        }
+        if name.eq_ignore_ascii_case(key) {
+            return Some(value);
+        }
+    }
+}
+
+// Logical value of an extended XYZ field
+fn is_true(field: &str) -> bool {
+    matches!(field, "T" | "True" | "true" | "1")
+}
+
+// Cell of an extended XYZ comment line, from `Lattice="ax ay az bx by bz cx cy cz"` and
+// `pbc="T T F"`, which defaults to periodic along all three vectors; None without a lattice
+fn xyz_cell(comment: &str) -> Result<Option<(Mat3, [bool; 3])>> {
+    let Some(vectors) = comment_value(comment, "Lattice") else {
+        return Ok(None);
+    };
+    let values = vectors
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<f32>, _>>()
+        .with_context(|| format!("Malformed Lattice key: {vectors}"))?;
+    ensure!(values.len() == 9, "Expected 9 Lattice values: {vectors}");
+    let lattice = Mat3::from_cols_slice(&values);
+    let pbc = match comment_value(comment, "pbc") {
+        Some(flags) => {
+            let flags: Vec<bool> = flags.split_whitespace().map(is_true).collect();
+            <[bool; 3]>::try_from(flags)
+                .map_err(|_| anyhow::anyhow!("Expected 3 pbc values: {comment}"))?
+        }
+        None => [true; 3],
+    };
+    Ok(Some((lattice, pbc)))
+}
+
+/// Kind of the values in a per-atom column of an extended XYZ file.
+#[derive(Clone, Copy)]
+enum ColumnKind {
+    /// One real or integer number.
+    Scalar,
+    /// One logical, T or F, stored as 1 or 0.
+    Flag,
+    /// Three real or integer numbers.
+    Vector,
+    /// One string.
+    Text,
+}
+
+/// A per-atom column other than the species and the positions.
+struct Column {
+    name: String,
+    kind: ColumnKind,
+    /// Index of its first field on an atom line.
+    field: usize,
+    /// Index of its first value among the numbers or the strings of a line.
+    offset: usize,
+}
+
+/// Where the fields of the atom lines are, from the `Properties` key of the comment line.
+struct AtomLayout {
+    species: usize,
+    position: usize,
+    /// Fields an atom line has at least.
+    width: usize,
+    columns: Vec<Column>,
+}
+
+/// Values of the columns of one atom line, numbers and strings in column order.
+#[derive(Default)]
+struct ColumnValues {
+    numbers: Vec<f32>,
+    strings: Vec<String>,
+}
+
+// Layout of the atom lines given by a comment line, e.g. with
+// `Properties=species:S:1:pos:R:3:forces:R:3`; plain XYZ files have just species and positions,
+// and columns of other shapes are skipped
+fn atom_layout(comment: &str) -> Result<AtomLayout> {
+    let mut layout = AtomLayout {
+        species: 0,
+        position: 1,
+        width: 4,
+        columns: Vec::new(),
+    };
+    let Some(spec) = comment_value(comment, "Properties") else {
+        return Ok(layout);
+    };
+    let fields: Vec<&str> = spec.split(':').collect();
+    ensure!(
+        fields.len().is_multiple_of(3),
+        "Malformed Properties key: {spec}"
+    );
+    let (mut species, mut position) = (None, None);
+    let (mut field, mut numbers, mut strings) = (0, 0, 0);
+    for entry in fields.chunks(3) {
+        let count: usize = entry[2]
+            .parse()
+            .with_context(|| format!("Malformed Properties key: {spec}"))?;
+        let kind = match (entry[0], entry[1].to_ascii_uppercase().as_str(), count) {
+            ("species", "S", 1) => {
+                species = Some(field);
+                None
+            }
+            ("pos", "R", 3) => {
+                position = Some(field);
+                None
+            }
+            (_, "R" | "I", 1) => Some(ColumnKind::Scalar),
+            (_, "L", 1) => Some(ColumnKind::Flag),
+            (_, "R" | "I", 3) => Some(ColumnKind::Vector),
+            (_, "S", 1) => Some(ColumnKind::Text),
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            let offset = match kind {
+                ColumnKind::Text => &mut strings,
+                _ => &mut numbers,
+            };
+            layout.columns.push(Column {
+                name: entry[0].to_string(),
+                kind,
+                field,
+                offset: *offset,
+            });
+            *offset += count;
+        }
+        field += count;
+    }
+    let (Some(species), Some(position)) = (species, position) else {
+        bail!("The Properties key has no species or positions: {spec}");
+    };
+    layout.species = species;
+    layout.position = position;
+    layout.width = field;
+    Ok(layout)
+}
+
+impl AtomLayout {
+    // Store the values of every column, one entry of `values` per atom, as properties of
+    // `crystal` under the names of the columns
+    fn store(&self, values: &[ColumnValues], crystal: &mut Crystal) {
+        let properties = &mut crystal.properties;
+        for column in &self.columns {
+            let name = column.name.clone();
+            let offset = column.offset;
+            match column.kind {
+                ColumnKind::Scalar | ColumnKind::Flag => {
+                    let scalars = values.iter().map(|row| row.numbers[offset]).collect();
+                    properties.scalars.insert(name, scalars);
+                }
+                ColumnKind::Vector => {
+                    let vectors = values
+                        .iter()
+                        .map(|row| Vec3::from_slice(&row.numbers[offset..offset + 3]))
+                        .collect();
+                    properties.vectors.insert(name, vectors);
+                }
+                ColumnKind::Text => {
+                    let strings = values
+                        .iter()
+                        .map(|row| row.strings[offset].clone())
+                        .collect();
+                    properties.strings.insert(name, strings);
+                }
+            }
+        }
+    }
+}
 
 // Function to parse XYZ file format from string content
-#[allow(dead_code)]
 fn parse_xyz_content(contents: &str) -> Result<Crystal> {
-    let lines = contents.lines().collect::<Vec<&str>>();
+    parse_xyz_lines(&contents.lines().collect::<Vec<&str>>())
+}
 
+// One XYZ frame, whose atom lines are parsed in parallel when there are many
+fn parse_xyz_lines(lines: &[&str]) -> Result<Crystal> {
     if lines.len() < 2 {
         return Err(anyhow::anyhow!("XYZ file too short"));
     }
@@ -16,30 +405,257 @@ fn parse_xyz_content(contents: &str) -> Result<Crystal> {
         .parse()
         .context("Failed to parse number of atoms")?;
 
-    // Second line is a comment (we can skip it)
-    // Remaining lines contain atom data
+    // Second line is a comment, kept as the title unless it holds extended XYZ keys, whose
+    // title is the value of a `title` key
+    // Remaining lines contain atom data, laid out as the comment line says
+    let comment = lines[1].trim();
+    let layout = atom_layout(comment)?;
+    let atom_lines = &lines[2..lines.len().min(num_atoms + 2)];
+    let (atoms, values): (Vec<Atom>, Vec<ColumnValues>) = atom_lines
+        .par_iter()
+        .with_min_len(MIN_LINES_PER_TASK)
+        .filter_map(|line| parse_atom_line(line, &layout).transpose())
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .unzip();
 
-    let mut atoms = Vec::new();
+    let mut crystal = match xyz_cell(comment)? {
+        Some((lattice, pbc)) => Crystal {
+            pbc,
+            ..Crystal::periodic(atoms, lattice)
+        },
+        None => Crystal::molecule(atoms),
+    };
+    layout.store(&values, &mut crystal);
+    let title = if comment.contains('=') {
+        comment_value(comment, "title")
+    } else {
+        Some(comment)
+    };
+    if let Some(title) = title.filter(|title| !title.is_empty()) {
+        crystal.metadata.title = Some(title.to_string());
+    }
+    Ok(crystal)
+}
 
-    for (i, line) in lines.iter().skip(2).enumerate() {
-        if i >= num_atoms {
-            break;
+// Atom of an XYZ line and the values of its other columns; None for a line that is too short,
+// which is skipped
+fn parse_atom_line(line: &str, layout: &AtomLayout) -> Result<Option<(Atom, ColumnValues)>> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.len() < layout.width {
+        return Ok(None);
+    }
+    let p = layout.position;
+    let atom = Atom {
+        element: Element::parse(parts[layout.species]),
+        x: parts[p].parse().context("Failed to parse x coordinate")?,
+        y: parts[p + 1]
+            .parse()
+            .context("Failed to parse y coordinate")?,
+        z: parts[p + 2]
+            .parse()
+            .context("Failed to parse z coordinate")?,
+    };
+    let mut values = ColumnValues::default();
+    for column in &layout.columns {
+        let number = |field: &str| {
+            field
+                .parse::<f32>()
+                .with_context(|| format!("Failed to parse {} value: {field}", column.name))
+        };
+        match column.kind {
+            ColumnKind::Scalar => values.numbers.push(number(parts[column.field])?),
+            ColumnKind::Flag => {
+                let set = is_true(parts[column.field]);
+                values.numbers.push(if set { 1.0 } else { 0.0 });
+            }
+            ColumnKind::Vector => {
+                for field in &parts[column.field..column.field + 3] {
+                    values.numbers.push(number(field)?);
+                }
+            }
+            ColumnKind::Text => values.strings.push(parts[column.field].to_string()),
         }
+    }
+    Ok(Some((atom, values)))
+}
 
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() < 4 {
-            continue; // Skip malformed lines
+// A name or string value as one whitespace-free field of an extended XYZ file: blanks and the
+// `:` of the Properties key become underscores, and an empty string is written as "_"
+fn xyz_field(text: &str) -> String {
+    match text {
+        "" => "_".to_string(),
+        text => text.replace(|c: char| c.is_whitespace() || c == ':', "_"),
+    }
+}
+
+/// Write a structure in extended XYZ format: the `Properties` key names the species, the
+/// positions and every per-atom array, periodic structures get the `Lattice`/`pbc` keys and
+/// the title is kept under a `title` key, so the file reads back as the same structure.
+pub fn write_xyz(crystal: &Crystal) -> String {
+    let count = crystal.atoms.len();
+    let properties = &crystal.properties;
+    let scalars: Vec<_> = properties
+        .scalars
+        .iter()
+        .filter(|(_, values)| values.len() == count)
+        .collect();
+    let vectors: Vec<_> = properties
+        .vectors
+        .iter()
+        .filter(|(_, values)| values.len() == count)
+        .collect();
+    let strings: Vec<_> = properties
+        .strings
+        .iter()
+        .filter(|(_, values)| values.len() == count)
+        .collect();
+
+    let mut spec = String::from("species:S:1:pos:R:3");
+    for (name, _) in &scalars {
+        spec.push_str(&format!(":{}:R:1", xyz_field(name)));
+    }
+    for (name, _) in &vectors {
+        spec.push_str(&format!(":{}:R:3", xyz_field(name)));
+    }
+    for (name, _) in &strings {
+        spec.push_str(&format!(":{}:S:1", xyz_field(name)));
+    }
+    let mut keys = vec![format!("Properties={spec}")];
+    if let Some(lattice) = crystal.lattice {
+        let vectors = [lattice.x_axis, lattice.y_axis, lattice.z_axis]
+            .iter()
+            .flat_map(|v| v.to_array())
+            .map(|value| format!("{value:.8}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let pbc = crystal
+            .pbc
+            .map(|periodic| if periodic { "T" } else { "F" })
+            .join(" ");
+        keys.push(format!("Lattice=\"{vectors}\""));
+        keys.push(format!("pbc=\"{pbc}\""));
+    }
+    if let Some(title) = &crystal.metadata.title {
+        keys.push(format!("title=\"{}\"", title.replace('"', "'")));
+    }
+
+    let mut contents = format!("{count}\n{}\n", keys.join(" "));
+    for (i, atom) in crystal.atoms.iter().enumerate() {
+        let mut line = format!("{} {:.8} {:.8} {:.8}", atom.element, atom.x, atom.y, atom.z);
+        for (_, values) in &scalars {
+            line.push_str(&format!(" {}", values[i]));
+        }
+        for (_, values) in &vectors {
+            let [x, y, z] = values[i].to_array();
+            line.push_str(&format!(" {x} {y} {z}"));
+        }
+        for (_, values) in &strings {
+            line.push_str(&format!(" {}", xyz_field(&values[i])));
         }
+        contents.push_str(&line);
+        contents.push('\n');
+    }
+    contents
+}
 
-        let atom = Atom {
-            element: parts[0].to_string(),
-            x: parts[1].parse().context("Failed to parse x coordinate")?,
-            y: parts[2].parse().context("Failed to parse y coordinate")?,
-            z: parts[3].parse().context("Failed to parse z coordinate")?,
-        };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::AtomProperties;
 
-        atoms.push(atom);
+    #[test]
+    fn properties_key_lays_out_the_columns() {
+        let crystal = parse_xyz_content(
+            "2
+Properties=pos:R:3:species:S:1:charge:R:1:forces:R:3:fixed:L:1:tag:S:1:pair:R:2 energy=-1.5
+0 0 0 O -0.8 1 2 3 T a 7 8
+0 0 1 H 0.4 -1 -2 -3 F b 7 8
+",
+        )
+        .unwrap();
+        assert_eq!(crystal.atoms[1].element, Element::H);
+        assert_eq!(crystal.atoms[1].z, 1.0);
+        let properties = &crystal.properties;
+        assert_eq!(properties.scalars["charge"], [-0.8, 0.4]);
+        assert_eq!(properties.scalars["fixed"], [1.0, 0.0]);
+        assert_eq!(properties.vectors["forces"][1], Vec3::new(-1.0, -2.0, -3.0));
+        assert_eq!(properties.strings["tag"], ["a", "b"]);
+        assert!(!properties.scalars.contains_key("pair"));
+        assert_eq!(crystal.metadata.title, None);
     }
 
-    Ok(Crystal { atoms })
+    #[test]
+    fn properties_need_species_and_positions() {
+        assert!(parse_xyz_content("1\nProperties=species:S:1\nH\n").is_err());
+        assert!(parse_xyz_content("1\nProperties=species:S\nH 0 0 0\n").is_err());
+    }
+
+    #[test]
+    fn quoted_comment_values_hold_spaces() {
+        let comment = r#"a=1 Lattice="1 0 0 0 1 0 0 0 1" flag energy=-2"#;
+        assert_eq!(comment_value(comment, "lattice"), Some("1 0 0 0 1 0 0 0 1"));
+        assert_eq!(comment_value(comment, "flag"), Some(""));
+        assert_eq!(comment_value(comment, "energy"), Some("-2"));
+        assert_eq!(comment_value(comment, "pbc"), None);
+    }
+
+    #[test]
+    fn lattice_and_pbc_keys_give_the_cell() {
+        let crystal = parse_xyz_content(
+            "1
+Lattice=\"2 0 0 0 3 0 0 0 4\" pbc=\"T T F\" Properties=species:S:1:pos:R:3
+Si 0 0 0
+",
+        )
+        .unwrap();
+        assert_eq!(
+            crystal.lattice,
+            Some(Mat3::from_diagonal(Vec3::new(2.0, 3.0, 4.0)))
+        );
+        assert_eq!(crystal.pbc, [true, true, false]);
+        assert!(parse_xyz_content("1\nLattice=\"1 0 0\"\nSi 0 0 0\n").is_err());
+    }
+
+    #[test]
+    fn written_xyz_reads_back_unchanged() {
+        let mut crystal = Crystal::periodic(
+            vec![
+                Atom::new("Fe", Vec3::new(0.0, 0.5, 1.0)),
+                Atom::new("O", Vec3::new(1.25, -0.5, 2.0)),
+            ],
+            Mat3::from_cols(
+                Vec3::new(3.0, 0.0, 0.0),
+                Vec3::new(1.0, 3.0, 0.0),
+                Vec3::Z * 5.0,
+            ),
+        );
+        crystal.pbc = [true, false, true];
+        crystal.metadata.title = Some("Iron oxide, \"relaxed\"".to_string());
+        let properties = &mut crystal.properties;
+        properties
+            .scalars
+            .insert(AtomProperties::CHARGES.to_string(), vec![0.3, -0.3]);
+        properties.vectors.insert(
+            AtomProperties::FORCES.to_string(),
+            vec![Vec3::new(0.1, 0.2, 0.3), Vec3::NEG_ONE],
+        );
+        properties.strings.insert(
+            AtomProperties::LABELS.to_string(),
+            vec!["Fe1".to_string(), "O1".to_string()],
+        );
+        // arrays that do not line up with the atoms are left out
+        properties.scalars.insert("stale".to_string(), vec![1.0]);
+
+        let read = parse_xyz_content(&write_xyz(&crystal)).unwrap();
+        crystal.properties.scalars.remove("stale");
+        assert_eq!(read.atoms, crystal.atoms);
+        assert_eq!(read.lattice, crystal.lattice);
+        assert_eq!(read.pbc, crystal.pbc);
+        assert_eq!(read.properties, crystal.properties);
+        assert_eq!(
+            read.metadata.title.as_deref(),
+            Some("Iron oxide, 'relaxed'")
+        );
+    }
 }
@@ -0,0 +1,90 @@
+// Render statistics panel
+// Frame rate, frame time, entity count and the meshes drawn, to see what a large structure
+// costs and to quote in performance reports. Atom chunks are instanced, so every mesh drawn is
+// one draw call.
+
+use std::time::Duration;
+
+use bevy::diagnostic::{
+    DiagnosticPath, DiagnosticsStore, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin,
+};
+use bevy::prelude::*;
+
+use crate::structure::Crystal;
+use crate::theme::Themed;
+use crate::ui::{SidePanelColumn, ToggleId, ToggleStates, ToggledPanel};
+use crate::widgets::spawn_table_text;
+
+// The numbers are smoothed anyway; rewriting them every frame only makes them flicker
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Root node of the statistics panel, hidden until toggled on.
+#[derive(Component)]
+pub(crate) struct PerformancePanel;
+
+/// Text holding the statistics.
+#[derive(Component)]
+pub(crate) struct PerformanceText;
+
+// Spawn the (hidden) statistics panel in the side column
+pub(crate) fn setup_performance_panel(
+    mut commands: Commands,
+    column: Single<Entity, With<SidePanelColumn>>,
+) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            PerformancePanel,
+            ToggledPanel(ToggleId::Performance),
+            ChildOf(*column),
+        ))
+        .with_children(|panel| {
+            spawn_table_text(panel, PerformanceText);
+        });
+}
+
+// Write the latest statistics a few times a second while the panel is shown
+pub(crate) fn refresh_performance_panel(
+    toggles: Res<ToggleStates>,
+    diagnostics: Res<DiagnosticsStore>,
+    crystal: Res<Crystal>,
+    meshes: Query<&ViewVisibility, With<Mesh3d>>,
+    time: Res<Time>,
+    mut since_refresh: Local<Duration>,
+    mut texts: Query<&mut Text, With<PerformanceText>>,
+) {
+    *since_refresh += time.delta();
+    if !toggles.get(ToggleId::Performance) || *since_refresh < REFRESH_INTERVAL {
+        return;
+    }
+    *since_refresh = Duration::ZERO;
+
+    let smoothed = |path: &DiagnosticPath| {
+        diagnostics
+            .get(path)
+            .and_then(|diagnostic| diagnostic.smoothed())
+    };
+    let fps = smoothed(&FrameTimeDiagnosticsPlugin::FPS);
+    let frame_time = smoothed(&FrameTimeDiagnosticsPlugin::FRAME_TIME);
+    let entities = diagnostics
+        .get(&EntityCountDiagnosticsPlugin::ENTITY_COUNT)
+        .and_then(|diagnostic| diagnostic.value());
+    let drawn = meshes.iter().filter(|visibility| visibility.get()).count();
+
+    let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    let text = format!(
+        "FPS      {}\nFrame    {}\nEntities {}\nMeshes   {drawn} drawn\nAtoms    {}",
+        or_dash(fps.map(|fps| format!("{fps:.1}"))),
+        or_dash(frame_time.map(|ms| format!("{ms:.2} ms"))),
+        or_dash(entities.map(|count| format!("{count:.0}"))),
+        crystal.atoms.len()
+    );
+    for mut content in &mut texts {
+        content.0 = text.clone();
+    }
+}
@@ -0,0 +1,368 @@
+// Periodic table panel for editing per-element colors, radii and materials
+// Clicking a cell selects the element for the editor below the table. Edits go into
+// `ElementOverrides`, from which the atom instances are rebuilt. The material makes an element
+// metallic (highlights in its own color, as for shiny gold atoms), rough (dull, spread
+// highlights) or emissive (glowing without light), see atoms.wgsl.
+
+use bevy::prelude::*;
+
+use crate::color::{ColorScheme, ElementMaterial, ElementOverrides};
+use crate::constants::{Element, ELEMENTS};
+use crate::theme::Themed;
+use crate::ui::{ToggleId, ToggledPanel};
+
+const CELL_SIZE: f32 = 26.0;
+const COLOR_STEP: f32 = 0.05;
+const RADIUS_STEP: f32 = 0.05;
+const MIN_RADIUS: f32 = 0.05;
+const MATERIAL_STEP: f32 = 0.1;
+
+/// Root node of the periodic table panel, hidden until toggled on.
+#[derive(Component)]
+pub(crate) struct PeriodicTablePanel;
+
+/// Button for one element of the table.
+#[derive(Component)]
+pub(crate) struct ElementCell(Element);
+
+/// Swatch showing the current color of the element being edited.
+#[derive(Component)]
+pub(crate) struct ElementEditorSwatch;
+
+/// Text describing the element being edited.
+#[derive(Component)]
+pub(crate) struct ElementEditorText;
+
+/// Step buttons of the element editor; materials are stepped by property: metallic, roughness
+/// or emissive.
+#[derive(Component, Clone, Copy)]
+pub(crate) enum ElementEditorButton {
+    Channel { channel: usize, delta: f32 },
+    Radius(f32),
+    Material { property: usize, delta: f32 },
+    Reset,
+}
+
+/// Element currently open in the editor.
+#[derive(Resource, Default)]
+pub(crate) struct EditingElement(pub Option<Element>);
+
+// Grid row and column of an element in the standard 18-column layout, with the
+// lanthanides and actinides moved to two rows below the main table
+fn table_position(number: u8) -> (u16, u16) {
+    match number {
+        1 => (0, 0),
+        2 => (0, 17),
+        3..=4 => (1, number as u16 - 3),
+        5..=10 => (1, number as u16 + 7),
+        11..=12 => (2, number as u16 - 11),
+        13..=18 => (2, number as u16 - 1),
+        19..=36 => (3, number as u16 - 19),
+        37..=54 => (4, number as u16 - 37),
+        55..=56 => (5, number as u16 - 55),
+        57..=71 => (8, number as u16 - 55),
+        72..=86 => (5, number as u16 - 69),
+        87..=88 => (6, number as u16 - 87),
+        89..=103 => (9, number as u16 - 87),
+        _ => (6, number as u16 - 101),
+    }
+}
+
+// Dark text on light swatches, light text on dark ones
+fn contrast_text(background: Color) -> Color {
+    let rgb = background.to_srgba();
+    if 0.299 * rgb.red + 0.587 * rgb.green + 0.114 * rgb.blue > 0.5 {
+        Color::BLACK
+    } else {
+        Color::WHITE
+    }
+}
+
+fn step_button(parent: &mut ChildSpawnerCommands, label: &str, action: ElementEditorButton) {
+    parent
+        .spawn((
+            Button,
+            Node {
+                padding: UiRect::axes(Val::Px(6.0), Val::Px(3.0)),
+                border: UiRect::all(Val::Px(1.0)),
+                ..default()
+            },
+            Themed::Button,
+            action,
+        ))
+        .with_children(|button| {
+            button.spawn((
+                Text::new(label),
+                TextFont {
+                    font: default(),
+                    font_size: 11.0,
+                    ..default()
+                },
+                Themed::Text,
+            ));
+        });
+}
+
+// Spawn the (hidden) periodic table panel at the bottom-right
+pub(crate) fn setup_periodic_table(
+    mut commands: Commands,
+    color_scheme: Res<ColorScheme>,
+    overrides: Res<ElementOverrides>,
+) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                right: Val::Px(8.0),
+                bottom: Val::Px(8.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(8.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            PeriodicTablePanel,
+            ToggledPanel(ToggleId::PeriodicTable),
+        ))
+        .with_children(|panel| {
+            panel
+                .spawn(Node {
+                    display: Display::Grid,
+                    grid_template_columns: RepeatedGridTrack::px(18, CELL_SIZE),
+                    grid_template_rows: RepeatedGridTrack::px(10, CELL_SIZE),
+                    column_gap: Val::Px(2.0),
+                    row_gap: Val::Px(2.0),
+                    ..default()
+                })
+                .with_children(|grid| {
+                    for (index, data) in ELEMENTS.iter().enumerate() {
+                        let number = index as u8 + 1;
+                        let Some(element) = Element::from_atomic_number(number) else {
+                            continue;
+                        };
+                        let (row, column) = table_position(number);
+                        let color = overrides.color(*color_scheme, element);
+
+                        grid.spawn((
+                            Button,
+                            Node {
+                                grid_row: GridPlacement::start(row as i16 + 1),
+                                grid_column: GridPlacement::start(column as i16 + 1),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            BackgroundColor(color),
+                            ElementCell(element),
+                        ))
+                        .with_children(|cell| {
+                            cell.spawn((
+                                Text::new(data.symbol),
+                                TextFont {
+                                    font: default(),
+                                    font_size: 11.0,
+                                    ..default()
+                                },
+                                TextColor(contrast_text(color)),
+                            ));
+                        });
+                    }
+                });
+
+            // Editor for the selected element
+            panel
+                .spawn(Node {
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    column_gap: Val::Px(4.0),
+                    ..default()
+                })
+                .with_children(|editor| {
+                    editor.spawn((
+                        Node {
+                            width: Val::Px(CELL_SIZE),
+                            height: Val::Px(CELL_SIZE),
+                            ..default()
+                        },
+                        BackgroundColor(Color::NONE),
+                        ElementEditorSwatch,
+                    ));
+                    editor.spawn((
+                        Text::new("Click an element to edit it"),
+                        TextFont {
+                            font: default(),
+                            font_size: 12.0,
+                            ..default()
+                        },
+                        Themed::Text,
+                        Node {
+                            width: Val::Px(180.0),
+                            ..default()
+                        },
+                        ElementEditorText,
+                    ));
+
+                    for (channel, name) in ["R", "G", "B"].into_iter().enumerate() {
+                        step_button(
+                            editor,
+                            &format!("{name}-"),
+                            ElementEditorButton::Channel {
+                                channel,
+                                delta: -COLOR_STEP,
+                            },
+                        );
+                        step_button(
+                            editor,
+                            &format!("{name}+"),
+                            ElementEditorButton::Channel {
+                                channel,
+                                delta: COLOR_STEP,
+                            },
+                        );
+                    }
+                    step_button(editor, "Size-", ElementEditorButton::Radius(-RADIUS_STEP));
+                    step_button(editor, "Size+", ElementEditorButton::Radius(RADIUS_STEP));
+                    step_button(editor, "Reset", ElementEditorButton::Reset);
+                });
+
+            // Material of the selected element
+            panel
+                .spawn(Node {
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    column_gap: Val::Px(4.0),
+                    ..default()
+                })
+                .with_children(|editor| {
+                    for (property, name) in ["Metal", "Rough", "Glow"].into_iter().enumerate() {
+                        step_button(
+                            editor,
+                            &format!("{name}-"),
+                            ElementEditorButton::Material {
+                                property,
+                                delta: -MATERIAL_STEP,
+                            },
+                        );
+                        step_button(
+                            editor,
+                            &format!("{name}+"),
+                            ElementEditorButton::Material {
+                                property,
+                                delta: MATERIAL_STEP,
+                            },
+                        );
+                    }
+                });
+        });
+}
+
+// Clicking a table cell opens that element in the editor
+pub(crate) fn element_cell_interaction(
+    cells: Query<(&Interaction, &ElementCell), Changed<Interaction>>,
+    mut editing: ResMut<EditingElement>,
+) {
+    for (interaction, cell) in &cells {
+        if *interaction == Interaction::Pressed {
+            editing.0 = Some(cell.0);
+        }
+    }
+}
+
+// Apply the editor step buttons to the element being edited
+pub(crate) fn element_editor_interaction(
+    buttons: Query<(&Interaction, &ElementEditorButton), Changed<Interaction>>,
+    editing: Res<EditingElement>,
+    color_scheme: Res<ColorScheme>,
+    mut overrides: ResMut<ElementOverrides>,
+) {
+    for (interaction, action) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some(element) = editing.0 else {
+            continue;
+        };
+
+        match *action {
+            ElementEditorButton::Channel { channel, delta } => {
+                let mut rgb = overrides.color(*color_scheme, element).to_srgba();
+                let value = match channel {
+                    0 => &mut rgb.red,
+                    1 => &mut rgb.green,
+                    _ => &mut rgb.blue,
+                };
+                *value = (*value + delta).clamp(0.0, 1.0);
+                overrides.get_mut(element).color = Some(rgb.into());
+            }
+            ElementEditorButton::Radius(delta) => {
+                let radius = overrides.size(element);
+                overrides.get_mut(element).radius = Some((radius + delta).max(MIN_RADIUS));
+            }
+            ElementEditorButton::Material { property, delta } => {
+                let mut material = overrides.material(element);
+                let value = match property {
+                    0 => &mut material.metallic,
+                    1 => &mut material.roughness,
+                    _ => &mut material.emissive,
+                };
+                *value = ((*value + delta) / MATERIAL_STEP).round() * MATERIAL_STEP;
+                *value = value.clamp(0.0, 1.0);
+                overrides.get_mut(element).material = Some(material);
+            }
+            ElementEditorButton::Reset => overrides.clear(element),
+        }
+    }
+}
+
+// Keep the table cells and the editor readout in sync with the current colors
+#[allow(clippy::type_complexity)]
+pub(crate) fn refresh_periodic_table(
+    mut cells: Query<(&ElementCell, &mut BackgroundColor, &Children), Without<ElementEditorSwatch>>,
+    mut cell_texts: Query<&mut TextColor, Without<ElementEditorText>>,
+    mut swatches: Query<&mut BackgroundColor, With<ElementEditorSwatch>>,
+    mut editor_texts: Query<&mut Text, With<ElementEditorText>>,
+    editing: Res<EditingElement>,
+    color_scheme: Res<ColorScheme>,
+    overrides: Res<ElementOverrides>,
+) {
+    if !editing.is_changed() && !color_scheme.is_changed() && !overrides.is_changed() {
+        return;
+    }
+
+    for (cell, mut background, children) in &mut cells {
+        let color = overrides.color(*color_scheme, cell.0);
+        *background = BackgroundColor(color);
+        for child in children.iter() {
+            if let Ok(mut text_color) = cell_texts.get_mut(child) {
+                text_color.0 = contrast_text(color);
+            }
+        }
+    }
+
+    let Some(element) = editing.0 else {
+        return;
+    };
+    let color = overrides.color(*color_scheme, element);
+    for mut swatch in &mut swatches {
+        *swatch = BackgroundColor(color);
+    }
+    for mut text in &mut editor_texts {
+        let rgb = color.to_srgba();
+        let ElementMaterial {
+            metallic,
+            roughness,
+            emissive,
+        } = overrides.material(element);
+        text.0 = format!(
+            "{} ({})\nrgb {:.2} {:.2} {:.2}  r {:.2}\n\
+             metal {metallic:.1}  rough {roughness:.1}  glow {emissive:.1}",
+            element.data().name,
+            element.symbol(),
+            rgb.red,
+            rgb.green,
+            rgb.blue,
+            overrides.size(element),
+        );
+    }
+}
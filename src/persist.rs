@@ -0,0 +1,201 @@
+// UI state kept between sessions
+// Toggles, coloring and the window size are written to `vizcrystal/ui-state.json` in the
+// user's config directory (localStorage in the browser) shortly after they change and on exit,
+// and restored on launch, over the defaults of the configuration file.
+// The browser has no configuration file, so there the mouse sensitivity, the last WebSocket
+// server and the Materials Project key are kept as well; natively the configuration file stays
+// in charge of them.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use serde::{Deserialize, Serialize};
+
+use crate::color::{ColorBy, ColorScheme};
+use crate::config::Config;
+use crate::ui::{MouseSensitivity, ToggleEvent, ToggleId, ToggleStates};
+
+// Changes are written at most this often
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[cfg(not(target_arch = "wasm32"))]
+const STATE_DIR: &str = "vizcrystal";
+#[cfg(not(target_arch = "wasm32"))]
+const STATE_FILE: &str = "ui-state.json";
+
+#[cfg(target_arch = "wasm32")]
+const STORAGE_KEY: &str = "vizcrystal.ui-state";
+
+/// What is saved; toggles are keyed by name so that ones a build lacks are skipped.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(default)]
+pub(crate) struct UiState {
+    toggles: BTreeMap<String, bool>,
+    color_scheme: Option<ColorScheme>,
+    color_by: Option<ColorBy>,
+    window_size: Option<[f32; 2]>,
+    /// These three are only kept in the browser.
+    mouse_sensitivity: Option<f32>,
+    ws_url: Option<String>,
+    materials_project_api_key: Option<String>,
+}
+
+// Settings the browser keeps in place of the configuration file
+const BROWSER_ONLY: bool = cfg!(target_arch = "wasm32");
+
+#[cfg(not(target_arch = "wasm32"))]
+fn state_path() -> Option<std::path::PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(STATE_DIR).join(STATE_FILE))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_state() -> Option<String> {
+    std::fs::read_to_string(state_path()?).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_state(contents: &str) -> anyhow::Result<()> {
+    let path = state_path().ok_or_else(|| anyhow::anyhow!("No config directory"))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, contents)?;
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_state() -> Option<String> {
+    local_storage()?.get_item(STORAGE_KEY).ok().flatten()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_state(contents: &str) -> anyhow::Result<()> {
+    local_storage()
+        .ok_or_else(|| anyhow::anyhow!("localStorage is not available"))?
+        .set_item(STORAGE_KEY, contents)
+        .map_err(|e| anyhow::anyhow!("{e:?}"))
+}
+
+fn toggle_name(id: ToggleId) -> Option<String> {
+    match serde_json::to_value(id) {
+        Ok(serde_json::Value::String(name)) => Some(name),
+        _ => None,
+    }
+}
+
+// Apply the saved state; panels are shown or hidden through the usual toggle events
+pub(crate) fn restore_ui_state(
+    mut toggles: ResMut<ToggleStates>,
+    mut toggle_events: EventWriter<ToggleEvent>,
+    mut color_scheme: ResMut<ColorScheme>,
+    mut color_by: ResMut<ColorBy>,
+    mut sensitivity: ResMut<MouseSensitivity>,
+    mut config: ResMut<Config>,
+    mut window: Single<&mut Window, With<PrimaryWindow>>,
+) {
+    let Some(contents) = read_state() else {
+        return;
+    };
+    let state: UiState = match serde_json::from_str(&contents) {
+        Ok(state) => state,
+        Err(e) => {
+            warn!("Ignoring saved UI state: {e}");
+            return;
+        }
+    };
+
+    for (name, on) in state.toggles {
+        let Ok(id) = serde_json::from_value::<ToggleId>(serde_json::Value::String(name)) else {
+            continue;
+        };
+        toggles.restore(id, on);
+        toggle_events.write(ToggleEvent { id, state: on });
+    }
+    if let Some(scheme) = state.color_scheme {
+        *color_scheme = scheme;
+    }
+    if let Some(by) = state.color_by {
+        *color_by = by;
+    }
+    if BROWSER_ONLY {
+        if let Some(factor) = state.mouse_sensitivity.filter(|factor| *factor > 0.0) {
+            sensitivity.0 = factor;
+        }
+        if state.ws_url.is_some() {
+            config.ws_url = state.ws_url;
+        }
+        if state.materials_project_api_key.is_some() {
+            config.materials_project_api_key = state.materials_project_api_key;
+        }
+    }
+    // the browser sizes the canvas itself
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some([width, height]) = state.window_size {
+        window.resolution.set(width.max(200.0), height.max(200.0));
+    }
+    #[cfg(target_arch = "wasm32")]
+    let _ = &mut window;
+    info!("Restored UI state");
+}
+
+// Write the state once it has settled after a change, and on exit
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn save_ui_state(
+    toggles: Res<ToggleStates>,
+    color_scheme: Res<ColorScheme>,
+    color_by: Res<ColorBy>,
+    sensitivity: Res<MouseSensitivity>,
+    config: Res<Config>,
+    window: Option<Single<&Window, With<PrimaryWindow>>>,
+    mut exits: EventReader<AppExit>,
+    time: Res<Time>,
+    mut last_saved: Local<Option<UiState>>,
+    mut since_save: Local<Duration>,
+) {
+    let exiting = exits.read().count() > 0;
+    *since_save += time.delta();
+
+    let state = UiState {
+        toggles: toggles
+            .iter()
+            .filter_map(|(id, on)| Some((toggle_name(id)?, on)))
+            .collect(),
+        color_scheme: Some(*color_scheme),
+        color_by: Some(*color_by),
+        // the window is already gone when closing it ends the app
+        window_size: window
+            .map(|window| [window.width(), window.height()])
+            .or_else(|| last_saved.as_ref().and_then(|saved| saved.window_size)),
+        mouse_sensitivity: BROWSER_ONLY.then_some(sensitivity.0),
+        ws_url: config.ws_url.clone().filter(|_| BROWSER_ONLY),
+        materials_project_api_key: config
+            .materials_project_api_key
+            .clone()
+            .filter(|_| BROWSER_ONLY),
+    };
+    // the first pass only remembers the restored state
+    let Some(previous) = last_saved.as_ref() else {
+        *last_saved = Some(state);
+        return;
+    };
+    if *previous == state || (!exiting && *since_save < SAVE_INTERVAL) {
+        return;
+    }
+    *since_save = Duration::ZERO;
+    match serde_json::to_string_pretty(&state) {
+        Ok(contents) => {
+            if let Err(e) = write_state(&contents) {
+                warn!("Cannot save UI state: {e:#}");
+            }
+        }
+        Err(e) => warn!("Cannot save UI state: {e}"),
+    }
+    *last_saved = Some(state);
+}
@@ -0,0 +1,159 @@
+// POSCAR reader
+// VASP structure files (POSCAR, CONTCAR, *.vasp): a comment line, the scale, the three cell
+// vectors, the species names (VASP 5 and later; VASP 4 files name them in the comment line
+// instead), the atom count of each species, an optional "Selective dynamics" line and then the
+// positions in Direct (fractional) or Cartesian coordinates. Selective dynamics flags and the
+// velocity block that may follow the positions are ignored.
+
+use anyhow::{bail, ensure, Context, Result};
+use bevy::math::Vec3;
+
+use crate::structure::Crystal;
+
+// The first `count` numbers of `line`
+fn numbers(line: &str, count: usize, what: &str) -> Result<Vec<f32>> {
+    let values = line
+        .split_whitespace()
+        .take(count)
+        .map(str::parse)
+        .collect::<Result<Vec<f32>, _>>()
+        .with_context(|| format!("Failed to parse {what}: {line}"))?;
+    ensure!(
+        values.len() == count,
+        "Expected {count} numbers in {what}: {line}"
+    );
+    Ok(values)
+}
+
+pub(crate) fn parse_poscar(contents: &str) -> Result<Crystal> {
+    let mut lines = contents.lines();
+    let mut next = |what: &str| {
+        lines
+            .next()
+            .with_context(|| format!("POSCAR file ends before the {what}"))
+    };
+    let comment = next("comment")?;
+
+    let scale = numbers(next("scale")?, 1, "the scale")?[0];
+    let mut vectors = [Vec3::ZERO; 3];
+    for vector in &mut vectors {
+        *vector = Vec3::from_slice(&numbers(next("cell vectors")?, 3, "a cell vector")?);
+    }
+    let volume = vectors[0].dot(vectors[1].cross(vectors[2]));
+    ensure!(
+        volume.abs() > f32::EPSILON,
+        "The cell vectors are degenerate"
+    );
+    // a negative scale is the volume of the cell
+    let factor = if scale < 0.0 {
+        (-scale / volume.abs()).cbrt()
+    } else {
+        scale
+    };
+    let [a, b, c] = vectors.map(|vector| vector * factor);
+
+    let mut line = next("atom counts")?;
+    let names: Vec<&str> = if line
+        .split_whitespace()
+        .next()
+        .is_some_and(|word| word.parse::<usize>().is_err())
+    {
+        let names = line.split_whitespace().collect();
+        line = next("atom counts")?;
+        names
+    } else {
+        comment.split_whitespace().collect()
+    };
+    let counts = line
+        .split_whitespace()
+        .map_while(|word| word.parse::<usize>().ok())
+        .collect::<Vec<_>>();
+    ensure!(!counts.is_empty(), "Failed to parse atom counts: {line}");
+    ensure!(
+        names.len() >= counts.len(),
+        "The file names {} species for {} atom counts",
+        names.len(),
+        counts.len()
+    );
+
+    let mut line = next("coordinate mode")?.trim_start();
+    if line.starts_with(['S', 's']) {
+        line = next("coordinate mode")?.trim_start();
+    }
+    let cartesian = line.starts_with(['C', 'c', 'K', 'k']);
+
+    let mut builder = Crystal::builder().lattice(a, b, c);
+    if !comment.trim().is_empty() {
+        builder = builder.title(comment.trim());
+    }
+    for (name, &count) in names.iter().zip(&counts) {
+        // names such as "Fe_pv" or "Fe/abc123" come from the POTCAR
+        let element = name.split(['_', '/']).next().unwrap_or(name);
+        for _ in 0..count {
+            let position = Vec3::from_slice(&numbers(next("positions")?, 3, "a position")?);
+            builder = if cartesian {
+                builder.atom(element, position * factor)
+            } else {
+                builder.fractional_atom(element, position)
+            };
+        }
+    }
+    match builder.build() {
+        Ok(crystal) if crystal.atoms.is_empty() => bail!("POSCAR file has no atoms"),
+        built => built,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::Element;
+
+    #[test]
+    fn reads_direct_coordinates_with_species_names() {
+        let crystal = parse_poscar(
+            "NaCl
+2.0
+1 0 0
+0 1 0
+0 0 1
+Na_pv Cl
+1 1
+Direct
+0 0 0
+0.5 0.5 0.5
+",
+        )
+        .unwrap();
+        assert_eq!(crystal.metadata.title.as_deref(), Some("NaCl"));
+        assert_eq!(crystal.atoms[0].element, Element::Na);
+        assert_eq!(crystal.atoms[1].element, Element::Cl);
+        assert_eq!(crystal.atoms[1].position(), Vec3::splat(1.0));
+        assert_eq!(crystal.lattice.unwrap().x_axis, Vec3::new(2.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn reads_vasp4_names_and_a_volume_scale() {
+        let crystal = parse_poscar(
+            "Si
+-8.0
+1 0 0
+0 1 0
+0 0 1
+2
+Selective dynamics
+Cartesian
+0 0 0 T T T
+0.5 0.5 0.5 F F F
+",
+        )
+        .unwrap();
+        assert!(crystal.atoms.iter().all(|atom| atom.element == Element::Si));
+        assert!((crystal.atoms[1].position() - Vec3::splat(1.0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn rejects_degenerate_cells() {
+        assert!(parse_poscar("x\n1\n1 0 0\n2 0 0\n0 0 1\nH\n1\nDirect\n0 0 0\n").is_err());
+    }
+}
@@ -0,0 +1,466 @@
+// Wire format shared by the WebSocket transports
+// Structures arrive either as JSON text frames or as MessagePack binary frames. The encoding is
+// detected per frame, and messages from the viewer use the encoding the peer sent last.
+// Both sides open a connection with a `hello` carrying the protocol version and the optional
+// features they support; peers that skip it are assumed to speak version 1.
+// Large frames may be zlib-compressed: a binary frame starting with a zlib header (0x78, which
+// no MessagePack message starts with) is inflated first and then read like any other frame.
+// Scripts can also drive the viewer with JSON-RPC 2.0 requests (`{"jsonrpc": "2.0", "id": 1,
+// "method": "clear"}`), which are answered with a `response` message.
+
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::Element;
+use crate::structure::{Atom, AtomProperties, UpdateStructure};
+
+// Version of the message format; bumped whenever older peers would misread a message
+pub(crate) const PROTOCOL_VERSION: u32 = 1;
+
+// Optional features of the viewer announced in its hello
+const VIEWER_CAPABILITIES: [&str; 10] = [
+    "json",
+    "msgpack",
+    "zlib",
+    "frames",
+    "lattice",
+    "properties",
+    "selection",
+    "structure_edits",
+    "camera",
+    "rpc",
+];
+
+// First message on a connection, sent by both sides
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Hello {
+    pub protocol: u32,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    // Access token for servers that require one; browsers cannot send it as a header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+impl Hello {
+    pub fn viewer(token: Option<String>) -> Self {
+        Self {
+            protocol: PROTOCOL_VERSION,
+            capabilities: VIEWER_CAPABILITIES.map(String::from).to_vec(),
+            token,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct AtomData {
+    pub element: Element,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+// A structure; with a `frame` index it is one frame of a streamed trajectory and gets buffered
+// instead of replacing the previous one
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct StructureMessage {
+    pub atoms: Vec<AtomData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame: Option<u64>,
+    // MD step number
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step: Option<u64>,
+    // Simulation time, in whatever unit the sender uses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<f64>,
+    // Potential energy, in whatever unit the sender uses; plotted along the timeline
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub energy: Option<f64>,
+    // Cell vectors a, b, c; a structure without one is a molecule unless `keep_cell` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lattice: Option<[[f32; 3]; 3]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pbc: Option<[bool; 3]>,
+    // Reuse the cell of the previous structure on the connection, e.g. for the frames of an
+    // NVT run; ignored when a lattice is given
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keep_cell: bool,
+    // Per-atom arrays, one entry per atom
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forces: Option<Vec<[f32; 3]>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub velocities: Option<Vec<[f32; 3]>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub charges: Option<Vec<f32>>,
+    // Any other named per-atom scalars, vectors and strings
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties: BTreeMap<String, Vec<f32>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vectors: BTreeMap<String, Vec<[f32; 3]>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub strings: BTreeMap<String, Vec<String>>,
+}
+
+// Cell and periodic flags of the last structure on a connection, for messages with `keep_cell`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct PeerCell {
+    pub lattice: Option<Mat3>,
+    pub pbc: [bool; 3],
+}
+
+impl StructureMessage {
+    // Structure update for Bevy, with the cell resolved against and recorded in `cell`;
+    // per-atom arrays of the wrong length are dropped
+    pub fn into_update(self, cell: &mut PeerCell) -> UpdateStructure {
+        if self.lattice.is_some() || !self.keep_cell {
+            let lattice = self.lattice.map(lattice_from_rows);
+            *cell = PeerCell {
+                lattice,
+                pbc: self.pbc.unwrap_or([lattice.is_some(); 3]),
+            };
+        }
+
+        let count = self.atoms.len();
+        let checked = |name: &str, len: usize| {
+            if len != count {
+                warn!("Ignoring `{name}` with {len} values for {count} atoms");
+            }
+            len == count
+        };
+
+        // the dedicated arrays are stored under their names with the other properties
+        let mut scalars = self.properties;
+        scalars.extend(
+            self.charges
+                .map(|values| (AtomProperties::CHARGES.to_string(), values)),
+        );
+        let mut vectors = self.vectors;
+        vectors.extend(
+            self.forces
+                .map(|values| (AtomProperties::FORCES.to_string(), values)),
+        );
+        vectors.extend(
+            self.velocities
+                .map(|values| (AtomProperties::VELOCITIES.to_string(), values)),
+        );
+        let properties = AtomProperties {
+            scalars: scalars
+                .into_iter()
+                .filter(|(name, values)| checked(name, values.len()))
+                .collect(),
+            vectors: vectors
+                .into_iter()
+                .filter(|(name, values)| checked(name, values.len()))
+                .map(|(name, values)| (name, values.into_iter().map(Vec3::from).collect()))
+                .collect(),
+            strings: self
+                .strings
+                .into_iter()
+                .filter(|(name, values)| checked(name, values.len()))
+                .collect(),
+        };
+        UpdateStructure {
+            atoms: self.atoms.into_iter().map(Atom::from).collect(),
+            lattice: cell.lattice,
+            pbc: cell.pbc,
+            properties,
+            metadata: None,
+        }
+    }
+}
+
+// Cell matrix (vectors as columns) from the rows of the wire format
+fn lattice_from_rows(rows: [[f32; 3]; 3]) -> Mat3 {
+    Mat3::from_cols_array_2d(&rows)
+}
+
+// Wire format rows of a cell matrix
+pub(crate) fn lattice_to_rows(lattice: Mat3) -> [[f32; 3]; 3] {
+    lattice.to_cols_array_2d()
+}
+
+impl From<&Atom> for AtomData {
+    fn from(atom: &Atom) -> Self {
+        AtomData {
+            element: atom.element,
+            x: atom.x,
+            y: atom.y,
+            z: atom.z,
+        }
+    }
+}
+
+impl From<AtomData> for Atom {
+    fn from(data: AtomData) -> Self {
+        Atom {
+            element: data.element,
+            x: data.x,
+            y: data.y,
+            z: data.z,
+        }
+    }
+}
+
+// Messages sent from the viewer to the server, tagged with a `type` field
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum ClientMessage {
+    Hello(Hello),
+    // Indices of the picked atoms
+    Selection {
+        atoms: Vec<usize>,
+    },
+    // Full atom list after a local edit, with the cell of periodic structures
+    Structure {
+        atoms: Vec<AtomData>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        lattice: Option<[[f32; 3]; 3]>,
+        pbc: [bool; 3],
+    },
+    // Camera position, rotation quaternion (x, y, z, w) and orbit target
+    Camera {
+        position: [f32; 3],
+        rotation: [f32; 4],
+        target: [f32; 3],
+    },
+    // Answer to an RPC request
+    Response(RpcResponse),
+}
+
+// JSON-RPC error codes
+pub(crate) const METHOD_NOT_FOUND: i64 = -32601;
+pub(crate) const INVALID_PARAMS: i64 = -32602;
+
+// Command for the viewer; requests without an `id` are notifications and get no response
+#[derive(Event, Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct RpcRequest {
+    pub jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<serde_json::Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+// Outcome of a request, carrying either a result or an error
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct RpcResponse {
+    pub jsonrpc: String,
+    pub id: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl RpcResponse {
+    pub fn new(id: serde_json::Value, outcome: Result<serde_json::Value, RpcError>) -> Self {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result,
+            error,
+        }
+    }
+}
+
+// Messages received from the peer, tagged with a `type` field
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum ServerMessage {
+    Hello(Hello),
+    Structure(StructureMessage),
+    Rpc(RpcRequest),
+}
+
+// JSON-RPC requests come without a `type`, as do structure messages that predate the
+// handshake; both are still accepted
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AnyServerMessage {
+    Tagged(ServerMessage),
+    Rpc(RpcRequest),
+    Untagged(StructureMessage),
+}
+
+// Payload of one WebSocket frame
+pub(crate) enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+// How messages are serialized on the wire
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+impl Encoding {
+    // Encoding a received frame was sent with
+    pub fn of(frame: &Frame) -> Self {
+        match frame {
+            Frame::Text(_) => Encoding::Json,
+            Frame::Binary(_) => Encoding::MessagePack,
+        }
+    }
+
+    pub fn encode<T: Serialize>(self, message: &T) -> anyhow::Result<Frame> {
+        Ok(match self {
+            Encoding::Json => Frame::Text(serde_json::to_string(message)?),
+            // named fields, so the maps look the same as the JSON objects on the other end
+            Encoding::MessagePack => Frame::Binary(rmp_serde::to_vec_named(message)?),
+        })
+    }
+}
+
+// First byte of a zlib stream using deflate with the usual 32 KiB window
+const ZLIB_HEADER: u8 = 0x78;
+
+// Inflated frames larger than this are refused, so a small malicious frame cannot exhaust memory
+const MAX_INFLATED_BYTES: u64 = 1 << 30;
+
+impl Frame {
+    // The frame itself, or the frame it inflates to when it is zlib-compressed; the inflated
+    // payload is JSON text if it reads as such and MessagePack otherwise
+    pub fn inflate(self) -> anyhow::Result<Frame> {
+        use std::io::Read;
+
+        let Frame::Binary(bytes) = &self else {
+            return Ok(self);
+        };
+        if bytes.first() != Some(&ZLIB_HEADER) {
+            return Ok(self);
+        }
+        let mut inflated = Vec::new();
+        flate2::read::ZlibDecoder::new(bytes.as_slice())
+            .take(MAX_INFLATED_BYTES + 1)
+            .read_to_end(&mut inflated)?;
+        if inflated.len() as u64 > MAX_INFLATED_BYTES {
+            anyhow::bail!("Compressed frame inflates to more than {MAX_INFLATED_BYTES} bytes");
+        }
+        Ok(match String::from_utf8(inflated) {
+            Ok(text) if text.trim_start().starts_with('{') => Frame::Text(text),
+            Ok(text) => Frame::Binary(text.into_bytes()),
+            Err(e) => Frame::Binary(e.into_bytes()),
+        })
+    }
+
+    pub fn decode<T: for<'de> Deserialize<'de>>(&self) -> anyhow::Result<T> {
+        Ok(match self {
+            Frame::Text(text) => serde_json::from_str(text)?,
+            Frame::Binary(bytes) => rmp_serde::from_slice(bytes)?,
+        })
+    }
+
+    pub fn decode_server_message(&self) -> anyhow::Result<ServerMessage> {
+        Ok(match self.decode::<AnyServerMessage>()? {
+            AnyServerMessage::Tagged(message) => message,
+            AnyServerMessage::Rpc(request) => ServerMessage::Rpc(request),
+            AnyServerMessage::Untagged(structure) => ServerMessage::Structure(structure),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCODINGS: [Encoding; 2] = [Encoding::Json, Encoding::MessagePack];
+
+    #[test]
+    fn hello_round_trips_in_both_encodings() {
+        let hello = Hello::viewer(Some("secret".to_string()));
+        for encoding in ENCODINGS {
+            let frame = encoding
+                .encode(&ClientMessage::Hello(hello.clone()))
+                .unwrap();
+            assert_eq!(Encoding::of(&frame), encoding);
+            match frame.decode_server_message().unwrap() {
+                ServerMessage::Hello(decoded) => assert_eq!(decoded, hello),
+                other => panic!("Expected a hello, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn untagged_structures_are_accepted() {
+        let message = serde_json::json!({
+            "atoms": [
+                {"element": "O", "x": 0.0, "y": 0.0, "z": 0.0},
+                {"element": "H", "x": 0.0, "y": 0.0, "z": 1.0},
+            ],
+            "charges": [-0.8, 0.4],
+            "forces": [[0.0, 0.0, 1.0]],
+        });
+        for encoding in ENCODINGS {
+            let frame = encoding.encode(&message).unwrap();
+            let ServerMessage::Structure(structure) = frame.decode_server_message().unwrap() else {
+                panic!("Expected a structure");
+            };
+            let update = structure.into_update(&mut PeerCell::default());
+            assert_eq!(update.atoms[1].element, Element::H);
+            assert_eq!(update.lattice, None);
+            assert_eq!(
+                update.properties.scalars[AtomProperties::CHARGES],
+                [-0.8, 0.4]
+            );
+            // one force for two atoms does not line up and is dropped
+            assert!(update.properties.vectors.is_empty());
+        }
+    }
+
+    fn compressed(bytes: &[u8]) -> Frame {
+        use std::io::Write;
+
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        Frame::Binary(encoder.finish().unwrap())
+    }
+
+    #[test]
+    fn compressed_frames_inflate_to_their_encoding() {
+        let hello = ClientMessage::Hello(Hello::viewer(None));
+        for encoding in ENCODINGS {
+            let bytes = match encoding.encode(&hello).unwrap() {
+                Frame::Text(text) => text.into_bytes(),
+                Frame::Binary(bytes) => bytes,
+            };
+            let frame = compressed(&bytes).inflate().unwrap();
+            assert_eq!(Encoding::of(&frame), encoding);
+            assert!(matches!(
+                frame.decode_server_message().unwrap(),
+                ServerMessage::Hello(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn uncompressed_frames_pass_through() {
+        let frame = Encoding::MessagePack
+            .encode(&ClientMessage::Hello(Hello::viewer(None)))
+            .unwrap();
+        let Frame::Binary(bytes) = &frame else {
+            panic!("Expected a binary frame");
+        };
+        let bytes = bytes.clone();
+        match frame.inflate().unwrap() {
+            Frame::Binary(passed) => assert_eq!(passed, bytes),
+            Frame::Text(_) => panic!("Expected the binary frame back"),
+        }
+    }
+}
@@ -0,0 +1,337 @@
+// Volume rendering by raymarching
+// An alternative to isosurfaces for fuzzy data such as charge densities: a grid of the volume
+// panel (see volume.rs) is drawn as a glowing cloud, every view ray collecting color and
+// opacity from the values it passes (see volume.wgsl). The transfer function turning values
+// into color and opacity is edited in the panel: colors are those of the slice plane, viridis
+// or blue through white to red for signed data, and opacity ramps up from none at the Low
+// fraction of the color scale (of the largest magnitude for signed data) to full at High;
+// Opacity sets how much light an Å of fully opaque values absorbs. A strip under the rows
+// previews the transfer function over the values of the grid, from the smallest to the largest.
+// The transfer function is baked into a 3D texture with one texel per grid point, which the
+// shader samples with linear filtering; the box drawn is the grid with half a step around it.
+
+use bevy::asset::{load_internal_asset, weak_handle, RenderAssetUsages};
+use bevy::image::ImageSampler;
+use bevy::pbr::{MaterialPipeline, MaterialPipelineKey};
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, MeshVertexBufferLayoutRef, PrimitiveTopology};
+use bevy::render::render_resource::{
+    AsBindGroup, Extent3d, Face, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
+    TextureDimension, TextureFormat,
+};
+use rayon::prelude::*;
+
+use crate::color::value_color;
+use crate::settings::stepped_index;
+use crate::slice::value_scale;
+use crate::structure::Crystal;
+use crate::volume::{VolumeGrid, VolumePanel, Volumes};
+use crate::widgets::{on_off, spawn_stepper_row, StepperSettings};
+
+const VOLUME_SHADER_HANDLE: Handle<Shader> = weak_handle!("35b05cb7-4b48-45ad-bfcb-51a9ac1fb047");
+/// Steps of the Low and High thresholds, as a fraction of the color scale.
+const THRESHOLD_STEP: f32 = 0.05;
+/// Opacities per Å of a fully opaque value.
+const DENSITIES: [f32; 7] = [0.2, 0.5, 1.0, 2.0, 5.0, 10.0, 20.0];
+/// Samples along each ray per grid point across the box.
+const SAMPLES_PER_POINT: usize = 2;
+const MAX_SAMPLES: usize = 512;
+/// Segments of the transfer function preview.
+const PREVIEW_SEGMENTS: usize = 40;
+/// Triangles of the box, by corner; corner c is at (c & 1, c >> 1 & 1, c >> 2) in the box, and
+/// the triangles wind counterclockwise seen from outside.
+const BOX_TRIANGLES: [[u32; 3]; 12] = [
+    [0, 4, 6],
+    [0, 6, 2],
+    [1, 3, 7],
+    [1, 7, 5],
+    [0, 1, 5],
+    [0, 5, 4],
+    [2, 6, 7],
+    [2, 7, 3],
+    [0, 2, 3],
+    [0, 3, 1],
+    [4, 5, 7],
+    [4, 7, 6],
+];
+
+/// Volume rendering settings and transfer function, shown in the volume panel.
+#[derive(Resource)]
+pub(crate) struct RaymarchSettings {
+    pub shown: bool,
+    /// Index into the loaded grids.
+    pub grid: usize,
+    /// Fraction of the color scale below which values are transparent.
+    pub low: f32,
+    /// Fraction of the color scale from which values are fully opaque.
+    pub high: f32,
+    /// Index into `DENSITIES`.
+    pub density: usize,
+}
+
+impl Default for RaymarchSettings {
+    fn default() -> Self {
+        Self {
+            shown: false,
+            grid: 0,
+            low: 0.05,
+            high: 0.5,
+            density: 3,
+        }
+    }
+}
+
+impl RaymarchSettings {
+    // Opacity, from 0 to 1, of a value on the color scale
+    fn opacity(&self, scaled: f32) -> f32 {
+        ((scaled.abs() - self.low) / (self.high - self.low)).clamp(0.0, 1.0)
+    }
+
+    // Color and opacity of a value on the color scale
+    fn transfer(&self, scaled: f32, signed: bool) -> Color {
+        value_color(scaled, signed).with_alpha(self.opacity(scaled))
+    }
+}
+
+impl StepperSettings for RaymarchSettings {
+    fn step(&mut self, field: usize, direction: i32) {
+        let stepped =
+            |value: f32| ((value / THRESHOLD_STEP).round() + direction as f32) * THRESHOLD_STEP;
+        match field {
+            0 => self.shown = direction > 0,
+            // kept below the number of grids by `update_volume_rendering`
+            1 => self.grid = self.grid.saturating_add_signed(direction as isize),
+            2 => self.low = stepped(self.low).clamp(0.0, self.high - THRESHOLD_STEP),
+            3 => self.high = stepped(self.high).clamp(self.low + THRESHOLD_STEP, 1.0),
+            _ => self.density = stepped_index(self.density, direction, DENSITIES.len()),
+        }
+    }
+
+    fn value_text(&self, field: usize) -> String {
+        match field {
+            0 => on_off(self.shown),
+            1 => (self.grid + 1).to_string(),
+            2 => format!("{:.0}%", self.low * 100.0),
+            3 => format!("{:.0}%", self.high * 100.0),
+            _ => format!("{}/Å", DENSITIES[self.density]),
+        }
+    }
+}
+
+/// Material raymarching a 3D texture of colors and opacities over the box it is drawn on.
+#[derive(Asset, TypePath, AsBindGroup, Clone)]
+pub(crate) struct VolumeMaterial {
+    #[uniform(0)]
+    pub texture_from_world: Mat4,
+    /// Opacity per Å of a fully opaque value.
+    #[uniform(1)]
+    pub density: f32,
+    /// Samples along each ray through the box.
+    #[uniform(2)]
+    pub steps: u32,
+    #[texture(3, dimension = "3d")]
+    #[sampler(4)]
+    pub texture: Handle<Image>,
+}
+
+impl Material for VolumeMaterial {
+    fn fragment_shader() -> ShaderRef {
+        VOLUME_SHADER_HANDLE.into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Blend
+    }
+
+    // draw the back of the box, which is there even with the camera inside it
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        descriptor.primitive.cull_mode = Some(Face::Front);
+        Ok(())
+    }
+}
+
+/// Draws `VolumeMaterial`s.
+pub(crate) struct VolumeRenderingPlugin;
+
+impl Plugin for VolumeRenderingPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            VOLUME_SHADER_HANDLE,
+            "shaders/volume.wgsl",
+            Shader::from_wgsl
+        );
+        app.add_plugins(MaterialPlugin::<VolumeMaterial>::default());
+    }
+}
+
+/// The raymarched box.
+#[derive(Component)]
+pub(crate) struct RaymarchedVolume;
+
+/// A segment of the transfer function preview, by index from the smallest value.
+#[derive(Component)]
+pub(crate) struct TransferPreview(usize);
+
+// Texture of the transfer function over `grid`, one texel per grid point
+fn transfer_texture(grid: &VolumeGrid, settings: &RaymarchSettings) -> Image {
+    let (signed, scaled) = value_scale(grid.range(), 1.0);
+    let data = grid
+        .values
+        .par_iter()
+        .flat_map_iter(|&value| {
+            settings
+                .transfer(scaled(value), signed)
+                .to_srgba()
+                .to_u8_array()
+        })
+        .collect();
+    let [width, height, depth] = grid.dims.map(|n| n as u32);
+    let mut image = Image::new(
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: depth,
+        },
+        TextureDimension::D3,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.sampler = ImageSampler::linear();
+    image
+}
+
+// Box of `grid`, half a step beyond its outer grid points, and the transform from world to
+// texture coordinates, where the box is the unit cube
+fn grid_box(grid: &VolumeGrid) -> (Mesh, Mat4) {
+    let dims = Vec3::from_array(grid.dims.map(|n| n as f32));
+    let corners: Vec<[f32; 3]> = (0..8)
+        .map(|c| {
+            let corner = Vec3::new((c & 1) as f32, (c >> 1 & 1) as f32, (c >> 2) as f32);
+            grid.position(corner * dims - 0.5).to_array()
+        })
+        .collect();
+    // a left-handed grid turns the box inside out
+    let mirrored = grid.steps.determinant() < 0.0;
+    let indices = BOX_TRIANGLES
+        .iter()
+        .flat_map(|&[a, b, c]| if mirrored { [a, c, b] } else { [a, b, c] })
+        .collect();
+    let mesh = Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, corners)
+    .with_inserted_indices(Indices::U32(indices));
+
+    let world_from_grid = Mat4::from_translation(grid.origin) * Mat4::from_mat3(grid.steps);
+    let texture_from_grid =
+        Mat4::from_scale(dims.recip()) * Mat4::from_translation(Vec3::splat(0.5));
+    (mesh, texture_from_grid * world_from_grid.inverse())
+}
+
+// Add the volume rendering rows and the transfer function preview to the volume panel
+pub(crate) fn setup_raymarch_controls(
+    mut commands: Commands,
+    panel: Single<Entity, With<VolumePanel>>,
+    settings: Res<RaymarchSettings>,
+) {
+    commands.entity(*panel).with_children(|panel| {
+        for (field, label) in ["Volume", "Grid", "Low", "High", "Opacity"]
+            .into_iter()
+            .enumerate()
+        {
+            spawn_stepper_row(panel, label, field, &*settings);
+        }
+        panel
+            .spawn(Node {
+                flex_direction: FlexDirection::Row,
+                height: Val::Px(10.0),
+                ..default()
+            })
+            .with_children(|strip| {
+                for segment in 0..PREVIEW_SEGMENTS {
+                    strip.spawn((
+                        Node {
+                            flex_grow: 1.0,
+                            ..default()
+                        },
+                        BackgroundColor(Color::NONE),
+                        TransferPreview(segment),
+                    ));
+                }
+            });
+    });
+}
+
+// Color the transfer function preview for the chosen grid
+pub(crate) fn refresh_transfer_preview(
+    settings: Res<RaymarchSettings>,
+    volumes: Res<Volumes>,
+    mut segments: Query<(&TransferPreview, &mut BackgroundColor)>,
+) {
+    if !settings.is_changed() && !volumes.is_changed() {
+        return;
+    }
+    let Some(grid) = volumes.grids.get(settings.grid) else {
+        for (_, mut background) in &mut segments {
+            background.0 = Color::NONE;
+        }
+        return;
+    };
+    let (min, max) = grid.range();
+    let (signed, scaled) = value_scale((min, max), 1.0);
+    for (TransferPreview(segment), mut background) in &mut segments {
+        let fraction = (*segment as f32 + 0.5) / PREVIEW_SEGMENTS as f32;
+        background.0 = settings.transfer(scaled(min + fraction * (max - min)), signed);
+    }
+}
+
+// Rebuild the raymarched box when its settings or the grids change, and remove it while the
+// grids' structure is not on screen
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_volume_rendering(
+    mut commands: Commands,
+    mut settings: ResMut<RaymarchSettings>,
+    volumes: Res<Volumes>,
+    crystal: Res<Crystal>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<VolumeMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    boxes: Query<Entity, With<RaymarchedVolume>>,
+    mut drawn: Local<bool>,
+) {
+    if settings.grid >= volumes.grids.len() && settings.grid > 0 {
+        settings.grid = volumes.grids.len().saturating_sub(1);
+    }
+    let grid = volumes.grids.get(settings.grid);
+    let visible = settings.shown && volumes.shown(&crystal) && grid.is_some();
+    if visible == *drawn && !settings.is_changed() && !volumes.is_changed() {
+        return;
+    }
+    *drawn = visible;
+    for entity in &boxes {
+        commands.entity(entity).despawn();
+    }
+    let Some(grid) = grid.filter(|_| visible) else {
+        return;
+    };
+    let (mesh, texture_from_world) = grid_box(grid);
+    let steps = grid.dims.iter().max().copied().unwrap_or(1) * SAMPLES_PER_POINT;
+    commands.spawn((
+        Mesh3d(meshes.add(mesh)),
+        MeshMaterial3d(materials.add(VolumeMaterial {
+            texture_from_world,
+            density: DENSITIES[settings.density],
+            steps: steps.min(MAX_SAMPLES) as u32,
+            texture: images.add(transfer_texture(grid, &settings)),
+        })),
+        RaymarchedVolume,
+    ));
+}
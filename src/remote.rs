@@ -0,0 +1,416 @@
+// Structures fetched over HTTP(S)
+// The Open panel takes a URL or a PDB ID; a URL is also accepted as `--url <URL>` on the
+// command line. Downloads run in the background (reqwest on a thread natively, fetch in the
+// browser) and are parsed there too, by the file parsers or by the reader of a database API.
+// Native downloads report the bytes received as they arrive, for the loading indicator.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bevy::prelude::*;
+use crossbeam_channel::{unbounded, Receiver, Sender};
+
+use crate::cli::Cli;
+use crate::events::StructureLoaded;
+use crate::materials_project::spawn_materials_project_row;
+#[cfg(feature = "cif")]
+use crate::parse::parse_structure;
+use crate::parse::{parse_structure_as, Format};
+use crate::structure::{Crystal, Selection};
+use crate::structure_list::{tab_name, StructureList};
+use crate::theme::Themed;
+use crate::toast::Toast;
+use crate::trajectory::Trajectory;
+use crate::ui::{FitView, ToggleId, ToggledPanel, ToolPanelRow};
+use crate::widgets::{entered_values, spawn_button, spawn_text_field, TextField, TextSubmitted};
+
+// Source name and structure of a finished download
+type Download = anyhow::Result<(String, Crystal)>;
+
+// Reader of a response body, given the source name and the body
+type ParseFn = Box<dyn Fn(&str, &str) -> anyhow::Result<Crystal> + Send>;
+
+// Bytes read from a response between two progress reports
+#[cfg(not(target_arch = "wasm32"))]
+const READ_CHUNK: usize = 256 << 10;
+
+/// What to download and how to read it.
+pub(crate) struct FetchRequest {
+    /// Names the source in log messages and, for files, decides the format.
+    pub name: String,
+    pub url: String,
+    pub headers: Vec<(&'static str, String)>,
+    /// Reads the structure from the response body, given the name and the body.
+    pub parse: ParseFn,
+}
+
+impl FetchRequest {
+    /// Structure file at `url` in `format`, or else in the format of its extension.
+    pub fn file(url: &str, format: Option<Format>) -> Self {
+        Self {
+            name: url.to_string(),
+            url: url.to_string(),
+            headers: Vec::new(),
+            parse: Box::new(move |name, contents| parse_structure_as(name, contents, format)),
+        }
+    }
+
+    fn download(self, contents: anyhow::Result<String>) -> Download {
+        let structure = (self.parse)(&self.name, &contents?)?;
+        Ok((self.name, structure))
+    }
+}
+
+/// How far a download got.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DownloadProgress {
+    /// Source name of the request.
+    pub name: String,
+    pub bytes_read: u64,
+    /// Size announced by the server, if any.
+    pub total_bytes: Option<u64>,
+    /// Whether the body arrived completely and is being parsed.
+    pub parsing: bool,
+}
+
+enum RemoteMessage {
+    Progress(u64, DownloadProgress),
+    // boxed, as a structure is much larger than a progress report
+    Done(u64, Box<Download>),
+}
+
+/// Channel the background downloads report back on, and the downloads in flight.
+#[derive(Resource)]
+pub(crate) struct RemoteLoader {
+    tx: Sender<RemoteMessage>,
+    rx: Receiver<RemoteMessage>,
+    // taken by `fetch`, which only borrows the loader
+    next_id: AtomicU64,
+    downloads: Vec<(u64, DownloadProgress)>,
+}
+
+impl Default for RemoteLoader {
+    fn default() -> Self {
+        let (tx, rx) = unbounded();
+        Self {
+            tx,
+            rx,
+            next_id: AtomicU64::new(0),
+            downloads: Vec::new(),
+        }
+    }
+}
+
+impl RemoteLoader {
+    /// Starts the download; the structure is loaded once it arrives.
+    pub fn fetch(&self, request: FetchRequest) {
+        info!("Fetching {}", request.name);
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let progress = DownloadProgress {
+            name: request.name.clone(),
+            ..default()
+        };
+        // listed right away, before the first bytes arrive
+        let _ = self.tx.send(RemoteMessage::Progress(id, progress.clone()));
+        spawn_fetch(request, id, progress, self.tx.clone());
+    }
+
+    /// Downloads in flight, oldest first.
+    pub fn downloads(&self) -> impl Iterator<Item = &DownloadProgress> {
+        self.downloads.iter().map(|(_, progress)| progress)
+    }
+}
+
+/// Text field holding the URL to open.
+#[derive(Component)]
+pub(crate) struct UrlField;
+
+/// Button fetching the URL in the field.
+#[derive(Component)]
+pub(crate) struct OpenUrlButton;
+
+/// Text field holding a PDB ID.
+#[cfg(feature = "cif")]
+#[derive(Component)]
+pub(crate) struct PdbIdField;
+
+/// Button downloading the entry in the PDB ID field.
+#[cfg(feature = "cif")]
+#[derive(Component)]
+pub(crate) struct FetchPdbButton;
+
+// mmCIF download of a PDB entry from RCSB
+#[cfg(feature = "cif")]
+fn pdb_request(id: &str) -> anyhow::Result<FetchRequest> {
+    let id = id.trim().to_ascii_uppercase();
+    let valid = id.len() == 4
+        && id.starts_with(|c: char| c.is_ascii_digit())
+        && id.chars().all(|c| c.is_ascii_alphanumeric());
+    if !valid {
+        anyhow::bail!("'{id}' is not a PDB ID (4 characters such as 1CRN)");
+    }
+    Ok(FetchRequest {
+        name: format!("{id}.cif"),
+        url: format!("https://files.rcsb.org/download/{id}.cif"),
+        headers: Vec::new(),
+        parse: Box::new(parse_structure),
+    })
+}
+
+// Parse the downloaded body and report the structure
+fn finish_download(
+    request: FetchRequest,
+    id: u64,
+    mut progress: DownloadProgress,
+    contents: anyhow::Result<String>,
+    tx: &Sender<RemoteMessage>,
+) {
+    if contents.is_ok() {
+        progress.parsing = true;
+        let _ = tx.send(RemoteMessage::Progress(id, progress));
+    }
+    let _ = tx.send(RemoteMessage::Done(
+        id,
+        Box::new(request.download(contents)),
+    ));
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn spawn_fetch(
+    request: FetchRequest,
+    id: u64,
+    mut progress: DownloadProgress,
+    tx: Sender<RemoteMessage>,
+) {
+    std::thread::spawn(move || {
+        let contents = fetch_text(&request, |bytes_read, total_bytes| {
+            progress.bytes_read = bytes_read;
+            progress.total_bytes = total_bytes;
+            let _ = tx.send(RemoteMessage::Progress(id, progress.clone()));
+        });
+        finish_download(request, id, progress, contents, &tx);
+    });
+}
+
+// The response body, reporting the bytes read so far and the announced size after every chunk
+#[cfg(not(target_arch = "wasm32"))]
+fn fetch_text(
+    request: &FetchRequest,
+    mut report: impl FnMut(u64, Option<u64>),
+) -> anyhow::Result<String> {
+    use std::io::Read;
+
+    use anyhow::Context;
+
+    let url = &request.url;
+    let mut builder = reqwest::blocking::Client::new().get(url);
+    for (name, value) in &request.headers {
+        builder = builder.header(*name, value);
+    }
+    let mut response = builder
+        .send()
+        .and_then(reqwest::blocking::Response::error_for_status)
+        .with_context(|| format!("Failed to fetch {url}"))?;
+    let total_bytes = response.content_length();
+    let failed = || format!("Failed to read the response of {url}");
+    let mut bytes = Vec::new();
+    let mut chunk = vec![0; READ_CHUNK];
+    loop {
+        let read = response.read(&mut chunk).with_context(failed)?;
+        if read == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&chunk[..read]);
+        report(bytes.len() as u64, total_bytes);
+    }
+    // as lenient as `Response::text`
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// The browser hands the body over in one piece, so only the parsing is reported
+#[cfg(target_arch = "wasm32")]
+fn spawn_fetch(
+    request: FetchRequest,
+    id: u64,
+    progress: DownloadProgress,
+    tx: Sender<RemoteMessage>,
+) {
+    wasm_bindgen_futures::spawn_local(async move {
+        let contents = fetch_text(&request).await;
+        finish_download(request, id, progress, contents, &tx);
+    });
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn fetch_text(request: &FetchRequest) -> anyhow::Result<String> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    let url = &request.url;
+    let js_error = |e: wasm_bindgen::JsValue| anyhow::anyhow!("Failed to fetch {url}: {e:?}");
+
+    let window = web_sys::window().ok_or_else(|| anyhow::anyhow!("No window to fetch from"))?;
+    let init = web_sys::RequestInit::new();
+    let headers = web_sys::Headers::new().map_err(js_error)?;
+    for (name, value) in &request.headers {
+        headers.set(name, value).map_err(js_error)?;
+    }
+    init.set_headers(&headers);
+    let response: web_sys::Response = JsFuture::from(window.fetch_with_str_and_init(url, &init))
+        .await
+        .map_err(js_error)?
+        .dyn_into()
+        .map_err(js_error)?;
+    if !response.ok() {
+        anyhow::bail!("Failed to fetch {url}: HTTP {}", response.status());
+    }
+    let text = JsFuture::from(response.text().map_err(js_error)?)
+        .await
+        .map_err(js_error)?;
+    text.as_string()
+        .ok_or_else(|| anyhow::anyhow!("Response of {url} is not text"))
+}
+
+// Fetch the structure named on the command line
+pub(crate) fn fetch_url_argument(cli: Res<Cli>, loader: Res<RemoteLoader>) {
+    if let Some(url) = &cli.url {
+        loader.fetch(FetchRequest::file(url, cli.format));
+    }
+}
+
+// Spawn the (hidden) Open panel in the tool row
+pub(crate) fn setup_open_panel(mut commands: Commands, row: Single<Entity, With<ToolPanelRow>>) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            ToggledPanel(ToggleId::Open),
+            ChildOf(*row),
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                Text::new("Open"),
+                TextFont {
+                    font: default(),
+                    font_size: 14.0,
+                    ..default()
+                },
+                Themed::Text,
+            ));
+            panel
+                .spawn(Node {
+                    flex_direction: FlexDirection::Row,
+                    column_gap: Val::Px(4.0),
+                    ..default()
+                })
+                .with_children(|row| {
+                    spawn_text_field(row, "https://.../structure.xyz", 260.0, UrlField);
+                    spawn_button(row, "Load", OpenUrlButton);
+                });
+            #[cfg(feature = "cif")]
+            panel
+                .spawn(Node {
+                    flex_direction: FlexDirection::Row,
+                    column_gap: Val::Px(4.0),
+                    ..default()
+                })
+                .with_children(|row| {
+                    spawn_text_field(row, "PDB ID, e.g. 1CRN", 260.0, PdbIdField);
+                    spawn_button(row, "Fetch PDB", FetchPdbButton);
+                });
+            spawn_materials_project_row(panel);
+        });
+}
+
+// Fetch the URL on Enter or a click on Load
+pub(crate) fn open_url_actions(
+    buttons: Query<&Interaction, (Changed<Interaction>, With<OpenUrlButton>)>,
+    fields: Query<&TextField, With<UrlField>>,
+    mut submitted: EventReader<TextSubmitted>,
+    loader: Res<RemoteLoader>,
+) {
+    let pressed = buttons.iter().any(|i| *i == Interaction::Pressed);
+    for url in entered_values(&mut submitted, &fields, pressed) {
+        loader.fetch(FetchRequest::file(&url, None));
+    }
+}
+
+// Download the PDB entry on Enter or a click on Fetch PDB
+#[cfg(feature = "cif")]
+pub(crate) fn fetch_pdb_actions(
+    buttons: Query<&Interaction, (Changed<Interaction>, With<FetchPdbButton>)>,
+    fields: Query<&TextField, With<PdbIdField>>,
+    mut submitted: EventReader<TextSubmitted>,
+    loader: Res<RemoteLoader>,
+    mut toasts: EventWriter<Toast>,
+) {
+    let pressed = buttons.iter().any(|i| *i == Interaction::Pressed);
+    for id in entered_values(&mut submitted, &fields, pressed) {
+        match pdb_request(&id) {
+            Ok(request) => loader.fetch(request),
+            Err(e) => {
+                error!("{e:#}");
+                toasts.write(Toast::error(format!("{e:#}")));
+            }
+        }
+    }
+}
+
+// Follow the progress of the downloads and replace the structure with finished ones
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn receive_downloads(
+    mut loader: ResMut<RemoteLoader>,
+    mut crystal: ResMut<Crystal>,
+    mut selection: ResMut<Selection>,
+    mut fit: EventWriter<FitView>,
+    mut announce: EventWriter<StructureLoaded>,
+    mut toasts: EventWriter<Toast>,
+    mut list: ResMut<StructureList>,
+    mut trajectory: ResMut<Trajectory>,
+) {
+    let messages: Vec<RemoteMessage> = loader.rx.try_iter().collect();
+    for message in messages {
+        let download = match message {
+            RemoteMessage::Progress(id, progress) => {
+                match loader.downloads.iter_mut().find(|(other, _)| *other == id) {
+                    Some((_, listed)) => *listed = progress,
+                    None => loader.downloads.push((id, progress)),
+                }
+                continue;
+            }
+            RemoteMessage::Done(id, download) => {
+                loader.downloads.retain(|(other, _)| *other != id);
+                *download
+            }
+        };
+        match download {
+            Ok((name, loaded)) => {
+                info!("Loaded {} atoms from {name}", loaded.atoms.len());
+                announce.write(StructureLoaded {
+                    source: name.clone(),
+                    atom_count: loaded.atoms.len(),
+                    frame_count: 1,
+                });
+                selection.atoms.clear();
+                // the frames of the tab left behind stay with that tab
+                trajectory.clear();
+                list.open(
+                    name.clone(),
+                    vec![(tab_name(&name), vec![loaded.clone()])],
+                    0,
+                );
+                *crystal = loaded;
+                fit.write(FitView);
+            }
+            Err(e) => {
+                error!("{e:#}");
+                toasts.write(Toast::error(format!("{e:#}")));
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+// JSON-RPC commands received over the WebSocket
+// Lets scripts drive the viewer beyond pushing structures:
+//   set_camera        {"position": [x, y, z], "target": [x, y, z]}, either may be left out
+//   take_screenshot   {"path": "shot.png", "axes": true}, saved without the UI into the working
+//                     directory (downloaded on the web); `path` must be a bare file name, since
+//                     peers may not write anywhere else. `axes` adds the axis gizmo, see figure.rs
+//   load_url          {"url": "https://..."}, fetched like the Open panel does (`fetch` feature)
+//   set_representation {"color_by": "coordination", "color_scheme": "vesta"}
+//   clear             removes the structure and any buffered trajectory
+
+use bevy::prelude::*;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::client::{ConnectionState, WebSocketStream};
+use crate::color::{ColorBy, ColorScheme};
+use crate::figure::SaveFigure;
+use crate::protocol::{
+    ClientMessage, RpcError, RpcRequest, RpcResponse, INVALID_PARAMS, METHOD_NOT_FOUND,
+};
+#[cfg(feature = "fetch")]
+use crate::remote::{FetchRequest, RemoteLoader};
+use crate::structure::{Crystal, Selection};
+use crate::trajectory::Trajectory;
+use crate::ui::{CameraRig, MainCamera};
+
+const DEFAULT_SCREENSHOT_PATH: &str = "vizmat-screenshot.png";
+
+#[derive(Deserialize)]
+struct SetCameraParams {
+    position: Option<[f32; 3]>,
+    target: Option<[f32; 3]>,
+}
+
+#[derive(Deserialize)]
+struct ScreenshotParams {
+    path: Option<String>,
+    #[serde(default)]
+    axes: bool,
+}
+
+#[cfg(feature = "fetch")]
+#[derive(Deserialize)]
+struct LoadUrlParams {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct RepresentationParams {
+    color_by: Option<String>,
+    color_scheme: Option<String>,
+}
+
+fn invalid_params(message: impl Into<String>) -> RpcError {
+    RpcError {
+        code: INVALID_PARAMS,
+        message: message.into(),
+    }
+}
+
+// Whether `path` names a file in the working directory, with no directory, root or `..`
+fn is_bare_file_name(path: &str) -> bool {
+    let mut components = std::path::Path::new(path).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(std::path::Component::Normal(_)), None)
+    )
+}
+
+// Parameters of a request; a missing `params` reads as an empty object
+fn params<T: for<'de> Deserialize<'de>>(request: &RpcRequest) -> Result<T, RpcError> {
+    let params = match &request.params {
+        Value::Null => json!({}),
+        params => params.clone(),
+    };
+    serde_json::from_value(params).map_err(|e| invalid_params(e.to_string()))
+}
+
+// Option named `name` (case-insensitive) among `options`
+fn pick<T: Copy>(options: &[T], label: fn(T) -> &'static str, name: &str) -> Result<T, RpcError> {
+    options
+        .iter()
+        .copied()
+        .find(|&option| label(option).eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            let known: Vec<_> = options.iter().map(|&option| label(option)).collect();
+            invalid_params(format!(
+                "Unknown '{name}', expected one of {}",
+                known.join(", ")
+            ))
+        })
+}
+
+// Run RPC requests and answer those with an id
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn handle_rpc_requests(
+    mut requests: EventReader<RpcRequest>,
+    mut figures: EventWriter<SaveFigure>,
+    stream: Res<WebSocketStream>,
+    state: Res<ConnectionState>,
+    #[cfg(feature = "fetch")] loader: Res<RemoteLoader>,
+    mut camera: Single<&mut Transform, With<MainCamera>>,
+    mut camera_rig: ResMut<CameraRig>,
+    mut color_by: ResMut<ColorBy>,
+    mut color_scheme: ResMut<ColorScheme>,
+    mut crystal: ResMut<Crystal>,
+    mut selection: ResMut<Selection>,
+    mut trajectory: ResMut<Trajectory>,
+) {
+    for request in requests.read() {
+        let outcome = match request.method.as_str() {
+            "set_camera" => params::<SetCameraParams>(request).and_then(|params| {
+                if params.position.is_none() && params.target.is_none() {
+                    return Err(invalid_params("Expected `position` and/or `target`"));
+                }
+                let target = params.target.map_or(camera_rig.target(), Vec3::from);
+                // without a position the camera keeps its offset from the target
+                let position = params.position.map_or(
+                    target + camera.translation - camera_rig.target(),
+                    Vec3::from,
+                );
+                camera_rig.place(&mut camera, position, target);
+                Ok(Value::Null)
+            }),
+            "take_screenshot" => params::<ScreenshotParams>(request).and_then(|params| {
+                let path = params
+                    .path
+                    .unwrap_or_else(|| DEFAULT_SCREENSHOT_PATH.to_string());
+                if !is_bare_file_name(&path) {
+                    return Err(invalid_params(format!(
+                        "Expected a file name without directories, got '{path}'"
+                    )));
+                }
+                figures.write(SaveFigure {
+                    path: path.clone(),
+                    axes: params.axes,
+                });
+                Ok(json!({ "path": path }))
+            }),
+            #[cfg(feature = "fetch")]
+            "load_url" => params::<LoadUrlParams>(request).map(|params| {
+                loader.fetch(FetchRequest::file(&params.url, None));
+                Value::Null
+            }),
+            "set_representation" => params::<RepresentationParams>(request).and_then(|params| {
+                let by = params
+                    .color_by
+                    .map(|name| pick(&ColorBy::ALL, ColorBy::label, &name))
+                    .transpose()?;
+                let scheme = params
+                    .color_scheme
+                    .map(|name| pick(&ColorScheme::ALL, ColorScheme::label, &name))
+                    .transpose()?;
+                if let Some(by) = by {
+                    *color_by = by;
+                }
+                if let Some(scheme) = scheme {
+                    *color_scheme = scheme;
+                }
+                Ok(Value::Null)
+            }),
+            "clear" => {
+                *crystal = Crystal::molecule(Vec::new());
+                selection.atoms.clear();
+                trajectory.clear();
+                Ok(Value::Null)
+            }
+            method => Err(RpcError {
+                code: METHOD_NOT_FOUND,
+                message: format!("Unknown method '{method}'"),
+            }),
+        };
+
+        if let Err(error) = &outcome {
+            warn!("RPC `{}` failed: {}", request.method, error.message);
+        }
+        if let Some(id) = &request.id {
+            stream.send(
+                *state,
+                ClientMessage::Response(RpcResponse::new(id.clone(), outcome)),
+            );
+        }
+    }
+}
@@ -0,0 +1,200 @@
+// Structure sanity checks
+// Loaded structures are checked for broken coordinates, overlapping atoms and implausible
+// cells; problems are shown in a dismissable banner instead of being rendered silently.
+
+use bevy::prelude::*;
+
+use crate::constants::get_covalent_radius;
+use crate::neighbors::NeighborList;
+use crate::structure::Crystal;
+use crate::widgets::spawn_button;
+
+// Atoms closer than this fraction of the sum of their covalent radii overlap
+const OVERLAP_FACTOR: f32 = 0.5;
+// Cell volume per atom (Å³) outside this range is implausible
+const MIN_VOLUME_PER_ATOM: f32 = 1.0;
+const MAX_CELL_VOLUME: f32 = 1.0e7;
+
+/// Problems found in the current structure.
+#[derive(Resource, Default)]
+pub(crate) struct StructureWarnings {
+    pub messages: Vec<String>,
+    /// The banner was dismissed for the current warnings.
+    pub dismissed: bool,
+}
+
+/// Banner listing the structure warnings.
+#[derive(Component)]
+pub(crate) struct WarningBanner;
+
+/// Text of the warning banner.
+#[derive(Component)]
+pub(crate) struct WarningText;
+
+/// Button hiding the banner until the warnings change.
+#[derive(Component)]
+pub(crate) struct DismissWarningsButton;
+
+pub(crate) fn check_structure(crystal: &Crystal) -> Vec<String> {
+    let mut messages = Vec::new();
+
+    let broken: Vec<usize> = crystal
+        .atoms
+        .iter()
+        .enumerate()
+        .filter(|(_, atom)| !atom.position().is_finite())
+        .map(|(i, _)| i)
+        .collect();
+    if let Some(&first) = broken.first() {
+        messages.push(format!(
+            "{} atom(s) have NaN or infinite coordinates (first: #{first})",
+            broken.len()
+        ));
+    }
+
+    if let Some(lattice) = crystal.lattice {
+        let volume = lattice.determinant().abs();
+        if !volume.is_finite() || volume < 1e-3 {
+            messages.push("Cell is degenerate (zero volume)".to_string());
+        } else if volume > MAX_CELL_VOLUME {
+            messages.push(format!("Cell volume {volume:.3e} Å³ is implausibly large"));
+        } else if volume / (crystal.atoms.len().max(1) as f32) < MIN_VOLUME_PER_ATOM {
+            messages.push(format!(
+                "Cell volume {volume:.2} Å³ is too small for {} atoms",
+                crystal.atoms.len()
+            ));
+        }
+    }
+
+    // overlaps are only meaningful when every position is usable
+    if broken.is_empty() {
+        let max_radius = crystal
+            .atoms
+            .iter()
+            .map(|atom| get_covalent_radius(atom.element))
+            .fold(0.0, f32::max);
+        let list = NeighborList::from_crystal(crystal, 2.0 * max_radius * OVERLAP_FACTOR);
+        let mut overlaps = 0;
+        let mut closest: Option<(usize, usize, f32)> = None;
+        for (i, neighbor) in list.pairs() {
+            let limit = OVERLAP_FACTOR
+                * (get_covalent_radius(crystal.atoms[i].element)
+                    + get_covalent_radius(crystal.atoms[neighbor.index].element));
+            if neighbor.distance < limit {
+                overlaps += 1;
+                if closest.is_none_or(|(_, _, d)| neighbor.distance < d) {
+                    closest = Some((i, neighbor.index, neighbor.distance));
+                }
+            }
+        }
+        if let Some((i, j, distance)) = closest {
+            messages.push(format!(
+                "{overlaps} pair(s) of overlapping atoms (closest: #{i} and #{j} at {distance:.3} Å)"
+            ));
+        }
+    }
+
+    messages
+}
+
+// Spawn the (hidden) warning banner centered at the top of the window
+pub(crate) fn setup_warning_banner(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(8.0),
+                width: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            // the full-width row must not swallow clicks meant for the atoms
+            Pickable::IGNORE,
+        ))
+        .with_children(|row| {
+            row.spawn((
+                Node {
+                    display: Display::None,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::FlexStart,
+                    row_gap: Val::Px(6.0),
+                    padding: UiRect::all(Val::Px(8.0)),
+                    border: UiRect::all(Val::Px(1.0)),
+                    ..default()
+                },
+                BackgroundColor(Color::srgba(0.35, 0.25, 0.05, 0.9)),
+                BorderColor(Color::srgb(0.9, 0.7, 0.2)),
+                WarningBanner,
+            ))
+            .with_children(|banner| {
+                banner.spawn((
+                    Text::new(""),
+                    TextFont {
+                        font: default(),
+                        font_size: 12.0,
+                        ..default()
+                    },
+                    TextColor(Color::WHITE),
+                    WarningText,
+                ));
+                spawn_button(banner, "Dismiss", DismissWarningsButton);
+            });
+        });
+}
+
+// Re-check the structure whenever it changes
+pub(crate) fn update_structure_warnings(
+    crystal: Res<Crystal>,
+    mut warnings: ResMut<StructureWarnings>,
+) {
+    if !crystal.is_changed() {
+        return;
+    }
+    let messages = check_structure(&crystal);
+    // streamed frames re-trigger the check; only report, and show a dismissed banner again,
+    // when the verdict changes
+    if warnings.messages != messages {
+        for message in &messages {
+            warn!("Structure check: {message}");
+        }
+        warnings.messages = messages;
+        warnings.dismissed = false;
+    }
+}
+
+// Show the banner while there are warnings that weren't dismissed
+pub(crate) fn refresh_warning_banner(
+    warnings: Res<StructureWarnings>,
+    mut banners: Query<&mut Node, With<WarningBanner>>,
+    mut texts: Query<&mut Text, With<WarningText>>,
+) {
+    if !warnings.is_changed() {
+        return;
+    }
+
+    for mut node in &mut banners {
+        node.display = if warnings.messages.is_empty() || warnings.dismissed {
+            Display::None
+        } else {
+            Display::Flex
+        };
+    }
+    let text = warnings
+        .messages
+        .iter()
+        .map(|message| format!("Warning: {message}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    for mut content in &mut texts {
+        content.0 = text.clone();
+    }
+}
+
+pub(crate) fn dismiss_warnings_button(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<DismissWarningsButton>)>,
+    mut warnings: ResMut<StructureWarnings>,
+) {
+    if interactions.iter().any(|i| *i == Interaction::Pressed) {
+        warnings.dismissed = true;
+    }
+}
@@ -0,0 +1,422 @@
+// Rhai scripting console (`scripting` feature)
+// The Script panel runs one-line scripts, and `--script recipe.rhai` runs files at startup, so
+// a view can be reproduced. Scripts see a copy of the structure and their changes are applied
+// once they finish:
+//   atom_count(), atom(i), atoms()      atoms as #{index, element, x, y, z}
+//   select(|a| a.element == "O")        pick atoms by predicate; returns how many
+//   selected(), clear_selection()
+//   translate(x, y, z)                  move every atom
+//   set_color("O", "#ff2020"), set_radius("O", 0.5), reset_element("O")
+//   color_scheme("vesta"), color_by("coordination")
+//   camera([x, y, z], [x, y, z]), look_at([x, y, z]), fit_view()
+//   export_xyz("out.xyz")
+//   screenshot("out.png")               without the UI, see figure.rs; with the axis gizmo
+//   screenshot("out.png", true)         as well
+// `print` goes to the log.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use bevy::prelude::*;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, FnPtr, Map, NativeCallContext, INT};
+
+use crate::cli::Cli;
+use crate::color::{ColorBy, ColorScheme, ElementOverrides};
+use crate::constants::Element;
+use crate::figure::SaveFigure;
+use crate::io::save_text_file;
+use crate::parse::write_xyz;
+use crate::structure::{Atom, Crystal, Selection};
+use crate::theme::{Themed, UiTheme};
+use crate::ui::{CameraRig, FitView, MainCamera, ToggleId, ToggledPanel, ToolPanelRow};
+use crate::widgets::{entered_values, spawn_button, spawn_text_field, TextField, TextSubmitted};
+
+// Operations a script cannot run away with
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// Scripts waiting to run, as (name, source).
+#[derive(Resource, Default)]
+pub(crate) struct ScriptQueue(pub Vec<(String, String)>);
+
+/// Text field of the Script panel.
+#[derive(Component)]
+pub(crate) struct ScriptField;
+
+/// Run button of the Script panel.
+#[derive(Component)]
+pub(crate) struct RunScriptButton;
+
+/// Result or error of the last script.
+#[derive(Component)]
+pub(crate) struct ScriptOutput;
+
+// Element edit requested by a script
+enum ElementEdit {
+    Color(Color),
+    Radius(f32),
+    Reset,
+}
+
+// Copy of the viewer state a script works on, and the changes it asks for
+#[derive(Default)]
+struct ScriptState {
+    crystal: Option<Crystal>,
+    moved: bool,
+    selection: Option<Vec<usize>>,
+    element_edits: Vec<(Element, ElementEdit)>,
+    color_scheme: Option<ColorScheme>,
+    color_by: Option<ColorBy>,
+    camera_position: Option<Vec3>,
+    camera_target: Option<Vec3>,
+    fit: bool,
+    screenshots: Vec<SaveFigure>,
+}
+
+type Shared = Rc<RefCell<ScriptState>>;
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+fn structure(state: &Shared) -> std::cell::RefMut<'_, Crystal> {
+    std::cell::RefMut::map(state.borrow_mut(), |state| {
+        state
+            .crystal
+            .as_mut()
+            .expect("the structure is copied before a script runs")
+    })
+}
+
+fn atom_map(index: usize, atom: &Atom) -> Map {
+    let mut map = Map::new();
+    map.insert("index".into(), (index as INT).into());
+    map.insert("element".into(), atom.element.symbol().into());
+    map.insert("x".into(), Dynamic::from_float(atom.x.into()));
+    map.insert("y".into(), Dynamic::from_float(atom.y.into()));
+    map.insert("z".into(), Dynamic::from_float(atom.z.into()));
+    map
+}
+
+fn vector(values: Array) -> ScriptResult<Vec3> {
+    let components: Vec<f32> = values
+        .into_iter()
+        .map(|value| {
+            value
+                .as_float()
+                .map(|v| v as f32)
+                .or_else(|_| value.as_int().map(|v| v as f32))
+        })
+        .collect::<Result<_, _>>()
+        .map_err(|_| "expected numbers in [x, y, z]")?;
+    match components[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err("expected [x, y, z]".into()),
+    }
+}
+
+fn element(symbol: &str) -> ScriptResult<Element> {
+    Element::from_symbol(symbol).ok_or_else(|| format!("unknown element '{symbol}'").into())
+}
+
+// Engine with the viewer functions bound to `state`
+fn engine(state: &Shared) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.on_print(|text| info!("script: {text}"));
+    engine.on_debug(|text, _, position| debug!("script {position}: {text}"));
+
+    let s = state.clone();
+    engine.register_fn("atom_count", move || structure(&s).atoms.len() as INT);
+    let s = state.clone();
+    engine.register_fn("atom", move |index: INT| -> ScriptResult<Map> {
+        let crystal = structure(&s);
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| crystal.atoms.get(i).map(|atom| atom_map(i, atom)))
+            .ok_or_else(|| format!("no atom {index}").into())
+    });
+    let s = state.clone();
+    engine.register_fn("atoms", move || -> Array {
+        let crystal = structure(&s);
+        crystal
+            .atoms
+            .iter()
+            .enumerate()
+            .map(|(i, atom)| atom_map(i, atom).into())
+            .collect()
+    });
+
+    let s = state.clone();
+    engine.register_fn(
+        "select",
+        move |context: NativeCallContext, predicate: FnPtr| -> ScriptResult<INT> {
+            // no borrow is held while the predicate runs, since it may call back in
+            let atoms = structure(&s).atoms.clone();
+            let mut picked = Vec::new();
+            for (i, atom) in atoms.iter().enumerate() {
+                if predicate.call_within_context::<bool>(&context, (atom_map(i, atom),))? {
+                    picked.push(i);
+                }
+            }
+            let count = picked.len() as INT;
+            s.borrow_mut().selection = Some(picked);
+            Ok(count)
+        },
+    );
+    let s = state.clone();
+    engine.register_fn("selected", move || -> Array {
+        let state = s.borrow();
+        state
+            .selection
+            .iter()
+            .flatten()
+            .map(|&i| (i as INT).into())
+            .collect()
+    });
+    let s = state.clone();
+    engine.register_fn("clear_selection", move || {
+        s.borrow_mut().selection = Some(Vec::new());
+    });
+
+    let s = state.clone();
+    engine.register_fn("translate", move |x: f64, y: f64, z: f64| {
+        structure(&s).translate(Vec3::new(x as f32, y as f32, z as f32));
+        s.borrow_mut().moved = true;
+    });
+
+    let s = state.clone();
+    engine.register_fn(
+        "set_color",
+        move |symbol: &str, hex: &str| -> ScriptResult<()> {
+            let color = Srgba::hex(hex).map_err(|e| format!("bad color '{hex}': {e}"))?;
+            let edit = (element(symbol)?, ElementEdit::Color(color.into()));
+            s.borrow_mut().element_edits.push(edit);
+            Ok(())
+        },
+    );
+    let s = state.clone();
+    engine.register_fn(
+        "set_radius",
+        move |symbol: &str, radius: f64| -> ScriptResult<()> {
+            let edit = (element(symbol)?, ElementEdit::Radius(radius as f32));
+            s.borrow_mut().element_edits.push(edit);
+            Ok(())
+        },
+    );
+    let s = state.clone();
+    engine.register_fn("reset_element", move |symbol: &str| -> ScriptResult<()> {
+        let edit = (element(symbol)?, ElementEdit::Reset);
+        s.borrow_mut().element_edits.push(edit);
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("color_scheme", move |name: &str| -> ScriptResult<()> {
+        let scheme = ColorScheme::ALL
+            .into_iter()
+            .find(|scheme| scheme.label().eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("unknown color scheme '{name}'"))?;
+        s.borrow_mut().color_scheme = Some(scheme);
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("color_by", move |name: &str| -> ScriptResult<()> {
+        let by = ColorBy::ALL
+            .into_iter()
+            .find(|by| by.label().eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("unknown coloring '{name}'"))?;
+        s.borrow_mut().color_by = Some(by);
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn(
+        "camera",
+        move |position: Array, target: Array| -> ScriptResult<()> {
+            let mut state = s.borrow_mut();
+            state.camera_position = Some(vector(position)?);
+            state.camera_target = Some(vector(target)?);
+            Ok(())
+        },
+    );
+    let s = state.clone();
+    engine.register_fn("look_at", move |target: Array| -> ScriptResult<()> {
+        s.borrow_mut().camera_target = Some(vector(target)?);
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("fit_view", move || {
+        s.borrow_mut().fit = true;
+    });
+
+    let s = state.clone();
+    engine.register_fn("export_xyz", move |path: &str| -> ScriptResult<String> {
+        let contents = write_xyz(&structure(&s));
+        save_text_file(path, &contents).map_err(|e| format!("{e:#}").into())
+    });
+    let s = state.clone();
+    engine.register_fn("screenshot", move |path: &str| {
+        s.borrow_mut().screenshots.push(SaveFigure {
+            path: path.to_string(),
+            axes: false,
+        });
+    });
+    let s = state.clone();
+    engine.register_fn("screenshot", move |path: &str, axes: bool| {
+        s.borrow_mut().screenshots.push(SaveFigure {
+            path: path.to_string(),
+            axes,
+        });
+    });
+
+    engine
+}
+
+// Queue the script files named on the command line
+pub(crate) fn queue_script_arguments(cli: Res<Cli>, mut queue: ResMut<ScriptQueue>) {
+    for path in &cli.scripts {
+        match std::fs::read_to_string(path) {
+            Ok(source) => queue.0.push((path.display().to_string(), source)),
+            Err(e) => error!("Cannot read script {}: {e}", path.display()),
+        }
+    }
+}
+
+// Spawn the (hidden) Script panel in the tool row
+pub(crate) fn setup_script_panel(mut commands: Commands, row: Single<Entity, With<ToolPanelRow>>) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            ToggledPanel(ToggleId::Script),
+            ChildOf(*row),
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                Text::new("Script"),
+                TextFont {
+                    font: default(),
+                    font_size: 14.0,
+                    ..default()
+                },
+                Themed::Text,
+            ));
+            panel
+                .spawn(Node {
+                    flex_direction: FlexDirection::Row,
+                    column_gap: Val::Px(4.0),
+                    ..default()
+                })
+                .with_children(|row| {
+                    spawn_text_field(row, "select(|a| a.element == \"O\")", 320.0, ScriptField);
+                    spawn_button(row, "Run", RunScriptButton);
+                });
+            panel.spawn((
+                Text::new(""),
+                TextFont {
+                    font: default(),
+                    font_size: 12.0,
+                    ..default()
+                },
+                Themed::Text,
+                ScriptOutput,
+            ));
+        });
+}
+
+// Queue the console line on Enter or a click on Run
+pub(crate) fn script_console_actions(
+    buttons: Query<&Interaction, (Changed<Interaction>, With<RunScriptButton>)>,
+    fields: Query<&TextField, With<ScriptField>>,
+    mut submitted: EventReader<TextSubmitted>,
+    mut queue: ResMut<ScriptQueue>,
+) {
+    let pressed = buttons.iter().any(|i| *i == Interaction::Pressed);
+    for source in entered_values(&mut submitted, &fields, pressed) {
+        queue.0.push(("console".to_string(), source));
+    }
+}
+
+// Run queued scripts and apply what they changed
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn run_scripts(
+    mut queue: ResMut<ScriptQueue>,
+    mut crystal: ResMut<Crystal>,
+    mut selection: ResMut<Selection>,
+    mut overrides: ResMut<ElementOverrides>,
+    mut color_scheme: ResMut<ColorScheme>,
+    mut color_by: ResMut<ColorBy>,
+    mut camera: Single<&mut Transform, With<MainCamera>>,
+    mut camera_rig: ResMut<CameraRig>,
+    mut fit: EventWriter<FitView>,
+    mut figures: EventWriter<SaveFigure>,
+    mut outputs: Query<(&mut Text, &mut TextColor), With<ScriptOutput>>,
+    theme: Res<UiTheme>,
+) {
+    for (name, source) in std::mem::take(&mut queue.0) {
+        let state = Rc::new(RefCell::new(ScriptState {
+            crystal: Some(crystal.clone()),
+            ..default()
+        }));
+        let result = engine(&state).eval::<Dynamic>(&source);
+        let (message, color) = match &result {
+            Ok(value) if value.is_unit() => {
+                info!("Ran script {name}");
+                ("ok".to_string(), theme.positive)
+            }
+            Ok(value) => {
+                info!("Script {name} returned {value}");
+                (format!("=> {value}"), theme.positive)
+            }
+            Err(e) => {
+                error!("Script {name} failed: {e}");
+                (e.to_string(), theme.negative)
+            }
+        };
+        for (mut text, mut text_color) in &mut outputs {
+            text.0 = message.clone();
+            text_color.0 = color;
+        }
+        // a failed script still applies what it did before the error, like a console would
+        let state = Rc::try_unwrap(state)
+            .map(RefCell::into_inner)
+            .unwrap_or_else(|shared| std::mem::take(&mut *shared.borrow_mut()));
+
+        if state.moved {
+            if let Some(edited) = state.crystal {
+                *crystal = edited;
+            }
+        }
+        if let Some(picked) = state.selection {
+            selection.atoms = picked;
+        }
+        for (element, edit) in state.element_edits {
+            match edit {
+                ElementEdit::Color(color) => overrides.get_mut(element).color = Some(color),
+                ElementEdit::Radius(radius) => overrides.get_mut(element).radius = Some(radius),
+                ElementEdit::Reset => overrides.clear(element),
+            }
+        }
+        if let Some(scheme) = state.color_scheme {
+            *color_scheme = scheme;
+        }
+        if let Some(by) = state.color_by {
+            *color_by = by;
+        }
+        if state.camera_position.is_some() || state.camera_target.is_some() {
+            let target = state.camera_target.unwrap_or(camera_rig.target());
+            // without a position the camera keeps its offset from the target
+            let position = state
+                .camera_position
+                .unwrap_or(target + camera.translation - camera_rig.target());
+            camera_rig.place(&mut camera, position, target);
+        }
+        if state.fit {
+            fit.write(FitView);
+        }
+        figures.write_batch(state.screenshots);
+    }
+}
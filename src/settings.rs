@@ -0,0 +1,427 @@
+// Render settings panel
+// Atom size, bonds, bond tolerance, contacts, background, fog, depth of field, quality and mouse
+// sensitivity in one place, stepped like the lattice parameters. The panel edits a copy of the
+// settings, which is then applied to the resources the viewer reads; the configuration file
+// gives the initial values.
+// The atom size is shown as the fraction of the van der Waals radius the atoms are drawn with;
+// the quality also sets the sphere detail, which can then be stepped on its own, rebuilding the
+// sphere mesh the atoms share. Bonds and contacts are drawn as described in bonds.rs.
+// Fog fades far atoms into the background for a sense of depth, and follows the camera distance
+// so that it looks the same at every zoom. Depth of field does the same with a blur, keeping the
+// camera target sharp for the shallow-focus look of renders in talks; it needs depth textures
+// that can be sampled, which WebGL2 lacks, so WebGL2 builds have no depth of field row.
+// Shadows are switched on and off with their toggle button; their quality is the size of the
+// shadow map. The lights themselves come from a preset of the lighting rig, see lighting.rs.
+
+#[cfg(not(all(target_arch = "wasm32", feature = "webgl2")))]
+use bevy::core_pipeline::dof::DepthOfField;
+use bevy::pbr::{DirectionalLightShadowMap, DistanceFog, FogFalloff};
+use bevy::prelude::*;
+
+use crate::analysis::BondTolerance;
+use crate::bonds::{BondStyle, ContactCutoff};
+use crate::constants::ATOM_SIZE_SCALE;
+use crate::lighting::{LightingPreset, LightingRig};
+use crate::theme::Themed;
+use crate::ui::{
+    AtomScale, CameraRig, MainCamera, MouseSensitivity, RenderQuality, SidePanelColumn,
+    SphereDetail, ToggleId, ToggledPanel,
+};
+use crate::widgets::{spawn_stepper_row, StepperSettings};
+
+// Backgrounds to step through; the first is Bevy's default clear color
+const BACKGROUNDS: [(&str, Color); 4] = [
+    ("dark", Color::srgb_u8(43, 44, 47)),
+    ("black", Color::BLACK),
+    ("gray", Color::srgb(0.5, 0.5, 0.5)),
+    ("white", Color::WHITE),
+];
+
+// Cutoffs of the dashed contacts, in sums of covalent radii like the bond tolerance
+const CONTACT_LEVELS: [(&str, Option<f32>); 4] = [
+    ("off", None),
+    ("1.5x", Some(1.5)),
+    ("2.0x", Some(2.0)),
+    ("2.5x", Some(2.5)),
+];
+
+// Fog strengths, as where the fog starts and where it hides everything, in camera distances
+const FOG_LEVELS: [(&str, Option<(f32, f32)>); 3] = [
+    ("off", None),
+    ("light", Some((0.6, 2.2))),
+    ("strong", Some((0.6, 1.5))),
+];
+
+// Depth of field strengths, as the lens f-stops times the camera distance: the lens opens up as
+// the camera comes closer, so that the blur looks the same at every zoom
+const DOF_LEVELS: [(&str, Option<f32>); 3] =
+    [("off", None), ("subtle", Some(1.0)), ("strong", Some(0.4))];
+// Anything farther than this many camera distances, like the background, is blurred as if it
+// were there
+#[cfg(not(all(target_arch = "wasm32", feature = "webgl2")))]
+const DOF_MAX_DEPTH: f32 = 3.0;
+// Whether the renderer can do depth of field at all
+const DOF_SUPPORTED: bool = cfg!(not(all(target_arch = "wasm32", feature = "webgl2")));
+
+// Shadow qualities, as the side of the shadow map; Bevy's default is the middle one
+const SHADOW_QUALITIES: [(&str, usize); 3] = [("low", 1024), ("medium", 2048), ("high", 4096)];
+
+const QUALITIES: [RenderQuality; 3] = [
+    RenderQuality::Low,
+    RenderQuality::Medium,
+    RenderQuality::High,
+];
+
+// Step and range of the atom size multiplier; 4x draws the atoms at their full van der Waals
+// radius, space-filling
+const ATOM_SCALE_STEP: f32 = 0.2;
+const ATOM_SCALE_RANGE: (f32, f32) = (0.2, 4.0);
+// Range of the icosphere subdivisions; the high quality uses 10
+const SPHERE_DETAIL_RANGE: (u32, u32) = (1, 20);
+// Step and range of the bond tolerance
+const TOLERANCE_STEP: f32 = 0.05;
+const TOLERANCE_RANGE: (f32, f32) = (1.0, 1.6);
+// Step and range of the mouse sensitivity
+const SENSITIVITY_STEP: f32 = 0.1;
+const SENSITIVITY_RANGE: (f32, f32) = (0.1, 5.0);
+
+/// Settings shown in the panel; `apply_settings` hands them to the viewer.
+#[derive(Resource)]
+pub(crate) struct SettingsEditor {
+    atom_scale: f32,
+    bonds: BondStyle,
+    bond_tolerance: f32,
+    /// Index into `CONTACT_LEVELS`.
+    contacts: usize,
+    /// Index into `BACKGROUNDS`; None for a color from the configuration file.
+    background: Option<usize>,
+    /// Index into `FOG_LEVELS`.
+    fog: usize,
+    /// Index into `DOF_LEVELS`.
+    depth_of_field: usize,
+    quality: RenderQuality,
+    sphere_detail: u32,
+    /// Index into `SHADOW_QUALITIES`.
+    shadows: usize,
+    lighting: LightingPreset,
+    sensitivity: f32,
+}
+
+// `value` moved by `direction` steps, kept in `range` and rounded to the step
+fn stepped(value: f32, step: f32, direction: i32, (min, max): (f32, f32)) -> f32 {
+    let value = value + direction as f32 * step;
+    ((value / step).round() * step).clamp(min, max)
+}
+
+// `index` moved by `direction`, kept below `count`
+pub(crate) fn stepped_index(index: usize, direction: i32, count: usize) -> usize {
+    index
+        .saturating_add_signed(direction as isize)
+        .min(count - 1)
+}
+
+impl StepperSettings for SettingsEditor {
+    fn step(&mut self, field: usize, direction: i32) {
+        match field {
+            0 => {
+                self.atom_scale = stepped(
+                    self.atom_scale,
+                    ATOM_SCALE_STEP,
+                    direction,
+                    ATOM_SCALE_RANGE,
+                )
+            }
+            1 => {
+                let index = BondStyle::ALL
+                    .iter()
+                    .position(|b| *b == self.bonds)
+                    .unwrap_or(0);
+                self.bonds = BondStyle::ALL[stepped_index(index, direction, BondStyle::ALL.len())];
+            }
+            2 => {
+                self.bond_tolerance = stepped(
+                    self.bond_tolerance,
+                    TOLERANCE_STEP,
+                    direction,
+                    TOLERANCE_RANGE,
+                )
+            }
+            3 => self.contacts = stepped_index(self.contacts, direction, CONTACT_LEVELS.len()),
+            4 => {
+                self.background = Some(match self.background {
+                    Some(index) => stepped_index(index, direction, BACKGROUNDS.len()),
+                    None => 0,
+                })
+            }
+            5 => self.fog = stepped_index(self.fog, direction, FOG_LEVELS.len()),
+            6 => {
+                self.depth_of_field =
+                    stepped_index(self.depth_of_field, direction, DOF_LEVELS.len())
+            }
+            7 => {
+                let index = QUALITIES
+                    .iter()
+                    .position(|q| *q == self.quality)
+                    .unwrap_or(1);
+                self.quality = QUALITIES[stepped_index(index, direction, QUALITIES.len())];
+                self.sphere_detail = self.quality.subdivisions();
+            }
+            8 => {
+                let (min, max) = SPHERE_DETAIL_RANGE;
+                self.sphere_detail = self
+                    .sphere_detail
+                    .saturating_add_signed(direction)
+                    .clamp(min, max);
+            }
+            9 => self.shadows = stepped_index(self.shadows, direction, SHADOW_QUALITIES.len()),
+            10 => {
+                let index = LightingPreset::ALL
+                    .iter()
+                    .position(|p| *p == self.lighting)
+                    .unwrap_or(0);
+                self.lighting =
+                    LightingPreset::ALL[stepped_index(index, direction, LightingPreset::ALL.len())];
+            }
+            _ => {
+                self.sensitivity = stepped(
+                    self.sensitivity,
+                    SENSITIVITY_STEP,
+                    direction,
+                    SENSITIVITY_RANGE,
+                )
+            }
+        }
+    }
+
+    fn value_text(&self, field: usize) -> String {
+        match field {
+            0 => format!("{:.2} vdW", self.atom_scale * ATOM_SIZE_SCALE),
+            1 => self.bonds.label().to_string(),
+            2 => format!("{:.2}", self.bond_tolerance),
+            3 => CONTACT_LEVELS[self.contacts].0.to_string(),
+            4 => self
+                .background
+                .map_or("custom", |index| BACKGROUNDS[index].0)
+                .to_string(),
+            5 => FOG_LEVELS[self.fog].0.to_string(),
+            6 => DOF_LEVELS[self.depth_of_field].0.to_string(),
+            7 => format!("{:?}", self.quality).to_lowercase(),
+            8 => self.sphere_detail.to_string(),
+            9 => SHADOW_QUALITIES[self.shadows].0.to_string(),
+            10 => self.lighting.label().to_string(),
+            _ => format!("{:.1}x", self.sensitivity),
+        }
+    }
+}
+
+// Spawn the (hidden) settings panel in the side column, starting from the values in effect
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn setup_settings_panel(
+    mut commands: Commands,
+    column: Single<Entity, With<SidePanelColumn>>,
+    scale: Res<AtomScale>,
+    bonds: Res<BondStyle>,
+    tolerance: Res<BondTolerance>,
+    contacts: Res<ContactCutoff>,
+    clear_color: Res<ClearColor>,
+    quality: Res<RenderQuality>,
+    detail: Res<SphereDetail>,
+    shadow_map: Res<DirectionalLightShadowMap>,
+    lighting: Res<LightingRig>,
+    sensitivity: Res<MouseSensitivity>,
+) {
+    let background = clear_color.0.to_srgba().to_u8_array();
+    let editor = SettingsEditor {
+        atom_scale: scale.0,
+        bonds: *bonds,
+        bond_tolerance: tolerance.0,
+        contacts: CONTACT_LEVELS
+            .iter()
+            .position(|(_, cutoff)| *cutoff == contacts.0)
+            .unwrap_or(0),
+        background: BACKGROUNDS
+            .iter()
+            .position(|(_, color)| color.to_srgba().to_u8_array() == background),
+        fog: 0,
+        depth_of_field: 0,
+        quality: *quality,
+        sphere_detail: detail.0,
+        shadows: SHADOW_QUALITIES
+            .iter()
+            .position(|(_, size)| *size == shadow_map.size)
+            .unwrap_or(1),
+        lighting: lighting.preset,
+        sensitivity: sensitivity.0,
+    };
+
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            ToggledPanel(ToggleId::Settings),
+            ChildOf(*column),
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                Text::new("Settings"),
+                TextFont {
+                    font: default(),
+                    font_size: 14.0,
+                    ..default()
+                },
+                Themed::Text,
+            ));
+            for (field, label) in [
+                "Atom size",
+                "Bonds",
+                "Bond tolerance",
+                "Contacts",
+                "Background",
+                "Fog",
+                "Depth of field",
+                "Quality",
+                "Sphere detail",
+                "Shadows",
+                "Lighting",
+                "Mouse",
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                if label == "Depth of field" && !DOF_SUPPORTED {
+                    continue;
+                }
+                spawn_stepper_row(panel, label, field, &editor);
+            }
+        });
+    commands.insert_resource(editor);
+}
+
+// Hand the settings stepped in the panel to the resources the viewer reads
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn apply_settings(
+    editor: Res<SettingsEditor>,
+    mut scale: ResMut<AtomScale>,
+    mut bonds: ResMut<BondStyle>,
+    mut tolerance: ResMut<BondTolerance>,
+    mut contacts: ResMut<ContactCutoff>,
+    mut clear_color: ResMut<ClearColor>,
+    mut quality: ResMut<RenderQuality>,
+    mut detail: ResMut<SphereDetail>,
+    mut shadow_map: ResMut<DirectionalLightShadowMap>,
+    mut lighting: ResMut<LightingRig>,
+    mut sensitivity: ResMut<MouseSensitivity>,
+    mut cameras: Query<&mut Msaa, With<MainCamera>>,
+) {
+    if !editor.is_changed() {
+        return;
+    }
+    scale.set_if_neq(AtomScale(editor.atom_scale));
+    bonds.set_if_neq(editor.bonds);
+    tolerance.set_if_neq(BondTolerance(editor.bond_tolerance));
+    contacts.set_if_neq(ContactCutoff(CONTACT_LEVELS[editor.contacts].1));
+    if let Some(index) = editor.background {
+        if clear_color.0 != BACKGROUNDS[index].1 {
+            clear_color.0 = BACKGROUNDS[index].1;
+        }
+    }
+    if quality.set_if_neq(editor.quality) {
+        for mut msaa in &mut cameras {
+            *msaa = editor.quality.msaa();
+        }
+    }
+    detail.set_if_neq(SphereDetail(editor.sphere_detail));
+    let shadow_size = SHADOW_QUALITIES[editor.shadows].1;
+    if shadow_map.size != shadow_size {
+        shadow_map.size = shadow_size;
+    }
+    if lighting.preset != editor.lighting {
+        lighting.preset = editor.lighting;
+    }
+    sensitivity.set_if_neq(MouseSensitivity(editor.sensitivity));
+}
+
+// Keep the fog around the structure as the camera moves, in the color of the background
+pub(crate) fn update_fog(
+    mut commands: Commands,
+    editor: Res<SettingsEditor>,
+    clear_color: Res<ClearColor>,
+    rig: Option<Res<CameraRig>>,
+    mut cameras: Query<(Entity, &Transform, Option<&mut DistanceFog>), With<MainCamera>>,
+) {
+    let Some(rig) = rig else {
+        return;
+    };
+    for (camera, transform, fog) in &mut cameras {
+        let Some((start, end)) = FOG_LEVELS[editor.fog].1 else {
+            if fog.is_some() {
+                commands.entity(camera).remove::<DistanceFog>();
+            }
+            continue;
+        };
+        let distance = transform.translation.distance(rig.target());
+        let falloff = FogFalloff::Linear {
+            start: start * distance,
+            end: end * distance,
+        };
+        match fog {
+            Some(mut fog) => {
+                fog.color = clear_color.0;
+                fog.falloff = falloff;
+            }
+            None => {
+                commands.entity(camera).insert(DistanceFog {
+                    color: clear_color.0,
+                    falloff,
+                    ..default()
+                });
+            }
+        }
+    }
+}
+
+// Keep the camera target in focus as the camera moves, blurring what is in front or behind
+#[cfg(not(all(target_arch = "wasm32", feature = "webgl2")))]
+pub(crate) fn update_depth_of_field(
+    mut commands: Commands,
+    editor: Res<SettingsEditor>,
+    rig: Option<Res<CameraRig>>,
+    mut cameras: Query<(Entity, &Transform, Option<&mut DepthOfField>), With<MainCamera>>,
+) {
+    let Some(rig) = rig else {
+        return;
+    };
+    for (camera, transform, depth_of_field) in &mut cameras {
+        let Some(aperture) = DOF_LEVELS[editor.depth_of_field].1 else {
+            if depth_of_field.is_some() {
+                commands.entity(camera).remove::<DepthOfField>();
+            }
+            continue;
+        };
+        let distance = transform
+            .translation
+            .distance(rig.target())
+            .max(f32::EPSILON);
+        match depth_of_field {
+            Some(mut depth_of_field) => {
+                depth_of_field.focal_distance = distance;
+                depth_of_field.aperture_f_stops = aperture / distance;
+                depth_of_field.max_depth = DOF_MAX_DEPTH * distance;
+            }
+            None => {
+                commands.entity(camera).insert(DepthOfField {
+                    focal_distance: distance,
+                    aperture_f_stops: aperture / distance,
+                    max_depth: DOF_MAX_DEPTH * distance,
+                    ..default()
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+// Atom IDs for picking: every atom sphere is drawn with its index in the structure plus one, so
+// that zero is left for the background.
+
+#ifdef SPHERE_IMPOSTOR
+#import vizmat::impostor::{impostor_hit, impostor_vertex}
+#endif
+
+struct PickingView {
+    clip_from_world: mat4x4<f32>,
+    view_from_world: mat4x4<f32>,
+    clip_from_view: mat4x4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> picking_view: PickingView;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    // center and radius of the atom
+    @location(3) i_position_radius: vec4<f32>,
+    @location(5) i_index: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) @interpolate(flat) id: u32,
+#ifdef SPHERE_IMPOSTOR
+    @location(1) view_position: vec3<f32>,
+    @location(2) @interpolate(flat) sphere: vec4<f32>,
+#endif
+};
+
+struct FragmentOutput {
+    @location(0) id: u32,
+#ifdef SPHERE_IMPOSTOR
+    @builtin(frag_depth) depth: f32,
+#endif
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+#ifdef SPHERE_IMPOSTOR
+    let impostor = impostor_vertex(
+        vertex.position.xy,
+        vertex.i_position_radius.xyz,
+        vertex.i_position_radius.w,
+        picking_view.view_from_world,
+        picking_view.clip_from_view,
+    );
+    out.clip_position = impostor.clip_position;
+    out.view_position = impostor.view_position;
+    out.sphere = impostor.sphere;
+#else
+    let world_position = vertex.position * vertex.i_position_radius.w + vertex.i_position_radius.xyz;
+    out.clip_position = picking_view.clip_from_world * vec4<f32>(world_position, 1.0);
+#endif
+    out.id = vertex.i_index + 1u;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> FragmentOutput {
+    var out: FragmentOutput;
+#ifdef SPHERE_IMPOSTOR
+    out.depth = impostor_hit(in.view_position, in.sphere, picking_view.clip_from_view).depth;
+#endif
+    out.id = in.id;
+    return out;
+}
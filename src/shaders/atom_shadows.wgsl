@@ -0,0 +1,54 @@
+// Atom spheres drawn into the shadow maps of directional lights: depth only, in the light's
+// orthographic view. Atoms between the light and the near plane of a cascade are clamped onto
+// the near plane instead of being clipped, so that they still cast their shadow.
+
+#import bevy_render::view::View
+#ifdef SPHERE_IMPOSTOR
+#import vizmat::impostor::{impostor_hit, impostor_vertex}
+#endif
+
+@group(0) @binding(0) var<uniform> view: View;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    // center and radius of the atom
+    @location(3) i_position_radius: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+#ifdef SPHERE_IMPOSTOR
+    @location(0) view_position: vec3<f32>,
+    @location(1) @interpolate(flat) sphere: vec4<f32>,
+#endif
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+#ifdef SPHERE_IMPOSTOR
+    let impostor = impostor_vertex(
+        vertex.position.xy,
+        vertex.i_position_radius.xyz,
+        vertex.i_position_radius.w,
+        view.view_from_world,
+        view.clip_from_view,
+    );
+    out.clip_position = impostor.clip_position;
+    out.view_position = impostor.view_position;
+    out.sphere = impostor.sphere;
+#else
+    let world_position = vertex.position * vertex.i_position_radius.w + vertex.i_position_radius.xyz;
+    out.clip_position = view.clip_from_world * vec4<f32>(world_position, 1.0);
+#endif
+    // reversed Z puts the near plane at 1
+    out.clip_position.z = min(out.clip_position.z, 1.0);
+    return out;
+}
+
+#ifdef SPHERE_IMPOSTOR
+@fragment
+fn fragment(in: VertexOutput) -> @builtin(frag_depth) f32 {
+    return min(impostor_hit(in.view_position, in.sphere, view.clip_from_view).depth, 1.0);
+}
+#endif
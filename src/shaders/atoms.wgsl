@@ -0,0 +1,140 @@
+// Atoms drawn as instances of one unit sphere, each moved, scaled and colored by its instance
+// attributes. Lit with Blinn-Phong by the directional lights of the lighting rig and the ambient
+// light, and shadowed by the lights' shadow maps, which atom_shadows.wgsl draws the atoms into.
+// The material of the element shapes the highlights: rough surfaces spread them, metals tint
+// them with their color and darken their diffuse light, and emissive ones glow in their color
+// without any light.
+// With SPHERE_IMPOSTOR the instanced mesh is a quad and the sphere is ray-traced on it.
+// Linear distance fog on the camera, the only kind the settings panel sets, fades atoms into
+// the background.
+
+#import bevy_pbr::mesh_view_bindings::{view, fog, lights}
+#import bevy_pbr::mesh_view_types::{DIRECTIONAL_LIGHT_FLAGS_SHADOWS_ENABLED_BIT, FOG_MODE_LINEAR}
+#import bevy_pbr::fog::linear_fog
+#import bevy_pbr::shadows::fetch_directional_shadow
+#import bevy_pbr::view_transformations::position_world_to_clip
+#ifdef TONEMAP_IN_SHADER
+#import bevy_core_pipeline::tonemapping::tone_mapping
+#endif
+#ifdef SPHERE_IMPOSTOR
+#import vizmat::impostor::{impostor_hit, impostor_vertex}
+#endif
+
+// Light intensities are relative to the headlight preset: its 10000 lux and ambient brightness
+// of 0.3 give the shading of a plain headlight
+const REFERENCE_ILLUMINANCE: f32 = 10000.0;
+const AMBIENT: f32 = 0.35 / 0.3;
+const DIFFUSE: f32 = 0.9;
+const SPECULAR: f32 = 0.25;
+// Diffuse light left on a fully metallic surface
+const METAL_DIFFUSE: f32 = 0.2;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    // center and radius of the atom
+    @location(3) i_position_radius: vec4<f32>,
+    @location(4) i_color: vec4<f32>,
+    // metallic, roughness and emissive
+    @location(6) i_material: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // view space for impostors
+    @location(0) world_position: vec3<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) color: vec4<f32>,
+#ifdef SPHERE_IMPOSTOR
+    @location(3) @interpolate(flat) sphere: vec4<f32>,
+#endif
+    @location(4) @interpolate(flat) material: vec3<f32>,
+};
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+#ifdef SPHERE_IMPOSTOR
+    @builtin(frag_depth) depth: f32,
+#endif
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+#ifdef SPHERE_IMPOSTOR
+    let impostor = impostor_vertex(
+        vertex.position.xy,
+        vertex.i_position_radius.xyz,
+        vertex.i_position_radius.w,
+        view.view_from_world,
+        view.clip_from_view,
+    );
+    out.clip_position = impostor.clip_position;
+    out.world_position = impostor.view_position;
+    out.sphere = impostor.sphere;
+#else
+    let world_position = vertex.position * vertex.i_position_radius.w + vertex.i_position_radius.xyz;
+    out.clip_position = position_world_to_clip(world_position);
+    out.world_position = world_position;
+    out.world_normal = vertex.normal;
+#endif
+    out.color = vertex.i_color;
+    out.material = vertex.i_material;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> FragmentOutput {
+    var out: FragmentOutput;
+#ifdef SPHERE_IMPOSTOR
+    let hit = impostor_hit(in.world_position, in.sphere, view.clip_from_view);
+    let normal = hit.normal;
+    let to_camera = hit.to_camera;
+    out.depth = hit.depth;
+    let distance = length(hit.view_position);
+    // shadow maps are looked up in world space
+    let world_position = view.world_from_view * vec4<f32>(hit.view_position, 1.0);
+    let world_normal = (view.world_from_view * vec4<f32>(normal, 0.0)).xyz;
+    let view_z = hit.view_position.z;
+#else
+    let normal = normalize(in.world_normal);
+    let to_camera = normalize(view.world_position - in.world_position);
+    let distance = length(view.world_position - in.world_position);
+    let world_position = vec4<f32>(in.world_position, 1.0);
+    let world_normal = normal;
+    let view_z = (view.view_from_world * world_position).z;
+#endif
+    let metallic = in.material.x;
+    // a roughness of 0.5 gives the shininess of 32 the atoms had before materials
+    let shininess = exp2(9.0 - 8.0 * in.material.y);
+    var diffuse = AMBIENT * lights.ambient_color.rgb;
+    var specular = vec3<f32>(0.0);
+    for (var i = 0u; i < lights.n_directional_lights; i += 1u) {
+        let light = &lights.directional_lights[i];
+#ifdef SPHERE_IMPOSTOR
+        // impostors are shaded in view space
+        let to_light = normalize((view.view_from_world * vec4<f32>((*light).direction_to_light, 0.0)).xyz);
+#else
+        let to_light = (*light).direction_to_light;
+#endif
+        var intensity = (*light).color.rgb / REFERENCE_ILLUMINANCE;
+        if ((*light).flags & DIRECTIONAL_LIGHT_FLAGS_SHADOWS_ENABLED_BIT) != 0u {
+            intensity *= fetch_directional_shadow(i, world_position, world_normal, view_z);
+        }
+        diffuse += DIFFUSE * max(dot(normal, to_light), 0.0) * intensity;
+        let half_way = normalize(to_light + to_camera);
+        specular += pow(max(dot(normal, half_way), 0.0), shininess) * intensity;
+    }
+    // metals reflect in their own color, other surfaces in the color of the light
+    let highlight = mix(vec3<f32>(SPECULAR), in.color.rgb, metallic);
+    let lit = in.color.rgb * (diffuse * mix(1.0, METAL_DIFFUSE, metallic) + in.material.z);
+    var color = vec4<f32>(lit + highlight * specular, in.color.a);
+    if fog.mode == FOG_MODE_LINEAR {
+        color = linear_fog(fog, color, distance, vec3<f32>(0.0));
+    }
+#ifdef TONEMAP_IN_SHADER
+    color = tone_mapping(color, view.color_grading);
+#endif
+    out.color = color;
+    return out;
+}
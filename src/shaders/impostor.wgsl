@@ -0,0 +1,90 @@
+// Atom spheres as impostors: a quad facing the camera that just covers the sphere's outline,
+// on which every fragment casts its view ray against the exact sphere.
+
+#define_import_path vizmat::impostor
+
+struct ImpostorVertex {
+    clip_position: vec4<f32>,
+    // point of the quad, and center and radius of the sphere, in view space
+    view_position: vec3<f32>,
+    sphere: vec4<f32>,
+};
+
+struct ImpostorHit {
+    view_position: vec3<f32>,
+    normal: vec3<f32>,
+    // unit vector from the surface towards the camera
+    to_camera: vec3<f32>,
+    depth: f32,
+};
+
+fn is_orthographic(clip_from_view: mat4x4<f32>) -> bool {
+    return clip_from_view[3].w == 1.0;
+}
+
+// Corner (-1 to 1 on both axes) of the quad for the sphere at `center` with `radius`
+fn impostor_vertex(
+    corner: vec2<f32>,
+    center: vec3<f32>,
+    radius: f32,
+    view_from_world: mat4x4<f32>,
+    clip_from_view: mat4x4<f32>,
+) -> ImpostorVertex {
+    let center_view = (view_from_world * vec4<f32>(center, 1.0)).xyz;
+    var right = vec3<f32>(1.0, 0.0, 0.0);
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    var half_size = radius;
+    if !is_orthographic(clip_from_view) {
+        // square across the cone of rays touching the sphere, through its center
+        let distance = length(center_view);
+        let forward = center_view / distance;
+        var side = cross(up, -forward);
+        if length(side) < 1e-3 {
+            side = cross(-forward, vec3<f32>(0.0, 0.0, 1.0));
+        }
+        right = normalize(side);
+        up = cross(-forward, right);
+        half_size = radius * distance / sqrt(max(distance * distance - radius * radius, 1e-4 * radius * radius));
+    }
+    let view_position = center_view + (corner.x * right + corner.y * up) * half_size;
+
+    var out: ImpostorVertex;
+    out.clip_position = clip_from_view * vec4<f32>(view_position, 1.0);
+    out.view_position = view_position;
+    out.sphere = vec4<f32>(center_view, radius);
+    return out;
+}
+
+// Nearest point of the sphere seen through the quad point `view_position`; discards the
+// fragment when the ray misses
+fn impostor_hit(
+    view_position: vec3<f32>,
+    sphere: vec4<f32>,
+    clip_from_view: mat4x4<f32>,
+) -> ImpostorHit {
+    var origin = vec3<f32>(0.0);
+    var direction = normalize(view_position);
+    if is_orthographic(clip_from_view) {
+        origin = vec3<f32>(view_position.xy, 0.0);
+        direction = vec3<f32>(0.0, 0.0, -1.0);
+    }
+    let offset = origin - sphere.xyz;
+    let half_b = dot(offset, direction);
+    let discriminant = half_b * half_b - (dot(offset, offset) - sphere.w * sphere.w);
+    if discriminant < 0.0 {
+        discard;
+    }
+    let distance = -half_b - sqrt(discriminant);
+    // the camera is inside the sphere
+    if distance < 0.0 && !is_orthographic(clip_from_view) {
+        discard;
+    }
+
+    var hit: ImpostorHit;
+    hit.view_position = origin + direction * distance;
+    hit.normal = (hit.view_position - sphere.xyz) / sphere.w;
+    hit.to_camera = -direction;
+    let clip = clip_from_view * vec4<f32>(hit.view_position, 1.0);
+    hit.depth = clip.z / clip.w;
+    return hit;
+}
@@ -0,0 +1,70 @@
+// Volumetric data drawn by raymarching (see raymarch.rs). The mesh is the box of the grid, drawn
+// from the inside so the camera may be within it; every fragment marches its view ray through
+// the box in texture coordinates, where the box is the unit cube, and composites the colors and
+// opacities of the transfer function, baked into the 3D texture, front to back. Opacities are
+// per Å of ray, so the look does not depend on the number of steps. The box is depth tested at
+// its far side, so where atoms inside it cover that side they are drawn clear of the volume.
+
+#import bevy_pbr::mesh_view_bindings::view
+#import bevy_pbr::forward_io::VertexOutput
+#ifdef TONEMAP_IN_SHADER
+#import bevy_core_pipeline::tonemapping::tone_mapping
+#endif
+
+@group(2) @binding(0) var<uniform> texture_from_world: mat4x4<f32>;
+// opacity per Å of a fully opaque value
+@group(2) @binding(1) var<uniform> density: f32;
+// samples along each ray through the box
+@group(2) @binding(2) var<uniform> steps: u32;
+@group(2) @binding(3) var volume_texture: texture_3d<f32>;
+@group(2) @binding(4) var volume_sampler: sampler;
+
+// Opacity above which the ray stops
+const OPAQUE: f32 = 0.99;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // the ray runs from the camera through the fragment, or along the view for orthographic views
+    var start = view.world_position;
+    var end = in.world_position.xyz;
+    if view.clip_from_view[3].w == 1.0 {
+        let forward = -view.world_from_view[2].xyz;
+        start = end - forward * dot(end - view.world_position, forward);
+    }
+    let origin = (texture_from_world * vec4<f32>(start, 1.0)).xyz;
+    let direction = (texture_from_world * vec4<f32>(end - start, 0.0)).xyz;
+
+    // entry and exit of the unit cube, as fractions of the way to the fragment
+    let inverse = 1.0 / direction;
+    let low = (vec3<f32>(0.0) - origin) * inverse;
+    let high = (vec3<f32>(1.0) - origin) * inverse;
+    let near = max(max(min(low.x, high.x), min(low.y, high.y)), max(min(low.z, high.z), 0.0));
+    let far = min(min(max(low.x, high.x), max(low.y, high.y)), max(low.z, high.z));
+    if far <= near {
+        discard;
+    }
+
+    let step = (far - near) / f32(steps);
+    let step_length = step * length(end - start);
+    var color = vec3<f32>(0.0);
+    var opacity = 0.0;
+    for (var i = 0u; i < steps; i++) {
+        let t = near + (f32(i) + 0.5) * step;
+        let sample = textureSampleLevel(volume_texture, volume_sampler, origin + t * direction, 0.0);
+        let alpha = 1.0 - exp(-sample.a * density * step_length);
+        color += (1.0 - opacity) * alpha * sample.rgb;
+        opacity += (1.0 - opacity) * alpha;
+        if opacity > OPAQUE {
+            break;
+        }
+    }
+    if opacity <= 0.0 {
+        discard;
+    }
+
+    var out = vec4<f32>(color / opacity, opacity);
+#ifdef TONEMAP_IN_SHADER
+    out = tone_mapping(out, view.color_grading);
+#endif
+    return out;
+}
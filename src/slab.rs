@@ -0,0 +1,327 @@
+// Surface slab generator
+// The bulk cell is re-expressed in a basis whose first two vectors span the (hkl) plane,
+// stacked until it is at least the requested thickness, and given a c vector along the
+// surface normal that leaves the requested vacuum gap between periodic images.
+
+use anyhow::{bail, Context, Result};
+use bevy::prelude::*;
+
+use crate::cell::recell;
+use crate::structure::{Atom, Crystal, Selection};
+use crate::theme::Themed;
+use crate::toast::Toast;
+use crate::ui::{ToggleId, ToggledPanel, ToolPanelRow};
+use crate::widgets::{spawn_button, spawn_stepper_row, StepperSettings};
+
+const MAX_MILLER: i32 = 6;
+const MAX_THICKNESS: f32 = 100.0;
+const MAX_VACUUM: f32 = 50.0;
+
+/// Parameters of the slab to cut, edited in the slab panel.
+#[derive(Resource, Clone, Copy, Debug)]
+pub(crate) struct SlabSettings {
+    pub miller: IVec3,
+    /// Minimum slab thickness (Å) along the surface normal.
+    pub thickness: f32,
+    /// Empty space (Å) between the slab and its periodic image.
+    pub vacuum: f32,
+}
+
+impl Default for SlabSettings {
+    fn default() -> Self {
+        Self {
+            miller: IVec3::new(0, 0, 1),
+            thickness: 10.0,
+            vacuum: 15.0,
+        }
+    }
+}
+
+impl StepperSettings for SlabSettings {
+    fn step(&mut self, field: usize, direction: i32) {
+        match field {
+            0..=2 => {
+                self.miller[field] = (self.miller[field] + direction).clamp(-MAX_MILLER, MAX_MILLER)
+            }
+            3 => self.thickness = (self.thickness + direction as f32).clamp(1.0, MAX_THICKNESS),
+            _ => self.vacuum = (self.vacuum + direction as f32).clamp(0.0, MAX_VACUUM),
+        }
+    }
+
+    fn value_text(&self, field: usize) -> String {
+        match field {
+            0..=2 => self.miller[field].to_string(),
+            3 => format!("{:.0} Å", self.thickness),
+            _ => format!("{:.0} Å", self.vacuum),
+        }
+    }
+}
+
+/// Root node of the slab panel, hidden until toggled on.
+#[derive(Component)]
+pub(crate) struct SlabPanel;
+
+/// Button that replaces the structure with the configured slab.
+#[derive(Component)]
+pub(crate) struct SlabBuildButton;
+
+fn gcd(a: i32, b: i32) -> i32 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// Floor division and modulo, matching the sign conventions the basis construction relies on
+fn floor_div(a: i32, b: i32) -> i32 {
+    (a as f64 / b as f64).floor() as i32
+}
+
+fn floor_mod(a: i32, b: i32) -> i32 {
+    a - b * floor_div(a, b)
+}
+
+// (x, y) with a * x + b * y = gcd(a, b)
+fn ext_gcd(a: i32, b: i32) -> (i32, i32) {
+    if b == 0 {
+        (1, 0)
+    } else if floor_mod(a, b) == 0 {
+        (0, 1)
+    } else {
+        let (x, y) = ext_gcd(b, floor_mod(a, b));
+        (y, x - y * floor_div(a, b))
+    }
+}
+
+// Integer basis (in units of the bulk cell vectors) whose first two vectors lie in the
+// (hkl) plane and are as orthogonal as possible; the third completes a unimodular basis
+fn surface_basis(lattice: Mat3, miller: IVec3) -> [IVec3; 3] {
+    let (h, k, l) = (miller.x, miller.y, miller.z);
+    let zeros = (h == 0, k == 0, l == 0);
+    match zeros {
+        (false, true, true) => return [IVec3::Y, IVec3::Z, IVec3::X],
+        (true, false, true) => return [IVec3::Z, IVec3::X, IVec3::Y],
+        (true, true, false) => return [IVec3::X, IVec3::Y, IVec3::Z],
+        _ => {}
+    }
+
+    let (mut p, mut q) = ext_gcd(k, l);
+    let [a1, a2, a3] = [lattice.x_axis, lattice.y_axis, lattice.z_axis];
+    let (hf, kf, lf) = (h as f32, k as f32, l as f32);
+    let u = kf * a1 - hf * a2;
+    let v = lf * a1 - hf * a3;
+    let w = lf * a2 - kf * a3;
+    // dot(c1, c2) = k1 + i * k2 for the family of valid first vectors
+    let k1 = (p as f32 * u + q as f32 * v).dot(w);
+    let k2 = (lf * u - kf * v).dot(w);
+    if k2.abs() > 1e-6 {
+        let i = -(k1 / k2).round() as i32;
+        p += i * l;
+        q -= i * k;
+    }
+
+    let (a, b) = ext_gcd(p * k + q * l, h);
+    [
+        IVec3::new(p * k + q * l, -p * h, -q * h),
+        IVec3::new(0, l, -k) / gcd(l, k).abs(),
+        IVec3::new(b, a * p, a * q),
+    ]
+}
+
+// Cut a slab with surface (hkl) from a periodic structure
+pub(crate) fn build_slab(
+    crystal: &Crystal,
+    miller: IVec3,
+    thickness: f32,
+    vacuum: f32,
+) -> Result<Crystal> {
+    let lattice = crystal
+        .lattice
+        .context("Slab generation needs a periodic structure")?;
+    if miller == IVec3::ZERO {
+        bail!("Miller index (0 0 0) does not define a surface");
+    }
+    let miller = miller / gcd(gcd(miller.x, miller.y), miller.z);
+
+    let [v1, v2, mut v3] = surface_basis(lattice, miller).map(|c| lattice * c.as_vec3());
+    let normal = v1.cross(v2).normalize();
+    if v3.dot(normal) < 0.0 {
+        v3 = -v3;
+    }
+    let spacing = v3.dot(normal);
+    let layers = (thickness / spacing - 1e-3).ceil().max(1.0);
+    let stacked = recell(crystal, Mat3::from_cols(v1, v2, v3 * layers))
+        .context("Failed to stack the cell along the surface normal")?;
+
+    // Rotate so that a lies along x and the surface normal along z
+    let a_dir = v1.normalize();
+    let rotation = Mat3::from_cols(a_dir, normal.cross(a_dir), normal).transpose();
+    let height = layers * spacing + vacuum;
+    let slab_lattice = Mat3::from_cols(rotation * v1, rotation * v2, Vec3::Z * height);
+    let inverse = slab_lattice.inverse();
+
+    let bottom = stacked
+        .atoms
+        .iter()
+        .map(|atom| atom.position().dot(normal))
+        .fold(f32::INFINITY, f32::min);
+
+    let atoms = stacked
+        .atoms
+        .iter()
+        .map(|atom| {
+            let mut frac = inverse * (rotation * atom.position());
+            frac.x -= frac.x.floor();
+            frac.y -= frac.y.floor();
+            // Slab starts half the vacuum above the cell origin
+            frac.z = (atom.position().dot(normal) - bottom + 0.5 * vacuum) / height;
+            let position = slab_lattice * frac;
+            Atom {
+                x: position.x,
+                y: position.y,
+                z: position.z,
+                ..atom.clone()
+            }
+        })
+        .collect();
+
+    Ok(Crystal {
+        atoms,
+        lattice: Some(slab_lattice),
+        pbc: crystal.pbc,
+        properties: stacked.properties,
+        metadata: crystal.metadata.clone(),
+    })
+}
+
+// Spawn the (hidden) slab panel in the tool row
+pub(crate) fn setup_slab_panel(
+    mut commands: Commands,
+    row: Single<Entity, With<ToolPanelRow>>,
+    settings: Res<SlabSettings>,
+) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            SlabPanel,
+            ToggledPanel(ToggleId::SlabTool),
+            ChildOf(*row),
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                Text::new("Surface slab"),
+                TextFont {
+                    font: default(),
+                    font_size: 14.0,
+                    ..default()
+                },
+                Themed::Text,
+            ));
+            for (field, label) in ["h", "k", "l", "Thickness", "Vacuum"]
+                .into_iter()
+                .enumerate()
+            {
+                spawn_stepper_row(panel, label, field, &*settings);
+            }
+            spawn_button(panel, "Build Slab", SlabBuildButton);
+        });
+}
+
+// Replace the structure with the configured slab
+pub(crate) fn slab_build_button(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<SlabBuildButton>)>,
+    settings: Res<SlabSettings>,
+    mut crystal: ResMut<Crystal>,
+    mut selection: ResMut<Selection>,
+    mut toasts: EventWriter<Toast>,
+) {
+    for interaction in &interactions {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let miller = settings.miller;
+        match build_slab(&crystal, miller, settings.thickness, settings.vacuum) {
+            Ok(slab) => {
+                info!(
+                    "Built ({} {} {}) slab with {} atoms",
+                    miller.x,
+                    miller.y,
+                    miller.z,
+                    slab.atoms.len()
+                );
+                selection.atoms.clear();
+                *crystal = slab;
+            }
+            Err(e) => {
+                warn!("{e:#}");
+                toasts.write(Toast::warning(format!("{e:#}")));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MILLER_INDICES: [[i32; 3]; 10] = [
+        [1, 0, 0],
+        [0, 1, 0],
+        [0, 0, 1],
+        [1, 1, 0],
+        [1, 1, 1],
+        [2, 1, 0],
+        [1, -1, 2],
+        [3, 2, 1],
+        [0, 2, 3],
+        [-2, 3, 5],
+    ];
+
+    fn lattices() -> [Mat3; 2] {
+        let cubic = Mat3::from_diagonal(Vec3::splat(4.0));
+        let triclinic = Mat3::from_cols(
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(1.2, 5.0, 0.0),
+            Vec3::new(-0.8, 0.9, 6.0),
+        );
+        [cubic, triclinic]
+    }
+
+    #[test]
+    fn surface_basis_is_unimodular() {
+        for lattice in lattices() {
+            for miller in MILLER_INDICES.map(IVec3::from) {
+                let [c1, c2, c3] = surface_basis(lattice, miller);
+                let determinant = c1.dot(c2.cross(c3));
+                assert_eq!(determinant.abs(), 1, "({miller}) gives {c1}, {c2}, {c3}");
+            }
+        }
+    }
+
+    #[test]
+    fn surface_basis_spans_the_plane() {
+        for lattice in lattices() {
+            for miller in MILLER_INDICES.map(IVec3::from) {
+                let [c1, c2, _] = surface_basis(lattice, miller);
+                assert_eq!(c1.dot(miller), 0, "({miller}) gives {c1}");
+                assert_eq!(c2.dot(miller), 0, "({miller}) gives {c2}");
+            }
+        }
+    }
+
+    #[test]
+    fn ext_gcd_gives_bezout_coefficients() {
+        for (a, b) in [(3, 5), (4, 6), (-2, 7), (0, 3), (5, 0), (-4, -6)] {
+            let (x, y) = ext_gcd(a, b);
+            assert_eq!((a * x + b * y).abs(), gcd(a, b).abs(), "{a}, {b}");
+        }
+    }
+}
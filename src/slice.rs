@@ -0,0 +1,231 @@
+// Slice plane through volumetric data
+// A plane across one of the grids of the volume panel (see volume.rs), drawn in the scene with
+// the values it cuts through as colors: viridis from the smallest to the largest value, or blue
+// through white to red around zero for signed data such as spin densities and orbitals. The
+// plane lies across one grid axis and is moved along it with the Position row; values are
+// sampled at the grid points of the plane, interpolated between the two grid layers around it.
+// Densities peak sharply at the nuclei, so the colors can saturate below the largest value
+// (Saturation row) to bring out the bonding regions.
+// The plane is drawn from both sides and without lighting, so its colors read as the values.
+
+use bevy::asset::RenderAssetUsages;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+use crate::color::value_color;
+use crate::settings::stepped_index;
+use crate::structure::Crystal;
+use crate::volume::{VolumeGrid, VolumePanel, Volumes};
+use crate::widgets::{on_off, spawn_stepper_row, StepperSettings};
+
+/// Steps of the plane along its axis, as a fraction of the grid.
+const POSITION_STEP: f32 = 0.02;
+/// Fractions of the largest magnitude at which the colors saturate.
+const SATURATIONS: [f32; 6] = [1.0, 0.5, 0.2, 0.1, 0.05, 0.01];
+const AXIS_NAMES: [&str; 3] = ["a", "b", "c"];
+
+/// Slice plane settings, shown in the volume panel.
+#[derive(Resource)]
+pub(crate) struct SliceSettings {
+    pub shown: bool,
+    /// Index into the loaded grids.
+    pub grid: usize,
+    /// Grid axis the plane lies across.
+    pub axis: usize,
+    /// Position of the plane along its axis, from 0 to 1.
+    pub position: f32,
+    /// Index into `SATURATIONS`.
+    pub saturation: usize,
+}
+
+impl Default for SliceSettings {
+    fn default() -> Self {
+        Self {
+            shown: false,
+            grid: 0,
+            axis: 2,
+            position: 0.5,
+            saturation: 0,
+        }
+    }
+}
+
+impl StepperSettings for SliceSettings {
+    fn step(&mut self, field: usize, direction: i32) {
+        match field {
+            0 => self.shown = direction > 0,
+            // kept below the number of grids by `update_slice_plane`
+            1 => self.grid = self.grid.saturating_add_signed(direction as isize),
+            2 => self.axis = stepped_index(self.axis, direction, AXIS_NAMES.len()),
+            3 => {
+                let position = self.position + direction as f32 * POSITION_STEP;
+                self.position =
+                    ((position / POSITION_STEP).round() * POSITION_STEP).clamp(0.0, 1.0);
+            }
+            _ => self.saturation = stepped_index(self.saturation, direction, SATURATIONS.len()),
+        }
+    }
+
+    fn value_text(&self, field: usize) -> String {
+        match field {
+            0 => on_off(self.shown),
+            1 => (self.grid + 1).to_string(),
+            2 => AXIS_NAMES[self.axis].to_string(),
+            3 => format!("{:.0}%", self.position * 100.0),
+            _ => format!("{}%", SATURATIONS[self.saturation] * 100.0),
+        }
+    }
+}
+
+/// The slice plane.
+#[derive(Component)]
+pub(crate) struct SlicePlane;
+
+// Whether values in `range` are signed, and their scaling onto the color scale of
+// `value_color`: by the largest magnitude times `saturation` when the values have both signs,
+// from the smallest value over the range times `saturation` otherwise
+pub(crate) fn value_scale(range: (f32, f32), saturation: f32) -> (bool, impl Fn(f32) -> f32) {
+    let (min, max) = range;
+    let signed = min < 0.0 && max > 0.0;
+    let scale = if signed {
+        min.abs().max(max) * saturation
+    } else {
+        (max - min) * saturation
+    };
+    let scale = if scale > 0.0 { scale } else { 1.0 };
+    let offset = if signed { 0.0 } else { min };
+    (signed, move |value: f32| (value - offset) / scale)
+}
+
+// Colors of `values` for a texture, scaled by `value_scale`
+pub(crate) fn value_colors(values: &[f32], range: (f32, f32), saturation: f32) -> Vec<Color> {
+    let (signed, scaled) = value_scale(range, saturation);
+    values
+        .iter()
+        .map(|&value| value_color(scaled(value), signed))
+        .collect()
+}
+
+// Mesh and texture of the plane across `axis` of `grid` at `position`
+fn slice(grid: &VolumeGrid, axis: usize, position: f32, saturation: f32) -> (Mesh, Image) {
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+    let (width, height) = (grid.dims[u], grid.dims[v]);
+    let layer = position * (grid.dims[axis] - 1) as f32;
+    let point = |i: usize, j: usize| {
+        let mut point = Vec3::ZERO;
+        point[axis] = layer;
+        point[u] = i as f32;
+        point[v] = j as f32;
+        point
+    };
+    let values: Vec<f32> = (0..height)
+        .flat_map(|j| (0..width).map(move |i| (i, j)))
+        .map(|(i, j)| grid.sample(point(i, j)))
+        .collect();
+    let data = value_colors(&values, grid.range(), saturation)
+        .into_iter()
+        .flat_map(|color| color.to_srgba().to_u8_array())
+        .collect();
+    let image = Image::new(
+        Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+
+    let corners = [
+        point(0, 0),
+        point(width - 1, 0),
+        point(width - 1, height - 1),
+        point(0, height - 1),
+    ]
+    .map(|corner| grid.position(corner));
+    let normal = (corners[1] - corners[0])
+        .cross(corners[3] - corners[0])
+        .normalize_or(Vec3::Z);
+    let mesh = Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    )
+    .with_inserted_attribute(
+        Mesh::ATTRIBUTE_POSITION,
+        corners.map(|c| c.to_array()).to_vec(),
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, vec![normal.to_array(); 4])
+    .with_inserted_attribute(
+        Mesh::ATTRIBUTE_UV_0,
+        vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+    )
+    .with_inserted_indices(Indices::U32(vec![0, 1, 2, 0, 2, 3]));
+    (mesh, image)
+}
+
+// Add the slice rows to the volume panel
+pub(crate) fn setup_slice_controls(
+    mut commands: Commands,
+    panel: Single<Entity, With<VolumePanel>>,
+    settings: Res<SliceSettings>,
+) {
+    commands.entity(*panel).with_children(|panel| {
+        for (field, label) in ["Slice", "Grid", "Axis", "Position", "Saturation"]
+            .into_iter()
+            .enumerate()
+        {
+            spawn_stepper_row(panel, label, field, &*settings);
+        }
+    });
+}
+
+// Redraw the slice plane when its settings or the grids change, and remove it while the grids'
+// structure is not on screen
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_slice_plane(
+    mut commands: Commands,
+    mut settings: ResMut<SliceSettings>,
+    volumes: Res<Volumes>,
+    crystal: Res<Crystal>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    planes: Query<Entity, With<SlicePlane>>,
+    mut drawn: Local<bool>,
+) {
+    if settings.grid >= volumes.grids.len() && settings.grid > 0 {
+        settings.grid = volumes.grids.len().saturating_sub(1);
+    }
+    let grid = volumes.grids.get(settings.grid);
+    let visible = settings.shown && volumes.shown(&crystal) && grid.is_some();
+    if visible == *drawn && !settings.is_changed() && !volumes.is_changed() {
+        return;
+    }
+    *drawn = visible;
+    for entity in &planes {
+        commands.entity(entity).despawn();
+    }
+    let Some(grid) = grid.filter(|_| visible) else {
+        return;
+    };
+    let (mesh, image) = slice(
+        grid,
+        settings.axis,
+        settings.position,
+        SATURATIONS[settings.saturation],
+    );
+    commands.spawn((
+        Mesh3d(meshes.add(mesh)),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color_texture: Some(images.add(image)),
+            unlit: true,
+            double_sided: true,
+            cull_mode: None,
+            ..default()
+        })),
+        SlicePlane,
+    ));
+}
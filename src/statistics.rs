@@ -0,0 +1,329 @@
+// Bond statistics panel
+// Lists every bond type with its length range and every bond angle type with its angle range,
+// with a button to export both tables as CSV.
+// While a trajectory with more than one frame is buffered, the panel also plots the RMSD of each
+// frame against the first, optionally after superposing the two; pressing or dragging on the
+// plot shows the frame under the cursor.
+
+use bevy::prelude::*;
+use bevy::ui::RelativeCursorPosition;
+
+use crate::analysis::{BondStatistics, Rmsd, RmsdSettings};
+use crate::events::FrameChanged;
+use crate::io::export_text_file;
+use crate::structure::UpdateStructure;
+use crate::theme::Themed;
+use crate::toast::Toast;
+use crate::trajectory::Trajectory;
+use crate::ui::{SidePanelColumn, ToggleId, ToggledPanel};
+use crate::widgets::{on_off, spawn_button, spawn_stepper_row, spawn_table_text, StepperSettings};
+
+const CSV_FILE_NAME: &str = "bond_statistics.csv";
+
+// Rows listed per table before it is cut off
+const MAX_ROWS: usize = 15;
+/// Size of the RMSD plot, in logical pixels.
+const PLOT_WIDTH: f32 = 260.0;
+const PLOT_HEIGHT: f32 = 60.0;
+/// Bars of the RMSD plot; frames are averaged into them when there are more.
+const PLOT_BARS: usize = 120;
+const PLOT_COLOR: Color = Color::srgba(0.4, 0.7, 1.0, 0.6);
+const MARKER_COLOR: Color = Color::srgb(1.0, 0.8, 0.2);
+
+/// Text holding the statistics tables.
+#[derive(Component)]
+pub(crate) struct BondStatisticsText;
+
+/// Button exporting the statistics as CSV.
+#[derive(Component)]
+pub(crate) struct ExportStatisticsButton;
+
+/// RMSD section of the panel, shown while a trajectory is buffered.
+#[derive(Component)]
+pub(crate) struct RmsdSection;
+
+/// Title of the RMSD plot, with the value at the frame on screen.
+#[derive(Component)]
+pub(crate) struct RmsdText;
+
+/// RMSD plot area; pressing it shows the frame under the cursor.
+#[derive(Component)]
+pub(crate) struct RmsdPlot;
+
+/// Row holding the bars of the RMSD plot.
+#[derive(Component)]
+pub(crate) struct RmsdBars;
+
+/// Line marking the frame on screen in the RMSD plot.
+#[derive(Component)]
+pub(crate) struct RmsdMarker;
+
+impl StepperSettings for RmsdSettings {
+    fn step(&mut self, _field: usize, direction: i32) {
+        self.align = direction > 0;
+    }
+
+    fn value_text(&self, _field: usize) -> String {
+        on_off(self.align)
+    }
+}
+
+// RMSD of each bar of the plot, averaged over its frames
+fn plot_bars(values: &[Option<f32>]) -> Vec<Option<f32>> {
+    let bars = values.len().min(PLOT_BARS);
+    (0..bars)
+        .map(|bar| {
+            let range = bar * values.len() / bars..(bar + 1) * values.len() / bars;
+            let taken: Vec<f32> = values[range].iter().flatten().copied().collect();
+            (!taken.is_empty()).then(|| taken.iter().sum::<f32>() / taken.len() as f32)
+        })
+        .collect()
+}
+
+fn tables(statistics: &BondStatistics) -> String {
+    if statistics.bonds.is_empty() {
+        return "No bonds".to_string();
+    }
+
+    let mut text = String::new();
+    for (title, unit, stats) in [
+        ("Bond", "Å", &statistics.bonds),
+        ("Angle", "deg", &statistics.angles),
+    ] {
+        if stats.is_empty() {
+            continue;
+        }
+        if !text.is_empty() {
+            text.push_str("\n\n");
+        }
+        text.push_str(&format!(
+            "{:<10} {:>5} {:>8} {:>8} {:>8}  ({unit})",
+            title, "N", "min", "max", "mean"
+        ));
+        for (key, stat) in stats.iter().take(MAX_ROWS) {
+            text.push_str(&format!(
+                "\n{:<10} {:>5} {:>8.3} {:>8.3} {:>8.3}",
+                key,
+                stat.count,
+                stat.min,
+                stat.max,
+                stat.mean()
+            ));
+        }
+        if stats.len() > MAX_ROWS {
+            text.push_str(&format!("\n... {} more", stats.len() - MAX_ROWS));
+        }
+    }
+    text
+}
+
+// Spawn the (hidden) statistics panel in the side column
+pub(crate) fn setup_statistics_panel(
+    mut commands: Commands,
+    column: Single<Entity, With<SidePanelColumn>>,
+    rmsd_settings: Res<RmsdSettings>,
+) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(6.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            ToggledPanel(ToggleId::BondStatistics),
+            ChildOf(*column),
+        ))
+        .with_children(|panel| {
+            spawn_table_text(panel, BondStatisticsText);
+            spawn_button(panel, "Export CSV", ExportStatisticsButton);
+            panel
+                .spawn((
+                    Node {
+                        display: Display::None,
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(4.0),
+                        ..default()
+                    },
+                    RmsdSection,
+                ))
+                .with_children(|section| {
+                    section.spawn((
+                        Text::new(""),
+                        TextFont {
+                            font: default(),
+                            font_size: 12.0,
+                            ..default()
+                        },
+                        Themed::Text,
+                        RmsdText,
+                    ));
+                    section
+                        .spawn((
+                            Node {
+                                width: Val::Px(PLOT_WIDTH),
+                                height: Val::Px(PLOT_HEIGHT),
+                                border: UiRect::all(Val::Px(1.0)),
+                                ..default()
+                            },
+                            Themed::Field,
+                            Interaction::default(),
+                            RelativeCursorPosition::default(),
+                            RmsdPlot,
+                        ))
+                        .with_children(|plot| {
+                            plot.spawn((
+                                Node {
+                                    position_type: PositionType::Absolute,
+                                    width: Val::Percent(100.0),
+                                    height: Val::Percent(100.0),
+                                    flex_direction: FlexDirection::Row,
+                                    align_items: AlignItems::FlexEnd,
+                                    ..default()
+                                },
+                                RmsdBars,
+                            ));
+                            plot.spawn((
+                                Node {
+                                    position_type: PositionType::Absolute,
+                                    width: Val::Px(2.0),
+                                    height: Val::Percent(100.0),
+                                    margin: UiRect::left(Val::Px(-1.0)),
+                                    ..default()
+                                },
+                                BackgroundColor(MARKER_COLOR),
+                                RmsdMarker,
+                            ));
+                        });
+                    spawn_stepper_row(section, "Align", 0, &*rmsd_settings);
+                });
+        });
+}
+
+// Rebuild the tables when the statistics change
+pub(crate) fn refresh_statistics_panel(
+    statistics: Res<BondStatistics>,
+    mut texts: Query<&mut Text, With<BondStatisticsText>>,
+) {
+    if !statistics.is_changed() {
+        return;
+    }
+
+    let text = tables(&statistics);
+    for mut content in &mut texts {
+        content.0 = text.clone();
+    }
+}
+
+// Show the frame under the cursor while the RMSD plot is pressed
+pub(crate) fn rmsd_plot_clicks(
+    plots: Query<(&Interaction, &RelativeCursorPosition), With<RmsdPlot>>,
+    mut trajectory: ResMut<Trajectory>,
+    mut updates: EventWriter<UpdateStructure>,
+    mut frame_changes: EventWriter<FrameChanged>,
+) {
+    if trajectory.len() < 2 {
+        return;
+    }
+    for (interaction, cursor) in &plots {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some(cursor) = cursor.normalized else {
+            continue;
+        };
+        let target = (cursor.x.clamp(0.0, 1.0) * trajectory.last() as f32).round() as usize;
+        if target != trajectory.position() {
+            trajectory.show(target, &mut updates, &mut frame_changes);
+        }
+    }
+}
+
+// Keep the RMSD plot, its marker and its title in sync with the trajectory; the bars are only
+// respawned when the values change
+pub(crate) fn refresh_rmsd_plot(
+    mut commands: Commands,
+    rmsd: Res<Rmsd>,
+    trajectory: Res<Trajectory>,
+    mut sections: Query<&mut Node, With<RmsdSection>>,
+    mut markers: Query<&mut Node, (With<RmsdMarker>, Without<RmsdSection>)>,
+    mut texts: Query<&mut Text, With<RmsdText>>,
+    bars: Query<Entity, With<RmsdBars>>,
+) {
+    if !rmsd.is_changed() && !trajectory.is_changed() {
+        return;
+    }
+    let count = rmsd.values.len();
+    for mut node in &mut sections {
+        node.display = if count > 1 {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+    if count < 2 {
+        return;
+    }
+
+    let current = trajectory.position().min(count - 1);
+    for mut node in &mut markers {
+        node.left = Val::Percent(100.0 * current as f32 / (count - 1) as f32);
+    }
+    let max = rmsd.values.iter().flatten().copied().fold(0.0, f32::max);
+    let mut text = format!("RMSD vs frame 1 (max {max:.3} Å)");
+    if let Some(value) = rmsd.values[current] {
+        text.push_str(&format!("\nframe {}: {value:.3} Å", current + 1));
+    }
+    for mut content in &mut texts {
+        content.0 = text.clone();
+    }
+
+    if !rmsd.is_changed() {
+        return;
+    }
+    let values = plot_bars(&rmsd.values);
+    for entity in &bars {
+        commands.entity(entity).despawn_related::<Children>();
+        for value in &values {
+            // frames holding other atoms than the first show as a gap
+            let height = match value {
+                Some(value) if max > 0.0 => 100.0 * value / max,
+                _ => 0.0,
+            };
+            commands.spawn((
+                Node {
+                    width: Val::Percent(100.0 / values.len() as f32),
+                    height: Val::Percent(height),
+                    ..default()
+                },
+                BackgroundColor(PLOT_COLOR),
+                ChildOf(entity),
+            ));
+        }
+    }
+}
+
+// Export the statistics when the button is clicked
+pub(crate) fn export_statistics_button(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<ExportStatisticsButton>)>,
+    statistics: Res<BondStatistics>,
+    mut toasts: EventWriter<Toast>,
+) {
+    for interaction in &interactions {
+        if *interaction == Interaction::Pressed {
+            export_statistics(&statistics, &mut toasts);
+        }
+    }
+}
+
+// Save both tables as CSV
+pub(crate) fn export_statistics(statistics: &BondStatistics, toasts: &mut EventWriter<Toast>) {
+    export_text_file(
+        CSV_FILE_NAME,
+        &statistics.to_csv(),
+        "bond statistics",
+        toasts,
+    );
+}
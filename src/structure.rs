@@ -1,40 +1,551 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
 use bevy::prelude::*;
 
-// Structure to represent an atom from XYZ file
+use crate::cell::lattice_from_parameters;
+use crate::constants::{get_element_size, Element};
+use crate::units::LengthUnit;
+
+/// Smallest cell volume in Å³ taken as spanning space.
+const MIN_CELL_VOLUME: f32 = 1e-6;
+
 // `#` is a macro. no inheritance. close to python decorator. injecting on top of something.
 // traits are like interfaces.
-#[derive(Debug, Clone)]
+/// An atom: element and Cartesian position in Å.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Atom {
-    pub element: String,
+    pub element: Element,
     pub x: f32,
     pub y: f32,
     pub z: f32,
 }
 
-// Structure to hold our crystal data
-#[derive(Resource)]
+impl Atom {
+    /// Atom of `element` (an [`Element`] or a symbol such as "Fe") at `position`.
+    pub fn new(element: impl Into<Element>, position: Vec3) -> Self {
+        Self {
+            element: element.into(),
+            x: position.x,
+            y: position.y,
+            z: position.z,
+        }
+    }
+
+    /// Cartesian position in Å.
+    pub fn position(&self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+}
+
+/// Optional per-atom data by name, e.g. streamed from a simulation or read from the columns of
+/// an extended XYZ file. Arrays are indexed like [`Crystal::atoms`]; edits that add or remove
+/// atoms keep them in step, and arrays that no longer line up are ignored. The names of the
+/// associated constants have a fixed meaning; any other name is shown as it is.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AtomProperties {
+    pub scalars: BTreeMap<String, Vec<f32>>,
+    pub vectors: BTreeMap<String, Vec<Vec3>>,
+    pub strings: BTreeMap<String, Vec<String>>,
+}
+
+impl AtomProperties {
+    /// Partial charges, in e.
+    pub const CHARGES: &str = "charges";
+    /// Forces, in whatever unit the source uses.
+    pub const FORCES: &str = "forces";
+    /// Velocities, in whatever unit the source uses.
+    pub const VELOCITIES: &str = "velocities";
+    /// Fraction of its site each atom fills, from 0 to 1, for partially occupied sites.
+    pub const OCCUPANCIES: &str = "occupancies";
+    /// Site labels such as "Fe1" or "CA", e.g. from the `_atom_site` loop of a CIF file.
+    pub const LABELS: &str = "labels";
+
+    pub fn is_empty(&self) -> bool {
+        self.scalars.is_empty() && self.vectors.is_empty() && self.strings.is_empty()
+    }
+
+    /// Add a value for one more atom to every array: zero, an empty string, or a fully
+    /// occupied site.
+    pub fn push_default(&mut self) {
+        for (name, values) in &mut self.scalars {
+            values.push(if name == Self::OCCUPANCIES { 1.0 } else { 0.0 });
+        }
+        for values in self.vectors.values_mut() {
+            values.push(Vec3::ZERO);
+        }
+        for values in self.strings.values_mut() {
+            values.push(String::new());
+        }
+    }
+
+    /// Arrays for a structure whose atom `i` is atom `indices[i]` of one with `atoms` atoms;
+    /// arrays that don't have one value per atom are dropped.
+    pub fn select(&self, atoms: usize, indices: &[usize]) -> Self {
+        fn pick<T: Clone>(
+            arrays: &BTreeMap<String, Vec<T>>,
+            atoms: usize,
+            indices: &[usize],
+        ) -> BTreeMap<String, Vec<T>> {
+            arrays
+                .iter()
+                .filter(|(_, values)| values.len() == atoms)
+                .map(|(name, values)| {
+                    let picked = indices.iter().map(|&i| values[i].clone()).collect();
+                    (name.clone(), picked)
+                })
+                .collect()
+        }
+        Self {
+            scalars: pick(&self.scalars, atoms, indices),
+            vectors: pick(&self.vectors, atoms, indices),
+            strings: pick(&self.strings, atoms, indices),
+        }
+    }
+
+    // Name of the first array whose length differs from `atoms`
+    fn mismatched(&self, atoms: usize) -> Option<&str> {
+        let scalars = self
+            .scalars
+            .iter()
+            .map(|(name, values)| (name, values.len()));
+        let vectors = self
+            .vectors
+            .iter()
+            .map(|(name, values)| (name, values.len()));
+        let strings = self
+            .strings
+            .iter()
+            .map(|(name, values)| (name, values.len()));
+        scalars
+            .chain(vectors)
+            .chain(strings)
+            .find(|&(_, length)| length != atoms)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// Where a structure comes from, beyond its atoms.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    /// Title of the structure, e.g. the comment line of an XYZ or POSCAR file or the data block
+    /// of a CIF file.
+    pub title: Option<String>,
+    /// Name of the file format it was read from.
+    pub format: Option<String>,
+    /// Length unit of the file it was read from; positions and cells are always in Å.
+    pub length_unit: Option<LengthUnit>,
+    /// Any other named value, e.g. an ID in the database it was fetched from.
+    pub entries: BTreeMap<String, String>,
+}
+
+/// The structure on screen, kept as a Bevy resource. Insert one before the app starts, or send
+/// an [`UpdateStructure`] event, to show it.
+#[derive(Resource, Clone)]
 pub struct Crystal {
     pub atoms: Vec<Atom>,
+    /// Cell vectors a, b, c as columns; None for molecules.
+    pub lattice: Option<Mat3>,
+    /// Whether the structure repeats along a, b, c; only meaningful with a lattice.
+    pub pbc: [bool; 3],
+    pub properties: AtomProperties,
+    pub metadata: Metadata,
+}
+
+impl Crystal {
+    /// Empty builder, for assembling a structure atom by atom.
+    pub fn builder() -> CrystalBuilder {
+        CrystalBuilder::default()
+    }
+
+    /// Isolated structure without a cell.
+    pub fn molecule(atoms: Vec<Atom>) -> Self {
+        Self {
+            atoms,
+            lattice: None,
+            pbc: [false; 3],
+            properties: AtomProperties::default(),
+            metadata: Metadata::default(),
+        }
+    }
+
+    /// Structure repeating along all three cell vectors.
+    pub fn periodic(atoms: Vec<Atom>, lattice: Mat3) -> Self {
+        Self {
+            atoms,
+            lattice: Some(lattice),
+            pbc: [true; 3],
+            properties: AtomProperties::default(),
+            metadata: Metadata::default(),
+        }
+    }
+
+    /// Periodic structure from (element, fractional position) sites.
+    pub fn from_fractional(
+        sites: impl IntoIterator<Item = (Element, Vec3)>,
+        lattice: Mat3,
+    ) -> Self {
+        let atoms = sites
+            .into_iter()
+            .map(|(element, frac)| Atom::new(element, lattice * frac))
+            .collect();
+        Self::periodic(atoms, lattice)
+    }
+
+    /// Fractional coordinates of a Cartesian position, or None without a lattice.
+    pub fn to_fractional(&self, position: Vec3) -> Option<Vec3> {
+        self.lattice.map(|lattice| lattice.inverse() * position)
+    }
+
+    /// Cartesian position of fractional coordinates, or None without a lattice.
+    pub fn to_cartesian(&self, fractional: Vec3) -> Option<Vec3> {
+        self.lattice.map(|lattice| lattice * fractional)
+    }
+
+    /// Fractional coordinates of every atom, or None without a lattice.
+    pub fn fractional_positions(&self) -> Option<Vec<Vec3>> {
+        let inverse = self.lattice?.inverse();
+        Some(
+            self.atoms
+                .iter()
+                .map(|atom| inverse * atom.position())
+                .collect(),
+        )
+    }
+
+    // Per-atom value from `array`, as long as it still lines up with the atoms
+    fn per_atom<'a, T>(&self, array: Option<&'a Vec<T>>, index: usize) -> Option<&'a T> {
+        array
+            .filter(|values| values.len() == self.atoms.len())
+            .and_then(|values| values.get(index))
+    }
+
+    /// Value of the named scalar for atom `index`, if known.
+    pub fn scalar(&self, name: &str, index: usize) -> Option<f32> {
+        self.per_atom(self.properties.scalars.get(name), index)
+            .copied()
+    }
+
+    /// Value of the named vector for atom `index`, if known.
+    pub fn vector(&self, name: &str, index: usize) -> Option<Vec3> {
+        self.per_atom(self.properties.vectors.get(name), index)
+            .copied()
+    }
+
+    /// Value of the named string for atom `index`, if known.
+    pub fn string(&self, name: &str, index: usize) -> Option<&str> {
+        self.per_atom(self.properties.strings.get(name), index)
+            .map(String::as_str)
+    }
+
+    /// Charge of atom `index`, if charges are known.
+    pub fn charge(&self, index: usize) -> Option<f32> {
+        self.scalar(AtomProperties::CHARGES, index)
+    }
+
+    /// Force on atom `index`, if forces are known.
+    pub fn force(&self, index: usize) -> Option<Vec3> {
+        self.vector(AtomProperties::FORCES, index)
+    }
+
+    /// Velocity of atom `index`, if velocities are known.
+    pub fn velocity(&self, index: usize) -> Option<Vec3> {
+        self.vector(AtomProperties::VELOCITIES, index)
+    }
+
+    /// Occupancy of the site of atom `index`, if occupancies are known.
+    pub fn occupancy(&self, index: usize) -> Option<f32> {
+        self.scalar(AtomProperties::OCCUPANCIES, index)
+    }
+
+    /// Site label of atom `index`, if the file had them.
+    pub fn site_label(&self, index: usize) -> Option<&str> {
+        self.string(AtomProperties::LABELS, index)
+    }
+
+    /// Check that the structure is sound: a cell that spans space, finite positions, per-atom
+    /// arrays with one value per atom and occupancies from 0 to 1.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(lattice) = self.lattice {
+            let volume = lattice.determinant();
+            if !volume.is_finite() || volume.abs() < MIN_CELL_VOLUME {
+                bail!("The cell vectors are degenerate (volume {volume} Å³)");
+            }
+        }
+        if let Some(index) = self
+            .atoms
+            .iter()
+            .position(|atom| !atom.position().is_finite())
+        {
+            bail!("Atom {index} has no finite position");
+        }
+        if let Some(name) = self.properties.mismatched(self.atoms.len()) {
+            bail!(
+                "`{name}` does not have one value per atom ({})",
+                self.atoms.len()
+            );
+        }
+        if let Some(index) = self
+            .properties
+            .scalars
+            .get(AtomProperties::OCCUPANCIES)
+            .into_iter()
+            .flatten()
+            .position(|occupancy| !(0.0..=1.0).contains(occupancy))
+        {
+            bail!("Atom {index} has an occupancy outside 0 to 1");
+        }
+        Ok(())
+    }
+
+    /// Whether `other` holds atoms of the same elements in the same order, wherever they are.
+    pub fn same_elements(&self, other: &Crystal) -> bool {
+        self.atoms.len() == other.atoms.len()
+            && self
+                .atoms
+                .iter()
+                .zip(&other.atoms)
+                .all(|(a, b)| a.element == b.element)
+    }
+
+    /// Mean position of all atoms.
+    pub fn centroid(&self) -> Option<Vec3> {
+        if self.atoms.is_empty() {
+            return None;
+        }
+        Some(self.atoms.iter().map(Atom::position).sum::<Vec3>() / self.atoms.len() as f32)
+    }
+
+    /// Mass-weighted mean position; unknown elements count as 1 u.
+    pub fn center_of_mass(&self) -> Option<Vec3> {
+        let mut total = 0.0;
+        let mut weighted = Vec3::ZERO;
+        for atom in &self.atoms {
+            let mass = atom.element.data().mass;
+            total += mass;
+            weighted += mass * atom.position();
+        }
+        (total > 0.0).then(|| weighted / total)
+    }
+
+    /// Move every atom by `offset`; the cell stays where it is.
+    pub fn translate(&mut self, offset: Vec3) {
+        for atom in &mut self.atoms {
+            atom.x += offset.x;
+            atom.y += offset.y;
+            atom.z += offset.z;
+        }
+    }
+
+    /// Convert positions and cell read in `unit` to Å, and record the unit in the metadata.
+    pub fn convert_lengths(&mut self, unit: LengthUnit) {
+        let factor = unit.in_angstrom();
+        if factor != 1.0 {
+            for atom in &mut self.atoms {
+                atom.x *= factor;
+                atom.y *= factor;
+                atom.z *= factor;
+            }
+            self.lattice = self.lattice.map(|lattice| lattice * factor);
+        }
+        self.metadata.length_unit = Some(unit);
+    }
+
+    /// Lattice and periodic axes, if the structure repeats along any axis.
+    pub fn periodicity(&self) -> Option<(Mat3, [bool; 3])> {
+        self.lattice
+            .filter(|_| self.pbc.iter().any(|&periodic| periodic))
+            .map(|lattice| (lattice, self.pbc))
+    }
+
+    /// Bounding sphere (center, radius) of the atoms at `indices`, or of every atom when
+    /// `indices` is empty. The radius includes the drawn size of each atom.
+    pub fn bounding_sphere(&self, indices: &[usize]) -> Option<(Vec3, f32)> {
+        let atoms: Vec<&Atom> = if indices.is_empty() {
+            self.atoms.iter().collect()
+        } else {
+            indices.iter().filter_map(|&i| self.atoms.get(i)).collect()
+        };
+
+        if atoms.is_empty() {
+            return None;
+        }
+
+        let center = atoms.iter().map(|atom| atom.position()).sum::<Vec3>() / atoms.len() as f32;
+        let radius = atoms
+            .iter()
+            .map(|atom| atom.position().distance(center) + get_element_size(atom.element))
+            .fold(0.0, f32::max);
+
+        Some((center, radius))
+    }
 }
 
-// XXX: entity is the id point to the thing consist of components
+// Position of a site added to a builder
+#[derive(Clone, Copy, Debug)]
+enum SitePosition {
+    Cartesian(Vec3),
+    Fractional(Vec3),
+}
 
-// Component to mark atom entities
-#[derive(Component)]
-pub struct AtomEntity;
+/// Builder for a [`Crystal`], from [`Crystal::builder`]. Atoms keep the order they were added
+/// in; fractional positions are converted once the cell is known, in [`CrystalBuilder::build`].
+#[derive(Clone, Debug, Default)]
+pub struct CrystalBuilder {
+    sites: Vec<(Element, SitePosition)>,
+    lattice: Option<Mat3>,
+    pbc: Option<[bool; 3]>,
+    properties: AtomProperties,
+    metadata: Metadata,
+    // Reported by `build`
+    error: Option<String>,
+}
 
-// Event to update the structure with new atom positions
+impl CrystalBuilder {
+    /// Add an atom at a Cartesian position in Å.
+    pub fn atom(mut self, element: impl Into<Element>, position: Vec3) -> Self {
+        self.sites
+            .push((element.into(), SitePosition::Cartesian(position)));
+        self
+    }
+
+    /// Add an atom at fractional coordinates of the cell.
+    pub fn fractional_atom(mut self, element: impl Into<Element>, fractional: Vec3) -> Self {
+        self.sites
+            .push((element.into(), SitePosition::Fractional(fractional)));
+        self
+    }
+
+    /// Add several atoms at Cartesian positions.
+    pub fn atoms(mut self, atoms: impl IntoIterator<Item = Atom>) -> Self {
+        self.sites.extend(
+            atoms
+                .into_iter()
+                .map(|atom| (atom.element, SitePosition::Cartesian(atom.position()))),
+        );
+        self
+    }
+
+    /// Set the cell vectors; the structure is periodic along all three unless [`Self::pbc`]
+    /// says otherwise.
+    pub fn lattice(mut self, a: Vec3, b: Vec3, c: Vec3) -> Self {
+        self.lattice = Some(Mat3::from_cols(a, b, c));
+        self
+    }
+
+    /// Set the cell from lengths a, b, c in Å and angles α, β, γ in degrees, with a along x and
+    /// b in the xy plane.
+    pub fn lattice_parameters(mut self, parameters: [f32; 6]) -> Self {
+        self.lattice = lattice_from_parameters(parameters);
+        if self.lattice.is_none() {
+            self.error = Some(format!("{parameters:?} are not valid lattice parameters"));
+        }
+        self
+    }
+
+    /// Set along which cell vectors the structure repeats.
+    pub fn pbc(mut self, pbc: [bool; 3]) -> Self {
+        self.pbc = Some(pbc);
+        self
+    }
+
+    /// Attach per-atom data; arrays must have one entry per atom.
+    pub fn properties(mut self, properties: AtomProperties) -> Self {
+        self.properties = properties;
+        self
+    }
+
+    /// Set the title of the structure.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.metadata.title = Some(title.into());
+        self
+    }
+
+    /// Attach metadata, replacing any title set before.
+    pub fn metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// The structure, or an error when fractional positions lack a cell or the result does not
+    /// pass [`Crystal::validate`].
+    pub fn build(self) -> Result<Crystal> {
+        if let Some(error) = self.error {
+            bail!(error);
+        }
+        let mut atoms = Vec::with_capacity(self.sites.len());
+        for (element, position) in self.sites {
+            let position = match (position, self.lattice) {
+                (SitePosition::Cartesian(position), _) => position,
+                (SitePosition::Fractional(fractional), Some(lattice)) => lattice * fractional,
+                (SitePosition::Fractional(_), None) => {
+                    bail!("{element} has fractional coordinates but no lattice was set")
+                }
+            };
+            atoms.push(Atom::new(element, position));
+        }
+        let periodic = self.lattice.is_some();
+        let crystal = Crystal {
+            atoms,
+            lattice: self.lattice,
+            pbc: self.pbc.unwrap_or([periodic; 3]),
+            properties: self.properties,
+            metadata: self.metadata,
+        };
+        crystal.validate()?;
+        Ok(crystal)
+    }
+}
+
+// Indices into `Crystal::atoms` picked by the user
+#[derive(Resource, Default)]
+pub struct Selection {
+    pub atoms: Vec<usize>,
+}
+
+/// Event replacing the atoms on screen, e.g. `UpdateStructure::from(crystal)`.
 #[derive(Event, Clone)]
 pub struct UpdateStructure {
     pub atoms: Vec<Atom>,
+    /// Cell of the new structure, None for a molecule.
+    pub lattice: Option<Mat3>,
+    /// Periodic flags of the new structure.
+    pub pbc: [bool; 3],
+    /// Replaces the previous properties, which belong to the old positions.
+    pub properties: AtomProperties,
+    /// New metadata; None keeps the current, e.g. for the frames of a stream.
+    pub metadata: Option<Metadata>,
+}
+
+impl From<Crystal> for UpdateStructure {
+    fn from(crystal: Crystal) -> Self {
+        Self {
+            atoms: crystal.atoms,
+            lattice: crystal.lattice,
+            pbc: crystal.pbc,
+            properties: crystal.properties,
+            metadata: Some(crystal.metadata),
+        }
+    }
 }
 
 // System to handle incoming structure updates
 pub fn update_crystal_system(
     mut crystal: ResMut<Crystal>,
+    mut selection: ResMut<Selection>,
     mut events: EventReader<UpdateStructure>,
 ) {
     for event in events.read() {
+        // indices only stay meaningful while the atom list keeps its shape
+        if event.atoms.len() != crystal.atoms.len() {
+            selection.atoms.clear();
+        }
         crystal.atoms = event.atoms.clone();
+        crystal.lattice = event.lattice;
+        crystal.pbc = event.pbc;
+        crystal.properties = event.properties.clone();
+        if let Some(metadata) = &event.metadata {
+            crystal.metadata = metadata.clone();
+        }
     }
 }
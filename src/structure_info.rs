@@ -0,0 +1,205 @@
+// Structure information panel
+// File name, formula, atom count, title, format and cell parameters of the structure on
+// screen, followed by the details of the atom picked last, including all of its per-atom
+// properties. Shown by default; the "Info" toggle collapses it.
+
+use bevy::prelude::*;
+
+use crate::analysis::Coordination;
+use crate::cell::lattice_parameters;
+use crate::composition::Composition;
+use crate::events::StructureLoaded;
+use crate::structure::{AtomProperties, Crystal, Selection};
+use crate::theme::Themed;
+use crate::ui::{SidePanelColumn, ToggleId, ToggleStates, ToggledPanel};
+use crate::units::LengthUnit;
+use crate::widgets::spawn_table_text;
+
+/// Per-atom properties with lines of their own in the panel; any other is listed by name.
+const SHOWN_PROPERTIES: [&str; 4] = [
+    AtomProperties::LABELS,
+    AtomProperties::OCCUPANCIES,
+    AtomProperties::CHARGES,
+    AtomProperties::FORCES,
+];
+
+/// Root node of the structure information panel.
+#[derive(Component)]
+pub(crate) struct StructureInfoPanel;
+
+/// Text holding the structure information.
+#[derive(Component)]
+pub(crate) struct StructureInfoText;
+
+// File names of the paths or URLs in `source`, which lists several files separated by commas
+fn file_names(source: &str) -> String {
+    source
+        .split(", ")
+        .map(|path| {
+            let path = path.split(['?', '#']).next().unwrap_or(path);
+            path.rsplit(['/', '\\'])
+                .find(|name| !name.is_empty())
+                .unwrap_or(path)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn summary(
+    source: Option<&str>,
+    crystal: &Crystal,
+    selection: &Selection,
+    coordination: &Coordination,
+) -> String {
+    let composition = Composition::from_crystal(crystal);
+    let formula = match composition.total() {
+        0 => "-".to_string(),
+        _ => composition.formula(),
+    };
+    let mut text = format!(
+        "File     {}\nFormula  {formula}\nAtoms    {}",
+        source.map_or_else(|| "-".to_string(), file_names),
+        composition.total()
+    );
+    if let Some(title) = &crystal.metadata.title {
+        text.push_str(&format!("\nTitle    {title}"));
+    }
+    if let Some(format) = &crystal.metadata.format {
+        text.push_str(&format!("\nFormat   {format}"));
+        // lengths are shown in Å whatever the file used
+        if let Some(unit) = crystal
+            .metadata
+            .length_unit
+            .filter(|&unit| unit != LengthUnit::Angstrom)
+        {
+            text.push_str(&format!(" (read in {})", unit.name()));
+        }
+    }
+
+    match crystal.lattice {
+        Some(lattice) => {
+            let [a, b, c, alpha, beta, gamma] = lattice_parameters(lattice);
+            text.push_str(&format!(
+                "\n\nCell     a     {a:>8.4}  b    {b:>8.4}  c     {c:>8.4}\
+                 \n         alpha {alpha:>8.3}  beta {beta:>8.3}  gamma {gamma:>8.3}"
+            ));
+        }
+        None => text.push_str("\n\nCell     none (molecule)"),
+    }
+
+    let Some((&index, atom)) = selection
+        .atoms
+        .last()
+        .and_then(|index| Some((index, crystal.atoms.get(*index)?)))
+    else {
+        return text;
+    };
+    let name = atom.element.data().name;
+    text.push_str(&format!(
+        "\n\nAtom     #{index} {} ({name})\nCart.    {:>9.4} {:>9.4} {:>9.4}",
+        atom.element, atom.x, atom.y, atom.z
+    ));
+    if let Some(frac) = crystal.to_fractional(atom.position()) {
+        text.push_str(&format!(
+            "\nFrac.    {:>9.4} {:>9.4} {:>9.4}",
+            frac.x, frac.y, frac.z
+        ));
+    }
+    if let Some(label) = crystal.site_label(index) {
+        text.push_str(&format!("\nLabel    {label}"));
+    }
+    if let Some(occupancy) = crystal.occupancy(index) {
+        text.push_str(&format!("\nOcc.     {occupancy:.3}"));
+    }
+    if let Some(cn) = coordination.numbers.get(index) {
+        text.push_str(&format!("\nCN       {cn}"));
+    }
+    if let Some(charge) = crystal.charge(index) {
+        text.push_str(&format!("\nCharge   {charge:.3}"));
+    }
+    if let Some(force) = crystal.force(index) {
+        text.push_str(&format!("\n|F|      {:.4}", force.length()));
+    }
+    // any other per-atom property under its own name
+    let properties = &crystal.properties;
+    let other = |name: &&String| !SHOWN_PROPERTIES.contains(&name.as_str());
+    for name in properties.scalars.keys().filter(other) {
+        if let Some(value) = crystal.scalar(name, index) {
+            text.push_str(&format!("\n{name:<8} {value:.4}"));
+        }
+    }
+    for name in properties.vectors.keys().filter(other) {
+        if let Some(value) = crystal.vector(name, index) {
+            text.push_str(&format!(
+                "\n{name:<8} {:>9.4} {:>9.4} {:>9.4}",
+                value.x, value.y, value.z
+            ));
+        }
+    }
+    for name in properties.strings.keys().filter(other) {
+        if let Some(value) = crystal.string(name, index) {
+            text.push_str(&format!("\n{name:<8} {value}"));
+        }
+    }
+    if selection.atoms.len() > 1 {
+        text.push_str(&format!(
+            "\n         ({} more selected)",
+            selection.atoms.len() - 1
+        ));
+    }
+    text
+}
+
+// Spawn the structure information panel in the side column, shown unless an earlier session
+// collapsed it
+pub(crate) fn setup_structure_info_panel(
+    mut commands: Commands,
+    column: Single<Entity, With<SidePanelColumn>>,
+    mut toggle_states: ResMut<ToggleStates>,
+) {
+    toggle_states.register(ToggleId::StructureInfo, true);
+    let display = if toggle_states.get(ToggleId::StructureInfo) {
+        Display::Flex
+    } else {
+        Display::None
+    };
+    commands
+        .spawn((
+            Node {
+                display,
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            StructureInfoPanel,
+            ToggledPanel(ToggleId::StructureInfo),
+            ChildOf(*column),
+        ))
+        .with_children(|panel| {
+            spawn_table_text(panel, StructureInfoText);
+        });
+}
+
+// Rebuild the information whenever the structure, its source or the selection changes
+pub(crate) fn refresh_structure_info_panel(
+    crystal: Res<Crystal>,
+    selection: Res<Selection>,
+    coordination: Res<Coordination>,
+    mut loaded: EventReader<StructureLoaded>,
+    mut source: Local<Option<String>>,
+    mut texts: Query<&mut Text, With<StructureInfoText>>,
+) {
+    let mut changed = crystal.is_changed() || selection.is_changed() || coordination.is_changed();
+    if let Some(event) = loaded.read().last() {
+        *source = Some(event.source.clone());
+        changed = true;
+    }
+    if !changed {
+        return;
+    }
+
+    let text = summary(source.as_deref(), &crystal, &selection, &coordination);
+    for mut content in &mut texts {
+        content.0 = text.clone();
+    }
+}
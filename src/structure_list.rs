@@ -0,0 +1,376 @@
+// Structures held side by side in tabs
+// Every file opened or structure downloaded gets a tab in the bar at the top of the screen, or
+// one per file when several files are dropped at once. Clicking a tab, or PageUp / PageDown,
+// shows its structure instead of the current one, with its frames to step through and the
+// edits made to it kept, so that e.g. polymorphs can be compared without reloading them.
+// Structures that arrive by other means (streams, pastes, scripts) are shown without a tab,
+// leaving the tabs in place for switching back.
+// Tabs of files too large to hold (see lazy_frames.rs) keep the frame on screen, with its edits,
+// and read the others from the files again.
+
+use std::sync::Arc;
+
+use bevy::prelude::*;
+
+use crate::bindings::{KeyAction, KeyBindings};
+use crate::events::StructureLoaded;
+use crate::lazy_frames::FrameFile;
+use crate::structure::{Crystal, Selection};
+use crate::theme::Themed;
+use crate::trajectory::Trajectory;
+use crate::ui::FitView;
+use crate::widgets::{spawn_button, FocusedField};
+
+/// One structure of the list, with all its frames.
+pub(crate) struct ListEntry {
+    pub name: String,
+    /// Source the structure was loaded from, as announced in `StructureLoaded`.
+    pub source: String,
+    /// Every frame; only the frame on screen when the frames are read from `file`.
+    pub frames: Vec<Crystal>,
+    /// Frames of large files, read on demand.
+    pub file: Option<Arc<FrameFile>>,
+    /// Frame on screen, or shown when the tab was left.
+    pub frame: usize,
+}
+
+/// Structures to switch between.
+#[derive(Resource, Default)]
+pub(crate) struct StructureList {
+    pub entries: Vec<ListEntry>,
+    /// Entry on screen; None once something else was opened.
+    pub current: Option<usize>,
+}
+
+impl StructureList {
+    // Add a tab for each named structure loaded from `source`, the first one shown at `frame`
+    pub fn open(&mut self, source: String, entries: Vec<(String, Vec<Crystal>)>, frame: usize) {
+        let first = self.entries.len();
+        self.entries
+            .extend(entries.into_iter().map(|(name, frames)| ListEntry {
+                name,
+                source: source.clone(),
+                frames,
+                file: None,
+                frame: 0,
+            }));
+        if let Some(entry) = self.entries.get_mut(first) {
+            entry.frame = frame;
+            self.current = Some(first);
+        }
+    }
+
+    // Add a tab for the frames of indexed files, showing `shown`, the frame at `frame`
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub fn open_file(
+        &mut self,
+        source: String,
+        name: String,
+        file: Arc<FrameFile>,
+        shown: Crystal,
+        frame: usize,
+    ) {
+        self.entries.push(ListEntry {
+            name,
+            source,
+            frames: vec![shown],
+            file: Some(file),
+            frame,
+        });
+        self.current = Some(self.entries.len() - 1);
+    }
+
+    // Replace the frames of the tab loaded from `source` alone, after the file changed on disk
+    #[cfg_attr(
+        not(all(feature = "watch", not(target_arch = "wasm32"))),
+        allow(dead_code)
+    )]
+    pub fn reload(&mut self, source: &str, frames: &[Crystal], frame: usize) {
+        for entry in &mut self.entries {
+            if entry.source == source {
+                entry.frames = frames.to_vec();
+                entry.file = None;
+                entry.frame = frame;
+            }
+        }
+    }
+
+    // Replace the frames of the tab loaded from `source` alone with newly indexed files
+    #[cfg_attr(
+        not(all(feature = "watch", not(target_arch = "wasm32"))),
+        allow(dead_code)
+    )]
+    pub fn reload_file(
+        &mut self,
+        source: &str,
+        file: &Arc<FrameFile>,
+        shown: &Crystal,
+        frame: usize,
+    ) {
+        for entry in &mut self.entries {
+            if entry.source == source {
+                entry.frames = vec![shown.clone()];
+                entry.file = Some(file.clone());
+                entry.frame = frame;
+            }
+        }
+    }
+
+    // Keep the structure on screen, with its edits, in the tab it belongs to
+    fn store(&mut self, crystal: &Crystal, frame: usize) {
+        let Some(entry) = self
+            .current
+            .and_then(|current| self.entries.get_mut(current))
+        else {
+            return;
+        };
+        if entry.file.is_some() {
+            entry.frames = vec![crystal.clone()];
+            entry.frame = frame;
+        } else if let Some(stored) = entry.frames.get_mut(frame) {
+            *stored = crystal.clone();
+            entry.frame = frame;
+        }
+    }
+}
+
+// Tab name for a structure from `source`: the last part of a path or URL
+#[cfg_attr(all(not(feature = "fetch"), target_arch = "wasm32"), allow(dead_code))]
+pub(crate) fn tab_name(source: &str) -> String {
+    source
+        .trim_end_matches(['/', '\\'])
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or(source)
+        .to_string()
+}
+
+/// Bar holding one tab per structure.
+#[derive(Component)]
+pub(crate) struct StructureTabBar;
+
+/// Tab showing the entry at this index.
+#[derive(Component)]
+pub(crate) struct StructureTabButton(usize);
+
+/// Button closing the tab at this index.
+#[derive(Component)]
+pub(crate) struct CloseTabButton(usize);
+
+pub(crate) fn setup_structure_tabs(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            display: Display::None,
+            position_type: PositionType::Absolute,
+            top: Val::Px(8.0),
+            left: Val::Px(0.0),
+            right: Val::Px(0.0),
+            justify_content: JustifyContent::Center,
+            flex_wrap: FlexWrap::Wrap,
+            column_gap: Val::Px(4.0),
+            row_gap: Val::Px(4.0),
+            ..default()
+        },
+        StructureTabBar,
+    ));
+}
+
+// Rebuild the tabs whenever the list changes, marking the one on screen
+pub(crate) fn refresh_structure_tabs(
+    mut commands: Commands,
+    list: Res<StructureList>,
+    bindings: Res<KeyBindings>,
+    mut bars: Query<(Entity, &mut Node), With<StructureTabBar>>,
+) {
+    if !list.is_changed() {
+        return;
+    }
+    for (bar, mut node) in &mut bars {
+        node.display = if list.entries.is_empty() {
+            Display::None
+        } else {
+            Display::Flex
+        };
+        commands
+            .entity(bar)
+            .despawn_related::<Children>()
+            .with_children(|bar| {
+                for (index, entry) in list.entries.iter().enumerate() {
+                    let marker = if list.current == Some(index) {
+                        "> "
+                    } else {
+                        ""
+                    };
+                    bar.spawn((
+                        Node {
+                            column_gap: Val::Px(2.0),
+                            padding: UiRect::all(Val::Px(2.0)),
+                            ..default()
+                        },
+                        Themed::Panel,
+                    ))
+                    .with_children(|tab| {
+                        spawn_button(
+                            tab,
+                            &format!("{marker}{}", entry.name),
+                            StructureTabButton(index),
+                        );
+                        spawn_button(tab, "x", CloseTabButton(index));
+                    });
+                }
+                bar.spawn((
+                    Text::new(format!(
+                        "{}/{}",
+                        bindings.key_text(KeyAction::PreviousStructure),
+                        bindings.key_text(KeyAction::NextStructure)
+                    )),
+                    TextFont {
+                        font: default(),
+                        font_size: 12.0,
+                        ..default()
+                    },
+                    Themed::Text,
+                ));
+            });
+    }
+}
+
+// Show the tab that was clicked, or the next or previous one with PageDown / PageUp, keeping
+// the structure left behind in its own tab
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn switch_structure(
+    buttons: Query<(&Interaction, &StructureTabButton), Changed<Interaction>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    focused: Res<FocusedField>,
+    mut list: ResMut<StructureList>,
+    mut crystal: ResMut<Crystal>,
+    mut selection: ResMut<Selection>,
+    mut trajectory: ResMut<Trajectory>,
+    mut fit: EventWriter<FitView>,
+) {
+    let count = list.entries.len();
+    if count == 0 {
+        return;
+    }
+    let clicked = buttons
+        .iter()
+        .find(|(interaction, _)| **interaction == Interaction::Pressed)
+        .map(|(_, button)| button.0);
+    let stepped = if focused.0.is_some() {
+        None
+    } else if bindings.just_pressed(KeyAction::NextStructure, &keys) {
+        Some(list.current.map_or(0, |current| (current + 1) % count))
+    } else if bindings.just_pressed(KeyAction::PreviousStructure, &keys) {
+        Some(
+            list.current
+                .map_or(count - 1, |current| (current + count - 1) % count),
+        )
+    } else {
+        None
+    };
+    let Some(index) = clicked.or(stepped) else {
+        return;
+    };
+    if index >= count || list.current == Some(index) {
+        return;
+    }
+
+    list.store(&crystal, trajectory.position());
+    show_entry(
+        &mut list,
+        index,
+        &mut crystal,
+        &mut selection,
+        &mut trajectory,
+    );
+    fit.write(FitView);
+}
+
+// Put the entry at `index` on screen
+fn show_entry(
+    list: &mut StructureList,
+    index: usize,
+    crystal: &mut Crystal,
+    selection: &mut Selection,
+    trajectory: &mut Trajectory,
+) {
+    let entry = &list.entries[index];
+    info!("Showing {} from the structure tabs", entry.name);
+    selection.atoms.clear();
+    trajectory.clear();
+    if let Some(file) = &entry.file {
+        *crystal = entry.frames[0].clone();
+        if file.len() > 1 {
+            trajectory.load_file(file.clone(), entry.frame);
+        }
+        list.current = Some(index);
+        return;
+    }
+    let frame = entry.frame.min(entry.frames.len() - 1);
+    *crystal = entry.frames[frame].clone();
+    if entry.frames.len() > 1 {
+        trajectory.load(
+            entry.frames.iter().cloned().map(Into::into).collect(),
+            frame,
+        );
+    }
+    list.current = Some(index);
+}
+
+// Drop the tab whose close button was clicked; closing the tab on screen shows a neighbour
+pub(crate) fn close_structure_tab(
+    buttons: Query<(&Interaction, &CloseTabButton), Changed<Interaction>>,
+    mut list: ResMut<StructureList>,
+    mut crystal: ResMut<Crystal>,
+    mut selection: ResMut<Selection>,
+    mut trajectory: ResMut<Trajectory>,
+    mut fit: EventWriter<FitView>,
+) {
+    let Some(index) = buttons
+        .iter()
+        .find(|(interaction, _)| **interaction == Interaction::Pressed)
+        .map(|(_, button)| button.0)
+    else {
+        return;
+    };
+    if index >= list.entries.len() {
+        return;
+    }
+
+    let closed = list.entries.remove(index);
+    info!("Closed {}", closed.name);
+    let current = list.current;
+    match current {
+        Some(current) if current == index => {
+            list.current = None;
+            // the structure stays on screen when it was the last tab
+            if !list.entries.is_empty() {
+                let neighbour = index.min(list.entries.len() - 1);
+                show_entry(
+                    &mut list,
+                    neighbour,
+                    &mut crystal,
+                    &mut selection,
+                    &mut trajectory,
+                );
+                fit.write(FitView);
+            }
+        }
+        Some(current) if current > index => list.current = Some(current - 1),
+        _ => {}
+    }
+}
+
+// No tab is on screen anymore once a structure from elsewhere was loaded
+pub(crate) fn unmark_replaced_entry(
+    mut loaded: EventReader<StructureLoaded>,
+    mut list: ResMut<StructureList>,
+) {
+    for event in loaded.read() {
+        let shown = list.current.and_then(|current| list.entries.get(current));
+        if shown.is_some_and(|entry| entry.source != event.source) {
+            list.current = None;
+        }
+    }
+}
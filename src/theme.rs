@@ -0,0 +1,152 @@
+// Colors of the interface
+// Panels, buttons, text fields and labels carry a `Themed` role instead of fixed colors, and
+// `apply_theme` paints them from the `UiTheme` resource, both when they are spawned and
+// whenever the theme changes. The theme is picked in the configuration file:
+//
+//   theme = "light"                # dark, light or one of the [themes.*] tables
+//
+//   [themes.solarized]
+//   base = "dark"                  # preset the unset colors come from
+//   panel = "#002b36e6"
+//   text = "#93a1a1"
+
+use bevy::prelude::*;
+
+/// Colors used by every panel of the interface.
+#[derive(Resource, Clone, Debug)]
+pub(crate) struct UiTheme {
+    pub panel: Color,
+    pub button: Color,
+    pub button_hovered: Color,
+    pub button_pressed: Color,
+    pub border: Color,
+    pub focused_border: Color,
+    pub field: Color,
+    pub text: Color,
+    pub placeholder: Color,
+    /// Status texts: working, waiting and failed.
+    pub positive: Color,
+    pub pending: Color,
+    pub negative: Color,
+}
+
+impl Default for UiTheme {
+    fn default() -> Self {
+        UiTheme::dark()
+    }
+}
+
+impl UiTheme {
+    pub fn dark() -> Self {
+        UiTheme {
+            panel: Color::srgba(0.1, 0.1, 0.1, 0.9),
+            button: Color::srgb(0.15, 0.15, 0.15),
+            button_hovered: Color::srgb(0.2, 0.2, 0.2),
+            button_pressed: Color::srgb(0.25, 0.25, 0.25),
+            border: Color::srgb(0.3, 0.3, 0.3),
+            focused_border: Color::srgb(0.6, 0.6, 0.9),
+            field: Color::srgb(0.05, 0.05, 0.05),
+            text: Color::WHITE,
+            placeholder: Color::srgb(0.5, 0.5, 0.5),
+            positive: Color::srgb(0.4, 0.9, 0.4),
+            pending: Color::srgb(0.9, 0.8, 0.3),
+            negative: Color::srgb(0.9, 0.4, 0.4),
+        }
+    }
+
+    pub fn light() -> Self {
+        UiTheme {
+            panel: Color::srgba(0.95, 0.95, 0.95, 0.9),
+            button: Color::srgb(0.86, 0.86, 0.86),
+            button_hovered: Color::srgb(0.8, 0.8, 0.8),
+            button_pressed: Color::srgb(0.72, 0.72, 0.72),
+            border: Color::srgb(0.6, 0.6, 0.6),
+            focused_border: Color::srgb(0.3, 0.4, 0.85),
+            field: Color::WHITE,
+            text: Color::srgb(0.1, 0.1, 0.1),
+            placeholder: Color::srgb(0.55, 0.55, 0.55),
+            positive: Color::srgb(0.1, 0.55, 0.1),
+            pending: Color::srgb(0.65, 0.5, 0.0),
+            negative: Color::srgb(0.75, 0.15, 0.15),
+        }
+    }
+
+    // Built-in theme called `name`
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(UiTheme::dark()),
+            "light" => Some(UiTheme::light()),
+            _ => None,
+        }
+    }
+
+    // Background of a button in the given interaction state
+    pub fn button_color(&self, interaction: Interaction) -> Color {
+        match interaction {
+            Interaction::Pressed => self.button_pressed,
+            Interaction::Hovered => self.button_hovered,
+            Interaction::None => self.button,
+        }
+    }
+}
+
+/// Theme colors a UI node takes.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Themed {
+    /// Panel background.
+    Panel,
+    /// Framed button, with hover and press feedback.
+    Button,
+    /// Text input frame.
+    Field,
+    /// Label text.
+    Text,
+}
+
+fn paint(commands: &mut Commands, entity: Entity, role: Themed, theme: &UiTheme) {
+    let mut entity = commands.entity(entity);
+    match role {
+        Themed::Panel => {
+            entity.insert(BackgroundColor(theme.panel));
+        }
+        Themed::Button => {
+            entity.insert((BackgroundColor(theme.button), BorderColor(theme.border)));
+        }
+        Themed::Field => {
+            entity.insert((BackgroundColor(theme.field), BorderColor(theme.border)));
+        }
+        Themed::Text => {
+            entity.insert(TextColor(theme.text));
+        }
+    }
+}
+
+// Paint newly spawned nodes, or every node when the theme changed
+pub(crate) fn apply_theme(
+    mut commands: Commands,
+    theme: Res<UiTheme>,
+    all: Query<(Entity, &Themed)>,
+    added: Query<(Entity, &Themed), Added<Themed>>,
+) {
+    let nodes = if theme.is_changed() {
+        all.iter().collect::<Vec<_>>()
+    } else {
+        added.iter().collect()
+    };
+    for (entity, role) in nodes {
+        paint(&mut commands, entity, *role, &theme);
+    }
+}
+
+// Hover and press feedback for every themed button
+#[allow(clippy::type_complexity)]
+pub(crate) fn themed_button_feedback(
+    theme: Res<UiTheme>,
+    mut buttons: Query<(&Interaction, &Themed, &mut BackgroundColor), Changed<Interaction>>,
+) {
+    for (interaction, role, mut background) in &mut buttons {
+        if *role == Themed::Button {
+            background.0 = theme.button_color(*interaction);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+// Toast notifications
+// Short messages stacked in the bottom-right corner that go away on their own, or when clicked,
+// for feedback the user should see without opening the log console: finished exports, lost
+// connections, files that failed to load. Any system can send a `Toast` event.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::theme::{Themed, UiTheme};
+
+// Toasts shown at once; a new one pushes out the oldest
+const MAX_TOASTS: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ToastLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+impl ToastLevel {
+    // How long a toast of this level stays up
+    fn lifetime(self) -> Duration {
+        match self {
+            ToastLevel::Info => Duration::from_secs(3),
+            ToastLevel::Warning => Duration::from_secs(5),
+            ToastLevel::Error => Duration::from_secs(8),
+        }
+    }
+
+    fn color(self, theme: &UiTheme) -> Color {
+        match self {
+            ToastLevel::Info => theme.positive,
+            ToastLevel::Warning => theme.pending,
+            ToastLevel::Error => theme.negative,
+        }
+    }
+}
+
+/// Message to pop up for a few seconds.
+#[derive(Event, Clone, Debug)]
+pub(crate) struct Toast {
+    pub level: ToastLevel,
+    pub message: String,
+}
+
+impl Toast {
+    pub fn info(message: impl Into<String>) -> Self {
+        Self {
+            level: ToastLevel::Info,
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            level: ToastLevel::Warning,
+            message: message.into(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            level: ToastLevel::Error,
+            message: message.into(),
+        }
+    }
+}
+
+/// Column the toasts are stacked in, newest at the bottom.
+#[derive(Component)]
+pub(crate) struct ToastStack;
+
+/// A shown toast, dismissed when its timer runs out.
+#[derive(Component)]
+pub(crate) struct ToastTimer(Timer);
+
+pub(crate) fn setup_toast_stack(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            right: Val::Px(8.0),
+            bottom: Val::Px(8.0),
+            max_width: Val::Px(360.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::FlexEnd,
+            row_gap: Val::Px(6.0),
+            ..default()
+        },
+        // above the panels
+        GlobalZIndex(10),
+        ToastStack,
+    ));
+}
+
+// Pop up the toasts sent this frame, dropping the oldest past the limit
+pub(crate) fn show_toasts(
+    mut commands: Commands,
+    mut toasts: EventReader<Toast>,
+    theme: Res<UiTheme>,
+    stack: Single<(Entity, Option<&Children>), With<ToastStack>>,
+) {
+    let (stack, shown) = *stack;
+    let mut shown: Vec<Entity> = shown.map(|children| children.to_vec()).unwrap_or_default();
+    for toast in toasts.read() {
+        let entity = commands
+            .spawn((
+                Button,
+                Node {
+                    padding: UiRect::axes(Val::Px(10.0), Val::Px(6.0)),
+                    border: UiRect::left(Val::Px(3.0)),
+                    ..default()
+                },
+                Themed::Panel,
+                BorderColor(toast.level.color(&theme)),
+                ToastTimer(Timer::new(toast.level.lifetime(), TimerMode::Once)),
+                ChildOf(stack),
+            ))
+            .with_children(|toast_node| {
+                toast_node.spawn((
+                    Text::new(toast.message.clone()),
+                    TextFont {
+                        font: default(),
+                        font_size: 13.0,
+                        ..default()
+                    },
+                    Themed::Text,
+                ));
+            })
+            .id();
+        shown.push(entity);
+    }
+    let excess = shown.len().saturating_sub(MAX_TOASTS);
+    for entity in shown.drain(..excess) {
+        commands.entity(entity).despawn();
+    }
+}
+
+// Dismiss toasts whose time is up or that were clicked
+pub(crate) fn expire_toasts(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut toasts: Query<(Entity, &Interaction, &mut ToastTimer)>,
+) {
+    for (entity, interaction, mut timer) in &mut toasts {
+        // hovering keeps a toast up, so that it can be read to the end
+        if *interaction == Interaction::None {
+            timer.0.tick(time.delta());
+        }
+        if timer.0.finished() || *interaction == Interaction::Pressed {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,803 @@
+// Trajectory buffer for streamed MD frames
+// Structures that arrive with a frame index are kept instead of overwriting each other, so the
+// history can be scrubbed with the arrow keys (Home/End jump to the ends). While the last frame
+// is shown, new frames are displayed as they arrive. Files with several frames are stepped
+// through the same way; the frames of large files are read as they are shown (see
+// lazy_frames.rs).
+// The timeline along the bottom edge shows the buffered frames, with a marker at the one on
+// screen and, when the sender reports energies, a sparkline of the energy of each frame; pressing
+// or dragging on it scrubs to the frame under the cursor.
+// Its Play button (or Space) plays the frames at a set rate, stopping at the last one. With
+// smoothing on, atoms move gradually from each frame to the next, so trajectories saved every
+// few hundred steps do not jump at high playback rates; atoms that cross a periodic boundary
+// between frames move the short way, through the boundary.
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use bevy::ui::RelativeCursorPosition;
+
+use crate::bindings::{KeyAction, KeyBindings};
+use crate::events::FrameChanged;
+use crate::lazy_frames::FrameFile;
+use crate::settings::stepped_index;
+use crate::structure::{Atom, UpdateStructure};
+use crate::theme::Themed;
+use crate::ui::{SidePanelColumn, ToolPanelRow};
+use crate::widgets::{on_off, spawn_button, spawn_stepper_row, FocusedField, StepperSettings};
+
+// Frames kept before the oldest are dropped
+const MAX_FRAMES: usize = 5000;
+/// Size of the timeline track, in logical pixels.
+const TIMELINE_WIDTH: f32 = 360.0;
+const TIMELINE_HEIGHT: f32 = 28.0;
+/// Bars of the energy sparkline; frames are averaged into them when there are more.
+const SPARKLINE_BARS: usize = 120;
+const SPARKLINE_COLOR: Color = Color::srgba(0.4, 0.7, 1.0, 0.6);
+const MARKER_COLOR: Color = Color::srgb(1.0, 0.8, 0.2);
+/// Playback rates, in frames per second.
+const PLAYBACK_RATES: [f32; 7] = [1.0, 2.0, 5.0, 10.0, 20.0, 30.0, 60.0];
+
+/// Position of a frame in the streamed trajectory.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct FrameInfo {
+    pub index: u64,
+    /// MD step number, if the sender reports it.
+    pub step: Option<u64>,
+    /// Simulation time in the sender's units, if reported.
+    pub time: Option<f64>,
+    /// Potential energy in the sender's units, if reported.
+    pub energy: Option<f64>,
+}
+
+/// A structure received as part of a trajectory.
+#[derive(Event, Clone)]
+pub(crate) struct StreamedFrame {
+    pub info: FrameInfo,
+    pub structure: UpdateStructure,
+}
+
+/// Buffered frames, ordered by index, and the one on screen.
+#[derive(Resource)]
+pub(crate) struct Trajectory {
+    frames: Vec<StreamedFrame>,
+    /// Frames of large files, read on demand; `frames` is empty while it is set.
+    file: Option<Arc<FrameFile>>,
+    current: usize,
+    /// Show new frames as they arrive; cleared by scrubbing back, set again at the last frame.
+    follow: bool,
+    /// Counts the times buffered frames were replaced, so that results kept by frame index can
+    /// tell when they are stale.
+    revision: u64,
+}
+
+impl Default for Trajectory {
+    fn default() -> Self {
+        Self {
+            frames: Vec::new(),
+            file: None,
+            current: 0,
+            follow: true,
+            revision: 0,
+        }
+    }
+}
+
+impl Trajectory {
+    // Insert a frame in index order, replacing a frame with the same index; returns its position.
+    // An index before the first buffered frame means the sender restarted, which starts over.
+    // Streamed frames replace frames read from a file.
+    fn insert(&mut self, frame: StreamedFrame) -> usize {
+        let restarted = self
+            .frames
+            .first()
+            .is_some_and(|first| frame.info.index < first.info.index);
+        if restarted || self.file.take().is_some() {
+            self.frames.clear();
+            self.current = 0;
+        }
+        let position = match self
+            .frames
+            .binary_search_by_key(&frame.info.index, |f| f.info.index)
+        {
+            Ok(position) => {
+                self.frames[position] = frame;
+                self.revision += 1;
+                position
+            }
+            Err(position) => {
+                self.frames.insert(position, frame);
+                if position <= self.current && self.frames.len() > 1 {
+                    self.current += 1;
+                }
+                position
+            }
+        };
+        if self.frames.len() > MAX_FRAMES {
+            self.frames.remove(0);
+            self.current = self.current.saturating_sub(1);
+            return position.saturating_sub(1);
+        }
+        position
+    }
+
+    // Drop all buffered frames
+    pub fn clear(&mut self) {
+        *self = Self {
+            revision: self.revision + 1,
+            ..default()
+        };
+    }
+
+    // Replace the buffer with frames read from files, numbered from 0, showing `current`
+    pub fn load(&mut self, structures: Vec<UpdateStructure>, current: usize) {
+        self.file = None;
+        self.frames = structures
+            .into_iter()
+            .enumerate()
+            .map(|(index, structure)| StreamedFrame {
+                info: FrameInfo {
+                    index: index as u64,
+                    step: None,
+                    time: None,
+                    energy: None,
+                },
+                structure,
+            })
+            .collect();
+        self.current = current.min(self.last());
+        self.follow = self.current == self.last();
+        self.revision += 1;
+    }
+
+    // Replace the buffer with the frames of indexed files, showing `current`
+    pub fn load_file(&mut self, file: Arc<FrameFile>, current: usize) {
+        self.frames.clear();
+        self.file = Some(file);
+        self.current = current.min(self.last());
+        self.follow = self.current == self.last();
+        self.revision += 1;
+    }
+
+    // Attach energies to the buffered frames in order, e.g. to the images of a NEB path
+    pub fn set_energies(&mut self, energies: &[Option<f64>]) {
+        for (frame, energy) in self.frames.iter_mut().zip(energies) {
+            frame.info.energy = *energy;
+        }
+    }
+
+    // Position of the frame on screen
+    pub fn position(&self) -> usize {
+        self.current
+    }
+
+    pub fn len(&self) -> usize {
+        self.file
+            .as_ref()
+            .map_or(self.frames.len(), |file| file.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Index, step, time and energy of the frame at `position`; frames of files are numbered
+    // from 0
+    pub fn info(&self, position: usize) -> Option<FrameInfo> {
+        match &self.file {
+            Some(file) => (position < file.len()).then_some(FrameInfo {
+                index: position as u64,
+                step: None,
+                time: None,
+                energy: None,
+            }),
+            None => self.frames.get(position).map(|frame| frame.info),
+        }
+    }
+
+    // Structure of the frame at `position`; frames of files are read, and None when that fails
+    pub fn structure(&self, position: usize) -> Option<Cow<'_, UpdateStructure>> {
+        match &self.file {
+            Some(file) => match file.read(position) {
+                Ok(crystal) => Some(Cow::Owned(crystal.into())),
+                Err(e) => {
+                    error!("{e:#}");
+                    None
+                }
+            },
+            None => self
+                .frames
+                .get(position)
+                .map(|frame| Cow::Borrowed(&frame.structure)),
+        }
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn last(&self) -> usize {
+        self.len().saturating_sub(1)
+    }
+
+    // Event announcing the frame on screen
+    fn frame_changed(&self) -> Option<FrameChanged> {
+        let info = self.info(self.current)?;
+        Some(FrameChanged {
+            position: self.current,
+            frame_count: self.len(),
+            index: info.index,
+            step: info.step,
+            time: info.time,
+        })
+    }
+
+    // Show the frame at `target`, following new frames again at the last one
+    pub fn show(
+        &mut self,
+        target: usize,
+        updates: &mut EventWriter<UpdateStructure>,
+        frame_changes: &mut EventWriter<FrameChanged>,
+    ) {
+        self.follow = target == self.last();
+        if target != self.current {
+            self.current = target;
+            updates.write_batch(self.structure(target).map(Cow::into_owned));
+            frame_changes.write_batch(self.frame_changed());
+        }
+    }
+
+    // Positions of the frame at `position`, each atom at its periodic image nearest to the atom
+    // in the first frame; None when the frame holds other atoms than the first
+    pub fn positions_near_first(&self, position: usize) -> Option<Vec<Vec3>> {
+        let first = self.structure(0)?;
+        let frame = self.structure(position)?;
+        if !same_atoms(&first, &frame) {
+            return None;
+        }
+        let cell = PeriodicCell::of(&frame).or_else(|| PeriodicCell::of(&first));
+        let positions = first
+            .atoms
+            .iter()
+            .zip(&frame.atoms)
+            .map(|(reference, atom)| {
+                let step = atom.position() - reference.position();
+                reference.position() + cell.as_ref().map_or(step, |cell| cell.shortest(step))
+            })
+            .collect();
+        Some(positions)
+    }
+
+    // Energy of each bar of the sparkline, averaged over its frames; empty when no frame has one,
+    // as for frames of files
+    fn sparkline(&self) -> Vec<Option<f64>> {
+        if self.frames.iter().all(|frame| frame.info.energy.is_none()) {
+            return Vec::new();
+        }
+        let count = self.frames.len();
+        let bars = count.min(SPARKLINE_BARS);
+        (0..bars)
+            .map(|bar| {
+                let frames = &self.frames[bar * count / bars..(bar + 1) * count / bars];
+                let energies: Vec<f64> = frames.iter().filter_map(|f| f.info.energy).collect();
+                (!energies.is_empty()).then(|| energies.iter().sum::<f64>() / energies.len() as f64)
+            })
+            .collect()
+    }
+}
+
+/// Text describing the frame on screen.
+#[derive(Component)]
+pub(crate) struct TrajectoryText;
+
+/// Root node of the trajectory status, shown once frames have been buffered.
+#[derive(Component)]
+pub(crate) struct TrajectoryPanel;
+
+/// Root node of the timeline, shown once there are frames to scrub through.
+#[derive(Component)]
+pub(crate) struct TimelinePanel;
+
+/// Track of the timeline, pressed or dragged to scrub.
+#[derive(Component)]
+pub(crate) struct TimelineTrack;
+
+/// Marker of the frame on screen on the timeline.
+#[derive(Component)]
+pub(crate) struct TimelineMarker;
+
+/// Node holding the bars of the energy sparkline.
+#[derive(Component)]
+pub(crate) struct Sparkline;
+
+/// Text with the frame count and the energy of the frame on screen.
+#[derive(Component)]
+pub(crate) struct TimelineText;
+
+/// Play/Pause button of the timeline.
+#[derive(Component)]
+pub(crate) struct PlayButton;
+
+/// Trajectory playback, set in the timeline.
+#[derive(Resource)]
+pub(crate) struct Playback {
+    pub playing: bool,
+    /// Index into `PLAYBACK_RATES`.
+    pub rate: usize,
+    /// Whether atoms move smoothly between frames.
+    pub interpolate: bool,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Self {
+            playing: false,
+            rate: 3,
+            interpolate: false,
+        }
+    }
+}
+
+impl StepperSettings for Playback {
+    fn step(&mut self, field: usize, direction: i32) {
+        match field {
+            0 => self.rate = stepped_index(self.rate, direction, PLAYBACK_RATES.len()),
+            _ => self.interpolate = direction > 0,
+        }
+    }
+
+    fn value_text(&self, field: usize) -> String {
+        match field {
+            0 => format!("{} fps", PLAYBACK_RATES[self.rate]),
+            _ => on_off(self.interpolate),
+        }
+    }
+}
+
+/// Periodic cell of a frame, for taking displacements through the boundaries.
+struct PeriodicCell {
+    matrix: Mat3,
+    inverse: Mat3,
+    pbc: [bool; 3],
+}
+
+impl PeriodicCell {
+    fn of(structure: &UpdateStructure) -> Option<Self> {
+        let matrix = structure.lattice?;
+        let pbc = structure.pbc;
+        (pbc.contains(&true) && matrix.determinant().abs() > f32::EPSILON).then(|| Self {
+            matrix,
+            inverse: matrix.inverse(),
+            pbc,
+        })
+    }
+
+    // Shortest periodic image of the displacement `step`
+    fn shortest(&self, step: Vec3) -> Vec3 {
+        let mut fraction = self.inverse * step;
+        for axis in 0..3 {
+            if self.pbc[axis] {
+                fraction[axis] -= fraction[axis].round();
+            }
+        }
+        self.matrix * fraction
+    }
+}
+
+// Whether two frames hold the same atoms in the same order
+fn same_atoms(a: &UpdateStructure, b: &UpdateStructure) -> bool {
+    a.atoms.len() == b.atoms.len()
+        && a.atoms
+            .iter()
+            .zip(&b.atoms)
+            .all(|(a, b)| a.element == b.element)
+}
+
+// Frame `t` of the way from `from` to `to`, or None when the two do not hold the same atoms.
+// With a periodic cell each atom moves along the shortest image of its displacement.
+pub(crate) fn interpolate(
+    from: &UpdateStructure,
+    to: &UpdateStructure,
+    t: f32,
+) -> Option<UpdateStructure> {
+    if !same_atoms(from, to) {
+        return None;
+    }
+    let cell = PeriodicCell::of(from);
+    let atoms = from
+        .atoms
+        .iter()
+        .zip(&to.atoms)
+        .map(|(a, b)| {
+            let mut step = b.position() - a.position();
+            if let Some(cell) = &cell {
+                step = cell.shortest(step);
+            }
+            Atom::new(a.element, a.position() + step * t)
+        })
+        .collect();
+    let lattice = match (from.lattice, to.lattice) {
+        (Some(a), Some(b)) => Some(a + (b - a) * t),
+        (lattice, _) => lattice,
+    };
+    Some(UpdateStructure {
+        atoms,
+        lattice,
+        pbc: from.pbc,
+        properties: from.properties.clone(),
+        metadata: None,
+    })
+}
+
+fn status(trajectory: &Trajectory) -> String {
+    let Some(info) = trajectory.info(trajectory.current) else {
+        return String::new();
+    };
+    let mut text = format!(
+        "Frame {} ({}/{})",
+        info.index,
+        trajectory.current + 1,
+        trajectory.len()
+    );
+    if let Some(step) = info.step {
+        text.push_str(&format!("  step {step}"));
+    }
+    if let Some(time) = info.time {
+        text.push_str(&format!("  t = {time}"));
+    }
+    if !trajectory.follow {
+        text.push_str("  [paused]");
+    }
+    text
+}
+
+// Spawn the (hidden) trajectory status in the side column
+pub(crate) fn setup_trajectory_panel(
+    mut commands: Commands,
+    column: Single<Entity, With<SidePanelColumn>>,
+) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
+                ..default()
+            },
+            Themed::Panel,
+            TrajectoryPanel,
+            ChildOf(*column),
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                Text::new(""),
+                TextFont {
+                    font: default(),
+                    font_size: 12.0,
+                    ..default()
+                },
+                Themed::Text,
+                TrajectoryText,
+            ));
+        });
+}
+
+// Spawn the (hidden) timeline at the start of the tool panel row
+pub(crate) fn setup_timeline(
+    mut commands: Commands,
+    row: Single<Entity, With<ToolPanelRow>>,
+    playback: Res<Playback>,
+) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            TimelinePanel,
+            ChildOf(*row),
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                Text::new(""),
+                TextFont {
+                    font: default(),
+                    font_size: 12.0,
+                    ..default()
+                },
+                Themed::Text,
+                TimelineText,
+            ));
+            panel
+                .spawn((
+                    Node {
+                        width: Val::Px(TIMELINE_WIDTH),
+                        height: Val::Px(TIMELINE_HEIGHT),
+                        border: UiRect::all(Val::Px(1.0)),
+                        ..default()
+                    },
+                    Themed::Field,
+                    Interaction::default(),
+                    RelativeCursorPosition::default(),
+                    TimelineTrack,
+                ))
+                .with_children(|track| {
+                    track.spawn((
+                        Node {
+                            position_type: PositionType::Absolute,
+                            width: Val::Percent(100.0),
+                            height: Val::Percent(100.0),
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::FlexEnd,
+                            ..default()
+                        },
+                        Sparkline,
+                    ));
+                    track.spawn((
+                        Node {
+                            position_type: PositionType::Absolute,
+                            width: Val::Px(2.0),
+                            height: Val::Percent(100.0),
+                            margin: UiRect::left(Val::Px(-1.0)),
+                            ..default()
+                        },
+                        BackgroundColor(MARKER_COLOR),
+                        TimelineMarker,
+                    ));
+                });
+            spawn_button(panel, "Play", PlayButton);
+            spawn_stepper_row(panel, "Speed", 0, &*playback);
+            spawn_stepper_row(panel, "Smooth", 1, &*playback);
+        });
+}
+
+// Buffer streamed frames and show the newest while following
+pub(crate) fn record_streamed_frames(
+    mut frames: EventReader<StreamedFrame>,
+    mut trajectory: ResMut<Trajectory>,
+    mut updates: EventWriter<UpdateStructure>,
+    mut frame_changes: EventWriter<FrameChanged>,
+) {
+    let mut shown = None;
+    for frame in frames.read() {
+        let position = trajectory.insert(frame.clone());
+        if trajectory.follow && position == trajectory.last() {
+            trajectory.current = position;
+            shown = Some(position);
+        }
+    }
+    if let Some(structure) = shown.and_then(|position| trajectory.structure(position)) {
+        updates.write(structure.into_owned());
+        frame_changes.write_batch(trajectory.frame_changed());
+    }
+}
+
+// Step through the buffered frames with the arrow keys
+pub(crate) fn scrub_trajectory(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut trajectory: ResMut<Trajectory>,
+    mut updates: EventWriter<UpdateStructure>,
+    mut frame_changes: EventWriter<FrameChanged>,
+) {
+    if trajectory.is_empty() {
+        return;
+    }
+    let last = trajectory.last();
+    let pressed = |action| bindings.just_pressed(action, &keys);
+    let target = if pressed(KeyAction::PreviousFrame) {
+        trajectory.current.saturating_sub(1)
+    } else if pressed(KeyAction::NextFrame) {
+        (trajectory.current + 1).min(last)
+    } else if pressed(KeyAction::FirstFrame) {
+        0
+    } else if pressed(KeyAction::LastFrame) {
+        last
+    } else {
+        return;
+    };
+    trajectory.show(target, &mut updates, &mut frame_changes);
+}
+
+// Show the frame under the cursor while the timeline is pressed
+pub(crate) fn scrub_timeline(
+    tracks: Query<(&Interaction, &RelativeCursorPosition), With<TimelineTrack>>,
+    mut trajectory: ResMut<Trajectory>,
+    mut updates: EventWriter<UpdateStructure>,
+    mut frame_changes: EventWriter<FrameChanged>,
+) {
+    if trajectory.is_empty() {
+        return;
+    }
+    for (interaction, cursor) in &tracks {
+        // pressed stays set while dragging off the track, until the button is released
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some(cursor) = cursor.normalized else {
+            continue;
+        };
+        let target = (cursor.x.clamp(0.0, 1.0) * trajectory.last() as f32).round() as usize;
+        if target != trajectory.current {
+            trajectory.show(target, &mut updates, &mut frame_changes);
+        }
+    }
+}
+
+// Keep the status line in sync with the buffer
+pub(crate) fn refresh_trajectory_panel(
+    trajectory: Res<Trajectory>,
+    mut panels: Query<&mut Node, With<TrajectoryPanel>>,
+    mut texts: Query<&mut Text, With<TrajectoryText>>,
+) {
+    if !trajectory.is_changed() {
+        return;
+    }
+    for mut node in &mut panels {
+        node.display = if trajectory.is_empty() {
+            Display::None
+        } else {
+            Display::Flex
+        };
+    }
+    let text = status(&trajectory);
+    for mut content in &mut texts {
+        content.0 = text.clone();
+    }
+}
+
+// Keep the timeline marker, text and sparkline in sync with the buffer; the sparkline bars are
+// only respawned when their energies change
+pub(crate) fn refresh_timeline(
+    mut commands: Commands,
+    trajectory: Res<Trajectory>,
+    mut panels: Query<&mut Node, With<TimelinePanel>>,
+    mut markers: Query<&mut Node, (With<TimelineMarker>, Without<TimelinePanel>)>,
+    mut texts: Query<&mut Text, With<TimelineText>>,
+    sparklines: Query<Entity, With<Sparkline>>,
+    mut shown_sparkline: Local<Vec<Option<f64>>>,
+) {
+    if !trajectory.is_changed() {
+        return;
+    }
+    let count = trajectory.len();
+    for mut node in &mut panels {
+        node.display = if count > 1 {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+    if count < 2 {
+        return;
+    }
+
+    let fraction = trajectory.current as f32 / trajectory.last() as f32;
+    for mut node in &mut markers {
+        node.left = Val::Percent(100.0 * fraction);
+    }
+    let mut text = format!("{count} frames");
+    if let Some(energy) = trajectory.info(trajectory.current).and_then(|f| f.energy) {
+        text.push_str(&format!("  E = {energy:.4}"));
+    }
+    for mut content in &mut texts {
+        content.0 = text.clone();
+    }
+
+    let sparkline = trajectory.sparkline();
+    if sparkline == *shown_sparkline {
+        return;
+    }
+    let energies = sparkline.iter().flatten();
+    let min = energies.clone().copied().fold(f64::INFINITY, f64::min);
+    let max = energies.copied().fold(f64::NEG_INFINITY, f64::max);
+    for entity in &sparklines {
+        commands.entity(entity).despawn_related::<Children>();
+        for energy in &sparkline {
+            // the lowest energy still shows as a sliver, frames without one as a gap
+            let height = energy.map_or(0.0, |energy| {
+                let range = max - min;
+                let level = if range > 0.0 {
+                    (energy - min) / range
+                } else {
+                    0.5
+                };
+                10.0 + 90.0 * level as f32
+            });
+            commands.spawn((
+                Node {
+                    width: Val::Percent(100.0 / sparkline.len() as f32),
+                    height: Val::Percent(height),
+                    ..default()
+                },
+                BackgroundColor(SPARKLINE_COLOR),
+                ChildOf(entity),
+            ));
+        }
+    }
+    *shown_sparkline = sparkline;
+}
+
+// Start or stop playback with the Play button or its key; playing from the last frame starts
+// over at the first
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn playback_controls(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    focused: Res<FocusedField>,
+    buttons: Query<&Interaction, (Changed<Interaction>, With<PlayButton>)>,
+    mut playback: ResMut<Playback>,
+    mut trajectory: ResMut<Trajectory>,
+    mut updates: EventWriter<UpdateStructure>,
+    mut frame_changes: EventWriter<FrameChanged>,
+) {
+    let clicked = buttons.iter().any(|i| *i == Interaction::Pressed);
+    let pressed = focused.0.is_none() && bindings.just_pressed(KeyAction::PlayPause, &keys);
+    if !(clicked || pressed) || trajectory.len() < 2 {
+        return;
+    }
+    playback.playing = !playback.playing;
+    if playback.playing && trajectory.current == trajectory.last() {
+        trajectory.show(0, &mut updates, &mut frame_changes);
+    }
+}
+
+// Advance playing trajectories at the playback rate, showing the atoms between frames when
+// smoothing is on
+pub(crate) fn play_trajectory(
+    time: Res<Time>,
+    mut playback: ResMut<Playback>,
+    mut trajectory: ResMut<Trajectory>,
+    mut updates: EventWriter<UpdateStructure>,
+    mut frame_changes: EventWriter<FrameChanged>,
+    // fraction of the way from the frame on screen to the next
+    mut progress: Local<f32>,
+) {
+    if !playback.playing {
+        *progress = 0.0;
+        return;
+    }
+    let last = trajectory.last();
+    *progress += time.delta_secs() * PLAYBACK_RATES[playback.rate];
+    let steps = progress.floor();
+    *progress -= steps;
+    let target = (trajectory.current + steps as usize).min(last);
+    if target != trajectory.current {
+        trajectory.show(target, &mut updates, &mut frame_changes);
+    }
+    if target == last {
+        playback.playing = false;
+        return;
+    }
+    if playback.interpolate {
+        if let (Some(from), Some(to)) = (
+            trajectory.structure(target),
+            trajectory.structure(target + 1),
+        ) {
+            updates.write_batch(interpolate(&from, &to, *progress));
+        }
+    }
+}
+
+// Keep the Play button label in step with the playback
+pub(crate) fn refresh_play_button(
+    playback: Res<Playback>,
+    buttons: Query<&Children, With<PlayButton>>,
+    mut texts: Query<&mut Text>,
+) {
+    if !playback.is_changed() {
+        return;
+    }
+    let label = if playback.playing { "Pause" } else { "Play" };
+    for children in &buttons {
+        for child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(child) {
+                text.0 = label.to_string();
+            }
+        }
+    }
+}
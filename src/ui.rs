@@ -1,32 +1,143 @@
 use std::collections::HashMap;
+use std::time::Duration;
 
 use bevy::input::mouse::{MouseMotion, MouseWheel};
+use bevy::platform::time::Instant;
 use bevy::prelude::*;
 use bevy::render::camera::Viewport;
-use bevy::render::view::RenderLayers;
+use bevy::render::primitives::Aabb;
+use bevy::render::view::{NoIndirectDrawing, RenderLayers};
+use bevy::window::{WindowResized, WindowScaleFactorChanged};
+use serde::{Deserialize, Serialize};
 
-use crate::constants::{get_element_color, get_element_size};
-use crate::structure::{AtomEntity, Crystal};
+use crate::analysis::Coordination;
+use crate::atom_picking::{AtomPickingCamera, PickedAtoms};
+use crate::bindings::{KeyAction, KeyBindings};
+use crate::cell::{find_conventional, find_primitive};
+use crate::color::{coordination_color, ColorBy, ColorScheme, ElementOverrides};
+use crate::constants::{get_element_size, Element};
+use crate::events::AtomPicked;
+use crate::file_dialog::OpenFileButton;
+use crate::instancing::{
+    chunk_bounds, impostor_quad, partition_atoms, AtomInstance, AtomInstances, AtomRendering,
+};
+use crate::lighting::{KeyLight, LightingRig};
+use crate::palette::PaletteButton;
+use crate::structure::{Crystal, Selection};
+use crate::theme::Themed;
+use crate::widgets::{spawn_button, FocusedField};
 
-const LAYER_GIZMO: RenderLayers = RenderLayers::layer(1);
-const LAYER_CANVAS: RenderLayers = RenderLayers::layer(0);
+pub(crate) const LAYER_GIZMO: RenderLayers = RenderLayers::layer(1);
+pub(crate) const LAYER_CANVAS: RenderLayers = RenderLayers::layer(0);
+
+/// Side of the axis gizmo viewport, and its distance from the window corner, in logical pixels.
+const GIZMO_VIEWPORT_SIZE: f32 = 200.0;
+const GIZMO_VIEWPORT_MARGIN: f32 = 10.0;
+
+const MIN_DISTANCE: f32 = 0.2;
+const MAX_DISTANCE: f32 = 200.0;
+
+/// Extra room left around the bounding sphere when fitting the view.
+const FIT_MARGIN: f32 = 1.1;
+/// Seconds the camera takes to glide to a new view.
+const TRANSITION_DURATION: f32 = 0.4;
+/// Time per frame spent building atom chunks, so that huge structures appear over several
+/// frames instead of stalling the window.
+const ATOM_BUILD_BUDGET: Duration = Duration::from_millis(8);
 
 #[derive(Component)]
 pub(crate) struct MainCamera;
 
+/// Camera drawing the axis gizmo in the bottom-left corner.
+#[derive(Component)]
+pub(crate) struct AxisCamera;
+
 /// Identifier for a reusable toggle interaction.
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
-enum ToggleId {
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub(crate) enum ToggleId {
     LightAttachment,
+    Shadows,
+    PeriodicTable,
+    SlabTool,
+    NanoparticleTool,
+    DefectTool,
+    Composition,
+    LatticeEditor,
+    BondStatistics,
+    StructureInfo,
+    Performance,
+    Settings,
+    Labels,
+    Console,
+    #[cfg(feature = "fetch")]
+    Open,
+    #[cfg(feature = "scripting")]
+    Script,
 }
 
 // struct AmbientLight
 
 impl ToggleId {
-    fn label(self, state: bool) -> &'static str {
+    /// Every toggle, in the order of the buttons.
+    pub(crate) const ALL: &[ToggleId] = &[
+        ToggleId::LightAttachment,
+        ToggleId::Shadows,
+        ToggleId::PeriodicTable,
+        ToggleId::SlabTool,
+        ToggleId::NanoparticleTool,
+        ToggleId::DefectTool,
+        ToggleId::Composition,
+        ToggleId::LatticeEditor,
+        ToggleId::BondStatistics,
+        ToggleId::StructureInfo,
+        ToggleId::Performance,
+        ToggleId::Settings,
+        ToggleId::Labels,
+        ToggleId::Console,
+        #[cfg(feature = "fetch")]
+        ToggleId::Open,
+        #[cfg(feature = "scripting")]
+        ToggleId::Script,
+    ];
+
+    pub(crate) fn label(self, state: bool) -> &'static str {
         match (self, state) {
             (ToggleId::LightAttachment, true) => "Light: Attached",
             (ToggleId::LightAttachment, false) => "Light: Detached",
+            (ToggleId::Shadows, true) => "Shadows: On",
+            (ToggleId::Shadows, false) => "Shadows: Off",
+            (ToggleId::PeriodicTable, true) => "Elements: Shown",
+            (ToggleId::PeriodicTable, false) => "Elements: Hidden",
+            (ToggleId::SlabTool, true) => "Slab Tool: Shown",
+            (ToggleId::SlabTool, false) => "Slab Tool: Hidden",
+            (ToggleId::NanoparticleTool, true) => "Cluster Tool: Shown",
+            (ToggleId::NanoparticleTool, false) => "Cluster Tool: Hidden",
+            (ToggleId::DefectTool, true) => "Defects: Shown",
+            (ToggleId::DefectTool, false) => "Defects: Hidden",
+            (ToggleId::Composition, true) => "Composition: Shown",
+            (ToggleId::Composition, false) => "Composition: Hidden",
+            (ToggleId::LatticeEditor, true) => "Lattice: Shown",
+            (ToggleId::LatticeEditor, false) => "Lattice: Hidden",
+            (ToggleId::BondStatistics, true) => "Bonds: Shown",
+            (ToggleId::BondStatistics, false) => "Bonds: Hidden",
+            (ToggleId::StructureInfo, true) => "Info: Shown",
+            (ToggleId::StructureInfo, false) => "Info: Hidden",
+            (ToggleId::Performance, true) => "Stats: Shown",
+            (ToggleId::Performance, false) => "Stats: Hidden",
+            (ToggleId::Settings, true) => "Settings: Shown",
+            (ToggleId::Settings, false) => "Settings: Hidden",
+            (ToggleId::Labels, true) => "Labels: Shown",
+            (ToggleId::Labels, false) => "Labels: Hidden",
+            (ToggleId::Console, true) => "Log: Shown",
+            (ToggleId::Console, false) => "Log: Hidden",
+            #[cfg(feature = "fetch")]
+            (ToggleId::Open, true) => "Open: Shown",
+            #[cfg(feature = "fetch")]
+            (ToggleId::Open, false) => "Open: Hidden",
+            #[cfg(feature = "scripting")]
+            (ToggleId::Script, true) => "Script: Shown",
+            #[cfg(feature = "scripting")]
+            (ToggleId::Script, false) => "Script: Hidden",
         }
     }
 }
@@ -40,15 +151,25 @@ pub(crate) struct ToggleStates {
 }
 
 impl ToggleStates {
-    fn register(&mut self, id: ToggleId, initial_state: bool) {
+    pub(crate) fn register(&mut self, id: ToggleId, initial_state: bool) {
         self.states.entry(id).or_insert(initial_state);
     }
 
-    fn get(&self, id: ToggleId) -> bool {
+    pub(crate) fn get(&self, id: ToggleId) -> bool {
         self.states.get(&id).copied().unwrap_or(false)
     }
 
-    fn toggle(&mut self, id: ToggleId) -> bool {
+    // State restored from an earlier session, which wins over the initial state
+    pub(crate) fn restore(&mut self, id: ToggleId, state: bool) {
+        self.states.insert(id, state);
+    }
+
+    // Toggles with a known state
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (ToggleId, bool)> + '_ {
+        self.states.iter().map(|(&id, &state)| (id, state))
+    }
+
+    pub(crate) fn toggle(&mut self, id: ToggleId) -> bool {
         let new_state = !self.get(id);
         self.states.insert(id, new_state);
         new_state
@@ -59,10 +180,6 @@ impl ToggleStates {
 #[derive(Resource)]
 pub(crate) struct MainCameraEntity(pub Entity);
 
-/// Marks the primary directional light used for shading.
-#[derive(Resource)]
-pub(crate) struct MainLightEntity(pub Entity);
-
 /// Component identifying a toggle button instance.
 #[derive(Component)]
 pub(crate) struct ToggleButton {
@@ -75,10 +192,118 @@ pub(crate) struct ToggleText {
     id: ToggleId,
 }
 
+/// Panel shown or hidden by a toggle.
+#[derive(Component)]
+pub(crate) struct ToggledPanel(pub ToggleId);
+
+/// Column at the top-right that stacks the information panels.
+#[derive(Component)]
+pub(crate) struct SidePanelColumn;
+
+/// Row along the bottom edge that holds the tool panels.
+#[derive(Component)]
+pub(crate) struct ToolPanelRow;
+
+/// Request to glide the camera onto the whole structure, e.g. after loading a new one.
+#[derive(Event)]
+pub(crate) struct FitView;
+
+/// Multiplier applied to mouse rotation, panning and zooming.
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub(crate) struct MouseSensitivity(pub f32);
+
+impl Default for MouseSensitivity {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// Multiplier applied to every atom radius.
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub(crate) struct AtomScale(pub f32);
+
+impl Default for AtomScale {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// Tessellation of the atom spheres and multisampling of the main camera.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RenderQuality {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+impl RenderQuality {
+    // Icosphere subdivisions the quality starts the atom spheres with
+    pub(crate) fn subdivisions(self) -> u32 {
+        match self {
+            RenderQuality::Low => 2,
+            RenderQuality::Medium => 5,
+            RenderQuality::High => 10,
+        }
+    }
+
+    pub(crate) fn msaa(self) -> Msaa {
+        match self {
+            RenderQuality::Low => Msaa::Off,
+            RenderQuality::Medium => Msaa::Sample4,
+            RenderQuality::High => Msaa::Sample8,
+        }
+    }
+}
+
+/// Icosphere subdivisions of the atom spheres; the render quality sets it, and the settings
+/// panel steps it on its own.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct SphereDetail(pub u32);
+
+impl Default for SphereDetail {
+    fn default() -> Self {
+        Self(RenderQuality::default().subdivisions())
+    }
+}
+
+/// Sphere meshes made so far, by subdivisions, reused across reloads and detail changes;
+/// impostors, which do not depend on the detail, are under None.
+#[derive(Resource, Default)]
+pub(crate) struct SphereMeshes(HashMap<Option<u32>, Handle<Mesh>>);
+
+impl SphereMeshes {
+    // Mesh the atoms are instances of, made on first use
+    fn get(
+        &mut self,
+        meshes: &mut Assets<Mesh>,
+        detail: SphereDetail,
+        rendering: AtomRendering,
+    ) -> Handle<Mesh> {
+        let key = match rendering {
+            AtomRendering::Meshes => Some(detail.0),
+            AtomRendering::Impostors => None,
+        };
+        self.0
+            .entry(key)
+            .or_insert_with(|| {
+                meshes.add(match key {
+                    Some(subdivisions) => Sphere::new(1.0)
+                        .mesh()
+                        .ico(subdivisions)
+                        .expect("subdivisions are below the icosphere limit"),
+                    None => impostor_quad(),
+                })
+            })
+            .clone()
+    }
+}
+
 /// Event emitted whenever a toggle switches state.
 #[derive(Event)]
 pub struct ToggleEvent {
-    id: ToggleId,
+    pub(crate) id: ToggleId,
     pub state: bool,
 }
 
@@ -89,53 +314,178 @@ pub(crate) struct CameraRig {
     distance: f32,
     initial_target: Vec3,
     initial_translation: Vec3,
-    initial_rotation: Quat,
-    initial_scale: Vec3,
+    animation: Option<CameraAnimation>,
+}
+
+impl CameraRig {
+    /// Point the camera orbits around.
+    pub(crate) fn target(&self) -> Vec3 {
+        self.target
+    }
+
+    /// Moves the camera to `position` at once, orbiting around `target` from there on.
+    #[cfg_attr(
+        not(any(feature = "websocket", feature = "scripting")),
+        allow(dead_code)
+    )]
+    pub(crate) fn place(&mut self, transform: &mut Transform, position: Vec3, target: Vec3) {
+        self.animation = None;
+        self.target = target;
+        self.distance = position.distance(target).clamp(MIN_DISTANCE, MAX_DISTANCE);
+        let direction = (position - target).normalize_or(Vec3::Z);
+        transform.translation = target + direction * self.distance;
+        transform.look_at(target, Vec3::Y);
+    }
+
+    /// Glides the camera to orbit `target` at `distance`, turning to look at it from
+    /// `direction` when given.
+    fn glide_to(
+        &mut self,
+        transform: &Transform,
+        target: Vec3,
+        distance: f32,
+        direction: Option<Vec3>,
+    ) {
+        let from_direction = (transform.translation - self.target).normalize_or(Vec3::Z);
+        self.animation = Some(CameraAnimation {
+            from_target: self.target,
+            to_target: target,
+            from_distance: self.distance,
+            to_distance: distance.clamp(MIN_DISTANCE, MAX_DISTANCE),
+            turn: direction.map(|direction| (from_direction, direction.normalize_or(Vec3::Z))),
+            elapsed: 0.0,
+        });
+    }
+
+    /// Orbits the point ahead of the camera from now on, at the current distance, e.g. after
+    /// flying somewhere.
+    pub(crate) fn orbit_ahead(&mut self, transform: &Transform) {
+        self.animation = None;
+        self.target = transform.translation + transform.forward() * self.distance;
+    }
+
+    /// Glides the camera around the target until it looks at it from `direction`, still
+    /// heading for the target of a glide under way.
+    pub(crate) fn turn_to(&mut self, transform: &Transform, direction: Vec3) {
+        let (target, distance) = self
+            .animation
+            .as_ref()
+            .map_or((self.target, self.distance), |animation| {
+                (animation.to_target, animation.to_distance)
+            });
+        self.glide_to(transform, target, distance, Some(direction));
+    }
+}
+
+/// Eased glide of the orbit target, distance and direction, e.g. when focusing on the structure
+/// or resetting the view.
+pub(crate) struct CameraAnimation {
+    from_target: Vec3,
+    to_target: Vec3,
+    from_distance: f32,
+    to_distance: f32,
+    /// Directions from the target to the camera to turn between, when snapping to a view.
+    turn: Option<(Vec3, Vec3)>,
+    elapsed: f32,
+}
+
+impl CameraAnimation {
+    /// Advances the animation and returns the interpolated target, distance and direction
+    /// (if turning), plus whether it has finished.
+    fn step(&mut self, dt: f32) -> (Vec3, f32, Option<Vec3>, bool) {
+        self.elapsed += dt;
+        let t = (self.elapsed / TRANSITION_DURATION).clamp(0.0, 1.0);
+        let eased = t * t * (3.0 - 2.0 * t);
+        let direction = self.turn.map(|(from, to)| {
+            Quat::IDENTITY.slerp(Quat::from_rotation_arc(from, to), eased) * from
+        });
+        (
+            self.from_target.lerp(self.to_target, eased),
+            self.from_distance + (self.to_distance - self.from_distance) * eased,
+            direction,
+            t >= 1.0,
+        )
+    }
+}
+
+/// What an atom color is shared by: its element, or its value of the coloring property.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum AtomColorKey {
+    Element(Element),
+    Coordination(usize),
 }
 
 /// Button that resets the camera to its original position/orientation.
 #[derive(Component)]
 pub(crate) struct ResetCameraButton;
 
-// System to set up the 3D scene
-pub(crate) fn setup_scene(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    crystal: Res<Crystal>,
-) {
-    // Create a sphere mesh for atoms
-    let sphere_mesh = meshes.add(Mesh::from(Sphere { radius: 1.0 }));
+/// Button cycling through the properties atoms can be colored by.
+#[derive(Component)]
+pub(crate) struct ColorByButton;
 
-    // Create materials for different elements
-    let mut element_materials: HashMap<String, Handle<StandardMaterial>> = HashMap::new();
+/// Text on the color-by button.
+#[derive(Component)]
+pub(crate) struct ColorByText;
 
-    // Spawn atoms as 3D spheres
-    for atom in &crystal.atoms {
-        // Get or create material for this element
-        let material = element_materials
-            .entry(atom.element.clone())
-            .or_insert_with(|| {
-                materials.add(StandardMaterial {
-                    base_color: get_element_color(&atom.element),
-                    metallic: 0.0,
-                    ..default()
-                })
-            })
-            .clone();
-
-        // Spawn the atom as a sphere
-        commands.spawn((
-            Mesh3d(sphere_mesh.clone()),
-            MeshMaterial3d(material),
-            Transform {
-                translation: Vec3::new(atom.x, atom.y, atom.z),
-                scale: Vec3::splat(get_element_size(&atom.element)),
-                ..default()
-            },
-            AtomEntity,
-        ));
+/// Cell setting a conversion button switches to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CellSetting {
+    Primitive,
+    Conventional,
+}
+
+impl CellSetting {
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            CellSetting::Primitive => "Primitive Cell",
+            CellSetting::Conventional => "Conventional Cell",
+        }
     }
+}
+
+/// Point moved to the origin by a centering button.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CenterMode {
+    Centroid,
+    CenterOfMass,
+}
+
+impl CenterMode {
+    fn label(self) -> &'static str {
+        match self {
+            CenterMode::Centroid => "Center: Centroid",
+            CenterMode::CenterOfMass => "Center: Mass",
+        }
+    }
+}
+
+/// Button translating the structure so the chosen center sits at the origin.
+#[derive(Component)]
+pub(crate) struct CenterButton(CenterMode);
+
+/// Button converting the structure to another cell setting.
+#[derive(Component)]
+pub(crate) struct CellConversionButton(CellSetting);
+
+/// Header button that opens the color scheme dropdown.
+#[derive(Component)]
+pub(crate) struct ColorSchemeButton;
+
+/// Text on the dropdown header showing the active scheme.
+#[derive(Component)]
+pub(crate) struct ColorSchemeText;
+
+/// Container for the dropdown entries, hidden while collapsed.
+#[derive(Component)]
+pub(crate) struct ColorSchemeList;
+
+/// One entry of the color scheme dropdown.
+#[derive(Component)]
+pub(crate) struct ColorSchemeOption(ColorScheme);
+
+// System to set up the 3D scene
+pub(crate) fn setup_scene(mut commands: Commands) {
+    // Atoms are spawned by refresh_atoms_system on its first run, which sees Crystal as changed
 
     // Remove static scene light; lighting will be attached to the camera in setup_camera
 
@@ -147,21 +497,90 @@ pub(crate) fn setup_scene(
     });
 }
 
+// Spawn the containers the information and tool panels are laid out in
+pub(crate) fn setup_side_panels(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            right: Val::Px(8.0),
+            top: Val::Px(8.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::FlexEnd,
+            row_gap: Val::Px(8.0),
+            ..default()
+        },
+        SidePanelColumn,
+    ));
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            // clear of the axis gizmo viewport in the bottom-left corner
+            left: Val::Px(220.0),
+            bottom: Val::Px(8.0),
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::FlexEnd,
+            column_gap: Val::Px(8.0),
+            ..default()
+        },
+        ToolPanelRow,
+    ));
+}
+
+// Viewport of the axis gizmo in the bottom-left corner of `window`, shrunk to fit small
+// windows; None when there is no room for it at all
+fn gizmo_viewport(window: &Window) -> Option<Viewport> {
+    let scale = window.scale_factor();
+    let window_size = window.physical_size();
+    let margin = (GIZMO_VIEWPORT_MARGIN * scale).round() as u32;
+    let room = window_size.min_element().checked_sub(2 * margin)?;
+    let side = ((GIZMO_VIEWPORT_SIZE * scale).round() as u32).min(room);
+    if side == 0 {
+        return None;
+    }
+    Some(Viewport {
+        physical_position: UVec2::new(margin, window_size.y - side - margin),
+        physical_size: UVec2::splat(side),
+        ..default()
+    })
+}
+
+// Keep the axis gizmo in its corner when the window is resized or moves to a screen with
+// another scale factor
+pub(crate) fn update_gizmo_viewport(
+    mut resized: EventReader<WindowResized>,
+    mut rescaled: EventReader<WindowScaleFactorChanged>,
+    windows: Query<&Window>,
+    mut cameras: Query<&mut Camera, With<AxisCamera>>,
+) {
+    // both readers are drained, so that old events do not trigger a later update
+    if resized.read().count() + rescaled.read().count() == 0 {
+        return;
+    }
+    let Ok(window) = windows.single() else {
+        return;
+    };
+    let viewport = gizmo_viewport(window);
+    for mut camera in &mut cameras {
+        camera.is_active = viewport.is_some();
+        if viewport.is_some() {
+            camera.viewport.clone_from(&viewport);
+        }
+    }
+}
+
 // System to set up the camera
 pub fn setup_cameras(
     mut commands: Commands,
     mut toggle_states: ResMut<ToggleStates>,
+    mut lighting: ResMut<LightingRig>,
     windows: Query<&Window>,
+    quality: Res<RenderQuality>,
 ) {
     let window = windows.single().unwrap();
-    let viewport_size = UVec2::new(200, 200);
-    let bottom_left_y = window.physical_height() - viewport_size.y - 10;
-    let viewport_position = UVec2::new(10, bottom_left_y);
+    let gizmo_viewport = gizmo_viewport(window);
 
     let camera_transform = Transform::from_xyz(5.0, 5.0, 5.0).looking_at(Vec3::ZERO, Vec3::Y);
     let initial_translation = camera_transform.translation;
-    let initial_rotation = camera_transform.rotation;
-    let initial_scale = camera_transform.scale;
     let initial_target = Vec3::ZERO;
 
     // Spawn cameras
@@ -173,68 +592,227 @@ pub fn setup_cameras(
                 ..default()
             },
             IsDefaultUiCamera,
+            quality.msaa(),
+            // the atom instances are drawn with plain draw calls
+            NoIndirectDrawing,
+            AtomPickingCamera,
             Transform::from_xyz(5.0, 5.0, 5.0).looking_at(Vec3::ZERO, Vec3::Y),
             LAYER_CANVAS,
             MainCamera,
         ))
-        .with_children(|parent| {
-            // Attach a directional light to the camera so it always points where the camera looks
-            // For directional lights, only rotation matters; translation is ignored
-            parent.spawn((
-                DirectionalLight {
-                    shadows_enabled: true,
-                    ..default()
-                },
-                Transform::default(), // inherit camera rotation; light points along -Z in local space
-            ));
-        })
         .with_children(|parent| {
             // GIZMO CAMERA
             parent.spawn((
                 Camera3d { ..default() },
                 Camera {
                     order: 1,
-                    viewport: Some(Viewport {
-                        physical_position: viewport_position,
-                        physical_size: viewport_size,
-                        ..default()
-                    }),
+                    is_active: gizmo_viewport.is_some(),
+                    viewport: gizmo_viewport,
                     ..default()
                 },
                 Transform::default(),
                 GlobalTransform::default(),
                 LAYER_GIZMO,
+                AxisCamera,
             ));
         })
         .id();
 
-    let light_entity = commands
-        .spawn((
-            DirectionalLight {
-                shadows_enabled: true,
-                ..default()
-            },
-            Transform::default(), // inherit camera rotation; light points along -Z in local space
-            ChildOf(camera_entity),
-        ))
+    // The lights of the lighting rig hang from this pivot, which follows the camera's rotation
+    let light_pivot = commands
+        .spawn((Transform::default(), ChildOf(camera_entity)))
         .id();
+    lighting.pivot = Some(light_pivot);
 
     toggle_states.register(ToggleId::LightAttachment, true);
+    toggle_states.register(ToggleId::Shadows, true);
 
     commands.insert_resource(MainCameraEntity(camera_entity));
-    commands.insert_resource(MainLightEntity(light_entity));
     commands.insert_resource(CameraRig {
         target: initial_target,
         distance: initial_translation.distance(initial_target),
         initial_target,
         initial_translation,
-        initial_rotation,
-        initial_scale,
+        animation: None,
     });
 }
 
+/// Camera distance at which a sphere of `radius` fills the view of `projection`.
+pub(crate) fn fit_distance(radius: f32, projection: &Projection) -> f32 {
+    let half_fov = match projection {
+        Projection::Perspective(perspective) => {
+            let vertical = perspective.fov * 0.5;
+            let horizontal = (vertical.tan() * perspective.aspect_ratio).atan();
+            vertical.min(horizontal)
+        }
+        _ => std::f32::consts::FRAC_PI_8,
+    };
+    (radius * FIT_MARGIN / half_fov.sin()).clamp(MIN_DISTANCE, MAX_DISTANCE)
+}
+
+// Frame the loaded structure instead of relying on the hardcoded start position
+pub(crate) fn fit_camera_on_load(
+    crystal: Res<Crystal>,
+    mut camera_query: Query<(&mut Transform, &Projection), With<MainCamera>>,
+    mut camera_rig: ResMut<CameraRig>,
+) {
+    let Some((center, radius)) = crystal.bounding_sphere(&[]) else {
+        return;
+    };
+    let Ok((mut transform, projection)) = camera_query.single_mut() else {
+        return;
+    };
+
+    let direction = (transform.translation - camera_rig.target).normalize_or(Vec3::Z);
+    let distance = fit_distance(radius, projection);
+
+    transform.translation = center + direction * distance;
+    transform.look_at(center, Vec3::Y);
+
+    // Reset Camera returns to the fitted view
+    camera_rig.target = center;
+    camera_rig.distance = distance;
+    camera_rig.initial_target = center;
+    camera_rig.initial_translation = transform.translation;
+}
+
+// Press F to glide the camera onto the selection, or onto the whole structure when nothing is
+// selected. A `FitView` request always frames the whole structure.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn focus_camera_hotkey(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    focused: Res<FocusedField>,
+    mut fit_requests: EventReader<FitView>,
+    crystal: Res<Crystal>,
+    selection: Res<Selection>,
+    camera_query: Query<(&Transform, &Projection), With<MainCamera>>,
+    mut camera_rig: ResMut<CameraRig>,
+) {
+    let indices: &[usize] = if fit_requests.read().count() > 0 {
+        &[]
+    } else if bindings.just_pressed(KeyAction::FocusCamera, &keys) && focused.0.is_none() {
+        &selection.atoms
+    } else {
+        return;
+    };
+    let Some((center, radius)) = crystal.bounding_sphere(indices) else {
+        return;
+    };
+    let Ok((transform, projection)) = camera_query.single() else {
+        return;
+    };
+
+    // a turn to a view under way is carried on
+    let direction = camera_rig
+        .animation
+        .as_ref()
+        .and_then(|animation| animation.turn)
+        .map(|(_, to)| to);
+    camera_rig.glide_to(
+        transform,
+        center,
+        fit_distance(radius, projection),
+        direction,
+    );
+}
+
+// Left click picks an atom; shift+click adds to or removes from the selection
+pub(crate) fn select_atom_on_click(
+    trigger: Trigger<Pointer<Click>>,
+    atoms: Query<(), With<AtomInstances>>,
+    picked_atoms: Res<PickedAtoms>,
+    keys: Res<ButtonInput<KeyCode>>,
+    crystal: Res<Crystal>,
+    mut selection: ResMut<Selection>,
+    mut picked: EventWriter<AtomPicked>,
+) {
+    if trigger.event().button != PointerButton::Primary {
+        return;
+    }
+    if !atoms.contains(trigger.target()) {
+        return;
+    }
+    let Some(index) = picked_atoms.clicked(trigger.event().pointer_id) else {
+        return;
+    };
+
+    let extend = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if let Some(picked_atom) = crystal.atoms.get(index) {
+        picked.write(AtomPicked {
+            index,
+            element: picked_atom.element,
+            position: picked_atom.position(),
+            extend,
+        });
+    }
+    if extend {
+        if let Some(position) = selection.atoms.iter().position(|&i| i == index) {
+            selection.atoms.remove(position);
+        } else {
+            selection.atoms.push(index);
+        }
+    } else {
+        selection.atoms = vec![index];
+    }
+}
+
+// Draw the edges of the unit cell for periodic structures
+pub(crate) fn draw_unit_cell(mut gizmos: Gizmos, crystal: Res<Crystal>) {
+    let Some(lattice) = crystal.lattice else {
+        return;
+    };
+    let [a, b, c] = [lattice.x_axis, lattice.y_axis, lattice.z_axis];
+    let color = Color::srgb(0.8, 0.8, 0.8);
+
+    for (start, edge) in [
+        (Vec3::ZERO, a),
+        (Vec3::ZERO, b),
+        (Vec3::ZERO, c),
+        (a, b),
+        (a, c),
+        (b, a),
+        (b, c),
+        (c, a),
+        (c, b),
+        (a + b, c),
+        (a + c, b),
+        (b + c, a),
+    ] {
+        gizmos.line(start, start + edge, color);
+    }
+}
+
+// Outline selected atoms with a wire sphere
+pub(crate) fn draw_selection(
+    mut gizmos: Gizmos,
+    crystal: Res<Crystal>,
+    selection: Res<Selection>,
+    scale: Res<AtomScale>,
+    overrides: Res<ElementOverrides>,
+) {
+    for atom in selection
+        .atoms
+        .iter()
+        .filter_map(|&i| crystal.atoms.get(i))
+        .filter(|atom| !overrides.is_hidden(atom.element))
+    {
+        gizmos.sphere(
+            Isometry3d::from_translation(atom.position()),
+            get_element_size(atom.element) * scale.0 * 1.25,
+            Color::srgb(1.0, 0.85, 0.2),
+        );
+    }
+}
+
 // Setup minimal UI with toggle buttons
-pub fn setup_buttons(mut commands: Commands, toggle_states: Res<ToggleStates>) {
+pub fn setup_buttons(
+    mut commands: Commands,
+    toggle_states: Res<ToggleStates>,
+    color_scheme: Res<ColorScheme>,
+    color_by: Res<ColorBy>,
+    bindings: Res<KeyBindings>,
+) {
     // buttons at top-left
     commands
         .spawn((
@@ -249,7 +827,17 @@ pub fn setup_buttons(mut commands: Commands, toggle_states: Res<ToggleStates>) {
             BackgroundColor(Color::NONE),
         ))
         .with_children(|parent| {
-            let mut spawn_button = |id: ToggleId| {
+            spawn_button(parent, "Open File...", OpenFileButton);
+            spawn_button(
+                parent,
+                &format!(
+                    "Commands ({})",
+                    bindings.key_text(KeyAction::CommandPalette)
+                ),
+                PaletteButton,
+            );
+
+            let mut spawn_toggle = |id: ToggleId| {
                 let state = toggle_states.get(id);
                 let label = id.label(state);
 
@@ -261,8 +849,7 @@ pub fn setup_buttons(mut commands: Commands, toggle_states: Res<ToggleStates>) {
                             border: UiRect::all(Val::Px(1.0)),
                             ..default()
                         },
-                        BorderColor(Color::srgb(0.3, 0.3, 0.3)),
-                        BackgroundColor(Color::srgb(0.15, 0.15, 0.15)),
+                        Themed::Button,
                         ToggleButton { id },
                     ))
                     .with_children(|button| {
@@ -273,14 +860,15 @@ pub fn setup_buttons(mut commands: Commands, toggle_states: Res<ToggleStates>) {
                                 font_size: 12.0,
                                 ..default()
                             },
-                            TextColor(Color::WHITE),
+                            Themed::Text,
                             ToggleText { id },
                         ));
                     });
             };
 
-            let id = ToggleId::LightAttachment;
-            spawn_button(id);
+            for &id in ToggleId::ALL {
+                spawn_toggle(id);
+            }
 
             parent
                 .spawn((
@@ -290,8 +878,7 @@ pub fn setup_buttons(mut commands: Commands, toggle_states: Res<ToggleStates>) {
                         border: UiRect::all(Val::Px(1.0)),
                         ..default()
                     },
-                    BorderColor(Color::srgb(0.3, 0.3, 0.3)),
-                    BackgroundColor(Color::srgb(0.15, 0.15, 0.15)),
+                    Themed::Button,
                     ResetCameraButton,
                 ))
                 .with_children(|button| {
@@ -302,111 +889,282 @@ pub fn setup_buttons(mut commands: Commands, toggle_states: Res<ToggleStates>) {
                             font_size: 12.0,
                             ..default()
                         },
-                        TextColor(Color::WHITE),
+                        Themed::Text,
+                    ));
+                });
+
+            parent
+                .spawn((
+                    Button,
+                    Node {
+                        padding: UiRect::axes(Val::Px(10.0), Val::Px(6.0)),
+                        border: UiRect::all(Val::Px(1.0)),
+                        ..default()
+                    },
+                    Themed::Button,
+                    ColorByButton,
+                ))
+                .with_children(|button| {
+                    button.spawn((
+                        Text::new(format!("Color by: {}", color_by.label())),
+                        TextFont {
+                            font: default(),
+                            font_size: 12.0,
+                            ..default()
+                        },
+                        Themed::Text,
+                        ColorByText,
                     ));
                 });
+
+            for setting in [CellSetting::Primitive, CellSetting::Conventional] {
+                parent
+                    .spawn((
+                        Button,
+                        Node {
+                            padding: UiRect::axes(Val::Px(10.0), Val::Px(6.0)),
+                            border: UiRect::all(Val::Px(1.0)),
+                            ..default()
+                        },
+                        Themed::Button,
+                        CellConversionButton(setting),
+                    ))
+                    .with_children(|button| {
+                        button.spawn((
+                            Text::new(setting.label()),
+                            TextFont {
+                                font: default(),
+                                font_size: 12.0,
+                                ..default()
+                            },
+                            Themed::Text,
+                        ));
+                    });
+            }
+
+            for mode in [CenterMode::Centroid, CenterMode::CenterOfMass] {
+                spawn_button(parent, mode.label(), CenterButton(mode));
+            }
+
+            // Color scheme dropdown: header button plus a collapsed list of schemes
+            parent
+                .spawn((
+                    Button,
+                    Node {
+                        padding: UiRect::axes(Val::Px(10.0), Val::Px(6.0)),
+                        border: UiRect::all(Val::Px(1.0)),
+                        ..default()
+                    },
+                    Themed::Button,
+                    ColorSchemeButton,
+                ))
+                .with_children(|button| {
+                    button.spawn((
+                        Text::new(format!("Colors: {}", color_scheme.label())),
+                        TextFont {
+                            font: default(),
+                            font_size: 12.0,
+                            ..default()
+                        },
+                        Themed::Text,
+                        ColorSchemeText,
+                    ));
+                });
+
+            parent
+                .spawn((
+                    Node {
+                        display: Display::None,
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(2.0),
+                        ..default()
+                    },
+                    ColorSchemeList,
+                ))
+                .with_children(|list| {
+                    for scheme in ColorScheme::ALL {
+                        list.spawn((
+                            Button,
+                            Node {
+                                padding: UiRect::axes(Val::Px(10.0), Val::Px(4.0)),
+                                ..default()
+                            },
+                            Themed::Button,
+                            ColorSchemeOption(scheme),
+                        ))
+                        .with_children(|button| {
+                            button.spawn((
+                                Text::new(scheme.label()),
+                                TextFont {
+                                    font: default(),
+                                    font_size: 12.0,
+                                    ..default()
+                                },
+                                Themed::Text,
+                            ));
+                        });
+                    }
+                });
         });
 }
 
-pub(crate) fn spawn_axis(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    let mut axis = |color: Color,
-                    (x, y, z): (f32, f32, f32),
-                    (x_, y_, z_): (f32, f32, f32)|
-     -> (
-        (Mesh3d, MeshMaterial3d<StandardMaterial>, Transform),
-        RenderLayers,
-    ) {
-        let mesh = meshes.add(Mesh::from(Cuboid::new(x, y, z)));
-        let material = materials.add(StandardMaterial {
-            base_color: color,
-            unlit: true,
-            ..default()
-        });
-        (
-            (
-                Mesh3d(mesh),
-                MeshMaterial3d(material),
-                Transform::from_xyz(x_, y_, z_),
-            ),
-            LAYER_GIZMO, // visible only to axis camera
-        )
-    };
+/// Chunks of the structure still to be built after a rebuild, filled in a few per frame.
+#[derive(Resource, Default)]
+pub(crate) struct AtomChunkQueue {
+    /// Atom indices of every chunk, and how many chunks are built.
+    chunks: Vec<Vec<usize>>,
+    built: usize,
+    /// Chunk entities in chunk order; surplus ones go once every chunk is built.
+    entities: Vec<Entity>,
+}
 
-    let scale = 2.0;
-    commands
-        .spawn((
-            Transform::default(),
-            GlobalTransform::default(),
-            LAYER_GIZMO,
-        ))
-        .with_children(|p| {
-            p.spawn(axis(
-                Srgba::RED.into(),
-                (scale * 1., scale * 0.1, scale * 0.1),
-                (scale * 1. / 2., 0., 0.),
-            )); // +X
-            p.spawn(axis(
-                Srgba::GREEN.into(),
-                (scale * 0.1, scale * 1., scale * 0.1),
-                (0., scale * 1. / 2., 0.),
-            )); // +Y
-            p.spawn(axis(
-                Srgba::BLUE.into(),
-                (scale * 0.1, scale * 0.1, scale * 1.),
-                (0., 0., scale * 1. / 2.),
-            )); // +Z
-        });
+impl AtomChunkQueue {
+    /// Chunks built and chunks in total, while a rebuild is in progress.
+    pub fn progress(&self) -> Option<(usize, usize)> {
+        (self.built < self.chunks.len()).then_some((self.built, self.chunks.len()))
+    }
 }
 
-// System to refresh atoms when Crystal resource changes
+// System to rebuild the atom instances when the Crystal resource changes. A rebuild partitions
+// the atoms at once, then fills the chunks until ATOM_BUILD_BUDGET is spent and carries on in
+// the next frames; chunks stay hidden until they hold the new atoms.
+#[allow(clippy::too_many_arguments)]
 pub fn refresh_atoms_system(
     mut commands: Commands,
     crystal: Res<Crystal>,
-    atom_entities: Query<Entity, With<AtomEntity>>,
+    mut chunks: Query<(&mut AtomInstances, &mut Aabb, &mut Mesh3d, &mut Visibility)>,
+    mut queue: ResMut<AtomChunkQueue>,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut sphere_meshes: ResMut<SphereMeshes>,
+    color_scheme: Res<ColorScheme>,
+    color_by: Res<ColorBy>,
+    coordination: Res<Coordination>,
+    overrides: Res<ElementOverrides>,
+    detail: Res<SphereDetail>,
+    rendering: Res<AtomRendering>,
+    scale: Res<AtomScale>,
+    mut shown_species: Local<Vec<Element>>,
 ) {
-    // Only run when Crystal resource changes
-    if !crystal.is_changed() {
+    let reshaped = detail.is_changed() || rendering.is_changed() || scale.is_changed();
+    let restyled =
+        color_scheme.is_changed() || color_by.is_changed() || overrides.is_changed() || reshaped;
+    // Only run when Crystal resource, the coloring, the element overrides or the sphere style or
+    // size changes, or a rebuild is unfinished
+    if crystal.is_changed() || restyled {
+        // A frame that only moves the same atoms keeps their radii and colors; coordination
+        // colors may change with the positions
+        let moved_only = !restyled
+            && *color_by == ColorBy::Element
+            && shown_species.len() == crystal.atoms.len()
+            && shown_species
+                .iter()
+                .zip(&crystal.atoms)
+                .all(|(element, atom)| *element == atom.element);
+        // the atoms stay in their chunks, whose bounds follow them
+        if moved_only && queue.progress().is_none() && !queue.entities.is_empty() {
+            for (mut instances, mut bounds, ..) in &mut chunks {
+                for instance in instances.iter_mut() {
+                    instance.position = crystal.atoms[instance.index as usize].position();
+                }
+                *bounds = chunk_bounds(&instances);
+            }
+            return;
+        }
+        *shown_species = crystal.atoms.iter().map(|atom| atom.element).collect();
+
+        let positions: Vec<Vec3> = crystal.atoms.iter().map(|atom| atom.position()).collect();
+        queue.chunks = partition_atoms(&positions);
+        queue.built = 0;
+        for (.., mut visibility) in &mut chunks {
+            *visibility = Visibility::Hidden;
+        }
+    } else if queue.progress().is_none() {
         return;
     }
 
-    // Despawn all existing atoms
-    for entity in atom_entities.iter() {
-        commands.entity(entity).despawn();
-    }
+    let mut colors: HashMap<AtomColorKey, [f32; 4]> = HashMap::new();
+    let mut materials: HashMap<Element, [f32; 3]> = HashMap::new();
+    let mut instance = |index: usize| {
+        let atom = &crystal.atoms[index];
+        let key = match *color_by {
+            ColorBy::Element => AtomColorKey::Element(atom.element),
+            ColorBy::Coordination => {
+                AtomColorKey::Coordination(coordination.numbers.get(index).copied().unwrap_or(0))
+            }
+        };
+        let color = *colors.entry(key).or_insert_with_key(|key| {
+            let color = match key {
+                AtomColorKey::Element(element) => overrides.color(*color_scheme, *element),
+                AtomColorKey::Coordination(n) => coordination_color(*n),
+            };
+            color.to_linear().to_f32_array()
+        });
+        let material = *materials
+            .entry(atom.element)
+            .or_insert_with(|| overrides.material(atom.element).to_array());
+        AtomInstance {
+            position: atom.position(),
+            radius: overrides.size(atom.element) * scale.0,
+            color,
+            index: index as u32,
+            material,
+        }
+    };
 
-    // Respawn with new positions
-    let sphere_mesh = meshes.add(Mesh::from(Sphere { radius: 1.0 }));
-    let mut element_materials: HashMap<String, Handle<StandardMaterial>> = HashMap::new();
+    // All chunks share one sphere, or one impostor quad; existing chunk entities are reused
+    let mesh = sphere_meshes.get(&mut meshes, *detail, *rendering);
+    let started = Instant::now();
+    while queue.built < queue.chunks.len() && started.elapsed() < ATOM_BUILD_BUDGET {
+        let chunk = queue.built;
+        // atoms of hidden elements are left out, which also makes them unpickable
+        let instances: Vec<AtomInstance> = queue.chunks[chunk]
+            .iter()
+            .filter(|&&index| !overrides.is_hidden(crystal.atoms[index].element))
+            .map(|&index| instance(index))
+            .collect();
+        let bounds = chunk_bounds(&instances);
+        // a chunk of hidden atoms only has nothing to draw
+        let shown = if instances.is_empty() {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        match queue
+            .entities
+            .get(chunk)
+            .and_then(|&entity| chunks.get_mut(entity).ok())
+        {
+            Some((mut current, mut current_bounds, mut chunk_mesh, mut visibility)) => {
+                current.0 = instances;
+                *current_bounds = bounds;
+                if chunk_mesh.0 != mesh {
+                    chunk_mesh.0 = mesh.clone();
+                }
+                *visibility = shown;
+            }
+            None => {
+                let entity = commands
+                    .spawn((
+                        Mesh3d(mesh.clone()),
+                        AtomInstances(instances),
+                        bounds,
+                        shown,
+                    ))
+                    .id();
+                match queue.entities.get_mut(chunk) {
+                    Some(slot) => *slot = entity,
+                    None => queue.entities.push(entity),
+                }
+            }
+        }
+        queue.built += 1;
+    }
 
-    for atom in &crystal.atoms {
-        // Get or create material for this element
-        let material = element_materials
-            .entry(atom.element.clone())
-            .or_insert_with(|| {
-                materials.add(StandardMaterial {
-                    base_color: get_element_color(&atom.element),
-                    metallic: 0.0,
-                    ..default()
-                })
-            })
-            .clone();
-
-        commands.spawn((
-            Mesh3d(sphere_mesh.clone()),
-            MeshMaterial3d(material),
-            Transform {
-                translation: Vec3::new(atom.x, atom.y, atom.z),
-                scale: Vec3::splat(get_element_size(&atom.element)),
-                ..default()
-            },
-            AtomEntity,
-        ));
+    if queue.progress().is_none() {
+        let built = queue.built;
+        for entity in queue.entities.drain(built..) {
+            commands.entity(entity).despawn();
+        }
     }
 }
 
@@ -418,6 +1176,9 @@ pub(crate) fn camera_controls(
     mut mouse_motion_events: EventReader<MouseMotion>,
     mut mouse_wheel_events: EventReader<MouseWheel>,
     mut camera_rig: ResMut<CameraRig>,
+    sensitivity: Res<MouseSensitivity>,
+    time: Res<Time>,
+    interactions: Query<&Interaction>,
 ) {
     if let Ok(mut transform) = camera_query.single_mut() {
         let mut yaw_delta = 0.0;
@@ -425,26 +1186,25 @@ pub(crate) fn camera_controls(
         let mut zoom_change = 0.0;
         let mut pan_request = Vec2::ZERO;
 
-        const MIN_DISTANCE: f32 = 0.2;
-        const MAX_DISTANCE: f32 = 200.0;
-
         let mut mouse_delta = Vec2::ZERO;
         for motion in mouse_motion_events.read() {
             mouse_delta += motion.delta;
         }
 
-        if mouse_buttons.pressed(MouseButton::Left) {
-            let sensitivity = 0.005;
+        // dragging on a pressed widget, such as the timeline, does not turn the camera
+        let dragging_widget = interactions.iter().any(|i| *i == Interaction::Pressed);
+        if mouse_buttons.pressed(MouseButton::Left) && !dragging_widget {
+            let sensitivity = 0.005 * sensitivity.0;
             yaw_delta -= mouse_delta.x * sensitivity;
             pitch_delta -= mouse_delta.y * sensitivity;
         }
 
         if mouse_buttons.pressed(MouseButton::Right) {
-            pan_request = mouse_delta;
+            pan_request = mouse_delta * sensitivity.0;
         }
 
         for wheel in mouse_wheel_events.read() {
-            zoom_change -= wheel.y * 0.2;
+            zoom_change -= wheel.y * 0.2 * sensitivity.0;
         }
 
         // Keep camera offset updated relative to target.
@@ -454,6 +1214,10 @@ pub(crate) fn camera_controls(
         }
 
         if yaw_delta != 0.0 || pitch_delta != 0.0 {
+            // rotating by hand stops a snap to a view, but not the glide to a new focus
+            if let Some(animation) = camera_rig.animation.as_mut() {
+                animation.turn = None;
+            }
             let rotation = Quat::from_euler(EulerRot::XYZ, pitch_delta, yaw_delta, 0.0);
             offset = rotation * offset;
         }
@@ -471,7 +1235,25 @@ pub(crate) fn camera_controls(
             camera_rig.target += pan_offset;
         }
 
+        // Manual pan/zoom takes over from a running focus animation
+        if zoom_change != 0.0 || pan_request != Vec2::ZERO {
+            camera_rig.animation = None;
+        }
+
         let mut distance = offset.length().max(MIN_DISTANCE);
+        if let Some(animation) = camera_rig.animation.as_mut() {
+            let (target, animated_distance, direction, finished) =
+                animation.step(time.delta_secs());
+            if finished {
+                camera_rig.animation = None;
+            }
+            camera_rig.target = target;
+            distance = animated_distance;
+            if let Some(direction) = direction {
+                offset = direction * distance;
+            }
+        }
+
         if zoom_change != 0.0 {
             let factor = (1.0 + zoom_change).clamp(0.2, 5.0);
             distance = (distance * factor).clamp(MIN_DISTANCE, MAX_DISTANCE);
@@ -491,120 +1273,300 @@ pub(crate) fn camera_controls(
     }
 }
 
-// Handle button interaction: toggle state and update label
+// Handle button interaction: toggle state; the label follows in `handle_toggle_events`
 #[allow(clippy::type_complexity)]
 pub fn toggle_button(
-    mut interactions: Query<
-        (&Interaction, &mut BackgroundColor, &ToggleButton),
-        (Changed<Interaction>, With<Button>),
-    >,
-    mut texts: Query<(&ToggleText, &mut Text)>,
+    interactions: Query<(&Interaction, &ToggleButton), (Changed<Interaction>, With<Button>)>,
     mut toggle_states: ResMut<ToggleStates>,
     mut toggle_events: EventWriter<ToggleEvent>,
 ) {
-    for (interaction, mut background, toggle_button) in &mut interactions {
-        match *interaction {
-            Interaction::Pressed => {
-                let new_state = toggle_states.toggle(toggle_button.id);
-                toggle_events.write(ToggleEvent {
-                    id: toggle_button.id,
-                    state: new_state,
-                });
-
-                for (text_marker, mut text) in &mut texts {
-                    if text_marker.id == toggle_button.id {
-                        text.0 = ToggleId::label(toggle_button.id, new_state).into();
-                    }
-                }
-
-                *background = BackgroundColor(Color::srgb(0.25, 0.25, 0.25));
-            }
-            Interaction::Hovered => {
-                *background = BackgroundColor(Color::srgb(0.2, 0.2, 0.2));
-            }
-            Interaction::None => {
-                *background = BackgroundColor(Color::srgb(0.15, 0.15, 0.15));
-            }
+    for (interaction, toggle_button) in &interactions {
+        if *interaction != Interaction::Pressed {
+            continue;
         }
+        let new_state = toggle_states.toggle(toggle_button.id);
+        toggle_events.write(ToggleEvent {
+            id: toggle_button.id,
+            state: new_state,
+        });
     }
 }
 
+// Glide the camera back to where it was when the structure was loaded
+pub(crate) fn reset_camera(rig: &mut CameraRig, transform: &Transform) {
+    let offset = rig.initial_translation - rig.initial_target;
+    rig.glide_to(
+        transform,
+        rig.initial_target,
+        offset.length().max(0.5),
+        Some(offset),
+    );
+}
+
 // Handle reset button interaction.
 #[allow(clippy::type_complexity)]
 pub fn reset_camera_button_interaction(
-    mut interactions: Query<
-        (&Interaction, &mut BackgroundColor),
+    interactions: Query<
+        &Interaction,
         (Changed<Interaction>, With<Button>, With<ResetCameraButton>),
     >,
     camera_entity: Option<Res<MainCameraEntity>>,
-    mut camera_query: Query<&mut Transform, With<Camera3d>>,
+    camera_query: Query<&Transform, With<Camera3d>>,
     mut camera_rig: Option<ResMut<CameraRig>>,
 ) {
-    for (interaction, mut background) in &mut interactions {
-        match *interaction {
-            Interaction::Pressed => {
-                *background = BackgroundColor(Color::srgb(0.25, 0.25, 0.25));
-
-                if let (Some(camera_entity), Some(rig)) =
-                    (camera_entity.as_deref(), camera_rig.as_deref_mut())
-                {
-                    if let Ok(mut transform) = camera_query.get_mut(camera_entity.0) {
-                        transform.translation = rig.initial_translation;
-                        transform.rotation = rig.initial_rotation;
-                        transform.scale = rig.initial_scale;
-                        rig.target = rig.initial_target;
-                        rig.distance = (rig.initial_translation - rig.initial_target)
-                            .length()
-                            .max(0.5);
-                    }
-                }
-            }
-            Interaction::Hovered => {
-                *background = BackgroundColor(Color::srgb(0.2, 0.2, 0.2));
-            }
-            Interaction::None => {
-                *background = BackgroundColor(Color::srgb(0.15, 0.15, 0.15));
+    for interaction in &interactions {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if let (Some(camera_entity), Some(rig)) =
+            (camera_entity.as_deref(), camera_rig.as_deref_mut())
+        {
+            if let Ok(transform) = camera_query.get(camera_entity.0) {
+                reset_camera(rig, transform);
             }
         }
     }
 }
 
-// Respond to toggle events by applying the desired world changes
+// The structure converted to another cell setting; None, with the reason logged, when it is
+// not periodic or already in that setting
+pub(crate) fn convert_cell(setting: CellSetting, crystal: &Crystal) -> Option<Crystal> {
+    if crystal.lattice.is_none() {
+        warn!("Cell conversion needs a periodic structure");
+        return None;
+    }
+
+    let converted = match setting {
+        CellSetting::Primitive => find_primitive(crystal),
+        CellSetting::Conventional => find_conventional(crystal),
+    };
+    match &converted {
+        Some(converted) => info!(
+            "Converted to {} with {} atoms",
+            setting.label().to_lowercase(),
+            converted.atoms.len()
+        ),
+        None => info!(
+            "Structure is already in the {}",
+            setting.label().to_lowercase()
+        ),
+    }
+    converted
+}
+
+// Convert the periodic structure between primitive and conventional cells
+pub(crate) fn cell_conversion_buttons(
+    interactions: Query<(&Interaction, &CellConversionButton), Changed<Interaction>>,
+    mut crystal: ResMut<Crystal>,
+    mut selection: ResMut<Selection>,
+) {
+    for (interaction, button) in &interactions {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if let Some(converted) = convert_cell(button.0, &crystal) {
+            selection.atoms.clear();
+            *crystal = converted;
+        }
+    }
+}
+
+// Move the structure so its centroid or center of mass sits at the origin. The camera moves
+// along with it, so the view doesn't jump, and then glides to orbit the new center.
+pub(crate) fn center_structure(
+    mode: CenterMode,
+    crystal: &mut Crystal,
+    camera_rig: &mut CameraRig,
+    camera: Option<&mut Transform>,
+) {
+    let center = match mode {
+        CenterMode::Centroid => crystal.centroid(),
+        CenterMode::CenterOfMass => crystal.center_of_mass(),
+    };
+    let Some(center) = center else {
+        return;
+    };
+
+    let shift = -center;
+    crystal.translate(shift);
+    camera_rig.target += shift;
+    camera_rig.initial_target += shift;
+    camera_rig.initial_translation += shift;
+    if let Some(animation) = camera_rig.animation.as_mut() {
+        animation.from_target += shift;
+        animation.to_target += shift;
+    }
+    match camera {
+        Some(transform) => {
+            transform.translation += shift;
+            let distance = camera_rig.distance;
+            camera_rig.glide_to(transform, Vec3::ZERO, distance, None);
+        }
+        None => camera_rig.target = Vec3::ZERO,
+    }
+}
+
+pub(crate) fn center_structure_buttons(
+    interactions: Query<(&Interaction, &CenterButton), Changed<Interaction>>,
+    mut crystal: ResMut<Crystal>,
+    mut camera_query: Query<&mut Transform, With<MainCamera>>,
+    mut camera_rig: ResMut<CameraRig>,
+) {
+    for (interaction, button) in &interactions {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        center_structure(
+            button.0,
+            &mut crystal,
+            &mut camera_rig,
+            camera_query.single_mut().ok().as_deref_mut(),
+        );
+    }
+}
+
+// Cycle the coloring property on click
+#[allow(clippy::type_complexity)]
+pub(crate) fn color_by_button(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<ColorByButton>)>,
+    mut texts: Query<&mut Text, With<ColorByText>>,
+    mut color_by: ResMut<ColorBy>,
+) {
+    for interaction in &interactions {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        *color_by = color_by.next();
+        for mut text in &mut texts {
+            text.0 = format!("Color by: {}", color_by.label());
+        }
+    }
+}
+
+// Open/close the color scheme dropdown and apply the picked scheme
+#[allow(clippy::type_complexity)]
+pub(crate) fn color_scheme_dropdown(
+    headers: Query<
+        &Interaction,
+        (
+            Changed<Interaction>,
+            With<ColorSchemeButton>,
+            Without<ColorSchemeOption>,
+        ),
+    >,
+    options: Query<(&Interaction, &ColorSchemeOption), Changed<Interaction>>,
+    mut lists: Query<&mut Node, With<ColorSchemeList>>,
+    mut texts: Query<&mut Text, With<ColorSchemeText>>,
+    mut color_scheme: ResMut<ColorScheme>,
+) {
+    for interaction in &headers {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        for mut node in &mut lists {
+            node.display = match node.display {
+                Display::None => Display::Flex,
+                _ => Display::None,
+            };
+        }
+    }
+
+    for (interaction, option) in &options {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if *color_scheme != option.0 {
+            *color_scheme = option.0;
+        }
+        for mut text in &mut texts {
+            text.0 = format!("Colors: {}", option.0.label());
+        }
+        for mut node in &mut lists {
+            node.display = Display::None;
+        }
+    }
+}
+
+// Keep the coloring labels in sync when the coloring is changed from elsewhere, e.g. over RPC
+pub(crate) fn refresh_color_labels(
+    color_by: Res<ColorBy>,
+    color_scheme: Res<ColorScheme>,
+    mut color_by_texts: Query<&mut Text, (With<ColorByText>, Without<ColorSchemeText>)>,
+    mut scheme_texts: Query<&mut Text, With<ColorSchemeText>>,
+) {
+    if color_by.is_changed() {
+        for mut text in &mut color_by_texts {
+            text.0 = format!("Color by: {}", color_by.label());
+        }
+    }
+    if color_scheme.is_changed() {
+        for mut text in &mut scheme_texts {
+            text.0 = format!("Colors: {}", color_scheme.label());
+        }
+    }
+}
+
+// Respond to toggle events by applying the desired world changes and relabeling the button
+#[allow(clippy::too_many_arguments)]
 pub fn handle_toggle_events(
     mut toggle_events: EventReader<ToggleEvent>,
     camera_entity: Option<Res<MainCameraEntity>>,
-    light_entity: Option<Res<MainLightEntity>>,
-    global_light_xforms: Query<&GlobalTransform, With<DirectionalLight>>,
+    lighting: Res<LightingRig>,
+    global_transforms: Query<&GlobalTransform>,
+    mut key_lights: Query<&mut DirectionalLight, With<KeyLight>>,
+    mut panels: Query<(&mut Node, &ToggledPanel)>,
+    mut texts: Query<(&ToggleText, &mut Text)>,
     mut commands: Commands,
 ) {
     let Some(camera_entity) = camera_entity else {
         return;
     };
-    let Some(light_entity) = light_entity else {
+    let Some(light_pivot) = lighting.pivot else {
         return;
     };
 
-    // XXX: only single event at the moment
     for event in toggle_events.read() {
+        for (text_marker, mut text) in &mut texts {
+            if text_marker.id == event.id {
+                text.0 = event.id.label(event.state).into();
+            }
+        }
         match event.id {
             ToggleId::LightAttachment => {
                 if event.state {
                     // Re-attach to camera; use default local transform so light follows camera orientation.
                     commands
-                        .entity(light_entity.0)
+                        .entity(light_pivot)
                         .insert(ChildOf(camera_entity.0))
                         .insert(Transform::default());
-                } else if let Ok(global_transform) = global_light_xforms.get(light_entity.0) {
+                } else if let Ok(global_transform) = global_transforms.get(light_pivot) {
                     let (scale, rotation, translation) =
                         global_transform.to_scale_rotation_translation();
-                    commands.entity(light_entity.0).remove::<ChildOf>();
-                    commands.entity(light_entity.0).insert(Transform {
+                    commands.entity(light_pivot).remove::<ChildOf>();
+                    commands.entity(light_pivot).insert(Transform {
                         translation,
                         rotation,
                         scale,
                     });
                 } else {
-                    commands.entity(light_entity.0).remove::<ChildOf>();
+                    commands.entity(light_pivot).remove::<ChildOf>();
+                }
+            }
+            ToggleId::Shadows => {
+                for mut light in &mut key_lights {
+                    light.shadows_enabled = event.state;
+                }
+            }
+            // every other toggle shows or hides its panel
+            id => {
+                for (mut node, panel) in &mut panels {
+                    if panel.0 == id {
+                        node.display = if event.state {
+                            Display::Flex
+                        } else {
+                            Display::None
+                        };
+                    }
                 }
             }
         }
@@ -0,0 +1,65 @@
+// Length units of structure files
+// Positions and cells are kept in Å throughout the viewer. Readers of formats that use another
+// unit, such as nm in GROMACS files or Bohr in cube and Molden files, convert on import with
+// `Crystal::convert_lengths`, which also records the unit of the file in its metadata.
+
+/// Å per Bohr.
+pub(crate) const BOHR: f32 = 0.529_177_2;
+/// Å per nm.
+pub(crate) const NANOMETER: f32 = 10.0;
+
+/// Length unit a structure was read in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LengthUnit {
+    #[default]
+    Angstrom,
+    Bohr,
+    Nanometer,
+}
+
+impl LengthUnit {
+    /// Å per unit.
+    pub fn in_angstrom(self) -> f32 {
+        match self {
+            LengthUnit::Angstrom => 1.0,
+            LengthUnit::Bohr => BOHR,
+            LengthUnit::Nanometer => NANOMETER,
+        }
+    }
+
+    /// Short name for display.
+    pub fn name(self) -> &'static str {
+        match self {
+            LengthUnit::Angstrom => "Å",
+            LengthUnit::Bohr => "Bohr",
+            LengthUnit::Nanometer => "nm",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::{Atom, Crystal};
+    use bevy::math::{Mat3, Vec3};
+
+    #[test]
+    fn converting_scales_positions_and_cell() {
+        let mut crystal = Crystal::periodic(
+            vec![Atom::new("H", Vec3::new(1.0, 0.0, 0.0))],
+            Mat3::from_diagonal(Vec3::splat(2.0)),
+        );
+        crystal.convert_lengths(LengthUnit::Bohr);
+        assert_eq!(crystal.atoms[0].x, BOHR);
+        assert_eq!(crystal.lattice.unwrap().z_axis.z, 2.0 * BOHR);
+        assert_eq!(crystal.metadata.length_unit, Some(LengthUnit::Bohr));
+    }
+
+    #[test]
+    fn angstrom_is_recorded_without_scaling() {
+        let mut crystal = Crystal::molecule(vec![Atom::new("H", Vec3::ONE)]);
+        crystal.convert_lengths(LengthUnit::Angstrom);
+        assert_eq!(crystal.atoms[0].position(), Vec3::ONE);
+        assert_eq!(crystal.metadata.length_unit, Some(LengthUnit::Angstrom));
+    }
+}
@@ -0,0 +1,612 @@
+// Vibrational modes
+// `vizmat --modes water.molden` opens the normal modes of a structure and animates its atoms
+// back and forth along one of them. Modes are read from the [FR-COORD], [FREQ] and
+// [FR-NORM-COORD] sections of Molden files, as written by ORCA, Gaussian (via converters) or
+// Molden itself, or from the first q-point of phonopy's qpoints.yaml, band.yaml or mesh.yaml
+// written with eigenvectors. Phonopy eigenvectors are mass-weighted and are divided by the
+// square root of each atom's mass to give displacements; only their real part is used, which is
+// exact at Γ.
+// Every mode is scaled so that its largest atom displacement is 1, and the amplitude set in the
+// vibrations panel is that displacement in Å. The panel also picks the mode, by index and
+// frequency (imaginary frequencies are listed with an i), pauses the animation and draws the
+// displacement of every atom as an arrow.
+
+use std::f32::consts::TAU;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use bevy::prelude::*;
+
+use crate::cli::Cli;
+use crate::constants::Element;
+use crate::events::StructureLoaded;
+use crate::settings::stepped_index;
+use crate::structure::{Atom, Crystal, Selection, UpdateStructure};
+use crate::structure_list::{tab_name, StructureList};
+use crate::theme::Themed;
+use crate::toast::Toast;
+use crate::trajectory::Trajectory;
+use crate::ui::{FitView, SidePanelColumn};
+use crate::units::LengthUnit;
+use crate::widgets::{on_off, spawn_stepper_row, StepperSettings};
+
+/// cm⁻¹ per THz, phonopy's frequency unit.
+const THZ_TO_WAVENUMBER: f64 = 33.356_41;
+/// Amplitudes of the largest displacement, in Å.
+const AMPLITUDE_STEP: f32 = 0.1;
+const AMPLITUDE_RANGE: (f32, f32) = (0.1, 2.0);
+/// Oscillations per second of the animation, whatever the frequency of the mode.
+const ANIMATION_HZ: f32 = 1.0;
+/// Length of the arrows relative to the amplitude, so they reach out of the atoms.
+const ARROW_SCALE: f32 = 2.0;
+/// Displacements below this fraction of the largest get no arrow.
+const MIN_ARROW: f32 = 0.05;
+const ARROW_COLOR: Color = Color::srgb(1.0, 0.6, 0.1);
+
+/// Request to open the vibrational modes of a structure.
+#[derive(Event, Clone)]
+pub(crate) struct OpenModes(pub PathBuf);
+
+/// A normal mode: its frequency and the displacement of every atom.
+struct Mode {
+    /// Frequency in cm⁻¹, negative for imaginary modes.
+    frequency: f64,
+    /// Displacements, the largest of length 1.
+    displacements: Vec<Vec3>,
+}
+
+/// Modes of the structure on screen and how they are shown, set in the vibrations panel.
+#[derive(Resource)]
+pub(crate) struct Vibrations {
+    /// Structure at rest, which the modes move.
+    equilibrium: Option<Crystal>,
+    modes: Vec<Mode>,
+    /// Index into `modes`.
+    pub mode: usize,
+    /// Largest displacement of an atom, in Å.
+    pub amplitude: f32,
+    pub animate: bool,
+    pub arrows: bool,
+}
+
+impl Default for Vibrations {
+    fn default() -> Self {
+        Self {
+            equilibrium: None,
+            modes: Vec::new(),
+            mode: 0,
+            amplitude: 0.5,
+            animate: true,
+            arrows: false,
+        }
+    }
+}
+
+impl Vibrations {
+    // Structure at rest and the chosen mode, while that structure is the one on screen
+    fn shown(&self, crystal: &Crystal) -> Option<(&Crystal, &Mode)> {
+        let equilibrium = self.equilibrium.as_ref()?;
+        equilibrium
+            .same_elements(crystal)
+            .then_some((equilibrium, self.modes.get(self.mode)?))
+    }
+}
+
+impl StepperSettings for Vibrations {
+    fn step(&mut self, field: usize, direction: i32) {
+        match field {
+            0 if !self.modes.is_empty() => {
+                self.mode = stepped_index(self.mode, direction, self.modes.len())
+            }
+            0 => {}
+            1 => {
+                let (min, max) = AMPLITUDE_RANGE;
+                let amplitude = self.amplitude + direction as f32 * AMPLITUDE_STEP;
+                self.amplitude =
+                    ((amplitude / AMPLITUDE_STEP).round() * AMPLITUDE_STEP).clamp(min, max);
+            }
+            2 => self.animate = direction > 0,
+            _ => self.arrows = direction > 0,
+        }
+    }
+
+    fn value_text(&self, field: usize) -> String {
+        match field {
+            0 => match self.modes.get(self.mode) {
+                Some(mode) if mode.frequency < 0.0 => {
+                    format!("{}: {:.1}i cm⁻¹", self.mode + 1, -mode.frequency)
+                }
+                Some(mode) => format!("{}: {:.1} cm⁻¹", self.mode + 1, mode.frequency),
+                None => "none".to_string(),
+            },
+            1 => format!("{:.1} Å", self.amplitude),
+            2 => on_off(self.animate),
+            _ => on_off(self.arrows),
+        }
+    }
+}
+
+/// Root node of the vibrations panel, shown while modes of the structure on screen are loaded.
+#[derive(Component)]
+pub(crate) struct VibrationPanel;
+
+// Numbers of a `[ x, y, ... ]` list
+fn bracketed(text: &str) -> Result<Vec<f64>> {
+    let inner = text
+        .trim()
+        .strip_prefix('[')
+        .and_then(|rest| rest.split(']').next())
+        .with_context(|| format!("Expected a [ ... ] list: {text}"))?;
+    inner
+        .split(',')
+        .map(|value| value.trim().parse::<f64>())
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("Failed to parse {text}"))
+}
+
+// Scale the displacements of every mode so that the largest is 1
+fn normalized(frequency: f64, displacements: Vec<Vec3>) -> Mode {
+    let largest = displacements.iter().map(|d| d.length()).fold(0.0, f32::max);
+    let scale = if largest > 0.0 { 1.0 / largest } else { 0.0 };
+    Mode {
+        frequency,
+        displacements: displacements.into_iter().map(|d| d * scale).collect(),
+    }
+}
+
+// Structure and modes of a Molden file; coordinates are in Bohr
+fn parse_molden(contents: &str) -> Result<(Crystal, Vec<Mode>)> {
+    let mut section = String::new();
+    let mut frequencies: Vec<f64> = Vec::new();
+    let mut atoms = Vec::new();
+    let mut vectors: Vec<Vec<Vec3>> = Vec::new();
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[') {
+            section = name.split(']').next().unwrap_or("").to_ascii_uppercase();
+            continue;
+        }
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let numbers = |values: &[&str]| {
+            values
+                .iter()
+                .map(|value| value.parse::<f32>())
+                .collect::<Result<Vec<f32>, _>>()
+                .with_context(|| format!("Failed to parse {line}"))
+        };
+        match section.as_str() {
+            "FREQ" => frequencies.push(
+                line.parse()
+                    .with_context(|| format!("Failed to parse frequency {line}"))?,
+            ),
+            "FR-COORD" => {
+                ensure!(
+                    parts.len() >= 4,
+                    "Expected an atom and its coordinates: {line}"
+                );
+                let position = Vec3::from_slice(&numbers(&parts[1..4])?);
+                atoms.push(Atom::new(Element::parse(parts[0]), position));
+            }
+            "FR-NORM-COORD" if parts[0].eq_ignore_ascii_case("vibration") => {
+                vectors.push(Vec::new())
+            }
+            "FR-NORM-COORD" => {
+                ensure!(parts.len() >= 3, "Expected a displacement: {line}");
+                vectors
+                    .last_mut()
+                    .context("Displacements before the first `vibration` line")?
+                    .push(Vec3::from_slice(&numbers(&parts[..3])?));
+            }
+            _ => {}
+        }
+    }
+    ensure!(!atoms.is_empty(), "The file has no [FR-COORD] section");
+    ensure!(
+        !vectors.is_empty(),
+        "The file has no [FR-NORM-COORD] section"
+    );
+    let mut modes = Vec::with_capacity(vectors.len());
+    for (index, displacements) in vectors.into_iter().enumerate() {
+        ensure!(
+            displacements.len() == atoms.len(),
+            "Vibration {} moves {} atoms, the structure has {}",
+            index + 1,
+            displacements.len(),
+            atoms.len()
+        );
+        let frequency = frequencies.get(index).copied().unwrap_or(0.0);
+        modes.push(normalized(frequency, displacements));
+    }
+    let mut crystal = Crystal::molecule(atoms);
+    crystal.convert_lengths(LengthUnit::Bohr);
+    Ok((crystal, modes))
+}
+
+/// Part of a phonopy YAML file being read.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum YamlSection {
+    Lattice,
+    Points,
+    Phonon,
+    Other,
+}
+
+// Structure and modes at the first q-point of a phonopy YAML file written with eigenvectors
+fn parse_phonopy(contents: &str) -> Result<(Crystal, Vec<Mode>)> {
+    let mut section = YamlSection::Other;
+    let mut lattice: Vec<Vec3> = Vec::new();
+    // element, fractional coordinates and mass of every atom
+    let mut sites: Vec<(Element, Vec3, Option<f64>)> = Vec::new();
+    // frequency in THz and the real parts of the eigenvector components
+    let mut bands: Vec<(f64, Vec<f64>)> = Vec::new();
+    let mut q_points = 0;
+    for line in contents.lines() {
+        if line.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            section = match line.split(':').next().unwrap_or("") {
+                "lattice" => YamlSection::Lattice,
+                "points" => YamlSection::Points,
+                "phonon" => YamlSection::Phonon,
+                _ => YamlSection::Other,
+            };
+            continue;
+        }
+        let item = line.trim_start();
+        let item = item.strip_prefix("- ").unwrap_or(item).trim_start();
+        let (key, value) = item.split_once(':').unwrap_or(("", item));
+        match (section, key) {
+            (YamlSection::Lattice, "") if value.starts_with('[') => {
+                let vector = bracketed(value)?;
+                ensure!(vector.len() == 3, "Expected a lattice vector: {line}");
+                lattice.push(Vec3::new(
+                    vector[0] as f32,
+                    vector[1] as f32,
+                    vector[2] as f32,
+                ));
+            }
+            (YamlSection::Points, "symbol") => {
+                let symbol = value.split('#').next().unwrap_or("").trim();
+                sites.push((Element::parse(symbol), Vec3::ZERO, None));
+            }
+            (YamlSection::Points, "coordinates") => {
+                let fractional = bracketed(value)?;
+                ensure!(fractional.len() == 3, "Expected coordinates: {line}");
+                if let Some(site) = sites.last_mut() {
+                    site.1 = Vec3::new(
+                        fractional[0] as f32,
+                        fractional[1] as f32,
+                        fractional[2] as f32,
+                    );
+                }
+            }
+            (YamlSection::Points, "mass") => {
+                if let Some(site) = sites.last_mut() {
+                    site.2 = value.trim().parse().ok();
+                }
+            }
+            (YamlSection::Phonon, "q-position") => {
+                q_points += 1;
+                if q_points > 1 {
+                    break;
+                }
+            }
+            (YamlSection::Phonon, "frequency") => {
+                let frequency = value
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("Failed to parse frequency: {line}"))?;
+                bands.push((frequency, Vec::new()));
+            }
+            (YamlSection::Phonon, "") if value.starts_with('[') => {
+                let component = bracketed(value)?;
+                if let Some((_, components)) = bands.last_mut() {
+                    components.push(component[0]);
+                }
+            }
+            _ => {}
+        }
+    }
+    ensure!(
+        lattice.len() == 3 && !sites.is_empty(),
+        "The file has no `lattice` and `points`; phonopy writes them from version 1.12"
+    );
+    if bands.iter().all(|(_, components)| components.is_empty()) {
+        bail!("The file has no eigenvectors; write it with phonopy's EIGENVECTORS = .TRUE.");
+    }
+
+    let mut builder = Crystal::builder().lattice(lattice[0], lattice[1], lattice[2]);
+    for &(element, fractional, _) in &sites {
+        builder = builder.fractional_atom(element, fractional);
+    }
+    let mut crystal = builder.build()?;
+    let masses: Vec<f64> = sites
+        .iter()
+        .map(|(element, _, mass)| mass.unwrap_or(element.data().mass as f64))
+        .collect();
+    let mut modes = Vec::with_capacity(bands.len());
+    for (index, (frequency, components)) in bands.into_iter().enumerate() {
+        ensure!(
+            components.len() == 3 * sites.len(),
+            "Band {} has {} eigenvector components, expected {}",
+            index + 1,
+            components.len(),
+            3 * sites.len()
+        );
+        let displacements = components
+            .chunks(3)
+            .zip(&masses)
+            .map(|(e, mass)| {
+                let d = [e[0], e[1], e[2]].map(|x| (x / mass.sqrt()) as f32);
+                Vec3::from_array(d)
+            })
+            .collect();
+        modes.push(normalized(frequency * THZ_TO_WAVENUMBER, displacements));
+    }
+    crystal.convert_lengths(LengthUnit::Angstrom);
+    Ok((crystal, modes))
+}
+
+// Structure and modes of a Molden or phonopy YAML file, told apart by the extension
+fn read_modes(path: &Path) -> Result<(Crystal, Vec<Mode>)> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let yaml = path
+        .extension()
+        .is_some_and(|extension| extension == "yaml" || extension == "yml");
+    let parsed = if yaml {
+        parse_phonopy(&contents)
+    } else {
+        parse_molden(&contents)
+    };
+    parsed.with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+// Open the modes named on the command line
+pub(crate) fn open_modes_argument(cli: Res<Cli>, mut requests: EventWriter<OpenModes>) {
+    if let Some(path) = &cli.modes {
+        requests.write(OpenModes(path.clone()));
+    }
+}
+
+// Show the structure of requested mode files, in a tab of their own, ready to vibrate
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn open_mode_files(
+    mut requests: EventReader<OpenModes>,
+    mut crystal: ResMut<Crystal>,
+    mut selection: ResMut<Selection>,
+    mut trajectory: ResMut<Trajectory>,
+    mut list: ResMut<StructureList>,
+    mut vibrations: ResMut<Vibrations>,
+    mut fit: EventWriter<FitView>,
+    mut loaded: EventWriter<StructureLoaded>,
+    mut toasts: EventWriter<Toast>,
+) {
+    for OpenModes(path) in requests.read() {
+        let (structure, modes) = match read_modes(path) {
+            Ok(read) => read,
+            Err(e) => {
+                error!("{e:#}");
+                toasts.write(Toast::error(format!("{e:#}")));
+                continue;
+            }
+        };
+        let source = path.display().to_string();
+        info!("Loaded {} vibrational modes from {source}", modes.len());
+        loaded.write(StructureLoaded {
+            source: source.clone(),
+            atom_count: structure.atoms.len(),
+            frame_count: 1,
+        });
+        selection.atoms.clear();
+        *crystal = structure.clone();
+        trajectory.clear();
+        list.open(
+            source.clone(),
+            vec![(tab_name(&source), vec![structure.clone()])],
+            0,
+        );
+        *vibrations = Vibrations {
+            equilibrium: Some(structure),
+            modes,
+            ..default()
+        };
+        fit.write(FitView);
+    }
+}
+
+// Spawn the (hidden) vibrations panel in the side column
+pub(crate) fn setup_vibration_panel(
+    mut commands: Commands,
+    column: Single<Entity, With<SidePanelColumn>>,
+    vibrations: Res<Vibrations>,
+) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            VibrationPanel,
+            ChildOf(*column),
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                Text::new("Vibrations"),
+                TextFont {
+                    font: default(),
+                    font_size: 14.0,
+                    ..default()
+                },
+                Themed::Text,
+            ));
+            for (field, label) in ["Mode", "Amplitude", "Animate", "Arrows"]
+                .into_iter()
+                .enumerate()
+            {
+                spawn_stepper_row(panel, label, field, &*vibrations);
+            }
+        });
+}
+
+// Show the vibrations panel while the structure on screen has modes
+pub(crate) fn refresh_vibration_panel(
+    vibrations: Res<Vibrations>,
+    crystal: Res<Crystal>,
+    mut panels: Query<&mut Node, With<VibrationPanel>>,
+) {
+    if !vibrations.is_changed() && !crystal.is_changed() {
+        return;
+    }
+    let display = if vibrations.shown(&crystal).is_some() {
+        Display::Flex
+    } else {
+        Display::None
+    };
+    for mut node in &mut panels {
+        node.display = display;
+    }
+}
+
+// Move the atoms along the chosen mode while the animation is on; the structure goes back to
+// rest when it is paused
+pub(crate) fn animate_vibrations(
+    time: Res<Time>,
+    vibrations: Res<Vibrations>,
+    crystal: Res<Crystal>,
+    mut updates: EventWriter<UpdateStructure>,
+    // phase of the oscillation, in radians
+    mut phase: Local<f32>,
+) {
+    let Some((equilibrium, mode)) = vibrations.shown(&crystal) else {
+        *phase = 0.0;
+        return;
+    };
+    if !vibrations.animate {
+        if vibrations.is_changed() {
+            updates.write(equilibrium.clone().into());
+        }
+        *phase = 0.0;
+        return;
+    }
+    *phase = (*phase + time.delta_secs() * TAU * ANIMATION_HZ) % TAU;
+    let offset = vibrations.amplitude * phase.sin();
+    let mut moved = equilibrium.clone();
+    for (atom, displacement) in moved.atoms.iter_mut().zip(&mode.displacements) {
+        let step = *displacement * offset;
+        atom.x += step.x;
+        atom.y += step.y;
+        atom.z += step.z;
+    }
+    updates.write(moved.into());
+}
+
+// Draw the displacement of every atom in the chosen mode as an arrow from its rest position
+pub(crate) fn draw_vibration_arrows(
+    mut gizmos: Gizmos,
+    vibrations: Res<Vibrations>,
+    crystal: Res<Crystal>,
+) {
+    if !vibrations.arrows {
+        return;
+    }
+    let Some((equilibrium, mode)) = vibrations.shown(&crystal) else {
+        return;
+    };
+    let length = vibrations.amplitude * ARROW_SCALE;
+    for (atom, displacement) in equilibrium.atoms.iter().zip(&mode.displacements) {
+        if displacement.length() < MIN_ARROW {
+            continue;
+        }
+        let start = atom.position();
+        gizmos.arrow(start, start + *displacement * length, ARROW_COLOR);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::BOHR;
+
+    const MOLDEN: &str = "[Molden Format]
+[FREQ]
+4401.2
+[FR-COORD]
+h 0.0 0.0 0.0
+H 0.0 0.0 1.4
+[FR-NORM-COORD]
+vibration 1
+0.0 0.0 -0.5
+0.0 0.0 0.25
+";
+
+    const PHONOPY: &str = "lattice:
+- [     4.000000000000000,     0.000000000000000,     0.000000000000000 ] # a
+- [     0.000000000000000,     4.000000000000000,     0.000000000000000 ] # b
+- [     0.000000000000000,     0.000000000000000,     4.000000000000000 ] # c
+points:
+- symbol: Na # 1
+  coordinates: [  0.000000000000000,  0.000000000000000,  0.000000000000000 ]
+  mass: 4.000000
+- symbol: Cl # 2
+  coordinates: [  0.500000000000000,  0.500000000000000,  0.500000000000000 ]
+  mass: 16.000000
+phonon:
+- q-position: [    0.0000000,    0.0000000,    0.0000000 ]
+  band:
+  - # 1
+    frequency:    3.0000000
+    eigenvector:
+    - # atom 1
+      - [  0.80000000,  0.10000000 ]
+      - [  0.00000000,  0.00000000 ]
+      - [  0.00000000,  0.00000000 ]
+    - # atom 2
+      - [ -0.80000000,  0.00000000 ]
+      - [  0.00000000,  0.00000000 ]
+      - [  0.00000000,  0.00000000 ]
+- q-position: [    0.5000000,    0.0000000,    0.0000000 ]
+  band:
+  - # 1
+    frequency:    9.0000000
+";
+
+    #[test]
+    fn molden_modes_are_read_in_bohr_and_normalized() {
+        let (crystal, modes) = parse_molden(MOLDEN).unwrap();
+        assert_eq!(crystal.atoms[0].element, Element::H);
+        assert_eq!(crystal.atoms[1].z, 1.4 * BOHR);
+        assert_eq!(crystal.metadata.length_unit, Some(LengthUnit::Bohr));
+        assert_eq!(modes.len(), 1);
+        assert_eq!(modes[0].frequency, 4401.2);
+        assert_eq!(modes[0].displacements, [Vec3::NEG_Z, Vec3::Z * 0.5]);
+    }
+
+    #[test]
+    fn molden_modes_must_move_every_atom() {
+        let truncated = MOLDEN.trim_end().rsplit_once('\n').unwrap().0;
+        assert!(parse_molden(truncated).is_err());
+    }
+
+    #[test]
+    fn phonopy_modes_come_from_the_first_q_point() {
+        let (crystal, modes) = parse_phonopy(PHONOPY).unwrap();
+        assert_eq!(crystal.atoms[1].element, Element::Cl);
+        assert_eq!(crystal.atoms[1].position(), Vec3::splat(2.0));
+        assert_eq!(modes.len(), 1);
+        assert!((modes[0].frequency - 3.0 * THZ_TO_WAVENUMBER).abs() < 1e-9);
+        // the real parts divided by the square roots of the masses, 2 and 4
+        assert_eq!(modes[0].displacements, [Vec3::X, Vec3::NEG_X * 0.5]);
+    }
+
+    #[test]
+    fn phonopy_files_need_eigenvectors() {
+        let without = PHONOPY.replace("      - [", "      # [");
+        assert!(parse_phonopy(&without).is_err());
+    }
+}
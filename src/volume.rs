@@ -0,0 +1,573 @@
+// Volumetric data
+// `vizmat --volume density.cube` opens a grid of values over space, such as a charge density,
+// a spin density or an orbital, together with the atoms of its file. Gaussian cube files and
+// XSF files (as written by Quantum ESPRESSO, VASP converters or XCrySDen) are read; an XSF file
+// can hold several grids, all of which are kept. Repeating `--volume` adds the grids of further
+// files, e.g. a potential to go with a density; the atoms are those of the first file.
+// Cube coordinates are in Bohr unless the grid counts are negative, XSF ones in Å.
+// Grids are stored with their first axis varying fastest, sampled with trilinear interpolation,
+// and shown by the tools of the volume panel (see slice.rs, isosurface.rs and raymarch.rs),
+// while the structure on screen holds the atoms of the volume files.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use bevy::prelude::*;
+
+use crate::cli::Cli;
+use crate::constants::Element;
+use crate::events::StructureLoaded;
+use crate::structure::{Atom, Crystal, Selection};
+use crate::structure_list::{tab_name, StructureList};
+use crate::theme::Themed;
+use crate::toast::Toast;
+use crate::trajectory::Trajectory;
+use crate::ui::{FitView, SidePanelColumn};
+use crate::units::LengthUnit;
+
+/// Request to open volumetric data files; the atoms are read from the first.
+#[derive(Event, Clone)]
+pub(crate) struct OpenVolumes(pub Vec<PathBuf>);
+
+/// Values on a regular grid over space.
+pub(crate) struct VolumeGrid {
+    pub name: String,
+    /// Position of the first grid point.
+    pub origin: Vec3,
+    /// Vectors between neighboring grid points along each axis, as columns.
+    pub steps: Mat3,
+    /// Grid points along each axis.
+    pub dims: [usize; 3],
+    /// Values with the first axis varying fastest.
+    pub values: Vec<f32>,
+}
+
+impl VolumeGrid {
+    fn new(
+        name: String,
+        origin: Vec3,
+        steps: Mat3,
+        dims: [usize; 3],
+        values: Vec<f32>,
+    ) -> Result<Self> {
+        ensure!(
+            dims.iter().all(|&n| n >= 2),
+            "Grid {name} has fewer than 2 points along an axis: {dims:?}"
+        );
+        ensure!(
+            values.len() >= dims.iter().product(),
+            "Grid {name} has {} values for {}×{}×{} points",
+            values.len(),
+            dims[0],
+            dims[1],
+            dims[2]
+        );
+        ensure!(
+            steps.determinant().abs() > f32::EPSILON,
+            "Grid {name} has degenerate axes"
+        );
+        let mut values = values;
+        values.truncate(dims.iter().product());
+        Ok(Self {
+            name,
+            origin,
+            steps,
+            dims,
+            values,
+        })
+    }
+
+    pub fn value(&self, [i, j, k]: [usize; 3]) -> f32 {
+        self.values[i + self.dims[0] * (j + self.dims[1] * k)]
+    }
+
+    /// Smallest and largest value.
+    pub fn range(&self) -> (f32, f32) {
+        self.values
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &value| {
+                (min.min(value), max.max(value))
+            })
+    }
+
+    /// Position of a point given in grid coordinates, where grid point (i, j, k) is at
+    /// (i, j, k).
+    pub fn position(&self, point: Vec3) -> Vec3 {
+        self.origin + self.steps * point
+    }
+
+    /// Grid coordinates of a position, the inverse of `position`.
+    pub fn grid_point(&self, position: Vec3) -> Vec3 {
+        self.steps.inverse() * (position - self.origin)
+    }
+
+    /// Value at a point given in grid coordinates, interpolated between the eight grid points
+    /// around it; points outside the grid take the value at its edge.
+    pub fn sample(&self, point: Vec3) -> f32 {
+        let mut low = [0; 3];
+        let mut t = [0.0; 3];
+        for axis in 0..3 {
+            let last = (self.dims[axis] - 1) as f32;
+            let x = point[axis].clamp(0.0, last);
+            low[axis] = (x.floor() as usize).min(self.dims[axis] - 2);
+            t[axis] = x - low[axis] as f32;
+        }
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut index = low;
+            for axis in 0..3 {
+                if corner >> axis & 1 == 1 {
+                    index[axis] += 1;
+                    weight *= t[axis];
+                } else {
+                    weight *= 1.0 - t[axis];
+                }
+            }
+            if weight > 0.0 {
+                value += weight * self.value(index);
+            }
+        }
+        value
+    }
+}
+
+/// Grids of the opened volume files and the structure they go with.
+#[derive(Resource, Default)]
+pub(crate) struct Volumes {
+    structure: Option<Crystal>,
+    pub grids: Vec<VolumeGrid>,
+}
+
+impl Volumes {
+    /// Whether the structure on screen is the one of the volume files.
+    pub fn shown(&self, crystal: &Crystal) -> bool {
+        self.structure
+            .as_ref()
+            .is_some_and(|structure| structure.same_elements(crystal))
+    }
+}
+
+/// Root node of the volume panel, shown while the grids' structure is on screen; the tools
+/// showing the grids add their rows to it.
+#[derive(Component)]
+pub(crate) struct VolumePanel;
+
+/// Text listing the grids.
+#[derive(Component)]
+pub(crate) struct VolumeText;
+
+// Element of an atomic number, Unknown for none
+fn element_of(number: f32) -> Element {
+    Element::from_atomic_number(number.round() as u8).unwrap_or_default()
+}
+
+// Atoms and grid of a Gaussian cube file
+fn parse_cube(name: &str, contents: &str) -> Result<(Crystal, Vec<VolumeGrid>)> {
+    let mut lines = contents.lines().skip(2);
+    let mut numbers = |what: &str| -> Result<Vec<f32>> {
+        let line = lines
+            .next()
+            .with_context(|| format!("Cube file ends before the {what}"))?;
+        line.split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<f32>, _>>()
+            .with_context(|| format!("Failed to parse the {what}: {line}"))
+    };
+    let header = numbers("atom count and origin")?;
+    ensure!(header.len() >= 4, "Expected the atom count and origin");
+    let atom_count = header[0] as i64;
+    let mut dims = [0; 3];
+    let mut steps = [Vec3::ZERO; 3];
+    let mut unit = LengthUnit::Bohr;
+    for axis in 0..3 {
+        let line = numbers("grid axes")?;
+        ensure!(line.len() >= 4, "Expected a grid count and step vector");
+        // negative counts mean Å
+        if line[0] < 0.0 {
+            unit = LengthUnit::Angstrom;
+        }
+        dims[axis] = line[0].abs() as usize;
+        steps[axis] = Vec3::new(line[1], line[2], line[3]);
+    }
+    let scale = unit.in_angstrom();
+    let origin = Vec3::new(header[1], header[2], header[3]) * scale;
+    let steps = Mat3::from_cols(steps[0], steps[1], steps[2]) * scale;
+
+    let mut atoms = Vec::new();
+    for _ in 0..atom_count.unsigned_abs() {
+        let line = numbers("atoms")?;
+        ensure!(line.len() >= 5, "Expected an atom line");
+        atoms.push(Atom::new(
+            element_of(line[0]),
+            Vec3::new(line[2], line[3], line[4]),
+        ));
+    }
+
+    let mut tokens = lines.flat_map(str::split_whitespace);
+    // orbital files list their orbitals before the values, each point holding one per orbital
+    let mut per_point = 1;
+    if atom_count < 0 {
+        let count: usize = tokens
+            .next()
+            .context("Cube file ends before its orbital list")?
+            .parse()
+            .context("Failed to parse the orbital count")?;
+        tokens.by_ref().take(count).for_each(drop);
+        per_point = count.max(1);
+    }
+    let values = tokens
+        .map(str::parse::<f32>)
+        .collect::<Result<Vec<f32>, _>>()
+        .context("Failed to parse the grid values")?;
+    // cube files list the last axis fastest; keep the first orbital of each point
+    let mut reordered = vec![0.0; dims.iter().product()];
+    ensure!(
+        values.len() >= reordered.len() * per_point,
+        "Cube file has {} values for {}×{}×{} points",
+        values.len(),
+        dims[0],
+        dims[1],
+        dims[2]
+    );
+    for i in 0..dims[0] {
+        for j in 0..dims[1] {
+            for k in 0..dims[2] {
+                let read = ((i * dims[1] + j) * dims[2] + k) * per_point;
+                reordered[i + dims[0] * (j + dims[1] * k)] = values[read];
+            }
+        }
+    }
+    let grid = VolumeGrid::new(tab_name(name), origin, steps, dims, reordered)?;
+    let mut crystal = Crystal::molecule(atoms);
+    crystal.convert_lengths(unit);
+    Ok((crystal, vec![grid]))
+}
+
+// Atoms and every 3D grid of an XSF file
+fn parse_xsf(name: &str, contents: &str) -> Result<(Crystal, Vec<VolumeGrid>)> {
+    let lines: Vec<&str> = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    let vector = |line: &str| -> Result<Vec3> {
+        let values = line
+            .split_whitespace()
+            .take(3)
+            .map(str::parse)
+            .collect::<Result<Vec<f32>, _>>()
+            .with_context(|| format!("Failed to parse {line}"))?;
+        ensure!(values.len() == 3, "Expected three numbers: {line}");
+        Ok(Vec3::from_slice(&values))
+    };
+    let atom = |line: &str| -> Result<Atom> {
+        let (element, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let element = match element.parse::<f32>() {
+            Ok(number) => element_of(number),
+            Err(_) => Element::parse(element),
+        };
+        Ok(Atom::new(element, vector(rest)?))
+    };
+
+    let mut lattice = None;
+    let mut atoms = Vec::new();
+    let mut grids = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index];
+        index += 1;
+        let keyword = line
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .to_ascii_uppercase();
+        if keyword == "PRIMVEC" {
+            ensure!(index + 3 <= lines.len(), "XSF file ends in PRIMVEC");
+            let [a, b, c] = [0, 1, 2].map(|row| vector(lines[index + row]));
+            lattice = Some(Mat3::from_cols(a?, b?, c?));
+            index += 3;
+        } else if keyword == "PRIMCOORD" {
+            let count: usize = lines
+                .get(index)
+                .and_then(|line| line.split_whitespace().next())
+                .and_then(|count| count.parse().ok())
+                .context("Failed to parse the PRIMCOORD atom count")?;
+            ensure!(
+                index + 1 + count <= lines.len(),
+                "XSF file ends in PRIMCOORD"
+            );
+            atoms = lines[index + 1..index + 1 + count]
+                .iter()
+                .map(|line| atom(line))
+                .collect::<Result<_>>()?;
+            index += 1 + count;
+        } else if keyword == "ATOMS" {
+            while index < lines.len() && lines[index].split_whitespace().count() >= 4 {
+                atoms.push(atom(lines[index])?);
+                index += 1;
+            }
+        } else if keyword.starts_with("BEGIN_DATAGRID_3D") || keyword.starts_with("DATAGRID_3D_") {
+            ensure!(index + 5 <= lines.len(), "XSF file ends in {line}");
+            let dims: Vec<usize> = lines[index]
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .with_context(|| format!("Failed to parse the grid size of {line}"))?;
+            ensure!(
+                dims.len() == 3,
+                "Expected three grid counts: {}",
+                lines[index]
+            );
+            let origin = vector(lines[index + 1])?;
+            let [a, b, c] = [2, 3, 4].map(|row| vector(lines[index + row]));
+            index += 5;
+            let mut values = Vec::new();
+            while index < lines.len()
+                && !lines[index]
+                    .to_ascii_uppercase()
+                    .starts_with("END_DATAGRID")
+            {
+                for value in lines[index].split_whitespace() {
+                    values.push(
+                        value
+                            .parse::<f32>()
+                            .with_context(|| format!("Failed to parse grid value {value}"))?,
+                    );
+                }
+                index += 1;
+            }
+            // general grids include the points on both faces, so n points span n - 1 steps
+            let spans = [a?, b?, c?];
+            let steps = Mat3::from_cols(
+                spans[0] / (dims[0].max(2) - 1) as f32,
+                spans[1] / (dims[1].max(2) - 1) as f32,
+                spans[2] / (dims[2].max(2) - 1) as f32,
+            );
+            let label = line.split_once("3D_").map_or_else(
+                || tab_name(name),
+                |(_, label)| format!("{} {label}", tab_name(name)),
+            );
+            grids.push(VolumeGrid::new(
+                label,
+                origin,
+                steps,
+                [dims[0], dims[1], dims[2]],
+                values,
+            )?);
+        }
+    }
+    if grids.is_empty() {
+        bail!("XSF file has no 3D data grid");
+    }
+    let mut crystal = match lattice {
+        Some(lattice) => Crystal::periodic(atoms, lattice),
+        None => Crystal::molecule(atoms),
+    };
+    crystal.convert_lengths(LengthUnit::Angstrom);
+    Ok((crystal, grids))
+}
+
+// Atoms and grids of a cube or XSF file, told apart by the extension
+fn read_volume(path: &Path) -> Result<(Crystal, Vec<VolumeGrid>)> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let name = path.to_string_lossy();
+    let xsf = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("xsf"));
+    let parsed = if xsf {
+        parse_xsf(&name, &contents)
+    } else {
+        parse_cube(&name, &contents)
+    };
+    parsed.with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+// Open the volume files named on the command line
+pub(crate) fn open_volume_argument(cli: Res<Cli>, mut requests: EventWriter<OpenVolumes>) {
+    if !cli.volumes.is_empty() {
+        requests.write(OpenVolumes(cli.volumes.clone()));
+    }
+}
+
+// Show the atoms of requested volume files, in a tab of their own, and keep their grids
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn open_volume_files(
+    mut requests: EventReader<OpenVolumes>,
+    mut crystal: ResMut<Crystal>,
+    mut selection: ResMut<Selection>,
+    mut trajectory: ResMut<Trajectory>,
+    mut list: ResMut<StructureList>,
+    mut volumes: ResMut<Volumes>,
+    mut fit: EventWriter<FitView>,
+    mut loaded: EventWriter<StructureLoaded>,
+    mut toasts: EventWriter<Toast>,
+) {
+    for OpenVolumes(paths) in requests.read() {
+        let read: Result<Vec<_>> = paths.iter().map(|path| read_volume(path)).collect();
+        let mut files = match read {
+            Ok(files) => files.into_iter(),
+            Err(e) => {
+                error!("{e:#}");
+                toasts.write(Toast::error(format!("{e:#}")));
+                continue;
+            }
+        };
+        let Some((structure, mut grids)) = files.next() else {
+            continue;
+        };
+        grids.extend(files.flat_map(|(_, grids)| grids));
+        let source = paths[0].display().to_string();
+        info!("Loaded {} volumetric grid(s) from {source}", grids.len());
+        loaded.write(StructureLoaded {
+            source: source.clone(),
+            atom_count: structure.atoms.len(),
+            frame_count: 1,
+        });
+        selection.atoms.clear();
+        *crystal = structure.clone();
+        trajectory.clear();
+        list.open(
+            source.clone(),
+            vec![(tab_name(&source), vec![structure.clone()])],
+            0,
+        );
+        *volumes = Volumes {
+            structure: Some(structure),
+            grids,
+        };
+        fit.write(FitView);
+    }
+}
+
+// Spawn the (hidden) volume panel in the side column
+pub(crate) fn setup_volume_panel(
+    mut commands: Commands,
+    column: Single<Entity, With<SidePanelColumn>>,
+) {
+    commands
+        .spawn((
+            Node {
+                display: Display::None,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            Themed::Panel,
+            VolumePanel,
+            ChildOf(*column),
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                Text::new("Volume"),
+                TextFont {
+                    font: default(),
+                    font_size: 14.0,
+                    ..default()
+                },
+                Themed::Text,
+            ));
+            panel.spawn((
+                Text::new(""),
+                TextFont {
+                    font: default(),
+                    font_size: 12.0,
+                    ..default()
+                },
+                Themed::Text,
+                VolumeText,
+            ));
+        });
+}
+
+// Show the volume panel while the grids' structure is on screen, listing the grids by number
+pub(crate) fn refresh_volume_panel(
+    volumes: Res<Volumes>,
+    crystal: Res<Crystal>,
+    mut panels: Query<&mut Node, With<VolumePanel>>,
+    mut texts: Query<&mut Text, With<VolumeText>>,
+) {
+    if !volumes.is_changed() && !crystal.is_changed() {
+        return;
+    }
+    let display = if volumes.shown(&crystal) {
+        Display::Flex
+    } else {
+        Display::None
+    };
+    for mut node in &mut panels {
+        node.display = display;
+    }
+    if !volumes.is_changed() {
+        return;
+    }
+    let text = volumes
+        .grids
+        .iter()
+        .enumerate()
+        .map(|(index, grid)| {
+            let (min, max) = grid.range();
+            format!(
+                "{}: {} {}×{}×{}, {min:.3e} to {max:.3e}",
+                index + 1,
+                grid.name,
+                grid.dims[0],
+                grid.dims[1],
+                grid.dims[2]
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    for mut content in &mut texts {
+        content.0 = text.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::BOHR;
+
+    const CUBE: &str = "Density
+written by a test
+    1    1.000000    0.000000    0.000000
+    2    0.500000    0.000000    0.000000
+    2    0.000000    0.500000    0.000000
+    2    0.000000    0.000000    0.500000
+    8    8.000000    0.000000    0.000000    2.000000
+ 0.0 1.0 2.0 3.0
+ 4.0 5.0 6.0 7.0
+";
+
+    #[test]
+    fn cube_grids_are_read_last_axis_fastest() {
+        let (crystal, grids) = parse_cube("density.cube", CUBE).unwrap();
+        assert_eq!(crystal.atoms[0].element, Element::O);
+        assert_eq!(crystal.atoms[0].z, 2.0 * BOHR);
+        assert_eq!(crystal.metadata.length_unit, Some(LengthUnit::Bohr));
+        let grid = &grids[0];
+        assert_eq!(grid.dims, [2, 2, 2]);
+        assert_eq!(grid.origin, Vec3::X * BOHR);
+        assert_eq!(grid.value([1, 0, 0]), 4.0);
+        assert_eq!(grid.value([0, 0, 1]), 1.0);
+        assert_eq!(grid.range(), (0.0, 7.0));
+        assert_eq!(grid.sample(Vec3::splat(0.5)), 3.5);
+    }
+
+    #[test]
+    fn negative_counts_give_angstrom() {
+        let cube = CUBE.replacen("    2    0.5", "   -2    0.5", 1);
+        let (crystal, grids) = parse_cube("density.cube", &cube).unwrap();
+        assert_eq!(crystal.atoms[0].z, 2.0);
+        assert_eq!(grids[0].steps.x_axis, Vec3::X * 0.5);
+    }
+
+    #[test]
+    fn cube_files_need_every_value() {
+        let short = CUBE.trim_end().strip_suffix(" 7.0").unwrap();
+        assert!(parse_cube("density.cube", short).is_err());
+    }
+}
@@ -0,0 +1,552 @@
+// Structure files opened from disk
+// `vizmat water.xyz` opens a file, which with the `watch` feature is then watched with notify
+// and re-read whenever it is saved, so edits made in a text editor show up live. Reloads keep
+// the camera where it is. Several files, or files with several frames, are loaded as a
+// trajectory to step through.
+//
+// Every file opened gets a structure tab (see structure_list.rs). Files dropped onto the window
+// open the same way, except that several files dropped at once get a tab each rather than
+// being joined into one trajectory.
+//
+// Files are read and parsed on the async compute task pool, so that a large trajectory does not
+// freeze the window; the loading indicator (see loading.rs) follows the progress. Opening files
+// again before a load finished discards the older one. XYZ files larger than the frame cache
+// are only indexed, and their frames read as they are shown (see lazy_frames.rs).
+
+#[cfg(feature = "watch")]
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use std::io::Read;
+
+use anyhow::Context;
+use bevy::prelude::*;
+use bevy::tasks::AsyncComputeTaskPool;
+use crossbeam_channel::{unbounded, Receiver, Sender};
+#[cfg(feature = "watch")]
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::events::StructureLoaded;
+use crate::lazy_frames::{cache_bytes, FrameFile};
+use crate::parse::{parse_frames, Format};
+#[cfg(feature = "watch")]
+use crate::structure::UpdateStructure;
+use crate::structure::{Crystal, Selection};
+use crate::structure_list::{tab_name, StructureList};
+use crate::toast::Toast;
+use crate::trajectory::Trajectory;
+use crate::ui::FitView;
+
+/// Bytes read between two progress reports.
+const READ_CHUNK: usize = 4 << 20;
+
+/// Request to open structure files; a single file is watched for changes.
+#[derive(Event, Clone)]
+pub(crate) struct OpenFiles {
+    pub paths: Vec<PathBuf>,
+    /// Format of every file; picked from each extension when None.
+    pub format: Option<Format>,
+    /// Frame shown first when the files hold several.
+    pub frame: usize,
+    /// Give every file a tab of its own instead of joining their frames.
+    pub list: bool,
+}
+
+/// File the structure was opened from, if any, and the watcher reporting its changes.
+#[cfg(feature = "watch")]
+#[derive(Resource, Default)]
+pub(crate) struct WatchedFile {
+    path: Option<PathBuf>,
+    format: Option<Format>,
+    // Dropping the watcher stops the notifications
+    watcher: Option<notify::RecommendedWatcher>,
+    changes: Option<Receiver<()>>,
+}
+
+#[cfg(feature = "watch")]
+impl WatchedFile {
+    // Watch `path` instead of the previous file. The directory is watched rather than the file,
+    // since many editors save by replacing the file with a new one.
+    fn watch(&mut self, path: &Path, format: Option<Format>) -> anyhow::Result<()> {
+        *self = Self::default();
+        let directory = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let file_name = path.file_name().map(|name| name.to_owned());
+
+        let (tx, rx) = unbounded();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                let written = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_));
+                if written
+                    && event
+                        .paths
+                        .iter()
+                        .any(|changed| changed.file_name() == file_name.as_deref())
+                {
+                    let _ = tx.send(());
+                }
+            })?;
+        watcher.watch(directory, RecursiveMode::NonRecursive)?;
+
+        self.path = Some(path.to_path_buf());
+        self.format = format;
+        self.watcher = Some(watcher);
+        self.changes = Some(rx);
+        Ok(())
+    }
+}
+
+/// How far a background load got.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LoadProgress {
+    /// File being read or parsed.
+    pub file: String,
+    /// Bytes read from all files, out of their total size.
+    pub bytes_read: u64,
+    pub total_bytes: u64,
+    /// Whether `file` is read completely and being parsed.
+    pub parsing: bool,
+    /// Frames parsed so far.
+    pub frames: usize,
+}
+
+// Every frame of the files, in order
+pub(crate) fn read_frames(
+    paths: &[PathBuf],
+    format: Option<Format>,
+) -> anyhow::Result<Vec<Crystal>> {
+    Ok(read_frames_reporting(paths, format, |_| {})?.concat())
+}
+
+// The frames of every file, file by file, reporting after every chunk read and every file
+// parsed
+fn read_frames_reporting(
+    paths: &[PathBuf],
+    format: Option<Format>,
+    mut report: impl FnMut(&LoadProgress),
+) -> anyhow::Result<Vec<Vec<Crystal>>> {
+    let mut progress = LoadProgress {
+        total_bytes: paths
+            .iter()
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum(),
+        ..default()
+    };
+    let mut files = Vec::new();
+    for path in paths {
+        progress.file = path.display().to_string();
+        progress.parsing = false;
+        let failed = || format!("Failed to read {}", path.display());
+        let mut file = std::fs::File::open(path).with_context(failed)?;
+        let mut bytes = Vec::new();
+        let mut chunk = vec![0; READ_CHUNK];
+        loop {
+            let read = file.read(&mut chunk).with_context(failed)?;
+            if read == 0 {
+                break;
+            }
+            bytes.extend_from_slice(&chunk[..read]);
+            progress.bytes_read += read as u64;
+            report(&progress);
+        }
+        let contents = String::from_utf8(bytes)
+            .with_context(|| format!("{} is not a text file", path.display()))?;
+
+        progress.parsing = true;
+        report(&progress);
+        let frames = parse_frames(&path.to_string_lossy(), &contents, format)?;
+        progress.frames += frames.len();
+        report(&progress);
+        files.push(frames);
+    }
+    anyhow::ensure!(!files.is_empty(), "No structure files given");
+    Ok(files)
+}
+
+fn describe_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Whether `paths` are read frame by frame rather than whole: XYZ files larger together than the
+// frame cache
+fn indexable(paths: &[PathBuf], format: Option<Format>, cache: u64) -> bool {
+    let size: u64 = paths
+        .iter()
+        .filter_map(|path| std::fs::metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    size > cache
+        && paths.iter().all(|path| {
+            format.or_else(|| Format::from_name(&path.to_string_lossy())) == Some(Format::Xyz)
+        })
+}
+
+// Index the frames of `paths`, reporting progress like `read_frames_reporting`
+fn index_frames_reporting(
+    paths: &[PathBuf],
+    cache: u64,
+    mut report: impl FnMut(&LoadProgress),
+) -> anyhow::Result<FrameFile> {
+    let mut progress = LoadProgress {
+        file: describe_paths(paths),
+        total_bytes: paths
+            .iter()
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum(),
+        ..default()
+    };
+    FrameFile::index(paths, cache, |bytes_read, frames| {
+        progress.bytes_read = bytes_read;
+        progress.frames = frames;
+        report(&progress);
+    })
+}
+
+/// Frames of a finished load.
+enum LoadedFiles {
+    /// Every frame of every file, file by file.
+    Parsed(Vec<Vec<Crystal>>),
+    /// Files too large to hold, indexed to be read frame by frame.
+    Indexed(Arc<FrameFile>),
+}
+
+/// What to do with the frames of a finished load.
+enum LoadKind {
+    Open(OpenFiles),
+    /// Reload of the watched file, staying on the same frame.
+    #[cfg(feature = "watch")]
+    Reload(PathBuf),
+}
+
+/// Load running in the background.
+struct Loading {
+    id: u64,
+    kind: LoadKind,
+    progress: LoadProgress,
+}
+
+enum LoadMessage {
+    Progress(u64, LoadProgress),
+    Done(u64, anyhow::Result<LoadedFiles>),
+}
+
+/// Files being read and parsed on a background task, and the channel it reports on.
+#[derive(Resource)]
+pub(crate) struct FileLoader {
+    tx: Sender<LoadMessage>,
+    rx: Receiver<LoadMessage>,
+    loading: Option<Loading>,
+    next_id: u64,
+}
+
+impl Default for FileLoader {
+    fn default() -> Self {
+        let (tx, rx) = unbounded();
+        Self {
+            tx,
+            rx,
+            loading: None,
+            next_id: 0,
+        }
+    }
+}
+
+impl FileLoader {
+    // Start reading `paths`, superseding the load in progress; files larger than `cache` bytes
+    // are indexed, unless they are opened in tabs of their own
+    fn start(&mut self, kind: LoadKind, paths: Vec<PathBuf>, format: Option<Format>, cache: u64) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let tx = self.tx.clone();
+        let separate = matches!(&kind, LoadKind::Open(request) if request.list);
+        AsyncComputeTaskPool::get()
+            .spawn(async move {
+                let report = |progress: &LoadProgress| {
+                    let _ = tx.send(LoadMessage::Progress(id, progress.clone()));
+                };
+                let files = if !separate && indexable(&paths, format, cache) {
+                    index_frames_reporting(&paths, cache, report)
+                        .map(|file| LoadedFiles::Indexed(Arc::new(file)))
+                } else {
+                    read_frames_reporting(&paths, format, report).map(LoadedFiles::Parsed)
+                };
+                let _ = tx.send(LoadMessage::Done(id, files));
+            })
+            .detach();
+        self.loading = Some(Loading {
+            id,
+            kind,
+            progress: LoadProgress::default(),
+        });
+    }
+
+    /// Progress of the load in progress, if any.
+    pub fn progress(&self) -> Option<&LoadProgress> {
+        self.loading.as_ref().map(|loading| &loading.progress)
+    }
+}
+
+// Open the files named on the command line
+pub(crate) fn open_cli_files(cli: Res<Cli>, mut files: EventWriter<OpenFiles>) {
+    if !cli.files.is_empty() {
+        files.write(OpenFiles {
+            paths: cli.files.clone(),
+            format: cli.format,
+            frame: cli.frame.unwrap_or(0),
+            list: false,
+        });
+    }
+}
+
+// Start loading requested files in the background
+pub(crate) fn open_files(
+    mut files: EventReader<OpenFiles>,
+    mut loader: ResMut<FileLoader>,
+    config: Res<Config>,
+) {
+    for request in files.read() {
+        info!("Opening {}", describe_paths(&request.paths));
+        let (paths, format) = (request.paths.clone(), request.format);
+        loader.start(
+            LoadKind::Open(request.clone()),
+            paths,
+            format,
+            cache_bytes(&config),
+        );
+    }
+}
+
+// Open the files dropped onto the window; several dropped together get a tab each
+pub(crate) fn open_dropped_files(
+    mut drops: EventReader<FileDragAndDrop>,
+    mut files: EventWriter<OpenFiles>,
+) {
+    let paths = drops
+        .read()
+        .filter_map(|drop| match drop {
+            FileDragAndDrop::DroppedFile { path_buf, .. } => Some(path_buf.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if paths.is_empty() {
+        return;
+    }
+    files.write(OpenFiles {
+        list: paths.len() > 1,
+        paths,
+        format: None,
+        frame: 0,
+    });
+}
+
+// The frame at `position` of indexed files, reporting a failure to read it
+fn read_indexed(
+    file: &FrameFile,
+    position: usize,
+    toasts: &mut EventWriter<Toast>,
+) -> Option<Crystal> {
+    file.read(position)
+        .inspect_err(|e| {
+            error!("{e:#}");
+            toasts.write(Toast::error(format!("{e:#}")));
+        })
+        .ok()
+}
+
+// Apply the progress and the result of the background load; loads that were superseded are
+// ignored
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn receive_loaded_files(
+    mut loader: ResMut<FileLoader>,
+    #[cfg(feature = "watch")] mut watched: ResMut<WatchedFile>,
+    #[cfg(feature = "watch")] mut updates: EventWriter<UpdateStructure>,
+    mut crystal: ResMut<Crystal>,
+    mut selection: ResMut<Selection>,
+    mut trajectory: ResMut<Trajectory>,
+    mut list: ResMut<StructureList>,
+    mut fit: EventWriter<FitView>,
+    mut loaded: EventWriter<StructureLoaded>,
+    mut toasts: EventWriter<Toast>,
+) {
+    let messages: Vec<LoadMessage> = loader.rx.try_iter().collect();
+    for message in messages {
+        let current = loader.loading.as_ref().map(|loading| loading.id);
+        match message {
+            LoadMessage::Progress(id, progress) if Some(id) == current => {
+                if let Some(loading) = loader.loading.as_mut() {
+                    loading.progress = progress;
+                }
+            }
+            LoadMessage::Done(id, files) if Some(id) == current => {
+                let Some(loading) = loader.loading.take() else {
+                    continue;
+                };
+                let files = match files {
+                    Ok(files) => files,
+                    Err(e) => {
+                        error!("{e:#}");
+                        toasts.write(Toast::error(format!("{e:#}")));
+                        continue;
+                    }
+                };
+                match loading.kind {
+                    LoadKind::Open(request) => {
+                        let source = describe_paths(&request.paths);
+                        let mut name = tab_name(&request.paths[0].to_string_lossy());
+                        if request.paths.len() > 1 {
+                            name.push_str(&format!(" +{}", request.paths.len() - 1));
+                        }
+                        // one tab per file, or one for all their frames, which indexed files
+                        // keep on disk
+                        let (tabs, file) = match files {
+                            LoadedFiles::Parsed(files) if request.list => {
+                                let names = request
+                                    .paths
+                                    .iter()
+                                    .map(|path| tab_name(&path.to_string_lossy()));
+                                (names.zip(files).collect::<Vec<_>>(), None)
+                            }
+                            LoadedFiles::Parsed(files) => {
+                                (vec![(name.clone(), files.concat())], None)
+                            }
+                            LoadedFiles::Indexed(file) => (Vec::new(), Some(file)),
+                        };
+                        let count = file
+                            .as_ref()
+                            .map_or_else(|| tabs[0].1.len(), |file| file.len());
+                        let shown = request.frame.min(count - 1);
+                        if request.frame != shown {
+                            warn!(
+                                "Frame {} requested but only {} loaded",
+                                request.frame, count
+                            );
+                        }
+                        let first = match &file {
+                            Some(file) => match read_indexed(file, shown, &mut toasts) {
+                                Some(first) => first,
+                                None => continue,
+                            },
+                            None => tabs[0].1[shown].clone(),
+                        };
+                        info!(
+                            "Loaded {} frame(s), showing {} with {} atoms",
+                            count,
+                            shown,
+                            first.atoms.len()
+                        );
+                        loaded.write(StructureLoaded {
+                            source: source.clone(),
+                            atom_count: first.atoms.len(),
+                            frame_count: count,
+                        });
+                        selection.atoms.clear();
+                        *crystal = first.clone();
+                        trajectory.clear();
+                        match file {
+                            Some(file) => {
+                                if count > 1 {
+                                    trajectory.load_file(file.clone(), shown);
+                                }
+                                list.open_file(source, name, file, first, shown);
+                            }
+                            None => {
+                                if count > 1 {
+                                    let frames = tabs[0].1.iter().cloned().map(Into::into);
+                                    trajectory.load(frames.collect(), shown);
+                                }
+                                list.open(source, tabs, shown);
+                            }
+                        }
+                        fit.write(FitView);
+
+                        #[cfg(feature = "watch")]
+                        {
+                            *watched = WatchedFile::default();
+                            if let [path] = request.paths.as_slice() {
+                                match watched.watch(path, request.format) {
+                                    Ok(()) => info!("Watching {} for changes", path.display()),
+                                    Err(e) => warn!("Cannot watch {}: {e}", path.display()),
+                                }
+                            }
+                        }
+                    }
+                    #[cfg(feature = "watch")]
+                    LoadKind::Reload(path) => {
+                        info!("Reloaded {}", path.display());
+                        let source = path.display().to_string();
+                        let (frames, file) = match files {
+                            LoadedFiles::Parsed(files) => (files.concat(), None),
+                            LoadedFiles::Indexed(file) => (Vec::new(), Some(file)),
+                        };
+                        let count = file.as_ref().map_or(frames.len(), |file| file.len());
+                        let shown = trajectory.position().min(count - 1);
+                        let first = match &file {
+                            Some(file) => match read_indexed(file, shown, &mut toasts) {
+                                Some(first) => first,
+                                None => continue,
+                            },
+                            None => frames[shown].clone(),
+                        };
+                        loaded.write(StructureLoaded {
+                            source: source.clone(),
+                            atom_count: first.atoms.len(),
+                            frame_count: count,
+                        });
+                        match &file {
+                            Some(file) => list.reload_file(&source, file, &first, shown),
+                            None => list.reload(&source, &frames, shown),
+                        }
+                        updates.write(first.into());
+                        trajectory.clear();
+                        match file {
+                            Some(file) if count > 1 => trajectory.load_file(file, shown),
+                            None if count > 1 => {
+                                trajectory.load(frames.into_iter().map(Into::into).collect(), shown)
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            // a superseded load
+            _ => {}
+        }
+    }
+}
+
+// Re-read the watched file after it changed, staying on the same frame; a file caught
+// half-written is retried on the next change
+#[cfg(feature = "watch")]
+pub(crate) fn reload_watched_file(
+    watched: Res<WatchedFile>,
+    mut loader: ResMut<FileLoader>,
+    config: Res<Config>,
+) {
+    let (Some(path), Some(changes)) = (&watched.path, &watched.changes) else {
+        return;
+    };
+    // one save often reports several changes
+    if changes.try_iter().count() == 0 {
+        return;
+    }
+    loader.start(
+        LoadKind::Reload(path.clone()),
+        vec![path.clone()],
+        watched.format,
+        cache_bytes(&config),
+    );
+}
@@ -0,0 +1,453 @@
+// JavaScript API of the WASM build, published on npm as `vizmat` with TypeScript declarations
+// A page embedding the canvas pushes structures to the viewer directly, without a WebSocket
+// server, and hears back about what the user does:
+//
+//     import init, { start, load_xyz, load_json, on_event } from "vizmat";
+//     await init();
+//     start("#viewer");  // draws into the canvas matching the selector, sized by its parent
+//     load_xyz(text);  // XYZ or extended XYZ; several frames can be stepped through
+//     load_structure(text, "cif");  // any supported format, guessed from the text if omitted
+//     load_json({ atoms: [{ element: "O", x: 0, y: 0, z: 0 }], lattice: [[4, 0, 0], ...] });
+//     on_event((event) => console.log(event.type, event));
+//     const xyz = export_xyz();  // structure on screen, undefined before the first frame
+//
+// `load_json` takes the structure message of the WebSocket protocol (without `type`), as an
+// object or as JSON text. The load functions throw when the structure cannot be read; otherwise
+// it replaces the one on screen on the next frame, like an opened file. They can be called
+// before `start`, and the last structure pushed is shown once the viewer runs. Listeners get
+// the `ViewerEvent`s described below, which are also posted to the page.
+//
+// Links can also open onto a structure: `?structure=https://...&format=cif` in the page URL
+// downloads it at startup (`fetch` feature), like `--url` and `--format` on the command line.
+// `format` is only needed when the extension does not tell it. `?ws=wss://...` connects to another
+// structure server, `?sensitivity=0.5` slows the mouse down and `?mp_api_key=...` sets the
+// Materials Project key; all three are remembered for later visits in localStorage, like the
+// panels and colors (see persist.rs), and the key is then removed from the address bar so it is
+// not passed on with the link. `?token=...` is sent in the hello to a server that requires one,
+// and is not remembered.
+//
+// Pages that embed the viewer in an iframe, such as Jupyter widgets and dashboards, talk to it
+// with `postMessage` instead. The viewer takes messages tagged with a `type`, and ignores others:
+//
+//     viewer.contentWindow.postMessage({ type: "xyz", text }, "*");
+//     viewer.contentWindow.postMessage({ type: "structure", atoms: [...] }, "*");
+//
+// It posts `{ type: "atom_picked", index, element, position, extend }` when an atom is clicked,
+// `{ type: "selection", atoms }` when the selection changes, `{ type: "structure_loaded", ... }`
+// and `{ type: "frame_changed", ... }`, to the parent window if it is embedded and to its own
+// window otherwise.
+
+use std::cell::RefCell;
+use std::sync::LazyLock;
+
+use anyhow::Context;
+use bevy::prelude::*;
+use clap::ValueEnum;
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::events::{AtomPicked, FrameChanged, SelectionChanged, StructureLoaded};
+use crate::parse::{parse_frames, write_xyz, Format};
+#[cfg(feature = "websocket")]
+use crate::protocol::{PeerCell, StructureMessage};
+use crate::structure::{Crystal, Selection};
+use crate::trajectory::Trajectory;
+use crate::ui::{FitView, MouseSensitivity};
+
+// Frames of a pushed structure, with the function or the files it came from
+type Pushed = (String, Vec<Crystal>);
+
+// The exported functions run outside the app, so they hand structures over through a channel
+// that lives as long as the page
+static PUSHED: LazyLock<(Sender<Pushed>, Receiver<Pushed>)> = LazyLock::new(unbounded);
+
+// JS values cannot leave the main thread, which is the only one the app runs on in the browser
+thread_local! {
+    // functions registered with `on_event`
+    static LISTENERS: RefCell<Vec<js_sys::Function>> = const { RefCell::new(Vec::new()) };
+    // structure on screen and the selected atoms, as of the last frame
+    static SHOWN: RefCell<Option<(Crystal, Vec<usize>)>> = const { RefCell::new(None) };
+}
+
+// Declarations of the values the functions below take and give, for TypeScript users
+#[wasm_bindgen(typescript_custom_section)]
+const TYPESCRIPT_TYPES: &str = r#"
+export type ViewerEvent =
+  | { type: "atom_picked"; index: number; element: string; position: [number, number, number]; extend: boolean }
+  | { type: "selection"; atoms: number[] }
+  | { type: "structure_loaded"; source: string; atom_count: number; frame_count: number }
+  | { type: "frame_changed"; position: number; frame_count: number; index: number; step: number | null; time: number | null };
+
+// "cif" needs a build with the `cif` feature
+export type StructureFormat = "xyz" | "extxyz" | "cif" | "mmcif" | "gro" | "gromacs";
+"#;
+
+// Message posted to the viewer
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum PageMessage {
+    Xyz {
+        text: String,
+    },
+    #[cfg(feature = "websocket")]
+    Structure(StructureMessage),
+}
+
+// Types of the messages above; messages of any other type are meant for someone else
+const PAGE_MESSAGE_TYPES: [&str; 2] = ["xyz", "structure"];
+
+// Message the viewer posts about what the user does
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ViewerMessage<'a> {
+    AtomPicked {
+        index: usize,
+        element: &'a str,
+        position: [f32; 3],
+        extend: bool,
+    },
+    Selection {
+        atoms: &'a [usize],
+    },
+    StructureLoaded {
+        source: &'a str,
+        atom_count: usize,
+        frame_count: usize,
+    },
+    FrameChanged {
+        position: usize,
+        frame_count: usize,
+        index: u64,
+        step: Option<u64>,
+        time: Option<f64>,
+    },
+}
+
+// Hand `frames` over to the app, which shows the first on its next update
+pub(crate) fn push(source: String, frames: Vec<Crystal>) {
+    let _ = PUSHED.0.send((source, frames));
+}
+
+// The frames of XYZ text passed by the page
+fn xyz_frames(source: &str, text: &str) -> anyhow::Result<Vec<Crystal>> {
+    parse_frames(source, text, Some(Format::Xyz))
+}
+
+// Structure described by a structure message of the WebSocket protocol
+#[cfg(feature = "websocket")]
+fn message_crystal(message: StructureMessage) -> Crystal {
+    let update = message.into_update(&mut PeerCell::default());
+    Crystal {
+        atoms: update.atoms,
+        lattice: update.lattice,
+        pbc: update.pbc,
+        properties: update.properties,
+        metadata: update.metadata.unwrap_or_default(),
+    }
+}
+
+/// Show the structure in `text`, in XYZ or extended XYZ format.
+#[wasm_bindgen]
+pub fn load_xyz(text: &str) -> Result<(), JsError> {
+    let frames = xyz_frames("load_xyz", text).map_err(|e| JsError::new(&format!("{e:#}")))?;
+    push("load_xyz".to_string(), frames);
+    Ok(())
+}
+
+/// Show the structure in `text`, in `format`, or the format the text looks like if omitted.
+#[wasm_bindgen]
+pub fn load_structure(
+    text: &str,
+    #[wasm_bindgen(unchecked_param_type = "StructureFormat")] format: Option<String>,
+) -> Result<(), JsError> {
+    let format = format
+        .map(|name| {
+            Format::from_str(&name, true)
+                .map_err(|_| JsError::new(&format!("Unknown format '{name}'")))
+        })
+        .transpose()?;
+    let frames = parse_frames("load_structure", text, format)
+        .map_err(|e| JsError::new(&format!("{e:#}")))?;
+    push("load_structure".to_string(), frames);
+    Ok(())
+}
+
+/// Show a structure given as a WebSocket structure message, either an object or JSON text.
+#[cfg(feature = "websocket")]
+#[wasm_bindgen]
+pub fn load_json(
+    #[wasm_bindgen(unchecked_param_type = "object | string")] structure: JsValue,
+) -> Result<(), JsError> {
+    let text = match structure.as_string() {
+        Some(text) => text,
+        None => js_sys::JSON::stringify(&structure)
+            .map_err(|e| JsError::new(&format!("Structure is not JSON: {e:?}")))?
+            .into(),
+    };
+    let message: StructureMessage = serde_json::from_str(&text)
+        .map_err(|e| JsError::new(&format!("Invalid structure: {e}")))?;
+    push("load_json".to_string(), vec![message_crystal(message)]);
+    Ok(())
+}
+
+/// The structure on screen as XYZ text, or undefined until the viewer has drawn a frame.
+#[wasm_bindgen]
+pub fn export_xyz() -> Option<String> {
+    SHOWN.with_borrow(|shown| shown.as_ref().map(|(crystal, _)| write_xyz(crystal)))
+}
+
+/// Indices of the selected atoms, in the order they were picked.
+#[wasm_bindgen]
+pub fn selected_atoms() -> Vec<u32> {
+    SHOWN.with_borrow(|shown| {
+        shown.as_ref().map_or_else(Vec::new, |(_, atoms)| {
+            atoms.iter().map(|&index| index as u32).collect()
+        })
+    })
+}
+
+/// Call `listener` with every event of the viewer, for as long as the page lives.
+#[wasm_bindgen]
+pub fn on_event(
+    #[wasm_bindgen(unchecked_param_type = "(event: ViewerEvent) => void")]
+    listener: js_sys::Function,
+) {
+    LISTENERS.with_borrow_mut(|listeners| listeners.push(listener));
+}
+
+// Show the structure posted in `data`, if it is a message for the viewer
+fn receive_page_message(data: JsValue) -> anyhow::Result<()> {
+    if !data.is_object() {
+        return Ok(());
+    }
+    let text: String = js_sys::JSON::stringify(&data)
+        .map_err(|e| anyhow::anyhow!("Posted message is not JSON: {e:?}"))?
+        .into();
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(&text) else {
+        return Ok(());
+    };
+    let known = value
+        .get("type")
+        .and_then(serde_json::Value::as_str)
+        .is_some_and(|kind| PAGE_MESSAGE_TYPES.contains(&kind));
+    if !known {
+        return Ok(());
+    }
+    match serde_json::from_value(value).context("Invalid message posted to the viewer")? {
+        PageMessage::Xyz { text } => {
+            push("postMessage".to_string(), xyz_frames("postMessage", &text)?)
+        }
+        #[cfg(feature = "websocket")]
+        PageMessage::Structure(message) => {
+            push("postMessage".to_string(), vec![message_crystal(message)]);
+        }
+    }
+    Ok(())
+}
+
+// Listen for the messages posted to the window for as long as the page lives
+pub(crate) fn listen_for_page_messages() {
+    let Some(window) = web_sys::window() else {
+        return;
+    };
+    let listener = Closure::<dyn Fn(web_sys::MessageEvent)>::new(|event: web_sys::MessageEvent| {
+        if let Err(e) = receive_page_message(event.data()) {
+            error!("{e:#}");
+        }
+    });
+    if let Err(e) =
+        window.add_event_listener_with_callback("message", listener.as_ref().unchecked_ref())
+    {
+        error!("Failed to listen for posted messages: {e:?}");
+    }
+    listener.forget();
+}
+
+// Hand `message` to the listeners, and post it to the page embedding the viewer or to the
+// viewer's own page
+fn post_to_page(message: &ViewerMessage) {
+    let Some(window) = web_sys::window() else {
+        return;
+    };
+    let value = match serde_json::to_string(message)
+        .map_err(|e| format!("{e}"))
+        .and_then(|text| js_sys::JSON::parse(&text).map_err(|e| format!("{e:?}")))
+    {
+        Ok(value) => value,
+        Err(e) => {
+            error!("Failed to convert a message for the page: {e}");
+            return;
+        }
+    };
+    LISTENERS.with_borrow(|listeners| {
+        for listener in listeners {
+            if let Err(e) = listener.call1(&JsValue::NULL, &value) {
+                error!("Event listener failed: {e:?}");
+            }
+        }
+    });
+    let target = window.parent().ok().flatten().unwrap_or(window);
+    if let Err(e) = target.post_message(&value, "*") {
+        error!("Failed to post a message to the page: {e:?}");
+    }
+}
+
+// Tell the page about picked atoms, selection changes, loaded structures and frames shown
+pub(crate) fn post_user_events(
+    mut picked: EventReader<AtomPicked>,
+    mut selection: EventReader<SelectionChanged>,
+    mut loaded: EventReader<StructureLoaded>,
+    mut frames: EventReader<FrameChanged>,
+) {
+    for load in loaded.read() {
+        post_to_page(&ViewerMessage::StructureLoaded {
+            source: &load.source,
+            atom_count: load.atom_count,
+            frame_count: load.frame_count,
+        });
+    }
+    for frame in frames.read() {
+        post_to_page(&ViewerMessage::FrameChanged {
+            position: frame.position,
+            frame_count: frame.frame_count,
+            index: frame.index,
+            step: frame.step,
+            time: frame.time,
+        });
+    }
+    for pick in picked.read() {
+        post_to_page(&ViewerMessage::AtomPicked {
+            index: pick.index,
+            element: pick.element.symbol(),
+            position: pick.position.to_array(),
+            extend: pick.extend,
+        });
+    }
+    for change in selection.read() {
+        post_to_page(&ViewerMessage::Selection {
+            atoms: &change.atoms,
+        });
+    }
+}
+
+// Keep the structure on screen and the selection for `export_xyz` and `selected_atoms`
+pub(crate) fn share_shown_structure(crystal: Res<Crystal>, selection: Res<Selection>) {
+    if !crystal.is_changed() && !selection.is_changed() {
+        return;
+    }
+    SHOWN.set(Some((crystal.clone(), selection.atoms.clone())));
+}
+
+// Take the structure to open, its format and settings from the query string of the page
+pub(crate) fn read_page_query(
+    mut cli: ResMut<Cli>,
+    mut config: ResMut<Config>,
+    mut sensitivity: ResMut<MouseSensitivity>,
+) {
+    let Some(search) = web_sys::window().and_then(|window| window.location().search().ok()) else {
+        return;
+    };
+    let Ok(query) = web_sys::UrlSearchParams::new_with_str(&search) else {
+        return;
+    };
+    if let Some(format) = query.get("format") {
+        match Format::from_str(&format, true) {
+            Ok(format) => cli.format = Some(format),
+            Err(_) => warn!("Ignoring unknown format '{format}' in the page URL"),
+        }
+    }
+    if let Some(url) = query.get("structure") {
+        #[cfg(feature = "fetch")]
+        {
+            cli.url = Some(url);
+        }
+        #[cfg(not(feature = "fetch"))]
+        warn!("Opening {url} from the page URL needs the `fetch` feature");
+    }
+    // wins over the server of the previous visit, and is remembered in its place
+    if let Some(url) = query.get("ws") {
+        #[cfg(not(feature = "websocket"))]
+        warn!("Connecting to {url} from the page URL needs the `websocket` feature");
+        config.ws_url = Some(url);
+    }
+    #[cfg(feature = "websocket")]
+    if let Some(token) = query.get("token") {
+        cli.token = Some(token);
+    }
+    // remembered like the server, since the browser has no configuration file to hold it, and
+    // taken out of the address bar so that links copied from it do not share the key
+    if let Some(key) = query.get("mp_api_key") {
+        config.materials_project_api_key = Some(key);
+        query.delete("mp_api_key");
+        replace_page_query(&query);
+    }
+    if let Some(factor) = query.get("sensitivity") {
+        match factor.parse::<f32>() {
+            Ok(factor) if factor > 0.0 => sensitivity.0 = factor,
+            _ => {
+                warn!("Ignoring sensitivity '{factor}' in the page URL, expected a positive number")
+            }
+        }
+    }
+}
+
+// Show `query` in the address bar in place of the current query string, without reloading
+fn replace_page_query(query: &web_sys::UrlSearchParams) {
+    let Some(window) = web_sys::window() else {
+        return;
+    };
+    let location = window.location();
+    let (Ok(path), Ok(hash), Ok(history)) =
+        (location.pathname(), location.hash(), window.history())
+    else {
+        return;
+    };
+    let query = String::from(query.to_string());
+    let url = if query.is_empty() {
+        format!("{path}{hash}")
+    } else {
+        format!("{path}?{query}{hash}")
+    };
+    if history
+        .replace_state_with_url(&JsValue::NULL, "", Some(&url))
+        .is_err()
+    {
+        warn!("Failed to update the page URL");
+    }
+}
+
+// Show the structures pushed by the page since the last frame
+pub(crate) fn receive_pushed_structures(
+    mut crystal: ResMut<Crystal>,
+    mut selection: ResMut<Selection>,
+    mut trajectory: ResMut<Trajectory>,
+    mut fit: EventWriter<FitView>,
+    mut loaded: EventWriter<StructureLoaded>,
+) {
+    let Some((source, frames)) = PUSHED.1.try_iter().last() else {
+        return;
+    };
+    let Some(first) = frames.first() else {
+        return;
+    };
+    info!(
+        "Loaded {} frame(s) from {source}, showing 0 with {} atoms",
+        frames.len(),
+        first.atoms.len()
+    );
+    loaded.write(StructureLoaded {
+        source,
+        atom_count: first.atoms.len(),
+        frame_count: frames.len(),
+    });
+    selection.atoms.clear();
+    *crystal = first.clone();
+    trajectory.clear();
+    if frames.len() > 1 {
+        trajectory.load(frames.into_iter().map(Into::into).collect(), 0);
+    }
+    fit.write(FitView);
+}
@@ -0,0 +1,347 @@
+// Small reusable UI pieces for tool panels
+// A stepper row shows one numeric field of a settings resource with -/+ buttons around it.
+// Each settings type implements `StepperSettings` and registers `stepper_buttons::<T>` and
+// `refresh_stepper_text::<T>`.
+// A text field takes keyboard input while focused and reports Enter as `TextSubmitted`; panels
+// tell their fields apart by a marker component on the field entity.
+
+use std::marker::PhantomData;
+
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::input::ButtonState;
+use bevy::prelude::*;
+
+use crate::theme::{Themed, UiTheme};
+
+/// Settings resource editable through stepper rows.
+pub(crate) trait StepperSettings: Resource {
+    /// Moves `field` one step up (`direction` = 1) or down (-1).
+    fn step(&mut self, field: usize, direction: i32);
+
+    /// Current value of `field` as shown between the buttons.
+    fn value_text(&self, field: usize) -> String;
+}
+
+/// -/+ button of a stepper row.
+#[derive(Component)]
+pub(crate) struct StepButton<T> {
+    field: usize,
+    direction: i32,
+    _settings: PhantomData<T>,
+}
+
+/// Value text of a stepper row.
+#[derive(Component)]
+pub(crate) struct StepValueText<T> {
+    field: usize,
+    _settings: PhantomData<T>,
+}
+
+fn text_bundle(label: impl Into<String>) -> (Text, TextFont, Themed) {
+    (
+        Text::new(label),
+        TextFont {
+            font: default(),
+            font_size: 12.0,
+            ..default()
+        },
+        Themed::Text,
+    )
+}
+
+/// Spawns a framed button with a text label and the given marker component.
+pub(crate) fn spawn_button(parent: &mut ChildSpawnerCommands, label: &str, marker: impl Bundle) {
+    parent
+        .spawn((
+            Button,
+            Node {
+                padding: UiRect::axes(Val::Px(10.0), Val::Px(6.0)),
+                border: UiRect::all(Val::Px(1.0)),
+                ..default()
+            },
+            Themed::Button,
+            marker,
+        ))
+        .with_children(|button| {
+            button.spawn(text_bundle(label));
+        });
+}
+
+/// Spawns an empty text for a table with padded columns, which line up because the default
+/// font is monospaced.
+pub(crate) fn spawn_table_text(parent: &mut ChildSpawnerCommands, marker: impl Bundle) {
+    parent.spawn((
+        Text::new(""),
+        TextFont {
+            font: default(),
+            font_size: 12.0,
+            ..default()
+        },
+        Themed::Text,
+        marker,
+    ));
+}
+
+/// Spawns a `label  [-] value [+]` row for `field` of `settings`.
+pub(crate) fn spawn_stepper_row<T: StepperSettings>(
+    parent: &mut ChildSpawnerCommands,
+    label: &str,
+    field: usize,
+    settings: &T,
+) {
+    parent
+        .spawn(Node {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            column_gap: Val::Px(4.0),
+            ..default()
+        })
+        .with_children(|row| {
+            row.spawn((
+                text_bundle(label),
+                Node {
+                    width: Val::Px(90.0),
+                    ..default()
+                },
+            ));
+            for (symbol, direction) in [("-", -1), ("+", 1)] {
+                if direction == 1 {
+                    row.spawn((
+                        text_bundle(settings.value_text(field)),
+                        Node {
+                            width: Val::Px(60.0),
+                            justify_content: JustifyContent::Center,
+                            ..default()
+                        },
+                        StepValueText::<T> {
+                            field,
+                            _settings: PhantomData,
+                        },
+                    ));
+                }
+                spawn_button(
+                    row,
+                    symbol,
+                    StepButton::<T> {
+                        field,
+                        direction,
+                        _settings: PhantomData,
+                    },
+                );
+            }
+        });
+}
+
+/// Value text of an on/off stepper field.
+pub(crate) fn on_off(value: bool) -> String {
+    if value { "on" } else { "off" }.to_string()
+}
+
+/// Applies stepper button presses to the settings resource.
+pub(crate) fn stepper_buttons<T: StepperSettings>(
+    interactions: Query<(&Interaction, &StepButton<T>), Changed<Interaction>>,
+    mut settings: ResMut<T>,
+) {
+    for (interaction, button) in &interactions {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        settings.step(button.field, button.direction);
+    }
+}
+
+/// Keeps stepper value texts in sync with the settings resource.
+pub(crate) fn refresh_stepper_text<T: StepperSettings>(
+    settings: Res<T>,
+    mut texts: Query<(&StepValueText<T>, &mut Text)>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    for (value, mut text) in &mut texts {
+        text.0 = settings.value_text(value.field);
+    }
+}
+
+/// Single-line text input.
+#[derive(Component)]
+pub(crate) struct TextField {
+    pub value: String,
+    placeholder: String,
+}
+
+/// Text showing the value of its parent text field.
+#[derive(Component)]
+pub(crate) struct TextFieldText;
+
+/// Text field receiving key presses, if any.
+#[derive(Resource, Default)]
+pub(crate) struct FocusedField(pub Option<Entity>);
+
+/// Sent when Enter is pressed in a text field.
+#[derive(Event)]
+pub(crate) struct TextSubmitted {
+    pub field: Entity,
+    pub value: String,
+}
+
+/// Run condition for keyboard shortcuts, which must not fire while typing.
+pub(crate) fn no_text_focus(focused: Res<FocusedField>) -> bool {
+    focused.0.is_none()
+}
+
+/// Spawns an empty text field `width` pixels wide with the given marker component.
+pub(crate) fn spawn_text_field(
+    parent: &mut ChildSpawnerCommands,
+    placeholder: &str,
+    width: f32,
+    marker: impl Bundle,
+) {
+    parent
+        .spawn((
+            Button,
+            Node {
+                width: Val::Px(width),
+                padding: UiRect::axes(Val::Px(6.0), Val::Px(6.0)),
+                border: UiRect::all(Val::Px(1.0)),
+                overflow: Overflow::clip(),
+                ..default()
+            },
+            Themed::Field,
+            TextField {
+                value: String::new(),
+                placeholder: placeholder.to_string(),
+            },
+            marker,
+        ))
+        .with_children(|field| {
+            // colored by `refresh_text_fields`, which tells the placeholder apart
+            field.spawn((
+                Text::new(placeholder),
+                TextFont {
+                    font: default(),
+                    font_size: 12.0,
+                    ..default()
+                },
+                TextFieldText,
+            ));
+        });
+}
+
+/// Focuses a text field on click; clicking anywhere else drops the focus.
+#[allow(clippy::type_complexity)]
+pub(crate) fn focus_text_fields(
+    fields: Query<(Entity, &Interaction), (Changed<Interaction>, With<TextField>)>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    mut focused: ResMut<FocusedField>,
+) {
+    let clicked = fields
+        .iter()
+        .find(|(_, interaction)| **interaction == Interaction::Pressed)
+        .map(|(entity, _)| entity);
+    if clicked.is_some() {
+        focused.0 = clicked;
+    } else if mouse_buttons.just_pressed(MouseButton::Left) && focused.0.is_some() {
+        focused.0 = None;
+    }
+}
+
+/// Types into the focused text field. Enter submits, Escape drops the focus.
+pub(crate) fn text_field_input(
+    mut key_events: EventReader<KeyboardInput>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut focused: ResMut<FocusedField>,
+    mut fields: Query<&mut TextField>,
+    mut submitted: EventWriter<TextSubmitted>,
+) {
+    let Some(entity) = focused.0 else {
+        key_events.clear();
+        return;
+    };
+    let Ok(mut field) = fields.get_mut(entity) else {
+        focused.0 = None;
+        return;
+    };
+    // shortcuts such as Ctrl+C arrive as characters too
+    let modified = keys.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::SuperLeft,
+        KeyCode::SuperRight,
+    ]);
+
+    for event in key_events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Character(text) if !modified => field.value.push_str(text),
+            Key::Space => field.value.push(' '),
+            Key::Backspace => {
+                field.value.pop();
+            }
+            Key::Enter => {
+                submitted.write(TextSubmitted {
+                    field: entity,
+                    value: field.value.trim().to_string(),
+                });
+            }
+            Key::Escape => {
+                focused.0 = None;
+                break;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Values entered into the text fields marked `F`: submitted with Enter, or the current value
+/// when `button_pressed`. Empty values are skipped.
+#[cfg_attr(not(any(feature = "fetch", feature = "scripting")), allow(dead_code))]
+pub(crate) fn entered_values<F: Component>(
+    submitted: &mut EventReader<TextSubmitted>,
+    fields: &Query<&TextField, With<F>>,
+    button_pressed: bool,
+) -> Vec<String> {
+    let mut values: Vec<String> = submitted
+        .read()
+        .filter(|event| fields.contains(event.field))
+        .map(|event| event.value.clone())
+        .collect();
+    if button_pressed {
+        values.extend(fields.iter().map(|field| field.value.trim().to_string()));
+    }
+    values.retain(|value| !value.is_empty());
+    values
+}
+
+/// Shows the value of each text field, with a cursor while focused.
+pub(crate) fn refresh_text_fields(
+    focused: Res<FocusedField>,
+    theme: Res<UiTheme>,
+    mut fields: Query<(Entity, Ref<TextField>, &Children, &mut BorderColor)>,
+    mut texts: Query<(&mut Text, &mut TextColor), With<TextFieldText>>,
+) {
+    for (entity, field, children, mut border) in &mut fields {
+        if !field.is_changed() && !focused.is_changed() && !theme.is_changed() {
+            continue;
+        }
+        let has_focus = focused.0 == Some(entity);
+        border.0 = if has_focus {
+            theme.focused_border
+        } else {
+            theme.border
+        };
+        let (content, color) = match (has_focus, field.value.is_empty()) {
+            (true, _) => (format!("{}_", field.value), theme.text),
+            (false, true) => (field.placeholder.clone(), theme.placeholder),
+            (false, false) => (field.value.clone(), theme.text),
+        };
+        for &child in children {
+            if let Ok((mut text, mut text_color)) = texts.get_mut(child) {
+                text.0 = content.clone();
+                text_color.0 = color;
+            }
+        }
+    }
+}
@@ -2,7 +2,7 @@ import init, { start } from "./vizmat.js";
 
 async function main() {
     await init();
-    start();
+    start("#bevy-canvas");
 }
 
 main();